@@ -0,0 +1,59 @@
+//!
+//! Encrypts to public-key recipients and decrypts with the matching identity.
+//!
+//! Every recipient gets a keyslot wrapping the same random data key, so any one of them can
+//! open the file and nobody else can, not even the sender.
+//!
+//! Run with: `cargo run --example recipients`
+//!
+use encryptx_backend::crypto::{
+    self, Argon2Params, CryptoError,
+    identity::{Identity, Recipient},
+    keyslot::{self, Credential},
+};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let alice = Identity::generate();
+    // Hybrid identities add ML-KEM-768 against future quantum attacks
+    let bob = Identity::generate_hybrid();
+    let mallory = Identity::generate();
+
+    // Recipients are shared as text, e.g. in a README or chat message
+    let recipients: Vec<Recipient> = [alice.recipient(), bob.recipient()]
+        .iter()
+        .map(|r| r.to_string().parse())
+        .collect::<Result<_, _>>()?;
+
+    let data_key = keyslot::generate_data_key();
+    let mut slots = Vec::new();
+    for recipient in &recipients {
+        let slot = keyslot::create(
+            &data_key,
+            Credential::Recipient(recipient),
+            Argon2Params::default(),
+            None,
+        )
+        .await?;
+        slots.push(slot);
+    }
+    let message = b"Meet at the usual place at noon.";
+    let encrypted = crypto::encrypt_with_keyslots(message, &data_key, "plan.txt", slots, None)?;
+    println!(
+        "Encrypted {} bytes to {} recipients",
+        message.len(),
+        recipients.len()
+    );
+
+    for (name, identity) in [("alice", &alice), ("bob", &bob)] {
+        let (plaintext, filename) = crypto::decrypt_with_identity(&encrypted, identity)?;
+        assert_eq!(plaintext, message);
+        println!("{name} opened '{filename}'");
+    }
+    assert!(matches!(
+        crypto::decrypt_with_identity(&encrypted, &mallory),
+        Err(CryptoError::AuthenticationError)
+    ));
+    println!("mallory was turned away");
+    Ok(())
+}
@@ -0,0 +1,76 @@
+//!
+//! Talks to a running EncryptX server: encrypts a document with a password over HTTP and
+//! decrypts it again.
+//!
+//! Start the server first (`cargo run`), then run:
+//! `cargo run --example server_client [HOST:PORT]` (defaults to `127.0.0.1:8080`).
+//!
+//! Only the standard library is used, to show the raw requests; any HTTP client works the same.
+//!
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+/// Sends a POST request and returns the status code and body.
+fn post(
+    addr: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> std::io::Result<(u16, Vec<u8>)> {
+    let mut stream = TcpStream::connect(addr)?;
+    let mut request = format!(
+        "POST {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\nContent-Length: {}\r\n",
+        body.len()
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{name}: {value}\r\n"));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes())?;
+    stream.write_all(body)?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    let split = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| std::io::Error::other("malformed HTTP response"))?;
+    let status = String::from_utf8_lossy(&response[..split])
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .unwrap_or(0);
+    Ok((status, response[split + 4..].to_vec()))
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    let document = b"Quarterly numbers: up and to the right.\n".repeat(100);
+    let password = "correct horse battery staple";
+
+    let (status, encrypted) = post(
+        &addr,
+        "/encrypt",
+        &[("x-password", password), ("x-orig-filename", "report.txt")],
+        &document,
+    )?;
+    assert_eq!(status, 200, "{}", String::from_utf8_lossy(&encrypted));
+    println!(
+        "Server encrypted {} bytes into {} bytes",
+        document.len(),
+        encrypted.len()
+    );
+
+    let (status, decrypted) = post(&addr, "/decrypt", &[("x-password", password)], &encrypted)?;
+    assert_eq!(status, 200, "{}", String::from_utf8_lossy(&decrypted));
+    assert_eq!(decrypted, document);
+    println!("Server decrypted it back");
+
+    // A wrong password is an authentication failure, not a server error
+    let (status, _) = post(&addr, "/decrypt", &[("x-password", "guess")], &encrypted)?;
+    println!("Wrong password answered with HTTP {status}");
+    assert!((400..500).contains(&status));
+    Ok(())
+}
@@ -0,0 +1,457 @@
+//!
+//! Snapshot repositories for the `backup` command: named, timestamped snapshots of a
+//! directory, stored as deduplicated encrypted chunks and one encrypted index per
+//! snapshot.
+//!
+//! A repository is a directory holding:
+//! - `config`: an `.xd` keyslot file whose data key is the repository's master key, opened
+//!   with the repository's password or key. Its body records how files are chunked.
+//! - `chunks/<ab>/<id>`: file contents, cut into chunks, each a master-key `.xd` file. A
+//!   chunk's id is an HMAC of its plaintext under a key derived from the master key, so
+//!   equal chunks are stored once across files and snapshots, yet the ids reveal nothing
+//!   to anyone without the key.
+//! - `snapshots/<time>`: one master-key `.xd` file per snapshot, holding its [`Snapshot`]
+//!   index (paths, metadata and the chunks of every file).
+//!
+//! Chunks are written before the index that refers to them, each under a temporary name
+//! renamed into place, so an interrupted backup never leaves a snapshot with missing data.
+//!
+use crate::api::{self, ApiError};
+use crate::cdc;
+use crate::compression;
+use crate::crypto::{self, CryptoError, SecureKey, keyslot};
+use crate::format::XdReader;
+use hkdf::hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+/// The repository's keyslot file.
+pub const CONFIG_FILE: &str = "config";
+
+/// Directory of the content chunks.
+pub const CHUNKS_DIR: &str = "chunks";
+
+/// Directory of the snapshot indexes.
+pub const SNAPSHOTS_DIR: &str = "snapshots";
+
+/// Average size of the pieces files are cut into in new repositories. Content-defined
+/// pieces range from a quarter of it to four times it.
+pub const DEFAULT_CHUNK_SIZE: u32 = 1 << 20;
+
+/// Version of the repository layout.
+const REPOSITORY_VERSION: u32 = 1;
+
+/// Version of the snapshot index layout.
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// HKDF info for the key chunk ids are computed with.
+const CHUNK_ID_INFO: &[u8] = b"encryptx backup chunk id";
+
+/// Errors of repository operations.
+#[derive(Error, Debug)]
+pub enum BackupError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// Opening the repository or one of its files failed; `AuthenticationError` means a
+    /// wrong password or key, or tampered data
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+    #[error(transparent)]
+    Api(#[from] ApiError),
+    /// The directory isn't a repository, or something in it is malformed
+    #[error("{0}")]
+    Repository(String),
+}
+
+/// What the repository's `config` records, encrypted.
+#[derive(Serialize, Deserialize)]
+struct RepositoryConfig {
+    version: u32,
+    /// How files are cut into pieces; repositories that don't record it use fixed sizes
+    #[serde(default)]
+    chunker: Chunker,
+    /// Size of the pieces files are cut into; the average size for [`Chunker::Cdc`]
+    chunk_size: u32,
+}
+
+/// How a repository cuts files into chunks. It is fixed when the repository is created,
+/// since changing it would make new chunks miss all the old ones.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Chunker {
+    /// Pieces of exactly the chunk size: an insertion shifts every later cut point, so
+    /// everything after it is stored anew
+    #[default]
+    Fixed,
+    /// Content-defined pieces (see [`crate::cdc`]): the cut points move with the content,
+    /// so the unchanged regions of an edited file keep their chunks
+    Cdc,
+}
+
+/// A snapshot: where it was taken, and every entry below the directory.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    /// Optional name given with `backup --name`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Unix time the snapshot was taken
+    pub time: u64,
+    /// Directory the snapshot was taken of
+    pub source: String,
+    /// Directories before their contents, names sorted
+    pub entries: Vec<SnapshotEntry>,
+}
+
+/// One file, directory or symbolic link of a snapshot.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    /// Path relative to the snapshot's directory, using `/` separators
+    pub path: String,
+    pub kind: EntryType,
+    /// Size of a file's contents
+    #[serde(default)]
+    pub size: u64,
+    /// Unix permission bits, where the platform has them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    /// Modification time, in seconds since the Unix epoch
+    #[serde(default)]
+    pub modified: u64,
+    /// Chunks of a file's contents, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<String>,
+    /// Target of a symbolic link
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+/// What a [`SnapshotEntry`] is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryType {
+    Dir,
+    File,
+    Symlink,
+}
+
+impl Snapshot {
+    /// A snapshot of `source` taken now.
+    pub fn new(name: Option<String>, source: String, entries: Vec<SnapshotEntry>) -> Self {
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            name,
+            time,
+            source,
+            entries,
+        }
+    }
+}
+
+/// Chunks of one stored file.
+pub struct Stored {
+    /// Ids of the chunks, in order
+    pub chunks: Vec<String>,
+    /// How many of them weren't in the repository yet
+    pub new_chunks: usize,
+    /// Bytes written for those
+    pub new_bytes: u64,
+}
+
+/// An open repository.
+pub struct Repository {
+    root: PathBuf,
+    master_key: SecureKey,
+    id_key: SecureKey,
+    chunker: Chunker,
+    chunk_size: u32,
+    /// zstd level chunks and indexes are compressed at
+    pub compression_level: i32,
+}
+
+impl Repository {
+    /// Whether `root` holds a repository.
+    pub fn exists(root: &Path) -> bool {
+        root.join(CONFIG_FILE).is_file()
+    }
+
+    /// Creates a repository in `root`, which must not exist or be empty, with a random
+    /// master key wrapped by `credential`.
+    pub async fn init(
+        root: &Path,
+        credential: keyslot::Credential<'_>,
+        params: crypto::Argon2Params,
+    ) -> Result<Self, BackupError> {
+        if root.exists() && fs::read_dir(root)?.next().is_some() {
+            return Err(BackupError::Repository(format!(
+                "'{}' is not empty and not a repository",
+                root.display()
+            )));
+        }
+        fs::create_dir_all(root.join(CHUNKS_DIR))?;
+        fs::create_dir_all(root.join(SNAPSHOTS_DIR))?;
+
+        let master_key = keyslot::generate_data_key();
+        let config = RepositoryConfig {
+            version: REPOSITORY_VERSION,
+            chunker: Chunker::Cdc,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        };
+        let body = [
+            &[0x00],
+            serde_json::to_vec(&config).unwrap_or_default().as_slice(),
+        ]
+        .concat();
+        let slot = keyslot::create(&master_key, credential, params, None).await?;
+        let encrypted = crypto::encrypt_with_keyslots(&body, &master_key, "", vec![slot], None)?;
+        replace(&root.join(CONFIG_FILE), &encrypted)?;
+        Self::with_key(root, master_key, &config)
+    }
+
+    /// Opens the repository in `root` with its password or key.
+    pub async fn open(
+        root: &Path,
+        password: Option<String>,
+        key: Option<&[u8]>,
+    ) -> Result<Self, BackupError> {
+        let config_path = root.join(CONFIG_FILE);
+        let data = fs::read(&config_path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => {
+                BackupError::Repository(format!("'{}' is not a repository", root.display()))
+            }
+            _ => BackupError::Io(e),
+        })?;
+        let parsed = XdReader::parse(&data).map_err(|_| {
+            BackupError::Repository(format!("'{}' is corrupted", config_path.display()))
+        })?;
+        let master_key = crypto::file_key(&parsed, password, key).await?;
+        let body = api::decode_body(crypto::decrypt_parsed(&parsed, &master_key)?)?;
+        let config: RepositoryConfig = serde_json::from_slice(&body).map_err(|e| {
+            BackupError::Repository(format!("'{}' is corrupted: {e}", config_path.display()))
+        })?;
+        if config.version != REPOSITORY_VERSION {
+            return Err(BackupError::Repository(format!(
+                "Repository '{}' has unsupported version {}",
+                root.display(),
+                config.version
+            )));
+        }
+        Self::with_key(root, master_key, &config)
+    }
+
+    fn with_key(
+        root: &Path,
+        master_key: SecureKey,
+        config: &RepositoryConfig,
+    ) -> Result<Self, BackupError> {
+        let mut id_key = [0u8; 32];
+        hkdf::Hkdf::<Sha256>::new(None, master_key.as_slice())
+            .expand(CHUNK_ID_INFO, &mut id_key)
+            .map_err(|_| CryptoError::KeyDerivationError("HKDF expansion failed".to_string()))?;
+        Ok(Repository {
+            root: root.to_path_buf(),
+            master_key,
+            id_key: SecureKey::new(id_key),
+            chunker: config.chunker,
+            chunk_size: config.chunk_size.max(1),
+            compression_level: crate::config::DEFAULT_COMPRESSION_LEVEL,
+        })
+    }
+
+    /// Cuts a file's contents into chunks and stores the ones the repository doesn't have
+    /// yet. `name` decides whether they are worth compressing.
+    pub fn store(&self, name: &str, data: &[u8]) -> Result<Stored, BackupError> {
+        let compress = compression::worth_compressing(name, data);
+        let mut stored = Stored {
+            chunks: Vec::new(),
+            new_chunks: 0,
+            new_bytes: 0,
+        };
+        let size = self.chunk_size as usize;
+        let pieces = match self.chunker {
+            Chunker::Fixed => (0..data.len())
+                .step_by(size)
+                .map(|start| start..(start + size).min(data.len()))
+                .collect(),
+            Chunker::Cdc => cdc::split(data, size / 4, size, size * 4),
+        };
+        for piece in pieces {
+            let chunk = &data[piece];
+            let id = self.chunk_id(chunk);
+            let path = self.chunk_path(&id);
+            if !path.is_file() {
+                let encrypted = self.seal(chunk, compress)?;
+                fs::create_dir_all(path.parent().unwrap_or(&self.root))?;
+                replace(&path, &encrypted)?;
+                stored.new_chunks += 1;
+                stored.new_bytes += encrypted.len() as u64;
+            }
+            stored.chunks.push(id);
+        }
+        Ok(stored)
+    }
+
+    /// Reads and decrypts a chunk, checking it is the one its id names.
+    pub async fn load_chunk(&self, id: &str) -> Result<Zeroizing<Vec<u8>>, BackupError> {
+        let path = self.chunk_path(id);
+        let data = fs::read(&path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => {
+                BackupError::Repository(format!("Chunk {id} is missing from the repository"))
+            }
+            _ => BackupError::Io(e),
+        })?;
+        let chunk = self.open_file(&data).await?;
+        if self.chunk_id(&chunk) != id {
+            return Err(BackupError::Repository(format!(
+                "Chunk {id} doesn't hold the data it names"
+            )));
+        }
+        Ok(chunk)
+    }
+
+    /// Writes the index of a snapshot, once all its chunks are stored, and returns its id:
+    /// the UTC time it was taken, e.g. `2024-06-01T12-30-00Z`.
+    pub fn save_snapshot(&self, snapshot: &Snapshot) -> Result<String, BackupError> {
+        let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(snapshot.time);
+        let base = humantime::format_rfc3339_seconds(time)
+            .to_string()
+            .replace(':', "-");
+        let index = serde_json::to_vec(snapshot).unwrap_or_default();
+        let encrypted = self.seal(&index, true)?;
+        let dir = self.root.join(SNAPSHOTS_DIR);
+        // Snapshots taken within the same second get a counter
+        let mut id = base.clone();
+        let mut n = 0;
+        while dir.join(&id).exists() {
+            n += 1;
+            id = format!("{base}-{n}");
+        }
+        replace(&dir.join(&id), &encrypted)?;
+        Ok(id)
+    }
+
+    /// Ids of the snapshots in the repository, oldest first.
+    pub fn snapshots(&self) -> Result<Vec<String>, BackupError> {
+        let mut ids = Vec::new();
+        for item in fs::read_dir(self.root.join(SNAPSHOTS_DIR))? {
+            let name = item?.file_name().to_string_lossy().into_owned();
+            // Leftovers of interrupted writes
+            if !name.starts_with('.') {
+                ids.push(name);
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// Id of the newest snapshot matching `spec`: `latest`, the start of its id (`2024-06-01`
+    /// picks the last snapshot of that day) or, failing those, its `--name`.
+    pub async fn find_snapshot(&self, spec: &str) -> Result<String, BackupError> {
+        let ids = self.snapshots()?;
+        let found = if spec == "latest" {
+            ids.last().cloned()
+        } else if let Some(id) = ids.iter().rev().find(|id| id.starts_with(spec)) {
+            Some(id.clone())
+        } else {
+            let mut named = None;
+            for id in ids.iter().rev() {
+                if self.load_snapshot(id).await?.name.as_deref() == Some(spec) {
+                    named = Some(id.clone());
+                    break;
+                }
+            }
+            named
+        };
+        found.ok_or_else(|| BackupError::Repository(format!("No snapshot matches '{spec}'")))
+    }
+
+    /// Reads and decrypts the index of a snapshot.
+    pub async fn load_snapshot(&self, id: &str) -> Result<Snapshot, BackupError> {
+        let path = self.root.join(SNAPSHOTS_DIR).join(id);
+        let data = fs::read(&path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => BackupError::Repository(format!("No snapshot {id}")),
+            _ => BackupError::Io(e),
+        })?;
+        let index = self.open_file(&data).await?;
+        let snapshot: Snapshot = serde_json::from_slice(&index)
+            .map_err(|e| BackupError::Repository(format!("Snapshot {id} is corrupted: {e}")))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(BackupError::Repository(format!(
+                "Snapshot {id} has unsupported version {}",
+                snapshot.version
+            )));
+        }
+        Ok(snapshot)
+    }
+
+    /// How the repository cuts files into chunks.
+    pub fn chunker(&self) -> Chunker {
+        self.chunker
+    }
+
+    fn chunk_id(&self, chunk: &[u8]) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(self.id_key.as_slice())
+            .expect("HMAC takes keys of any length");
+        mac.update(chunk);
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+
+    fn chunk_path(&self, id: &str) -> PathBuf {
+        self.root
+            .join(CHUNKS_DIR)
+            .join(id.get(..2).unwrap_or_default())
+            .join(id)
+    }
+
+    /// Encrypts data under the master key, as an anonymous master-key `.xd` file.
+    fn seal(&self, data: &[u8], compress: bool) -> Result<Vec<u8>, BackupError> {
+        let level = compress.then_some(self.compression_level);
+        let body = Zeroizing::new(api::encode_into(
+            data,
+            level,
+            compression::Tuning::default(),
+            Vec::with_capacity(data.len() + 1),
+        )?);
+        Ok(crypto::encrypt_with_master_key(
+            &body,
+            self.master_key.as_slice(),
+            "",
+            None,
+        )?)
+    }
+
+    async fn open_file(&self, data: &[u8]) -> Result<Zeroizing<Vec<u8>>, BackupError> {
+        let parsed = XdReader::parse(data)
+            .map_err(|_| BackupError::Repository("A repository file is corrupted".to_string()))?;
+        let key = crypto::file_key(&parsed, None, Some(self.master_key.as_slice())).await?;
+        Ok(api::decode_body(crypto::decrypt_parsed(&parsed, &key)?)?)
+    }
+}
+
+/// Writes `data` to a temporary file next to `path`, syncs it and renames it over `path`.
+fn replace(path: &Path, data: &[u8]) -> io::Result<()> {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("file");
+    let temp = path.with_file_name(format!(".{name}.{}.tmp", std::process::id()));
+    let result = (|| {
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&temp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
@@ -0,0 +1,249 @@
+//!
+//! Capability discovery: what this build and configuration supports.
+//!
+//! The same report is served by the API at `GET /capabilities` and printed by
+//! `capabilities --json`, so clients and scripts can check a deployment's algorithms, limits
+//! and optional features instead of assuming them. Everything comes from constants, Cargo
+//! features and the loaded config; nothing is probed at runtime.
+//!
+use crate::api;
+use crate::config::Config;
+use crate::crypto::{self, KdfProfile, chunked, keyslot};
+use crate::format::{self, CURRENT_VERSION, ENVELOPE_VERSION};
+use serde::Serialize;
+
+/// Header `version` written for key-mode files (envelope files; see [`crypto::rewrap`]).
+pub const KEY_FORMAT_VERSION: u32 = ENVELOPE_VERSION as u32;
+
+/// Header `version` written for password-mode files.
+pub const PASSWORD_FORMAT_VERSION: u32 = CURRENT_VERSION as u32;
+
+/// A key derivation function and the parameters accepted for it.
+#[derive(Debug, Serialize)]
+pub struct KdfCapability {
+    pub name: &'static str,
+    /// Whether new password-mode files use this KDF (`kdf` in the config)
+    pub default: bool,
+    /// Named parameter sets, weakest first
+    pub profiles: Vec<KdfProfileCapability>,
+    /// Profile used for new password-mode files
+    pub default_profile: String,
+    /// Largest parameters accepted from a file header
+    pub max_memory_cost: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_time_cost: Option<u32>,
+    pub max_parallelism: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_log_n: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_block_size: Option<u32>,
+}
+
+/// One named parameter set; Argon2 sets have a time cost, scrypt sets `log_n` and a
+/// block size.
+#[derive(Debug, Serialize)]
+pub struct KdfProfileCapability {
+    pub name: &'static str,
+    /// Memory cost in KB
+    pub memory_cost: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_cost: Option<u32>,
+    pub parallelism: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_n: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_size: Option<u32>,
+}
+
+/// Header versions written for each mode.
+#[derive(Debug, Serialize)]
+pub struct FormatVersions {
+    pub key: u32,
+    pub password: u32,
+}
+
+/// A compression algorithm and its levels.
+#[derive(Debug, Serialize)]
+pub struct CompressionCapability {
+    pub name: &'static str,
+    pub min_level: i32,
+    pub max_level: i32,
+    /// Level used for new files
+    pub level: i32,
+    /// Worker threads for large inputs
+    pub threads: u32,
+    pub long_distance_matching: bool,
+}
+
+/// Size limits, in bytes unless noted.
+#[derive(Debug, Serialize)]
+pub struct Limits {
+    /// Largest request body the API accepts
+    pub max_payload_bytes: u64,
+    /// Smallest and largest plaintext chunk size of the chunked layout
+    pub min_chunk_size: u32,
+    pub max_chunk_size: u32,
+    /// Configured plaintext chunk size for new files, if any
+    pub chunk_size: Option<u32>,
+    /// Largest header JSON of an `.xd` file
+    pub max_header_bytes: usize,
+    /// Most keyslots one file may carry
+    pub max_keyslots: usize,
+    /// Largest size a decrypted body may decompress to
+    pub max_decompressed_bytes: u64,
+}
+
+/// Optional features and whether this build or deployment has them.
+#[derive(Debug, Serialize)]
+pub struct Features {
+    /// The server speaks plain HTTP; TLS is terminated by a proxy in front of it
+    pub tls: bool,
+    /// The API has no authentication; credentials only protect files
+    pub auth: bool,
+    /// Where the server reads stored files from: `local` when `server.stream_dir` is
+    /// served by `/stream`; otherwise every request carries its file
+    pub storage_backends: Vec<&'static str>,
+    /// `--format pgp` and OpenPGP decryption (`openpgp` Cargo feature)
+    pub openpgp: bool,
+    /// Decrypting password-protected 7z archives (`archive-ingest` Cargo feature)
+    pub archive_ingest: bool,
+    /// Whether `server.memory_limit_mb` turns away password-mode requests under pressure
+    pub memory_watchdog: bool,
+    /// Whether `server.share_secret` enables passcode-guarded `/share` links
+    pub share_links: bool,
+}
+
+/// Everything a client may want to know before talking to this deployment.
+#[derive(Debug, Serialize)]
+pub struct Capabilities {
+    /// Crate version of the build
+    pub version: &'static str,
+    pub ciphers: Vec<&'static str>,
+    pub kdfs: Vec<KdfCapability>,
+    pub format_versions: FormatVersions,
+    /// Body layouts of `.xd` files
+    pub layouts: Vec<&'static str>,
+    /// Formats `encrypt --format` can write
+    pub output_formats: Vec<&'static str>,
+    pub compression: Vec<CompressionCapability>,
+    /// Credentials a keyslot can be bound to
+    pub keyslot_kinds: Vec<&'static str>,
+    /// Key encapsulation suites of `kem` keyslots
+    pub kem_suites: Vec<&'static str>,
+    pub signatures: Vec<&'static str>,
+    pub limits: Limits,
+    pub features: Features,
+}
+
+impl Capabilities {
+    /// Builds the report for this build running with `config`.
+    pub fn new(config: &Config) -> Self {
+        let levels = zstd::compression_level_range();
+        let tuning = config.compression_tuning();
+        let mut output_formats = vec!["xd", "zip-aes"];
+        if cfg!(feature = "openpgp") {
+            output_formats.push("pgp");
+        }
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            ciphers: vec!["aes-256-gcm"],
+            kdfs: vec![
+                KdfCapability {
+                    name: "argon2id",
+                    default: config.kdf.eq_ignore_ascii_case("argon2id"),
+                    profiles: KdfProfile::ALL
+                        .iter()
+                        .map(|profile| {
+                            let params = profile.params();
+                            KdfProfileCapability {
+                                name: profile.name(),
+                                memory_cost: u64::from(params.memory_cost),
+                                time_cost: Some(params.time_cost),
+                                parallelism: params.parallelism,
+                                log_n: None,
+                                block_size: None,
+                            }
+                        })
+                        .collect(),
+                    default_profile: config.kdf_profile.clone(),
+                    max_memory_cost: crypto::ARGON2_MAX_MEMORY_COST,
+                    max_time_cost: Some(crypto::ARGON2_MAX_TIME_COST),
+                    max_parallelism: crypto::ARGON2_MAX_PARALLELISM,
+                    max_log_n: None,
+                    max_block_size: None,
+                },
+                KdfCapability {
+                    name: "scrypt",
+                    default: config.kdf.eq_ignore_ascii_case("scrypt"),
+                    profiles: KdfProfile::ALL
+                        .iter()
+                        .map(|profile| {
+                            let params = profile.scrypt_params();
+                            KdfProfileCapability {
+                                name: profile.name(),
+                                memory_cost: params.memory_cost(),
+                                time_cost: None,
+                                parallelism: params.parallelism,
+                                log_n: Some(params.log_n),
+                                block_size: Some(params.block_size),
+                            }
+                        })
+                        .collect(),
+                    default_profile: config.kdf_profile.clone(),
+                    max_memory_cost: crypto::ARGON2_MAX_MEMORY_COST,
+                    max_time_cost: None,
+                    max_parallelism: crypto::SCRYPT_MAX_PARALLELISM,
+                    max_log_n: Some(crypto::SCRYPT_MAX_LOG_N),
+                    max_block_size: Some(crypto::SCRYPT_MAX_BLOCK_SIZE),
+                },
+            ],
+            format_versions: FormatVersions {
+                key: KEY_FORMAT_VERSION,
+                password: PASSWORD_FORMAT_VERSION,
+            },
+            layouts: vec!["single", "chunked", "delta"],
+            output_formats,
+            compression: vec![CompressionCapability {
+                name: "zstd",
+                min_level: *levels.start(),
+                max_level: *levels.end(),
+                level: config.compression_level,
+                threads: tuning.threads,
+                long_distance_matching: tuning.long_distance_matching,
+            }],
+            keyslot_kinds: vec![
+                keyslot::KIND_PASSWORD,
+                keyslot::KIND_KEY,
+                keyslot::KIND_X25519,
+                keyslot::KIND_KEM,
+                keyslot::KIND_FIDO2,
+                keyslot::KIND_PKCS11,
+                keyslot::KIND_TPM2,
+                keyslot::KIND_KMS,
+            ],
+            kem_suites: vec![keyslot::SUITE_X25519_MLKEM768],
+            signatures: vec!["ed25519"],
+            limits: Limits {
+                max_payload_bytes: config.server.max_payload_mb as u64 * 1024 * 1024,
+                min_chunk_size: chunked::MIN_CHUNK_SIZE,
+                max_chunk_size: chunked::MAX_CHUNK_SIZE,
+                chunk_size: config.chunk_size().ok().flatten(),
+                max_header_bytes: format::MAX_HEADER_LEN,
+                max_keyslots: format::MAX_KEYSLOTS,
+                max_decompressed_bytes: config
+                    .max_decompressed_size()
+                    .unwrap_or(api::DEFAULT_MAX_DECOMPRESSED_SIZE),
+            },
+            features: Features {
+                tls: false,
+                auth: false,
+                storage_backends: config.server.stream_dir.iter().map(|_| "local").collect(),
+                openpgp: cfg!(feature = "openpgp"),
+                archive_ingest: cfg!(feature = "archive-ingest"),
+                memory_watchdog: config.server.memory_limit_mb.is_some(),
+                share_links: config.server.share_secret.is_some()
+                    && config.server.stream_dir.is_some(),
+            },
+        }
+    }
+}
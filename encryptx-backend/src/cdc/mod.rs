@@ -0,0 +1,56 @@
+//!
+//! Content-defined chunking.
+//!
+//! Cut points are chosen where a gear hash over the preceding bytes matches a bit mask, so
+//! they depend only on nearby content: inserting or removing bytes moves the cut points
+//! around the edit but leaves all others (relative to the content) where they were.
+//!
+use std::ops::Range;
+
+/// Pseudo-random table for the gear hash, generated with splitmix64 so it is stable
+/// across builds and platforms (cut points must never change for the same input).
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x6a09_e667_f3bc_c908;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// Splits `data` into content-defined pieces of `min..=max` bytes averaging about `avg`.
+///
+/// The last piece may be shorter than `min`. Empty input yields no pieces.
+pub fn split(data: &[u8], min: usize, avg: usize, max: usize) -> Vec<Range<usize>> {
+    let min = min.max(1);
+    let max = max.max(min);
+    // Cut when the top bits are zero; the expected gap past `min` is about 2^bits
+    let bits = avg.saturating_sub(min).max(2).ilog2();
+    let mask = !(u64::MAX >> bits);
+
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        let end = (start + max).min(data.len());
+        let mut cut = end;
+        let mut hash = 0u64;
+        let mut pos = start + min;
+        while pos < end {
+            hash = (hash << 1).wrapping_add(GEAR[data[pos] as usize]);
+            if hash & mask == 0 {
+                cut = pos + 1;
+                break;
+            }
+            pos += 1;
+        }
+        pieces.push(start..cut);
+        start = cut;
+    }
+    pieces
+}
@@ -0,0 +1,381 @@
+//!
+//! `agent` subcommand: a background process that caches unlocked file keys, so batches of
+//! commands don't derive (or ask for) the same key again.
+//!
+//! After `decrypt --password` opens a file, its file key (the Argon2-derived key of a
+//! password file, or the data key a keyslot unlocked) is handed to a running agent. Within
+//! the agent's TTL the same file then decrypts without a credential, and the keystore file
+//! of `key add` opens without asking for its passphrase. Keys are filed under SHA-256 of the
+//! file's header, which changes whenever the file is rekeyed or gains a keyslot.
+//!
+//! Clients reach the agent over a Unix socket (mode 0600) at `ENCRYPTX_AGENT_SOCK`,
+//! `$XDG_RUNTIME_DIR/encryptx-agent.sock` or `agent.sock` in the config directory. Each
+//! connection carries one JSON request line and gets one JSON response line. When no agent
+//! is listening, clients carry on as if it had no keys.
+//!
+use super::{CliError, print_json_report, status};
+use crate::config::Config;
+use crate::crypto::{self, SecureKey};
+use crate::format::XdReader;
+use crate::locale::tr;
+use base64::{Engine, engine::general_purpose};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::time::Duration;
+use zeroize::{Zeroize, Zeroizing};
+
+/// Environment variable naming the agent's socket.
+const SOCKET_ENV: &str = "ENCRYPTX_AGENT_SOCK";
+
+/// How long a client waits for the agent before going on without it.
+#[cfg(unix)]
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A request line sent to the agent.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "kebab-case")]
+enum Request {
+    Get { id: String },
+    Put { id: String, secret: String },
+    Clear,
+    Status,
+    Stop,
+}
+
+impl Drop for Request {
+    fn drop(&mut self) {
+        if let Request::Put { secret, .. } = self {
+            secret.zeroize();
+        }
+    }
+}
+
+/// The agent's answer to a request.
+#[derive(Default, Serialize, Deserialize)]
+struct Response {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entries: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ttl_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Drop for Response {
+    fn drop(&mut self) {
+        if let Some(secret) = &mut self.secret {
+            secret.zeroize();
+        }
+    }
+}
+
+/// Result of the `agent` actions, printed with `--json`.
+#[derive(Serialize)]
+pub struct AgentReport {
+    pub operation: &'static str,
+    pub socket: String,
+    /// Whether an agent answered on the socket
+    pub running: bool,
+    /// Number of cached keys
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entries: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl_secs: Option<u64>,
+}
+
+/// The agent's socket: `ENCRYPTX_AGENT_SOCK`, else in `$XDG_RUNTIME_DIR`, else in the
+/// config directory.
+pub fn socket_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(SOCKET_ENV).filter(|v| !v.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|v| !v.is_empty()) {
+        Some(dir) => Some(PathBuf::from(dir).join("encryptx-agent.sock")),
+        None => Some(Config::config_dir()?.join("agent.sock")),
+    }
+}
+
+/// Name a file's key is cached under.
+fn file_id(file: &XdReader<'_>) -> String {
+    let digest = Sha256::digest(file.header_json);
+    let hex: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+    format!("file:{hex}")
+}
+
+/// Sends `request` to the agent; `None` if no agent answers.
+#[cfg(unix)]
+fn send(request: &Request) -> Option<Response> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    let mut stream = UnixStream::connect(socket_path()?).ok()?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT)).ok()?;
+    let mut line = Zeroizing::new(serde_json::to_vec(request).ok()?);
+    line.push(b'\n');
+    stream.write_all(&line).ok()?;
+    let mut answer = Zeroizing::new(String::new());
+    BufReader::new(stream).read_line(&mut answer).ok()?;
+    serde_json::from_str(&answer).ok()
+}
+
+#[cfg(not(unix))]
+fn send(_request: &Request) -> Option<Response> {
+    None
+}
+
+/// The key of `file` if a running agent has it cached.
+pub fn cached_file_key(file: &XdReader<'_>) -> Option<SecureKey> {
+    let response = send(&Request::Get { id: file_id(file) })?;
+    let bytes = Zeroizing::new(
+        general_purpose::STANDARD
+            .decode(response.secret.as_deref()?)
+            .ok()?,
+    );
+    Some(SecureKey::new(bytes.as_slice().try_into().ok()?))
+}
+
+/// Hands the key that opened `file` to the agent, if one is running.
+pub fn remember_file_key(file: &XdReader<'_>, key: &SecureKey) {
+    send(&Request::Put {
+        id: file_id(file),
+        secret: general_purpose::STANDARD.encode(key.as_slice()),
+    });
+}
+
+/// Decrypts a password-protected file (or keyslot file) like
+/// [`crypto::decrypt_with_password_async`], handing its file key to the agent.
+pub async fn decrypt_with_password(
+    data: &[u8],
+    password: String,
+) -> Result<(Vec<u8>, String), crypto::CryptoError> {
+    let password = Zeroizing::new(password);
+    let file = XdReader::parse(data)?;
+    let key = crypto::file_key(&file, Some(password.to_string()), None).await?;
+    let plaintext = crypto::decrypt_parsed(&file, &key)?;
+    remember_file_key(&file, &key);
+    Ok((plaintext, file.header.filename().to_string()))
+}
+
+fn report(
+    operation: &'static str,
+    socket: &std::path::Path,
+    response: Option<&Response>,
+) -> AgentReport {
+    AgentReport {
+        operation,
+        socket: socket.display().to_string(),
+        running: response.is_some(),
+        entries: response.and_then(|r| r.entries),
+        ttl_secs: response.and_then(|r| r.ttl_secs),
+    }
+}
+
+fn no_socket() -> CliError {
+    CliError::InvalidInput(format!(
+        "Cannot place the agent socket; set {SOCKET_ENV} or HOME"
+    ))
+}
+
+/// Handles `agent status`.
+pub fn status_command(json: bool) -> Result<(), CliError> {
+    let socket = socket_path().ok_or_else(no_socket)?;
+    let response = send(&Request::Status);
+    if json {
+        print_json_report(&report("agent-status", &socket, response.as_ref()));
+        return Ok(());
+    }
+    match response {
+        Some(response) => println!(
+            "{}",
+            tr!(
+                "agent-status",
+                socket = socket.display(),
+                keys = response.entries.unwrap_or_default(),
+                ttl = response.ttl_secs.unwrap_or_default()
+            )
+        ),
+        None => println!("{}", tr!("agent-not-running", socket = socket.display())),
+    }
+    Ok(())
+}
+
+/// Handles `agent clear` and `agent stop`, which both drop every cached key.
+pub fn forget_command(stop: bool, json: bool) -> Result<(), CliError> {
+    let socket = socket_path().ok_or_else(no_socket)?;
+    let response = send(&if stop { Request::Stop } else { Request::Clear });
+    let (operation, done) = if stop {
+        ("agent-stop", tr!("agent-stopped"))
+    } else {
+        ("agent-clear", tr!("agent-cleared"))
+    };
+    if response.is_none() {
+        return Err(CliError::InvalidInput(format!(
+            "No agent is running at '{}'",
+            socket.display()
+        )));
+    }
+    if json {
+        print_json_report(&report(operation, &socket, response.as_ref()));
+    } else {
+        status(done);
+    }
+    Ok(())
+}
+
+/// Handles `agent start`: serves the cache in the foreground until stopped.
+#[cfg(unix)]
+pub async fn start_command(ttl: Option<&str>, config: &Config, json: bool) -> Result<(), CliError> {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::UnixListener;
+
+    let ttl = match ttl {
+        Some(ttl) => humantime::parse_duration(ttl)
+            .map_err(|e| CliError::InvalidInput(format!("Invalid --ttl '{ttl}': {e}")))?,
+        None => config
+            .agent_ttl()
+            .map_err(|e| CliError::InvalidInput(e.to_string()))?,
+    };
+    let socket = socket_path().ok_or_else(no_socket)?;
+    if send(&Request::Status).is_some() {
+        return Err(CliError::InvalidInput(format!(
+            "An agent is already running at '{}'",
+            socket.display()
+        )));
+    }
+    // Left behind by an agent that was killed
+    let _ = fs::remove_file(&socket);
+    if let Some(dir) = socket.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+    }
+    let listener = UnixListener::bind(&socket).map_err(|e| {
+        CliError::Io(std::io::Error::new(
+            e.kind(),
+            format!("Failed to listen on '{}': {e}", socket.display()),
+        ))
+    })?;
+    fs::set_permissions(&socket, fs::Permissions::from_mode(0o600))?;
+
+    if json {
+        print_json_report(&AgentReport {
+            operation: "agent-start",
+            socket: socket.display().to_string(),
+            running: true,
+            entries: Some(0),
+            ttl_secs: Some(ttl.as_secs()),
+        });
+    } else {
+        status(tr!(
+            "agent-listening",
+            socket = socket.display(),
+            ttl = humantime::format_duration(ttl)
+        ));
+        status(tr!(
+            "agent-export-hint",
+            variable = SOCKET_ENV,
+            socket = socket.display()
+        ));
+    }
+
+    type Cache = Arc<Mutex<HashMap<String, (Zeroizing<String>, Instant)>>>;
+    let cache: Cache = Arc::default();
+    let (stop_tx, mut stop_rx) = tokio::sync::mpsc::channel::<()>(1);
+    let mut sweep =
+        tokio::time::interval(ttl.clamp(Duration::from_secs(1), Duration::from_secs(60)));
+
+    let answer = move |cache: &Cache, request: &Request| -> (Response, bool) {
+        let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache.retain(|_, (_, expires)| *expires > Instant::now());
+        let mut response = Response::default();
+        let mut stop = false;
+        match request {
+            Request::Get { id } => {
+                response.secret = cache.get(id).map(|(secret, _)| secret.to_string());
+            }
+            Request::Put { id, secret } => {
+                cache.insert(
+                    id.clone(),
+                    (Zeroizing::new(secret.clone()), Instant::now() + ttl),
+                );
+            }
+            Request::Clear => cache.clear(),
+            Request::Status => {}
+            Request::Stop => {
+                cache.clear();
+                stop = true;
+            }
+        }
+        response.entries = Some(cache.len());
+        response.ttl_secs = Some(ttl.as_secs());
+        (response, stop)
+    };
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let Ok((stream, _)) = accepted else { continue };
+                let (cache, stop_tx) = (cache.clone(), stop_tx.clone());
+                tokio::spawn(async move {
+                    let (reader, mut writer) = stream.into_split();
+                    let mut reader = BufReader::new(reader);
+                    let mut line = Zeroizing::new(String::new());
+                    let read = tokio::time::timeout(
+                        Duration::from_secs(5),
+                        reader.read_line(&mut line),
+                    );
+                    if !matches!(read.await, Ok(Ok(_))) {
+                        return;
+                    }
+                    let (response, stop) = match serde_json::from_str::<Request>(&line) {
+                        Ok(request) => answer(&cache, &request),
+                        Err(e) => {
+                            let mut response = Response::default();
+                            response.error = Some(format!("Bad request: {e}"));
+                            (response, false)
+                        }
+                    };
+                    if let Ok(bytes) = serde_json::to_vec(&response) {
+                        let mut bytes = Zeroizing::new(bytes);
+                        bytes.push(b'\n');
+                        let _ = writer.write_all(&bytes).await;
+                    }
+                    if stop {
+                        let _ = stop_tx.send(()).await;
+                    }
+                });
+            }
+            _ = sweep.tick() => {
+                let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+                cache.retain(|_, (_, expires)| *expires > Instant::now());
+            }
+            _ = stop_rx.recv() => break,
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    cache.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    let _ = fs::remove_file(&socket);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub async fn start_command(
+    _ttl: Option<&str>,
+    _config: &Config,
+    _json: bool,
+) -> Result<(), CliError> {
+    Err(CliError::InvalidInput(
+        "The agent listens on a Unix domain socket, which this platform does not support"
+            .to_string(),
+    ))
+}
@@ -0,0 +1,265 @@
+//!
+//! `backup` subcommand: takes a snapshot of a directory into a repository (see
+//! [`crate::backup`]), creating the repository on first use.
+//!
+//! Each file is read, cut into chunks and only the chunks the repository doesn't hold yet
+//! are encrypted and written, so a second snapshot of a mostly unchanged tree costs little
+//! more than its index. The index is written last: a backup that fails halfway leaves no
+//! snapshot, only chunks the next one reuses.
+//!
+use super::{
+    BackupArgs, CliError, check_password_strength, keystore, read_input, status, validate_key,
+    walk_filter,
+};
+use crate::backup::{EntryType, Repository, Snapshot, SnapshotEntry};
+use crate::compression;
+use crate::config::Config;
+use crate::crypto::keyslot::Credential;
+use crate::crypto::secret::SecretBuffer;
+use crate::locale::tr;
+use crate::walk::{self, EntryKind, SymlinkPolicy};
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Instant, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+/// Result of a `backup` run, printed with `--json`.
+#[derive(Serialize)]
+pub struct BackupReport {
+    pub operation: &'static str,
+    pub repository: String,
+    pub snapshot: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub files: usize,
+    pub dirs: usize,
+    pub symlinks: usize,
+    /// Total size of the files
+    pub size: u64,
+    /// Chunks the files were cut into, and how many of them were new
+    pub chunks: usize,
+    pub new_chunks: usize,
+    /// Bytes written to the repository for the new chunks
+    pub added_bytes: u64,
+    pub duration_ms: u128,
+}
+
+/// Handles the `backup` subcommand.
+pub async fn backup_command(args: BackupArgs, config: &Config) -> Result<BackupReport, CliError> {
+    let started = Instant::now();
+    let BackupArgs {
+        dir,
+        repo,
+        name,
+        password,
+        key,
+        key_name,
+        follow_symlinks,
+        skip_symlinks,
+        exclude,
+        exclude_from,
+        include,
+    } = args;
+    let key = repository_key(&password, key, key_name, config).await?;
+    let symlinks = if follow_symlinks {
+        SymlinkPolicy::Follow
+    } else if skip_symlinks {
+        SymlinkPolicy::Skip
+    } else {
+        SymlinkPolicy::Store
+    };
+    let filter = walk_filter(&exclude_from, &exclude, &include)?;
+    let level = compression::validate_level(config.compression_level)
+        .map_err(|e| CliError::InvalidInput(format!("compression_level: {e}")))?;
+
+    let root = fs::canonicalize(&dir).map_err(|e| {
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to resolve '{dir}': {e}"),
+        ))
+    })?;
+    if !root.is_dir() {
+        return Err(CliError::InvalidInput(format!(
+            "backup needs a directory, and '{dir}' is not one"
+        )));
+    }
+    let repo_path = Path::new(&repo);
+    // The repository may not exist yet, so resolve the directory it goes into
+    let resolved = match fs::canonicalize(repo_path) {
+        Ok(canonical) => Some(canonical),
+        Err(_) => repo_path.file_name().and_then(|file_name| {
+            let parent = match repo_path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            fs::canonicalize(parent).ok().map(|p| p.join(file_name))
+        }),
+    };
+    if resolved.is_some_and(|resolved| resolved.starts_with(&root)) {
+        return Err(CliError::InvalidInput(format!(
+            "The repository '{repo}' is inside the directory being backed up"
+        )));
+    }
+
+    let mut repository = if Repository::exists(repo_path) {
+        Repository::open(repo_path, password, key.as_deref())
+            .await
+            .map_err(|e| CliError::from_backup("Opening the repository failed", e))?
+    } else {
+        let credential = match (&password, &key) {
+            (Some(password), _) => {
+                check_password_strength(password, &repo, false)?;
+                Credential::Password(Zeroizing::new(password.clone()))
+            }
+            (None, key) => Credential::Key(key.as_deref().unwrap_or_default()),
+        };
+        let params = config
+            .kdf_profile()
+            .map_err(|e| CliError::InvalidInput(e.to_string()))?
+            .params();
+        let repository = Repository::init(repo_path, credential, params)
+            .await
+            .map_err(|e| CliError::from_backup("Creating the repository failed", e))?;
+        status(tr!("backup-created-repository", repo = repo));
+        repository
+    };
+    repository.compression_level = level;
+
+    status(tr!("backup-started", dir = dir, repo = repo));
+    let walked = walk::walk(&root, symlinks, &filter).map_err(|e| {
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to walk '{dir}': {e}"),
+        ))
+    })?;
+    let mut entries = Vec::with_capacity(walked.len());
+    let (mut chunks, mut new_chunks, mut added_bytes) = (0, 0, 0);
+    for entry in walked {
+        let read_error = |e: io::Error| {
+            CliError::Io(io::Error::new(
+                e.kind(),
+                format!("Failed to read '{}': {e}", entry.path.display()),
+            ))
+        };
+        let (kind, meta, target) = match &entry.kind {
+            EntryKind::Dir => (EntryType::Dir, fs::metadata(&entry.path), None),
+            EntryKind::File => (EntryType::File, fs::metadata(&entry.path), None),
+            EntryKind::Symlink(target) => (
+                EntryType::Symlink,
+                fs::symlink_metadata(&entry.path),
+                Some(target.to_string_lossy().into_owned()),
+            ),
+            EntryKind::Other => {
+                status(tr!("backup-skipped-special", entry = entry.name));
+                continue;
+            }
+        };
+        let meta = meta.map_err(read_error)?;
+        let mut record = SnapshotEntry {
+            path: entry.name.clone(),
+            kind,
+            size: 0,
+            mode: mode(&meta),
+            modified: meta
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |since| since.as_secs()),
+            chunks: Vec::new(),
+            target,
+        };
+        if kind == EntryType::File {
+            let data = read_input(&entry.path.to_string_lossy(), "file")?;
+            let stored = repository
+                .store(&entry.name, &data)
+                .map_err(|e| CliError::from_backup("Storing the data failed", e))?;
+            record.size = data.len() as u64;
+            chunks += stored.chunks.len();
+            new_chunks += stored.new_chunks;
+            added_bytes += stored.new_bytes;
+            record.chunks = stored.chunks;
+        }
+        entries.push(record);
+    }
+
+    let count = |kind| entries.iter().filter(|e| e.kind == kind).count();
+    let (files, dirs, symlinks) = (
+        count(EntryType::File),
+        count(EntryType::Dir),
+        count(EntryType::Symlink),
+    );
+    let size = entries.iter().map(|e| e.size).sum();
+    let snapshot = Snapshot::new(name.clone(), root.to_string_lossy().into_owned(), entries);
+    let id = repository
+        .save_snapshot(&snapshot)
+        .map_err(|e| CliError::from_backup("Saving the snapshot failed", e))?;
+
+    let label = match &name {
+        Some(name) => format!("{id} ({name})"),
+        None => id.clone(),
+    };
+    status(tr!(
+        "backup-snapshot",
+        snapshot = label,
+        dir = dir,
+        files = files,
+        bytes = size
+    ));
+    status(tr!(
+        "backup-chunks",
+        new = new_chunks,
+        total = chunks,
+        bytes = added_bytes
+    ));
+    Ok(BackupReport {
+        operation: "backup",
+        repository: repo,
+        snapshot: id,
+        name,
+        files,
+        dirs,
+        symlinks,
+        size,
+        chunks,
+        new_chunks,
+        added_bytes,
+        duration_ms: started.elapsed().as_millis(),
+    })
+}
+
+/// Resolves `--key` or `--key-name`, and checks exactly one credential for the repository
+/// was given.
+pub(super) async fn repository_key(
+    password: &Option<String>,
+    key: Option<String>,
+    key_name: Option<String>,
+    config: &Config,
+) -> Result<Option<SecretBuffer>, CliError> {
+    let key = match key_name {
+        Some(name) => Some(keystore::load_key(&name, config).await?.to_string()),
+        None => key,
+    };
+    let key = key.as_deref().map(validate_key).transpose()?;
+    if password.is_some() == key.is_some() {
+        return Err(CliError::InvalidInput(
+            "The repository needs either --password or --key (or --key-name)".to_string(),
+        ));
+    }
+    Ok(key)
+}
+
+/// Permission bits to restore the entry with.
+fn mode(meta: &fs::Metadata) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        Some(meta.permissions().mode() & 0o7777)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = meta;
+        None
+    }
+}
@@ -0,0 +1,382 @@
+//!
+//! `encrypt` over several files: `--file` given more than once, or files and glob patterns
+//! such as `'docs/**/*.pdf'` after the options. Each file is encrypted on its own into
+//! `<name>.xd` next to it, or below `--output DIR` in the same relative place, by a pool of
+//! `--jobs` workers; a failure on one file doesn't stop the others, and a summary lists
+//! what happened to each.
+//!
+//! Patterns are expanded here rather than by the shell, so `**` works everywhere and a
+//! pattern matching nothing is an error instead of a file of that name. The CLI runs on a
+//! single-threaded runtime, so each worker is a thread with a runtime of its own.
+//!
+use super::tree::settle_credentials;
+use super::{
+    CliError, EncryptArgs, STATUS_MUTED, STDIO_PATH, encrypt_command, print_json_report, status,
+};
+use crate::compression;
+use crate::config::Config;
+use crate::locale::tr;
+use crate::walk::{self, EntryKind, Filter, SymlinkPolicy};
+use globset::GlobBuilder;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// What happened to one file.
+#[derive(Serialize)]
+struct FileResult {
+    file: String,
+    /// "encrypted" or "failed"
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<String>,
+    /// Why the file failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+/// Result of encrypting several files, printed with `--json`.
+#[derive(Serialize)]
+struct BatchReport {
+    operation: &'static str,
+    mode: &'static str,
+    encrypted: usize,
+    failed: usize,
+    /// Bytes of plaintext encrypted
+    input_size: usize,
+    /// Bytes of ciphertext written
+    output_size: usize,
+    /// Randomly generated key, base64 (only when one was generated)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    generated_key: Option<String>,
+    files: Vec<FileResult>,
+    duration_ms: u128,
+}
+
+/// Whether `input` is a glob pattern rather than a path.
+fn is_pattern(input: &str) -> bool {
+    input.contains(['*', '?', '[', '{'])
+}
+
+/// Moves a lone input file into `args.file` for the ordinary single-file encryption;
+/// returns false when several files or a pattern were given, for [`encrypt_batch`].
+pub(super) fn take_single_input(args: &mut EncryptArgs) -> bool {
+    let mut inputs = args.files.iter().chain(&args.paths);
+    match (inputs.next(), inputs.next()) {
+        (None, _) => true,
+        (Some(input), None) if !is_pattern(input) || Path::new(input).exists() => {
+            args.file = Some(input.clone());
+            true
+        }
+        _ => false,
+    }
+}
+
+/// The files a pattern matches, in walk order. The directories before the first component
+/// with a wildcard are where the walk starts.
+fn expand(pattern: &str) -> Result<Vec<String>, CliError> {
+    let components: Vec<&str> = pattern.split('/').collect();
+    let fixed = components
+        .iter()
+        .position(|component| is_pattern(component))
+        .unwrap_or(components.len());
+    let base = components[..fixed].join("/");
+    let rest = components[fixed..].join("/");
+    let matcher = GlobBuilder::new(&rest)
+        .literal_separator(true)
+        .build()
+        .map_err(|e| CliError::InvalidInput(format!("Invalid pattern '{pattern}': {e}")))?
+        .compile_matcher();
+    let root = match base.as_str() {
+        "" if pattern.starts_with('/') => "/",
+        "" => ".",
+        base => base,
+    };
+    let entries = match walk::walk(Path::new(root), SymlinkPolicy::Skip, &Filter::default()) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => {
+            return Err(CliError::Io(io::Error::new(
+                e.kind(),
+                format!("Failed to expand '{pattern}': {e}"),
+            )));
+        }
+    };
+    Ok(entries
+        .into_iter()
+        .filter(|entry| entry.kind == EntryKind::File && matcher.is_match(&entry.name))
+        .map(|entry| match base.as_str() {
+            "" if root == "/" => format!("/{}", entry.name),
+            "" => entry.name,
+            base => format!("{}/{}", base.trim_end_matches('/'), entry.name),
+        })
+        .collect())
+}
+
+/// The inputs with patterns expanded, each file once.
+fn inputs(args: &EncryptArgs) -> Result<Vec<String>, CliError> {
+    let mut inputs: Vec<String> = Vec::new();
+    for input in args.files.iter().chain(&args.paths) {
+        if input == STDIO_PATH {
+            return Err(CliError::InvalidInput(
+                "stdin can't be read as one of several files".to_string(),
+            ));
+        }
+        let matches = if is_pattern(input) && !Path::new(input).exists() {
+            let matches = expand(input)?;
+            if matches.is_empty() {
+                return Err(CliError::InvalidInput(format!(
+                    "'{input}' matches no files"
+                )));
+            }
+            matches
+        } else {
+            vec![input.clone()]
+        };
+        for file in matches {
+            if !inputs.contains(&file) {
+                inputs.push(file);
+            }
+        }
+    }
+    Ok(inputs)
+}
+
+/// Where `input` is encrypted to: `<name>.<extension>` next to it, or below `out_dir`,
+/// keeping the directories of a relative input.
+fn target(input: &str, out_dir: Option<&Path>, extension: &str) -> PathBuf {
+    let path = Path::new(input);
+    let name = format!(
+        "{}.{extension}",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    );
+    let Some(out_dir) = out_dir else {
+        return path.with_file_name(name);
+    };
+    let mut target = out_dir.to_path_buf();
+    let below = path
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    if below && let Some(parent) = path.parent() {
+        target.extend(parent.components().filter(|c| *c != Component::CurDir));
+    }
+    target.join(name)
+}
+
+/// Handles `encrypt` with several files or patterns.
+pub async fn encrypt_batch(args: EncryptArgs, config: &Config, json: bool) -> Result<(), CliError> {
+    let started = Instant::now();
+    let single_file_only = [
+        ("--delta-from", args.delta_from.is_some()),
+        ("--recovery-out", args.recovery_out.is_some()),
+        ("--wrap", args.wrap.is_some()),
+        ("--qr", args.qr.is_some()),
+    ];
+    if let Some((flag, _)) = single_file_only.iter().find(|(_, given)| *given) {
+        return Err(CliError::InvalidInput(format!(
+            "{flag} works on one file at a time"
+        )));
+    }
+    let out_dir = match args.output.as_deref() {
+        Some(STDIO_PATH) => {
+            return Err(CliError::InvalidInput(
+                "Several files can't all be written to stdout; --output takes a directory"
+                    .to_string(),
+            ));
+        }
+        Some(dir) => {
+            fs::create_dir_all(dir).map_err(|e| {
+                CliError::Io(io::Error::new(
+                    e.kind(),
+                    format!("Failed to create output directory '{dir}': {e}"),
+                ))
+            })?;
+            Some(PathBuf::from(dir))
+        }
+        None => None,
+    };
+    let inputs = inputs(&args)?;
+    let extension = if args.armor {
+        "asc"
+    } else {
+        args.format.extension()
+    };
+    let targets: Vec<PathBuf> = inputs
+        .iter()
+        .map(|input| target(input, out_dir.as_deref(), extension))
+        .collect();
+    let mut seen = HashMap::new();
+    for (input, target) in inputs.iter().zip(&targets) {
+        if let Some(other) = seen.insert(target, input) {
+            return Err(CliError::InvalidInput(format!(
+                "'{other}' and '{input}' would both be encrypted to '{}'",
+                target.display()
+            )));
+        }
+    }
+
+    // Credentials are settled once for all files, not once per file
+    let (key, generated_key) = settle_credentials(&args, &inputs[0], config).await?;
+    let jobs = args
+        .jobs
+        .unwrap_or_else(|| compression::resolve_threads(config.threads) as usize)
+        .clamp(1, inputs.len());
+    let in_place = args.in_place;
+    let template = EncryptArgs {
+        files: Vec::new(),
+        paths: Vec::new(),
+        key: key.as_deref().cloned(),
+        key_name: None,
+        enforce_strong_password: false,
+        output: None,
+        ..args
+    };
+
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<FileResult>>> = Mutex::new(inputs.iter().map(|_| None).collect());
+    let sizes = Mutex::new((0, 0));
+    let errors = Mutex::new(Vec::new());
+    STATUS_MUTED.store(true, Ordering::Relaxed);
+    std::thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build();
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(input) = inputs.get(i) else {
+                        break;
+                    };
+                    let target = &targets[i];
+                    let file_args = EncryptArgs {
+                        file: Some(input.clone()),
+                        // In place, the file is its own output
+                        output: (!in_place).then(|| target.to_string_lossy().into_owned()),
+                        ..template.clone()
+                    };
+                    let result = match &runtime {
+                        Ok(runtime) => {
+                            let parent = target.parent().filter(|_| !in_place);
+                            parent
+                                .map(fs::create_dir_all)
+                                .transpose()
+                                .map_err(|e| {
+                                    CliError::Io(io::Error::new(
+                                        e.kind(),
+                                        format!(
+                                            "Failed to create a directory for '{}': {e}",
+                                            target.display()
+                                        ),
+                                    ))
+                                })
+                                .and_then(|_| {
+                                    runtime.block_on(encrypt_command(file_args, config, json))
+                                })
+                        }
+                        Err(e) => Err(CliError::Io(io::Error::new(
+                            e.kind(),
+                            format!("Failed to start a worker: {e}"),
+                        ))),
+                    };
+                    let result = match result {
+                        Ok(report) => {
+                            let mut sizes = sizes.lock().unwrap_or_else(|e| e.into_inner());
+                            sizes.0 += report.input_size;
+                            sizes.1 += report.output_size;
+                            FileResult {
+                                file: input.clone(),
+                                status: "encrypted",
+                                output: Some(report.output),
+                                detail: None,
+                            }
+                        }
+                        Err(e) => {
+                            let result = FileResult {
+                                file: input.clone(),
+                                status: "failed",
+                                output: None,
+                                detail: Some(e.to_string()),
+                            };
+                            errors.lock().unwrap_or_else(|e| e.into_inner()).push(e);
+                            result
+                        }
+                    };
+                    if !json {
+                        print_result(&result);
+                    }
+                    results.lock().unwrap_or_else(|e| e.into_inner())[i] = Some(result);
+                }
+            });
+        }
+    });
+    STATUS_MUTED.store(false, Ordering::Relaxed);
+
+    let results: Vec<FileResult> = results
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .flatten()
+        .collect();
+    let (input_size, output_size) = sizes.into_inner().unwrap_or_else(|e| e.into_inner());
+    let failed: Vec<&str> = results
+        .iter()
+        .filter(|r| r.status == "failed")
+        .map(|r| r.file.as_str())
+        .collect();
+    let encrypted = results.len() - failed.len();
+    let mut errors = errors.into_inner().unwrap_or_else(|e| e.into_inner());
+    if errors.len() == 1 && encrypted == 0 {
+        // A single file keeps its own exit code, e.g. 3 for an unreadable file
+        return Err(errors.remove(0));
+    }
+    if !failed.is_empty() {
+        return Err(CliError::Crypto(tr!(
+            "batch-failed",
+            failed = failed.len(),
+            total = results.len(),
+            files = failed.join(", ")
+        )));
+    }
+    if json {
+        print_json_report(&BatchReport {
+            operation: "encrypt",
+            mode: "batch",
+            encrypted,
+            failed: failed.len(),
+            input_size,
+            output_size,
+            generated_key,
+            files: results,
+            duration_ms: started.elapsed().as_millis(),
+        });
+    } else {
+        status(tr!("batch-done", count = encrypted, jobs = jobs));
+    }
+    Ok(())
+}
+
+fn print_result(result: &FileResult) {
+    let file = &result.file;
+    match (&result.output, &result.detail) {
+        (Some(output), _) => {
+            tracing::info!(
+                "{}",
+                tr!("batch-file-encrypted", file = file, output = output)
+            )
+        }
+        (None, detail) => tracing::warn!(
+            "{}",
+            tr!(
+                "batch-file-failed",
+                file = file,
+                detail = detail.as_deref().unwrap_or_default()
+            )
+        ),
+    }
+}
@@ -0,0 +1,92 @@
+//!
+//! Recipient instructions written next to an encrypted file with `--bundle-readme`.
+//!
+//! The readme carries the file's SHA-256 fingerprint and step-by-step decryption
+//! instructions. It never contains the key or password: those must travel separately.
+//!
+use super::{CliError, OutputFormat};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+/// Returns the readme path for an encrypted output file (`<output>.readme.html`).
+pub fn readme_path(output_file: &str) -> String {
+    format!("{output_file}.readme.html")
+}
+
+/// Escapes text for safe inclusion in HTML.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Writes the instructions file for `output_file` and returns its path.
+///
+/// `password_mode` only selects which secret the recipient has to ask for.
+pub fn write_readme(
+    output_file: &str,
+    encrypted: &[u8],
+    format: OutputFormat,
+    password_mode: bool,
+) -> Result<String, CliError> {
+    let path = readme_path(output_file);
+
+    let file_name = Path::new(output_file)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(output_file);
+    let name = escape_html(file_name);
+    let fingerprint = Sha256::digest(encrypted)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    let secret = if password_mode { "password" } else { "key" };
+
+    let steps = match format {
+        OutputFormat::Xd => format!(
+            "<li>Ask the sender for the <strong>{secret}</strong> over a different channel (phone, chat, in person), never by reply to this email.</li>\n\
+             <li>Check that the file you received matches the fingerprint above:<br><code>sha256sum {name}</code> (Linux/macOS) or <code>certutil -hashfile {name} SHA256</code> (Windows).</li>\n\
+             <li>Open the EncryptX web app, choose <em>Decrypt</em>, select <code>{name}</code> and enter the {secret}.</li>\n\
+             <li>Or, with the EncryptX command-line tool:<br><code>encryptx-backend decrypt --file {name} --{secret} &lt;{secret}&gt;</code></li>"
+        ),
+        OutputFormat::ZipAes => format!(
+            "<li>Ask the sender for the <strong>password</strong> over a different channel (phone, chat, in person), never by reply to this email.</li>\n\
+             <li>Check that the file you received matches the fingerprint above:<br><code>sha256sum {name}</code> (Linux/macOS) or <code>certutil -hashfile {name} SHA256</code> (Windows).</li>\n\
+             <li>Open <code>{name}</code> with 7-Zip, WinZip or another archiver that supports AES-256 ZIP files.</li>\n\
+             <li>Enter the password when prompted and extract the files.</li>"
+        ),
+        OutputFormat::Pgp => format!(
+            "<li>{}</li>\n\
+             <li>Check that the file you received matches the fingerprint above:<br><code>sha256sum {name}</code> (Linux/macOS) or <code>certutil -hashfile {name} SHA256</code> (Windows).</li>\n\
+             <li>Decrypt it with GnuPG or any other OpenPGP tool:<br><code>gpg --decrypt-files {name}</code></li>\n\
+             <li>Or, with the EncryptX command-line tool:<br><code>encryptx-backend decrypt --file {name} {}</code></li>",
+            if password_mode {
+                "Ask the sender for the <strong>password</strong> over a different channel (phone, chat, in person), never by reply to this email."
+            } else {
+                "The file was encrypted to your OpenPGP public key; gpg uses your secret key to open it."
+            },
+            if password_mode {
+                "--password &lt;password&gt;"
+            } else {
+                "--pgp-key &lt;secret key file&gt;"
+            }
+        ),
+    };
+
+    let html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>How to open {name}</title>\n</head>\n\
+         <body style=\"font-family: sans-serif; max-width: 40em; margin: 2em auto;\">\n\
+         <h1>How to open {name}</h1>\n\
+         <p>This file was encrypted with EncryptX (AES-256). Nobody can read it without the {secret}.</p>\n\
+         <p><strong>SHA-256 fingerprint:</strong><br><code>{fingerprint}</code></p>\n\
+         <ol>\n{steps}\n</ol>\n\
+         <p>If the fingerprint does not match, do not open the file and contact the sender.</p>\n\
+         </body>\n</html>\n"
+    );
+
+    fs::write(&path, html)?;
+    Ok(path)
+}
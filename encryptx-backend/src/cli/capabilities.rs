@@ -0,0 +1,138 @@
+//!
+//! `capabilities` subcommand: prints what this build and configuration supports.
+//!
+//! The JSON report is the same document the server returns from `GET /capabilities`, so a
+//! script can check a local install the same way a client checks a deployment.
+//!
+use super::print_json_report;
+use crate::capabilities::Capabilities;
+use crate::config::Config;
+use crate::locale::tr;
+use serde::Serialize;
+
+/// Result of `capabilities`, printed with `--json`.
+#[derive(Serialize)]
+pub struct CapabilitiesReport {
+    pub operation: &'static str,
+    #[serde(flatten)]
+    pub capabilities: Capabilities,
+}
+
+fn enabled(on: bool) -> String {
+    if on { tr!("enabled") } else { tr!("disabled") }
+}
+
+/// Handles `capabilities`.
+pub fn capabilities_command(config: &Config, json: bool) {
+    let capabilities = Capabilities::new(config);
+    if json {
+        print_json_report(&CapabilitiesReport {
+            operation: "capabilities",
+            capabilities,
+        });
+        return;
+    }
+
+    let c = &capabilities;
+    println!("{}", tr!("capabilities-version", version = c.version));
+    println!(
+        "{}",
+        tr!("capabilities-ciphers", ciphers = c.ciphers.join(", "))
+    );
+    for kdf in &c.kdfs {
+        let profiles: Vec<&str> = kdf.profiles.iter().map(|p| p.name).collect();
+        let profiles = profiles.join(", ");
+        let line = if kdf.default {
+            tr!(
+                "capabilities-default-kdf",
+                name = kdf.name,
+                profiles = profiles,
+                default = kdf.default_profile
+            )
+        } else {
+            tr!(
+                "capabilities-kdf",
+                name = kdf.name,
+                profiles = profiles,
+                default = kdf.default_profile
+            )
+        };
+        println!("{line}");
+    }
+    println!(
+        "{}",
+        tr!(
+            "capabilities-formats",
+            key = c.format_versions.key,
+            password = c.format_versions.password,
+            layouts = c.layouts.join(", ")
+        )
+    );
+    println!(
+        "{}",
+        tr!(
+            "capabilities-outputs",
+            formats = c.output_formats.join(", ")
+        )
+    );
+    for compression in &c.compression {
+        let line = if compression.long_distance_matching {
+            tr!(
+                "capabilities-compression-ldm",
+                name = compression.name,
+                min = compression.min_level,
+                max = compression.max_level,
+                level = compression.level,
+                threads = compression.threads
+            )
+        } else {
+            tr!(
+                "capabilities-compression",
+                name = compression.name,
+                min = compression.min_level,
+                max = compression.max_level,
+                level = compression.level,
+                threads = compression.threads
+            )
+        };
+        println!("{line}");
+    }
+    println!(
+        "{}",
+        tr!("capabilities-keyslots", kinds = c.keyslot_kinds.join(", "))
+    );
+    println!(
+        "{}",
+        tr!(
+            "capabilities-signatures",
+            signatures = c.signatures.join(", ")
+        )
+    );
+    println!(
+        "{}",
+        tr!(
+            "capabilities-limits",
+            megabytes = c.limits.max_payload_bytes / (1024 * 1024),
+            min = c.limits.min_chunk_size,
+            max = c.limits.max_chunk_size
+        )
+    );
+    let storage = if c.features.storage_backends.is_empty() {
+        tr!("capabilities-no-storage")
+    } else {
+        c.features.storage_backends.join(", ")
+    };
+    println!(
+        "{}",
+        tr!(
+            "capabilities-features",
+            tls = enabled(c.features.tls),
+            auth = enabled(c.features.auth),
+            storage = storage,
+            openpgp = enabled(c.features.openpgp),
+            archive_ingest = enabled(c.features.archive_ingest),
+            watchdog = enabled(c.features.memory_watchdog),
+            share_links = enabled(c.features.share_links)
+        )
+    );
+}
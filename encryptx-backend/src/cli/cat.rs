@@ -0,0 +1,55 @@
+//!
+//! `cat` subcommand: decrypts a file to stdout and nowhere else, for a quick look at an
+//! encrypted note or for piping into another program without a plaintext copy on disk.
+//!
+//! It runs `decrypt --output -` with a smaller set of credentials, and refuses to dump
+//! binary data onto a terminal unless `--force` is given.
+//!
+use super::{CatArgs, CliError, DecryptArgs, STDIO_PATH, decrypt_command};
+use crate::config::Config;
+
+/// How much of the output is looked at to tell text from binary data.
+const SNIFF_LEN: usize = 8192;
+
+/// Whether `data` looks like binary data rather than text: its start holds a NUL byte or
+/// isn't UTF-8.
+pub(super) fn looks_binary(data: &[u8]) -> bool {
+    let head = &data[..data.len().min(SNIFF_LEN)];
+    if head.contains(&0) {
+        return true;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => false,
+        // A character cut in two at the end of the sniffed bytes is still text
+        Err(e) => e.error_len().is_some() || head.len() == data.len(),
+    }
+}
+
+/// Handles `cat`.
+pub async fn cat_command(args: CatArgs, config: &Config, json: bool) -> Result<(), CliError> {
+    let CatArgs {
+        file,
+        password,
+        keyfile,
+        key,
+        key_name,
+        identity,
+        token,
+        force,
+    } = args;
+    let args = DecryptArgs {
+        file: Some(file),
+        password,
+        keyfile,
+        key,
+        key_name,
+        identity,
+        token,
+        output: Some(STDIO_PATH.to_string()),
+        force,
+        refuse_binary_tty: true,
+        ..Default::default()
+    };
+    decrypt_command(args, config, json).await?;
+    Ok(())
+}
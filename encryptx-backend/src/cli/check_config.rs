@@ -0,0 +1,305 @@
+//!
+//! `--check-config`: validates the server configuration and exits without binding ports.
+//!
+//! Loading the file already rejects syntax errors, unknown keys and out-of-range values;
+//! these checks cover what only fails once the server starts (addresses, CORS origins) or
+//! what is valid on its own but wrong in combination, so a bad deploy fails in CI instead.
+//!
+use super::{CliError, print_json_report};
+use crate::config::Config;
+use crate::crypto::PasswordKdf;
+use crate::locale::tr;
+use crate::watchdog;
+use actix_web::http::Uri;
+use serde::Serialize;
+use std::net::ToSocketAddrs;
+use std::path::Path;
+
+/// Outcome of a single check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum CheckStatus {
+    #[serde(rename = "ok")]
+    Passed,
+    /// Works, but probably not as intended
+    #[serde(rename = "warning")]
+    Warning,
+    /// The server would fail to start or misbehave
+    #[serde(rename = "error")]
+    Failed,
+}
+
+/// One line of the report.
+#[derive(Serialize)]
+pub struct ConfigCheck {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+/// Result of `--check-config`, printed with `--json`.
+#[derive(Serialize)]
+pub struct CheckConfigReport {
+    pub operation: &'static str,
+    /// Config file that was checked, or "built-in defaults"
+    pub source: String,
+    pub checks: Vec<ConfigCheck>,
+}
+
+fn check(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> ConfigCheck {
+    ConfigCheck {
+        name,
+        status,
+        detail: detail.into(),
+    }
+}
+
+/// Checks that `origin` is a bare origin such as `https://app.example.com:8443`.
+fn validate_origin(origin: &str) -> Result<(), String> {
+    if origin == "*" {
+        return Err("'*' is not accepted; list each origin explicitly".to_string());
+    }
+    let uri: Uri = origin
+        .parse()
+        .map_err(|e| format!("'{origin}' is not a valid URL: {e}"))?;
+    match (uri.scheme_str(), uri.host(), uri.path()) {
+        (Some("http" | "https"), Some(_), "" | "/") if !origin.ends_with('/') => Ok(()),
+        (Some("http" | "https"), Some(_), _) => Err(format!(
+            "'{origin}' must be an origin without a path or trailing slash"
+        )),
+        _ => Err(format!(
+            "'{origin}' must start with http:// or https:// and name a host"
+        )),
+    }
+}
+
+/// Runs every check against an already loaded configuration.
+pub fn run_checks(config: &Config) -> Vec<ConfigCheck> {
+    use CheckStatus::{Failed, Passed, Warning};
+    let mut checks = Vec::new();
+
+    let kdf = match config.kdf_profile() {
+        Ok(profile) => {
+            let params = profile.params();
+            let detail = match config.password_kdf() {
+                Ok(PasswordKdf::Scrypt(scrypt)) => format!(
+                    "{}, scrypt (N = 2^{}, r = {}, p = {}; {} KB)",
+                    profile.name(),
+                    scrypt.log_n,
+                    scrypt.block_size,
+                    scrypt.parallelism,
+                    scrypt.memory_cost()
+                ),
+                _ => format!(
+                    "{} ({} KB, {} passes, {} lanes)",
+                    profile.name(),
+                    params.memory_cost,
+                    params.time_cost,
+                    params.parallelism
+                ),
+            };
+            checks.push(check("kdf_profile", Passed, detail));
+            Some(params)
+        }
+        Err(e) => {
+            checks.push(check("kdf_profile", Failed, e.to_string()));
+            None
+        }
+    };
+
+    checks.push(match config.chunk_size() {
+        Ok(Some(size)) => check(
+            "chunk_size",
+            Passed,
+            format!("{size} plaintext bytes per chunk"),
+        ),
+        Ok(None) => check("chunk_size", Passed, "single-message layout"),
+        Err(e) => check("chunk_size", Failed, e.to_string()),
+    });
+
+    let server = &config.server;
+    checks.push(
+        match (server.host.as_str(), server.port).to_socket_addrs() {
+            Ok(mut addrs) => match addrs.next() {
+                Some(addr) if server.port == 0 => check(
+                    "listen",
+                    Warning,
+                    format!("{addr}: port 0 picks a random port on every start"),
+                ),
+                Some(addr) => check("listen", Passed, addr.to_string()),
+                None => check(
+                    "listen",
+                    Failed,
+                    format!("'{}' resolves to no address", server.host),
+                ),
+            },
+            Err(e) => check(
+                "listen",
+                Failed,
+                format!("cannot resolve '{}:{}': {e}", server.host, server.port),
+            ),
+        },
+    );
+
+    // Same precedence as the server: ALLOWED_ORIGIN replaces the configured list
+    let (origins, origin_source) = match std::env::var("ALLOWED_ORIGIN") {
+        Ok(origins) => (
+            origins.split(',').map(|s| s.trim().to_string()).collect(),
+            "ALLOWED_ORIGIN",
+        ),
+        Err(_) => (server.allowed_origins.clone(), "server.allowed_origins"),
+    };
+    let invalid: Vec<String> = origins
+        .iter()
+        .filter_map(|origin| validate_origin(origin).err())
+        .collect();
+    checks.push(if !invalid.is_empty() {
+        check("allowed_origins", Failed, invalid.join("; "))
+    } else if origins.is_empty() {
+        check(
+            "allowed_origins",
+            Warning,
+            format!("{origin_source} is empty, so browsers can't call the API"),
+        )
+    } else {
+        check(
+            "allowed_origins",
+            Passed,
+            format!("{} (from {origin_source})", origins.join(", ")),
+        )
+    });
+
+    if let Some(dir) = &config.output_dir {
+        checks.push(if dir.is_dir() {
+            check("output_dir", Passed, dir.display().to_string())
+        } else {
+            check(
+                "output_dir",
+                Warning,
+                format!(
+                    "{} does not exist; CLI commands will fail to write there",
+                    dir.display()
+                ),
+            )
+        });
+    }
+
+    if let Some(dir) = &server.stream_dir {
+        checks.push(if dir.is_dir() {
+            check("stream_dir", Passed, dir.display().to_string())
+        } else {
+            check(
+                "stream_dir",
+                Warning,
+                format!(
+                    "{} does not exist; /stream will answer 404 for every file",
+                    dir.display()
+                ),
+            )
+        });
+    }
+
+    if server.share_secret.is_some() {
+        checks.push(if server.stream_dir.is_some() {
+            check(
+                "share_secret",
+                Passed,
+                format!("share links last up to {} s", server.share_max_ttl_secs),
+            )
+        } else {
+            check(
+                "share_secret",
+                Warning,
+                "set without server.stream_dir; /share will answer 404",
+            )
+        });
+    }
+
+    if let Some(limit_mb) = server.memory_limit_mb {
+        let limit = limit_mb * 1024 * 1024;
+        let argon2 = kdf.map_or(0, |params| u64::from(params.memory_cost) * 1024);
+        checks.push(if watchdog::current_rss().is_none() {
+            check(
+                "memory_limit_mb",
+                Warning,
+                "process memory can't be read on this platform; the watchdog stays inactive",
+            )
+        } else if argon2 >= limit {
+            check(
+                "memory_limit_mb",
+                Failed,
+                format!(
+                    "{limit_mb} MB is below the {} MB one Argon2 derivation needs; every password request would get 503",
+                    argon2 / (1024 * 1024)
+                ),
+            )
+        } else if (server.max_payload_mb as u64) >= limit_mb {
+            check(
+                "memory_limit_mb",
+                Warning,
+                format!(
+                    "{limit_mb} MB is not above max_payload_mb ({} MB); a single large upload can exhaust it",
+                    server.max_payload_mb
+                ),
+            )
+        } else {
+            check("memory_limit_mb", Passed, format!("{limit_mb} MB"))
+        });
+    }
+
+    checks
+}
+
+/// Handles `--check-config`.
+///
+/// Fails with [`CliError::InvalidInput`] (exit code 2) if any check reports an error.
+pub fn check_config_command(
+    config: &Config,
+    path: Option<&Path>,
+    json: bool,
+) -> Result<(), CliError> {
+    let path = path
+        .map(Path::to_path_buf)
+        .or_else(Config::default_path)
+        .filter(|path| path.exists());
+    let source = match &path {
+        Some(path) => path.display().to_string(),
+        None => "built-in defaults".to_string(),
+    };
+    let checks = run_checks(config);
+    let errors: Vec<String> = checks
+        .iter()
+        .filter(|c| c.status == CheckStatus::Failed)
+        .map(|c| format!("{}: {}", c.name, c.detail))
+        .collect();
+
+    if !json {
+        match &path {
+            Some(_) => println!("{}", tr!("check-config-source", source = source)),
+            None => println!("{}", tr!("check-config-defaults")),
+        }
+        for c in &checks {
+            let icon = match c.status {
+                CheckStatus::Passed => "✅",
+                CheckStatus::Warning => "⚠️ ",
+                CheckStatus::Failed => "❌",
+            };
+            println!("{icon} {}: {}", c.name, c.detail);
+        }
+    }
+    if !errors.is_empty() {
+        return Err(CliError::InvalidInput(tr!(
+            "check-config-failed",
+            errors = errors.join("; ")
+        )));
+    }
+    if json {
+        print_json_report(&CheckConfigReport {
+            operation: "check-config",
+            source,
+            checks,
+        });
+    } else {
+        println!("{}", tr!("check-config-valid"));
+    }
+    Ok(())
+}
@@ -0,0 +1,266 @@
+//!
+//! `clip` subcommand: encrypts or decrypts the system clipboard in place, so a secret can
+//! go through chat or email without touching the disk.
+//!
+//! `clip encrypt` replaces the clipboard's text with an armored message: the `.xd` file in
+//! base64 between `BEGIN`/`END` lines. `clip decrypt` turns such a message back into the
+//! text, then leaves a background `clip clear` behind that empties the clipboard after
+//! `--clear-after` seconds, unless something else was copied in the meantime.
+//!
+//! The clipboard is driven through the tool each platform has: `pbcopy`/`pbpaste` on macOS,
+//! PowerShell on Windows, and `wl-copy`/`wl-paste` under Wayland or `xclip` under X11
+//! elsewhere. `ENCRYPTX_CLIPBOARD_TOOL` replaces them with any program that takes `copy`
+//! (text on stdin) or `paste` (text on stdout) as its argument, e.g. for tmux buffers.
+//!
+use super::text::{armor, dearmor, open, resolve_credentials, seal};
+use super::{CliError, MessageCredentials, print_json_report, status};
+use crate::config::Config;
+use crate::locale::tr;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+/// Environment variable naming a replacement clipboard tool.
+const TOOL_ENV: &str = "ENCRYPTX_CLIPBOARD_TOOL";
+
+/// How the clipboard is reached: a program with the arguments that copy to and paste from
+/// it.
+struct Clipboard {
+    copy: (String, Vec<&'static str>),
+    paste: (String, Vec<&'static str>),
+    hint: &'static str,
+}
+
+impl Clipboard {
+    fn detect() -> Self {
+        if let Ok(tool) = std::env::var(TOOL_ENV) {
+            return Self {
+                copy: (tool.clone(), vec!["copy"]),
+                paste: (tool, vec!["paste"]),
+                hint: "check the program it names",
+            };
+        }
+        let tool = |name: &str, args: &[&'static str]| (name.to_string(), args.to_vec());
+        if cfg!(target_os = "macos") {
+            Self {
+                copy: tool("pbcopy", &[]),
+                paste: tool("pbpaste", &[]),
+                hint: "it ships with the OS",
+            }
+        } else if cfg!(windows) {
+            let script =
+                |command: &'static str| tool("powershell", &["-NoProfile", "-Command", command]);
+            Self {
+                copy: script(
+                    "$t = [Console]::In.ReadToEnd(); if ($t) { Set-Clipboard -Value $t } else { Set-Clipboard -Value $null }",
+                ),
+                paste: script("[Console]::Out.Write((Get-Clipboard -Raw))"),
+                hint: "it ships with the OS",
+            }
+        } else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            Self {
+                copy: tool("wl-copy", &[]),
+                paste: tool("wl-paste", &["--no-newline"]),
+                hint: "install wl-clipboard",
+            }
+        } else {
+            Self {
+                copy: tool("xclip", &["-selection", "clipboard", "-in"]),
+                paste: tool("xclip", &["-selection", "clipboard", "-out"]),
+                hint: "install xclip",
+            }
+        }
+    }
+
+    fn spawn(
+        &self,
+        (tool, args): &(String, Vec<&str>),
+        stdout: Stdio,
+    ) -> Result<std::process::Child, CliError> {
+        Command::new(tool)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(stdout)
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => CliError::InvalidInput(format!(
+                    "{tool} not found; {} or set {TOOL_ENV}",
+                    self.hint
+                )),
+                _ => CliError::Io(io::Error::new(
+                    e.kind(),
+                    format!("Failed to run {tool}: {e}"),
+                )),
+            })
+    }
+
+    /// The clipboard's contents; empty if it holds no text.
+    fn paste(&self) -> Result<Zeroizing<Vec<u8>>, CliError> {
+        let child = self.spawn(&self.paste, Stdio::piped())?;
+        let output = child.wait_with_output()?;
+        let text = Zeroizing::new(output.stdout);
+        // xclip and wl-paste fail on an empty clipboard
+        Ok(if output.status.success() {
+            text
+        } else {
+            Zeroizing::new(Vec::new())
+        })
+    }
+
+    /// Replaces the clipboard's contents with `text`.
+    fn copy(&self, text: &[u8]) -> Result<(), CliError> {
+        // xclip and wl-copy stay behind to serve the selection, so only the exit status is
+        // waited for; reading their output would wait for them to go away
+        let mut child = self.spawn(&self.copy, Stdio::null())?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text)?;
+        }
+        if !child.wait()?.success() {
+            return Err(CliError::Io(io::Error::other(format!(
+                "Failed to write to the clipboard with {}",
+                self.copy.0
+            ))));
+        }
+        Ok(())
+    }
+}
+
+/// What `clip encrypt --json` and `clip decrypt --json` report.
+#[derive(Serialize)]
+struct ClipReport {
+    operation: &'static str,
+    mode: &'static str,
+    input_size: usize,
+    output_size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clear_after: Option<u64>,
+}
+
+/// Handles `clip encrypt`.
+pub async fn encrypt_command(
+    credentials: MessageCredentials,
+    config: &Config,
+    json: bool,
+) -> Result<(), CliError> {
+    let (password, key) = resolve_credentials(credentials, config, "clip encrypt").await?;
+    let clipboard = Clipboard::detect();
+    let text = clipboard.paste()?;
+    if text.is_empty() {
+        return Err(CliError::InvalidInput(
+            "The clipboard holds no text to encrypt".to_string(),
+        ));
+    }
+
+    // No file name, so the header says nothing about what was copied
+    let (encrypted, mode) =
+        seal(&text, password.as_deref().map(|p| p.as_str()), key.as_ref()).await?;
+    let armored = armor(&encrypted);
+    clipboard.copy(armored.as_bytes())?;
+
+    if json {
+        print_json_report(&ClipReport {
+            operation: "clip-encrypt",
+            mode,
+            input_size: text.len(),
+            output_size: armored.len(),
+            clear_after: None,
+        });
+    } else {
+        status(tr!(
+            "clip-encrypted",
+            bytes = text.len(),
+            armored = armored.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Handles `clip decrypt`, leaving a `clip clear` behind unless `clear_after` is 0.
+pub async fn decrypt_command(
+    credentials: MessageCredentials,
+    clear_after: u64,
+    config: &Config,
+    json: bool,
+) -> Result<(), CliError> {
+    let (password, key) = resolve_credentials(credentials, config, "clip decrypt").await?;
+    let clipboard = Clipboard::detect();
+    let message = clipboard.paste()?;
+    let encrypted = dearmor(&String::from_utf8_lossy(&message))?;
+    let plaintext = open(
+        &encrypted,
+        password.as_deref().map(|p| p.as_str()),
+        key.as_ref(),
+        config,
+    )
+    .await?;
+    clipboard.copy(&plaintext)?;
+    if clear_after > 0 {
+        spawn_clear(&plaintext, clear_after)?;
+    }
+
+    if json {
+        print_json_report(&ClipReport {
+            operation: "clip-decrypt",
+            mode: if password.is_some() {
+                "password"
+            } else {
+                "key"
+            },
+            input_size: message.len(),
+            output_size: plaintext.len(),
+            clear_after: (clear_after > 0).then_some(clear_after),
+        });
+    } else {
+        status(tr!("clip-decrypted", bytes = plaintext.len()));
+        if clear_after > 0 {
+            status(tr!("clip-clear-scheduled", seconds = clear_after));
+        }
+    }
+    Ok(())
+}
+
+/// Hash identifying the text `clip clear` may remove, so it leaves alone anything copied
+/// after it.
+fn fingerprint(text: &[u8]) -> String {
+    Sha256::digest(text)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Starts `clip clear --after SECS` in the background, handing it the text's hash on stdin
+/// rather than in its arguments.
+fn spawn_clear(text: &[u8], after: u64) -> Result<(), CliError> {
+    let exe = std::env::current_exe()?;
+    let mut command = Command::new(exe);
+    command
+        .args(["clip", "clear", "--after", &after.to_string()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // Its own process group, so Ctrl-C in the shell doesn't take it down too
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let mut child = command.spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(fingerprint(text).as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Handles the hidden `clip clear`: waits, then empties the clipboard if it still holds the
+/// text whose hash comes on stdin.
+pub fn clear_command(after: u64) -> Result<(), CliError> {
+    let mut expected = String::new();
+    io::stdin().read_to_string(&mut expected)?;
+    std::thread::sleep(Duration::from_secs(after));
+    let clipboard = Clipboard::detect();
+    if fingerprint(&clipboard.paste()?) == expected.trim() {
+        clipboard.copy(b"")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,193 @@
+//!
+//! Git pre-commit hook that keeps plaintext secrets out of the repository.
+//!
+//! Protected paths are listed (one glob per line) in `.encryptx-protect` at the repository
+//! root, so the whole team shares the same policy. The installed hook calls back into
+//! `hook check`, which refuses the commit if any staged file matching those patterns is not
+//! a valid `.xd` file.
+//!
+use super::{CliError, status};
+use crate::crypto;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Name of the pattern file kept at the repository root.
+pub const PROTECT_FILE: &str = ".encryptx-protect";
+
+/// Marker line used to recognise hooks written by EncryptX.
+const HOOK_MARKER: &str = "# Installed by encryptx hook install";
+
+/// Runs a git command and returns its stdout, mapping failures to CLI errors.
+fn git(args: &[&str]) -> Result<Vec<u8>, CliError> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|e| CliError::Io(io::Error::new(e.kind(), format!("Failed to run git: {e}"))))?;
+    if !output.status.success() {
+        return Err(CliError::InvalidInput(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+/// Runs a git command whose output is a single path.
+fn git_path(args: &[&str]) -> Result<PathBuf, CliError> {
+    let out = git(args)?;
+    Ok(PathBuf::from(String::from_utf8_lossy(&out).trim()))
+}
+
+/// Reads the protected patterns, skipping blank lines and `#` comments.
+fn read_patterns(root: &Path) -> Result<Vec<String>, CliError> {
+    let path = root.join(PROTECT_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(&path)?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Compiles the patterns into a matcher.
+fn build_globset(patterns: &[String]) -> Result<GlobSet, CliError> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern)
+            .map_err(|e| CliError::InvalidInput(format!("Invalid pattern '{pattern}': {e}")))?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|e| CliError::InvalidInput(format!("Invalid patterns: {e}")))
+}
+
+/// Installs the pre-commit hook and records any new patterns in `.encryptx-protect`.
+///
+/// An existing hook that was not written by EncryptX is left alone unless `force` is set.
+pub fn install(patterns: &[String], force: bool) -> Result<(), CliError> {
+    let root = git_path(&["rev-parse", "--show-toplevel"])?;
+    let hooks_dir = git_path(&["rev-parse", "--git-path", "hooks"])?;
+    let hooks_dir = if hooks_dir.is_absolute() {
+        hooks_dir
+    } else {
+        std::env::current_dir()?.join(hooks_dir)
+    };
+
+    // Validate before touching anything on disk
+    build_globset(patterns)?;
+
+    let hook_path = hooks_dir.join("pre-commit");
+    if hook_path.exists() && !force {
+        let existing = fs::read_to_string(&hook_path).unwrap_or_default();
+        if !existing.contains(HOOK_MARKER) {
+            return Err(CliError::InvalidInput(format!(
+                "A pre-commit hook already exists at '{}'. Use --force to replace it",
+                hook_path.display()
+            )));
+        }
+    }
+
+    // Point the hook at this exact binary so it works outside of PATH too
+    let exe = std::env::current_exe()
+        .ok()
+        .and_then(|p| p.to_str().map(str::to_string))
+        .unwrap_or_else(|| "encryptx-backend".to_string());
+    let script = format!("#!/bin/sh\n{HOOK_MARKER}\nexec \"{exe}\" hook check\n");
+
+    fs::create_dir_all(&hooks_dir)?;
+    fs::write(&hook_path, script)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&hook_path, fs::Permissions::from_mode(0o755))?;
+    }
+
+    let mut existing = read_patterns(&root)?;
+    let added: Vec<&String> = patterns.iter().filter(|p| !existing.contains(p)).collect();
+    if !added.is_empty() {
+        let mut content = fs::read_to_string(root.join(PROTECT_FILE)).unwrap_or_default();
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        for pattern in added {
+            content.push_str(pattern);
+            content.push('\n');
+            existing.push(pattern.clone());
+        }
+        fs::write(root.join(PROTECT_FILE), content)?;
+    }
+
+    status(format!(
+        "✅ Pre-commit hook installed at '{}'",
+        hook_path.display()
+    ));
+    if existing.is_empty() {
+        status(format!(
+            "⚠️  No protected patterns yet. Add globs to '{PROTECT_FILE}' or use --pattern"
+        ));
+    } else {
+        status("🛡️  Protected patterns:");
+        for pattern in &existing {
+            status(format!("   {pattern}"));
+        }
+    }
+    Ok(())
+}
+
+/// Checks the staged files against the protected patterns.
+///
+/// Staged content is read from the index (not the working tree), so what gets checked is
+/// exactly what would be committed.
+pub fn check() -> Result<(), CliError> {
+    let root = git_path(&["rev-parse", "--show-toplevel"])?;
+    let patterns = read_patterns(&root)?;
+    if patterns.is_empty() {
+        return Ok(());
+    }
+    let globset = build_globset(&patterns)?;
+
+    let staged = git(&[
+        "diff",
+        "--cached",
+        "--name-only",
+        "--diff-filter=ACMR",
+        "-z",
+    ])?;
+    let mut offenders = Vec::new();
+    for path in staged
+        .split(|b| *b == 0)
+        .filter(|p| !p.is_empty())
+        .map(|p| String::from_utf8_lossy(p).into_owned())
+    {
+        if !globset.is_match(&path) {
+            continue;
+        }
+        let blob = git(&["show", &format!(":{path}")])?;
+        if !crypto::is_valid_xd(&blob) {
+            offenders.push(path);
+        }
+    }
+
+    if offenders.is_empty() {
+        return Ok(());
+    }
+
+    status("❌ Refusing to commit protected files that are not encrypted:");
+    for path in &offenders {
+        status(format!("   {path}"));
+    }
+    status("💡 Encrypt them with EncryptX first, or unstage them with 'git restore --staged'.");
+    Err(CliError::InvalidInput(format!(
+        "{} protected file(s) are not valid .xd files",
+        offenders.len()
+    )))
+}
@@ -0,0 +1,169 @@
+//!
+//! `identity` subcommand and helpers for public-key (X25519, X25519 + ML-KEM) encryption
+//! and Ed25519 file signing.
+//!
+use super::{CliError, check_output_file, print_json_report, status, write_secret_file};
+use crate::crypto::identity::{Identity, PQ_RECIPIENT_PREFIX, RECIPIENT_PREFIX, Recipient};
+use crate::crypto::keyslot::{KIND_KEM, KIND_X25519};
+use crate::crypto::signing::{SigningKey, VERIFYING_KEY_PREFIX, VerifyingKey};
+use crate::format::XdReader;
+use serde::Serialize;
+use std::fs;
+use std::io;
+use zeroize::Zeroizing;
+
+/// Result of `identity generate`, printed with `--json`.
+#[derive(Serialize)]
+pub struct IdentityReport {
+    pub operation: &'static str,
+    pub output: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipient: Option<String>,
+    /// Verifying key of a signing key (`--signing`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verifying_key: Option<String>,
+}
+
+/// Reads recipients given on the command line: each argument is a recipient string or the
+/// path of a file listing recipients (one per line, `#` comments allowed).
+pub fn read_recipients(args: &[String]) -> Result<Vec<Recipient>, CliError> {
+    let mut recipients = Vec::new();
+    for arg in args {
+        if arg.starts_with(RECIPIENT_PREFIX) || arg.starts_with(PQ_RECIPIENT_PREFIX) {
+            recipients.push(parse_recipient(arg, arg)?);
+            continue;
+        }
+        let text = fs::read_to_string(arg).map_err(|e| {
+            CliError::InvalidInput(format!(
+                "'{arg}' is neither a recipient nor a readable recipients file: {e}"
+            ))
+        })?;
+        for line in text.lines().map(str::trim) {
+            if !line.is_empty() && !line.starts_with('#') {
+                recipients.push(parse_recipient(line, arg)?);
+            }
+        }
+    }
+    Ok(recipients)
+}
+
+fn parse_recipient(text: &str, source: &str) -> Result<Recipient, CliError> {
+    text.parse()
+        .map_err(|_| CliError::InvalidInput(format!("'{source}' contains an invalid recipient")))
+}
+
+/// Reads identity files, each holding one or more identities.
+pub fn read_identities(paths: &[String]) -> Result<Vec<Identity>, CliError> {
+    let mut identities = Vec::new();
+    for path in paths {
+        let text = Zeroizing::new(fs::read_to_string(path).map_err(|e| {
+            CliError::Io(io::Error::new(
+                e.kind(),
+                format!("Failed to read identity file '{path}': {e}"),
+            ))
+        })?);
+        identities.extend(Identity::parse_identities(&text).map_err(|_| {
+            CliError::InvalidInput(format!("'{path}' is not a valid identity file"))
+        })?);
+    }
+    Ok(identities)
+}
+
+/// Whether `data` is a keyslot file encrypted to at least one recipient.
+pub fn encrypted_to_recipients(data: &[u8]) -> bool {
+    XdReader::parse(data)
+        .ok()
+        .and_then(|file| {
+            let (slots, _) = file.header.keyslots()?;
+            Some(
+                slots
+                    .iter()
+                    .any(|slot| slot.kind == KIND_X25519 || slot.kind == KIND_KEM),
+            )
+        })
+        .unwrap_or(false)
+}
+
+/// Reads a signing key file.
+pub fn read_signing_key(path: &str) -> Result<SigningKey, CliError> {
+    let text = Zeroizing::new(fs::read_to_string(path).map_err(|e| {
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to read signing key file '{path}': {e}"),
+        ))
+    })?);
+    SigningKey::parse_file(&text)
+        .map_err(|_| CliError::InvalidInput(format!("'{path}' is not a valid signing key file")))
+}
+
+/// Reads a verifying key given on the command line: a key string or the path of a file
+/// containing one (such as a signing key file's `# verifying key:` comment, or a bare key).
+pub fn read_verifying_key(arg: &str) -> Result<VerifyingKey, CliError> {
+    let invalid = || CliError::InvalidInput(format!("'{arg}' is not a valid verifying key"));
+    if arg.starts_with(VERIFYING_KEY_PREFIX) {
+        return arg.parse().map_err(|_| invalid());
+    }
+    let text = fs::read_to_string(arg).map_err(|e| {
+        CliError::InvalidInput(format!(
+            "'{arg}' is neither a verifying key nor a readable key file: {e}"
+        ))
+    })?;
+    text.lines()
+        .map(|line| line.trim_start_matches('#').trim())
+        .map(|line| line.strip_prefix("verifying key:").unwrap_or(line).trim())
+        .find(|line| line.starts_with(VERIFYING_KEY_PREFIX))
+        .ok_or_else(invalid)?
+        .parse()
+        .map_err(|_| invalid())
+}
+
+/// Handles `identity generate`.
+pub fn generate_command(
+    output: &str,
+    post_quantum: bool,
+    signing: bool,
+    force: bool,
+    json: bool,
+) -> Result<(), CliError> {
+    check_output_file(output, force)?;
+    if signing {
+        let key = SigningKey::generate();
+        write_secret_file(output, &Zeroizing::new(key.to_file()))?;
+        let verifying_key = key.verifying_key();
+        if json {
+            print_json_report(&IdentityReport {
+                operation: "signing-key-generate",
+                output: output.to_string(),
+                recipient: None,
+                verifying_key: Some(verifying_key.to_string()),
+            });
+            return Ok(());
+        }
+        status(format!("✍️  Signing key written to '{output}'"));
+        println!("🔏 Verifying key: {verifying_key}");
+        println!("🔖 Fingerprint: {}", verifying_key.fingerprint());
+        status("💡 Share the verifying key so recipients can check your signatures.");
+        return Ok(());
+    }
+    let identity = if post_quantum {
+        Identity::generate_hybrid()
+    } else {
+        Identity::generate()
+    };
+    write_secret_file(output, &Zeroizing::new(identity.to_file()))?;
+    let recipient = identity.recipient().to_string();
+
+    if json {
+        print_json_report(&IdentityReport {
+            operation: "identity-generate",
+            output: output.to_string(),
+            recipient: Some(recipient),
+            verifying_key: None,
+        });
+        return Ok(());
+    }
+    status(format!("🪪 Identity written to '{output}'"));
+    println!("📮 Recipient: {recipient}");
+    status("💡 Share the recipient freely; keep the identity file private.");
+    Ok(())
+}
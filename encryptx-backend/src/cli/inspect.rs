@@ -0,0 +1,322 @@
+//!
+//! `inspect` subcommand: shows `.xd` metadata without decrypting anything.
+//!
+use super::{CliError, print_json_report, read_input, unwrap_input};
+use crate::crypto;
+use crate::format::{ParsedHeader, XdReader};
+use crate::interop;
+use crate::locale::tr;
+use serde::Serialize;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Key derivation details of a password-based file.
+#[derive(Serialize)]
+pub struct KdfReport {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_cost: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_cost: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parallelism: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_n: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_size: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iterations: Option<u32>,
+}
+
+/// Everything `inspect` can tell about a file.
+#[derive(Serialize)]
+pub struct InspectReport {
+    pub file: String,
+    pub version: u8,
+    /// "key", "password", "keyslots", "master-key" or "convergent"
+    pub mode: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kdf: Option<KdfReport>,
+    pub timestamp: u64,
+    pub filename: String,
+    pub key_embedded: bool,
+    /// Fingerprint of the embedded key, never the key itself
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embedded_key_fingerprint: Option<String>,
+    pub ciphertext_size: usize,
+    /// Plaintext bytes per chunk, for chunked files
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk_size: Option<u32>,
+    /// Delta chain identifier, for files written with `--delta-from`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delta_id: Option<String>,
+    /// Byte ranges that can be uploaded as separate multipart parts
+    pub parts: Vec<PartReport>,
+    /// Credentials that can unlock the file, for keyslot files
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub keyslots: Vec<KeyslotReport>,
+    /// Signature trailer, for files written with `--sign-key`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<SignatureReport>,
+}
+
+/// Signature of a signed file; checking it needs no secret.
+#[derive(Serialize)]
+pub struct SignatureReport {
+    /// Fingerprint of the key the file claims to be signed with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signer: Option<String>,
+    pub valid: bool,
+}
+
+/// One keyslot, without any key material.
+#[derive(Serialize)]
+pub struct KeyslotReport {
+    pub index: usize,
+    /// "password", "key", "x25519" or "kem"
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Key encapsulation suite of kem slots, e.g. "x25519-mlkem768"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suite: Option<String>,
+    /// Number of quorum slots needed together (quorum slots only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<u8>,
+}
+
+/// One independently uploadable byte range of the file.
+#[derive(Serialize)]
+pub struct PartReport {
+    pub offset: usize,
+    pub length: usize,
+}
+
+/// Builds the report for an encrypted file.
+pub fn inspect(file: &str, data: &[u8]) -> Result<InspectReport, CliError> {
+    if let Some(kind) = interop::archive::detect(data) {
+        return Err(CliError::Format(format!(
+            "'{file}' is a {} archive, not an .xd file",
+            kind.name()
+        )));
+    }
+    if interop::openpgp::detect(data) {
+        return Err(CliError::Format(format!(
+            "'{file}' is an OpenPGP message, not an .xd file"
+        )));
+    }
+
+    let parsed = XdReader::parse(data)
+        .map_err(|_| CliError::Format(format!("'{file}' is not a valid .xd file")))?;
+    let parts = crypto::chunked::part_boundaries(data)
+        .map_err(|_| CliError::Format(format!("'{file}' has an invalid chunk size")))?
+        .into_iter()
+        .map(|r| PartReport {
+            offset: r.start,
+            length: r.len(),
+        })
+        .collect();
+
+    let (mode, kdf, embedded_key) = match &parsed.header {
+        ParsedHeader::Key(h) if h.keyslots.is_some() => ("keyslots", None, None),
+        ParsedHeader::Key(h) if h.subkey_salt.is_some() => (
+            if h.convergent == Some(true) {
+                "convergent"
+            } else {
+                "master-key"
+            },
+            Some(KdfReport {
+                name: "hkdf-sha256".to_string(),
+                memory_cost: None,
+                time_cost: None,
+                parallelism: None,
+                log_n: None,
+                block_size: None,
+                iterations: None,
+            }),
+            None,
+        ),
+        ParsedHeader::Key(h) => ("key", None, h.key.as_deref()),
+        ParsedHeader::Password(h) => (
+            "password",
+            Some(KdfReport {
+                name: h.kdf.clone(),
+                memory_cost: h.memory_cost,
+                time_cost: h.time_cost,
+                parallelism: h.parallelism,
+                log_n: h.log_n,
+                block_size: h.block_size,
+                iterations: h.iterations,
+            }),
+            None,
+        ),
+    };
+
+    // Fingerprint the decoded key so the output can be compared with `--json` encrypt results
+    let embedded_key_fingerprint = embedded_key.map(|k| {
+        use base64::Engine;
+        base64::engine::general_purpose::STANDARD
+            .decode(k)
+            .map(|bytes| crypto::key_fingerprint(&bytes))
+            .unwrap_or_else(|_| "invalid".to_string())
+    });
+
+    Ok(InspectReport {
+        file: file.to_string(),
+        version: parsed.header.version(),
+        mode,
+        kdf,
+        timestamp: parsed.header.timestamp(),
+        filename: parsed.header.filename().to_string(),
+        key_embedded: embedded_key.is_some(),
+        embedded_key_fingerprint,
+        ciphertext_size: parsed.ciphertext.len(),
+        chunk_size: parsed.header.chunk_size(),
+        delta_id: parsed.header.delta_id().map(str::to_string),
+        parts,
+        keyslots: parsed
+            .header
+            .keyslots()
+            .map(|(slots, _)| slots)
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+            .map(|(index, slot)| KeyslotReport {
+                index,
+                kind: slot.kind.clone(),
+                label: slot.label.clone(),
+                suite: slot.suite.clone(),
+                threshold: slot.threshold,
+            })
+            .collect(),
+        signature: crypto::signing::split(data).1.map(|_| SignatureReport {
+            signer: crypto::signing::claimed_signer(data).map(|key| key.fingerprint()),
+            valid: crypto::signing::verify(data).is_ok(),
+        }),
+    })
+}
+
+/// Handles the `inspect` subcommand.
+pub fn inspect_command(file: &str, json: bool) -> Result<(), CliError> {
+    let data = unwrap_input(file, read_input(file, "encrypted file")?)?;
+    let report = inspect(file, &data)?;
+
+    if json {
+        print_json_report(&report);
+        return Ok(());
+    }
+
+    println!("{}", tr!("inspect-file", file = report.file));
+    println!("{}", tr!("inspect-version", version = report.version));
+    println!("{}", tr!("inspect-mode", mode = report.mode));
+    if let Some(kdf) = &report.kdf {
+        let name = &kdf.name;
+        let line = match (
+            kdf.memory_cost,
+            kdf.time_cost,
+            kdf.parallelism,
+            kdf.iterations,
+        ) {
+            (Some(memory), Some(time), Some(parallelism), _) => tr!(
+                "inspect-kdf-argon2",
+                name = name,
+                memory = memory,
+                time = time,
+                parallelism = parallelism
+            ),
+            (_, _, _, Some(iterations)) => {
+                tr!("inspect-kdf-pbkdf2", name = name, iterations = iterations)
+            }
+            _ => match (kdf.log_n, kdf.block_size, kdf.parallelism) {
+                (Some(log_n), Some(block_size), Some(parallelism)) => tr!(
+                    "inspect-kdf-scrypt",
+                    name = name,
+                    log_n = log_n,
+                    block_size = block_size,
+                    parallelism = parallelism
+                ),
+                _ => tr!("inspect-kdf", name = name),
+            },
+        };
+        println!("{line}");
+    }
+    // Anonymous (--no-metadata) files record neither
+    if report.timestamp == 0 {
+        println!("{}", tr!("inspect-time-unrecorded"));
+    } else {
+        let encrypted_at =
+            humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(report.timestamp));
+        println!(
+            "{}",
+            tr!("inspect-time", time = encrypted_at, unix = report.timestamp)
+        );
+    }
+    if report.filename.is_empty() {
+        println!("{}", tr!("inspect-filename-unrecorded"));
+    } else {
+        println!("{}", tr!("inspect-filename", filename = report.filename));
+    }
+    match &report.embedded_key_fingerprint {
+        Some(fp) => println!("{}", tr!("inspect-embedded-key", fingerprint = fp)),
+        None => println!("{}", tr!("inspect-no-embedded-key")),
+    }
+    for slot in &report.keyslots {
+        let kind = match (&slot.suite, slot.threshold) {
+            (Some(suite), _) => format!("{} {suite}", slot.kind),
+            (None, Some(threshold)) => {
+                tr!(
+                    "inspect-quorum-slot",
+                    kind = slot.kind,
+                    threshold = threshold
+                )
+            }
+            (None, None) => slot.kind.clone(),
+        };
+        let line = match &slot.label {
+            Some(label) => tr!(
+                "inspect-labeled-keyslot",
+                index = slot.index,
+                kind = kind,
+                label = label
+            ),
+            None => tr!("inspect-keyslot", index = slot.index, kind = kind),
+        };
+        println!("{line}");
+    }
+    if let Some(signature) = &report.signature {
+        let signer = match &signature.signer {
+            Some(signer) => signer.clone(),
+            None => tr!("inspect-invalid-signer"),
+        };
+        if signature.valid {
+            println!("{}", tr!("inspect-signed", signer = signer));
+        } else {
+            println!("{}", tr!("inspect-signed-invalid", signer = signer));
+        }
+    }
+    println!(
+        "{}",
+        tr!("inspect-ciphertext", bytes = report.ciphertext_size)
+    );
+    if let (Some(chunk_size), Some(delta_id)) = (report.chunk_size, &report.delta_id) {
+        println!(
+            "{}",
+            tr!(
+                "inspect-delta",
+                chunks = report.parts.len(),
+                chunk_size = chunk_size,
+                delta_id = delta_id
+            )
+        );
+    } else if let Some(chunk_size) = report.chunk_size {
+        println!(
+            "{}",
+            tr!(
+                "inspect-chunked",
+                chunks = report.parts.len(),
+                chunk_size = chunk_size,
+                encrypted = chunk_size as usize + crypto::chunked::TAG_LEN
+            )
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,233 @@
+//!
+//! `key` subcommand: splits keys into Shamir shares and combines them again, and prints
+//! and reads paper backups. Also reads the key parts written by `encrypt --split-credentials`.
+//!
+use super::{
+    CliError, STDIO_PATH, check_output_file, print_json_report, read_input, status, validate_key,
+    write_output,
+};
+use crate::crypto::{self, paper, shamir, split};
+use crate::locale::tr;
+use base64::{Engine, engine::general_purpose};
+use serde::Serialize;
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+/// Result of `key split`, printed with `--json`.
+#[derive(Serialize)]
+pub struct KeySplitReport {
+    pub operation: &'static str,
+    pub threshold: u8,
+    pub shares: Vec<String>,
+    pub key_fingerprint: String,
+}
+
+/// Result of `key combine`, printed with `--json`.
+#[derive(Serialize)]
+pub struct KeyCombineReport {
+    pub operation: &'static str,
+    pub key: String,
+    pub key_fingerprint: String,
+}
+
+/// Result of `key paper`, printed with `--json`.
+#[derive(Serialize)]
+pub struct KeyPaperReport {
+    pub operation: &'static str,
+    pub key_fingerprint: String,
+    pub output: String,
+    pub sheet: String,
+}
+
+/// Result of `key recover-paper`, printed with `--json`.
+#[derive(Serialize)]
+pub struct KeyRecoverPaperReport {
+    pub operation: &'static str,
+    pub key: String,
+    pub key_fingerprint: String,
+    pub repaired_lines: Vec<usize>,
+}
+
+/// Reads a share given on the command line: either the share string itself or the path of
+/// a file containing it.
+fn read_share(arg: &str) -> Result<shamir::Share, CliError> {
+    let text = if arg.starts_with(shamir::SHARE_PREFIX) || !Path::new(arg).is_file() {
+        Zeroizing::new(arg.to_string())
+    } else {
+        Zeroizing::new(fs::read_to_string(arg)?)
+    };
+    text.parse()
+        .map_err(|_| CliError::InvalidInput(format!("'{arg}' is not a valid key share")))
+}
+
+/// Recovers a key from shares given on the command line, returned as base64.
+pub fn key_from_shares(args: &[String]) -> Result<Zeroizing<String>, CliError> {
+    let shares = args
+        .iter()
+        .map(|arg| read_share(arg))
+        .collect::<Result<Vec<_>, _>>()?;
+    let key = shamir::combine(&shares).map_err(|e| match e {
+        crypto::CryptoError::AuthenticationError => CliError::Authentication(
+            "Key shares do not combine to their key; a share is damaged or from another split"
+                .to_string(),
+        ),
+        e => CliError::InvalidInput(e.to_string()),
+    })?;
+    Ok(Zeroizing::new(
+        general_purpose::STANDARD.encode(key.as_slice()),
+    ))
+}
+
+/// Derives a key from the two part files written by `encrypt --split-credentials`, returned
+/// as base64.
+pub fn key_from_parts(args: &[String]) -> Result<Zeroizing<String>, CliError> {
+    let parts = args
+        .iter()
+        .map(|arg| {
+            let text = if arg.starts_with(split::PART_PREFIX) {
+                Zeroizing::new(arg.to_string())
+            } else {
+                Zeroizing::new(fs::read_to_string(arg)?)
+            };
+            text.parse::<split::Part>()
+                .map_err(|_| CliError::InvalidInput(format!("'{arg}' is not a valid key part")))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let [a, b] = parts.as_slice() else {
+        return Err(CliError::InvalidInput(
+            "Exactly two key parts (--credential) are required".to_string(),
+        ));
+    };
+    let key = split::combine(a, b).map_err(|e| CliError::InvalidInput(e.to_string()))?;
+    Ok(Zeroizing::new(
+        general_purpose::STANDARD.encode(key.as_slice()),
+    ))
+}
+
+/// Handles `key split`.
+pub fn split_command(key: &str, shares: u8, threshold: u8, json: bool) -> Result<(), CliError> {
+    let key = validate_key(key)?;
+    let split = shamir::split(&key, threshold, shares)
+        .map_err(|e| CliError::InvalidInput(e.to_string()))?;
+    let shares: Vec<String> = split.iter().map(ToString::to_string).collect();
+    let key_fingerprint = crypto::key_fingerprint(&key);
+
+    if json {
+        print_json_report(&KeySplitReport {
+            operation: "key-split",
+            threshold,
+            shares,
+            key_fingerprint,
+        });
+        return Ok(());
+    }
+    status(format!(
+        "🧩 Split key {key_fingerprint} into {} shares; any {threshold} of them recover it:",
+        shares.len()
+    ));
+    for share in &shares {
+        println!("{share}");
+    }
+    status(format!(
+        "💡 Give each share to a different custodian; fewer than {threshold} reveal nothing."
+    ));
+    Ok(())
+}
+
+/// Handles `key combine`.
+pub fn combine_command(shares: &[String], json: bool) -> Result<(), CliError> {
+    let key = key_from_shares(shares)?;
+    let key_fingerprint = crypto::key_fingerprint(
+        &general_purpose::STANDARD
+            .decode(key.as_bytes())
+            .map_err(|e| CliError::Crypto(e.to_string()))?,
+    );
+
+    if json {
+        print_json_report(&KeyCombineReport {
+            operation: "key-combine",
+            key: key.to_string(),
+            key_fingerprint,
+        });
+        return Ok(());
+    }
+    println!("🔑 Recovered key (base64): {}", *key);
+    println!("🔖 Fingerprint: {key_fingerprint}");
+    Ok(())
+}
+
+/// Handles `key paper`: writes the key as a printable sheet to `output` ('-' for stdout).
+pub fn paper_command(key: &str, output: &str, force: bool, json: bool) -> Result<(), CliError> {
+    let key = validate_key(key)?;
+    let sheet = Zeroizing::new(paper::render(&key).map_err(|e| CliError::Crypto(e.to_string()))?);
+    let key_fingerprint = crypto::key_fingerprint(&key);
+    if output != STDIO_PATH {
+        check_output_file(output, force)?;
+        write_output(output, sheet.as_bytes(), "paper key")?;
+    }
+
+    if json {
+        print_json_report(&KeyPaperReport {
+            operation: "key-paper",
+            key_fingerprint,
+            output: output.to_string(),
+            sheet: sheet.to_string(),
+        });
+        return Ok(());
+    }
+    if output == STDIO_PATH {
+        print!("{}", *sheet);
+        println!("\nRecover with: encryptx-backend key recover-paper");
+    } else {
+        status(format!(
+            "📝 Paper key for {key_fingerprint} written to '{output}'"
+        ));
+        status("💡 Print it, then delete the file.");
+    }
+    Ok(())
+}
+
+/// Handles `key recover-paper`: reads a typed-in sheet from `input` ('-' for stdin, where a
+/// terminal is prompted) and prints the key.
+pub fn recover_paper_command(input: &str, json: bool) -> Result<(), CliError> {
+    let text = if input == STDIO_PATH && io::stdin().is_terminal() {
+        eprintln!("{}", tr!("prompt-paper-key"));
+        let mut text = Zeroizing::new(String::new());
+        io::stdin().read_to_string(&mut text)?;
+        text
+    } else {
+        let data = read_input(input, "paper key")?;
+        Zeroizing::new(String::from_utf8_lossy(&data).into_owned())
+    };
+    let recovered = paper::recover(&text).map_err(|e| match e {
+        crypto::CryptoError::AuthenticationError => CliError::Authentication(
+            "The recovered key doesn't match the fingerprint on the paper key".to_string(),
+        ),
+        e => CliError::InvalidInput(e.to_string()),
+    })?;
+    let key = Zeroizing::new(general_purpose::STANDARD.encode(recovered.key.as_slice()));
+    let key_fingerprint = crypto::key_fingerprint(recovered.key.as_slice());
+
+    if json {
+        print_json_report(&KeyRecoverPaperReport {
+            operation: "key-recover-paper",
+            key: key.to_string(),
+            key_fingerprint,
+            repaired_lines: recovered.repaired_lines,
+        });
+        return Ok(());
+    }
+    if !recovered.repaired_lines.is_empty() {
+        let lines: Vec<String> = recovered
+            .repaired_lines
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        status(format!("🩹 Repaired line(s) {}", lines.join(", ")));
+    }
+    println!("🔑 Recovered key (base64): {}", *key);
+    println!("🔖 Fingerprint: {key_fingerprint}");
+    Ok(())
+}
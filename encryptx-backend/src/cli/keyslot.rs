@@ -0,0 +1,405 @@
+//!
+//! `keyslot` subcommand: adds or removes credentials that unlock a file.
+//!
+//! Any existing credential unlocks the data key. Envelope files (see [`crypto::rewrap`])
+//! then only get a new header; older files are re-sealed under the same data key with the
+//! new slot list, since their chunked layout authenticates the slots too. Plain password or
+//! key files are converted on their first `keyslot add`.
+//!
+use super::{
+    CliError, CommandReport, KeyslotAddArgs, KeyslotRemoveArgs, STDIO_PATH, check_output_file,
+    check_password_strength, identity, kms, lock, read_input, status, token, validate_key,
+    with_kdf_profile, write_atomic, write_output,
+};
+use crate::config::Config;
+use crate::crypto::{
+    self, Argon2Params, SecureKey,
+    keyslot::{self, Credential, Keyslot, KmsSecret, TokenSecret},
+};
+use crate::format::{ParsedHeader, XdReader};
+use crate::{interop, stego};
+use rand::RngCore;
+use std::time::Instant;
+use zeroize::Zeroizing;
+
+/// A file opened with one of its credentials, ready to be re-sealed.
+struct Unlocked {
+    data_key: SecureKey,
+    slots: Vec<Keyslot>,
+    /// Decrypted body, for files that can't simply be rewrapped
+    plaintext: Option<Zeroizing<Vec<u8>>>,
+    filename: String,
+    chunk_size: Option<u32>,
+    /// File contents as read, to detect concurrent changes before replacing it
+    original: Vec<u8>,
+}
+
+/// Resolves the output path; slot changes replace the input unless `-o` is given.
+fn output_path(
+    file: &str,
+    output: Option<String>,
+    force: bool,
+    json: bool,
+) -> Result<String, CliError> {
+    let output_file = match output {
+        Some(o) => o,
+        None if file == STDIO_PATH => {
+            return Err(CliError::InvalidInput(
+                "--output is required when reading from stdin".to_string(),
+            ));
+        }
+        None => file.to_string(),
+    };
+    if output_file == STDIO_PATH && json {
+        return Err(CliError::InvalidInput(
+            "--json cannot be combined with writing data to stdout".to_string(),
+        ));
+    }
+    if output_file != STDIO_PATH && output_file != file {
+        check_output_file(&output_file, force)?;
+    }
+    Ok(output_file)
+}
+
+/// Reads `file` and unlocks it with `password` or `key`.
+///
+/// Files without keyslots are converted: a fresh data key is generated and the credential
+/// that opened the file becomes slot 0.
+async fn unlock(
+    file: &str,
+    password: Option<String>,
+    key: Option<String>,
+) -> Result<Unlocked, CliError> {
+    if password.is_some() && key.is_some() {
+        return Err(CliError::InvalidInput(
+            "Cannot specify both password and key. Choose one.".to_string(),
+        ));
+    }
+    let key = key.as_deref().map(validate_key).transpose()?;
+
+    let data = read_input(file, "encrypted file")?;
+    if stego::is_png(&data) || interop::archive::detect(&data).is_some() {
+        return Err(CliError::Format(format!(
+            "'{file}' is not a plain .xd file; decrypt and re-encrypt it instead"
+        )));
+    }
+    let parsed = XdReader::parse(&data)
+        .map_err(|_| CliError::Format(format!("'{file}' is not a valid .xd file")))?;
+    if password.is_none() && key.is_none() {
+        return Err(CliError::InvalidInput(
+            "An existing --password or --key is required to change keyslots".to_string(),
+        ));
+    }
+
+    status(format!("🔓 Unlocking '{file}'..."));
+    let file_key = crypto::file_key(&parsed, password.clone(), key.as_deref())
+        .await
+        .map_err(|e| CliError::from_crypto("Unlocking failed", e))?;
+    // Envelope files keep their body, so the slots' key check is all that needs to hold;
+    // anything else is decrypted, which also authenticates the header of chunked files
+    let plaintext = match &parsed.header {
+        ParsedHeader::Key(h) if h.is_envelope() => None,
+        _ => Some(Zeroizing::new(
+            crypto::decrypt_parsed(&parsed, &file_key)
+                .map_err(|e| CliError::from_crypto("Unlocking failed", e))?,
+        )),
+    };
+
+    let (data_key, slots) = match parsed.header.keyslots() {
+        Some((slots, _)) => (file_key, slots.to_vec()),
+        None => {
+            let data_key = keyslot::generate_data_key();
+            let (credential, params) = match (&parsed.header, password, &key) {
+                (ParsedHeader::Password(h), Some(password), _) => (
+                    Credential::Password(Zeroizing::new(password)),
+                    Argon2Params::from_header(h)
+                        .map_err(|e| CliError::from_crypto("Unlocking failed", e))?,
+                ),
+                (_, _, Some(key)) => (Credential::Key(key), Argon2Params::default()),
+                _ => unreachable!("file_key succeeded with the given credential"),
+            };
+            let first = keyslot::create(&data_key, credential, params, None)
+                .await
+                .map_err(|e| CliError::from_crypto("Creating keyslot failed", e))?;
+            (data_key, vec![first])
+        }
+    };
+
+    Ok(Unlocked {
+        data_key,
+        slots,
+        plaintext,
+        filename: parsed.header.filename().to_string(),
+        chunk_size: parsed.header.chunk_size(),
+        original: data.to_vec(),
+    })
+}
+
+/// Re-seals (or just rewraps) the unlocked file with `slots` and writes it out.
+///
+/// In-place updates only replace `file` if nobody changed it since it was read; otherwise
+/// nothing is written and `None` is returned so the caller can re-apply its change.
+fn reseal(
+    unlocked: &Unlocked,
+    slots: Vec<Keyslot>,
+    file: &str,
+    output_file: &str,
+) -> Result<Option<usize>, CliError> {
+    let encrypted = match &unlocked.plaintext {
+        Some(plaintext) => crypto::encrypt_with_keyslots(
+            plaintext,
+            &unlocked.data_key,
+            &unlocked.filename,
+            slots,
+            unlocked.chunk_size,
+        ),
+        None => crypto::rewrap(&unlocked.original, slots),
+    }
+    .map_err(|e| CliError::from_crypto("Re-sealing failed", e))?;
+
+    if output_file == STDIO_PATH {
+        write_output(output_file, &encrypted, "encrypted file")?;
+    } else {
+        if output_file == file {
+            if !lock::replace_if_unchanged(file, &unlocked.original, &encrypted)? {
+                return Ok(None);
+            }
+        } else {
+            write_atomic(output_file, &encrypted)?;
+        }
+        status(format!("✅ Updated file written to '{output_file}'"));
+    }
+    Ok(Some(encrypted.len()))
+}
+
+/// How often an in-place change is re-applied after losing a race with another writer.
+const MAX_ATTEMPTS: usize = 3;
+
+/// Handles `keyslot add`.
+pub async fn add_command(
+    args: KeyslotAddArgs,
+    config: &Config,
+    json: bool,
+) -> Result<CommandReport, CliError> {
+    let started = Instant::now();
+    let KeyslotAddArgs {
+        file,
+        password,
+        key,
+        new_password,
+        kdf_profile,
+        enforce_strong_password,
+        new_key,
+        new_recipient,
+        new_token,
+        token_device,
+        new_kms,
+        label,
+        output,
+        force,
+    } = args;
+    let config = &*with_kdf_profile(config, kdf_profile)?;
+    if let Some(password) = &new_password {
+        check_password_strength(password, &file, enforce_strong_password)?;
+    }
+
+    let new_key = new_key.as_deref().map(validate_key).transpose()?;
+    let new_recipient = match new_recipient {
+        Some(arg) => match identity::read_recipients(&[arg])?.as_slice() {
+            [recipient] => Some(recipient.clone()),
+            _ => {
+                return Err(CliError::InvalidInput(
+                    "--new-recipient must name exactly one recipient".to_string(),
+                ));
+            }
+        },
+        None => None,
+    };
+    let given = [
+        new_password.is_some(),
+        new_key.is_some(),
+        new_recipient.is_some(),
+        new_token.is_some(),
+        new_kms.is_some(),
+    ];
+    if given.iter().filter(|&&g| g).count() != 1 {
+        return Err(CliError::InvalidInput(
+            "Must specify exactly one of --new-password, --new-key, --new-recipient, --new-token or --new-kms."
+                .to_string(),
+        ));
+    }
+    let key_fingerprint = new_key.as_deref().map(crypto::key_fingerprint);
+    let kdf_params = config
+        .kdf_profile()
+        .map_err(|e| CliError::InvalidInput(e.to_string()))?
+        .params();
+    let output_file = output_path(&file, output, force, json)?;
+
+    // The token is asked once up front, so retries below don't need another touch
+    let new_token = match new_token {
+        Some(path) => {
+            let credential_id = token::read_token_file(&path)?;
+            let device = token::pick_device(token_device.as_deref())?;
+            let mut salt = [0u8; 32];
+            rand::rngs::OsRng.fill_bytes(&mut salt);
+            status("👆 Touch your security key...");
+            let secret = token::hmac_secret(&device, &credential_id, &salt)?
+                .ok_or_else(|| {
+                    CliError::Authentication(format!(
+                        "The token at '{device}' did not answer for '{path}'; is it the one that was enrolled?"
+                    ))
+                })?;
+            Some((credential_id, salt, secret))
+        }
+        None => None,
+    };
+    // Likewise the KMS is only asked once to wrap the slot's key
+    let new_kms = match new_kms {
+        Some(uri) => {
+            let kek = keyslot::generate_data_key();
+            let wrapped = kms::wrap(&uri, kek.as_slice())?;
+            status(format!("🔐 Slot key wrapped by {}", wrapped.uri));
+            Some((wrapped, kek))
+        }
+        None => None,
+    };
+
+    // Optimistic update: if another process changed the file meanwhile, add the slot to
+    // its version instead
+    let mut attempt = 0;
+    let (unlocked, index, output_size) = loop {
+        attempt += 1;
+        let unlocked = unlock(&file, password.clone(), key.clone()).await?;
+        let credential = match (
+            &new_password,
+            &new_key,
+            &new_recipient,
+            &new_token,
+            &new_kms,
+        ) {
+            (Some(password), ..) => Credential::Password(Zeroizing::new(password.clone())),
+            (_, Some(key), ..) => Credential::Key(key),
+            (_, _, Some(recipient), ..) => Credential::Recipient(recipient),
+            (_, _, _, Some((credential_id, salt, secret)), _) => Credential::Token(TokenSecret {
+                credential_id,
+                salt,
+                secret,
+            }),
+            (.., Some((wrapped, kek))) => Credential::Kms(KmsSecret {
+                uri: &wrapped.uri,
+                sealed: &wrapped.sealed,
+                secret: kek.as_slice(),
+            }),
+            (None, None, None, None, None) => unreachable!("checked above"),
+        };
+        let slot = keyslot::create(&unlocked.data_key, credential, kdf_params, label.clone())
+            .await
+            .map_err(|e| CliError::from_crypto("Creating keyslot failed", e))?;
+
+        let mut slots = unlocked.slots.clone();
+        slots.push(slot);
+        let index = slots.len() - 1;
+        match reseal(&unlocked, slots, &file, &output_file)? {
+            Some(size) => break (unlocked, index, size),
+            None if attempt < MAX_ATTEMPTS => {
+                status(format!("🔁 '{file}' changed meanwhile, retrying..."));
+            }
+            None => return Err(lock::conflict(&file)),
+        }
+    };
+    status(format!("🔑 Added keyslot {index}"));
+
+    Ok(CommandReport {
+        operation: "keyslot-add",
+        mode: "keyslots".to_string(),
+        input: file,
+        output: output_file,
+        input_size: unlocked.original.len(),
+        output_size,
+        key_fingerprint,
+        generated_key: None,
+        recovery_key: None,
+        delta: None,
+        signer: None,
+        compression_level: None,
+        duration_ms: started.elapsed().as_millis(),
+    })
+}
+
+/// Handles `keyslot remove`.
+pub async fn remove_command(
+    args: KeyslotRemoveArgs,
+    json: bool,
+) -> Result<CommandReport, CliError> {
+    let started = Instant::now();
+    let KeyslotRemoveArgs {
+        file,
+        slot,
+        password,
+        key,
+        output,
+        force,
+    } = args;
+
+    let output_file = output_path(&file, output, force, json)?;
+
+    // The slot is identified by index only in the version first read; retries after a
+    // concurrent change look it up by its wrapped key, so indices shifted by the other
+    // writer can't make us remove the wrong slot
+    let mut target: Option<String> = None;
+    let mut attempt = 0;
+    let (unlocked, output_size) = loop {
+        attempt += 1;
+        let unlocked = unlock(&file, password.clone(), key.clone()).await?;
+        let mut slots = unlocked.slots.clone();
+        let index = match &target {
+            None if slot >= slots.len() => {
+                return Err(CliError::InvalidInput(format!(
+                    "'{file}' has no keyslot {slot} (it has {})",
+                    slots.len()
+                )));
+            }
+            None => slot,
+            Some(wrapped) => slots
+                .iter()
+                .position(|s| &s.wrapped_key == wrapped)
+                .ok_or_else(|| {
+                    CliError::InvalidInput(format!(
+                        "Keyslot {slot} of '{file}' was already removed by another process"
+                    ))
+                })?,
+        };
+        if slots.len() == 1 {
+            return Err(CliError::InvalidInput(
+                "Refusing to remove the last keyslot; the file could never be opened again"
+                    .to_string(),
+            ));
+        }
+        let removed = slots.remove(index);
+        match reseal(&unlocked, slots, &file, &output_file)? {
+            Some(size) => break (unlocked, size),
+            None if attempt < MAX_ATTEMPTS => {
+                target = Some(removed.wrapped_key);
+                status(format!("🔁 '{file}' changed meanwhile, retrying..."));
+            }
+            None => return Err(lock::conflict(&file)),
+        }
+    };
+    status(format!("🗑️ Removed keyslot {slot}"));
+
+    Ok(CommandReport {
+        operation: "keyslot-remove",
+        mode: "keyslots".to_string(),
+        input: file,
+        output: output_file,
+        input_size: unlocked.original.len(),
+        output_size,
+        key_fingerprint: None,
+        generated_key: None,
+        recovery_key: None,
+        delta: None,
+        signer: None,
+        compression_level: None,
+        duration_ms: started.elapsed().as_millis(),
+    })
+}
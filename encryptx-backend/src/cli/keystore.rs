@@ -0,0 +1,770 @@
+//!
+//! Named keys, so `--key-name work` works without pasting a base64 key on the command line.
+//!
+//! Keys live in the platform's secret store unless the config file sets `keystore` to the
+//! path of an encrypted keystore file. That file is a password-mode `.xd` file (Argon2id with
+//! the configured KDF profile) holding the keys as JSON, and is protected by a master
+//! passphrase from `ENCRYPTX_KEYSTORE_PASSPHRASE`, or asked for on the terminal.
+//!
+//! Each platform's store is driven through the tool it ships with:
+//! - Linux and other Unix: the Secret Service (GNOME Keyring, KWallet) via libsecret's
+//!   `secret-tool`
+//! - macOS: the login Keychain via `security`
+//! - Windows: the Credential Manager via PowerShell and the `Cred*` functions of advapi32
+//!
+//! Set `ENCRYPTX_KEYSTORE_TOOL` to the tool's path if it isn't on `PATH`. Keys are stored as
+//! base64 under the service `encryptx` and the key's name, and are handed to and read from
+//! the tool through pipes, so they never show up in `ps`.
+//!
+use super::{CliError, agent, print_json_report, status, validate_key, write_atomic};
+use crate::config::Config;
+use crate::crypto::{self, Argon2Params};
+use crate::format::XdReader;
+use crate::locale::tr;
+use base64::{Engine, engine::general_purpose};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use zeroize::Zeroizing;
+
+/// Environment variable naming the secret store's command-line tool.
+const TOOL_ENV: &str = "ENCRYPTX_KEYSTORE_TOOL";
+
+/// Service the keys are filed under.
+const SERVICE: &str = "encryptx";
+
+/// Longest accepted key name.
+const MAX_NAME_LEN: usize = 64;
+
+/// Environment variable holding the keystore file's master passphrase.
+const PASSPHRASE_ENV: &str = "ENCRYPTX_KEYSTORE_PASSPHRASE";
+
+/// Name recorded in the keystore file's header.
+const KEYSTORE_FILENAME: &str = "keystore.json";
+
+/// A platform secret store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    SecretService,
+    Keychain,
+    CredentialManager,
+}
+
+/// Credential Manager access for PowerShell. `@ACTION@` and `@NAME@` are substituted before
+/// it runs; exit code 2 means the credential doesn't exist.
+const CREDENTIAL_MANAGER_SCRIPT: &str = r#"
+$ErrorActionPreference = 'Stop'
+Add-Type -Namespace EncryptX -Name Cred -MemberDefinition @'
+[StructLayout(LayoutKind.Sequential, CharSet = CharSet.Unicode)]
+public struct CREDENTIAL {
+    public int Flags; public int Type; public string TargetName; public string Comment;
+    public System.Runtime.InteropServices.ComTypes.FILETIME LastWritten;
+    public int CredentialBlobSize; public IntPtr CredentialBlob; public int Persist;
+    public int AttributeCount; public IntPtr Attributes; public string TargetAlias;
+    public string UserName;
+}
+[DllImport("advapi32.dll", CharSet = CharSet.Unicode, SetLastError = true)]
+public static extern bool CredWrite(ref CREDENTIAL credential, int flags);
+[DllImport("advapi32.dll", CharSet = CharSet.Unicode, SetLastError = true)]
+public static extern bool CredRead(string target, int type, int flags, out IntPtr credential);
+[DllImport("advapi32.dll", CharSet = CharSet.Unicode, SetLastError = true)]
+public static extern bool CredDelete(string target, int type, int flags);
+[DllImport("advapi32.dll", CharSet = CharSet.Unicode, SetLastError = true)]
+public static extern bool CredEnumerate(string filter, int flags, out int count, out IntPtr credentials);
+[DllImport("advapi32.dll")]
+public static extern void CredFree(IntPtr buffer);
+'@
+$Marshal = [Runtime.InteropServices.Marshal]
+$target = 'encryptx:@NAME@'
+function Fail($call) {
+    $code = $Marshal::GetLastWin32Error()
+    if ($code -eq 1168) { exit 2 }
+    [Console]::Error.WriteLine("$call failed with error $code"); exit 1
+}
+switch ('@ACTION@') {
+    'store' {
+        $bytes = [Text.Encoding]::UTF8.GetBytes([Console]::In.ReadToEnd())
+        $cred = New-Object EncryptX.Cred+CREDENTIAL
+        $cred.Type = 1; $cred.Persist = 2; $cred.TargetName = $target; $cred.UserName = 'encryptx'
+        $cred.CredentialBlobSize = $bytes.Length
+        $cred.CredentialBlob = $Marshal::AllocHGlobal($bytes.Length)
+        $Marshal::Copy($bytes, 0, $cred.CredentialBlob, $bytes.Length)
+        $ok = [EncryptX.Cred]::CredWrite([ref]$cred, 0)
+        $Marshal::FreeHGlobal($cred.CredentialBlob)
+        if (-not $ok) { Fail 'CredWrite' }
+    }
+    'lookup' {
+        $ptr = [IntPtr]::Zero
+        if (-not [EncryptX.Cred]::CredRead($target, 1, 0, [ref]$ptr)) { Fail 'CredRead' }
+        $cred = $Marshal::PtrToStructure($ptr, [Type][EncryptX.Cred+CREDENTIAL])
+        $bytes = New-Object byte[] $cred.CredentialBlobSize
+        $Marshal::Copy($cred.CredentialBlob, $bytes, 0, $cred.CredentialBlobSize)
+        [EncryptX.Cred]::CredFree($ptr)
+        [Console]::Out.Write([Text.Encoding]::UTF8.GetString($bytes))
+    }
+    'remove' {
+        if (-not [EncryptX.Cred]::CredDelete($target, 1, 0)) { Fail 'CredDelete' }
+    }
+    'list' {
+        $count = 0; $ptr = [IntPtr]::Zero
+        if (-not [EncryptX.Cred]::CredEnumerate('encryptx:*', 0, [ref]$count, [ref]$ptr)) { Fail 'CredEnumerate' }
+        for ($i = 0; $i -lt $count; $i++) {
+            $item = $Marshal::ReadIntPtr($ptr, $i * [IntPtr]::Size)
+            $cred = $Marshal::PtrToStructure($item, [Type][EncryptX.Cred+CREDENTIAL])
+            [Console]::Out.WriteLine($cred.TargetName.Substring(9))
+        }
+        [EncryptX.Cred]::CredFree($ptr)
+    }
+}
+"#;
+
+impl Backend {
+    /// The store of the platform this was built for.
+    fn current() -> Self {
+        if cfg!(target_os = "macos") {
+            Backend::Keychain
+        } else if cfg!(windows) {
+            Backend::CredentialManager
+        } else {
+            Backend::SecretService
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Backend::SecretService => "secret-service",
+            Backend::Keychain => "keychain",
+            Backend::CredentialManager => "credential-manager",
+        }
+    }
+
+    /// Human name of the store, for messages.
+    fn display(self) -> &'static str {
+        match self {
+            Backend::SecretService => "the Secret Service keyring",
+            Backend::Keychain => "the macOS Keychain",
+            Backend::CredentialManager => "the Windows Credential Manager",
+        }
+    }
+
+    fn default_tool(self) -> &'static str {
+        match self {
+            Backend::SecretService => "secret-tool",
+            Backend::Keychain => "security",
+            Backend::CredentialManager => "powershell",
+        }
+    }
+
+    fn install_hint(self) -> &'static str {
+        match self {
+            Backend::SecretService => "install libsecret-tools",
+            Backend::Keychain | Backend::CredentialManager => "it ships with the OS",
+        }
+    }
+
+    /// Arguments and stdin for `action` ("store", "lookup", "remove" or "list") on the key
+    /// `name`.
+    fn command(self, action: &str, name: &str, secret: &[u8]) -> (Vec<String>, Vec<u8>) {
+        let owned = |args: &[&str]| args.iter().map(|s| s.to_string()).collect();
+        match self {
+            Backend::SecretService => {
+                let label = format!("--label=EncryptX key {name}");
+                let args = match action {
+                    "store" => owned(&["store", &label, "service", SERVICE, "account", name]),
+                    "lookup" => owned(&["lookup", "service", SERVICE, "account", name]),
+                    "list" => owned(&["search", "--all", "service", SERVICE]),
+                    _ => owned(&["clear", "service", SERVICE, "account", name]),
+                };
+                (args, secret.to_vec())
+            }
+            // `security -i` reads its commands from stdin, which keeps the key out of argv
+            Backend::Keychain => match action {
+                "store" => {
+                    let mut input = format!(
+                        "add-generic-password -U -s {SERVICE} -a {name} -l \"EncryptX key {name}\" -w "
+                    )
+                    .into_bytes();
+                    input.extend_from_slice(secret);
+                    input.push(b'\n');
+                    (owned(&["-i"]), input)
+                }
+                "lookup" => (
+                    owned(&["find-generic-password", "-s", SERVICE, "-a", name, "-w"]),
+                    Vec::new(),
+                ),
+                "list" => (owned(&["dump-keychain"]), Vec::new()),
+                _ => (
+                    owned(&["delete-generic-password", "-s", SERVICE, "-a", name]),
+                    Vec::new(),
+                ),
+            },
+            Backend::CredentialManager => {
+                let script = CREDENTIAL_MANAGER_SCRIPT
+                    .replace("@ACTION@", action)
+                    .replace("@NAME@", name);
+                (
+                    owned(&["-NoProfile", "-NonInteractive", "-Command", &script]),
+                    secret.to_vec(),
+                )
+            }
+        }
+    }
+
+    /// Whether a failed lookup, removal or listing just means there is no such key.
+    fn not_found(self, output: &Output) -> bool {
+        match self {
+            // secret-tool exits 1 without a message when nothing matches
+            Backend::SecretService => output.stderr.iter().all(u8::is_ascii_whitespace),
+            Backend::Keychain => output.status.code() == Some(44),
+            Backend::CredentialManager => output.status.code() == Some(2),
+        }
+    }
+
+    /// Runs `action` on the key `name`.
+    fn run(self, action: &str, name: &str, secret: &[u8]) -> Result<Output, CliError> {
+        let tool = std::env::var(TOOL_ENV).unwrap_or_else(|_| self.default_tool().to_string());
+        let (args, input) = self.command(action, name, secret);
+        let input = Zeroizing::new(input);
+        let mut child = Command::new(&tool)
+            .args(&args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => CliError::InvalidInput(format!(
+                    "{tool} not found; {} or set {TOOL_ENV}",
+                    self.install_hint()
+                )),
+                _ => CliError::Io(io::Error::new(
+                    e.kind(),
+                    format!("Failed to run {tool}: {e}"),
+                )),
+            })?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // The tool may fail (locked keyring, access denied) before reading; its stderr
+            // says why
+            match stdin.write_all(&input) {
+                Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(child.wait_with_output()?)
+    }
+
+    fn failed(self, action: &str, output: &Output) -> CliError {
+        CliError::Io(io::Error::other(format!(
+            "Failed to {action} {}: {}",
+            self.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+
+    fn store(self, name: &str, key_b64: &str) -> Result<(), CliError> {
+        let output = self.run("store", name, key_b64.as_bytes())?;
+        // `security -i` reports failed commands on stderr but still exits 0
+        if !output.status.success() || self == Backend::Keychain && !output.stderr.is_empty() {
+            return Err(self.failed("write to", &output));
+        }
+        Ok(())
+    }
+
+    fn lookup(self, name: &str) -> Result<Option<Zeroizing<String>>, CliError> {
+        let output = self.run("lookup", name, &[])?;
+        let stdout = Zeroizing::new(output.stdout.clone());
+        if !output.status.success() {
+            if self.not_found(&output) {
+                return Ok(None);
+            }
+            return Err(self.failed("read from", &output));
+        }
+        let key = String::from_utf8_lossy(&stdout).trim().to_string();
+        Ok(Some(Zeroizing::new(key)).filter(|key| !key.is_empty()))
+    }
+
+    fn remove(self, name: &str) -> Result<(), CliError> {
+        let output = self.run("remove", name, &[])?;
+        if !output.status.success() && !self.not_found(&output) {
+            return Err(self.failed("remove a key from", &output));
+        }
+        Ok(())
+    }
+
+    /// Names of the keys filed under [`SERVICE`].
+    fn list(self) -> Result<Vec<String>, CliError> {
+        let output = self.run("list", "", &[])?;
+        // secret-tool prints the secrets along with the attributes
+        let stdout = Zeroizing::new(output.stdout.clone());
+        if !output.status.success() {
+            if self.not_found(&output) {
+                return Ok(Vec::new());
+            }
+            return Err(self.failed("list", &output));
+        }
+        let text = String::from_utf8_lossy(&stdout);
+        let mut names: Vec<String> = match self {
+            Backend::SecretService => text
+                .lines()
+                .filter_map(|line| line.strip_prefix("attribute.account = "))
+                .map(str::to_string)
+                .collect(),
+            // One block per item, each starting with a "keychain:" line
+            Backend::Keychain => text
+                .split("keychain: ")
+                .filter(|item| item.contains(&format!("\"svce\"<blob>=\"{SERVICE}\"")))
+                .filter_map(|item| {
+                    let (_, rest) = item.split_once("\"acct\"<blob>=\"")?;
+                    rest.split_once('"').map(|(name, _)| name.to_string())
+                })
+                .collect(),
+            Backend::CredentialManager => text.lines().map(str::to_string).collect(),
+        };
+        names.retain(|name| validate_name(name).is_ok());
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+}
+
+/// Contents of a keystore file.
+#[derive(Default, Serialize, Deserialize)]
+struct KeystoreContents {
+    /// Base64 keys by name
+    keys: BTreeMap<String, String>,
+}
+
+impl Drop for KeystoreContents {
+    fn drop(&mut self) {
+        for key in self.keys.values_mut() {
+            zeroize::Zeroize::zeroize(key);
+        }
+    }
+}
+
+/// An encrypted keystore file.
+struct KeystoreFile {
+    path: PathBuf,
+    params: Argon2Params,
+}
+
+/// Reads a line from the terminal with echo turned off where `stty` can do that.
+fn read_hidden(prompt: &str) -> Result<Zeroizing<String>, CliError> {
+    eprint!("{prompt} ");
+    io::stderr().flush()?;
+    let stty = |arg: &str| {
+        Command::new("stty")
+            .arg(arg)
+            .stdin(Stdio::inherit())
+            .status()
+            .is_ok_and(|status| status.success())
+    };
+    let hidden = cfg!(unix) && stty("-echo");
+    let mut line = Zeroizing::new(String::new());
+    let read = io::stdin().read_line(&mut line);
+    if hidden {
+        stty("echo");
+        eprintln!();
+    }
+    read?;
+    Ok(Zeroizing::new(
+        line.trim_end_matches(['\r', '\n']).to_string(),
+    ))
+}
+
+impl KeystoreFile {
+    /// Gets the master passphrase; a new keystore asks for it twice.
+    fn passphrase(&self, new: bool) -> Result<Zeroizing<String>, CliError> {
+        if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+            return Ok(Zeroizing::new(passphrase));
+        }
+        if !io::stdin().is_terminal() {
+            return Err(CliError::InvalidInput(format!(
+                "Set {PASSPHRASE_ENV} to the passphrase of the keystore '{}'",
+                self.path.display()
+            )));
+        }
+        let passphrase = read_hidden(&tr!(
+            "prompt-keystore-passphrase",
+            path = self.path.display()
+        ))?;
+        if new && *read_hidden(&tr!("prompt-keystore-repeat"))? != *passphrase {
+            return Err(CliError::InvalidInput(
+                "The passphrases do not match".to_string(),
+            ));
+        }
+        if passphrase.is_empty() {
+            return Err(CliError::InvalidInput(
+                "The keystore passphrase cannot be empty".to_string(),
+            ));
+        }
+        Ok(passphrase)
+    }
+
+    /// The encrypted keystore, or `None` if it doesn't exist yet.
+    fn read_file(&self) -> Result<Option<Vec<u8>>, CliError> {
+        match fs::read(&self.path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(CliError::Io(io::Error::new(
+                e.kind(),
+                format!("Failed to read keystore '{}': {e}", self.path.display()),
+            ))),
+        }
+    }
+
+    fn parse(&self, plain: &[u8]) -> Result<KeystoreContents, CliError> {
+        serde_json::from_slice(plain).map_err(|e| {
+            CliError::Format(format!(
+                "The keystore '{}' is damaged: {e}",
+                self.path.display()
+            ))
+        })
+    }
+
+    /// Decrypts the keystore for reading, without the passphrase if a running agent still
+    /// holds its key.
+    async fn read(&self) -> Result<KeystoreContents, CliError> {
+        if let Some(data) = self.read_file()?
+            && let Ok(file) = XdReader::parse(&data)
+            && let Some(key) = agent::cached_file_key(&file)
+            && let Ok(plain) = crypto::decrypt_parsed(&file, &key)
+        {
+            return self.parse(&Zeroizing::new(plain));
+        }
+        Ok(self.open().await?.0)
+    }
+
+    /// Decrypts the keystore, returning it with the passphrase that opened it. A missing
+    /// file is an empty keystore.
+    async fn open(&self) -> Result<(KeystoreContents, Zeroizing<String>), CliError> {
+        let Some(data) = self.read_file()? else {
+            return Ok((KeystoreContents::default(), self.passphrase(true)?));
+        };
+        let passphrase = self.passphrase(false)?;
+        let (plain, _) = agent::decrypt_with_password(&data, passphrase.to_string())
+            .await
+            .map_err(|e| match e {
+                crypto::CryptoError::AuthenticationError => CliError::Authentication(format!(
+                    "Wrong passphrase for the keystore '{}', or the file was tampered with",
+                    self.path.display()
+                )),
+                e => CliError::from_crypto("Failed to open the keystore", e),
+            })?;
+        Ok((self.parse(&Zeroizing::new(plain))?, passphrase))
+    }
+
+    /// Encrypts `contents` under `passphrase` and replaces the file.
+    async fn save(
+        &self,
+        contents: &KeystoreContents,
+        passphrase: &Zeroizing<String>,
+    ) -> Result<(), CliError> {
+        let plain = Zeroizing::new(
+            serde_json::to_vec(contents).map_err(|e| CliError::Crypto(e.to_string()))?,
+        );
+        let mut salt = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut salt);
+        let encrypted = crypto::encrypt_with_password_params_async(
+            &plain,
+            passphrase.to_string(),
+            KEYSTORE_FILENAME,
+            salt.to_vec(),
+            self.params,
+            None,
+        )
+        .await
+        .map_err(|e| CliError::from_crypto("Failed to encrypt the keystore", e))?;
+        if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        write_atomic(&self.path.to_string_lossy(), &encrypted)
+    }
+}
+
+/// Where named keys are kept.
+enum Store {
+    Os(Backend),
+    File(KeystoreFile),
+}
+
+impl Store {
+    /// The keystore file from the config, or else the platform's secret store.
+    fn from_config(config: &Config) -> Result<Self, CliError> {
+        Ok(match &config.keystore {
+            Some(path) => Store::File(KeystoreFile {
+                path: path.clone(),
+                params: config
+                    .kdf_profile()
+                    .map_err(|e| CliError::InvalidInput(e.to_string()))?
+                    .params(),
+            }),
+            None => Store::Os(Backend::current()),
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Store::Os(backend) => backend.name(),
+            Store::File(_) => "file",
+        }
+    }
+
+    fn display(&self) -> String {
+        match self {
+            Store::Os(backend) => backend.display().to_string(),
+            Store::File(file) => format!("the keystore '{}'", file.path.display()),
+        }
+    }
+
+    async fn get(&self, name: &str) -> Result<Option<Zeroizing<String>>, CliError> {
+        match self {
+            Store::Os(backend) => backend.lookup(name),
+            Store::File(file) => {
+                let contents = file.read().await?;
+                Ok(contents.keys.get(name).cloned().map(Zeroizing::new))
+            }
+        }
+    }
+
+    /// Saves `key` as `name`, replacing an existing key only if `force` is set.
+    async fn insert(&self, name: &str, key: &str, force: bool) -> Result<(), CliError> {
+        let exists = || {
+            CliError::InvalidInput(format!(
+                "A key named '{name}' already exists in {}; use --force to replace it",
+                self.display()
+            ))
+        };
+        match self {
+            Store::Os(backend) => {
+                if !force && backend.lookup(name)?.is_some() {
+                    return Err(exists());
+                }
+                backend.store(name, key)?;
+                // A locked or read-only store can accept the write and still lose it
+                if backend.lookup(name)?.as_ref().map(|saved| saved.as_str()) != Some(key) {
+                    return Err(CliError::Io(io::Error::other(format!(
+                        "The key was not saved: {} returned something else when read back",
+                        backend.display()
+                    ))));
+                }
+                Ok(())
+            }
+            Store::File(file) => {
+                let (mut contents, passphrase) = file.open().await?;
+                if !force && contents.keys.contains_key(name) {
+                    return Err(exists());
+                }
+                contents.keys.insert(name.to_string(), key.to_string());
+                file.save(&contents, &passphrase).await
+            }
+        }
+    }
+
+    /// Deletes `name`, returning its key.
+    async fn delete(&self, name: &str) -> Result<Zeroizing<String>, CliError> {
+        match self {
+            Store::Os(backend) => {
+                let key = backend.lookup(name)?.ok_or_else(|| self.missing(name))?;
+                backend.remove(name)?;
+                Ok(key)
+            }
+            Store::File(file) => {
+                let (mut contents, passphrase) = file.open().await?;
+                let key = contents
+                    .keys
+                    .remove(name)
+                    .map(Zeroizing::new)
+                    .ok_or_else(|| self.missing(name))?;
+                file.save(&contents, &passphrase).await?;
+                Ok(key)
+            }
+        }
+    }
+
+    async fn names(&self) -> Result<Vec<String>, CliError> {
+        match self {
+            Store::Os(backend) => backend.list(),
+            Store::File(file) => Ok(file.read().await?.keys.keys().cloned().collect()),
+        }
+    }
+
+    fn missing(&self, name: &str) -> CliError {
+        CliError::InvalidInput(format!(
+            "No key named '{name}' in {}; store one with `key add {name}`",
+            self.display()
+        ))
+    }
+}
+
+/// Result of `key add`, `key export` and `key rm`, printed with `--json`.
+#[derive(Serialize)]
+pub struct KeystoreReport {
+    pub operation: &'static str,
+    pub name: String,
+    /// "secret-service", "keychain", "credential-manager" or "file"
+    pub backend: &'static str,
+    pub key_fingerprint: String,
+    /// Whether the key was generated rather than given with --key
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub generated: bool,
+    /// The key, base64 (`key export` only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+/// Checks a key name: letters, digits, '.', '_' and '-', so it is safe in every tool's
+/// command line.
+fn validate_name(name: &str) -> Result<(), CliError> {
+    if name.is_empty()
+        || name.len() > MAX_NAME_LEN
+        || !name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"._-".contains(&b))
+    {
+        return Err(CliError::InvalidInput(format!(
+            "Key name '{name}' must be 1-{MAX_NAME_LEN} letters, digits, '.', '_' or '-'"
+        )));
+    }
+    Ok(())
+}
+
+/// Result of `key list`, printed with `--json`.
+#[derive(Serialize)]
+pub struct KeyListReport {
+    pub operation: &'static str,
+    pub backend: &'static str,
+    pub names: Vec<String>,
+}
+
+/// Reads the key stored as `name`, returned as base64 like `--key`.
+pub async fn load_key(name: &str, config: &Config) -> Result<Zeroizing<String>, CliError> {
+    validate_name(name)?;
+    let store = Store::from_config(config)?;
+    let key = store.get(name).await?.ok_or_else(|| store.missing(name))?;
+    validate_key(&key).map_err(|_| {
+        CliError::Format(format!(
+            "The key named '{name}' in {} is not a base64 256-bit key",
+            store.display()
+        ))
+    })?;
+    Ok(key)
+}
+
+fn fingerprint(key_b64: &str) -> Result<String, CliError> {
+    Ok(crypto::key_fingerprint(&validate_key(key_b64)?))
+}
+
+/// Handles `key add`: stores `key`, or a new random key, as `name`.
+pub async fn add_command(
+    name: &str,
+    key: Option<&str>,
+    force: bool,
+    config: &Config,
+    json: bool,
+) -> Result<(), CliError> {
+    validate_name(name)?;
+    let store = Store::from_config(config)?;
+    let generated = key.is_none();
+    let key = match key {
+        Some(key) => Zeroizing::new(key.to_string()),
+        None => {
+            let mut bytes = Zeroizing::new([0u8; 32]);
+            rand::rngs::OsRng.fill_bytes(&mut bytes[..]);
+            Zeroizing::new(general_purpose::STANDARD.encode(&bytes[..]))
+        }
+    };
+    let key_fingerprint = fingerprint(&key)?;
+    store.insert(name, &key, force).await?;
+
+    if json {
+        print_json_report(&KeystoreReport {
+            operation: "key-add",
+            name: name.to_string(),
+            backend: store.name(),
+            key_fingerprint,
+            generated,
+            key: None,
+        });
+        return Ok(());
+    }
+    let origin = if generated { "Generated key" } else { "Key" };
+    status(format!(
+        "🔑 {origin} {key_fingerprint} saved as '{name}' in {}",
+        store.display()
+    ));
+    status(format!(
+        "💡 Use it with --key-name {name}; `key export {name}` prints it for a backup."
+    ));
+    Ok(())
+}
+
+/// Handles `key list`: prints the names of the stored keys.
+pub async fn list_command(config: &Config, json: bool) -> Result<(), CliError> {
+    let store = Store::from_config(config)?;
+    let names = store.names().await?;
+    if json {
+        print_json_report(&KeyListReport {
+            operation: "key-list",
+            backend: store.name(),
+            names,
+        });
+        return Ok(());
+    }
+    if names.is_empty() {
+        println!("🔑 No keys in {}", store.display());
+    } else {
+        println!("🔑 {} key(s) in {}:", names.len(), store.display());
+        for name in &names {
+            println!("{name}");
+        }
+    }
+    Ok(())
+}
+
+/// Handles `key export`: prints the key stored as `name`.
+pub async fn export_command(name: &str, config: &Config, json: bool) -> Result<(), CliError> {
+    let key = load_key(name, config).await?;
+    let key_fingerprint = fingerprint(&key)?;
+    if json {
+        print_json_report(&KeystoreReport {
+            operation: "key-export",
+            name: name.to_string(),
+            backend: Store::from_config(config)?.name(),
+            key_fingerprint,
+            generated: false,
+            key: Some(key.to_string()),
+        });
+        return Ok(());
+    }
+    println!("{}", key.as_str());
+    Ok(())
+}
+
+/// Handles `key rm`: deletes the key stored as `name`.
+pub async fn remove_command(name: &str, config: &Config, json: bool) -> Result<(), CliError> {
+    validate_name(name)?;
+    let store = Store::from_config(config)?;
+    let key = store.delete(name).await?;
+    let key_fingerprint = fingerprint(&key).unwrap_or_default();
+
+    if json {
+        print_json_report(&KeystoreReport {
+            operation: "key-rm",
+            name: name.to_string(),
+            backend: store.name(),
+            key_fingerprint,
+            generated: false,
+            key: None,
+        });
+        return Ok(());
+    }
+    status(format!(
+        "🗑️  Removed key {key_fingerprint} ('{name}') from {}",
+        store.display()
+    ));
+    Ok(())
+}
@@ -0,0 +1,371 @@
+//!
+//! Cloud key management services holding the key that protects a file.
+//!
+//! `encrypt --kms URI` adds a kms keyslot whose random key-encryption key is wrapped by the
+//! service key the URI names; `decrypt` sends it back to the same key to be unwrapped, so the
+//! file opens for whoever the service's access policy lets use that key. The provider is
+//! picked by the URI's scheme:
+//! - `gcpkms://projects/P/locations/L/keyRings/R/cryptoKeys/K`: Google Cloud KMS, through
+//!   `gcloud kms encrypt/decrypt`
+//! - `azurekv://VAULT.vault.azure.net/keys/NAME[/VERSION]`: an RSA key in Azure Key Vault
+//!   (wrapped with RSA-OAEP-256), through `az` for an access token and `curl` for the call
+//!
+//! Both use the CLI's own login (`gcloud auth login`, `az login`), and secrets go to the tools
+//! through pipes only. Set `ENCRYPTX_KMS_TOOLS` to the directory holding the tools if they
+//! aren't on `PATH`.
+//!
+use super::{CliError, status};
+use crate::crypto::keyslot::KIND_KMS;
+use crate::format::XdReader;
+use base64::{Engine, engine::general_purpose};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use zeroize::Zeroizing;
+
+/// Environment variable naming the directory of the providers' tools.
+const TOOLS_ENV: &str = "ENCRYPTX_KMS_TOOLS";
+
+/// Key Vault REST API version used for wrapkey/unwrapkey.
+const AZURE_API_VERSION: &str = "7.4";
+
+/// A key wrapped by a provider.
+pub struct Wrapped {
+    /// URI of the exact key (version) that wrapped it, as stored in the slot
+    pub uri: String,
+    pub sealed: Vec<u8>,
+}
+
+/// A key management service that wraps and unwraps keys with a key it never hands out.
+///
+/// Both calls return the service's error message rather than failing when the service
+/// itself refuses (no permission, disabled key), so callers can tell that from a broken
+/// local setup.
+pub trait Provider {
+    /// Wraps `secret`.
+    fn wrap(&self, secret: &[u8]) -> Result<Result<Wrapped, String>, CliError>;
+    /// Unwraps a key returned by [`Provider::wrap`].
+    fn unwrap(&self, sealed: &[u8]) -> Result<Result<Zeroizing<Vec<u8>>, String>, CliError>;
+}
+
+/// Returns the provider for a key URI.
+pub fn provider(uri: &str) -> Result<Box<dyn Provider>, CliError> {
+    let invalid =
+        |expected: &str| CliError::InvalidInput(format!("--kms '{uri}' must look like {expected}"));
+    match uri.split_once("://") {
+        Some(("gcpkms", name)) => {
+            let parts: Vec<&str> = name.split('/').collect();
+            // projects/P/locations/L/keyRings/R/cryptoKeys/K
+            let valid = parts.len() == 8
+                && parts
+                    .iter()
+                    .step_by(2)
+                    .eq(&["projects", "locations", "keyRings", "cryptoKeys"])
+                && parts.iter().all(|part| valid_segment(part));
+            if !valid {
+                return Err(invalid(
+                    "gcpkms://projects/P/locations/L/keyRings/R/cryptoKeys/K",
+                ));
+            }
+            Ok(Box::new(GcpKms {
+                name: name.to_string(),
+            }))
+        }
+        Some(("azurekv", rest)) => {
+            let parts: Vec<&str> = rest.split('/').collect();
+            let (vault, key, version) = match parts.as_slice() {
+                [vault, "keys", key] => (*vault, *key, None),
+                [vault, "keys", key, version] => (*vault, *key, Some(*version)),
+                _ => return Err(invalid("azurekv://VAULT.vault.azure.net/keys/NAME")),
+            };
+            let valid_host = vault.contains('.')
+                && vault
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"-.".contains(&b));
+            if !valid_host || !valid_segment(key) || !version.is_none_or(valid_segment) {
+                return Err(invalid("azurekv://VAULT.vault.azure.net/keys/NAME"));
+            }
+            Ok(Box::new(AzureKeyVault {
+                vault: vault.to_string(),
+                key: key.to_string(),
+                version: version.map(str::to_string),
+            }))
+        }
+        _ => Err(CliError::InvalidInput(format!(
+            "--kms '{uri}' must start with gcpkms:// or azurekv://"
+        ))),
+    }
+}
+
+/// Whether a URI path segment is a plain resource name.
+fn valid_segment(segment: &str) -> bool {
+    !segment.is_empty()
+        && segment
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_".contains(&b))
+}
+
+fn tool_path(name: &str) -> PathBuf {
+    match std::env::var_os(TOOLS_ENV) {
+        Some(dir) => PathBuf::from(dir).join(name),
+        None => PathBuf::from(name),
+    }
+}
+
+/// Runs a provider tool with `input` on its stdin.
+fn run_tool(name: &str, args: &[&str], input: &[u8]) -> Result<Output, CliError> {
+    let mut child = Command::new(tool_path(name))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => {
+                CliError::InvalidInput(format!("{name} not found; install it or set {TOOLS_ENV}"))
+            }
+            _ => CliError::Io(io::Error::new(
+                e.kind(),
+                format!("Failed to run {name}: {e}"),
+            )),
+        })?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // The tool may fail (not logged in, no permission) before reading; its stderr says why
+        match stdin.write_all(input) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(child.wait_with_output()?)
+}
+
+/// Returns a tool's stdout, or its error message if it failed.
+fn tool_result(name: &str, output: Output) -> Result<Vec<u8>, String> {
+    if !output.status.success() {
+        return Err(format!(
+            "{name} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+/// A Google Cloud KMS symmetric key.
+struct GcpKms {
+    /// Resource name, `projects/.../cryptoKeys/K`
+    name: String,
+}
+
+impl GcpKms {
+    fn run(&self, operation: &str, input: &[u8]) -> Result<Result<Vec<u8>, String>, CliError> {
+        let key = format!("--key={}", self.name);
+        let output = run_tool(
+            "gcloud",
+            &[
+                "kms",
+                operation,
+                &key,
+                "--plaintext-file=-",
+                "--ciphertext-file=-",
+                "--quiet",
+            ],
+            input,
+        )?;
+        Ok(tool_result("gcloud", output))
+    }
+}
+
+impl Provider for GcpKms {
+    fn wrap(&self, secret: &[u8]) -> Result<Result<Wrapped, String>, CliError> {
+        // The ciphertext names the key version, so the URI needn't
+        Ok(self.run("encrypt", secret)?.map(|sealed| Wrapped {
+            uri: format!("gcpkms://{}", self.name),
+            sealed,
+        }))
+    }
+
+    fn unwrap(&self, sealed: &[u8]) -> Result<Result<Zeroizing<Vec<u8>>, String>, CliError> {
+        Ok(self.run("decrypt", sealed)?.map(Zeroizing::new))
+    }
+}
+
+/// Result of a Key Vault key operation.
+struct KeyOperation {
+    /// ID of the key version that did it
+    kid: String,
+    value: Zeroizing<Vec<u8>>,
+}
+
+/// An RSA key in Azure Key Vault (or Managed HSM).
+struct AzureKeyVault {
+    /// Host, e.g. `myvault.vault.azure.net`
+    vault: String,
+    key: String,
+    /// Key version; unversioned URIs use the current version
+    version: Option<String>,
+}
+
+impl AzureKeyVault {
+    /// Gets an access token for the vault's cloud from `az`'s login.
+    fn token(&self) -> Result<Result<Zeroizing<String>, String>, CliError> {
+        // myvault.vault.azure.net -> https://vault.azure.net
+        let audience = self.vault.split_once('.').map_or("", |(_, domain)| domain);
+        let resource = format!("https://{audience}");
+        let output = run_tool(
+            "az",
+            &[
+                "account",
+                "get-access-token",
+                "--resource",
+                &resource,
+                "--query",
+                "accessToken",
+                "--output",
+                "tsv",
+            ],
+            &[],
+        )?;
+        Ok(tool_result("az", output)
+            .map(|token| Zeroizing::new(String::from_utf8_lossy(&token).trim().to_string())))
+    }
+
+    /// Calls `wrapkey` or `unwrapkey` on `value`.
+    fn call(
+        &self,
+        operation: &str,
+        value: &[u8],
+    ) -> Result<Result<KeyOperation, String>, CliError> {
+        let token = match self.token()? {
+            Ok(token) => token,
+            Err(message) => return Ok(Err(message)),
+        };
+        let version = self
+            .version
+            .as_deref()
+            .map(|v| format!("/{v}"))
+            .unwrap_or_default();
+        let body = Zeroizing::new(
+            serde_json::json!({
+                "alg": "RSA-OAEP-256",
+                "value": general_purpose::URL_SAFE_NO_PAD.encode(value),
+            })
+            .to_string(),
+        );
+        // curl reads its whole configuration from stdin, so the token and key stay out of argv
+        let config = Zeroizing::new(format!(
+            "url = \"https://{}/keys/{}{version}/{operation}?api-version={AZURE_API_VERSION}\"\n\
+             header = \"Authorization: Bearer {}\"\n\
+             header = \"Content-Type: application/json\"\n\
+             data = \"{}\"\n\
+             silent\nshow-error\n\
+             write-out = \"\\n%{{http_code}}\"\n",
+            self.vault,
+            self.key,
+            token.as_str(),
+            body.replace('\\', "\\\\").replace('"', "\\\""),
+        ));
+        let output = match tool_result(
+            "curl",
+            run_tool("curl", &["--config", "-"], config.as_bytes())?,
+        ) {
+            Ok(output) => Zeroizing::new(output),
+            Err(message) => return Ok(Err(message)),
+        };
+        let text = String::from_utf8_lossy(&output);
+        let (response, code) = text.trim_end().rsplit_once('\n').unwrap_or(("", &text));
+        let response: serde_json::Value = serde_json::from_str(response).unwrap_or_default();
+        if !code.starts_with('2') {
+            let message = response["error"]["message"]
+                .as_str()
+                .unwrap_or("no error message");
+            return Ok(Err(format!("Key Vault returned HTTP {code}: {message}")));
+        }
+        let kid = response["kid"].as_str().unwrap_or_default().to_string();
+        match response["value"]
+            .as_str()
+            .and_then(|v| general_purpose::URL_SAFE_NO_PAD.decode(v).ok())
+        {
+            Some(value) => Ok(Ok(KeyOperation {
+                kid,
+                value: Zeroizing::new(value),
+            })),
+            None => Ok(Err(format!("Key Vault returned no {operation} result"))),
+        }
+    }
+}
+
+impl Provider for AzureKeyVault {
+    fn wrap(&self, secret: &[u8]) -> Result<Result<Wrapped, String>, CliError> {
+        let KeyOperation { kid, value: sealed } = match self.call("wrapkey", secret)? {
+            Ok(result) => result,
+            Err(message) => return Ok(Err(message)),
+        };
+        // Pin the version that wrapped it, so rotating the key doesn't strand the file
+        let version = kid
+            .rsplit_once('/')
+            .map(|(_, version)| version)
+            .filter(|v| valid_segment(v) && *v != self.key.as_str())
+            .map(str::to_string)
+            .or_else(|| self.version.clone());
+        let uri = match version {
+            Some(version) => format!("azurekv://{}/keys/{}/{version}", self.vault, self.key),
+            None => format!("azurekv://{}/keys/{}", self.vault, self.key),
+        };
+        Ok(Ok(Wrapped {
+            uri,
+            sealed: sealed.to_vec(),
+        }))
+    }
+
+    fn unwrap(&self, sealed: &[u8]) -> Result<Result<Zeroizing<Vec<u8>>, String>, CliError> {
+        Ok(self
+            .call("unwrapkey", sealed)?
+            .map(|operation| operation.value))
+    }
+}
+
+/// Wraps `secret` with the key at `uri`.
+pub fn wrap(uri: &str, secret: &[u8]) -> Result<Wrapped, CliError> {
+    match provider(uri)?.wrap(secret)? {
+        Ok(wrapped) if !wrapped.sealed.is_empty() => Ok(wrapped),
+        Ok(_) => Err(CliError::Crypto(format!(
+            "The key management service returned no wrapped key for {uri}"
+        ))),
+        Err(message) => Err(CliError::Crypto(format!(
+            "Wrapping with {uri} failed: {message}"
+        ))),
+    }
+}
+
+/// Whether `data` is a keyslot file with a slot wrapped by a key management service.
+pub fn wrapped_by_kms(data: &[u8]) -> bool {
+    XdReader::parse(data)
+        .ok()
+        .and_then(|file| {
+            let (slots, _) = file.header.keyslots()?;
+            Some(slots.iter().any(|slot| slot.kind == KIND_KMS))
+        })
+        .unwrap_or(false)
+}
+
+/// Returns a callback for [`crypto::decrypt_with_kms`].
+///
+/// Slots the service won't unwrap are skipped; its reason is shown, since a missing login or
+/// permission would otherwise look like a wrong key.
+pub fn asker() -> impl FnMut(&str, &[u8]) -> Option<Zeroizing<Vec<u8>>> {
+    move |uri, sealed| {
+        status(format!("🔐 Unwrapping with {uri}..."));
+        let unwrapped = provider(uri).and_then(|provider| provider.unwrap(sealed));
+        match unwrapped {
+            Ok(Ok(secret)) => Some(secret),
+            Ok(Err(message)) => {
+                status(format!("⚠️  The key management service refused: {message}"));
+                None
+            }
+            Err(e) => {
+                status(format!("⚠️  {e}"));
+                None
+            }
+        }
+    }
+}
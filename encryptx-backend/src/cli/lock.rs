@@ -0,0 +1,83 @@
+//!
+//! Advisory locking for in-place updates of encrypted files.
+//!
+//! Commands that rewrite a file in place (`rekey`, `keyslot`) read it, do the slow work
+//! (key derivation, re-encryption) without holding any lock, and only then lock the file
+//! and check that it still holds what they read before renaming the new version over it.
+//! A concurrent writer therefore either wins cleanly or is detected, never interleaved;
+//! callers decide whether to re-apply their change on top of the new version or give up.
+//!
+//! The lock is taken on the file being replaced. A writer that waited on a file which was
+//! renamed away meanwhile sees different contents at the path and backs off, so locking a
+//! stale inode is harmless.
+//!
+use super::{CliError, write_atomic};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Exclusive advisory lock, released when dropped.
+pub struct FileLock {
+    _file: fs::File,
+}
+
+/// Takes the exclusive lock on `path`, waiting for other holders to release it.
+pub fn lock(path: &str) -> Result<FileLock, CliError> {
+    let file = fs::File::open(path)
+        .and_then(|f| f.lock().map(|_| f))
+        .map_err(|e| {
+            CliError::Io(io::Error::new(
+                e.kind(),
+                format!("Failed to lock '{path}': {e}"),
+            ))
+        })?;
+    Ok(FileLock { _file: file })
+}
+
+/// Takes the exclusive lock on `<path>.lock`, creating it if need be, for files that are
+/// merged with what another writer put there rather than compared: the lock file is never
+/// renamed, so every writer locks the same inode however often `path` is replaced.
+pub fn lock_beside(path: &Path) -> Result<FileLock, CliError> {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".lock");
+    let lock_path = Path::new(&name);
+    let file = fs::File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_path)
+        .and_then(|f| f.lock().map(|_| f))
+        .map_err(|e| {
+            CliError::Io(io::Error::new(
+                e.kind(),
+                format!("Failed to lock '{}': {e}", lock_path.display()),
+            ))
+        })?;
+    Ok(FileLock { _file: file })
+}
+
+/// Atomically replaces `path` with `data`, but only if it still contains `expected`.
+///
+/// Returns `Ok(false)` without writing when another process changed the file since it was
+/// read.
+pub fn replace_if_unchanged(path: &str, expected: &[u8], data: &[u8]) -> Result<bool, CliError> {
+    let _lock = lock(path)?;
+    let current = fs::read(path).map_err(|e| {
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to re-read '{path}': {e}"),
+        ))
+    })?;
+    if current != expected {
+        return Ok(false);
+    }
+    write_atomic(path, data)?;
+    Ok(true)
+}
+
+/// Error for an in-place update that lost a race with another writer.
+pub fn conflict(path: &str) -> CliError {
+    CliError::Io(io::Error::other(format!(
+        "'{path}' was modified by another process while it was being updated; nothing was written"
+    )))
+}
@@ -0,0 +1,220 @@
+//!
+//! The manifest `encrypt --dir` writes next to the encrypted files, and the
+//! `verify-manifest` subcommand that checks a directory against it.
+//!
+//! The manifest lists every file encrypted, with its size, the SHA-256 hash of its
+//! plaintext and the `.xd` it was encrypted to. It is encrypted like the files themselves,
+//! since the hashes would let anyone confirm a guess of a file's contents. After a
+//! restore, `verify-manifest` proves the tree came back complete and unaltered, which the
+//! per-file authentication alone can't: a file missing from the backup decrypts to nothing.
+//!
+use super::{CliError, print_json_report, read_input, status, unwrap_input, validate_key};
+use crate::api;
+use crate::crypto;
+use crate::walk::{self, EntryKind, Filter, SymlinkPolicy};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Instant;
+
+/// Name of the manifest in the output directory of `encrypt --dir`. Encrypted files are
+/// all named `<name>.xd`, so it can't collide with one.
+pub(super) const MANIFEST_FILE: &str = ".encryptx-manifest";
+
+/// Name the manifest's plaintext is recorded under, and `decrypt` restores it to.
+pub(super) const MANIFEST_NAME: &str = "encryptx-manifest.json";
+
+/// Version of the manifest layout.
+const MANIFEST_VERSION: u32 = 1;
+
+/// Plaintext of the manifest.
+#[derive(Serialize, Deserialize)]
+pub(super) struct Manifest {
+    version: u32,
+    /// Unix time the manifest was written
+    created: u64,
+    files: Vec<ManifestEntry>,
+}
+
+/// One file of the encrypted directory.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub(super) struct ManifestEntry {
+    /// Path relative to the directory, using `/` separators
+    pub path: String,
+    pub size: u64,
+    /// SHA-256 of the plaintext, hex
+    pub sha256: String,
+    /// The encrypted file, relative to the output directory
+    pub output: String,
+}
+
+impl Manifest {
+    pub(super) fn new(mut files: Vec<ManifestEntry>) -> Self {
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        let created = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        Manifest {
+            version: MANIFEST_VERSION,
+            created,
+            files,
+        }
+    }
+
+    /// The files listed, by their path.
+    pub(super) fn into_files(self) -> BTreeMap<String, ManifestEntry> {
+        self.files
+            .into_iter()
+            .map(|entry| (entry.path.clone(), entry))
+            .collect()
+    }
+}
+
+/// Reads the manifest in `data`, decrypting it with `password` or `key` unless it was
+/// already decrypted with `decrypt`. `name` is what errors call it.
+pub(super) async fn open_manifest(
+    data: &[u8],
+    name: &str,
+    password: Option<&str>,
+    key: Option<&[u8]>,
+) -> Result<Manifest, CliError> {
+    let manifest: Manifest = match serde_json::from_slice(data) {
+        Ok(manifest) => manifest,
+        Err(_) => {
+            crypto::signing::verify(data)
+                .map_err(|e| CliError::from_crypto("Signature check failed", e))?;
+            let (plaintext, _) = api::decrypt_file_bytes(data, password, key)
+                .await
+                .map_err(|e| CliError::from_api("Opening the manifest failed", e))?;
+            serde_json::from_slice(&plaintext)
+                .map_err(|e| CliError::Format(format!("'{name}' is not a manifest: {e}")))?
+        }
+    };
+    if manifest.version != MANIFEST_VERSION {
+        return Err(CliError::Format(format!(
+            "Manifest '{name}' has unsupported version {}",
+            manifest.version
+        )));
+    }
+    Ok(manifest)
+}
+
+/// SHA-256 of a file's contents, hex.
+pub(super) fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+/// Outcome of a successful `verify-manifest` run.
+#[derive(Serialize)]
+struct ManifestReport {
+    operation: &'static str,
+    manifest: String,
+    dir: String,
+    /// Files that match the manifest
+    matched: usize,
+    /// Files in the directory the manifest doesn't list; they don't fail the check
+    extra: Vec<String>,
+    duration_ms: u128,
+}
+
+/// Handles the `verify-manifest` subcommand.
+///
+/// Every listed file is checked, so all missing and modified files are reported, not just
+/// the first one.
+pub async fn verify_manifest_command(
+    manifest_file: &str,
+    dir: &str,
+    password: Option<String>,
+    key: Option<String>,
+    json: bool,
+) -> Result<(), CliError> {
+    let started = Instant::now();
+    if password.is_some() && key.is_some() {
+        return Err(CliError::InvalidInput(
+            "Cannot specify both password and key. Choose one.".to_string(),
+        ));
+    }
+    let key = key.as_deref().map(validate_key).transpose()?;
+    let data = unwrap_input(manifest_file, read_input(manifest_file, "manifest")?)?;
+    // A manifest already decrypted with `decrypt` is taken as it is
+    let manifest = open_manifest(&data, manifest_file, password.as_deref(), key.as_deref()).await?;
+
+    status(format!(
+        "🔍 Checking '{dir}' against {} file(s) in '{manifest_file}'...",
+        manifest.files.len()
+    ));
+    let root = Path::new(dir);
+    let mut problems = Vec::new();
+    for entry in &manifest.files {
+        let path = root.join(&entry.path);
+        let problem = match fs::metadata(&path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Some("missing".to_string()),
+            Err(e) => Some(format!("unreadable: {e}")),
+            Ok(meta) if !meta.is_file() => Some("not a regular file".to_string()),
+            Ok(meta) if meta.len() != entry.size => Some(format!(
+                "size is {} bytes, expected {}",
+                meta.len(),
+                entry.size
+            )),
+            Ok(_) => match hash_file(&path) {
+                Ok(hash) if hash == entry.sha256 => None,
+                Ok(_) => Some("contents differ".to_string()),
+                Err(e) => Some(format!("unreadable: {e}")),
+            },
+        };
+        if let Some(problem) = problem {
+            status(format!("❌ '{}': {problem}", entry.path));
+            problems.push(entry.path.as_str());
+        }
+    }
+
+    let listed: BTreeSet<&str> = manifest.files.iter().map(|e| e.path.as_str()).collect();
+    let entries = walk::walk(root, SymlinkPolicy::Skip, &Filter::default()).map_err(|e| {
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to walk '{dir}': {e}"),
+        ))
+    })?;
+    let extra: Vec<String> = entries
+        .into_iter()
+        .filter(|entry| entry.kind == EntryKind::File && !listed.contains(entry.name.as_str()))
+        .map(|entry| entry.name)
+        .collect();
+    for name in &extra {
+        status(format!("➕ '{name}': not in the manifest"));
+    }
+
+    if !problems.is_empty() {
+        return Err(CliError::Authentication(format!(
+            "{} of {} file(s) in '{dir}' don't match the manifest: {}",
+            problems.len(),
+            manifest.files.len(),
+            problems.join(", ")
+        )));
+    }
+    let matched = manifest.files.len();
+    if json {
+        print_json_report(&ManifestReport {
+            operation: "verify-manifest",
+            manifest: manifest_file.to_string(),
+            dir: dir.to_string(),
+            matched,
+            extra,
+            duration_ms: started.elapsed().as_millis(),
+        });
+    } else {
+        status(format!(
+            "✅ All {matched} file(s) in '{dir}' match the manifest"
+        ));
+    }
+    Ok(())
+}
@@ -1,14 +1,78 @@
 //!
 //! This is EncryptX, but in CLI form for CLI users.
 //!
-use crate::crypto;
+mod agent;
+mod backup;
+mod batch;
+mod bundle;
+mod capabilities;
+mod cat;
+mod check_config;
+mod clip;
+mod fix_header;
+mod hook;
+mod identity;
+mod inspect;
+mod key;
+mod keyslot;
+mod keystore;
+mod kms;
+mod lock;
+mod manifest;
+mod mount;
+mod pgp;
+mod pkcs11;
+mod qr;
+mod rekey;
+mod remote;
+mod replay;
+mod restore;
+mod s3;
+mod salvage;
+mod shred;
+mod sign;
+mod tarball;
+mod text;
+mod token;
+mod tpm;
+mod tree;
+mod upgrade;
+mod verify;
+mod view;
+mod volume;
+mod watch;
+mod webdav;
+
+use crate::api::{self, ApiError};
+use crate::backup::BackupError;
+use crate::cdc;
+use crate::compression;
+use crate::config::{self, Config};
+use crate::crypto::{
+    self,
+    padding::{self, Padding},
+    secret::SecretBuffer,
+    strength,
+};
+use crate::format::{ParsedHeader, XdReader};
+use crate::interop::{self, ArchiveEntry, zip_aes};
+use crate::locale::{self, tr};
+use crate::logging;
+use crate::stego;
+use crate::walk::{Filter, SymlinkPolicy};
 use base64::{Engine, engine::general_purpose};
-use clap::{Parser, Subcommand};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
 use rand::RngCore;
+use serde::Serialize;
+use std::borrow::Cow;
 use std::fs;
-use std::io;
-use std::path::Path;
-use zstd::stream::{encode_all, decode_all};
+use std::io::{self, IsTerminal, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use tracing::level_filters::LevelFilter;
+use zstd::stream::write::Encoder;
 
 /// Command-line interface for EncryptX Backend.
 ///
@@ -16,10 +80,75 @@ use zstd::stream::{encode_all, decode_all};
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
+    /// Path to a config file (defaults to ~/.config/encryptx/config.toml)
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Print a machine-readable JSON result to stdout (human messages go to stderr)
+    #[arg(long, global = true)]
+    json: bool,
+    /// Threads for compressing large inputs, sealing and opening chunks, password key
+    /// derivations and `encrypt` over several files; 0 uses every core the process may use.
+    /// Overrides `threads` in the config
+    #[arg(long, global = true, value_name = "N", value_parser = clap::value_parser!(u32).range(..=256))]
+    threads: Option<u32>,
+    /// Log more on stderr: -v adds debug events, -vv trace events
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "log_level")]
+    verbose: u8,
+    /// Log events at LEVEL and above on stderr (off, error, warn, info, debug or trace);
+    /// overrides RUST_LOG
+    #[arg(long, global = true, value_name = "LEVEL")]
+    log_level: Option<LevelFilter>,
+    /// Also append the log to FILE, with timestamps and secrets redacted
+    #[arg(long, global = true, value_name = "FILE")]
+    log_file: Option<PathBuf>,
+    /// Language of messages, e.g. `de` or `de_DE.UTF-8` (error details and some subcommands
+    /// are English only); defaults to the one LC_ALL, LC_MESSAGES or LANG names, else
+    /// English
+    #[arg(long, global = true, value_name = "LANG")]
+    locale: Option<String>,
+    /// Run encrypt and decrypt on an EncryptX server (e.g. https://host:8080) instead of
+    /// locally; files are still read and written here
+    #[arg(long, global = true, value_name = "URL")]
+    remote: Option<String>,
+    /// Validate the server configuration and exit without binding any ports
+    #[arg(long)]
+    check_config: bool,
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
+impl Cli {
+    /// Returns the config file path given on the command line, if any.
+    pub fn config_path(&self) -> Option<&Path> {
+        self.config.as_deref()
+    }
+
+    /// Returns `true` when `--json` output was requested.
+    pub fn json(&self) -> bool {
+        self.json
+    }
+
+    /// The log level asked for with `-v` or `--log-level`, if any.
+    fn log_level(&self) -> Option<LevelFilter> {
+        match (self.log_level, self.verbose) {
+            (Some(level), _) => Some(level),
+            (None, 0) => None,
+            (None, 1) => Some(LevelFilter::DEBUG),
+            (None, _) => Some(LevelFilter::TRACE),
+        }
+    }
+
+    /// Loads the config file given with `--config` (or the default one), with `--threads`
+    /// in place of `threads` if given.
+    pub fn load_config(&self) -> Result<Config, config::ConfigError> {
+        let mut config = Config::load(self.config_path())?;
+        if let Some(threads) = self.threads {
+            config.threads = threads;
+        }
+        Ok(config)
+    }
+}
+
 /// CLI subcommands for encryption and decryption.
 #[derive(Subcommand)]
 pub enum Commands {
@@ -29,46 +158,1272 @@ pub enum Commands {
     ///   encrypt --file secret.txt --password supersecret
     ///   encrypt --file secret.txt --key BASE64KEY
     ///   encrypt --file secret.txt --output encrypted.xd
-    Encrypt {
-        /// Path to the file to encrypt
-        #[arg(short, long)]
+    ///   encrypt --file secret.txt --recipient xdpub1:... --recipient team.txt
+    ///   encrypt --file secret.txt --split-credentials
+    ///   encrypt --file secret.txt --tpm --tpm-pcrs 7
+    ///   encrypt --file secret.txt --kms gcpkms://projects/P/locations/global/keyRings/R/cryptoKeys/K
+    ///   encrypt --tar-from ./project --password supersecret --output - | ...
+    ///   encrypt --dir ./photos --output /mnt/backup/photos --key-name backup --incremental
+    Encrypt(Box<EncryptArgs>),
+    /// Decrypt a file using a password or key.
+    ///
+    /// Example:
+    ///   decrypt --file secret.xd --password supersecret
+    ///   decrypt --file secret.xd --key BASE64KEY
+    ///   decrypt --file secret.xd --output decrypted.txt
+    ///   decrypt --file secret.xd --identity me.key
+    ///   decrypt --file secret.xd --credential secret.xd.1.cred --credential secret.xd.2.cred
+    ///   ... | decrypt --password supersecret --untar-to .
+    Decrypt(DecryptArgs),
+    /// Print the decrypted contents of a file to stdout, without writing anything to disk.
+    ///
+    /// Example:
+    ///   cat secret.xd --password supersecret
+    ///   cat notes.xd --key-name work | less
+    ///   cat photo.jpg.xd --key BASE64KEY --force > /dev/null
+    Cat(CatArgs),
+    /// Watch a directory and encrypt every file created or changed in it into another
+    /// directory, until Ctrl-C.
+    ///
+    /// Example:
+    ///   watch ./dropbox-in --output ./encrypted-out --key-name backups
+    ///   watch ./scans --output /mnt/nas/scans --password mypass --exclude '*.tmp'
+    Watch(WatchArgs),
+    /// Show the metadata of an encrypted file without decrypting it.
+    ///
+    /// Example:
+    ///   inspect secret.xd
+    ///   inspect secret.xd --json
+    Inspect {
+        /// Path to the encrypted file ('-' reads from stdin)
+        file: String,
+    },
+    /// Re-encrypt a file under a new key or password without writing plaintext to disk.
+    ///
+    /// Example:
+    ///   rekey secret.xd --old-password oldpass --new-password newpass
+    ///   rekey secret.xd --old-password oldpass --new-key BASE64KEY --output rotated.xd
+    Rekey(RekeyArgs),
+    /// Rewrite files from older format versions (old headers, PBKDF2, weak KDF parameters)
+    /// in the current format, under the same password or key.
+    ///
+    /// Example:
+    ///   upgrade old.xd --password supersecret
+    ///   upgrade archive/*.xd --password supersecret --key BASE64KEY
+    ///   upgrade archive/*.xd --dry-run
+    Upgrade(UpgradeArgs),
+    /// Back up a directory as a named, timestamped snapshot into an encrypted repository,
+    /// storing only the chunks of data the repository doesn't have yet.
+    ///
+    /// Example:
+    ///   backup ~/documents --repo /mnt/usb/backups --password supersecret
+    ///   backup ~/documents --repo /mnt/usb/backups --key-name backup --name weekly
+    Backup(BackupArgs),
+    /// Restore a snapshot from a backup repository, whole or below some paths, or list the
+    /// snapshots.
+    ///
+    /// Example:
+    ///   restore --repo /mnt/usb/backups --snapshot 2024-06-01 --target ./restore/ --password supersecret
+    ///   restore --repo /mnt/usb/backups --snapshot weekly --target ./restore/ --path photos/2023 --key-name backup
+    ///   restore --repo /mnt/usb/backups --list --key-name backup
+    Restore(RestoreArgs),
+    /// Check that an encrypted file is intact without writing any plaintext.
+    ///
+    /// Example:
+    ///   verify secret.xd --password supersecret
+    ///   verify secret.xd --key BASE64KEY --json
+    Verify {
+        /// Path to the encrypted file ('-' reads from stdin)
         file: String,
-        /// Password to use for encryption (optional)
+        /// Password the file was encrypted with
+        #[arg(short, long)]
+        password: Option<String>,
+        /// Key the file was encrypted with (base64; defaults to the embedded key)
+        #[arg(short, long)]
+        key: Option<String>,
+    },
+    /// Check a directory, e.g. one restored from `encrypt --dir`, against the encrypted
+    /// manifest written with it: every file listed must be there with the same contents.
+    ///
+    /// Example:
+    ///   verify-manifest backup/.encryptx-manifest ./restored --key BASE64KEY
+    ///   verify-manifest backup/.encryptx-manifest ./restored --key-name backup --json
+    VerifyManifest {
+        /// Path to the manifest (encrypted, or as decrypted with `decrypt`)
+        manifest: String,
+        /// Directory to check
+        dir: String,
+        /// Password the manifest was encrypted with
+        #[arg(short, long)]
+        password: Option<String>,
+        /// Key the manifest was encrypted with (base64; defaults to the embedded key)
+        #[arg(short, long)]
+        key: Option<String>,
+        /// Use the key saved under this name (see `key add`)
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["password", "key"])]
+        key_name: Option<String>,
+    },
+    /// Mount an encrypted archive or an `encrypt --dir` output directory as a read-only
+    /// filesystem, to browse it without extracting it; Ctrl-C unmounts it. Needs a build
+    /// with `--features fuse`.
+    ///
+    /// Example:
+    ///   mount photos.xd /mnt/secret --password supersecret
+    ///   mount /mnt/backup/photos /mnt/secret --key-name backup
+    Mount {
+        /// Encrypted file, or a directory written by `encrypt --dir`
+        source: String,
+        /// Empty directory to mount it on
+        mountpoint: String,
+        /// Password the files were encrypted with
+        #[arg(short, long)]
+        password: Option<String>,
+        /// Key the files were encrypted with (base64; defaults to the embedded key)
+        #[arg(short, long)]
+        key: Option<String>,
+        /// Use the key saved under this name (see `key add`)
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["password", "key"])]
+        key_name: Option<String>,
+    },
+    /// Serve an encrypted archive or an `encrypt --dir` output directory over read-only
+    /// WebDAV, decrypting files as they are requested, so file managers can browse it
+    /// without extracting it; Ctrl-C stops the server.
+    ///
+    /// Example:
+    ///   webdav photos.xd --password supersecret
+    ///   webdav /mnt/backup/photos --listen 127.0.0.1:8091 --key-name backup
+    Webdav {
+        /// Encrypted file, or a directory written by `encrypt --dir`
+        source: String,
+        /// Address to listen on
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8090")]
+        listen: String,
+        /// Allow listening on an address other machines can reach; the server has no
+        /// authentication
+        #[arg(long)]
+        allow_remote: bool,
+        /// Password the files were encrypted with
         #[arg(short, long)]
         password: Option<String>,
-        /// Key to use for encryption (base64, optional; if not provided, random key is generated and printed)
+        /// Key the files were encrypted with (base64; defaults to the embedded key)
         #[arg(short, long)]
         key: Option<String>,
-        /// Output file path (optional; defaults to <basename>.xd)
+        /// Use the key saved under this name (see `key add`)
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["password", "key"])]
+        key_name: Option<String>,
+    },
+    /// Create a detached signature, so recipients can check who produced a file without
+    /// needing its decryption secret.
+    ///
+    /// Example:
+    ///   sign secret.xd --key signing.key
+    ///   sign secret.xd --key signing.key --output release.sig
+    Sign {
+        /// Path to the file to sign ('-' reads from stdin)
+        file: String,
+        /// Signing key file from `identity generate --signing`
+        #[arg(short, long, value_name = "PATH")]
+        key: String,
+        /// Signature file to write (defaults to <file>.sig, '-' writes to stdout)
         #[arg(short, long)]
         output: Option<String>,
-        /// Force overwrite if output file exists
+        /// Overwrite an existing signature file
         #[arg(long)]
         force: bool,
     },
-    /// Decrypt a file using a password or key.
+    /// Check a detached signature from `sign` against the signer's verifying key.
     ///
     /// Example:
-    ///   decrypt --file secret.xd --password supersecret
-    ///   decrypt --file secret.xd --key BASE64KEY
-    ///   decrypt --file secret.xd --output decrypted.txt
-    Decrypt {
-        /// Path to the file to decrypt
-        #[arg(short, long)]
+    ///   verify-sig secret.xd secret.xd.sig --pubkey xdsigpub1:...
+    ///   verify-sig secret.xd secret.xd.sig --pubkey alice.pub
+    VerifySig {
+        /// Path to the signed file ('-' reads from stdin)
+        file: String,
+        /// Path to the signature file
+        signature: String,
+        /// Verifying key the signature must come from (a key string or a file containing one)
+        #[arg(long, value_name = "KEY")]
+        pubkey: String,
+    },
+    /// Put a file split with `encrypt --volume-size` back together from its volumes.
+    ///
+    /// Example:
+    ///   join backup.xd.001
+    ///   join backup.xd --output /mnt/usb/backup.xd
+    Join {
+        /// First volume (<name>.001), or the name the volumes were split from
         file: String,
-        /// Password to use for decryption (optional)
+        /// File to write (defaults to the name without the volume number, '-' writes to
+        /// stdout)
         #[arg(short, long)]
-        password: Option<String>,
-        /// Key to use for decryption (base64, optional)
+        output: Option<String>,
+        /// Overwrite an existing output file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Recover the intact parts of a damaged or truncated encrypted file.
+    ///
+    /// Example:
+    ///   salvage backup.xd --password supersecret
+    ///   salvage backup.xd --key BASE64KEY --output recovered.tar
+    Salvage(SalvageArgs),
+    /// Rebuild a damaged header so the intact ciphertext can be decrypted again.
+    ///
+    /// Example:
+    ///   fix-header secret.xd --key BASE64KEY --filename report.pdf
+    ///   fix-header secret.xd --password supersecret --salt BASE64SALT --timestamp 1700000000
+    FixHeader(FixHeaderArgs),
+    /// Run saved fuzz and crash inputs through every file parser and report any that panic
+    /// or hang, so previously fixed crashes stay fixed.
+    ///
+    /// Example:
+    ///   replay-corpus fuzz/artifacts
+    ///   replay-corpus fuzz/corpus --json
+    ReplayCorpus {
+        /// Directory of inputs, searched recursively
+        dir: String,
+    },
+    /// List supported ciphers, KDFs, format versions, compression, size limits and enabled
+    /// features, as served by the API at GET /capabilities.
+    ///
+    /// Example:
+    ///   capabilities
+    ///   capabilities --json
+    Capabilities,
+    /// Split a key into Shamir shares, or combine shares back into the key. Also manages the
+    /// named keys used by --key-name.
+    ///
+    /// Example:
+    ///   key split --key BASE64KEY --shares 5 --threshold 3
+    ///   key combine SHARE1 SHARE2 share3.txt
+    ///   key add work
+    ///   key list
+    ///   encrypt --file secret.txt --key-name work
+    Key {
+        #[command(subcommand)]
+        action: KeyAction,
+    },
+    /// Generate an X25519 (optionally hybrid post-quantum) identity for public-key encryption,
+    /// or an Ed25519 key for signing encrypted files.
+    ///
+    /// Example:
+    ///   identity generate --output me.key
+    ///   identity generate --output me.key --post-quantum
+    ///   identity generate --output signing.key --signing
+    Identity {
+        #[command(subcommand)]
+        action: IdentityAction,
+    },
+    /// Encrypt or decrypt the text on the system clipboard in place, as an armored message
+    /// that can be pasted into chat or email.
+    ///
+    /// Example:
+    ///   clip encrypt --key-name work
+    ///   clip decrypt --key-name work --clear-after 15
+    Clip {
+        #[command(subcommand)]
+        action: ClipAction,
+    },
+    /// Encrypt a short secret into an armored message for chat or email, or decrypt one.
+    /// The text comes from the argument or stdin.
+    ///
+    /// Example:
+    ///   text encrypt --key-name work "db password: hunter2"
+    ///   pass show db | text encrypt --password mypass
+    ///   text decrypt --key-name work < message.txt
+    Text {
+        #[command(subcommand)]
+        action: TextAction,
+    },
+    /// Enroll FIDO2 security keys (YubiKey, SoloKey, ...) for use as keyslots.
+    ///
+    /// Example:
+    ///   token list
+    ///   token enroll --output yubikey.token
+    ///   keyslot add secret.xd --password mypass --new-token yubikey.token
+    ///   decrypt --file secret.xd --token
+    Token {
+        #[command(subcommand)]
+        action: TokenAction,
+    },
+    /// Add or remove credentials (keyslots) that can unlock a file.
+    ///
+    /// Example:
+    ///   keyslot add secret.xd --password mypass --new-key BASE64KEY --label recovery
+    ///   keyslot remove secret.xd --slot 1 --password mypass
+    Keyslot {
+        #[command(subcommand)]
+        action: KeyslotAction,
+    },
+    /// Run an agent that caches the keys of opened files for a while, so repeated commands
+    /// don't need the password (or keystore passphrase) again.
+    ///
+    /// Example:
+    ///   agent start --ttl 30m &
+    ///   decrypt --file a.xd --password supersecret
+    ///   decrypt --file a.xd --output again.txt
+    ///   agent stop
+    Agent {
+        #[command(subcommand)]
+        action: AgentAction,
+    },
+    /// Manage the Git pre-commit hook that blocks plaintext secrets.
+    ///
+    /// Example:
+    ///   hook install --pattern 'secrets/*.env'
+    Hook {
+        #[command(subcommand)]
+        action: HookAction,
+    },
+}
+
+/// Arguments for the `encrypt` subcommand.
+#[derive(Args, Clone, Default)]
+#[command(group(ArgGroup::new("directory").args(["tar_from", "dir"])))]
+#[command(group(ArgGroup::new("input").args(["files", "paths"]).multiple(true)))]
+pub struct EncryptArgs {
+    /// Path to the file to encrypt ('-' reads from stdin, s3://BUCKET/KEY from S3); repeat
+    /// it to encrypt several files
+    #[arg(
+        short,
+        long = "file",
+        value_name = "FILE",
+        required_unless_present_any = ["tar_from", "dir", "paths"]
+    )]
+    files: Vec<String>,
+    /// Files or glob patterns to encrypt, each into a <name>.xd file of its own (quote
+    /// patterns, e.g. 'docs/**/*.pdf', so the shell leaves them alone)
+    #[arg(value_name = "PATH")]
+    paths: Vec<String>,
+    /// With several files, how many are encrypted at the same time (default: `--threads`)
+    #[arg(long, value_name = "N")]
+    jobs: Option<usize>,
+    /// The one file encrypted, from --file or the paths
+    #[arg(skip)]
+    file: Option<String>,
+    /// Directory to pack into a tar archive and encrypt as a single file
+    #[arg(long, conflicts_with = "input")]
+    tar_from: Option<String>,
+    /// Directory whose files are each encrypted on their own, into the same tree below
+    /// --output as <name>.xd files
+    #[arg(
+        long,
+        requires = "output",
+        conflicts_with_all = [
+            "input", "tar_from", "wrap", "bundle_readme", "delta_from", "split_credentials",
+            "recovery_key", "recovery_out", "in_place", "shred_original"
+        ]
+    )]
+    dir: Option<String>,
+    /// With --dir, only encrypt the files that changed since the last run, as recorded in a
+    /// state file (see --state); outputs of files deleted since are removed
+    #[arg(long, requires = "dir")]
+    incremental: bool,
+    /// Where --incremental records sizes, modification times and SHA-256 hashes of the
+    /// files (defaults to .encryptx-state.json in the --dir directory)
+    #[arg(long, value_name = "PATH", requires = "incremental")]
+    state: Option<String>,
+    /// With --tar-from or --dir, pack what symlinks point at in their place (a link leading
+    /// back into the directory is an error)
+    #[arg(long, requires = "directory", conflicts_with_all = ["store_symlinks", "skip_symlinks"])]
+    follow_symlinks: bool,
+    /// With --tar-from, store symlinks as links to their targets (the default, except for
+    /// --format zip-aes, which can't store them)
+    #[arg(long, requires = "tar_from", conflicts_with_all = ["skip_symlinks", "dir"])]
+    store_symlinks: bool,
+    /// With --tar-from or --dir, leave symlinks out (the default for --dir and --format
+    /// zip-aes)
+    #[arg(long, requires = "directory")]
+    skip_symlinks: bool,
+    /// With --tar-from or --dir, leave out what matches this .gitignore-style pattern, e.g.
+    /// '*.tmp' or 'target/' (repeatable; a later '!pattern' keeps it after all)
+    #[arg(long, value_name = "PATTERN", requires = "directory")]
+    exclude: Vec<String>,
+    /// With --tar-from or --dir, read exclude patterns from this file, one per line like
+    /// .gitignore, e.g. .encryptxignore (repeatable; read before the --exclude patterns)
+    #[arg(long, value_name = "PATH", requires = "directory")]
+    exclude_from: Vec<String>,
+    /// With --tar-from or --dir, only take files matching this pattern or inside a directory
+    /// matching it (repeatable); excludes still apply
+    #[arg(long, value_name = "PATTERN", requires = "directory")]
+    include: Vec<String>,
+    /// Password to use for encryption (optional)
+    #[arg(short, long)]
+    password: Option<String>,
+    /// Key to use for encryption (base64, optional; if not provided, random key is generated and printed)
+    #[arg(short, long)]
+    key: Option<String>,
+    /// Use the key saved under this name (see `key add`)
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["password", "key"])]
+    key_name: Option<String>,
+    /// Treat --key or --key-name as a master key: the file gets its own key, derived from
+    /// it with HKDF and a random salt, and no key is embedded
+    #[arg(long)]
+    master_key: bool,
+    /// Encrypt deterministically under --key or --key-name, so identical inputs give
+    /// identical files for deduplicating backups (reveals which files are equal)
+    #[arg(long, conflicts_with = "master_key")]
+    convergent: bool,
+    /// Output file path (optional; defaults to <basename>.xd, '-' writes to stdout,
+    /// s3://BUCKET/KEY to S3)
+    #[arg(short, long)]
+    output: Option<String>,
+    /// Output format: native .xd, an AES-256 ZIP or an OpenPGP message for recipients
+    /// without EncryptX
+    #[arg(long, value_enum, default_value_t = OutputFormat::Xd)]
+    format: OutputFormat,
+    /// With --format pgp, encrypt to this OpenPGP public key file (repeatable; combine with
+    /// --password to also allow password decryption)
+    #[arg(long = "pgp-recipient", value_name = "PATH")]
+    pgp_recipients: Vec<String>,
+    /// With --format pgp, write an ASCII-armored message (defaults to <basename>.asc)
+    #[arg(long)]
+    armor: bool,
+    /// Also write <output>.readme.html with the file's fingerprint and decryption steps for the recipient
+    #[arg(long)]
+    bundle_readme: bool,
+    /// Split the ciphertext into independently authenticated chunks of this encrypted size
+    /// (e.g. 8MiB, to match S3 multipart part sizes)
+    #[arg(long, value_name = "SIZE")]
+    chunk_size: Option<String>,
+    /// zstd level to compress at before encrypting, up to 22 (smallest); negative levels are
+    /// faster. Overrides `compression_level` in the config
+    #[arg(long, value_name = "LEVEL", allow_negative_numbers = true)]
+    compression_level: Option<i32>,
+    /// Store the data uncompressed. Without it, data that zstd can't shrink (video, images,
+    /// archives) is detected and stored uncompressed anyway
+    #[arg(long, conflicts_with_all = ["compression_level", "delta_from"])]
+    no_compress: bool,
+    /// Let zstd find repeats up to 128 MiB apart, for large inputs with distant
+    /// duplicates (uses up to 128 MiB more memory while compressing)
+    #[arg(long, conflicts_with = "no_compress")]
+    long: bool,
+    /// With --tar-from, compress with a zstd dictionary trained on the directory's files and
+    /// stored in the encrypted file; helps many small, similar files split into small chunks
+    #[arg(long, requires = "tar_from", conflicts_with_all = ["no_compress", "delta_from"])]
+    dict: bool,
+    /// Pad the file so its size hides the input size: 'padme' (at most 12% larger) or a
+    /// bucket size such as 1MiB to round up to
+    #[arg(long, value_name = "SCHEME", conflicts_with = "delta_from")]
+    pad: Option<String>,
+    /// Record neither the original filename nor the time of encryption in the header
+    /// (decrypting then needs --output)
+    #[arg(long)]
+    no_metadata: bool,
+    /// Encrypt as a new version of this earlier .xd file, reusing the ciphertext of unchanged
+    /// chunks so rsync/backup tools only transfer what changed (same key/password required)
+    #[arg(long, value_name = "OLD")]
+    delta_from: Option<String>,
+    /// With --delta-from, cut compression frames and chunks at content-defined points so an
+    /// edit only changes the chunks around it
+    #[arg(long, requires = "delta_from")]
+    rsyncable: bool,
+    /// Hide the encrypted file inside a cover image, e.g. 'png:cover.png' (output defaults to <basename>.png)
+    #[arg(long, value_name = "SPEC")]
+    wrap: Option<String>,
+    /// Also generate a recovery key that unlocks the file if the password is lost
+    /// (printed once; see --recovery-out)
+    #[arg(long, requires = "password", conflicts_with = "delta_from")]
+    recovery_key: bool,
+    /// Save the generated recovery key to this file instead of printing it (implies --recovery-key)
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "password",
+        conflicts_with = "delta_from"
+    )]
+    recovery_out: Option<String>,
+    /// Also require this keyfile to decrypt: the file key is derived from both the password
+    /// and the keyfile's contents, so either one alone is useless
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "password",
+        conflicts_with_all = ["delta_from", "recipients", "tpm", "kms", "pkcs11_module"]
+    )]
+    keyfile: Option<String>,
+    /// Strength of the password derivation: interactive, moderate or paranoid (defaults to
+    /// `kdf_profile` from the config file)
+    #[arg(long, value_name = "PROFILE", requires = "password")]
+    kdf_profile: Option<String>,
+    /// Refuse a weak password instead of warning about it
+    #[arg(long, requires = "password")]
+    enforce_strong_password: bool,
+    /// Password KDF for a plain password file: argon2id or scrypt (defaults to `kdf` from
+    /// the config file); the strength follows the configured KDF profile
+    #[arg(
+        long,
+        value_name = "KDF",
+        requires = "password",
+        conflicts_with_all = [
+            "delta_from", "recovery_key", "recovery_out", "keyfile", "recipients", "tpm", "kms",
+            "pkcs11_module"
+        ]
+    )]
+    kdf: Option<String>,
+    /// Encrypt to this public key from `identity generate` (repeatable; a recipient string or a
+    /// file listing recipients). Combine with --password to also allow password decryption
+    #[arg(
+        long = "recipient",
+        value_name = "RECIPIENT",
+        conflicts_with_all = ["key", "delta_from", "recovery_key", "recovery_out"]
+    )]
+    recipients: Vec<String>,
+    /// Require this many of the --quorum-key keys together to decrypt, e.g. 2 for 2 of 3
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "quorum_keys",
+        conflicts_with_all = [
+            "password", "key", "key_name", "master_key", "recipients", "recovery_key",
+            "recovery_out", "keyfile", "delta_from", "tpm", "kms", "pkcs11_module"
+        ]
+    )]
+    quorum: Option<u8>,
+    /// Key of one quorum member (base64, repeatable; see --quorum)
+    #[arg(long = "quorum-key", value_name = "KEY", requires = "quorum")]
+    quorum_keys: Vec<String>,
+    /// Encrypt under two random key parts that are both needed to decrypt, written to
+    /// <output>.1.cred and <output>.2.cred; send them over different channels
+    #[arg(
+        long,
+        conflicts_with_all = ["password", "key", "recipients", "delta_from", "recovery_key", "recovery_out", "quorum"]
+    )]
+    split_credentials: bool,
+    /// Sign the encrypted file with this signing key (from `identity generate --signing`), so
+    /// recipients can check who produced it
+    #[arg(long, value_name = "PATH")]
+    sign_key: Option<String>,
+    #[command(flatten)]
+    pkcs11: Pkcs11Args,
+    /// Seal the file key to this machine's TPM 2.0, so `decrypt` opens the file here without
+    /// a credential (combine with --password or --recipient to open it elsewhere)
+    #[arg(long)]
+    tpm: bool,
+    /// With --tpm, only unseal while these PCRs keep their current values, e.g. 7 or sha256:0,7
+    #[arg(long, value_name = "PCRS", requires = "tpm")]
+    tpm_pcrs: Option<String>,
+    /// Wrap the file key with this cloud KMS key (gcpkms://projects/.../cryptoKeys/K or
+    /// azurekv://VAULT.vault.azure.net/keys/NAME), so `decrypt` opens the file for anyone the
+    /// key's access policy allows
+    #[arg(long, value_name = "URI")]
+    kms: Option<String>,
+    /// Replace the input file with its encrypted version under the same name: written to a
+    /// temporary file next to it, synced, then renamed over the original
+    #[arg(
+        long,
+        requires = "input",
+        conflicts_with_all = ["output", "tar_from", "wrap", "bundle_readme"]
+    )]
+    in_place: bool,
+    /// With --in-place, also overwrite the original's blocks with zeros once it has been
+    /// replaced (best effort: SSDs and copy-on-write filesystems may keep the old data)
+    #[arg(long, requires = "in_place")]
+    wipe: bool,
+    /// Read the written file back from disk and authenticate all of it before reporting
+    /// success (costs one more key derivation for password files)
+    #[arg(long)]
+    verify: bool,
+    /// Once the encrypted file is written and verified (implies --verify), overwrite the
+    /// input file with zeros and delete it (best effort: SSDs and copy-on-write filesystems
+    /// may keep the old data)
+    #[arg(long, requires = "input", conflicts_with_all = ["in_place", "tar_from"])]
+    shred_original: bool,
+    /// Split the encrypted file into volumes of this size (e.g. 1G, 4000MB), written as
+    /// <output>.001, <output>.002, ...; `decrypt` and `join` put them back together
+    #[arg(
+        long,
+        value_name = "SIZE",
+        conflicts_with_all = ["in_place", "verify", "shred_original", "dir", "bundle_readme"]
+    )]
+    volume_size: Option<String>,
+    /// Also show the encrypted file as a QR code of its base64 text, drawn in the terminal
+    /// or written to PNG if given (files up to 2214 bytes; read it back with `base64 -d`)
+    #[arg(
+        long,
+        value_name = "PNG",
+        num_args = 0..=1,
+        default_missing_value = STDIO_PATH,
+        conflicts_with_all = ["volume_size", "dir"]
+    )]
+    qr: Option<String>,
+    /// Force overwrite if output file exists
+    #[arg(long)]
+    force: bool,
+    /// Data to encrypt instead of reading --file, for inputs built in memory (the manifest
+    /// `encrypt --dir` writes); --file then only names it
+    #[arg(skip)]
+    contents: Option<zeroize::Zeroizing<Vec<u8>>>,
+}
+
+/// PKCS#11 device options shared by `encrypt` and `decrypt`.
+#[derive(Args, Clone, Default)]
+pub struct Pkcs11Args {
+    /// PKCS#11 module of the HSM or smartcard holding the key, e.g.
+    /// /usr/lib/softhsm/libsofthsm2.so (encrypt: adds a keyslot wrapped on the device)
+    #[arg(long, value_name = "PATH")]
+    pkcs11_module: Option<String>,
+    /// Slot ID of the device (defaults to the first slot with a token)
+    #[arg(long, requires = "pkcs11_module")]
+    slot: Option<u64>,
+    /// Hex object ID of the RSA key pair on the device that wraps the file key (encrypt only;
+    /// decryption reads it from the file)
+    #[arg(long, value_name = "HEX", requires = "pkcs11_module")]
+    pkcs11_key_id: Option<String>,
+    /// Where to read the device PIN: 'env:NAME' or 'file:PATH'
+    #[arg(long, value_name = "SOURCE", requires = "pkcs11_module")]
+    pin_source: Option<String>,
+}
+
+/// Container format written by `encrypt`.
+#[derive(Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Native EncryptX format (default)
+    #[default]
+    Xd,
+    /// AES-256 encrypted ZIP readable by 7-Zip/WinZip (password only)
+    ZipAes,
+    /// OpenPGP message readable by gpg (password and/or --pgp-recipient)
+    Pgp,
+}
+
+impl OutputFormat {
+    /// File extension used for default output names.
+    fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Xd => "xd",
+            OutputFormat::ZipAes => "zip",
+            OutputFormat::Pgp => "gpg",
+        }
+    }
+}
+
+/// Arguments for the `decrypt` subcommand.
+#[derive(Args, Default)]
+pub struct DecryptArgs {
+    /// Path to the file to decrypt (omit or use '-' to read from stdin, s3://BUCKET/KEY
+    /// reads from S3)
+    #[arg(short, long)]
+    file: Option<String>,
+    /// Password to use for decryption (optional)
+    #[arg(short, long)]
+    password: Option<String>,
+    /// Keyfile required together with --password by files encrypted with `--keyfile`
+    #[arg(long, value_name = "PATH", requires = "password")]
+    keyfile: Option<String>,
+    /// Key of one quorum member, for files encrypted with `--quorum` (base64, repeatable)
+    #[arg(long = "quorum-key", value_name = "KEY", conflicts_with_all = ["password", "key", "key_name"])]
+    quorum_keys: Vec<String>,
+    /// Key to use for decryption (base64, optional)
+    #[arg(short, long)]
+    key: Option<String>,
+    /// Use the key saved under this name (see `key add`)
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["password", "key"])]
+    key_name: Option<String>,
+    /// Key share from `key split` instead of --key (repeat until the threshold is met; a share
+    /// string or a file containing one)
+    #[arg(long = "share", value_name = "SHARE", conflicts_with_all = ["password", "key", "key_name"])]
+    shares: Vec<String>,
+    /// Key part file from `encrypt --split-credentials` (give both parts)
+    #[arg(
+        long = "credential",
+        value_name = "PATH",
+        conflicts_with_all = ["password", "key", "key_name", "shares"]
+    )]
+    credentials: Vec<String>,
+    /// Identity file for files encrypted to recipients (repeatable; a file may hold several
+    /// identities, one per line). Without a credential, the identities file from the config
+    /// (default ~/.config/encryptx/identities.txt) is tried
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["password", "key", "key_name", "shares", "credentials"])]
+    identity: Vec<String>,
+    /// OpenPGP secret key file for messages encrypted to a PGP public key (--password unlocks
+    /// the key if it is protected)
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["key", "key_name", "shares", "credentials", "identity"])]
+    pgp_key: Option<String>,
+    /// Unlock with a FIDO2 token enrolled in one of the file's keyslots (touch it when asked)
+    #[arg(
+        long,
+        conflicts_with_all = ["password", "key", "shares", "credentials", "identity", "pgp_key"]
+    )]
+    token: bool,
+    /// Device path of the token (needed if several tokens are connected)
+    #[arg(long, requires = "token")]
+    token_device: Option<String>,
+    #[command(flatten)]
+    pkcs11: Pkcs11Args,
+    /// Output file path (optional; defaults to original filename from encrypted file, '-' writes to stdout,
+    /// s3://BUCKET/KEY to S3).
+    /// ZIP/7z archive inputs are extracted into this directory instead
+    #[arg(short, long)]
+    output: Option<String>,
+    /// Treat the decrypted data as a tar archive and extract it into this directory
+    #[arg(long, conflicts_with = "output")]
+    untar_to: Option<String>,
+    /// Only decrypt if the file is signed by this verifying key (a key string or a file
+    /// containing one); signed files are always checked, this also requires the signer
+    #[arg(long, value_name = "KEY")]
+    signer: Option<String>,
+    /// Force overwrite if output file exists
+    #[arg(long)]
+    force: bool,
+    /// Set by `cat`: binary output to a terminal is refused unless --force
+    #[arg(skip)]
+    refuse_binary_tty: bool,
+}
+
+/// Arguments for the `cat` subcommand.
+#[derive(Args)]
+pub struct CatArgs {
+    /// Path to the file to decrypt ('-' reads from stdin)
+    file: String,
+    /// Password to use for decryption
+    #[arg(short, long)]
+    password: Option<String>,
+    /// Keyfile required together with --password by files encrypted with `--keyfile`
+    #[arg(long, value_name = "PATH", requires = "password")]
+    keyfile: Option<String>,
+    /// Key to use for decryption (base64)
+    #[arg(short, long)]
+    key: Option<String>,
+    /// Use the key saved under this name (see `key add`)
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["password", "key"])]
+    key_name: Option<String>,
+    /// Identity file for files encrypted to recipients (repeatable)
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["password", "key", "key_name"])]
+    identity: Vec<String>,
+    /// Unlock with a FIDO2 token enrolled in one of the file's keyslots
+    #[arg(long, conflicts_with_all = ["password", "key", "identity"])]
+    token: bool,
+    /// Print binary data even when stdout is a terminal
+    #[arg(long)]
+    force: bool,
+}
+
+/// Arguments for the `watch` subcommand.
+#[derive(Args)]
+pub struct WatchArgs {
+    /// Directory to watch, subdirectories included
+    dir: String,
+    /// Directory the encrypted files go into, in the same tree as the watched directory
+    #[arg(short, long)]
+    output: String,
+    /// Password to encrypt with
+    #[arg(short, long)]
+    password: Option<String>,
+    /// Key to encrypt with (base64)
+    #[arg(short, long, conflicts_with = "password")]
+    key: Option<String>,
+    /// Use the key saved under this name (see `key add`)
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["password", "key"])]
+    key_name: Option<String>,
+    /// Leave out files matching this gitignore-style pattern (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    exclude: Vec<String>,
+    /// Milliseconds a file must go without changes before it is encrypted
+    #[arg(long, value_name = "MS", default_value_t = 1000)]
+    debounce: u64,
+    /// Seconds before a file that failed is tried again, doubling with each further failure
+    #[arg(long, value_name = "SECS", default_value_t = 30)]
+    retry_after: u64,
+    /// Failed retries after which a file is left alone until it changes again
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    max_retries: u32,
+}
+
+/// Arguments for the `rekey` subcommand.
+#[derive(Args)]
+pub struct RekeyArgs {
+    /// Path to the encrypted file ('-' reads from stdin)
+    file: String,
+    /// Current password of the file
+    #[arg(long)]
+    old_password: Option<String>,
+    /// Current key of the file (base64; defaults to the embedded key)
+    #[arg(long)]
+    old_key: Option<String>,
+    /// New password to encrypt with
+    #[arg(long)]
+    new_password: Option<String>,
+    /// New key to encrypt with (base64)
+    #[arg(long)]
+    new_key: Option<String>,
+    /// Argon2 strength for the new password: interactive, moderate or paranoid (defaults to
+    /// `kdf_profile` from the config file)
+    #[arg(long, value_name = "PROFILE", requires = "new_password")]
+    kdf_profile: Option<String>,
+    /// Refuse a weak password instead of warning about it
+    #[arg(long, requires = "new_password")]
+    enforce_strong_password: bool,
+    /// Output file path (optional; defaults to replacing the input, '-' writes to stdout)
+    #[arg(short, long)]
+    output: Option<String>,
+    /// Force overwrite if a different output file exists
+    #[arg(long)]
+    force: bool,
+}
+
+/// Arguments for the `upgrade` subcommand.
+#[derive(Args)]
+pub struct UpgradeArgs {
+    /// Encrypted files to upgrade in place
+    #[arg(required = true)]
+    files: Vec<String>,
+    /// Password of the password-encrypted files
+    #[arg(short, long)]
+    password: Option<String>,
+    /// Key of the key-encrypted files (base64; defaults to each file's embedded key)
+    #[arg(short, long)]
+    key: Option<String>,
+    /// Argon2 strength for upgraded password files: interactive, moderate or paranoid
+    /// (defaults to `kdf_profile` from the config file)
+    #[arg(long, value_name = "PROFILE")]
+    kdf_profile: Option<String>,
+    /// Only report which files would be upgraded; no credentials are needed
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// Arguments for the `backup` subcommand.
+#[derive(Args)]
+pub struct BackupArgs {
+    /// Directory to back up
+    dir: String,
+    /// Repository to store the snapshot in; created with the given password or key if it
+    /// doesn't exist yet
+    #[arg(long, value_name = "PATH")]
+    repo: String,
+    /// Name for the snapshot, shown next to its time
+    #[arg(long)]
+    name: Option<String>,
+    /// Password of the repository
+    #[arg(short, long)]
+    password: Option<String>,
+    /// Key of the repository (base64)
+    #[arg(short, long)]
+    key: Option<String>,
+    /// Use the key saved under this name (see `key add`)
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["password", "key"])]
+    key_name: Option<String>,
+    /// Back up what symlinks point at in their place (a link leading back into the
+    /// directory is an error); by default the links themselves are recorded
+    #[arg(long, conflicts_with = "skip_symlinks")]
+    follow_symlinks: bool,
+    /// Leave symlinks out
+    #[arg(long)]
+    skip_symlinks: bool,
+    /// Leave out what matches this .gitignore-style pattern (repeatable; see `encrypt
+    /// --exclude`)
+    #[arg(long, value_name = "PATTERN")]
+    exclude: Vec<String>,
+    /// Read exclude patterns from this file, one per line like .gitignore (repeatable)
+    #[arg(long, value_name = "PATH")]
+    exclude_from: Vec<String>,
+    /// Only back up files matching this pattern or inside a directory matching it
+    /// (repeatable); excludes still apply
+    #[arg(long, value_name = "PATTERN")]
+    include: Vec<String>,
+}
+
+/// Arguments for the `restore` subcommand.
+#[derive(Args)]
+pub struct RestoreArgs {
+    /// Repository to restore from
+    #[arg(long, value_name = "PATH")]
+    repo: String,
+    /// Snapshot to restore: `latest`, the start of its id (a date picks that day's last
+    /// snapshot) or its name
+    #[arg(long, value_name = "SNAPSHOT", required_unless_present = "list")]
+    snapshot: Option<String>,
+    /// Directory to restore into; created if missing
+    #[arg(long, value_name = "DIR", required_unless_present = "list")]
+    target: Option<String>,
+    /// Only restore this file or directory of the snapshot (repeatable)
+    #[arg(long, value_name = "PREFIX")]
+    path: Vec<String>,
+    /// List the snapshots in the repository instead of restoring one
+    #[arg(long, conflicts_with_all = ["snapshot", "target", "path", "force"])]
+    list: bool,
+    /// Password of the repository
+    #[arg(short, long)]
+    password: Option<String>,
+    /// Key of the repository (base64)
+    #[arg(short, long)]
+    key: Option<String>,
+    /// Use the key saved under this name (see `key add`)
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["password", "key"])]
+    key_name: Option<String>,
+    /// Overwrite files and links that already exist in the target
+    #[arg(long)]
+    force: bool,
+}
+
+/// Arguments for the `salvage` subcommand.
+#[derive(Args)]
+pub struct SalvageArgs {
+    /// Path to the damaged encrypted file ('-' reads from stdin)
+    file: String,
+    /// Password the file was encrypted with
+    #[arg(short, long)]
+    password: Option<String>,
+    /// Key the file was encrypted with (base64; defaults to the embedded key)
+    #[arg(short, long)]
+    key: Option<String>,
+    /// Identity file for files encrypted to recipients
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["password", "key"])]
+    identity: Option<String>,
+    /// Output file path (optional; defaults to <original name>.salvaged, '-' writes to stdout)
+    #[arg(short, long)]
+    output: Option<String>,
+    /// Force overwrite if output file exists
+    #[arg(long)]
+    force: bool,
+}
+
+/// Arguments for the `fix-header` subcommand.
+#[derive(Args)]
+pub struct FixHeaderArgs {
+    /// Path to the encrypted file ('-' reads from stdin)
+    file: String,
+    /// Password the file was encrypted with
+    #[arg(short, long)]
+    password: Option<String>,
+    /// Key the file was encrypted with (base64; needed if the embedded key is damaged)
+    #[arg(short, long)]
+    key: Option<String>,
+    /// Original filename, if unreadable (or to re-stamp a single-message file)
+    #[arg(long)]
+    filename: Option<String>,
+    /// Encryption time as a Unix timestamp (must match the original for chunked files)
+    #[arg(long)]
+    timestamp: Option<u64>,
+    /// Plaintext chunk size in bytes recorded in the header (chunked files)
+    #[arg(long)]
+    chunk_size: Option<u32>,
+    /// Delta chain identifier (delta-layout files)
+    #[arg(long)]
+    delta_id: Option<String>,
+    /// Argon2 salt in base64 (password files)
+    #[arg(long)]
+    salt: Option<String>,
+    /// Argon2 memory cost in KB (password files)
+    #[arg(long)]
+    memory_cost: Option<u32>,
+    /// Argon2 time cost (password files)
+    #[arg(long)]
+    time_cost: Option<u32>,
+    /// Argon2 parallelism (password files)
+    #[arg(long)]
+    parallelism: Option<u32>,
+    /// Output file path (optional; defaults to replacing the input, '-' writes to stdout)
+    #[arg(short, long)]
+    output: Option<String>,
+    /// Force overwrite if a different output file exists
+    #[arg(long)]
+    force: bool,
+}
+
+/// Actions for the `key` subcommand.
+#[derive(Subcommand)]
+pub enum KeyAction {
+    /// Split a key into shares, any THRESHOLD of which recover it
+    Split {
+        /// Key to split (base64)
         #[arg(short, long)]
+        key: String,
+        /// Number of shares to create (2-255)
+        #[arg(long, value_parser = clap::value_parser!(u8).range(2..))]
+        shares: u8,
+        /// Number of shares needed to recover the key
+        #[arg(long, value_parser = clap::value_parser!(u8).range(2..))]
+        threshold: u8,
+    },
+    /// Recover a key from shares (share strings or files containing them)
+    Combine {
+        #[arg(required = true, num_args = 1..)]
+        shares: Vec<String>,
+    },
+    /// Save a key under NAME for --key-name, in the keystore file if the config sets
+    /// `keystore`, else in the OS secret store (Keychain, Credential Manager or Secret Service)
+    Add {
+        /// Name to save the key under (letters, digits, '.', '_', '-')
+        name: String,
+        /// Key to save (base64; a random key is generated if omitted)
+        #[arg(short, long)]
+        key: Option<String>,
+        /// Replace an existing key with the same name
+        #[arg(long)]
+        force: bool,
+    },
+    /// List the names of the saved keys
+    List,
+    /// Print the key saved under NAME, e.g. to back it up
+    Export { name: String },
+    /// Delete the key saved under NAME
+    Rm { name: String },
+    /// Print a key as a paper backup: numbered base32 lines with checksums and Reed-Solomon
+    /// parity, so `key recover-paper` can read it back despite typos and lost lines
+    Paper {
+        /// Key to print (base64)
+        #[arg(short, long, required_unless_present = "key_name")]
+        key: Option<String>,
+        /// Name of a saved key to print instead
+        #[arg(long, conflicts_with = "key")]
+        key_name: Option<String>,
+        /// Write the sheet to this file instead of stdout
+        #[arg(short, long, default_value = STDIO_PATH)]
+        output: String,
+        /// Overwrite an existing file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Recover a key from a typed-in paper backup, correcting typos and missing lines
+    RecoverPaper {
+        /// File holding the paper key's lines ('-' reads stdin)
+        #[arg(default_value = STDIO_PATH)]
+        input: String,
+    },
+    /// Show a key as a QR code, e.g. to carry it to a phone
+    Qr {
+        /// Key to show (base64)
+        #[arg(short, long, required_unless_present = "key_name")]
         key: Option<String>,
-        /// Output file path (optional; defaults to original filename from encrypted file)
+        /// Name of a saved key to show instead
+        #[arg(long, conflicts_with = "key")]
+        key_name: Option<String>,
+        /// Write the QR code to this PNG file instead of drawing it in the terminal
+        #[arg(short, long, value_name = "PNG", default_value = STDIO_PATH)]
+        output: String,
+        /// Overwrite an existing PNG file
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// Actions for the `identity` subcommand.
+#[derive(Subcommand)]
+pub enum IdentityAction {
+    /// Create a new identity file and print its recipient
+    Generate {
+        /// Path of the identity file to create
         #[arg(short, long)]
-        output: Option<String>,
-        /// Force overwrite if output file exists
+        output: String,
+        /// Add an ML-KEM-768 key, so files encrypted to this identity's recipient stay
+        /// confidential against future quantum computers (hybrid with X25519)
+        #[arg(long)]
+        post_quantum: bool,
+        /// Create an Ed25519 signing key for `encrypt --sign-key` instead, and print its
+        /// verifying key
+        #[arg(long, conflicts_with = "post_quantum")]
+        signing: bool,
+        /// Overwrite an existing file
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// Credentials for the `clip` and `text` subcommands.
+#[derive(Args)]
+pub struct MessageCredentials {
+    /// Password to encrypt or decrypt with
+    #[arg(short, long)]
+    password: Option<String>,
+    /// Key to encrypt or decrypt with (base64)
+    #[arg(short, long, conflicts_with = "password")]
+    key: Option<String>,
+    /// Use the key saved under this name (see `key add`)
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["password", "key"])]
+    key_name: Option<String>,
+}
+
+/// Actions for the `clip` subcommand.
+#[derive(Subcommand)]
+pub enum ClipAction {
+    /// Replace the clipboard's text with an armored encrypted message
+    Encrypt(MessageCredentials),
+    /// Replace an armored message on the clipboard with its text, and clear the clipboard
+    /// again after a while
+    Decrypt {
+        #[command(flatten)]
+        credentials: MessageCredentials,
+        /// Seconds until the decrypted text is cleared from the clipboard (0 keeps it); text
+        /// copied in the meantime is left alone
+        #[arg(long, value_name = "SECS", default_value_t = 30)]
+        clear_after: u64,
+    },
+    /// Clear the clipboard after a delay if it still holds the text hashed on stdin
+    #[command(hide = true)]
+    Clear {
+        #[arg(long)]
+        after: u64,
+    },
+}
+
+/// Actions for the `text` subcommand.
+#[derive(Subcommand)]
+pub enum TextAction {
+    /// Encrypt text into an armored message, with no file name
+    Encrypt {
+        /// Text to encrypt; read from stdin when left out. Arguments show up in the process
+        /// list and shell history, so prefer stdin for real secrets
+        text: Option<String>,
+        #[command(flatten)]
+        credentials: MessageCredentials,
+        /// Where to write the message ('-' for stdout)
+        #[arg(short, long, default_value = STDIO_PATH)]
+        output: String,
+        /// Overwrite an existing file
+        #[arg(long)]
+        force: bool,
+        /// Write the bare encrypted file instead of armored text
+        #[arg(long)]
+        binary: bool,
+    },
+    /// Decrypt an armored message, or a bare encrypted file, back into its text
+    Decrypt {
+        /// The armored message; read from stdin when left out
+        message: Option<String>,
+        #[command(flatten)]
+        credentials: MessageCredentials,
+        /// Where to write the text ('-' for stdout)
+        #[arg(short, long, default_value = STDIO_PATH)]
+        output: String,
+        /// Overwrite an existing file
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// Actions for the `token` subcommand.
+#[derive(Subcommand)]
+pub enum TokenAction {
+    /// List the connected FIDO2 tokens
+    List,
+    /// Create an hmac-secret credential on a token (touch it when asked) and save it to a
+    /// token file for `keyslot add --new-token`
+    Enroll {
+        /// Path of the token file to create
+        #[arg(short, long)]
+        output: String,
+        /// Device path from `token list` (needed if several tokens are connected)
+        #[arg(long)]
+        token_device: Option<String>,
+        /// Overwrite an existing file
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// Actions for the `keyslot` subcommand.
+#[derive(Subcommand)]
+pub enum KeyslotAction {
+    /// Add a password, key, recipient or FIDO2 token that unlocks the file (converts plain
+    /// files to keyslots)
+    Add(KeyslotAddArgs),
+    /// Remove a keyslot by index (see `inspect`)
+    Remove(KeyslotRemoveArgs),
+}
+
+/// Arguments for `keyslot add`.
+#[derive(Args)]
+pub struct KeyslotAddArgs {
+    /// Path to the encrypted file ('-' reads from stdin)
+    file: String,
+    /// An existing password of the file
+    #[arg(short, long)]
+    password: Option<String>,
+    /// An existing key of the file (base64)
+    #[arg(short, long)]
+    key: Option<String>,
+    /// Password for the new slot
+    #[arg(long)]
+    new_password: Option<String>,
+    /// Argon2 strength for the new password slot: interactive, moderate or paranoid
+    /// (defaults to `kdf_profile` from the config file)
+    #[arg(long, value_name = "PROFILE", requires = "new_password")]
+    kdf_profile: Option<String>,
+    /// Refuse a weak password instead of warning about it
+    #[arg(long, requires = "new_password")]
+    enforce_strong_password: bool,
+    /// Key for the new slot (base64)
+    #[arg(long)]
+    new_key: Option<String>,
+    /// Recipient for the new slot (a recipient string or a file containing one)
+    #[arg(long)]
+    new_recipient: Option<String>,
+    /// Token file from `token enroll` for the new slot (touch the token when asked)
+    #[arg(long, value_name = "PATH")]
+    new_token: Option<String>,
+    /// Device path of the token (needed if several tokens are connected)
+    #[arg(long, requires = "new_token")]
+    token_device: Option<String>,
+    /// Cloud KMS key URI for the new slot (see `encrypt --kms`)
+    #[arg(long, value_name = "URI")]
+    new_kms: Option<String>,
+    /// Label stored with the new slot, e.g. "recovery"
+    #[arg(long)]
+    label: Option<String>,
+    /// Output file path (optional; defaults to replacing the input, '-' writes to stdout)
+    #[arg(short, long)]
+    output: Option<String>,
+    /// Force overwrite if a different output file exists
+    #[arg(long)]
+    force: bool,
+}
+
+/// Arguments for `keyslot remove`.
+#[derive(Args)]
+pub struct KeyslotRemoveArgs {
+    /// Path to the encrypted file ('-' reads from stdin)
+    file: String,
+    /// Index of the slot to remove
+    #[arg(long)]
+    slot: usize,
+    /// An existing password of the file
+    #[arg(short, long)]
+    password: Option<String>,
+    /// An existing key of the file (base64)
+    #[arg(short, long)]
+    key: Option<String>,
+    /// Output file path (optional; defaults to replacing the input, '-' writes to stdout)
+    #[arg(short, long)]
+    output: Option<String>,
+    /// Force overwrite if a different output file exists
+    #[arg(long)]
+    force: bool,
+}
+
+/// Actions for the `hook` subcommand.
+#[derive(Subcommand)]
+pub enum HookAction {
+    /// Install the pre-commit hook in the current repository
+    Install {
+        /// Glob of files that must only be committed as .xd (repeatable; saved to .encryptx-protect)
+        #[arg(short, long = "pattern")]
+        patterns: Vec<String>,
+        /// Replace an existing pre-commit hook not installed by EncryptX
         #[arg(long)]
         force: bool,
     },
+    /// Check staged files against the protected patterns (run by the hook)
+    #[command(hide = true)]
+    Check,
+}
+
+/// Actions for the `agent` subcommand.
+#[derive(Subcommand)]
+pub enum AgentAction {
+    /// Serve the key cache in the foreground until stopped
+    Start {
+        /// How long each key is kept (e.g. 15m, 1h; defaults to `agent_ttl` from the config)
+        #[arg(long, value_name = "DURATION")]
+        ttl: Option<String>,
+    },
+    /// Show whether an agent is running and how many keys it holds
+    Status,
+    /// Forget every cached key
+    Clear,
+    /// Forget every cached key and stop the agent
+    Stop,
 }
 
 /// Custom error type for CLI operations
@@ -77,14 +1432,94 @@ pub enum CliError {
     Io(io::Error),
     Crypto(String),
     InvalidInput(String),
+    /// Wrong key/password, or the ciphertext was tampered with
+    Authentication(String),
+    /// Input is not a valid encrypted file (or needs the other decryption method)
+    Format(String),
+}
+
+impl CliError {
+    /// Maps a crypto error to the matching CLI error, prefixing the message with `context`.
+    fn from_crypto(context: &str, error: crypto::CryptoError) -> Self {
+        match error {
+            crypto::CryptoError::AuthenticationError | crypto::CryptoError::SignatureError(_) => {
+                CliError::Authentication(format!("{context}: {error}"))
+            }
+            crypto::CryptoError::FormatError | crypto::CryptoError::WrongDecryptionMethod(_) => {
+                CliError::Format(format!("{context}: {error}"))
+            }
+            _ => CliError::Crypto(format!("{context}: {error}")),
+        }
+    }
+
+    /// Maps an API error to the matching CLI error; crypto errors are prefixed with `context`.
+    fn from_api(context: &str, error: ApiError) -> Self {
+        match error {
+            ApiError::Crypto(e) => Self::from_crypto(context, e),
+            ApiError::InvalidInput(msg) => CliError::InvalidInput(msg),
+            ApiError::Compression(_) => CliError::Crypto(error.to_string()),
+            ApiError::Decompression(_) | ApiError::InvalidPadding => {
+                CliError::Format(error.to_string())
+            }
+            ApiError::DecompressedTooLarge { .. } => {
+                CliError::Format(format!("{error}; raise max_decompressed_size to allow it"))
+            }
+            ApiError::Io(e) => CliError::Io(e),
+        }
+    }
+
+    /// Maps a backup repository error to the matching CLI error; crypto errors are prefixed
+    /// with `context`.
+    fn from_backup(context: &str, error: BackupError) -> Self {
+        match error {
+            BackupError::Io(e) => CliError::Io(e),
+            BackupError::Crypto(e) => Self::from_crypto(context, e),
+            BackupError::Api(e) => Self::from_api(context, e),
+            BackupError::Repository(msg) => CliError::Format(msg),
+        }
+    }
+
+    /// Stable process exit code, so wrappers can react without parsing messages.
+    ///
+    /// | Code | Meaning |
+    /// |------|---------|
+    /// | 1 | Cryptographic or other internal failure |
+    /// | 2 | Invalid input or usage |
+    /// | 3 | I/O error |
+    /// | 4 | Authentication failed (wrong key/password or tampered file) |
+    /// | 5 | Invalid or unsupported file format |
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Crypto(_) => 1,
+            CliError::InvalidInput(_) => 2,
+            CliError::Io(_) => 3,
+            CliError::Authentication(_) => 4,
+            CliError::Format(_) => 5,
+        }
+    }
+
+    /// Short machine-readable error kind used in `--json` output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CliError::Crypto(_) => "crypto",
+            CliError::InvalidInput(_) => "invalid_input",
+            CliError::Io(_) => "io",
+            CliError::Authentication(_) => "authentication",
+            CliError::Format(_) => "format",
+        }
+    }
 }
 
 impl std::fmt::Display for CliError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            CliError::Io(e) => write!(f, "File operation failed: {e}"),
-            CliError::Crypto(e) => write!(f, "Cryptographic operation failed: {e}"),
-            CliError::InvalidInput(e) => write!(f, "Invalid input: {e}"),
+            CliError::Io(e) => write!(f, "{}", tr!("error-io", detail = e)),
+            CliError::Crypto(e) => write!(f, "{}", tr!("error-crypto", detail = e)),
+            CliError::InvalidInput(e) => write!(f, "{}", tr!("error-invalid-input", detail = e)),
+            CliError::Authentication(e) => {
+                write!(f, "{}", tr!("error-authentication", detail = e))
+            }
+            CliError::Format(e) => write!(f, "{}", tr!("error-format", detail = e)),
         }
     }
 }
@@ -102,7 +1537,10 @@ impl From<CliError> for io::Error {
         match error {
             CliError::Io(e) => e,
             CliError::Crypto(e) => io::Error::other(e),
-            CliError::InvalidInput(e) => io::Error::new(io::ErrorKind::InvalidInput, e),
+            CliError::InvalidInput(e) | CliError::Format(e) => {
+                io::Error::new(io::ErrorKind::InvalidInput, e)
+            }
+            CliError::Authentication(e) => io::Error::new(io::ErrorKind::PermissionDenied, e),
         }
     }
 }
@@ -129,6 +1567,14 @@ fn validate_input_file(file_path: &str) -> Result<(), CliError> {
 
 /// Checks if output file exists and handles overwrite logic
 fn check_output_file(output_path: &str, force: bool) -> Result<(), CliError> {
+    if s3::is_url(output_path) {
+        if !force && s3::exists(output_path)? {
+            return Err(CliError::InvalidInput(format!(
+                "Object '{output_path}' already exists. Use --force to overwrite"
+            )));
+        }
+        return Ok(());
+    }
     let path = Path::new(output_path);
     if path.exists() {
         if !force {
@@ -146,22 +1592,24 @@ fn check_output_file(output_path: &str, force: bool) -> Result<(), CliError> {
         }
     } else {
         // Only check parent if it exists (i.e., not current directory)
-        if let Some(parent) = path.parent() {
-            if !parent.as_os_str().is_empty() && !parent.exists() {
-                return Err(CliError::InvalidInput(format!(
-                    "Parent directory '{}' does not exist",
-                    parent.display()
-                )));
-            }
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+            && !parent.exists()
+        {
+            return Err(CliError::InvalidInput(format!(
+                "Parent directory '{}' does not exist",
+                parent.display()
+            )));
         }
         Ok(())
     }
 }
 
 /// Validates and decodes a base64 key
-fn validate_key(key_b64: &str) -> Result<Vec<u8>, CliError> {
+fn validate_key(key_b64: &str) -> Result<SecretBuffer, CliError> {
     let key = general_purpose::STANDARD
         .decode(key_b64)
+        .map(zeroize::Zeroizing::new)
         .map_err(|e| CliError::InvalidInput(format!("Invalid base64 key: {e}")))?;
 
     if key.len() != 32 {
@@ -171,213 +1619,2486 @@ fn validate_key(key_b64: &str) -> Result<Vec<u8>, CliError> {
         )));
     }
 
-    Ok(key)
+    Ok(SecretBuffer::from_slice(&key))
 }
 
 /// Generates a default output filename for encryption
-fn generate_encrypt_output(input_file: &str) -> String {
+fn generate_encrypt_output(input_file: &str, extension: &str) -> String {
     let path = Path::new(input_file);
     let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("file");
-    format!("{stem}.xd")
+    format!("{stem}.{extension}")
 }
 
-/// Runs the CLI. Returns Ok(true) if a CLI command was run, Ok(false) if not.
-///
-/// - Encrypt: Writes <basename>.xd as output. Prints random key if generated (copy it somewhere safe!).
-/// - Decrypt: Writes the original filename from the encrypted file header.
-/// - Returns Ok(false) if no CLI command is given (so the server can start).
+/// Compresses data for encryption: a `0x01` flag byte followed by zstd data, compressed
+/// with `dictionary` unless it is empty.
 ///
-/// # Security Note
-/// If you forget your password or key, not even we can help you. That's real security!
-pub async fn run_cli() -> Result<bool, CliError> {
-    let cli = Cli::parse();
+/// With `frame_size`, the input is compressed as independent frames of that many bytes, so
+/// an edit only changes the frames it touches. Decompression handles both forms alike.
+fn compress_body(
+    data: &[u8],
+    level: i32,
+    tuning: compression::Tuning,
+    dictionary: &[u8],
+    frame_size: Option<usize>,
+) -> Result<Vec<u8>, CliError> {
+    let frames: Vec<Range<usize>> = match frame_size {
+        Some(size) if !data.is_empty() => (0..data.len())
+            .step_by(size)
+            .map(|start| start..(start + size).min(data.len()))
+            .collect(),
+        _ => {
+            let whole = 0..data.len();
+            vec![whole]
+        }
+    };
+    Ok(compress_frames(data, level, tuning, dictionary, &frames)?.0)
+}
 
-    match cli.command {
-        Some(Commands::Encrypt {
-            file,
-            password,
-            key,
-            output,
-            force,
-        }) => {
-            // Validate input file
-            validate_input_file(&file)?;
+/// Compresses each of `frames` (ranges covering `data` in order) as an independent zstd
+/// frame after the `0x01` flag byte and the level frame (see [`compression`]), and the
+/// dictionary frame unless `dictionary` is empty. Also returns where each frame ends in the
+/// body. `tuning` applies to each frame by its own size.
+fn compress_frames(
+    data: &[u8],
+    level: i32,
+    tuning: compression::Tuning,
+    dictionary: &[u8],
+    frames: &[Range<usize>],
+) -> Result<(Vec<u8>, Vec<usize>), CliError> {
+    let compression_error = |e| CliError::Crypto(format!("Compression error: {e}"));
+    let mut body = vec![0x01];
+    body.extend_from_slice(&compression::level_frame(level));
+    if !dictionary.is_empty() {
+        body.extend_from_slice(&compression::dictionary_frame(dictionary));
+    }
+    let mut ends = Vec::with_capacity(frames.len());
+    for frame in frames {
+        // Each frame is streamed straight into the body
+        let input = &data[frame.clone()];
+        let mut encoder =
+            Encoder::with_dictionary(body, level, dictionary).map_err(compression_error)?;
+        tuning
+            .apply(&mut encoder, input.len())
+            .map_err(compression_error)?;
+        encoder
+            .set_pledged_src_size(Some(input.len() as u64))
+            .map_err(compression_error)?;
+        encoder.write_all(input).map_err(compression_error)?;
+        body = encoder.finish().map_err(compression_error)?;
+        ends.push(body.len());
+    }
+    Ok((body, ends))
+}
 
-            // Validate that either password or key is provided (not both)
-            match (&password, &key) {
-                (Some(_), Some(_)) => {
-                    return Err(CliError::InvalidInput(
-                        "Cannot specify both password and key. Choose one.".to_string(),
-                    ));
-                }
-                (None, None) => {
-                    // This is fine, we'll generate a random key
-                }
-                _ => {} // One of them is provided, which is fine
+/// `config` with the profile given by `--kdf-profile` in place of `kdf_profile`, if any.
+fn with_kdf_profile(config: &Config, profile: Option<String>) -> Result<Cow<'_, Config>, CliError> {
+    let Some(profile) = profile else {
+        return Ok(Cow::Borrowed(config));
+    };
+    let config = Config {
+        kdf_profile: profile,
+        ..config.clone()
+    };
+    config
+        .kdf_profile()
+        .map_err(|e| CliError::InvalidInput(format!("--kdf-profile: {e}")))?;
+    Ok(Cow::Owned(config))
+}
+
+/// Warns on stderr about a weak password, or refuses it with `--enforce-strong-password`.
+/// The name of `file` counts as a guess an attacker would try.
+fn check_password_strength(password: &str, file: &str, enforce: bool) -> Result<(), CliError> {
+    let name = Path::new(file)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+    let strength = strength::estimate(password, &[name]);
+    if !strength.is_weak() {
+        return Ok(());
+    }
+    if enforce {
+        return Err(CliError::InvalidInput(tr!(
+            "password-too-weak",
+            reason = strength.describe()
+        )));
+    }
+    status(tr!("password-weak", reason = strength.describe()));
+    Ok(())
+}
+
+/// Parses a `--pad` scheme: `padme` or a bucket size.
+fn parse_pad_spec(spec: &str) -> Result<Padding, CliError> {
+    if spec.eq_ignore_ascii_case("padme") {
+        return Ok(Padding::Padme);
+    }
+    match config::parse_size(spec) {
+        Ok(size) if size > 0 => Ok(Padding::Bucket(size)),
+        _ => Err(CliError::InvalidInput(format!(
+            "Invalid --pad '{spec}'. Expected padme or a size such as 64KiB"
+        ))),
+    }
+}
+
+/// Parses a `--wrap` spec (`png:<cover path>`) and returns the cover image path.
+fn parse_wrap_spec(spec: &str) -> Result<String, CliError> {
+    match spec.split_once(':') {
+        Some(("png", path)) if !path.is_empty() => Ok(path.to_string()),
+        _ => Err(CliError::InvalidInput(format!(
+            "Invalid --wrap '{spec}'. Expected png:<cover.png>"
+        ))),
+    }
+}
+
+/// Pulls the encrypted payload out of a PNG produced with `--wrap`; other data is returned as is.
+fn unwrap_input(file: &str, data: Input) -> Result<Input, CliError> {
+    if !stego::is_png(&data) {
+        return Ok(data);
+    }
+    stego::extract_png(&data)
+        .map(Input::from)
+        .map_err(|e| CliError::Format(format!("'{file}': {e}")))
+}
+
+/// Path value that stands for stdin/stdout instead of a file.
+const STDIO_PATH: &str = "-";
+
+/// Whether a path names a local file, not stdin/stdout or an S3 object.
+fn is_local_path(path: &str) -> bool {
+    path != STDIO_PATH && !s3::is_url(path)
+}
+
+/// Set while `encrypt --dir` runs the single-file encryption, whose status lines would
+/// drown the per-directory summary; they are only logged at `debug` then.
+static STATUS_MUTED: AtomicBool = AtomicBool::new(false);
+
+/// Tells the user how a command is getting on: an `info` event, which goes to stderr so
+/// stdout only carries data and `--json` results.
+fn status(msg: impl std::fmt::Display) {
+    if STATUS_MUTED.load(Ordering::Relaxed) {
+        tracing::debug!("{msg}");
+    } else {
+        tracing::info!("{msg}");
+    }
+}
+
+/// Shows a secret the user must keep, such as a generated key, straight on stderr: neither
+/// the log level nor [`STATUS_MUTED`] may hide the only copy there will ever be.
+fn show_secret(msg: impl std::fmt::Display) {
+    eprintln!("{msg}");
+}
+
+/// The whole input of a command: local files and downloaded S3 objects are memory-mapped
+/// rather than copied onto the heap, so multi-gigabyte inputs cost address space instead of
+/// memory; stdin is buffered.
+enum Input {
+    Mapped(memmap2::Mmap),
+    Buffered(zeroize::Zeroizing<Vec<u8>>),
+}
+
+impl From<Vec<u8>> for Input {
+    fn from(data: Vec<u8>) -> Self {
+        Input::Buffered(zeroize::Zeroizing::new(data))
+    }
+}
+
+impl std::ops::Deref for Input {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Input::Mapped(map) => map,
+            Input::Buffered(data) => data,
+        }
+    }
+}
+
+/// Reads the whole input, either from a file, from stdin when the path is `-`, or from
+/// an S3 object.
+fn read_input(file: &str, what: &str) -> Result<Input, CliError> {
+    if s3::is_url(file) {
+        let mut spool = s3::download(file)?;
+        return Ok(map_file(&mut spool)?);
+    }
+    if file == STDIO_PATH {
+        let mut stdin = io::stdin();
+        if stdin.is_terminal() {
+            return Err(CliError::InvalidInput(
+                "No input file given and stdin is a terminal. Use --file or pipe data in."
+                    .to_string(),
+            ));
+        }
+        let mut data = Vec::new();
+        stdin.read_to_end(&mut data)?;
+        return Ok(data.into());
+    }
+
+    validate_input_file(file)?;
+    let read_error = |e: io::Error| {
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to read {what} '{file}': {e}"),
+        ))
+    };
+    let mut handle = fs::File::open(file).map_err(read_error)?;
+    map_file(&mut handle).map_err(read_error)
+}
+
+/// Maps an open file, or reads it from the start where the filesystem can't map files.
+fn map_file(handle: &mut fs::File) -> io::Result<Input> {
+    // SAFETY: the map is read-only and private to this process. Another process truncating
+    // the file while we hold it would fault the read, the same hazard every mmap reader takes.
+    match unsafe { memmap2::Mmap::map(&*handle) } {
+        Ok(map) => Ok(Input::Mapped(map)),
+        // Some filesystems can't map files; read those the ordinary way
+        Err(_) => {
+            let mut data = Vec::new();
+            handle.read_to_end(&mut data)?;
+            Ok(data.into())
+        }
+    }
+}
+
+/// Reads a keyfile for two-factor encryption; it may hold anything but must not be empty.
+fn read_keyfile(path: &str) -> Result<zeroize::Zeroizing<Vec<u8>>, CliError> {
+    let keyfile = zeroize::Zeroizing::new(read_input(path, "keyfile")?.to_vec());
+    if keyfile.is_empty() {
+        return Err(CliError::InvalidInput(format!("Keyfile '{path}' is empty")));
+    }
+    Ok(keyfile)
+}
+
+/// Writes the output, either to a file, to stdout when the path is `-`, or to an S3
+/// object.
+fn write_output(output_file: &str, data: &[u8], what: &str) -> Result<(), CliError> {
+    if s3::is_url(output_file) {
+        return s3::upload(output_file, data);
+    }
+    if output_file == STDIO_PATH {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(data)?;
+        stdout.flush()?;
+        return Ok(());
+    }
+
+    let result = fs::File::create(output_file).and_then(|mut file| {
+        preallocate(&file, data.len() as u64)?;
+        file.write_all(data)
+    });
+    result.map_err(|e| {
+        // Neither a short write nor a reservation nothing was written into is any use;
+        // devices such as /dev/full are left alone
+        if fs::symlink_metadata(output_file).is_ok_and(|m| m.is_file()) {
+            let _ = fs::remove_file(output_file);
+        }
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to write {what} '{output_file}': {e}"),
+        ))
+    })
+}
+
+/// Reserves `len` bytes of disk for `file` before it is written, so the filesystem can lay
+/// it out in one go and a full disk fails at the start rather than halfway through. Only
+/// Linux can reserve blocks; elsewhere, and on filesystems without `fallocate`, the length
+/// is just set, which reserves nothing. Devices such as `/dev/null` are written as they are.
+fn preallocate(file: &fs::File, len: u64) -> io::Result<()> {
+    if !file.metadata()?.is_file() {
+        return Ok(());
+    }
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::fd::AsRawFd;
+
+        // SAFETY: the descriptor belongs to `file`, which outlives the call
+        if len > 0 && unsafe { libc::fallocate(file.as_raw_fd(), 0, 0, len as libc::off_t) } != 0 {
+            let error = io::Error::last_os_error();
+            if !matches!(
+                error.raw_os_error(),
+                Some(libc::EOPNOTSUPP | libc::ENOSYS | libc::EINVAL)
+            ) {
+                return Err(error);
+            }
+        }
+    }
+    file.set_len(len)
+}
+
+/// Builds the filter of a directory walk: the patterns of the ignore files come first, in
+/// order, then the `--exclude` patterns.
+fn walk_filter(
+    exclude_from: &[String],
+    exclude: &[String],
+    include: &[String],
+) -> Result<Filter, CliError> {
+    let mut excludes = Vec::new();
+    for path in exclude_from {
+        let contents = fs::read_to_string(path).map_err(|e| {
+            CliError::Io(io::Error::new(
+                e.kind(),
+                format!("Failed to read exclude file '{path}': {e}"),
+            ))
+        })?;
+        excludes.extend(Filter::patterns_in(&contents));
+    }
+    excludes.extend_from_slice(exclude);
+    Filter::new(&excludes, include).map_err(CliError::InvalidInput)
+}
+
+/// Resolves the file `--in-place` replaces or `--shred-original` destroys (named by `flag`),
+/// refusing anything but a regular file with a single name. A symlink is followed, so the
+/// file it points at is the one replaced or destroyed, not the link.
+fn plaintext_target(file: &str, flag: &str) -> Result<String, CliError> {
+    let target = fs::canonicalize(file).map_err(|e| {
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to resolve '{file}': {e}"),
+        ))
+    })?;
+    let meta = fs::metadata(&target)?;
+    if !meta.is_file() {
+        return Err(CliError::InvalidInput(format!(
+            "{flag} needs a regular file, and '{file}' is not one"
+        )));
+    }
+    if shred::link_count(&meta) > 1 {
+        return Err(CliError::InvalidInput(format!(
+            "'{file}' has other hard links to the same data; remove them first or encrypt without {flag}"
+        )));
+    }
+    Ok(target.to_string_lossy().into_owned())
+}
+
+/// Replaces `path` atomically: the data is written and synced to a temporary file in the
+/// same directory, which is then renamed over the destination.
+fn write_atomic(path: &str, data: &[u8]) -> Result<(), CliError> {
+    StagedFile::write(path, data)?.commit()
+}
+
+/// The new contents of a file, written and synced to a temporary file next to it but not
+/// yet renamed over it; dropped without [`StagedFile::commit`], the temporary file is removed.
+struct StagedFile {
+    target: PathBuf,
+    temp: PathBuf,
+}
+
+impl StagedFile {
+    fn write(path: &str, data: &[u8]) -> Result<Self, CliError> {
+        let target = PathBuf::from(path);
+        let name = target
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("output");
+        let temp = staging_dir(&target).join(format!(".{name}.{}.tmp", std::process::id()));
+
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)
+            .map_err(|e| write_error(path, e))?;
+        let staged = StagedFile { target, temp };
+        let result = (|| -> io::Result<()> {
+            if let Ok(meta) = fs::metadata(&staged.target) {
+                file.set_permissions(meta.permissions())?;
             }
+            file.write_all(data)?;
+            file.sync_all()
+        })();
+        result.map_err(|e| write_error(path, e))?;
+        Ok(staged)
+    }
+
+    /// Where the new contents are until the commit.
+    fn temp_path(&self) -> &Path {
+        &self.temp
+    }
+
+    fn commit(self) -> Result<(), CliError> {
+        let result = (|| -> io::Result<()> {
+            fs::rename(&self.temp, &self.target)?;
+            // Make the rename itself durable
+            #[cfg(unix)]
+            fs::File::open(staging_dir(&self.target))?.sync_all()?;
+            Ok(())
+        })();
+        result.map_err(|e| write_error(&self.target.to_string_lossy(), e))
+    }
+}
+
+impl Drop for StagedFile {
+    fn drop(&mut self) {
+        // Already gone after a successful commit
+        let _ = fs::remove_file(&self.temp);
+    }
+}
+
+fn staging_dir(target: &Path) -> &Path {
+    match target.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    }
+}
+
+fn write_error(path: &str, e: io::Error) -> CliError {
+    CliError::Io(io::Error::new(
+        e.kind(),
+        format!("Failed to write '{path}': {e}"),
+    ))
+}
+
+/// Writes a secret to a new file that only the owner can read.
+fn write_secret_file(path: &str, contents: &str) -> Result<(), CliError> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .and_then(|mut f| f.write_all(contents.as_bytes()))
+        .map_err(|e| {
+            CliError::Io(io::Error::new(
+                e.kind(),
+                format!("Failed to write '{path}': {e}"),
+            ))
+        })
+}
+
+/// Result of an encrypt/decrypt command, printed with `--json`.
+#[derive(Serialize)]
+pub struct CommandReport {
+    pub operation: &'static str,
+    /// "key", "password" or the foreign archive format
+    pub mode: String,
+    pub input: String,
+    pub output: String,
+    pub input_size: usize,
+    pub output_size: usize,
+    /// Short SHA-256 fingerprint of the key (key mode only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_fingerprint: Option<String>,
+    /// Randomly generated key, base64 (only when one was generated)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generated_key: Option<String>,
+    /// Generated recovery key, base64 (only when printed rather than saved to a file)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovery_key: Option<String>,
+    /// Chunk reuse statistics (`--delta-from` only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delta: Option<crypto::delta::DeltaStats>,
+    /// Fingerprint of the key the file was signed with (signed with `--sign-key`, or
+    /// verified on decryption)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signer: Option<String>,
+    /// zstd level the body was compressed at (`.xd` files; recorded ones on decryption)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression_level: Option<i32>,
+    pub duration_ms: u128,
+}
+
+/// Prints a successful command result as a single JSON line.
+fn print_json_report(report: &impl Serialize) {
+    let mut value = serde_json::to_value(report).unwrap_or_default();
+    if let Some(map) = value.as_object_mut() {
+        map.insert("status".to_string(), "ok".into());
+    }
+    println!("{value}");
+}
+
+/// Prints an error as a single JSON line, for `--json` mode.
+pub fn print_json_error(error: &CliError) {
+    let value = serde_json::json!({
+        "status": "error",
+        "kind": error.kind(),
+        "exit_code": error.exit_code(),
+        "message": error.to_string(),
+    });
+    println!("{value}");
+}
+
+/// Handles the `encrypt` subcommand.
+async fn encrypt_command(
+    args: EncryptArgs,
+    config: &Config,
+    json: bool,
+) -> Result<CommandReport, CliError> {
+    let started = Instant::now();
+    let EncryptArgs {
+        // Several files and patterns go through `batch::encrypt_batch`, which calls this
+        // once per file
+        files: _,
+        paths: _,
+        jobs: _,
+        file,
+        tar_from,
+        // `encrypt --dir` runs through `tree::encrypt_dir`, which calls this once per file
+        dir: _,
+        incremental: _,
+        state: _,
+        follow_symlinks,
+        store_symlinks,
+        skip_symlinks,
+        exclude,
+        exclude_from,
+        include,
+        password,
+        key,
+        key_name,
+        master_key,
+        convergent,
+        output,
+        format,
+        pgp_recipients,
+        armor,
+        bundle_readme,
+        chunk_size,
+        compression_level,
+        no_compress,
+        long,
+        dict,
+        pad,
+        no_metadata,
+        delta_from,
+        rsyncable,
+        wrap,
+        recovery_key,
+        recovery_out,
+        keyfile,
+        kdf_profile,
+        enforce_strong_password,
+        kdf,
+        recipients,
+        quorum,
+        quorum_keys,
+        split_credentials,
+        sign_key,
+        pkcs11,
+        tpm,
+        tpm_pcrs,
+        kms,
+        in_place,
+        wipe,
+        verify,
+        shred_original,
+        volume_size,
+        qr,
+        force,
+        contents,
+    } = args;
+    let password = password.map(zeroize::Zeroizing::new);
+    let config = &*with_kdf_profile(config, kdf_profile)?;
+    if let Some(password) = &password {
+        check_password_strength(
+            password,
+            file.as_deref().unwrap_or_default(),
+            enforce_strong_password,
+        )?;
+    }
+    let recovery_key = recovery_key || recovery_out.is_some();
+    let recipients = identity::read_recipients(&recipients)?;
+    let key = match key_name {
+        Some(name) => Some(keystore::load_key(&name, config).await?.to_string()),
+        None => key,
+    };
 
-            // Validate key if provided
-            let validated_key = if let Some(ref key_str) = key {
-                Some(validate_key(key_str)?)
+    // Validate that either password or key is provided (not both)
+    if password.is_some() && key.is_some() {
+        return Err(CliError::InvalidInput(
+            "Cannot specify both password and key. Choose one.".to_string(),
+        ));
+    }
+    if format == OutputFormat::ZipAes && password.is_none() {
+        return Err(CliError::InvalidInput(
+            "--format zip-aes requires --password".to_string(),
+        ));
+    }
+    if format == OutputFormat::Pgp
+        && (key.is_some() || password.is_none() && pgp_recipients.is_empty())
+    {
+        return Err(CliError::InvalidInput(
+            "--format pgp requires --password and/or --pgp-recipient".to_string(),
+        ));
+    }
+    if (!pgp_recipients.is_empty() || armor) && format != OutputFormat::Pgp {
+        return Err(CliError::InvalidInput(
+            "--pgp-recipient and --armor can only be used with --format pgp".to_string(),
+        ));
+    }
+
+    let chunk_size = match chunk_size {
+        Some(size) => {
+            let part_size = config::parse_size(&size).map_err(CliError::InvalidInput)?;
+            Some(
+                crypto::chunked::chunk_size_for_part(part_size)
+                    .map_err(|e| CliError::InvalidInput(format!("--chunk-size: {e}")))?,
+            )
+        }
+        None => config
+            .chunk_size()
+            .map_err(|e| CliError::InvalidInput(e.to_string()))?,
+    };
+    if chunk_size.is_some() && format != OutputFormat::Xd {
+        return Err(CliError::InvalidInput(
+            "--chunk-size can only be used with --format xd".to_string(),
+        ));
+    }
+    let level = compression::validate_level(compression_level.unwrap_or(config.compression_level))
+        .map_err(|e| CliError::InvalidInput(format!("--compression-level: {e}")))?;
+    let tuning = compression::Tuning {
+        threads: compression::resolve_threads(config.threads),
+        long_distance_matching: long || config.long_distance_matching,
+    };
+    if dict && format != OutputFormat::Xd {
+        return Err(CliError::InvalidInput(
+            "--dict can only be used with --format xd".to_string(),
+        ));
+    }
+    let padding = pad.as_deref().map(parse_pad_spec).transpose()?;
+    if padding.is_some() && format != OutputFormat::Xd {
+        return Err(CliError::InvalidInput(
+            "--pad can only be used with --format xd".to_string(),
+        ));
+    }
+    let password_kdf = match &kdf {
+        Some(name) => config
+            .kdf_profile()
+            .map_err(|e| CliError::InvalidInput(e.to_string()))
+            .and_then(|profile| {
+                crypto::PasswordKdf::for_profile(name, profile)
+                    .map_err(|e| CliError::InvalidInput(format!("--kdf: {e}")))
+            })?,
+        None => config
+            .password_kdf()
+            .map_err(|e| CliError::InvalidInput(e.to_string()))?,
+    };
+    if kdf.is_some() && format != OutputFormat::Xd {
+        return Err(CliError::InvalidInput(
+            "--kdf can only be used with --format xd".to_string(),
+        ));
+    }
+    if no_metadata && format != OutputFormat::Xd {
+        return Err(CliError::InvalidInput(
+            "--no-metadata can only be used with --format xd".to_string(),
+        ));
+    }
+    if delta_from.is_some() && (format != OutputFormat::Xd || wrap.is_some()) {
+        return Err(CliError::InvalidInput(
+            "--delta-from can only be used with --format xd and without --wrap".to_string(),
+        ));
+    }
+    if recovery_key && format != OutputFormat::Xd {
+        return Err(CliError::InvalidInput(
+            "--recovery-key can only be used with --format xd".to_string(),
+        ));
+    }
+    if !recipients.is_empty() && format != OutputFormat::Xd {
+        return Err(CliError::InvalidInput(
+            "--recipient can only be used with --format xd".to_string(),
+        ));
+    }
+    if keyfile.is_some() && format != OutputFormat::Xd {
+        return Err(CliError::InvalidInput(
+            "--keyfile can only be used with --format xd".to_string(),
+        ));
+    }
+    let keyfile = keyfile.as_deref().map(read_keyfile).transpose()?;
+    if quorum.is_some() && format != OutputFormat::Xd {
+        return Err(CliError::InvalidInput(
+            "--quorum can only be used with --format xd".to_string(),
+        ));
+    }
+    let quorum_keys = quorum_keys
+        .iter()
+        .map(|key| validate_key(key))
+        .collect::<Result<Vec<_>, _>>()?;
+    if split_credentials && format != OutputFormat::Xd {
+        return Err(CliError::InvalidInput(
+            "--split-credentials can only be used with --format xd".to_string(),
+        ));
+    }
+    if sign_key.is_some() && format != OutputFormat::Xd {
+        return Err(CliError::InvalidInput(
+            "--sign-key can only be used with --format xd".to_string(),
+        ));
+    }
+    let sign_key = sign_key
+        .as_deref()
+        .map(identity::read_signing_key)
+        .transpose()?;
+    let hsm = pkcs11::Hsm::from_args(&pkcs11)?;
+    let hsm_key_id = match (&hsm, pkcs11.pkcs11_key_id) {
+        (Some(_), _) if format != OutputFormat::Xd => {
+            return Err(CliError::InvalidInput(
+                "--pkcs11-module can only be used with --format xd".to_string(),
+            ));
+        }
+        (Some(_), _) if key.is_some() || split_credentials || delta_from.is_some() => {
+            return Err(CliError::InvalidInput(
+                "--pkcs11-module cannot be combined with --key, --split-credentials or --delta-from"
+                    .to_string(),
+            ));
+        }
+        (Some(_), _) if recovery_key => {
+            return Err(CliError::InvalidInput(
+                "--pkcs11-module cannot be combined with --recovery-key; add a password slot instead"
+                    .to_string(),
+            ));
+        }
+        (Some(_), Some(key_id)) => {
+            pkcs11::validate_key_id(&key_id)?;
+            Some(key_id)
+        }
+        (Some(_), None) => {
+            return Err(CliError::InvalidInput(
+                "--pkcs11-key-id is required to encrypt with a PKCS#11 device".to_string(),
+            ));
+        }
+        (None, _) => None,
+    };
+    if tpm && format != OutputFormat::Xd {
+        return Err(CliError::InvalidInput(
+            "--tpm can only be used with --format xd".to_string(),
+        ));
+    }
+    if tpm && (key.is_some() || split_credentials || delta_from.is_some() || recovery_key) {
+        return Err(CliError::InvalidInput(
+            "--tpm cannot be combined with --key, --split-credentials, --delta-from or --recovery-key"
+                .to_string(),
+        ));
+    }
+    let tpm_pcrs = tpm_pcrs.as_deref().map(tpm::validate_pcrs).transpose()?;
+    if let Some(uri) = &kms {
+        if format != OutputFormat::Xd {
+            return Err(CliError::InvalidInput(
+                "--kms can only be used with --format xd".to_string(),
+            ));
+        }
+        if key.is_some() || split_credentials || delta_from.is_some() || recovery_key {
+            return Err(CliError::InvalidInput(
+                "--kms cannot be combined with --key, --split-credentials, --delta-from or --recovery-key"
+                    .to_string(),
+            ));
+        }
+        kms::provider(uri)?;
+    }
+    if store_symlinks && format == OutputFormat::ZipAes {
+        return Err(CliError::InvalidInput(
+            "--format zip-aes can't store symlinks; use --follow-symlinks or --skip-symlinks"
+                .to_string(),
+        ));
+    }
+    let symlinks = if follow_symlinks {
+        SymlinkPolicy::Follow
+    } else if skip_symlinks || format == OutputFormat::ZipAes {
+        SymlinkPolicy::Skip
+    } else {
+        SymlinkPolicy::Store
+    };
+    let filter = walk_filter(&exclude_from, &exclude, &include)?;
+    let cover = wrap.as_deref().map(parse_wrap_spec).transpose()?;
+    if cover.is_some() && format != OutputFormat::Xd {
+        return Err(CliError::InvalidInput(
+            "--wrap can only be used with --format xd".to_string(),
+        ));
+    }
+    if shred_original
+        && (format != OutputFormat::Xd
+            || file.as_deref().is_some_and(|file| !is_local_path(file))
+            || output
+                .as_deref()
+                .is_some_and(|output| !is_local_path(output)))
+    {
+        return Err(CliError::InvalidInput(
+            "--shred-original needs input and output files and --format xd".to_string(),
+        ));
+    }
+    let verify = verify || shred_original;
+    if verify
+        && (format != OutputFormat::Xd || output.as_deref().is_some_and(|o| !is_local_path(o)))
+    {
+        return Err(CliError::InvalidInput(
+            "--verify needs an output file and --format xd".to_string(),
+        ));
+    }
+    if in_place
+        && (format != OutputFormat::Xd || file.as_deref().is_some_and(|f| !is_local_path(f)))
+    {
+        return Err(CliError::InvalidInput(
+            "--in-place needs an input file and --format xd".to_string(),
+        ));
+    }
+    let extension = if cover.is_some() {
+        "png"
+    } else if armor {
+        "asc"
+    } else {
+        format.extension()
+    };
+
+    // Validate key if provided
+    let validated_key = if let Some(ref key_str) = key {
+        Some(validate_key(key_str)?)
+    } else {
+        None
+    };
+
+    // Resolve the input: a single file/stdin, or a directory packed as tar
+    let (source, orig_name, default_output) = if let Some(dir) = &tar_from {
+        let name = tarball::archive_name(dir);
+        let stem = name.trim_end_matches(".tar").to_string();
+        let default_output = config.output_path(&format!("{stem}.{extension}"));
+        (dir.clone(), name, Some(default_output))
+    } else {
+        let file = file.unwrap_or_else(|| STDIO_PATH.to_string());
+        if file == STDIO_PATH {
+            (file, "file.bin".to_string(), None)
+        } else {
+            // Get original filename for metadata
+            let name = Path::new(&file)
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("file.bin")
+                .to_string();
+            // OpenPGP messages don't carry the name, so keep it in the output name like gpg
+            let default_output = if format == OutputFormat::Pgp {
+                config.output_path(&format!("{name}.{extension}"))
             } else {
-                None
+                config.output_path(&generate_encrypt_output(&file, extension))
             };
+            (file, name, Some(default_output))
+        }
+    };
+    // Anonymous files record an empty name, and with it no timestamp
+    let orig_name = if no_metadata {
+        String::new()
+    } else {
+        orig_name
+    };
+
+    let shred_target = shred_original
+        .then(|| plaintext_target(&source, "--shred-original"))
+        .transpose()?;
+
+    // Determine output file; in place, that is the file a symlink points at
+    let output = if in_place {
+        Some(plaintext_target(&source, "--in-place")?)
+    } else {
+        output
+    };
+    let output_file = match output.or(default_output) {
+        Some(o) => o,
+        None => {
+            return Err(CliError::InvalidInput(
+                "--output is required when reading from stdin".to_string(),
+            ));
+        }
+    };
+    let to_stdout = output_file == STDIO_PATH;
+    if to_stdout && json {
+        return Err(CliError::InvalidInput(
+            "--json cannot be combined with writing data to stdout".to_string(),
+        ));
+    }
+    if let Some(target) = &shred_target
+        && fs::canonicalize(&output_file).is_ok_and(|output| output == Path::new(target))
+    {
+        return Err(CliError::InvalidInput(
+            "--shred-original cannot write the encrypted file over the input".to_string(),
+        ));
+    }
+    if let Some(size) = chunk_size
+        && ((size as usize + crypto::chunked::TAG_LEN) as u64) < crypto::chunked::S3_MIN_PART_SIZE
+    {
+        status(tr!("s3-chunk-too-small"));
+    }
 
-            // Determine output file
-            let output_file = output.unwrap_or_else(|| generate_encrypt_output(&file));
+    let volume_size = match volume_size {
+        Some(size) => match config::parse_size(&size) {
+            _ if format != OutputFormat::Xd || !is_local_path(&output_file) => {
+                return Err(CliError::InvalidInput(
+                    "--volume-size needs a local output file and --format xd".to_string(),
+                ));
+            }
+            Ok(size) if size > 0 => Some(size),
+            Ok(_) => {
+                return Err(CliError::InvalidInput(
+                    "--volume-size must be larger than 0".to_string(),
+                ));
+            }
+            Err(e) => return Err(CliError::InvalidInput(format!("--volume-size: {e}"))),
+        },
+        None => None,
+    };
 
-            // Check output file
-            check_output_file(&output_file, force)?;
+    if let Some(path) = &qr
+        && path != STDIO_PATH
+    {
+        check_output_file(path, force)?;
+    }
 
-            // Read input file
-            let data = fs::read(&file).map_err(|e| {
-                CliError::Io(io::Error::new(
+    // Check output file
+    if volume_size.is_some() {
+        check_output_file(&volume::volume_path(&output_file, 1), force)?;
+    } else if !to_stdout && !in_place {
+        check_output_file(&output_file, force)?;
+    }
+    if let Some(path) = &recovery_out {
+        check_output_file(path, force)?;
+    }
+    let credential_files = [1, 2].map(|index| format!("{output_file}.{index}.cred"));
+    if split_credentials {
+        if !is_local_path(&output_file) {
+            return Err(CliError::InvalidInput(
+                "--split-credentials needs a local output file".to_string(),
+            ));
+        }
+        for path in &credential_files {
+            check_output_file(path, force)?;
+        }
+    }
+    if bundle_readme {
+        if !is_local_path(&output_file) {
+            return Err(CliError::InvalidInput(
+                "--bundle-readme needs a local output file".to_string(),
+            ));
+        }
+        check_output_file(&bundle::readme_path(&output_file), force)?;
+    }
+
+    if format == OutputFormat::ZipAes {
+        let password = zeroize::Zeroizing::new(password.unwrap_or_default());
+        let entries = if let Some(dir) = &tar_from {
+            status(tr!("collecting-dir", dir = dir));
+            zip_aes::collect_dir(Path::new(dir), symlinks, &filter).map_err(|e| match e {
+                interop::InteropError::Io(e) => CliError::Io(io::Error::new(
                     e.kind(),
-                    format!("Failed to read input file '{file}': {e}"),
-                ))
+                    format!("Failed to collect '{dir}': {e}"),
+                )),
+                e => CliError::Crypto(e.to_string()),
+            })?
+        } else {
+            vec![ArchiveEntry {
+                name: orig_name.clone(),
+                data: read_input(&source, "input file")?.to_vec(),
+            }]
+        };
+        let original_size: usize = entries.iter().map(|e| e.data.len()).sum();
+
+        status(tr!("encrypting-zip", file = source));
+        let archive = zip_aes::write_archive(&entries, &password)
+            .map_err(|e| CliError::Crypto(format!("ZIP encryption failed: {e}")))?;
+        write_output(&output_file, &archive, "encrypted archive")?;
+
+        if !to_stdout {
+            status(tr!("zip-written", output = output_file));
+        }
+        if bundle_readme {
+            let readme = bundle::write_readme(&output_file, &archive, format, true)?;
+            status(tr!("readme-written", path = readme));
+        }
+        status(tr!("original-size", bytes = original_size));
+        status(tr!("encrypted-size", bytes = archive.len()));
+        return Ok(CommandReport {
+            operation: "encrypt",
+            mode: "zip-aes".to_string(),
+            input: source,
+            output: output_file,
+            input_size: original_size,
+            output_size: archive.len(),
+            key_fingerprint: None,
+            generated_key: None,
+            recovery_key: None,
+            delta: None,
+            signer: None,
+            compression_level: None,
+            duration_ms: started.elapsed().as_millis(),
+        });
+    }
+
+    let data = if let Some(dir) = &tar_from {
+        status(tr!("packing-dir", dir = dir));
+        Input::from(tarball::pack_dir(dir, symlinks, &filter)?)
+    } else if let Some(contents) = contents {
+        Input::Buffered(contents)
+    } else {
+        read_input(&source, "input file")?
+    };
+
+    if format == OutputFormat::Pgp {
+        status(tr!("encrypting-pgp", file = source));
+        let message = pgp::encrypt_message(
+            &data,
+            password.as_deref().map(String::as_str),
+            &pgp_recipients,
+            armor,
+        )?;
+        write_output(&output_file, &message, "encrypted message")?;
+
+        if !to_stdout {
+            status(tr!("pgp-written", output = output_file));
+        }
+        if bundle_readme {
+            let readme =
+                bundle::write_readme(&output_file, &message, format, pgp_recipients.is_empty())?;
+            status(tr!("readme-written", path = readme));
+        }
+        status(tr!("original-size", bytes = data.len()));
+        status(tr!("encrypted-size", bytes = message.len()));
+        return Ok(CommandReport {
+            operation: "encrypt",
+            mode: "pgp".to_string(),
+            input: source,
+            output: output_file,
+            input_size: data.len(),
+            output_size: message.len(),
+            key_fingerprint: None,
+            generated_key: None,
+            recovery_key: None,
+            delta: None,
+            signer: None,
+            compression_level: None,
+            duration_ms: started.elapsed().as_millis(),
+        });
+    }
+
+    status(tr!("encrypting-file", file = source));
+
+    let mode = if quorum.is_some() {
+        "quorum"
+    } else if !recipients.is_empty() {
+        "recipients"
+    } else if hsm.is_some() {
+        "pkcs11"
+    } else if tpm {
+        "tpm"
+    } else if kms.is_some() {
+        "kms"
+    } else if split_credentials {
+        "split-credentials"
+    } else if keyfile.is_some() {
+        "two-factor"
+    } else if password.is_some() {
+        "password"
+    } else if master_key {
+        "master-key"
+    } else if convergent {
+        "convergent"
+    } else {
+        "key"
+    };
+    if master_key && (validated_key.is_none() || mode != "master-key" || delta_from.is_some()) {
+        return Err(CliError::InvalidInput(
+            "--master-key needs --key or --key-name, without another credential or --delta-from"
+                .to_string(),
+        ));
+    }
+    if convergent && (validated_key.is_none() || mode != "convergent" || delta_from.is_some()) {
+        return Err(CliError::InvalidInput(
+            "--convergent needs --key or --key-name, without another credential or --delta-from"
+                .to_string(),
+        ));
+    }
+    let mut key_fingerprint = None;
+    let mut generated_key = None;
+    let mut generated_recovery_key = None;
+    let mut delta = None;
+    // Chunked files get a zstd frame per chunk's worth of input, so `salvage` can still
+    // decompress the data that follows a damaged chunk
+    let frame_size = chunk_size.map(|size| size as usize);
+    // Media and archives gain nothing from zstd, so they are stored behind a 0x00 flag;
+    // delta encryption always compresses, as it relies on the frames
+    let compress =
+        delta_from.is_some() || !no_compress && compression::worth_compressing(&source, &data);
+    if !compress && !no_compress {
+        status(tr!("stored-uncompressed", file = source));
+    }
+    // With --dict, a dictionary trained on the directory's files lets small frames share
+    // what the files have in common
+    let dictionary = if dict && compress {
+        let files = tarball::file_ranges(&data)?;
+        let samples: Vec<&[u8]> = files.iter().map(|file| &data[file.clone()]).collect();
+        let dictionary = compression::train_dictionary(&samples);
+        match &dictionary {
+            Some(dictionary) => status(tr!(
+                "dictionary-trained",
+                bytes = dictionary.len(),
+                files = samples.len()
+            )),
+            None => status(tr!("dictionary-too-little-data")),
+        }
+        dictionary.map(zeroize::Zeroizing::new)
+    } else {
+        None
+    };
+    let encode_body = |data: &[u8]| -> Result<zeroize::Zeroizing<Vec<u8>>, CliError> {
+        let body = if compress {
+            let body =
+                zeroize::Zeroizing::new(compress_body(data, level, tuning, &[], frame_size)?);
+            match &dictionary {
+                // The dictionary is stored in the file, so it is only kept if it pays for itself
+                Some(dictionary) => {
+                    let with = zeroize::Zeroizing::new(compress_body(
+                        data, level, tuning, dictionary, frame_size,
+                    )?);
+                    if with.len() < body.len() {
+                        status(tr!("dictionary-saves", bytes = body.len() - with.len()));
+                        with
+                    } else {
+                        status(tr!("dictionary-not-worth-it"));
+                        body
+                    }
+                }
+                None => body,
+            }
+        } else {
+            zeroize::Zeroizing::new([&[0x00], data].concat())
+        };
+        Ok(match padding {
+            Some(scheme) => zeroize::Zeroizing::new(padding::pad(&body, scheme)),
+            None => body,
+        })
+    };
+    // What --verify opens the written file with: the key the body was sealed with, or else
+    // the credential it was encrypted under
+    let verify_password = password.as_ref().filter(|_| verify).map(|p| p.to_string());
+    let mut sealed_key: Option<crypto::SecureKey> = None;
+    let mut credential_key: Option<SecretBuffer> = None;
+    let encrypted = if let Some(previous_path) = &delta_from {
+        // New version of an existing file: same secret, unchanged chunks are reused
+        let previous_data = read_input(previous_path, "previous version")?;
+        let previous = XdReader::parse(&previous_data)
+            .map_err(|_| CliError::Format(format!("'{previous_path}' is not a valid .xd file")))?;
+        let file_key = crypto::file_key(
+            &previous,
+            password.as_deref().cloned(),
+            validated_key.as_deref(),
+        )
+        .await
+        .map_err(|e| CliError::from_crypto("Opening the previous version failed", e))?;
+        // Keyslot files are opened with a data key that must not be fingerprinted, and
+        // master-key files with a subkey
+        if matches!(previous.header, ParsedHeader::Key(_)) && previous.header.keyslots().is_none() {
+            key_fingerprint = Some(crypto::key_fingerprint(
+                validated_key.as_deref().unwrap_or(file_key.as_slice()),
+            ));
+        }
+
+        // Independent zstd frames keep unchanged input regions byte-identical after compression
+        let frame_size = chunk_size
+            .or(previous.header.chunk_size())
+            .unwrap_or(crypto::DEFAULT_DELTA_CHUNK_SIZE) as usize;
+        let (body, cuts) = if rsyncable {
+            // Content-defined frames: an edit only shifts the frames after it, never changes them
+            let frames = cdc::split(&data, frame_size / 4, frame_size / 2, frame_size);
+            let (body, ends) = compress_frames(&data, level, tuning, &[], &frames)?;
+            (body, Some(ends))
+        } else {
+            (
+                compress_body(&data, level, tuning, &[], Some(frame_size))?,
+                None,
+            )
+        };
+        let body = zeroize::Zeroizing::new(body);
+        let (encrypted, stats) = crypto::encrypt_delta(
+            &body,
+            &orig_name,
+            &previous,
+            &file_key,
+            chunk_size,
+            cuts.as_deref(),
+        )
+        .map_err(|e| CliError::from_crypto("Delta encryption failed", e))?;
+        status(tr!(
+            "delta-reused",
+            reused = stats.reused_chunks,
+            total = stats.reused_chunks + stats.new_chunks,
+            bytes = stats.reused_bytes,
+            previous = previous_path
+        ));
+        delta = Some(stats);
+        sealed_key = Some(file_key);
+        encrypted
+    } else if split_credentials {
+        // Only a key slot for the key derived from both parts; neither part is stored
+        let compressed_with_flag = encode_body(&data)?;
+        let data_key = crypto::keyslot::generate_data_key();
+        let (part_a, part_b) = crypto::split::generate();
+        let combined = crypto::split::combine(&part_a, &part_b)
+            .map_err(|e| CliError::from_crypto("Split-credential encryption failed", e))?;
+        let slot = crypto::keyslot::create(
+            &data_key,
+            crypto::keyslot::Credential::Key(combined.as_slice()),
+            crypto::Argon2Params::default(),
+            Some("split".to_string()),
+        )
+        .await
+        .map_err(|e| CliError::from_crypto("Split-credential encryption failed", e))?;
+        let encrypted = crypto::encrypt_with_keyslots(
+            &compressed_with_flag,
+            &data_key,
+            &orig_name,
+            vec![slot],
+            chunk_size,
+        )
+        .map_err(|e| CliError::from_crypto("Split-credential encryption failed", e))?;
+        sealed_key = Some(data_key);
+        for (path, part) in credential_files.iter().zip([&part_a, &part_b]) {
+            write_secret_file(path, &zeroize::Zeroizing::new(format!("{part}\n")))?;
+            status(tr!("key-part-written", index = part.index, path = path));
+        }
+        status(tr!("key-parts-hint"));
+        encrypted
+    } else if let Some(threshold) = quorum {
+        // Every quorum key wraps one share of the data key
+        let compressed_with_flag = encode_body(&data)?;
+        let data_key = crypto::keyslot::generate_data_key();
+        let keys: Vec<&[u8]> = quorum_keys.iter().map(|key| key.as_slice()).collect();
+        let slots =
+            crypto::keyslot::create_quorum(&data_key, &keys, threshold).map_err(|e| match e {
+                crypto::CryptoError::EncryptionError(message) => {
+                    CliError::InvalidInput(format!("--quorum: {message}"))
+                }
+                e => CliError::from_crypto("Quorum encryption failed", e),
             })?;
+        status(tr!(
+            "quorum-hint",
+            threshold = threshold,
+            count = keys.len()
+        ));
+        let encrypted = crypto::encrypt_with_keyslots(
+            &compressed_with_flag,
+            &data_key,
+            &orig_name,
+            slots,
+            chunk_size,
+        )
+        .map_err(|e| CliError::from_crypto("Quorum encryption failed", e))?;
+        sealed_key = Some(data_key);
+        encrypted
+    } else if !recipients.is_empty() || hsm.is_some() || tpm || kms.is_some() {
+        // Every recipient and device (and the password, if given) gets a slot wrapping a
+        // random data key
+        let compressed_with_flag = encode_body(&data)?;
+        let data_key = crypto::keyslot::generate_data_key();
+        let mut slots = Vec::with_capacity(recipients.len() + 4);
+        if let (Some(hsm), Some(key_id)) = (&hsm, &hsm_key_id) {
+            let kek = crypto::keyslot::generate_data_key();
+            let sealed = hsm.wrap(key_id, kek.as_slice())?;
+            slots.push(
+                crypto::keyslot::create(
+                    &data_key,
+                    crypto::keyslot::Credential::Hsm(crypto::keyslot::HsmSecret {
+                        key_id,
+                        sealed: &sealed,
+                        secret: kek.as_slice(),
+                    }),
+                    crypto::Argon2Params::default(),
+                    None,
+                )
+                .await
+                .map_err(|e| CliError::from_crypto("PKCS#11 encryption failed", e))?,
+            );
+            status(tr!("wrapped-pkcs11", key = key_id));
+        }
+        if tpm {
+            let kek = crypto::keyslot::generate_data_key();
+            let sealed = tpm::seal(kek.as_slice(), tpm_pcrs.as_deref())?;
+            slots.push(
+                crypto::keyslot::create(
+                    &data_key,
+                    crypto::keyslot::Credential::Tpm(crypto::keyslot::TpmSecret {
+                        object: crypto::keyslot::TpmObject {
+                            public: &sealed.public,
+                            private: &sealed.private,
+                            pcrs: tpm_pcrs.as_deref(),
+                        },
+                        secret: kek.as_slice(),
+                    }),
+                    crypto::Argon2Params::default(),
+                    None,
+                )
+                .await
+                .map_err(|e| CliError::from_crypto("TPM encryption failed", e))?,
+            );
+            match &tpm_pcrs {
+                Some(pcrs) => status(tr!("sealed-tpm-pcrs", pcrs = pcrs)),
+                None => status(tr!("sealed-tpm")),
+            }
+        }
+        if let Some(uri) = &kms {
+            let kek = crypto::keyslot::generate_data_key();
+            let wrapped = kms::wrap(uri, kek.as_slice())?;
+            slots.push(
+                crypto::keyslot::create(
+                    &data_key,
+                    crypto::keyslot::Credential::Kms(crypto::keyslot::KmsSecret {
+                        uri: &wrapped.uri,
+                        sealed: &wrapped.sealed,
+                        secret: kek.as_slice(),
+                    }),
+                    crypto::Argon2Params::default(),
+                    None,
+                )
+                .await
+                .map_err(|e| CliError::from_crypto("KMS encryption failed", e))?,
+            );
+            status(tr!("wrapped-kms", uri = wrapped.uri));
+        }
+        for recipient in &recipients {
+            slots.push(
+                crypto::keyslot::create(
+                    &data_key,
+                    crypto::keyslot::Credential::Recipient(recipient),
+                    crypto::Argon2Params::default(),
+                    None,
+                )
+                .await
+                .map_err(|e| CliError::from_crypto("Recipient encryption failed", e))?,
+            );
+        }
+        if let Some(password) = password {
+            let kdf_params = config
+                .kdf_profile()
+                .map_err(|e| CliError::InvalidInput(e.to_string()))?
+                .params();
+            slots.push(
+                crypto::keyslot::create(
+                    &data_key,
+                    crypto::keyslot::Credential::Password(zeroize::Zeroizing::new(
+                        password.to_string(),
+                    )),
+                    kdf_params,
+                    None,
+                )
+                .await
+                .map_err(|e| CliError::from_crypto("Password encryption failed", e))?,
+            );
+        }
+        if !recipients.is_empty() {
+            status(tr!("encrypted-to-recipients", count = recipients.len()));
+        }
+        let encrypted = crypto::encrypt_with_keyslots(
+            &compressed_with_flag,
+            &data_key,
+            &orig_name,
+            slots,
+            chunk_size,
+        )
+        .map_err(|e| CliError::from_crypto("Recipient encryption failed", e))?;
+        sealed_key = Some(data_key);
+        encrypted
+    } else if let Some(password) = password {
+        let compressed_with_flag = encode_body(&data)?;
+        // Password-based encryption (Argon2id)
+        let mut salt = [0u8; 32];
+        rand::rngs::OsRng
+            .try_fill_bytes(&mut salt)
+            .map_err(|e| CliError::Crypto(format!("Failed to generate salt: {e}")))?;
 
-            // Get original filename for metadata
-            let orig_name = Path::new(&file)
-                .file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("file.bin");
-
-            println!("🔐 Encrypting file '{file}'...");
-
-            // Compress before encryption
-            let compressed = encode_all(&data[..], 3).map_err(|e| CliError::Crypto(format!("Compression error: {e}")))?;
-            let mut compressed_with_flag = Vec::with_capacity(1 + compressed.len());
-            compressed_with_flag.push(0x01);
-            compressed_with_flag.extend_from_slice(&compressed);
-            let encrypted = if let Some(password) = password {
-                // Password-based encryption (Argon2id)
-                let mut salt = [0u8; 32];
-                rand::rngs::OsRng
-                    .try_fill_bytes(&mut salt)
-                    .map_err(|e| CliError::Crypto(format!("Failed to generate salt: {e}")))?;
-
-                crypto::encrypt_with_password_async(&compressed_with_flag, password, orig_name, salt.to_vec())
+        let kdf_params = config
+            .kdf_profile()
+            .map_err(|e| CliError::InvalidInput(e.to_string()))?
+            .params();
+        if recovery_key || keyfile.is_some() {
+            // The password (with the keyfile, if any) and the recovery key each get a slot
+            // wrapping the same random data key
+            let data_key = crypto::keyslot::generate_data_key();
+            let credential = match &keyfile {
+                Some(keyfile) => {
+                    crypto::keyslot::Credential::TwoFactor(crypto::keyslot::TwoFactorSecret {
+                        password: zeroize::Zeroizing::new(password.to_string()),
+                        keyfile,
+                    })
+                }
+                None => crypto::keyslot::Credential::Password(zeroize::Zeroizing::new(
+                    password.to_string(),
+                )),
+            };
+            let mut slots = vec![
+                crypto::keyslot::create(&data_key, credential, kdf_params, None)
                     .await
-                    .map_err(|e| CliError::Crypto(format!("Password encryption failed: {e}")))?
-            } else {
-                // Key-based encryption (AES-256-GCM)
-                let final_key = if let Some(key) = validated_key {
-                    key
+                    .map_err(|e| CliError::from_crypto("Password encryption failed", e))?,
+            ];
+            let recovery = recovery_key.then(crypto::keyslot::generate_data_key);
+            if let Some(recovery) = &recovery {
+                slots.push(
+                    crypto::keyslot::create(
+                        &data_key,
+                        crypto::keyslot::Credential::Key(recovery.as_slice()),
+                        kdf_params,
+                        Some("recovery".to_string()),
+                    )
+                    .await
+                    .map_err(|e| CliError::from_crypto("Password encryption failed", e))?,
+                );
+            }
+            let encrypted = crypto::encrypt_with_keyslots(
+                &compressed_with_flag,
+                &data_key,
+                &orig_name,
+                slots,
+                chunk_size,
+            )
+            .map_err(|e| CliError::from_crypto("Password encryption failed", e))?;
+            sealed_key = Some(data_key);
+
+            if keyfile.is_some() {
+                status(tr!("keyfile-hint"));
+            }
+            if let Some(recovery) = &recovery {
+                let recovery_b64 =
+                    zeroize::Zeroizing::new(general_purpose::STANDARD.encode(recovery.as_slice()));
+                if let Some(path) = &recovery_out {
+                    write_secret_file(path, &format!("{}\n", *recovery_b64))?;
+                    status(tr!("recovery-key-saved", path = path));
                 } else {
-                    // Generate random key
-                    let mut k = [0u8; 32];
-                    rand::rngs::OsRng
-                        .try_fill_bytes(&mut k)
-                        .map_err(|e| CliError::Crypto(format!("Failed to generate key: {e}")))?;
-
-                    let key_b64 = general_purpose::STANDARD.encode(k);
-                    println!("✨ Generated random key (base64): {key_b64}");
-                    println!(
-                        "💡 Save this key somewhere safe! You'll need it to decrypt your file."
-                    );
-                    println!("⚠️  This key will NOT be shown again!");
-
-                    k.to_vec()
-                };
-
-                crypto::encrypt_with_header(&compressed_with_flag, &final_key, orig_name)
-                    .map_err(|e| CliError::Crypto(format!("Key encryption failed: {e}")))?
-            };
+                    show_secret(tr!("recovery-key-shown", key = *recovery_b64));
+                    show_secret(tr!("recovery-key-once"));
+                    generated_recovery_key = Some(recovery_b64.to_string());
+                }
+                status(tr!("recovery-key-hint"));
+            }
+            encrypted
+        } else {
+            crypto::encrypt_with_password_kdf_async(
+                &compressed_with_flag,
+                password.to_string(),
+                &orig_name,
+                salt.to_vec(),
+                password_kdf,
+                chunk_size,
+            )
+            .await
+            .map_err(|e| CliError::from_crypto("Password encryption failed", e))?
+        }
+    } else {
+        // Key-based encryption (AES-256-GCM)
+        let compressed_with_flag = encode_body(&data)?;
+        let final_key = if let Some(key) = validated_key {
+            key
+        } else {
+            // Generate random key
+            let mut k = SecretBuffer::new(32);
+            rand::rngs::OsRng
+                .try_fill_bytes(&mut k)
+                .map_err(|e| CliError::Crypto(format!("Failed to generate key: {e}")))?;
+
+            let key_b64 = general_purpose::STANDARD.encode(&*k);
+            show_secret(tr!("generated-key", key = key_b64));
+            show_secret(tr!("generated-key-hint"));
+            show_secret(tr!("generated-key-once"));
+            generated_key = Some(key_b64);
+
+            k
+        };
+        key_fingerprint = Some(crypto::key_fingerprint(&final_key));
 
-            // Write encrypted file
-            fs::write(&output_file, &encrypted).map_err(|e| {
+        let encrypted = if master_key {
+            crypto::encrypt_with_master_key(
+                &compressed_with_flag,
+                &final_key,
+                &orig_name,
+                chunk_size,
+            )
+        } else if convergent {
+            crypto::encrypt_convergent(&compressed_with_flag, &final_key, &orig_name, chunk_size)
+        } else {
+            crypto::encrypt_with_wrapped_key(
+                &compressed_with_flag,
+                &final_key,
+                &orig_name,
+                chunk_size,
+            )
+            .await
+        }
+        .map_err(|e| CliError::from_crypto("Key encryption failed", e))?;
+        credential_key = Some(final_key);
+        encrypted
+    };
+
+    // Sign header and ciphertext, so the signature also covers the layout and keyslots
+    let signer = sign_key
+        .as_ref()
+        .map(|key| key.verifying_key().fingerprint());
+    let encrypted = match &sign_key {
+        Some(key) => {
+            status(tr!(
+                "signing-with",
+                fingerprint = signer.as_deref().unwrap_or_default()
+            ));
+            crypto::signing::sign(&encrypted, key)
+        }
+        None => encrypted,
+    };
+
+    // Hide the result in the cover image if requested
+    let encrypted = match &cover {
+        Some(cover_path) => {
+            let cover_data = fs::read(cover_path).map_err(|e| {
                 CliError::Io(io::Error::new(
                     e.kind(),
-                    format!("Failed to write encrypted file '{output_file}': {e}"),
+                    format!("Failed to read cover image '{cover_path}': {e}"),
                 ))
             })?;
+            status(tr!("hiding-in-cover", cover = cover_path));
+            stego::embed_png(&cover_data, &encrypted).map_err(|e| match e {
+                stego::StegoError::InsufficientCapacity { .. } => {
+                    CliError::InvalidInput(e.to_string())
+                }
+                _ => CliError::Format(e.to_string()),
+            })?
+        }
+        None => encrypted,
+    };
+    // Encoded before anything is written, so an oversized file fails cleanly
+    let qr = qr
+        .map(|output| qr::Qr::for_payload(&encrypted, &output, true))
+        .transpose()?;
 
-            println!("✅ Encrypted file written to '{output_file}'");
-            println!("📊 Original size: {} bytes", data.len());
-            println!("📊 Encrypted size: {} bytes", encrypted.len());
+    // Write encrypted file
+    let input_size = data.len();
+    // In place, the original stays reachable through this handle after the rename, for wiping
+    let original = if in_place && wipe {
+        let handle = fs::OpenOptions::new().write(true).open(&output_file);
+        Some(handle.map_err(|e| {
+            CliError::Io(io::Error::new(
+                e.kind(),
+                format!("Failed to open '{output_file}' for wiping: {e}"),
+            ))
+        })?)
+    } else {
+        None
+    };
+    // What --verify opens the written file with
+    let written_key = verify.then(|| match sealed_key {
+        Some(key) => verify::WrittenKey::FileKey(key),
+        None => verify::WrittenKey::Credential {
+            password: verify_password,
+            key: credential_key.as_deref(),
+        },
+    });
+    let mut verified_regions = None;
+    if in_place {
+        // Verified before the rename, so a bad copy never replaces the original
+        let staged = StagedFile::write(&output_file, &encrypted)?;
+        if let Some(written_key) = written_key {
+            verified_regions =
+                Some(verify::verify_written(&output_file, staged.temp_path(), written_key).await?);
+        }
+        staged.commit()?;
+        status(tr!("encrypted-in-place", output = output_file));
+    } else if let Some(size) = volume_size {
+        let volumes = volume::write_volumes(&output_file, &encrypted, size, force)?;
+        status(tr!(
+            "encrypted-volumes",
+            count = volumes.len(),
+            first = volumes[0],
+            last = volumes[volumes.len() - 1]
+        ));
+    } else {
+        write_output(&output_file, &encrypted, "encrypted file")?;
+        if !to_stdout {
+            status(tr!("encrypted-written", output = output_file));
+        }
+        if let Some(written_key) = written_key {
+            let path = Path::new(&output_file);
+            match verify::verify_written(&output_file, path, written_key).await {
+                Ok(regions) => verified_regions = Some(regions),
+                Err(e) => {
+                    // Nobody should rely on a file that doesn't decrypt
+                    let _ = fs::remove_file(path);
+                    return Err(e);
+                }
+            }
+        }
+    }
+    if let Some(regions) = verified_regions {
+        status(tr!("verified-regions", regions = regions));
+    }
+    // Only wiped once the encrypted file is in place (and verified, if asked)
+    drop(data);
+    if let Some(mut original) = original {
+        let wiped = shred::overwrite(&mut original).map_err(|e| {
+            CliError::Io(io::Error::new(
+                e.kind(),
+                format!("'{output_file}' was encrypted, but wiping the original failed: {e}"),
+            ))
+        })?;
+        status(tr!("wiped-original", bytes = wiped));
+        for caveat in shred::caveats(Path::new(&output_file)) {
+            status(tr!("caveat", caveat = caveat));
+        }
+    }
+    if let Some(target) = &shred_target {
+        let caveats = shred::caveats(Path::new(target));
+        let wiped = shred::shred(Path::new(target)).map_err(|e| {
+            CliError::Io(io::Error::new(
+                e.kind(),
+                format!("'{source}' was encrypted, but shredding it failed: {e}"),
+            ))
+        })?;
+        status(tr!("shredded-original", bytes = wiped, file = source));
+        for caveat in caveats {
+            status(tr!("caveat", caveat = caveat));
+        }
+    }
+    if bundle_readme {
+        let readme = bundle::write_readme(&output_file, &encrypted, format, mode == "password")?;
+        status(tr!("readme-written", path = readme));
+    }
+    if let Some(qr) = &qr {
+        // Shown alongside the other status lines, never mixed into the output
+        qr.show(true)?;
+        if let Some(path) = qr.png_path() {
+            status(tr!("qr-written", path = path));
+        }
+    }
+    status(tr!("original-size", bytes = input_size));
+    status(tr!("encrypted-size", bytes = encrypted.len()));
+
+    Ok(CommandReport {
+        operation: "encrypt",
+        mode: mode.to_string(),
+        input: source,
+        output: output_file,
+        input_size,
+        output_size: encrypted.len(),
+        key_fingerprint,
+        generated_key,
+        recovery_key: generated_recovery_key,
+        delta,
+        signer,
+        compression_level: compress.then_some(level),
+        duration_ms: started.elapsed().as_millis(),
+    })
+}
+
+/// Handles the `decrypt` subcommand.
+async fn decrypt_command(
+    args: DecryptArgs,
+    config: &Config,
+    json: bool,
+) -> Result<CommandReport, CliError> {
+    let started = Instant::now();
+    let DecryptArgs {
+        file,
+        password,
+        keyfile,
+        quorum_keys,
+        key,
+        key_name,
+        shares,
+        credentials,
+        identity,
+        pgp_key,
+        token,
+        token_device,
+        pkcs11,
+        output,
+        untar_to,
+        signer,
+        force,
+        refuse_binary_tty,
+    } = args;
+    let password = password.map(zeroize::Zeroizing::new);
+    let key = if !shares.is_empty() {
+        Some(key::key_from_shares(&shares)?.to_string())
+    } else if !credentials.is_empty() {
+        Some(key::key_from_parts(&credentials)?.to_string())
+    } else if let Some(name) = key_name {
+        Some(keystore::load_key(&name, config).await?.to_string())
+    } else {
+        key
+    };
+    let keyfile = keyfile.as_deref().map(read_keyfile).transpose()?;
+    let quorum_keys = quorum_keys
+        .iter()
+        .map(|key| validate_key(key))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut identities = identity::read_identities(&identity)?;
+    if pkcs11.pkcs11_key_id.is_some() {
+        return Err(CliError::InvalidInput(
+            "--pkcs11-key-id is only used by encrypt; the file records its key ID".to_string(),
+        ));
+    }
+    let hsm = pkcs11::Hsm::from_args(&pkcs11)?;
+    if hsm.is_some() && (password.is_some() || key.is_some() || !identities.is_empty() || token) {
+        return Err(CliError::InvalidInput(
+            "--pkcs11-module cannot be combined with another credential".to_string(),
+        ));
+    }
+
+    // Validate that either password or key is provided (not both)
+    if password.is_some() && key.is_some() {
+        return Err(CliError::InvalidInput(
+            "Cannot specify both password and key. Choose one.".to_string(),
+        ));
+    }
+    let no_credential = password.is_none()
+        && key.is_none()
+        && quorum_keys.is_empty()
+        && identities.is_empty()
+        && pgp_key.is_none()
+        && !token
+        && hsm.is_none();
+
+    // Validate key if provided
+    let validated_key = if let Some(ref key_str) = key {
+        Some(validate_key(key_str)?)
+    } else {
+        None
+    };
+
+    let file = file.unwrap_or_else(|| STDIO_PATH.to_string());
+    let to_stdout = output.as_deref() == Some(STDIO_PATH);
+    if to_stdout && json {
+        return Err(CliError::InvalidInput(
+            "--json cannot be combined with writing data to stdout".to_string(),
+        ));
+    }
+
+    // Read encrypted file, joining its volumes or unwrapping it from a cover image if needed
+    let (file, data) = match volume::volume_base(&file) {
+        Some(base) => {
+            let (joined, count) = volume::join(&base)?;
+            status(tr!("joined-volumes", count = count, base = base));
+            (base, Input::from(joined))
+        }
+        None => {
+            let data = read_input(&file, "encrypted file")?;
+            (file, data)
+        }
+    };
+    let data = unwrap_input(&file, data)?;
+
+    // Files encrypted to recipients open with the identities file, if there is one
+    let default_identities = config
+        .identities_path()
+        .filter(|path| no_credential && path.exists() && identity::encrypted_to_recipients(&data))
+        .map(|path| path.to_string_lossy().into_owned());
+    if let Some(path) = &default_identities {
+        identities = identity::read_identities(std::slice::from_ref(path))?;
+        status(tr!("using-identities", path = path));
+    }
+    let no_credential = no_credential && identities.is_empty();
+
+    // Files opened recently, while an agent is running, open with the key it kept
+    let cached_key = if no_credential {
+        XdReader::parse(&data)
+            .ok()
+            .and_then(|file| agent::cached_file_key(&file))
+    } else {
+        None
+    };
+    let no_credential = no_credential && cached_key.is_none();
+
+    // Files sealed to this machine's TPM or wrapped by a KMS open without a credential
+    let tpm = no_credential && tpm::sealed_to_tpm(&data);
+    let kms = no_credential && !tpm && kms::wrapped_by_kms(&data);
+    if no_credential && !tpm && !kms {
+        return Err(CliError::InvalidInput(
+            "Must specify a password, key, identity, token or PKCS#11 module for decryption."
+                .to_string(),
+        ));
+    }
+
+    if file == STDIO_PATH {
+        status(tr!("decrypting-stdin"));
+    } else {
+        status(tr!("decrypting-file", file = file));
+    }
+
+    // Password-protected ZIP/7z archives from other tools are extracted directly
+    if let Some(kind) = interop::archive::detect(&data) {
+        let dest = untar_to
+            .or(output)
+            .or_else(|| {
+                config
+                    .output_dir
+                    .as_ref()
+                    .map(|d| d.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| ".".to_string());
+        let password = password.filter(|_| keyfile.is_none()).ok_or_else(|| {
+            CliError::InvalidInput(format!(
+                "{} archives can only be decrypted with --password",
+                kind.name()
+            ))
+        })?;
+        let max_size = config
+            .max_decompressed_size()
+            .map_err(|e| CliError::InvalidInput(e.to_string()))?;
+        let output_size = extract_archive(kind, &data, &password, &dest, force, max_size)?;
+        return Ok(CommandReport {
+            operation: "decrypt",
+            mode: kind.name().to_string(),
+            input: file,
+            output: dest,
+            input_size: data.len(),
+            output_size,
+            key_fingerprint: None,
+            generated_key: None,
+            recovery_key: None,
+            delta: None,
+            signer: None,
+            compression_level: None,
+            duration_ms: started.elapsed().as_millis(),
+        });
+    }
+
+    // OpenPGP messages from gpg open with a password or a PGP secret key
+    let pgp_message = interop::openpgp::detect(&data);
+    if pgp_message
+        && (validated_key.is_some()
+            || keyfile.is_some()
+            || !quorum_keys.is_empty()
+            || !identities.is_empty()
+            || token
+            || hsm.is_some())
+    {
+        return Err(CliError::InvalidInput(
+            "OpenPGP messages can only be decrypted with --password or --pgp-key".to_string(),
+        ));
+    }
+    if !pgp_message && pgp_key.is_some() {
+        return Err(CliError::InvalidInput(format!(
+            "'{file}' is not an OpenPGP message; --pgp-key only applies to those"
+        )));
+    }
+
+    // A signature is checked before anything is decrypted, and must be valid if present
+    let expected_signer = signer
+        .as_deref()
+        .map(identity::read_verifying_key)
+        .transpose()?;
+    let signer = if pgp_message {
+        None
+    } else {
+        crypto::signing::verify(&data)
+            .map_err(|e| CliError::from_crypto("Signature check failed", e))?
+    };
+    match (&expected_signer, &signer) {
+        (Some(expected), Some(actual)) if expected != actual => {
+            return Err(CliError::Authentication(format!(
+                "'{file}' is signed by {}, not by the expected key {}",
+                actual.fingerprint(),
+                expected.fingerprint()
+            )));
+        }
+        (Some(_), None) => {
+            return Err(CliError::Authentication(format!("'{file}' is not signed")));
+        }
+        _ => {}
+    }
+    if let Some(signer) = &signer {
+        status(tr!("signature-valid", fingerprint = signer.fingerprint()));
+    }
+    let signer = signer.map(|key| key.fingerprint());
+
+    // Perform decryption
+    let mode = if pgp_message {
+        "pgp"
+    } else if !identities.is_empty() {
+        "recipients"
+    } else if cached_key.is_some() {
+        "agent"
+    } else if token {
+        "token"
+    } else if hsm.is_some() {
+        "pkcs11"
+    } else if tpm {
+        "tpm"
+    } else if kms {
+        "kms"
+    } else if keyfile.is_some() {
+        "two-factor"
+    } else if !quorum_keys.is_empty() {
+        "quorum"
+    } else if password.is_some() {
+        "password"
+    } else {
+        "key"
+    };
+    let key_fingerprint = validated_key.as_deref().map(crypto::key_fingerprint);
+    let (decrypted, orig_filename) = if pgp_message {
+        let (plaintext, name) = pgp::decrypt_message(
+            &data,
+            password.as_deref().map(String::as_str),
+            pgp_key.as_deref(),
+        )?;
+        // A name stored by a foreign tool: keep only its last component
+        let name = Path::new(&name)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| pgp_output_name(&file));
+        (plaintext, name)
+    } else if !identities.is_empty() {
+        crypto::decrypt_with_identities(&data, &identities)
+            .map_err(|e| CliError::from_crypto("Identity decryption failed", e))?
+    } else if let Some(key) = &cached_key {
+        let parsed = XdReader::parse(&data)
+            .map_err(|e| CliError::from_crypto("Agent decryption failed", e))?;
+        let plaintext = crypto::decrypt_parsed(&parsed, key)
+            .map_err(|e| CliError::from_crypto("Agent decryption failed", e))?;
+        (plaintext, parsed.header.filename().to_string())
+    } else if token {
+        let device = token::pick_device(token_device.as_deref())?;
+        crypto::decrypt_with_token(&data, token::asker(&device))
+            .map_err(|e| CliError::from_crypto("Token decryption failed", e))?
+    } else if let Some(hsm) = &hsm {
+        crypto::decrypt_with_hsm(&data, hsm.asker())
+            .map_err(|e| CliError::from_crypto("PKCS#11 decryption failed", e))?
+    } else if tpm {
+        crypto::decrypt_with_tpm(&data, tpm::asker())
+            .map_err(|e| CliError::from_crypto("TPM decryption failed", e))?
+    } else if kms {
+        crypto::decrypt_with_kms(&data, kms::asker())
+            .map_err(|e| CliError::from_crypto("KMS decryption failed", e))?
+    } else if !quorum_keys.is_empty() {
+        // Too few keys can't work, which is worth saying before trying them
+        let threshold = XdReader::parse(&data)
+            .ok()
+            .and_then(|file| crypto::keyslot::quorum_threshold(file.header.keyslots()?.0));
+        if let Some(threshold) = threshold.filter(|&t| quorum_keys.len() < t as usize) {
+            return Err(CliError::InvalidInput(format!(
+                "'{file}' needs {threshold} quorum keys, but {} were given",
+                quorum_keys.len()
+            )));
+        }
+        let keys: Vec<&[u8]> = quorum_keys.iter().map(|key| key.as_slice()).collect();
+        crypto::decrypt_with_quorum(&data, &keys)
+            .map_err(|e| CliError::from_crypto("Quorum decryption failed", e))?
+    } else if let (Some(password), Some(keyfile)) = (&password, &keyfile) {
+        crypto::decrypt_with_password_and_keyfile(&data, password.to_string(), keyfile)
+            .await
+            .map_err(|e| CliError::from_crypto("Two-factor decryption failed", e))?
+    } else if let Some(password) = password {
+        // Password-based decryption; a running agent keeps the file key
+        agent::decrypt_with_password(&data, password.to_string())
+            .await
+            .map_err(|e| CliError::from_crypto("Password decryption failed", e))?
+    } else {
+        // Key-based decryption
+        let key_ref = validated_key.as_deref();
+        crypto::decrypt_with_header(&data, key_ref)
+            .map_err(|e| CliError::from_crypto("Key decryption failed", e))?
+    };
+
+    // Remove padding and decompress after decryption if needed
+    let compression_level = compression::recorded_level(&decrypted);
+    let output_bytes = if pgp_message {
+        zeroize::Zeroizing::new(decrypted)
+    } else {
+        // Undoes `compress_body` and `--pad`
+        let max_size = config
+            .max_decompressed_size()
+            .map_err(|e| CliError::InvalidInput(e.to_string()))?;
+        api::decode_body_with_limit(decrypted, max_size)
+            .map_err(|e| CliError::from_api("Decryption failed", e))?
+    };
 
+    let report = |output: String| CommandReport {
+        operation: "decrypt",
+        mode: mode.to_string(),
+        input: file.clone(),
+        output,
+        input_size: data.len(),
+        output_size: output_bytes.len(),
+        key_fingerprint: key_fingerprint.clone(),
+        generated_key: None,
+        recovery_key: None,
+        delta: None,
+        signer: signer.clone(),
+        compression_level,
+        duration_ms: started.elapsed().as_millis(),
+    };
+
+    if let Some(target) = untar_to {
+        let count = tarball::unpack_to(&output_bytes, &target, force)?;
+        status(tr!("extracted-entries", count = count, target = target));
+        return Ok(report(target));
+    }
+
+    if refuse_binary_tty && !force && io::stdout().is_terminal() && cat::looks_binary(&output_bytes)
+    {
+        return Err(CliError::InvalidInput(format!(
+            "'{file}' decrypts to binary data; pipe it elsewhere, or use --force to print it anyway"
+        )));
+    }
+
+    // Determine output file; anonymous files have no name to fall back on
+    let output_file = match output {
+        Some(output) => output,
+        None if orig_filename.is_empty() => {
+            return Err(CliError::InvalidInput(
+                "The file records no filename (--no-metadata); specify --output".to_string(),
+            ));
+        }
+        None => config.output_path(&orig_filename),
+    };
+
+    // Check output file
+    if !to_stdout {
+        check_output_file(&output_file, force)?;
+    }
+
+    // Write decrypted file
+    write_output(&output_file, &output_bytes, "decrypted file")?;
+
+    if !to_stdout {
+        status(tr!("decrypted-written", output = output_file));
+    }
+    status(tr!("decrypted-size", bytes = output_bytes.len()));
+
+    Ok(report(output_file))
+}
+
+/// Output name for an OpenPGP message that stores no file name: the input without its
+/// `.gpg`/`.pgp`/`.asc` extension, like gpg uses.
+fn pgp_output_name(file: &str) -> String {
+    let path = Path::new(file);
+    let stripped = match path.extension().and_then(|e| e.to_str()) {
+        Some("gpg" | "pgp" | "asc") => path.file_stem(),
+        _ => None,
+    };
+    stripped
+        .and_then(|n| n.to_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| "file.bin".to_string())
+}
+
+/// Extracts a password-protected ZIP or 7z archive into `dest`.
+#[cfg(feature = "archive-ingest")]
+fn extract_archive(
+    kind: interop::archive::ArchiveKind,
+    data: &[u8],
+    password: &str,
+    dest: &str,
+    force: bool,
+    max_size: u64,
+) -> Result<usize, CliError> {
+    let entries =
+        interop::archive::read_entries(kind, data, password, max_size).map_err(|e| match e {
+            interop::InteropError::WrongPassword => {
+                CliError::Authentication(format!("{} extraction failed: {e}", kind.name()))
+            }
+            interop::InteropError::TooLarge { .. } => CliError::Format(format!(
+                "{} extraction failed: {e}; raise max_decompressed_size to allow it",
+                kind.name()
+            )),
+            _ => CliError::Format(format!("{} extraction failed: {e}", kind.name())),
+        })?;
+    interop::archive::write_entries(&entries, Path::new(dest), force)
+        .map_err(|e| CliError::InvalidInput(e.to_string()))?;
+    status(tr!(
+        "extracted-archive",
+        count = entries.len(),
+        kind = kind.name(),
+        target = dest
+    ));
+    Ok(entries.iter().map(|e| e.data.len()).sum())
+}
+
+/// Reports that archive extraction was not compiled in.
+#[cfg(not(feature = "archive-ingest"))]
+fn extract_archive(
+    kind: interop::archive::ArchiveKind,
+    _data: &[u8],
+    _password: &str,
+    _dest: &str,
+    _force: bool,
+    _max_size: u64,
+) -> Result<usize, CliError> {
+    Err(CliError::Format(format!(
+        "Input is a {} archive, but this build has no archive support. Rebuild with --features archive-ingest",
+        kind.name()
+    )))
+}
+
+/// Runs the CLI. Returns Ok(true) if a CLI command was run, Ok(false) if not.
+///
+/// - Encrypt: Writes <basename>.xd as output. Prints random key if generated (copy it somewhere safe!).
+/// - Decrypt: Writes the original filename from the encrypted file header.
+/// - Returns Ok(false) if no CLI command is given (so the server can start).
+///
+/// # Security Note
+/// If you forget your password or key, not even we can help you. That's real security!
+pub async fn run_cli() -> Result<bool, CliError> {
+    let cli = Cli::parse();
+    let config = cli
+        .load_config()
+        .map_err(|e| CliError::InvalidInput(e.to_string()))?;
+    run(cli, &config).await
+}
+
+/// Runs an already parsed command line with the given configuration.
+///
+/// Same contract as [`run_cli`]; used by the server binary, which needs the
+/// configuration itself when no subcommand is given.
+pub async fn run(cli: Cli, config: &Config) -> Result<bool, CliError> {
+    crypto::chunked::set_threads(config.threads);
+    crypto::set_kdf_threads(config.threads);
+    locale::init(cli.locale.as_deref()).map_err(CliError::InvalidInput)?;
+    // Without a subcommand the server runs, and installs its own subscriber
+    if cli.command.is_some() || cli.check_config {
+        logging::init_cli(cli.log_level(), cli.log_file.as_deref()).map_err(|e| {
+            CliError::Io(io::Error::new(
+                e.kind(),
+                format!("Failed to open the log file: {e}"),
+            ))
+        })?;
+    }
+    if cli.check_config {
+        if cli.command.is_some() {
+            return Err(CliError::InvalidInput(
+                "--check-config cannot be combined with a subcommand".to_string(),
+            ));
+        }
+        check_config::check_config_command(config, cli.config_path(), cli.json)?;
+        return Ok(true);
+    }
+    if let Some(remote) = &cli.remote {
+        let report = match cli.command {
+            Some(Commands::Encrypt(mut args)) => {
+                if !batch::take_single_input(&mut args) {
+                    return Err(CliError::InvalidInput(
+                        "--remote encrypts one file at a time".to_string(),
+                    ));
+                }
+                remote::encrypt_command(*args, remote, config, cli.json).await?
+            }
+            Some(Commands::Decrypt(args)) => {
+                remote::decrypt_command(args, remote, config, cli.json).await?
+            }
+            _ => {
+                return Err(CliError::InvalidInput(
+                    "--remote only works with encrypt and decrypt".to_string(),
+                ));
+            }
+        };
+        if cli.json {
+            print_json_report(&report);
+        }
+        return Ok(true);
+    }
+    match cli.command {
+        Some(Commands::Encrypt(args)) if args.dir.is_some() => {
+            tree::encrypt_dir(*args, config, cli.json).await?;
+            Ok(true)
+        }
+        Some(Commands::Encrypt(mut args)) => {
+            if !batch::take_single_input(&mut args) {
+                batch::encrypt_batch(*args, config, cli.json).await?;
+                return Ok(true);
+            }
+            let report = encrypt_command(*args, config, cli.json).await?;
+            if cli.json {
+                print_json_report(&report);
+            }
             Ok(true)
         }
 
-        Some(Commands::Decrypt {
+        Some(Commands::Decrypt(args)) => {
+            let report = decrypt_command(args, config, cli.json).await?;
+            if cli.json {
+                print_json_report(&report);
+            }
+            Ok(true)
+        }
+
+        Some(Commands::Cat(args)) => {
+            cat::cat_command(args, config, cli.json).await?;
+            Ok(true)
+        }
+
+        Some(Commands::Watch(args)) => {
+            watch::watch_command(args, config, cli.json).await?;
+            Ok(true)
+        }
+
+        Some(Commands::Inspect { file }) => {
+            inspect::inspect_command(&file, cli.json)?;
+            Ok(true)
+        }
+
+        Some(Commands::Rekey(args)) => {
+            let report = rekey::rekey_command(args, config, cli.json).await?;
+            if cli.json {
+                print_json_report(&report);
+            }
+            Ok(true)
+        }
+
+        Some(Commands::Upgrade(args)) => {
+            upgrade::upgrade_command(args, config, cli.json).await?;
+            Ok(true)
+        }
+
+        Some(Commands::Backup(args)) => {
+            let report = backup::backup_command(args, config).await?;
+            if cli.json {
+                print_json_report(&report);
+            }
+            Ok(true)
+        }
+
+        Some(Commands::Restore(args)) => {
+            restore::restore_command(args, config, cli.json).await?;
+            Ok(true)
+        }
+
+        Some(Commands::Verify {
             file,
             password,
             key,
+        }) => {
+            verify::verify_command(&file, password, key, cli.json).await?;
+            Ok(true)
+        }
+
+        Some(Commands::VerifyManifest {
+            manifest,
+            dir,
+            password,
+            key,
+            key_name,
+        }) => {
+            let key = match key_name {
+                Some(name) => Some(keystore::load_key(&name, config).await?.to_string()),
+                None => key,
+            };
+            manifest::verify_manifest_command(&manifest, &dir, password, key, cli.json).await?;
+            Ok(true)
+        }
+
+        Some(Commands::Mount {
+            source,
+            mountpoint,
+            password,
+            key,
+            key_name,
+        }) => {
+            let key = match key_name {
+                Some(name) => Some(keystore::load_key(&name, config).await?.to_string()),
+                None => key,
+            };
+            mount::mount_command(&source, &mountpoint, password, key, config).await?;
+            Ok(true)
+        }
+
+        Some(Commands::Webdav {
+            source,
+            listen,
+            allow_remote,
+            password,
+            key,
+            key_name,
+        }) => {
+            let key = match key_name {
+                Some(name) => Some(keystore::load_key(&name, config).await?.to_string()),
+                None => key,
+            };
+            webdav::webdav_command(&source, &listen, allow_remote, password, key, config).await?;
+            Ok(true)
+        }
+
+        Some(Commands::Sign {
+            file,
+            key,
             output,
             force,
         }) => {
-            // Validate input file
-            validate_input_file(&file)?;
+            sign::sign_command(&file, &key, output, force, cli.json)?;
+            Ok(true)
+        }
 
-            // Validate that either password or key is provided (not both)
-            match (&password, &key) {
-                (Some(_), Some(_)) => {
-                    return Err(CliError::InvalidInput(
-                        "Cannot specify both password and key. Choose one.".to_string(),
-                    ));
-                }
-                (None, None) => {
-                    return Err(CliError::InvalidInput(
-                        "Must specify either password or key for decryption.".to_string(),
-                    ));
-                }
-                _ => {} // One of them is provided, which is correct
+        Some(Commands::VerifySig {
+            file,
+            signature,
+            pubkey,
+        }) => {
+            sign::verify_sig_command(&file, &signature, &pubkey, cli.json)?;
+            Ok(true)
+        }
+
+        Some(Commands::Join {
+            file,
+            output,
+            force,
+        }) => {
+            volume::join_command(&file, output, force, cli.json)?;
+            Ok(true)
+        }
+
+        Some(Commands::Salvage(args)) => {
+            let report = salvage::salvage_command(args, config, cli.json).await?;
+            if cli.json {
+                print_json_report(&report);
             }
+            Ok(true)
+        }
 
-            // Validate key if provided
-            let validated_key = if let Some(ref key_str) = key {
-                Some(validate_key(key_str)?)
-            } else {
-                None
-            };
+        Some(Commands::FixHeader(args)) => {
+            fix_header::fix_header_command(args, cli.json).await?;
+            Ok(true)
+        }
 
-            // Read encrypted file
-            let data = fs::read(&file).map_err(|e| {
-                CliError::Io(io::Error::new(
-                    e.kind(),
-                    format!("Failed to read encrypted file '{file}': {e}"),
-                ))
-            })?;
+        Some(Commands::ReplayCorpus { dir }) => {
+            replay::replay_command(&dir, cli.json)?;
+            Ok(true)
+        }
 
-            println!("🔓 Decrypting file '{file}'...");
+        Some(Commands::Capabilities) => {
+            capabilities::capabilities_command(config, cli.json);
+            Ok(true)
+        }
 
-            // Perform decryption
-            let (decrypted, orig_filename) = if let Some(password) = password {
-                // Password-based decryption
-                crypto::decrypt_with_password_async(&data, password)
-                    .await
-                    .map_err(|e| CliError::Crypto(format!("Password decryption failed: {e}")))?
-            } else {
-                // Key-based decryption
-                let key_ref = validated_key.as_deref();
-                crypto::decrypt_with_header(&data, key_ref)
-                    .map_err(|e| CliError::Crypto(format!("Key decryption failed: {e}")))?
-            };
+        Some(Commands::Key { action }) => {
+            match action {
+                KeyAction::Split {
+                    key,
+                    shares,
+                    threshold,
+                } => key::split_command(&key, shares, threshold, cli.json)?,
+                KeyAction::Combine { shares } => key::combine_command(&shares, cli.json)?,
+                KeyAction::Add { name, key, force } => {
+                    keystore::add_command(&name, key.as_deref(), force, config, cli.json).await?
+                }
+                KeyAction::List => keystore::list_command(config, cli.json).await?,
+                KeyAction::Export { name } => {
+                    keystore::export_command(&name, config, cli.json).await?
+                }
+                KeyAction::Rm { name } => keystore::remove_command(&name, config, cli.json).await?,
+                KeyAction::Paper {
+                    key,
+                    key_name,
+                    output,
+                    force,
+                } => {
+                    let key = match key_name {
+                        Some(name) => keystore::load_key(&name, config).await?,
+                        None => zeroize::Zeroizing::new(key.unwrap_or_default()),
+                    };
+                    key::paper_command(&key, &output, force, cli.json)?
+                }
+                KeyAction::RecoverPaper { input } => key::recover_paper_command(&input, cli.json)?,
+                KeyAction::Qr {
+                    key,
+                    key_name,
+                    output,
+                    force,
+                } => {
+                    let key = match key_name {
+                        Some(name) => keystore::load_key(&name, config).await?,
+                        None => zeroize::Zeroizing::new(key.unwrap_or_default()),
+                    };
+                    qr::key_qr_command(&key, &output, force, cli.json)?
+                }
+            }
+            Ok(true)
+        }
 
-            // Determine output file
-            let output_file = output.unwrap_or(orig_filename);
+        Some(Commands::Clip { action }) => {
+            match action {
+                ClipAction::Encrypt(credentials) => {
+                    clip::encrypt_command(credentials, config, cli.json).await?
+                }
+                ClipAction::Decrypt {
+                    credentials,
+                    clear_after,
+                } => clip::decrypt_command(credentials, clear_after, config, cli.json).await?,
+                ClipAction::Clear { after } => clip::clear_command(after)?,
+            }
+            Ok(true)
+        }
 
-            // Check output file
-            check_output_file(&output_file, force)?;
+        Some(Commands::Text { action }) => {
+            match action {
+                TextAction::Encrypt {
+                    text,
+                    credentials,
+                    output,
+                    force,
+                    binary,
+                } => {
+                    text::encrypt_command(
+                        text,
+                        credentials,
+                        &output,
+                        force,
+                        binary,
+                        config,
+                        cli.json,
+                    )
+                    .await?
+                }
+                TextAction::Decrypt {
+                    message,
+                    credentials,
+                    output,
+                    force,
+                } => {
+                    text::decrypt_command(message, credentials, &output, force, config, cli.json)
+                        .await?
+                }
+            }
+            Ok(true)
+        }
 
-            // Write decrypted file
-            // Decompress after decryption if needed
-            let output_bytes = if decrypted.first() == Some(&0x01) {
-                decode_all(&decrypted[1..]).map_err(|e| CliError::Crypto(format!("Decompression error: {e}")))?
-            } else {
-                decrypted
+        Some(Commands::Identity { action }) => {
+            match action {
+                IdentityAction::Generate {
+                    output,
+                    post_quantum,
+                    signing,
+                    force,
+                } => identity::generate_command(&output, post_quantum, signing, force, cli.json)?,
+            }
+            Ok(true)
+        }
+
+        Some(Commands::Token { action }) => {
+            match action {
+                TokenAction::List => token::list_command(cli.json)?,
+                TokenAction::Enroll {
+                    output,
+                    token_device,
+                    force,
+                } => token::enroll_command(&output, token_device.as_deref(), force, cli.json)?,
+            }
+            Ok(true)
+        }
+
+        Some(Commands::Keyslot { action }) => {
+            let report = match action {
+                KeyslotAction::Add(args) => keyslot::add_command(args, config, cli.json).await?,
+                KeyslotAction::Remove(args) => keyslot::remove_command(args, cli.json).await?,
             };
-            fs::write(&output_file, &output_bytes).map_err(|e| {
-                CliError::Io(io::Error::new(
-                    e.kind(),
-                    format!("Failed to write decrypted file '{output_file}': {e}"),
-                ))
-            })?;
+            if cli.json {
+                print_json_report(&report);
+            }
+            Ok(true)
+        }
 
-            println!("✅ Decrypted file written to '{output_file}'");
-            println!("📊 Decrypted size: {} bytes", output_bytes.len());
+        Some(Commands::Agent { action }) => {
+            match action {
+                AgentAction::Start { ttl } => {
+                    agent::start_command(ttl.as_deref(), config, cli.json).await?
+                }
+                AgentAction::Status => agent::status_command(cli.json)?,
+                AgentAction::Clear => agent::forget_command(false, cli.json)?,
+                AgentAction::Stop => agent::forget_command(true, cli.json)?,
+            }
+            Ok(true)
+        }
 
+        Some(Commands::Hook { action }) => {
+            match action {
+                HookAction::Install { patterns, force } => hook::install(&patterns, force)?,
+                HookAction::Check => hook::check()?,
+            }
             Ok(true)
         }
 
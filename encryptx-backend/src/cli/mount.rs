@@ -0,0 +1,282 @@
+//!
+//! `mount` subcommand: a read-only FUSE view of an encrypted archive or of a directory
+//! written by `encrypt --dir` (see [`View`]), so its files can be browsed and opened
+//! without extracting them. Only built with the `fuse` feature, on Unix.
+//!
+use super::CliError;
+#[cfg(all(unix, feature = "fuse"))]
+use super::view::{NodeKind, Unavailable, View};
+#[cfg(all(unix, feature = "fuse"))]
+use super::{status, validate_key};
+#[cfg(all(unix, feature = "fuse"))]
+use crate::config::Config;
+#[cfg(all(unix, feature = "fuse"))]
+use fuser::{
+    Errno, FileAttr, FileHandle, FileType, Filesystem, Generation, INodeNo, LockOwner, MountOption,
+    OpenFlags, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, Request,
+};
+#[cfg(all(unix, feature = "fuse"))]
+use std::ffi::OsStr;
+#[cfg(all(unix, feature = "fuse"))]
+use std::io;
+#[cfg(all(unix, feature = "fuse"))]
+use std::path::Path;
+#[cfg(all(unix, feature = "fuse"))]
+use std::time::Duration;
+#[cfg(all(unix, feature = "fuse"))]
+use zeroize::Zeroizing;
+
+/// How long the kernel may cache attributes and lookups; nothing changes while mounted.
+#[cfg(all(unix, feature = "fuse"))]
+const TTL: Duration = Duration::from_secs(60);
+
+/// The mounted filesystem. FUSE calls come in on their own threads, which wait on
+/// `runtime` for files that have to be decrypted first.
+#[cfg(all(unix, feature = "fuse"))]
+struct EncryptedFs {
+    view: View,
+    runtime: tokio::runtime::Handle,
+    uid: u32,
+    gid: u32,
+}
+
+#[cfg(all(unix, feature = "fuse"))]
+impl From<Unavailable> for Errno {
+    fn from(e: Unavailable) -> Self {
+        match e {
+            Unavailable::NotFound => Errno::ENOENT,
+            Unavailable::NotAFile => Errno::EISDIR,
+            Unavailable::Failed => Errno::EIO,
+        }
+    }
+}
+
+#[cfg(all(unix, feature = "fuse"))]
+impl EncryptedFs {
+    /// Runs `f` on the contents of a file.
+    fn with_contents<R>(&self, ino: u64, f: impl FnOnce(&[u8]) -> R) -> Result<R, Errno> {
+        let contents = self.runtime.block_on(self.view.contents(ino))?;
+        Ok(f(&contents))
+    }
+
+    fn attr(&self, ino: u64) -> Result<FileAttr, Errno> {
+        let node = self.view.node(ino).ok_or(Errno::ENOENT)?;
+        let (kind, size, nlink) = match &node.kind {
+            NodeKind::Dir(_) => (FileType::Directory, 0, 2),
+            NodeKind::Symlink(target) => (FileType::Symlink, target.as_os_str().len() as u64, 1),
+            NodeKind::File(_) | NodeKind::Encrypted(_) => (
+                FileType::RegularFile,
+                self.with_contents(ino, |data| data.len() as u64)?,
+                1,
+            ),
+        };
+        Ok(FileAttr {
+            ino: INodeNo(ino),
+            size,
+            blocks: size.div_ceil(512),
+            atime: node.mtime,
+            mtime: node.mtime,
+            ctime: node.mtime,
+            crtime: node.mtime,
+            kind,
+            perm: node.perm,
+            nlink,
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            flags: 0,
+            blksize: 4096,
+        })
+    }
+}
+
+#[cfg(all(unix, feature = "fuse"))]
+impl Filesystem for EncryptedFs {
+    fn lookup(&self, _req: &Request, parent: INodeNo, name: &OsStr, reply: ReplyEntry) {
+        let attr = self
+            .view
+            .lookup(parent.0, name)
+            .ok_or(Errno::ENOENT)
+            .and_then(|ino| self.attr(ino));
+        match attr {
+            Ok(attr) => reply.entry(&TTL, &attr, Generation(0)),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn getattr(&self, _req: &Request, ino: INodeNo, _fh: Option<FileHandle>, reply: ReplyAttr) {
+        match self.attr(ino.0) {
+            Ok(attr) => reply.attr(&TTL, &attr),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn readlink(&self, _req: &Request, ino: INodeNo, reply: ReplyData) {
+        match self.view.node(ino.0).map(|node| &node.kind) {
+            Some(NodeKind::Symlink(target)) => {
+                use std::os::unix::ffi::OsStrExt;
+                reply.data(target.as_os_str().as_bytes())
+            }
+            Some(_) => reply.error(Errno::EINVAL),
+            None => reply.error(Errno::ENOENT),
+        }
+    }
+
+    fn read(
+        &self,
+        _req: &Request,
+        ino: INodeNo,
+        _fh: FileHandle,
+        offset: u64,
+        size: u32,
+        _flags: OpenFlags,
+        _lock_owner: Option<LockOwner>,
+        reply: ReplyData,
+    ) {
+        let result = self.with_contents(ino.0, |data| {
+            let start = usize::try_from(offset)
+                .unwrap_or(usize::MAX)
+                .min(data.len());
+            let end = start.saturating_add(size as usize).min(data.len());
+            Zeroizing::new(data[start..end].to_vec())
+        });
+        match result {
+            Ok(data) => reply.data(&data),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn readdir(
+        &self,
+        _req: &Request,
+        ino: INodeNo,
+        _fh: FileHandle,
+        offset: u64,
+        mut reply: ReplyDirectory,
+    ) {
+        let Some(node) = self.view.node(ino.0) else {
+            reply.error(Errno::ENOENT);
+            return;
+        };
+        let NodeKind::Dir(children) = &node.kind else {
+            reply.error(Errno::ENOTDIR);
+            return;
+        };
+        let mut entries = vec![
+            (ino.0, FileType::Directory, OsStr::new(".")),
+            (node.parent, FileType::Directory, OsStr::new("..")),
+        ];
+        for &child in children {
+            let Some(child_node) = self.view.node(child) else {
+                continue;
+            };
+            let kind = match child_node.kind {
+                NodeKind::Dir(_) => FileType::Directory,
+                NodeKind::Symlink(_) => FileType::Symlink,
+                NodeKind::File(_) | NodeKind::Encrypted(_) => FileType::RegularFile,
+            };
+            entries.push((child, kind, child_node.name.as_os_str()));
+        }
+        for (i, (ino, kind, name)) in entries.into_iter().enumerate().skip(offset as usize) {
+            // The offset passed back is that of the next entry
+            if reply.add(INodeNo(ino), (i + 1) as u64, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
+
+/// Handles the `mount` subcommand: mounts `source` on `mountpoint` until Ctrl-C or an
+/// unmount from outside (`umount`, `fusermount -u`).
+#[cfg(all(unix, feature = "fuse"))]
+pub async fn mount_command(
+    source: &str,
+    mountpoint: &str,
+    password: Option<String>,
+    key: Option<String>,
+    config: &Config,
+) -> Result<(), CliError> {
+    if password.is_some() && key.is_some() {
+        return Err(CliError::InvalidInput(
+            "Cannot specify both password and key. Choose one.".to_string(),
+        ));
+    }
+    if !Path::new(mountpoint).is_dir() {
+        return Err(CliError::InvalidInput(format!(
+            "Mount point '{mountpoint}' is not a directory"
+        )));
+    }
+    let max_size = config
+        .max_decompressed_size()
+        .map_err(|e| CliError::InvalidInput(e.to_string()))?;
+    let key = key.as_deref().map(validate_key).transpose()?;
+    let view = View::open(source, password, key, max_size).await?;
+
+    // SAFETY: getuid and getgid can't fail
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    let filesystem = EncryptedFs {
+        view,
+        runtime: tokio::runtime::Handle::current(),
+        uid,
+        gid,
+    };
+    let mut options = fuser::Config::default();
+    options.mount_options = vec![
+        MountOption::RO,
+        MountOption::NoDev,
+        MountOption::NoSuid,
+        MountOption::FSName(format!("encryptx:{source}")),
+        MountOption::Subtype("encryptx".to_string()),
+    ];
+    let session = fuser::spawn_mount(filesystem, mountpoint, &options).map_err(|e| {
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to mount on '{mountpoint}': {e}"),
+        ))
+    })?;
+    status(format!(
+        "📂 Mounted '{source}' read-only on '{mountpoint}'; press Ctrl-C to unmount"
+    ));
+
+    let mut poll = tokio::time::interval(Duration::from_millis(250));
+    let unmounted_outside = loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break false,
+            _ = poll.tick() => {
+                if session.guard.is_finished() {
+                    break true;
+                }
+            }
+        }
+    };
+    let result = if unmounted_outside {
+        session.join()
+    } else {
+        tokio::task::spawn_blocking(move || session.umount_and_join())
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e)))
+    };
+    result.map_err(|e| {
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to unmount '{mountpoint}': {e}"),
+        ))
+    })?;
+    status(format!("🔒 Unmounted '{mountpoint}'"));
+    Ok(())
+}
+
+/// Reports that this build can't mount.
+#[cfg(not(all(unix, feature = "fuse")))]
+pub async fn mount_command(
+    _source: &str,
+    _mountpoint: &str,
+    _password: Option<String>,
+    _key: Option<String>,
+    _config: &crate::config::Config,
+) -> Result<(), CliError> {
+    Err(CliError::InvalidInput(
+        "This build has no FUSE support. Rebuild with --features fuse (Unix only)".to_string(),
+    ))
+}
@@ -0,0 +1,92 @@
+//!
+//! OpenPGP bridge for `encrypt --format pgp` and decrypting messages from gpg.
+//!
+use super::CliError;
+#[cfg(feature = "openpgp")]
+use crate::interop::{InteropError, openpgp};
+#[cfg(feature = "openpgp")]
+use std::fs;
+#[cfg(feature = "openpgp")]
+use std::io;
+
+/// Reads a key file given on the command line.
+#[cfg(feature = "openpgp")]
+fn read_key_file(path: &str, what: &str) -> Result<Vec<u8>, CliError> {
+    fs::read(path).map_err(|e| {
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to read {what} '{path}': {e}"),
+        ))
+    })
+}
+
+/// Encrypts `data` as an OpenPGP message to a password and/or the public key files in
+/// `recipients`.
+#[cfg(feature = "openpgp")]
+pub fn encrypt_message(
+    data: &[u8],
+    password: Option<&str>,
+    recipients: &[String],
+    armor: bool,
+) -> Result<Vec<u8>, CliError> {
+    let certs = recipients
+        .iter()
+        .map(|path| {
+            openpgp::read_public_key(&read_key_file(path, "OpenPGP public key")?).map_err(|e| {
+                CliError::InvalidInput(format!("'{path}' is not a usable OpenPGP public key: {e}"))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    openpgp::encrypt(data, password, &certs, armor)
+        .map_err(|e| CliError::Crypto(format!("OpenPGP encryption failed: {e}")))
+}
+
+/// Decrypts an OpenPGP message with a password, or with the secret key file `secret_key`
+/// (unlocked by `password` if it is protected).
+#[cfg(feature = "openpgp")]
+pub fn decrypt_message(
+    data: &[u8],
+    password: Option<&str>,
+    secret_key: Option<&str>,
+) -> Result<(Vec<u8>, String), CliError> {
+    let secret_key = secret_key
+        .map(|path| {
+            openpgp::read_secret_key(&read_key_file(path, "OpenPGP secret key")?).map_err(|e| {
+                CliError::InvalidInput(format!("'{path}' is not a usable OpenPGP secret key: {e}"))
+            })
+        })
+        .transpose()?;
+    openpgp::decrypt(data, password, secret_key.as_ref()).map_err(|e| match e {
+        InteropError::WrongPassword => CliError::Authentication(
+            "OpenPGP decryption failed: wrong password or key, or the message is corrupted"
+                .to_string(),
+        ),
+        _ => CliError::Format(format!("OpenPGP decryption failed: {e}")),
+    })
+}
+
+/// Reports that OpenPGP support was not compiled in.
+#[cfg(not(feature = "openpgp"))]
+pub fn encrypt_message(
+    _data: &[u8],
+    _password: Option<&str>,
+    _recipients: &[String],
+    _armor: bool,
+) -> Result<Vec<u8>, CliError> {
+    Err(CliError::InvalidInput(
+        "This build has no OpenPGP support. Rebuild with --features openpgp".to_string(),
+    ))
+}
+
+/// Reports that OpenPGP support was not compiled in.
+#[cfg(not(feature = "openpgp"))]
+pub fn decrypt_message(
+    _data: &[u8],
+    _password: Option<&str>,
+    _secret_key: Option<&str>,
+) -> Result<(Vec<u8>, String), CliError> {
+    Err(CliError::Format(
+        "Input is an OpenPGP message, but this build has no OpenPGP support. Rebuild with --features openpgp"
+            .to_string(),
+    ))
+}
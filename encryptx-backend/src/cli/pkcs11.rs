@@ -0,0 +1,171 @@
+//!
+//! PKCS#11 devices (HSMs, smartcards) holding the key that protects a file.
+//!
+//! The device is driven through OpenSC's `pkcs11-tool` (set `ENCRYPTX_PKCS11_TOOL` to its
+//! path if it isn't on `PATH`), which loads the vendor's module. A pkcs11 keyslot's random
+//! key-encryption key is wrapped and unwrapped with RSA-OAEP (SHA-256) on the device, by the
+//! key pair with the given ID, so the private key never leaves it.
+//!
+use super::{CliError, Pkcs11Args, status};
+use std::fs;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use zeroize::Zeroizing;
+
+/// Environment variable naming the `pkcs11-tool` binary.
+const TOOL_ENV: &str = "ENCRYPTX_PKCS11_TOOL";
+
+/// Environment variable the PIN is handed to `pkcs11-tool` in, so it never shows up in `ps`.
+const PIN_ENV: &str = "ENCRYPTX_PKCS11_PIN";
+
+/// A PKCS#11 module and slot, with the PIN to log in with.
+pub struct Hsm {
+    module: String,
+    slot: Option<u64>,
+    pin: Option<Zeroizing<String>>,
+}
+
+/// Reads the PIN from a `--pin-source`: `env:NAME` or `file:PATH`.
+fn read_pin(source: &str) -> Result<Zeroizing<String>, CliError> {
+    let pin = if let Some(name) = source.strip_prefix("env:") {
+        std::env::var(name).map_err(|_| {
+            CliError::InvalidInput(format!(
+                "--pin-source: environment variable {name} is not set"
+            ))
+        })?
+    } else if let Some(path) = source.strip_prefix("file:") {
+        fs::read_to_string(path).map_err(|e| {
+            CliError::Io(io::Error::new(
+                e.kind(),
+                format!("Failed to read PIN file '{path}': {e}"),
+            ))
+        })?
+    } else {
+        return Err(CliError::InvalidInput(
+            "--pin-source must be 'env:NAME' or 'file:PATH'".to_string(),
+        ));
+    };
+    Ok(Zeroizing::new(
+        pin.trim_end_matches(['\r', '\n']).to_string(),
+    ))
+}
+
+/// Checks that a key ID is hex, as `pkcs11-tool --id` expects.
+pub fn validate_key_id(key_id: &str) -> Result<(), CliError> {
+    if key_id.is_empty()
+        || !key_id.len().is_multiple_of(2)
+        || !key_id.bytes().all(|b| b.is_ascii_hexdigit())
+    {
+        return Err(CliError::InvalidInput(format!(
+            "--pkcs11-key-id '{key_id}' must be the key's object ID in hex, e.g. 01"
+        )));
+    }
+    Ok(())
+}
+
+impl Hsm {
+    /// Builds the device from the command line, or `None` if no module was given.
+    pub fn from_args(args: &Pkcs11Args) -> Result<Option<Self>, CliError> {
+        let Some(module) = &args.pkcs11_module else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            module: module.clone(),
+            slot: args.slot,
+            pin: args.pin_source.as_deref().map(read_pin).transpose()?,
+        }))
+    }
+
+    /// Runs an RSA-OAEP operation (`--encrypt` or `--decrypt`) with the key `key_id`.
+    fn run(
+        &self,
+        operation: &str,
+        key_id: &str,
+        input: &[u8],
+    ) -> Result<Result<Zeroizing<Vec<u8>>, String>, CliError> {
+        let tool = std::env::var(TOOL_ENV).unwrap_or_else(|_| "pkcs11-tool".to_string());
+        let mut command = Command::new(&tool);
+        command.args(["--module", &self.module]);
+        if let Some(slot) = self.slot {
+            command.args(["--slot", &slot.to_string()]);
+        }
+        if let Some(pin) = &self.pin {
+            command
+                .args(["--login", "--pin", &format!("env:{PIN_ENV}")])
+                .env(PIN_ENV, pin.as_str());
+        }
+        command.args([
+            operation,
+            "--id",
+            key_id,
+            "--mechanism",
+            "RSA-PKCS-OAEP",
+            "--hash-algorithm",
+            "SHA256",
+            "--mgf",
+            "MGF1-SHA256",
+        ]);
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => CliError::InvalidInput(format!(
+                    "{tool} not found; install OpenSC or set {TOOL_ENV}"
+                )),
+                _ => CliError::Io(io::Error::new(
+                    e.kind(),
+                    format!("Failed to run {tool}: {e}"),
+                )),
+            })?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // The tool may bail out (bad PIN, unknown key) before reading; its stderr says why
+            match stdin.write_all(input) {
+                Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e.into()),
+                _ => {}
+            }
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Ok(Err(String::from_utf8_lossy(&output.stderr)
+                .trim()
+                .to_string()));
+        }
+        Ok(Ok(Zeroizing::new(output.stdout)))
+    }
+
+    /// Wraps `secret` with the device key `key_id`.
+    pub fn wrap(&self, key_id: &str, secret: &[u8]) -> Result<Vec<u8>, CliError> {
+        match self.run("--encrypt", key_id, secret)? {
+            Ok(sealed) if !sealed.is_empty() => Ok(sealed.to_vec()),
+            Ok(_) => Err(CliError::Crypto(
+                "The PKCS#11 device returned no wrapped key".to_string(),
+            )),
+            Err(message) => Err(CliError::Crypto(format!(
+                "Wrapping with PKCS#11 key {key_id} failed: {message}"
+            ))),
+        }
+    }
+
+    /// Returns a callback for [`crate::crypto::decrypt_with_hsm`].
+    ///
+    /// Slots the device can't unwrap are skipped; the device's reason is shown, since a wrong
+    /// PIN or a missing `pkcs11-tool` would otherwise look like a wrong key.
+    pub fn asker(&self) -> impl FnMut(&str, &[u8]) -> Option<Zeroizing<Vec<u8>>> + '_ {
+        move |key_id, sealed| {
+            status(format!("🔐 Unwrapping with PKCS#11 key {key_id}..."));
+            match self.run("--decrypt", key_id, sealed) {
+                Ok(Ok(secret)) => Some(secret),
+                Ok(Err(message)) => {
+                    status(format!("⚠️  The device refused: {message}"));
+                    None
+                }
+                Err(e) => {
+                    status(format!("⚠️  {e}"));
+                    None
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//!
+//! QR codes: `key qr` shows a key and `encrypt --qr` a small encrypted file as a QR code,
+//! drawn in the terminal or written as a PNG, so a phone can pick them up with its camera.
+//!
+//! Both are encoded as base64 text, which every scanner app reads; the encrypted file comes
+//! back with `base64 -d`.
+//!
+use super::{CliError, STDIO_PATH, check_output_file, print_json_report, status, validate_key};
+use crate::crypto;
+use base64::{Engine, engine::general_purpose};
+use png::{BitDepth, ColorType};
+use qrcode::render::unicode::Dense1x2;
+use qrcode::{Color, EcLevel, QrCode};
+use serde::Serialize;
+
+/// Largest encrypted file `encrypt --qr` accepts: its base64 text just fits the biggest
+/// QR code (version 40 at the lowest error correction holds 2953 bytes).
+pub const MAX_PAYLOAD: usize = 2214;
+
+/// Modules of blank margin around the code, as scanners expect.
+const QUIET_ZONE: usize = 4;
+
+/// Pixels per module in PNG output.
+const PNG_SCALE: usize = 8;
+
+/// Encodes `text` with the most error correction it fits in.
+fn encode(text: &str) -> Result<QrCode, CliError> {
+    [EcLevel::M, EcLevel::L]
+        .into_iter()
+        .find_map(|level| QrCode::with_error_correction_level(text, level).ok())
+        .ok_or_else(|| {
+            CliError::InvalidInput(format!(
+                "{} characters are too many for a QR code",
+                text.len()
+            ))
+        })
+}
+
+/// Draws the code with half-block characters, two modules per character cell. Dark modules
+/// are left blank so the code reads on the usual dark terminal background.
+fn render_terminal(code: &QrCode) -> String {
+    code.render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build()
+}
+
+/// Draws the code as a black-on-white grayscale PNG.
+fn render_png(code: &QrCode) -> Result<Vec<u8>, CliError> {
+    let modules = code.width();
+    let colors = code.to_colors();
+    let side = (modules + 2 * QUIET_ZONE) * PNG_SCALE;
+    let mut pixels = vec![0xff; side * side];
+    for (index, color) in colors.iter().enumerate() {
+        if *color == Color::Light {
+            continue;
+        }
+        let (x, y) = (index % modules + QUIET_ZONE, index / modules + QUIET_ZONE);
+        for row in y * PNG_SCALE..(y + 1) * PNG_SCALE {
+            pixels[row * side + x * PNG_SCALE..row * side + (x + 1) * PNG_SCALE].fill(0);
+        }
+    }
+
+    let mut out = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut out, side as u32, side as u32);
+        encoder.set_color(ColorType::Grayscale);
+        encoder.set_depth(BitDepth::Eight);
+        let mut writer = encoder
+            .write_header()
+            .map_err(|e| CliError::Format(e.to_string()))?;
+        writer
+            .write_image_data(&pixels)
+            .map_err(|e| CliError::Format(e.to_string()))?;
+    }
+    Ok(out)
+}
+
+/// A QR code ready to be shown: the terminal drawing, or the PNG for `output`.
+pub struct Qr {
+    code: QrCode,
+    output: String,
+}
+
+impl Qr {
+    /// Encodes `text` for `output`, a PNG path or '-' for the terminal, checking the path
+    /// first so nothing is written when it can't be.
+    pub fn new(text: &str, output: &str, force: bool) -> Result<Self, CliError> {
+        if output != STDIO_PATH {
+            check_output_file(output, force)?;
+        }
+        Ok(Self {
+            code: encode(text)?,
+            output: output.to_string(),
+        })
+    }
+
+    /// Encodes `data` as base64 for `encrypt --qr`, refusing files over [`MAX_PAYLOAD`].
+    pub fn for_payload(data: &[u8], output: &str, force: bool) -> Result<Self, CliError> {
+        if data.len() > MAX_PAYLOAD {
+            return Err(CliError::InvalidInput(format!(
+                "The encrypted file is {} bytes; --qr fits at most {MAX_PAYLOAD}",
+                data.len()
+            )));
+        }
+        Self::new(&general_purpose::STANDARD.encode(data), output, force)
+    }
+
+    /// Path the PNG is written to, `None` for the terminal.
+    pub fn png_path(&self) -> Option<&str> {
+        (self.output != STDIO_PATH).then_some(self.output.as_str())
+    }
+
+    /// Modules per side, without the quiet zone.
+    pub fn width(&self) -> usize {
+        self.code.width()
+    }
+
+    /// Writes the PNG, or draws the code on stdout (stderr if `to_stderr`).
+    pub fn show(&self, to_stderr: bool) -> Result<(), CliError> {
+        match self.png_path() {
+            Some(path) => super::write_output(path, &render_png(&self.code)?, "QR code"),
+            None => {
+                let drawing = render_terminal(&self.code);
+                if to_stderr {
+                    eprintln!("{drawing}");
+                } else {
+                    println!("{drawing}");
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// What `key qr --json` reports.
+#[derive(Serialize)]
+struct KeyQrReport {
+    operation: &'static str,
+    key_fingerprint: String,
+    modules: usize,
+    output: Option<String>,
+}
+
+/// Handles `key qr`. With `--json` a terminal drawing goes to stderr, leaving stdout to
+/// the report.
+pub fn key_qr_command(key: &str, output: &str, force: bool, json: bool) -> Result<(), CliError> {
+    let key_fingerprint = crypto::key_fingerprint(&validate_key(key)?);
+    let qr = Qr::new(key, output, force)?;
+    qr.show(json)?;
+
+    if json {
+        print_json_report(&KeyQrReport {
+            operation: "key-qr",
+            key_fingerprint,
+            modules: qr.width(),
+            output: qr.png_path().map(str::to_string),
+        });
+        return Ok(());
+    }
+    if let Some(path) = qr.png_path() {
+        status(format!("✅ QR code written to '{path}'"));
+    }
+    status(format!("🔖 Fingerprint: {key_fingerprint}"));
+    Ok(())
+}
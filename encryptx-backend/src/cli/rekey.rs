@@ -0,0 +1,242 @@
+//!
+//! `rekey` subcommand: re-encrypts a file under a new key or password.
+//!
+//! Envelope files (see [`crypto::rewrap`]) only get their data key rewrapped; anything else
+//! is decrypted and encrypted again. The plaintext only ever exists in memory (and is
+//! zeroized afterwards), and for chunked files only one chunk of it at a time; the result
+//! replaces the target file atomically, so an interrupted rekey leaves the old file intact.
+//!
+use super::{
+    CliError, CommandReport, RekeyArgs, STDIO_PATH, check_output_file, check_password_strength,
+    lock, read_input, status, validate_key, with_kdf_profile, write_atomic, write_output,
+};
+use crate::api::{self, ApiError};
+use crate::config::Config;
+use crate::crypto;
+use crate::crypto::keyslot::{self, Credential};
+use crate::format::{ParsedHeader, XdReader};
+use crate::{interop, stego};
+use rand::RngCore;
+use std::io;
+use std::time::Instant;
+use zeroize::Zeroizing;
+
+/// Handles the `rekey` subcommand.
+pub async fn rekey_command(
+    args: RekeyArgs,
+    config: &Config,
+    json: bool,
+) -> Result<CommandReport, CliError> {
+    let started = Instant::now();
+    let RekeyArgs {
+        file,
+        old_password,
+        old_key,
+        new_password,
+        new_key,
+        kdf_profile,
+        enforce_strong_password,
+        output,
+        force,
+    } = args;
+    let config = &*with_kdf_profile(config, kdf_profile)?;
+    if let Some(password) = &new_password {
+        check_password_strength(password, &file, enforce_strong_password)?;
+    }
+
+    if old_password.is_some() && old_key.is_some() {
+        return Err(CliError::InvalidInput(
+            "Cannot specify both --old-password and --old-key. Choose one.".to_string(),
+        ));
+    }
+    match (&new_password, &new_key) {
+        (Some(_), Some(_)) => {
+            return Err(CliError::InvalidInput(
+                "Cannot specify both --new-password and --new-key. Choose one.".to_string(),
+            ));
+        }
+        (None, None) => {
+            return Err(CliError::InvalidInput(
+                "Must specify either --new-password or --new-key.".to_string(),
+            ));
+        }
+        _ => {}
+    }
+    let old_key = old_key.as_deref().map(validate_key).transpose()?;
+    let new_key = new_key.as_deref().map(validate_key).transpose()?;
+
+    // Default to replacing the input in place
+    let output_file = match output {
+        Some(o) => o,
+        None if file == STDIO_PATH => {
+            return Err(CliError::InvalidInput(
+                "--output is required when reading from stdin".to_string(),
+            ));
+        }
+        None => file.clone(),
+    };
+    let to_stdout = output_file == STDIO_PATH;
+    if to_stdout && json {
+        return Err(CliError::InvalidInput(
+            "--json cannot be combined with writing data to stdout".to_string(),
+        ));
+    }
+    if !to_stdout && output_file != file {
+        check_output_file(&output_file, force)?;
+    }
+
+    let data = read_input(&file, "encrypted file")?;
+    if stego::is_png(&data) || interop::archive::detect(&data).is_some() {
+        return Err(CliError::Format(format!(
+            "'{file}' is not a plain .xd file; decrypt and re-encrypt it instead"
+        )));
+    }
+    let parsed = XdReader::parse(&data)
+        .map_err(|_| CliError::Format(format!("'{file}' is not a valid .xd file")))?;
+    let filename = parsed.header.filename().to_string();
+    let chunk_size = parsed.header.chunk_size();
+    if old_password.is_none()
+        && old_key.is_none()
+        && matches!(parsed.header, ParsedHeader::Password(_))
+    {
+        return Err(CliError::InvalidInput(
+            "This is a password-encrypted file. Use --old-password.".to_string(),
+        ));
+    }
+
+    let kdf_params = config
+        .kdf_profile()
+        .map_err(|e| CliError::InvalidInput(e.to_string()))?
+        .params();
+    let mut key_fingerprint = None;
+    if let Some(key) = &new_key {
+        key_fingerprint = Some(crypto::key_fingerprint(key));
+    }
+    let mode = if new_password.is_some() {
+        "password"
+    } else {
+        "key"
+    };
+
+    let rekeyed = if matches!(&parsed.header, ParsedHeader::Key(h) if h.is_envelope()) {
+        // Only the wrapped data key changes; the content stays encrypted as it is
+        status(format!("🔓 Unlocking '{file}' with the old secret..."));
+        let data_key = crypto::file_key(&parsed, old_password, old_key.as_deref())
+            .await
+            .map_err(|e| CliError::from_crypto("Unlocking with the old secret failed", e))?;
+        status("🔁 Rewrapping the data key with the new secret...");
+        let credential = match (new_password, &new_key) {
+            (Some(password), _) => Credential::Password(Zeroizing::new(password)),
+            (None, key) => Credential::Key(key.as_deref().unwrap_or_default()),
+        };
+        let slot = keyslot::create(&data_key, credential, kdf_params, None)
+            .await
+            .map_err(|e| CliError::from_crypto("Creating keyslot failed", e))?;
+        crypto::rewrap(&data, vec![slot])
+            .map_err(|e| CliError::from_crypto("Rewrapping failed", e))?
+    } else {
+        status(format!("🔓 Decrypting '{file}' with the old secret..."));
+        let old_file_key = crypto::file_key(&parsed, old_password, old_key.as_deref())
+            .await
+            .map_err(|e| CliError::from_crypto("Decryption with the old secret failed", e))?;
+        // A new password gets a fresh salt, whichever layout the file has
+        let new_password = match new_password {
+            Some(password) => {
+                let mut salt = [0u8; 32];
+                rand::rngs::OsRng
+                    .try_fill_bytes(&mut salt)
+                    .map_err(|e| CliError::Crypto(format!("Failed to generate salt: {e}")))?;
+                let kdf = config
+                    .password_kdf()
+                    .map_err(|e| CliError::InvalidInput(e.to_string()))?;
+                Some((password, salt.to_vec(), kdf))
+            }
+            None => None,
+        };
+        // New key-mode files are envelopes, so the next rekey is only a rewrap
+        let new_key = new_key.as_deref().unwrap_or_default();
+
+        let chunks = crypto::chunk_reader(&parsed, &old_file_key)
+            .map_err(|e| CliError::from_crypto("Decryption with the old secret failed", e))?;
+        status("🔐 Re-encrypting with the new secret...");
+        match (chunks, chunk_size) {
+            // Chunked files pass through one chunk at a time, never as a whole plaintext
+            (Some(mut chunks), Some(chunk_size)) => {
+                let mut writer = match new_password {
+                    Some((password, salt, kdf)) => crypto::password_file_writer(
+                        Vec::new(),
+                        password,
+                        &filename,
+                        salt,
+                        kdf,
+                        chunk_size,
+                    )
+                    .await
+                    .map_err(|e| CliError::from_crypto("Password encryption failed", e))?,
+                    None => {
+                        crypto::wrapped_key_file_writer(Vec::new(), new_key, &filename, chunk_size)
+                            .await
+                            .map_err(|e| CliError::from_crypto("Key encryption failed", e))?
+                    }
+                };
+                io::copy(&mut chunks, &mut writer).map_err(|e| {
+                    CliError::from_api(
+                        "Decryption with the old secret failed",
+                        api::stream_error(e, ApiError::Io),
+                    )
+                })?;
+                writer
+                    .finish()
+                    .map_err(|e| CliError::from_crypto("Re-encryption failed", e))?
+            }
+            _ => {
+                let plaintext =
+                    Zeroizing::new(crypto::decrypt_parsed(&parsed, &old_file_key).map_err(
+                        |e| CliError::from_crypto("Decryption with the old secret failed", e),
+                    )?);
+                match new_password {
+                    Some((password, salt, kdf)) => crypto::encrypt_with_password_kdf_async(
+                        &plaintext, password, &filename, salt, kdf, chunk_size,
+                    )
+                    .await
+                    .map_err(|e| CliError::from_crypto("Password encryption failed", e))?,
+                    None => {
+                        crypto::encrypt_with_wrapped_key(&plaintext, new_key, &filename, chunk_size)
+                            .await
+                            .map_err(|e| CliError::from_crypto("Key encryption failed", e))?
+                    }
+                }
+            }
+        }
+    };
+
+    if to_stdout {
+        write_output(&output_file, &rekeyed, "rekeyed file")?;
+    } else {
+        if output_file == file {
+            // Never silently drop a change another process made while we were working
+            if !lock::replace_if_unchanged(&file, &data, &rekeyed)? {
+                return Err(lock::conflict(&file));
+            }
+        } else {
+            write_atomic(&output_file, &rekeyed)?;
+        }
+        status(format!("✅ Rekeyed file written to '{output_file}'"));
+    }
+
+    Ok(CommandReport {
+        operation: "rekey",
+        mode: mode.to_string(),
+        input: file,
+        output: output_file,
+        input_size: data.len(),
+        output_size: rekeyed.len(),
+        key_fingerprint,
+        generated_key: None,
+        recovery_key: None,
+        delta: None,
+        signer: None,
+        compression_level: None,
+        duration_ms: started.elapsed().as_millis(),
+    })
+}
@@ -0,0 +1,523 @@
+//!
+//! `--remote URL`: `encrypt` and `decrypt` as clients of an EncryptX server, which does the
+//! Argon2 and AES work through its `/encrypt` and `/decrypt` endpoints while the files are
+//! read and written here.
+//!
+//! Only what the endpoints take can be asked for: a password (with `--kdf-profile`), a key
+//! (or none, for the server to generate one), `--chunk-size` and `--compression-level`.
+//! `--tar-from` is packed and `--untar-to` unpacked locally. Options the server can't honor
+//! are refused rather than silently dropped. Credentials travel in request headers, so the
+//! server should be reached over HTTPS.
+//!
+use super::{
+    CliError, CommandReport, DecryptArgs, EncryptArgs, OutputFormat, STDIO_PATH, check_output_file,
+    check_password_strength, generate_encrypt_output, key, keystore, read_input, show_secret,
+    status, tarball, unwrap_input, validate_key, walk_filter, write_output,
+};
+use crate::config::Config;
+use crate::crypto;
+use crate::walk::SymlinkPolicy;
+use std::io;
+use std::path::Path;
+use std::time::Instant;
+use ureq::http::{HeaderMap, StatusCode, header};
+use zeroize::Zeroizing;
+
+/// What the server answered: its body and headers.
+struct Answer {
+    body: Zeroizing<Vec<u8>>,
+    headers: HeaderMap,
+}
+
+/// Checks a `--remote` URL and returns it without a trailing `/`.
+fn base_url(remote: &str) -> Result<&str, CliError> {
+    let base = remote.trim_end_matches('/');
+    let host = base
+        .strip_prefix("https://")
+        .or_else(|| base.strip_prefix("http://"))
+        .filter(|host| !host.is_empty())
+        .ok_or_else(|| {
+            CliError::InvalidInput(format!(
+                "--remote '{remote}' must be an http:// or https:// URL"
+            ))
+        })?;
+    let loopback = ["localhost", "127.", "[::1]"]
+        .iter()
+        .any(|prefix| host.starts_with(prefix));
+    if base.starts_with("http://") && !loopback {
+        status("⚠️  --remote uses plain HTTP; the credential is sent unencrypted");
+    }
+    Ok(base)
+}
+
+/// Posts `body` to an endpoint of the server.
+fn post(
+    url: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    bad_request: fn(String) -> CliError,
+) -> Result<Answer, CliError> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .build()
+        .into();
+    let mut request = agent
+        .post(url)
+        .header(header::CONTENT_TYPE, "application/octet-stream");
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let mut response = request.send(body).map_err(|e| transport_error(url, e))?;
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = response
+        .body_mut()
+        .with_config()
+        .limit(u64::MAX)
+        .read_to_vec()
+        .map_err(|e| transport_error(url, e))?;
+    if status.is_success() {
+        return Ok(Answer {
+            body: Zeroizing::new(body),
+            headers,
+        });
+    }
+
+    let message = String::from_utf8_lossy(&body).trim().to_string();
+    let message = if message.is_empty() {
+        status.to_string()
+    } else {
+        message
+    };
+    Err(match status {
+        StatusCode::BAD_REQUEST => {
+            bad_request(format!("The server refused the request: {message}"))
+        }
+        StatusCode::UNAUTHORIZED => CliError::Authentication(message),
+        StatusCode::PAYLOAD_TOO_LARGE => CliError::InvalidInput(format!(
+            "The server refused a request this large: {message}"
+        )),
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => {
+            let retry = headers
+                .get(header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .map(|secs| format!(" (retry in {secs}s)"))
+                .unwrap_or_default();
+            CliError::Io(io::Error::other(format!("{message}{retry}")))
+        }
+        _ => CliError::Crypto(format!("The server failed ({status}): {message}")),
+    })
+}
+
+fn transport_error(url: &str, error: ureq::Error) -> CliError {
+    let kind = match &error {
+        ureq::Error::Io(e) => e.kind(),
+        ureq::Error::Timeout(_) => io::ErrorKind::TimedOut,
+        _ => io::ErrorKind::Other,
+    };
+    CliError::Io(io::Error::new(
+        kind,
+        format!("Request to '{url}' failed: {error}"),
+    ))
+}
+
+/// A header value as text, if there is one.
+fn header_text<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// Handles `encrypt --remote`.
+pub async fn encrypt_command(
+    args: EncryptArgs,
+    remote: &str,
+    config: &Config,
+    json: bool,
+) -> Result<CommandReport, CliError> {
+    let started = Instant::now();
+    let EncryptArgs {
+        files: _,
+        paths: _,
+        jobs: _,
+        file,
+        tar_from,
+        dir,
+        incremental: _,
+        state: _,
+        follow_symlinks,
+        store_symlinks: _,
+        skip_symlinks,
+        exclude,
+        exclude_from,
+        include,
+        password,
+        key,
+        key_name,
+        master_key,
+        convergent,
+        output,
+        format,
+        pgp_recipients: _,
+        armor,
+        bundle_readme,
+        chunk_size,
+        compression_level,
+        no_compress,
+        long,
+        dict,
+        pad,
+        no_metadata,
+        delta_from,
+        rsyncable: _,
+        wrap,
+        recovery_key,
+        recovery_out,
+        keyfile,
+        kdf_profile,
+        enforce_strong_password,
+        kdf,
+        recipients,
+        quorum,
+        quorum_keys: _,
+        split_credentials,
+        sign_key,
+        pkcs11,
+        tpm,
+        tpm_pcrs: _,
+        kms,
+        in_place,
+        wipe: _,
+        verify,
+        shred_original,
+        volume_size,
+        qr,
+        force,
+        contents: _,
+    } = args;
+    let local_only = [
+        ("--dir", dir.is_some()),
+        ("--master-key", master_key),
+        ("--convergent", convergent),
+        ("--format", format != OutputFormat::Xd),
+        ("--armor", armor),
+        ("--bundle-readme", bundle_readme),
+        ("--no-compress", no_compress),
+        ("--long", long),
+        ("--dict", dict),
+        ("--pad", pad.is_some()),
+        ("--no-metadata", no_metadata),
+        ("--delta-from", delta_from.is_some()),
+        ("--wrap", wrap.is_some()),
+        ("--recovery-key", recovery_key || recovery_out.is_some()),
+        ("--keyfile", keyfile.is_some()),
+        ("--kdf", kdf.is_some()),
+        ("--recipient", !recipients.is_empty()),
+        ("--quorum", quorum.is_some()),
+        ("--split-credentials", split_credentials),
+        ("--sign-key", sign_key.is_some()),
+        ("--pkcs11-module", pkcs11.pkcs11_module.is_some()),
+        ("--tpm", tpm),
+        ("--kms", kms.is_some()),
+        ("--in-place", in_place),
+        ("--verify", verify),
+        ("--shred-original", shred_original),
+        ("--volume-size", volume_size.is_some()),
+        ("--qr", qr.is_some()),
+    ];
+    reject_local_only(&local_only)?;
+
+    let key = match key_name {
+        Some(name) => Some(keystore::load_key(&name, config).await?.to_string()),
+        None => key,
+    };
+    if password.is_some() && key.is_some() {
+        return Err(CliError::InvalidInput(
+            "Cannot specify both password and key. Choose one.".to_string(),
+        ));
+    }
+    let password = password.map(Zeroizing::new);
+    if let Some(password) = &password {
+        check_password_strength(
+            password,
+            file.as_deref().unwrap_or_default(),
+            enforce_strong_password,
+        )?;
+    }
+    let key_fingerprint = key
+        .as_deref()
+        .map(validate_key)
+        .transpose()?
+        .map(|key| crypto::key_fingerprint(&key));
+
+    let (source, orig_name, default_output) = if let Some(dir) = &tar_from {
+        let name = tarball::archive_name(dir);
+        let stem = name.trim_end_matches(".tar").to_string();
+        (
+            dir.clone(),
+            name,
+            Some(config.output_path(&format!("{stem}.xd"))),
+        )
+    } else {
+        let file = file.unwrap_or_else(|| STDIO_PATH.to_string());
+        let name = Path::new(&file)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .filter(|_| file != STDIO_PATH)
+            .unwrap_or("file.bin")
+            .to_string();
+        let default_output =
+            (file != STDIO_PATH).then(|| config.output_path(&generate_encrypt_output(&file, "xd")));
+        (file, name, default_output)
+    };
+    let output_file = output.or(default_output).ok_or_else(|| {
+        CliError::InvalidInput("--output is required when reading from stdin".to_string())
+    })?;
+    let to_stdout = output_file == STDIO_PATH;
+    if to_stdout && json {
+        return Err(CliError::InvalidInput(
+            "--json cannot be combined with writing data to stdout".to_string(),
+        ));
+    }
+    let base = base_url(remote)?;
+    if !to_stdout {
+        check_output_file(&output_file, force)?;
+    }
+
+    let data = if let Some(dir) = &tar_from {
+        status(format!("📦 Packing directory '{dir}'..."));
+        let symlinks = if follow_symlinks {
+            SymlinkPolicy::Follow
+        } else if skip_symlinks {
+            SymlinkPolicy::Skip
+        } else {
+            SymlinkPolicy::Store
+        };
+        let filter = walk_filter(&exclude_from, &exclude, &include)?;
+        Zeroizing::new(tarball::pack_dir(dir, symlinks, &filter)?)
+    } else {
+        Zeroizing::new(read_input(&source, "input file")?.to_vec())
+    };
+
+    // Header values must be visible ASCII; the server falls back to file.bin without one
+    let mut headers = Vec::new();
+    if orig_name.bytes().all(|b| (0x20..0x7f).contains(&b)) {
+        headers.push(("x-orig-filename", orig_name.clone()));
+    }
+    if let Some(size) = &chunk_size {
+        headers.push(("x-chunk-size", size.clone()));
+    }
+    if let Some(level) = compression_level {
+        headers.push(("x-compression-level", level.to_string()));
+    }
+    if let Some(profile) = &kdf_profile {
+        headers.push(("x-kdf-profile", profile.clone()));
+    }
+    let credential = match (&password, &key) {
+        (Some(password), _) => Some(("x-password", Zeroizing::new(password.to_string()))),
+        (None, Some(key)) => Some(("x-enc-key", Zeroizing::new(key.clone()))),
+        (None, None) => None,
+    };
+    let mut header_refs: Vec<(&str, &str)> =
+        headers.iter().map(|(n, v)| (*n, v.as_str())).collect();
+    if let Some((name, value)) = &credential {
+        header_refs.push((name, value.as_str()));
+    }
+
+    status(format!("🌐 Encrypting '{source}' on {base}..."));
+    let answer = post(
+        &format!("{base}/encrypt"),
+        &header_refs,
+        &data,
+        CliError::InvalidInput,
+    )?;
+    write_output(&output_file, &answer.body, "encrypted file")?;
+    if !to_stdout {
+        status(format!("✅ Encrypted file written to '{output_file}'"));
+    }
+
+    let generated_key = header_text(&answer.headers, "x-generated-key").map(str::to_string);
+    let key_fingerprint = match &generated_key {
+        Some(key_b64) => {
+            show_secret(format!("✨ Generated random key (base64): {key_b64}"));
+            show_secret("💡 Save this key somewhere safe! You'll need it to decrypt your file.");
+            show_secret("⚠️  This key will NOT be shown again!");
+            Some(crypto::key_fingerprint(&validate_key(key_b64)?))
+        }
+        None => key_fingerprint,
+    };
+    status(format!("📊 Original size: {} bytes", data.len()));
+    status(format!("📊 Encrypted size: {} bytes", answer.body.len()));
+
+    Ok(CommandReport {
+        operation: "encrypt",
+        mode: if password.is_some() {
+            "password"
+        } else {
+            "key"
+        }
+        .to_string(),
+        input: source,
+        output: output_file,
+        input_size: data.len(),
+        output_size: answer.body.len(),
+        key_fingerprint,
+        generated_key,
+        recovery_key: None,
+        delta: None,
+        signer: None,
+        compression_level,
+        duration_ms: started.elapsed().as_millis(),
+    })
+}
+
+/// Handles `decrypt --remote`.
+pub async fn decrypt_command(
+    args: DecryptArgs,
+    remote: &str,
+    config: &Config,
+    json: bool,
+) -> Result<CommandReport, CliError> {
+    let started = Instant::now();
+    let DecryptArgs {
+        file,
+        password,
+        keyfile,
+        quorum_keys,
+        key,
+        key_name,
+        shares,
+        credentials,
+        identity,
+        pgp_key,
+        token,
+        token_device: _,
+        pkcs11,
+        output,
+        untar_to,
+        signer,
+        force,
+        refuse_binary_tty: _,
+    } = args;
+    let local_only = [
+        ("--keyfile", keyfile.is_some()),
+        ("--quorum-key", !quorum_keys.is_empty()),
+        ("--identity", !identity.is_empty()),
+        ("--pgp-key", pgp_key.is_some()),
+        ("--token", token),
+        ("--pkcs11-module", pkcs11.pkcs11_module.is_some()),
+        ("--signer", signer.is_some()),
+    ];
+    reject_local_only(&local_only)?;
+
+    let key = if !shares.is_empty() {
+        Some(key::key_from_shares(&shares)?.to_string())
+    } else if !credentials.is_empty() {
+        Some(key::key_from_parts(&credentials)?.to_string())
+    } else if let Some(name) = key_name {
+        Some(keystore::load_key(&name, config).await?.to_string())
+    } else {
+        key
+    };
+    if password.is_some() && key.is_some() {
+        return Err(CliError::InvalidInput(
+            "Cannot specify both password and key. Choose one.".to_string(),
+        ));
+    }
+    let password = password.map(Zeroizing::new);
+    let key_fingerprint = key
+        .as_deref()
+        .map(validate_key)
+        .transpose()?
+        .map(|key| crypto::key_fingerprint(&key));
+
+    let file = file.unwrap_or_else(|| STDIO_PATH.to_string());
+    let to_stdout = output.as_deref() == Some(STDIO_PATH);
+    if to_stdout && json {
+        return Err(CliError::InvalidInput(
+            "--json cannot be combined with writing data to stdout".to_string(),
+        ));
+    }
+    let base = base_url(remote)?;
+    let data = unwrap_input(&file, read_input(&file, "encrypted file")?)?;
+
+    let credential = match (&password, &key) {
+        (Some(password), _) => Some(("x-password", Zeroizing::new(password.to_string()))),
+        (None, Some(key)) => Some(("x-enc-key", Zeroizing::new(key.clone()))),
+        // Files carrying their own key open without one
+        (None, None) => None,
+    };
+    let headers: Vec<(&str, &str)> = credential
+        .iter()
+        .map(|(name, value)| (*name, value.as_str()))
+        .collect();
+
+    status(format!("🌐 Decrypting '{file}' on {base}..."));
+    let answer = post(
+        &format!("{base}/decrypt"),
+        &headers,
+        &data,
+        CliError::Format,
+    )?;
+    let report = |output: String| CommandReport {
+        operation: "decrypt",
+        mode: if password.is_some() {
+            "password"
+        } else {
+            "key"
+        }
+        .to_string(),
+        input: file.clone(),
+        output,
+        input_size: data.len(),
+        output_size: answer.body.len(),
+        key_fingerprint: key_fingerprint.clone(),
+        generated_key: None,
+        recovery_key: None,
+        delta: None,
+        signer: None,
+        compression_level: None,
+        duration_ms: started.elapsed().as_millis(),
+    };
+
+    if let Some(target) = untar_to {
+        let count = tarball::unpack_to(&answer.body, &target, force)?;
+        status(format!("✅ Extracted {count} entries into '{target}'"));
+        return Ok(report(target));
+    }
+
+    // The name the server read from the file; only its last component is used
+    let orig_filename = header_text(&answer.headers, "content-disposition")
+        .and_then(|value| value.split_once("filename=\""))
+        .and_then(|(_, name)| name.strip_suffix('"'))
+        .and_then(|name| Path::new(name).file_name())
+        .and_then(|name| name.to_str())
+        .unwrap_or_default()
+        .to_string();
+    let output_file = match output {
+        Some(output) => output,
+        None if orig_filename.is_empty() => {
+            return Err(CliError::InvalidInput(
+                "The file records no filename (--no-metadata); specify --output".to_string(),
+            ));
+        }
+        None => config.output_path(&orig_filename),
+    };
+    if !to_stdout {
+        check_output_file(&output_file, force)?;
+    }
+    write_output(&output_file, &answer.body, "decrypted file")?;
+    if !to_stdout {
+        status(format!("✅ Decrypted file written to '{output_file}'"));
+    }
+    status(format!("📊 Decrypted size: {} bytes", answer.body.len()));
+    Ok(report(output_file))
+}
+
+/// Refuses the first option set that only local encryption or decryption can honor.
+fn reject_local_only(options: &[(&str, bool)]) -> Result<(), CliError> {
+    match options.iter().find(|(_, set)| *set) {
+        Some((flag, _)) => Err(CliError::InvalidInput(format!(
+            "{flag} is not available with --remote; the server only works with a password or key"
+        ))),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,384 @@
+//!
+//! `replay-corpus` subcommand: runs saved fuzz and crash inputs through every parser.
+//!
+//! Each input goes through the same fixed set of targets in the same order, so a replay is
+//! deterministic: a fixed all-zero key stands in for real credentials, and password headers
+//! are never run through their KDF (an input's Argon2 parameters can't stall the replay).
+//! Nothing is written to disk. Every target runs on its own thread; panics are caught and
+//! a target that doesn't return within [`TARGET_TIMEOUT`] is reported as hung.
+//!
+use super::{CliError, inspect, print_json_report, status};
+use crate::crypto::identity::{Identity, PQ_IDENTITY_PREFIX, Recipient};
+use crate::crypto::{self, SecureKey, keyslot, repair, shamir, signing, split};
+use crate::format::XdReader;
+use crate::{interop, stego};
+use base64::{Engine, engine::general_purpose};
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long one target may take on one input before it counts as hung.
+const TARGET_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most an archive input may expand to, so a zip bomb in the corpus can't exhaust memory.
+#[cfg(feature = "archive-ingest")]
+const ARCHIVE_LIMIT: u64 = 64 * 1024 * 1024;
+
+/// A parser entry point: `Ok` if it accepted the input, `Err` with its reason otherwise.
+type Target = fn(&[u8]) -> Result<(), String>;
+
+/// Every target, in replay order.
+const TARGETS: &[(&str, Target)] = &[
+    ("xd", xd),
+    ("decrypt", decrypt),
+    ("keyslots", keyslots),
+    ("repair", repair_header),
+    ("inspect", inspect_file),
+    ("signature", signature),
+    ("png", png),
+    ("archive", archive),
+    ("openpgp", openpgp),
+    ("text", text),
+];
+
+/// How a target handled an input.
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// Parsed without error
+    Accepted,
+    /// Returned an error, which is what malformed input should do
+    Rejected,
+    Panicked,
+    TimedOut,
+}
+
+/// Result of one target on one input.
+#[derive(Serialize)]
+pub struct TargetResult {
+    pub target: &'static str,
+    pub outcome: Outcome,
+    /// Error or panic message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Results for one corpus input.
+#[derive(Serialize)]
+pub struct InputResult {
+    /// Path relative to the corpus directory
+    pub file: String,
+    pub size: usize,
+    pub targets: Vec<TargetResult>,
+}
+
+/// Result of `replay-corpus`, printed with `--json`.
+#[derive(Serialize)]
+pub struct ReplayReport {
+    pub operation: &'static str,
+    pub directory: String,
+    pub inputs: usize,
+    pub accepted: usize,
+    pub rejected: usize,
+    pub results: Vec<InputResult>,
+    pub duration_ms: u128,
+}
+
+fn fixed_key() -> SecureKey {
+    SecureKey::new([0u8; 32])
+}
+
+/// Runs every check, so an early rejection doesn't keep the later ones from being exercised,
+/// and reports the first error.
+fn first_error<E: std::fmt::Display>(
+    results: impl IntoIterator<Item = Result<(), E>>,
+) -> Result<(), String> {
+    let mut first = None;
+    for result in results {
+        if let (Err(e), None) = (result, &first) {
+            first = Some(e.to_string());
+        }
+    }
+    first.map_or(Ok(()), Err)
+}
+
+fn xd(data: &[u8]) -> Result<(), String> {
+    first_error([
+        XdReader::parse(data).map(drop),
+        crypto::chunked::part_boundaries(data).map(drop),
+    ])
+}
+
+fn decrypt(data: &[u8]) -> Result<(), String> {
+    let file = XdReader::parse(data).map_err(|e| e.to_string())?;
+    let key = fixed_key();
+    first_error([
+        crypto::salvage_regions(&file, &key).map(drop),
+        crypto::verify_regions(&file, &key).map(drop),
+        crypto::decrypt_parsed(&file, &key).map(drop),
+    ])
+}
+
+fn keyslots(data: &[u8]) -> Result<(), String> {
+    let file = XdReader::parse(data).map_err(|e| e.to_string())?;
+    let (slots, check) = file
+        .header
+        .keyslots()
+        .ok_or_else(|| "no keyslots".to_string())?;
+    // A fixed hybrid identity tries both x25519 and kem slots
+    let identity = Identity::parse_file(&format!(
+        "{PQ_IDENTITY_PREFIX}{}",
+        general_purpose::STANDARD.encode([0u8; 32 + 64])
+    ))
+    .map_err(|e| e.to_string())?;
+    let outcomes = [
+        keyslot::unlock_with_key(slots, check, &[0u8; 32]).map(drop),
+        keyslot::unlock_with_identity(slots, check, &identity).map(drop),
+    ];
+    outcomes
+        .into_iter()
+        .collect::<Result<(), _>>()
+        .map_err(|e| e.to_string())
+}
+
+fn repair_header(data: &[u8]) -> Result<(), String> {
+    for password_mode in [false, true] {
+        let damaged = repair::locate(data, Some(password_mode)).map_err(|e| e.to_string())?;
+        let (fields, _) = repair::recover_fields(damaged.header_json, damaged.password_mode);
+        repair::missing_fields(&fields, damaged.password_mode);
+    }
+    Ok(())
+}
+
+fn inspect_file(data: &[u8]) -> Result<(), String> {
+    inspect::inspect("corpus", data)
+        .map(drop)
+        .map_err(|e| e.to_string())
+}
+
+fn signature(data: &[u8]) -> Result<(), String> {
+    signing::claimed_signer(data);
+    signing::verify(data).map(drop).map_err(|e| e.to_string())
+}
+
+fn png(data: &[u8]) -> Result<(), String> {
+    stego::png_capacity(data).map_err(|e| e.to_string())?;
+    stego::extract_png(data)
+        .map(drop)
+        .map_err(|e| e.to_string())
+}
+
+fn archive(data: &[u8]) -> Result<(), String> {
+    let kind = interop::archive::detect(data).ok_or_else(|| "not an archive".to_string())?;
+    #[cfg(feature = "archive-ingest")]
+    interop::archive::read_entries(kind, data, "", ARCHIVE_LIMIT).map_err(|e| e.to_string())?;
+    #[cfg(not(feature = "archive-ingest"))]
+    let _ = kind;
+    Ok(())
+}
+
+fn openpgp(data: &[u8]) -> Result<(), String> {
+    if !interop::openpgp::detect(data) {
+        return Err("not an OpenPGP message".to_string());
+    }
+    #[cfg(feature = "openpgp")]
+    {
+        interop::openpgp::read_public_key(data).map_err(|e| e.to_string())?;
+        interop::openpgp::read_secret_key(data).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// The text formats: recipients, identities, key shares and parts, signing keys and
+/// detached signatures. Accepted if any of them parses.
+fn text(data: &[u8]) -> Result<(), String> {
+    let text = String::from_utf8_lossy(data);
+    let parsed = [
+        text.parse::<Recipient>().is_ok(),
+        Identity::parse_file(&text).is_ok(),
+        text.parse::<shamir::Share>().is_ok(),
+        text.parse::<split::Part>().is_ok(),
+        text.parse::<signing::VerifyingKey>().is_ok(),
+        signing::SigningKey::parse_file(&text).is_ok(),
+        signing::DetachedSignature::parse_file(&text).is_ok(),
+    ];
+    if parsed.contains(&true) {
+        Ok(())
+    } else {
+        Err("no text format matched".to_string())
+    }
+}
+
+/// Describes a caught panic by its message.
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "panic with a non-string payload".to_string())
+}
+
+/// Runs one target on its own thread, catching panics and giving up after the timeout.
+fn run_target(
+    name: &'static str,
+    target: Target,
+    data: Arc<Vec<u8>>,
+) -> Result<TargetResult, CliError> {
+    let (sender, receiver) = mpsc::channel();
+    thread::Builder::new()
+        .name(format!("replay-{name}"))
+        .spawn(move || {
+            let result = panic::catch_unwind(|| target(&data));
+            let _ = sender.send(result);
+        })?;
+    let (outcome, detail) = match receiver.recv_timeout(TARGET_TIMEOUT) {
+        Ok(Ok(Ok(()))) => (Outcome::Accepted, None),
+        Ok(Ok(Err(reason))) => (Outcome::Rejected, Some(reason)),
+        Ok(Err(payload)) => (Outcome::Panicked, Some(panic_message(payload.as_ref()))),
+        // A hung thread can't be stopped; it is left behind and the replay moves on
+        Err(_) => (
+            Outcome::TimedOut,
+            Some(format!("no result after {}s", TARGET_TIMEOUT.as_secs())),
+        ),
+    };
+    Ok(TargetResult {
+        target: name,
+        outcome,
+        detail,
+    })
+}
+
+/// Lists the files under `dir` recursively, sorted, skipping hidden entries.
+fn corpus_files(dir: &Path) -> Result<Vec<PathBuf>, CliError> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in fs::read_dir(&current)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Runs every target on every file, in order.
+fn replay_files(root: &Path, files: &[PathBuf]) -> Result<Vec<InputResult>, CliError> {
+    let mut results = Vec::with_capacity(files.len());
+    for path in files {
+        let data = fs::read(path).map(Arc::new).map_err(|e| {
+            CliError::Io(io::Error::new(
+                e.kind(),
+                format!("Failed to read '{}': {e}", path.display()),
+            ))
+        })?;
+        let targets = TARGETS
+            .iter()
+            .map(|&(name, target)| run_target(name, target, Arc::clone(&data)))
+            .collect::<Result<_, _>>()?;
+        results.push(InputResult {
+            file: path
+                .strip_prefix(root)
+                .unwrap_or(path)
+                .to_string_lossy()
+                .into_owned(),
+            size: data.len(),
+            targets,
+        });
+    }
+    Ok(results)
+}
+
+/// Handles the `replay-corpus` subcommand.
+///
+/// Fails with exit code 1 if any target panicked or hung on any input; rejections are the
+/// expected outcome for malformed files and don't count as failures.
+pub fn replay_command(dir: &str, json: bool) -> Result<(), CliError> {
+    let started = Instant::now();
+    let root = Path::new(dir);
+    if !root.is_dir() {
+        return Err(CliError::InvalidInput(format!(
+            "Corpus directory '{dir}' does not exist"
+        )));
+    }
+    let files = corpus_files(root)?;
+    if !json {
+        status(format!(
+            "🧪 Replaying {} input(s) from '{dir}' through {} targets",
+            files.len(),
+            TARGETS.len()
+        ));
+    }
+
+    // Caught panics would otherwise print their own message and backtrace mid-report
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let results = replay_files(root, &files);
+    panic::set_hook(default_hook);
+    let results = results?;
+
+    let mut failures = Vec::new();
+    for input in &results {
+        for result in &input.targets {
+            let label = match result.outcome {
+                Outcome::Panicked => "panicked",
+                Outcome::TimedOut => "hung",
+                Outcome::Accepted | Outcome::Rejected => continue,
+            };
+            let detail = result.detail.as_deref().unwrap_or_default();
+            if !json {
+                status(format!(
+                    "❌ {} [{}]: {label}: {detail}",
+                    input.file, result.target
+                ));
+            }
+            failures.push(format!("{} [{}] {label}", input.file, result.target));
+        }
+    }
+    if !failures.is_empty() {
+        return Err(CliError::Crypto(format!(
+            "{} crash(es) replaying the corpus: {}",
+            failures.len(),
+            failures.join(", ")
+        )));
+    }
+
+    let count = |outcome| {
+        results
+            .iter()
+            .flat_map(|input| &input.targets)
+            .filter(|r| r.outcome == outcome)
+            .count()
+    };
+    let (accepted, rejected) = (count(Outcome::Accepted), count(Outcome::Rejected));
+    if json {
+        print_json_report(&ReplayReport {
+            operation: "replay-corpus",
+            directory: dir.to_string(),
+            inputs: results.len(),
+            accepted,
+            rejected,
+            results,
+            duration_ms: started.elapsed().as_millis(),
+        });
+    } else {
+        status(format!(
+            "✅ No crashes: {} input(s), {accepted} accepted and {rejected} rejected target runs",
+            results.len()
+        ));
+    }
+    Ok(())
+}
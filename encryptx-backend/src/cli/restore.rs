@@ -0,0 +1,336 @@
+//!
+//! `restore` subcommand: brings a snapshot of a backup repository (see [`crate::backup`])
+//! back into a directory, whole or below some paths, or lists the snapshots.
+//!
+//! Every chunk is checked against its id as it is read, so a restored file is exactly the
+//! one backed up. Files are written under a temporary name and renamed into place once
+//! complete; directory times and permissions are set last, after their contents.
+//!
+use super::backup::repository_key;
+use super::{CliError, RestoreArgs, print_json_report, status};
+use crate::backup::{BackupError, EntryType, Repository, SnapshotEntry};
+use crate::config::Config;
+use crate::interop::archive::safe_join;
+use crate::locale::tr;
+use serde::Serialize;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+/// One snapshot, as listed by `restore --list`.
+#[derive(Serialize)]
+struct SnapshotSummary {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    source: String,
+    files: usize,
+    size: u64,
+}
+
+/// Outcome of `restore --list`.
+#[derive(Serialize)]
+struct ListReport {
+    operation: &'static str,
+    repository: String,
+    snapshots: Vec<SnapshotSummary>,
+}
+
+/// Outcome of a restore.
+#[derive(Serialize)]
+struct RestoreReport {
+    operation: &'static str,
+    repository: String,
+    snapshot: String,
+    target: String,
+    files: usize,
+    dirs: usize,
+    symlinks: usize,
+    size: u64,
+    duration_ms: u128,
+}
+
+/// Handles the `restore` subcommand.
+pub async fn restore_command(
+    args: RestoreArgs,
+    config: &Config,
+    json: bool,
+) -> Result<(), CliError> {
+    let started = Instant::now();
+    let RestoreArgs {
+        repo,
+        snapshot,
+        target,
+        path,
+        list,
+        password,
+        key,
+        key_name,
+        force,
+    } = args;
+    let key = repository_key(&password, key, key_name, config).await?;
+    let repo_path = Path::new(&repo);
+    if !Repository::exists(repo_path) {
+        return Err(CliError::InvalidInput(format!(
+            "'{repo}' is not a backup repository"
+        )));
+    }
+    let repository = Repository::open(repo_path, password, key.as_deref())
+        .await
+        .map_err(|e| CliError::from_backup("Opening the repository failed", e))?;
+
+    if list {
+        return list_snapshots(&repository, repo, json).await;
+    }
+    // Both are required by clap without --list
+    let (spec, target) = (snapshot.unwrap_or_default(), target.unwrap_or_default());
+    let id = repository.find_snapshot(&spec).await.map_err(|e| match e {
+        BackupError::Repository(msg) => CliError::InvalidInput(msg),
+        e => CliError::from_backup("Finding the snapshot failed", e),
+    })?;
+    let snapshot = repository
+        .load_snapshot(&id)
+        .await
+        .map_err(|e| CliError::from_backup("Opening the snapshot failed", e))?;
+
+    let prefixes: Vec<&str> = path.iter().map(|p| p.trim_matches('/')).collect();
+    let selected: Vec<&SnapshotEntry> = snapshot
+        .entries
+        .iter()
+        .filter(|entry| {
+            prefixes.is_empty()
+                || prefixes.iter().any(|prefix| {
+                    prefix.is_empty()
+                        || entry.path == *prefix
+                        || entry
+                            .path
+                            .strip_prefix(prefix)
+                            .is_some_and(|rest| rest.starts_with('/'))
+                })
+        })
+        .collect();
+    if selected.is_empty() {
+        return Err(CliError::InvalidInput(format!(
+            "Snapshot {id} has nothing under {}",
+            path.join(", ")
+        )));
+    }
+
+    // Check every destination before writing anything
+    let root = Path::new(&target);
+    let mut dests = Vec::with_capacity(selected.len());
+    for entry in &selected {
+        let dest = safe_join(root, &entry.path)
+            .map_err(|e| CliError::Format(format!("Snapshot {id} is not restorable: {e}")))?;
+        if !force && entry.kind != EntryType::Dir && fs::symlink_metadata(&dest).is_ok() {
+            return Err(CliError::InvalidInput(format!(
+                "'{}' already exists. Use --force to overwrite",
+                dest.display()
+            )));
+        }
+        dests.push(dest);
+    }
+
+    status(tr!(
+        "restore-started",
+        entries = selected.len(),
+        snapshot = id,
+        target = target
+    ));
+    fs::create_dir_all(root).map_err(|e| write_error(root, e))?;
+    let (mut files, mut dirs, mut symlinks, mut size) = (0, 0, 0, 0);
+    for (entry, dest) in selected.iter().zip(&dests) {
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).map_err(|e| write_error(parent, e))?;
+        }
+        match entry.kind {
+            EntryType::Dir => {
+                fs::create_dir_all(dest).map_err(|e| write_error(dest, e))?;
+                dirs += 1;
+            }
+            EntryType::File => {
+                restore_file(&repository, entry, dest).await?;
+                files += 1;
+                size += entry.size;
+            }
+            EntryType::Symlink => {
+                if restore_symlink(entry, dest, force)? {
+                    symlinks += 1;
+                } else {
+                    status(tr!("restore-skipped-symlink", entry = entry.path));
+                }
+            }
+        }
+    }
+    // Innermost directories first, so setting a parent's time isn't undone by a child
+    for (entry, dest) in selected.iter().zip(&dests).rev() {
+        if entry.kind == EntryType::Dir {
+            set_metadata(entry, dest).map_err(|e| write_error(dest, e))?;
+        }
+    }
+
+    if json {
+        print_json_report(&RestoreReport {
+            operation: "restore",
+            repository: repo,
+            snapshot: id,
+            target,
+            files,
+            dirs,
+            symlinks,
+            size,
+            duration_ms: started.elapsed().as_millis(),
+        });
+    } else {
+        status(tr!(
+            "restore-done",
+            snapshot = id,
+            target = target,
+            files = files,
+            dirs = dirs,
+            symlinks = symlinks,
+            bytes = size
+        ));
+    }
+    Ok(())
+}
+
+/// Prints the snapshots of the repository, oldest first.
+async fn list_snapshots(repository: &Repository, repo: String, json: bool) -> Result<(), CliError> {
+    let mut snapshots = Vec::new();
+    for id in repository
+        .snapshots()
+        .map_err(|e| CliError::from_backup("Listing the snapshots failed", e))?
+    {
+        let snapshot = repository
+            .load_snapshot(&id)
+            .await
+            .map_err(|e| CliError::from_backup("Opening the snapshot failed", e))?;
+        let files = snapshot
+            .entries
+            .iter()
+            .filter(|e| e.kind == EntryType::File);
+        snapshots.push(SnapshotSummary {
+            id,
+            name: snapshot.name,
+            source: snapshot.source,
+            files: files.clone().count(),
+            size: files.map(|e| e.size).sum(),
+        });
+    }
+    if json {
+        print_json_report(&ListReport {
+            operation: "restore-list",
+            repository: repo,
+            snapshots,
+        });
+        return Ok(());
+    }
+    if snapshots.is_empty() {
+        println!("{}", tr!("restore-no-snapshots", repo = repo));
+        return Ok(());
+    }
+    println!(
+        "{}",
+        tr!("restore-snapshots", count = snapshots.len(), repo = repo)
+    );
+    for s in &snapshots {
+        let name = s
+            .name
+            .as_deref()
+            .map(|n| format!(" ({n})"))
+            .unwrap_or_default();
+        println!(
+            "  {}",
+            tr!(
+                "restore-snapshot",
+                snapshot = s.id,
+                name = name,
+                files = s.files,
+                bytes = s.size,
+                source = s.source
+            )
+        );
+    }
+    Ok(())
+}
+
+/// Writes a file's chunks to a temporary file next to `dest` and renames it into place.
+async fn restore_file(
+    repository: &Repository,
+    entry: &SnapshotEntry,
+    dest: &Path,
+) -> Result<(), CliError> {
+    let name = dest
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temp = dest.with_file_name(format!(".{name}.{}.tmp", std::process::id()));
+    let result = async {
+        let mut file = fs::File::create(&temp).map_err(|e| write_error(dest, e))?;
+        let mut written = 0;
+        for id in &entry.chunks {
+            let chunk = repository.load_chunk(id).await.map_err(|e| {
+                CliError::from_backup(&format!("Restoring '{}' failed", entry.path), e)
+            })?;
+            file.write_all(&chunk).map_err(|e| write_error(dest, e))?;
+            written += chunk.len() as u64;
+        }
+        if written != entry.size {
+            return Err(CliError::Format(format!(
+                "'{}' restored to {written} bytes, but the snapshot records {}",
+                entry.path, entry.size
+            )));
+        }
+        file.sync_all().map_err(|e| write_error(dest, e))?;
+        drop(file);
+        set_metadata(entry, &temp).map_err(|e| write_error(dest, e))?;
+        fs::rename(&temp, dest).map_err(|e| write_error(dest, e))
+    }
+    .await;
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Recreates a symbolic link; returns `false` where the platform can't.
+fn restore_symlink(entry: &SnapshotEntry, dest: &Path, force: bool) -> Result<bool, CliError> {
+    #[cfg(unix)]
+    {
+        let target = entry.target.as_deref().unwrap_or_default();
+        if force && fs::symlink_metadata(dest).is_ok_and(|meta| !meta.is_dir()) {
+            fs::remove_file(dest).map_err(|e| write_error(dest, e))?;
+        }
+        std::os::unix::fs::symlink(target, dest).map_err(|e| write_error(dest, e))?;
+        Ok(true)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (entry, dest, force);
+        Ok(false)
+    }
+}
+
+/// Applies the recorded permissions and modification time.
+fn set_metadata(entry: &SnapshotEntry, path: &Path) -> io::Result<()> {
+    let modified = UNIX_EPOCH + Duration::from_secs(entry.modified);
+    // Directories can only be opened for this on some platforms; their times are a nicety
+    if let Ok(file) = fs::File::open(path) {
+        let _ = file.set_modified(modified);
+    }
+    #[cfg(unix)]
+    if let Some(mode) = entry.mode {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+    Ok(())
+}
+
+fn write_error(path: &Path, e: io::Error) -> CliError {
+    CliError::Io(io::Error::new(
+        e.kind(),
+        format!("Failed to write '{}': {e}", path.display()),
+    ))
+}
@@ -0,0 +1,160 @@
+//!
+//! S3 objects as command input and output: `--file s3://BUCKET/KEY` reads an object and
+//! `--output s3://BUCKET/KEY` writes one, piped through the AWS CLI (`aws s3 cp`). Uploads
+//! go straight from memory into the pipe. Downloads are streamed into an unnamed temporary
+//! file, which is memory-mapped like a local input and vanishes once it is closed.
+//!
+//! Credentials, region and endpoint come from the AWS CLI's own chain (`AWS_ACCESS_KEY_ID`
+//! and friends, `AWS_PROFILE`, `~/.aws/config`, instance roles, `AWS_ENDPOINT_URL` for
+//! S3-compatible stores). Set `ENCRYPTX_S3_TOOLS` to the directory holding `aws` if it
+//! isn't on `PATH`.
+//!
+use super::CliError;
+use std::fs;
+use std::io::{self, Seek, Write};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+/// Environment variable naming the directory of the `aws` tool.
+const TOOLS_ENV: &str = "ENCRYPTX_S3_TOOLS";
+
+const SCHEME: &str = "s3://";
+
+/// Whether a path names an S3 object rather than a local file.
+pub fn is_url(path: &str) -> bool {
+    path.starts_with(SCHEME)
+}
+
+/// Splits `s3://BUCKET/KEY` into bucket and key.
+fn parse(url: &str) -> Result<(&str, &str), CliError> {
+    url.strip_prefix(SCHEME)
+        .and_then(|rest| rest.split_once('/'))
+        .filter(|(bucket, key)| !bucket.is_empty() && !key.is_empty() && !key.ends_with('/'))
+        .ok_or_else(|| CliError::InvalidInput(format!("'{url}' must look like s3://BUCKET/KEY")))
+}
+
+/// Streams an object into an unnamed temporary file and returns that file, rewound.
+pub fn download(url: &str) -> Result<fs::File, CliError> {
+    parse(url)?;
+    let mut spool = tempfile::tempfile()?;
+    let args = ["s3", "cp", url, "-", "--only-show-errors"];
+    let output = run_aws(&args, None, Some(spool.try_clone()?))?;
+    if !output.status.success() {
+        return Err(match aws_error(url, "read", &output) {
+            CliError::Io(e) if e.kind() == io::ErrorKind::NotFound => {
+                CliError::InvalidInput(format!("Object '{url}' does not exist"))
+            }
+            e => e,
+        });
+    }
+    // aws wrote through a clone of the handle, which shares its offset
+    spool.rewind()?;
+    Ok(spool)
+}
+
+/// Writes `data` as an object, replacing any object of that name.
+pub fn upload(url: &str, data: &[u8]) -> Result<(), CliError> {
+    parse(url)?;
+    // The expected size lets the CLI pick part sizes for uploads over 50 GB
+    let size = data.len().to_string();
+    let args = [
+        "s3",
+        "cp",
+        "-",
+        url,
+        "--expected-size",
+        &size,
+        "--only-show-errors",
+    ];
+    let output = run_aws(&args, Some(data), None)?;
+    if !output.status.success() {
+        return Err(aws_error(url, "write", &output));
+    }
+    Ok(())
+}
+
+/// Whether an object exists.
+pub fn exists(url: &str) -> Result<bool, CliError> {
+    let (bucket, key) = parse(url)?;
+    let output = run_aws(
+        &["s3api", "head-object", "--bucket", bucket, "--key", key],
+        None,
+        None,
+    )?;
+    if output.status.success() {
+        return Ok(true);
+    }
+    match aws_error(url, "check", &output) {
+        CliError::Io(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        e => Err(e),
+    }
+}
+
+fn tool_path() -> PathBuf {
+    match std::env::var_os(TOOLS_ENV) {
+        Some(dir) => PathBuf::from(dir).join("aws"),
+        None => PathBuf::from("aws"),
+    }
+}
+
+/// Runs `aws`, with `input` on its stdin if given, and its stdout written to `stdout`
+/// rather than collected if given.
+fn run_aws(
+    args: &[&str],
+    input: Option<&[u8]>,
+    stdout: Option<fs::File>,
+) -> Result<Output, CliError> {
+    let mut child = Command::new(tool_path())
+        .args(args)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(stdout.map_or_else(Stdio::piped, Stdio::from))
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => CliError::InvalidInput(format!(
+                "aws not found; install the AWS CLI or set {TOOLS_ENV}"
+            )),
+            _ => CliError::Io(io::Error::new(e.kind(), format!("Failed to run aws: {e}"))),
+        })?;
+    let stdin = child.stdin.take();
+    std::thread::scope(|scope| {
+        // The input is fed from its own thread while this one drains stdout and stderr:
+        // aws may fill either pipe before it has read all of its input, and would then wait
+        // for us while we waited for it
+        let writer = stdin.zip(input).map(|(mut stdin, input)| {
+            scope.spawn(move || match stdin.write_all(input) {
+                // aws may fail (no credentials, no bucket) before reading; its stderr says why
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+                result => result,
+            })
+        });
+        let output = child.wait_with_output()?;
+        if let Some(writer) = writer {
+            writer
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))?;
+        }
+        Ok(output)
+    })
+}
+
+/// Turns a failed `aws` call into an error, telling missing objects and refused access
+/// apart from other failures by the status code in its message.
+fn aws_error(url: &str, action: &str, output: &Output) -> CliError {
+    let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    let kind = if message.contains("(404)") || message.contains("NoSuchKey") {
+        io::ErrorKind::NotFound
+    } else if message.contains("(403)") || message.contains("AccessDenied") {
+        io::ErrorKind::PermissionDenied
+    } else {
+        io::ErrorKind::Other
+    };
+    CliError::Io(io::Error::new(
+        kind,
+        format!("Failed to {action} '{url}': {message}"),
+    ))
+}
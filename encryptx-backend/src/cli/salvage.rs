@@ -0,0 +1,351 @@
+//!
+//! `salvage` subcommand: recovers what is left of a damaged or truncated encrypted file.
+//!
+//! Every chunk that still authenticates is decrypted; lost regions are reported by their
+//! byte range in the encrypted file. Compressed bodies are then decoded frame by frame, so
+//! data behind a lost chunk is recovered as long as it sits in a zstd frame of its own
+//! (chunked files are compressed that way; see `encrypt --chunk-size`). A dictionary
+//! stored at the start of the body (`encrypt --dict`) is used for every frame, so it must
+//! survive for the rest to decode. Decoding stops with an error once the output passes
+//! `max_decompressed_size`, as in `decrypt`.
+//!
+use super::{
+    CliError, STDIO_PATH, SalvageArgs, check_output_file, identity, read_input, status,
+    unwrap_input, validate_key, write_output,
+};
+use crate::api::ApiError;
+use crate::compression;
+use crate::config::Config;
+use crate::crypto::{self, SalvagedRegion, padding};
+use crate::format::{ParsedHeader, XdReader};
+use serde::Serialize;
+use std::io::Read;
+use std::path::Path;
+use std::time::Instant;
+use zeroize::{Zeroize, Zeroizing};
+use zstd::stream::read::Decoder;
+
+/// Magic number starting every zstd frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// A byte range of the encrypted file that could not be recovered.
+#[derive(Serialize)]
+pub struct LostRange {
+    pub offset: usize,
+    pub length: usize,
+}
+
+/// Result of `salvage`, printed with `--json`.
+#[derive(Serialize)]
+pub struct SalvageReport {
+    pub operation: &'static str,
+    pub mode: &'static str,
+    pub input: String,
+    pub output: String,
+    /// Whether nothing was lost and the end of the file authenticated
+    pub intact: bool,
+    pub regions: usize,
+    pub recovered_regions: usize,
+    /// Damaged or missing byte ranges of the encrypted file
+    pub lost: Vec<LostRange>,
+    /// Offsets in the output where lost data would have been
+    pub gaps: Vec<usize>,
+    pub output_size: usize,
+    pub duration_ms: u128,
+}
+
+/// Decodes the complete zstd frame at the start of `bytes`, returning its size and content.
+/// `dictionary` is empty for bodies compressed without one. At most one byte more than
+/// `limit` is decoded, so a frame expanding past it shows without being decoded in full.
+fn decode_frame(bytes: &[u8], dictionary: &[u8], limit: u64) -> Option<(usize, Vec<u8>)> {
+    let size = zstd::zstd_safe::find_frame_compressed_size(bytes).ok()?;
+    let mut data = Vec::new();
+    Decoder::with_dictionary(&bytes[..size], dictionary)
+        .ok()?
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut data)
+        .ok()?;
+    Some((size, data))
+}
+
+/// Decodes as much as possible of a frame that was cut off by damage, up to one byte more
+/// than `limit`.
+fn decode_prefix(bytes: &[u8], dictionary: &[u8], limit: u64, out: &mut Vec<u8>) {
+    let Ok(decoder) = Decoder::with_dictionary(bytes, dictionary) else {
+        return;
+    };
+    let mut decoder = decoder.single_frame().take(limit.saturating_add(1));
+    let mut buf = vec![0u8; 64 * 1024];
+    while let Ok(n @ 1..) = decoder.read(&mut buf) {
+        out.extend_from_slice(&buf[..n]);
+    }
+    buf.zeroize();
+}
+
+/// Finds the next position at or after `from` where a complete zstd frame starts.
+fn resync(run: &[u8], from: usize, dictionary: &[u8], limit: u64) -> Option<usize> {
+    (from..run.len()).find(|&p| {
+        run[p..].starts_with(&ZSTD_MAGIC) && decode_frame(&run[p..], dictionary, limit).is_some()
+    })
+}
+
+/// Reassembles the recovered output from the salvaged regions.
+struct Recovery {
+    out: Zeroizing<Vec<u8>>,
+    gaps: Vec<usize>,
+    compressed: bool,
+    /// Dictionary from the start of the body, once read
+    dictionary: Zeroizing<Vec<u8>>,
+    /// Most bytes decompression may produce (`max_decompressed_size`)
+    limit: u64,
+    /// Set once the output passed `limit`; nothing more is decoded after that
+    too_large: bool,
+}
+
+impl Recovery {
+    /// How many more bytes decompression may produce.
+    fn remaining(&self) -> u64 {
+        self.limit.saturating_sub(self.out.len() as u64)
+    }
+
+    /// Records whether the output passed the limit, returning `true` if it did.
+    fn check_limit(&mut self) -> bool {
+        self.too_large |= self.out.len() as u64 > self.limit;
+        self.too_large
+    }
+
+    fn gap(&mut self) {
+        if self.gaps.last() != Some(&self.out.len()) {
+            self.gaps.push(self.out.len());
+        }
+    }
+
+    /// Appends one run of consecutive recovered bytes; `from_start` if it begins the body.
+    fn push_run(&mut self, run: &[u8], from_start: bool) {
+        if !self.compressed {
+            self.out.extend_from_slice(run);
+            return;
+        }
+        if self.too_large {
+            return;
+        }
+        let mut pos = if from_start {
+            // The level and dictionary frames come first
+            let start = compression::stream_start(run.get(1..).unwrap_or_default());
+            if let Some(dictionary) = start.dictionary {
+                self.dictionary = Zeroizing::new(dictionary.to_vec());
+            }
+            run.len() - start.frames.len()
+        } else {
+            match resync(run, 0, &self.dictionary, self.remaining()) {
+                Some(p) => p,
+                None => return,
+            }
+        };
+        while pos < run.len() {
+            let limit = self.remaining();
+            if let Some((size, mut data)) = decode_frame(&run[pos..], &self.dictionary, limit) {
+                self.out.extend_from_slice(&data);
+                data.zeroize();
+                if self.check_limit() {
+                    return;
+                }
+                pos += size;
+                continue;
+            }
+            // A frame cut off by the next lost region: keep what decodes
+            decode_prefix(&run[pos..], &self.dictionary, limit, &mut self.out);
+            if self.check_limit() {
+                return;
+            }
+            self.gap();
+            match resync(run, pos + 1, &self.dictionary, self.remaining()) {
+                Some(p) => pos = p,
+                None => break,
+            }
+        }
+    }
+}
+
+/// Decodes the salvaged regions into the recovered file contents and its gaps, failing if
+/// they decompress to more than `max_size` bytes.
+fn recover(
+    regions: &[SalvagedRegion],
+    complete: bool,
+    max_size: u64,
+) -> Result<(Zeroizing<Vec<u8>>, Vec<usize>), CliError> {
+    let first = regions.first().and_then(|r| r.plaintext.as_ref());
+    // A padded body (`encrypt --pad`) records where the original body ends; without the
+    // first region there is no telling, so everything is kept
+    let mut body = first
+        .and_then(|plain| padding::body_range(plain))
+        .unwrap_or(0..usize::MAX);
+    // The flag byte says whether the body is compressed; if it was lost, assume it is, as
+    // the CLI compresses all but incompressible data
+    let flag = first.and_then(|first| first.get(body.start));
+    let compressed = matches!(flag, Some(0x01) | None);
+    if flag == Some(&0x00) {
+        // Stored uncompressed: everything after the flag is the file
+        body.start += 1;
+    }
+    let mut recovery = Recovery {
+        out: Zeroizing::new(Vec::new()),
+        gaps: Vec::new(),
+        compressed,
+        dictionary: Zeroizing::new(Vec::new()),
+        limit: max_size,
+        too_large: false,
+    };
+    let mut run = Zeroizing::new(Vec::new());
+    let mut run_from_start = true;
+    // Offset of the current region in the decrypted body
+    let mut pos = 0;
+    for region in regions {
+        if pos >= body.end {
+            break;
+        }
+        match &region.plaintext {
+            Some(plain) => {
+                let start = body.start.clamp(pos, pos + plain.len());
+                let end = body.end.clamp(pos, pos + plain.len());
+                run.extend_from_slice(&plain[start - pos..end - pos]);
+                pos += plain.len();
+            }
+            None => {
+                recovery.push_run(&run, run_from_start);
+                run.clear();
+                run_from_start = false;
+                recovery.gap();
+                pos += region.length.saturating_sub(crypto::chunked::TAG_LEN);
+            }
+        }
+    }
+    recovery.push_run(&run, run_from_start);
+    if recovery.too_large {
+        return Err(CliError::from_api(
+            "Salvage failed",
+            ApiError::DecompressedTooLarge { limit: max_size },
+        ));
+    }
+    if !complete && pos < body.end {
+        recovery.gap();
+    }
+    Ok((recovery.out, recovery.gaps))
+}
+
+/// Handles the `salvage` subcommand.
+pub async fn salvage_command(
+    args: SalvageArgs,
+    config: &Config,
+    json: bool,
+) -> Result<SalvageReport, CliError> {
+    let started = Instant::now();
+    let SalvageArgs {
+        file,
+        password,
+        key,
+        identity,
+        output,
+        force,
+    } = args;
+    if password.is_some() && key.is_some() {
+        return Err(CliError::InvalidInput(
+            "Cannot specify both password and key. Choose one.".to_string(),
+        ));
+    }
+    let key = key.as_deref().map(validate_key).transpose()?;
+    let max_size = config
+        .max_decompressed_size()
+        .map_err(|e| CliError::InvalidInput(e.to_string()))?;
+    let identities = identity::read_identities(identity.as_slice())?;
+
+    let data = unwrap_input(&file, read_input(&file, "encrypted file")?)?;
+    let parsed = XdReader::parse(&data).map_err(|_| {
+        CliError::Format(format!(
+            "'{file}': header is missing or corrupted, nothing can be salvaged"
+        ))
+    })?;
+    let mode = if parsed.header.keyslots().is_some() {
+        "keyslots"
+    } else if matches!(parsed.header, ParsedHeader::Password(_)) {
+        "password"
+    } else {
+        "key"
+    };
+
+    let output_file = output.unwrap_or_else(|| {
+        let name = Path::new(parsed.header.filename())
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("file.bin");
+        config.output_path(&format!("{name}.salvaged"))
+    });
+    let to_stdout = output_file == STDIO_PATH;
+    if to_stdout && json {
+        return Err(CliError::InvalidInput(
+            "--json cannot be combined with writing data to stdout".to_string(),
+        ));
+    }
+    if !to_stdout {
+        check_output_file(&output_file, force)?;
+    }
+
+    status(format!("🩹 Salvaging '{file}'..."));
+    let file_key = match identities.as_slice() {
+        [] => crypto::file_key(&parsed, password, key.as_deref()).await,
+        identities => crypto::identity_file_key(&parsed, identities),
+    }
+    .map_err(|e| CliError::from_crypto("Salvage failed", e))?;
+    let (regions, complete) = crypto::salvage_regions(&parsed, &file_key)
+        .map_err(|e| CliError::from_crypto("Salvage failed", e))?;
+
+    let recovered_regions = regions.iter().filter(|r| r.plaintext.is_some()).count();
+    if recovered_regions == 0 {
+        return Err(CliError::Authentication(format!(
+            "Nothing in '{file}' could be authenticated: wrong key/password or the data is destroyed"
+        )));
+    }
+    let lost: Vec<LostRange> = regions
+        .iter()
+        .filter(|r| r.plaintext.is_none())
+        .map(|r| LostRange {
+            offset: r.offset,
+            length: r.length,
+        })
+        .collect();
+    let (recovered, gaps) = recover(&regions, complete, max_size)?;
+    write_output(&output_file, &recovered, "salvaged file")?;
+
+    for range in &lost {
+        status(format!(
+            "❌ Lost bytes {}-{} of '{file}'",
+            range.offset,
+            range.offset + range.length
+        ));
+    }
+    if !complete {
+        status("⚠️  The end of the file is missing or damaged; it may have been truncated");
+    }
+    for gap in &gaps {
+        status(format!("🕳️  Data missing at output byte {gap}"));
+    }
+    status(format!(
+        "✅ Recovered {recovered_regions} of {} regions ({} bytes) to '{output_file}'",
+        regions.len(),
+        recovered.len()
+    ));
+
+    Ok(SalvageReport {
+        operation: "salvage",
+        mode,
+        input: file,
+        output: output_file,
+        intact: lost.is_empty() && complete,
+        regions: regions.len(),
+        recovered_regions,
+        lost,
+        gaps,
+        output_size: recovered.len(),
+        duration_ms: started.elapsed().as_millis(),
+    })
+}
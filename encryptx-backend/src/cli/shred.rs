@@ -0,0 +1,132 @@
+//! Overwriting plaintext that encryption has replaced or made redundant.
+//!
+//! Overwriting a file in place only reaches the blocks it was stored in on filesystems
+//! that rewrite blocks where they are. Copy-on-write filesystems (btrfs, ZFS, bcachefs)
+//! write the zeros somewhere new, and SSDs remap writes internally, so on those the old
+//! contents may survive until the space is reused. Everything here is best effort.
+
+use std::fs;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Size of the zero block written over a file.
+const BLOCK_LEN: usize = 1 << 20;
+
+/// Overwrites the whole file with zeros and syncs it to disk, returning its length. The
+/// handle may belong to a file that has already been renamed over or unlinked.
+pub(super) fn overwrite(file: &mut fs::File) -> io::Result<u64> {
+    let len = file.metadata()?.len();
+    let zeros = vec![0u8; BLOCK_LEN];
+    file.seek(SeekFrom::Start(0))?;
+    let mut left = len;
+    while left > 0 {
+        let n = left.min(BLOCK_LEN as u64) as usize;
+        file.write_all(&zeros[..n])?;
+        left -= n as u64;
+    }
+    file.sync_all()?;
+    Ok(len)
+}
+
+/// Overwrites the file at `path` with zeros and deletes it, returning its length.
+pub(super) fn shred(path: &Path) -> io::Result<u64> {
+    let mut file = fs::OpenOptions::new().write(true).open(path)?;
+    let len = overwrite(&mut file)?;
+    drop(file);
+    fs::remove_file(path)?;
+    Ok(len)
+}
+
+/// Reasons overwriting `path` in place may leave its old contents on disk. On Linux these
+/// are the ones detected for the file's filesystem and device; elsewhere nothing can be
+/// detected, so the general caveat is always given.
+pub(super) fn caveats(path: &Path) -> Vec<String> {
+    if !cfg!(target_os = "linux") {
+        return vec![
+            "Overwriting is best effort: SSDs and copy-on-write filesystems may keep the old \
+             data until the space is reused"
+                .to_string(),
+        ];
+    }
+    let mut caveats = Vec::new();
+    if let Some(fs_name) = copy_on_write_filesystem(path) {
+        caveats.push(format!(
+            "'{}' is on {fs_name}, a copy-on-write filesystem: the zeros go to new blocks and \
+             the old contents may remain until the space is reused",
+            path.display()
+        ));
+    }
+    if solid_state(path) {
+        caveats.push(format!(
+            "'{}' is on a solid-state drive, which remaps writes: the old contents may remain \
+             in cells the drive has not erased yet",
+            path.display()
+        ));
+    }
+    caveats
+}
+
+#[cfg(target_os = "linux")]
+fn copy_on_write_filesystem(path: &Path) -> Option<&'static str> {
+    use std::os::unix::ffi::OsStrExt;
+
+    const BTRFS_SUPER_MAGIC: i64 = 0x9123_683e;
+    const ZFS_SUPER_MAGIC: i64 = 0x2fc1_2fc1;
+    const BCACHEFS_SUPER_MAGIC: i64 = 0xca45_1a4e;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is NUL-terminated and `stats` is a valid, writable statfs
+    if unsafe { libc::statfs(path.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+    #[allow(clippy::unnecessary_cast)]
+    match stats.f_type as i64 {
+        BTRFS_SUPER_MAGIC => Some("btrfs"),
+        ZFS_SUPER_MAGIC => Some("ZFS"),
+        BCACHEFS_SUPER_MAGIC => Some("bcachefs"),
+        _ => None,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_on_write_filesystem(_path: &Path) -> Option<&'static str> {
+    None
+}
+
+/// Whether the block device holding `path` reports itself as non-rotational.
+#[cfg(target_os = "linux")]
+fn solid_state(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let Ok(meta) = fs::metadata(path) else {
+        return false;
+    };
+    let (major, minor) = (libc::major(meta.dev()), libc::minor(meta.dev()));
+    let device = format!("/sys/dev/block/{major}:{minor}");
+    // A partition has no queue of its own; its parent disk does
+    ["queue/rotational", "../queue/rotational"]
+        .iter()
+        .find_map(|queue| fs::read_to_string(format!("{device}/{queue}")).ok())
+        .is_some_and(|rotational| rotational.trim() == "0")
+}
+
+#[cfg(not(target_os = "linux"))]
+fn solid_state(_path: &Path) -> bool {
+    false
+}
+
+/// Number of names the file has; another hard link keeps the plaintext reachable after
+/// the name being encrypted is replaced.
+pub(super) fn link_count(meta: &fs::Metadata) -> u64 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        meta.nlink()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = meta;
+        1
+    }
+}
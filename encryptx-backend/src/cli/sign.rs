@@ -0,0 +1,113 @@
+//!
+//! `sign` and `verify-sig` subcommands: detached Ed25519 signatures.
+//!
+//! Unlike `encrypt --sign-key`, the signature lives in its own `.sig` file and covers the
+//! file exactly as it is, so it can be checked without the decryption secret and without
+//! touching the signed file.
+//!
+use super::{
+    CliError, STDIO_PATH, check_output_file, identity, print_json_report, read_input, status,
+    write_output,
+};
+use crate::crypto::signing::DetachedSignature;
+use serde::Serialize;
+use std::fs;
+use std::io;
+
+/// Result of `sign` or `verify-sig`, printed with `--json`.
+#[derive(Serialize)]
+pub struct SignatureReport {
+    pub operation: &'static str,
+    pub file: String,
+    pub signature: String,
+    /// Fingerprint of the signing key
+    pub signer: String,
+}
+
+/// Default signature path for a signed file (`<file>.sig`).
+pub fn signature_path(file: &str) -> String {
+    format!("{file}.sig")
+}
+
+/// Handles the `sign` subcommand.
+pub fn sign_command(
+    file: &str,
+    key: &str,
+    output: Option<String>,
+    force: bool,
+    json: bool,
+) -> Result<(), CliError> {
+    let output = match output {
+        Some(output) => output,
+        None if file == STDIO_PATH => {
+            return Err(CliError::InvalidInput(
+                "--output is required when reading from stdin".to_string(),
+            ));
+        }
+        None => signature_path(file),
+    };
+    if output == STDIO_PATH && json {
+        return Err(CliError::InvalidInput(
+            "--json cannot be combined with writing the signature to stdout".to_string(),
+        ));
+    }
+    if output != STDIO_PATH {
+        check_output_file(&output, force)?;
+    }
+    let key = identity::read_signing_key(key)?;
+    let data = read_input(file, "file to sign")?;
+
+    let signature = DetachedSignature::sign(&data, &key);
+    write_output(&output, signature.to_file().as_bytes(), "signature")?;
+    let signer = signature.signer().fingerprint();
+
+    if json {
+        print_json_report(&SignatureReport {
+            operation: "sign",
+            file: file.to_string(),
+            signature: output,
+            signer,
+        });
+    } else if output != STDIO_PATH {
+        status(format!("✍️  Signature written to '{output}'"));
+        status(format!("🔖 Signer: {signer}"));
+    }
+    Ok(())
+}
+
+/// Handles the `verify-sig` subcommand.
+pub fn verify_sig_command(
+    file: &str,
+    signature_file: &str,
+    pubkey: &str,
+    json: bool,
+) -> Result<(), CliError> {
+    let expected = identity::read_verifying_key(pubkey)?;
+    let text = fs::read_to_string(signature_file).map_err(|e| {
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to read signature '{signature_file}': {e}"),
+        ))
+    })?;
+    let signature = DetachedSignature::parse_file(&text).map_err(|_| {
+        CliError::Format(format!("'{signature_file}' is not a valid signature file"))
+    })?;
+    let data = read_input(file, "signed file")?;
+
+    signature.verify(&data, &expected).map_err(|e| {
+        CliError::from_crypto(&format!("Checking the signature of '{file}' failed"), e)
+    })?;
+    let signer = expected.fingerprint();
+
+    if json {
+        print_json_report(&SignatureReport {
+            operation: "verify-sig",
+            file: file.to_string(),
+            signature: signature_file.to_string(),
+            signer,
+        });
+    } else {
+        status(format!("✅ Good signature on '{file}' by {signer}"));
+    }
+    Ok(())
+}
@@ -0,0 +1,102 @@
+//!
+//! Tar packing and unpacking so whole directories can be encrypted in one go.
+//!
+use super::CliError;
+use crate::walk::{self, Filter, SymlinkPolicy};
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+/// Packs a directory into an in-memory tar archive.
+///
+/// Entries are stored relative to the directory itself, so extracting the archive
+/// recreates the directory's contents (not its absolute path). Symlinks are stored as
+/// links, followed or left out according to `symlinks`, and `filter` leaves out whatever
+/// its patterns exclude.
+pub fn pack_dir(dir: &str, symlinks: SymlinkPolicy, filter: &Filter) -> Result<Vec<u8>, CliError> {
+    let path = Path::new(dir);
+    if !path.is_dir() {
+        return Err(CliError::InvalidInput(format!(
+            "'{dir}' is not a directory"
+        )));
+    }
+
+    let archive_error = |e: io::Error| {
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to archive '{dir}': {e}"),
+        ))
+    };
+    let mut builder = tar::Builder::new(Vec::new());
+    builder.follow_symlinks(symlinks == SymlinkPolicy::Follow);
+    let root = Path::new(".");
+    builder.append_dir(root, path).map_err(archive_error)?;
+    for entry in walk::walk(path, symlinks, filter).map_err(archive_error)? {
+        builder
+            .append_path_with_name(&entry.path, root.join(&entry.name))
+            .map_err(archive_error)?;
+    }
+    builder.into_inner().map_err(CliError::Io)
+}
+
+/// Where the contents of each non-empty file sit in a tar archive made by [`pack_dir`].
+pub fn file_ranges(tar: &[u8]) -> Result<Vec<Range<usize>>, CliError> {
+    let mut files = Vec::new();
+    let mut archive = tar::Archive::new(tar);
+    for entry in archive.entries().map_err(CliError::Io)? {
+        let entry = entry.map_err(|e| CliError::Format(format!("Invalid tar archive: {e}")))?;
+        let start = entry.raw_file_position() as usize;
+        let size = entry.size() as usize;
+        if size > 0 {
+            files.push(start..start + size);
+        }
+    }
+    Ok(files)
+}
+
+/// Default name recorded in the header for a packed directory (`<dirname>.tar`).
+pub fn archive_name(dir: &str) -> String {
+    let name = Path::new(dir)
+        .canonicalize()
+        .ok()
+        .and_then(|p| p.file_name().and_then(|n| n.to_str()).map(str::to_string))
+        .unwrap_or_else(|| "archive".to_string());
+    format!("{name}.tar")
+}
+
+/// Extracts a tar archive into `target`, creating it if needed.
+///
+/// Existing files are only overwritten when `force` is set. Entries that would escape the
+/// target directory are rejected by the tar crate during unpacking.
+pub fn unpack_to(data: &[u8], target: &str, force: bool) -> Result<usize, CliError> {
+    let target_path = Path::new(target);
+
+    // Pre-scan so we fail before writing anything
+    let mut count = 0;
+    let mut archive = tar::Archive::new(data);
+    for entry in archive.entries().map_err(CliError::Io)? {
+        let entry = entry.map_err(|e| CliError::Format(format!("Invalid tar archive: {e}")))?;
+        let entry_path = entry
+            .path()
+            .map_err(|e| CliError::Format(format!("Invalid tar entry path: {e}")))?;
+        let dest = target_path.join(&entry_path);
+        if !force && dest.exists() && !dest.is_dir() {
+            return Err(CliError::InvalidInput(format!(
+                "'{}' already exists. Use --force to overwrite",
+                dest.display()
+            )));
+        }
+        count += 1;
+    }
+
+    std::fs::create_dir_all(target_path)?;
+    let mut archive = tar::Archive::new(data);
+    archive.set_preserve_permissions(true);
+    archive.unpack(target_path).map_err(|e| {
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to extract archive into '{target}': {e}"),
+        ))
+    })?;
+    Ok(count)
+}
@@ -0,0 +1,305 @@
+//!
+//! `text` subcommand: encrypts a short secret (a password, a token, a line of text) given
+//! on the command line or stdin into an armored message that pastes into chat or email,
+//! and turns such a message back into the text.
+//!
+//! Messages carry no file name, and a key is wrapped in a keyslot rather than embedded as
+//! in a plain key-mode file, so nothing but the ciphertext goes along. The armor is the
+//! `.xd` file in base64 between `BEGIN`/`END` lines; `clip` uses the same.
+//!
+use super::{
+    CliError, MessageCredentials, STDIO_PATH, check_output_file, keystore, print_json_report,
+    read_input, status, validate_key, write_output,
+};
+use crate::api::{self, ApiError};
+use crate::compression;
+use crate::config::{Config, DEFAULT_COMPRESSION_LEVEL};
+use crate::crypto::{self, secret::SecretBuffer};
+use crate::locale::tr;
+use base64::{Engine, engine::general_purpose};
+use serde::Serialize;
+use std::io::{self, IsTerminal, Read};
+use zeroize::Zeroizing;
+
+/// First line of an armored message.
+const ARMOR_BEGIN: &str = "-----BEGIN ENCRYPTX MESSAGE-----";
+
+/// Last line of an armored message.
+const ARMOR_END: &str = "-----END ENCRYPTX MESSAGE-----";
+
+/// Base64 characters per armor line.
+const ARMOR_WIDTH: usize = 64;
+
+/// Wraps an encrypted file in armor lines.
+pub(super) fn armor(encrypted: &[u8]) -> String {
+    let encoded = general_purpose::STANDARD.encode(encrypted);
+    let mut text = format!("{ARMOR_BEGIN}\n");
+    for line in encoded.as_bytes().chunks(ARMOR_WIDTH) {
+        text.push_str(&String::from_utf8_lossy(line));
+        text.push('\n');
+    }
+    text.push_str(ARMOR_END);
+    text.push('\n');
+    text
+}
+
+/// Whether `data` holds an armored message rather than a bare encrypted file.
+fn is_armored(data: &[u8]) -> bool {
+    data.windows(ARMOR_BEGIN.len())
+        .any(|window| window == ARMOR_BEGIN.as_bytes())
+}
+
+/// The encrypted file inside an armored message; text around the armor (a mail quote, a
+/// greeting) is ignored, and so are quote markers at the start of its lines.
+pub(super) fn dearmor(text: &str) -> Result<Vec<u8>, CliError> {
+    let not_armored = || CliError::InvalidInput("No EncryptX message found".to_string());
+    let (_, rest) = text.split_once(ARMOR_BEGIN).ok_or_else(not_armored)?;
+    let (body, _) = rest.split_once(ARMOR_END).ok_or_else(not_armored)?;
+    let encoded: String = body
+        .lines()
+        .map(|line| line.trim_start_matches(['>', ' ']))
+        .flat_map(str::chars)
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|e| CliError::InvalidInput(format!("The armored message is damaged: {e}")))
+}
+
+/// The password and the key given as `--key` or `--key-name`, decoded; `command` needs
+/// one of them.
+pub(super) async fn resolve_credentials(
+    credentials: MessageCredentials,
+    config: &Config,
+    command: &str,
+) -> Result<(Option<Zeroizing<String>>, Option<SecretBuffer>), CliError> {
+    if credentials.password.is_none() && credentials.key.is_none() && credentials.key_name.is_none()
+    {
+        return Err(CliError::InvalidInput(format!(
+            "{command} needs --password, --key or --key-name"
+        )));
+    }
+    let key = match credentials.key_name {
+        Some(name) => Some(keystore::load_key(&name, config).await?),
+        None => credentials.key.map(Zeroizing::new),
+    };
+    let key = key.map(|key| validate_key(&key)).transpose()?;
+    Ok((credentials.password.map(Zeroizing::new), key))
+}
+
+/// Encrypts `plaintext` as a message, with no file name; returns the encrypted file and the
+/// mode used.
+pub(super) async fn seal(
+    plaintext: &[u8],
+    password: Option<&str>,
+    key: Option<&SecretBuffer>,
+) -> Result<(Vec<u8>, &'static str), CliError> {
+    match (password, key) {
+        (Some(password), _) => {
+            let outcome = api::encrypt_file(plaintext, Some(password), None, "")
+                .await
+                .map_err(|e| CliError::from_api("Encryption failed", e))?;
+            Ok((outcome.ciphertext, "password"))
+        }
+        // A plain key-mode file would carry the key in its header; wrapped in a keyslot, it
+        // stays out of the message
+        (None, Some(key)) => {
+            let level =
+                compression::worth_compressing("", plaintext).then_some(DEFAULT_COMPRESSION_LEVEL);
+            let body = Zeroizing::new(
+                api::encode_into(plaintext, level, compression::Tuning::default(), Vec::new())
+                    .map_err(|e| CliError::from_api("Encryption failed", e))?,
+            );
+            let encrypted = crypto::encrypt_with_wrapped_key(&body, key, "", None)
+                .await
+                .map_err(|e| CliError::from_crypto("Key encryption failed", e))?;
+            Ok((encrypted, "key"))
+        }
+        (None, None) => unreachable!("checked by resolve_credentials"),
+    }
+}
+
+/// Decrypts a message's encrypted file back into its text.
+pub(super) async fn open(
+    encrypted: &[u8],
+    password: Option<&str>,
+    key: Option<&SecretBuffer>,
+    config: &Config,
+) -> Result<Zeroizing<Vec<u8>>, CliError> {
+    let max_size = config
+        .max_decompressed_size()
+        .map_err(|e| CliError::InvalidInput(e.to_string()))?;
+    let (plaintext, _) =
+        api::decrypt_file_with_limit(encrypted, password, key.map(|k| &**k), max_size)
+            .await
+            .map_err(|e| match e {
+                ApiError::Crypto(crypto::CryptoError::FormatError) => {
+                    CliError::Format("The message is not an EncryptX file".to_string())
+                }
+                e => CliError::from_api("Decryption failed", e),
+            })?;
+    Ok(Zeroizing::new(plaintext))
+}
+
+/// Reads the command's input: `arg` itself, else stdin. A terminal is prompted, and the
+/// newline ending the typed text dropped.
+fn read_text(
+    arg: Option<String>,
+    prompt: &str,
+    what: &str,
+) -> Result<Zeroizing<Vec<u8>>, CliError> {
+    if let Some(text) = arg {
+        return Ok(Zeroizing::new(text.into_bytes()));
+    }
+    if io::stdin().is_terminal() {
+        eprintln!("{prompt}");
+        let mut text = Zeroizing::new(String::new());
+        io::stdin().read_to_string(&mut text)?;
+        if text.ends_with('\n') {
+            text.pop();
+        }
+        return Ok(Zeroizing::new(text.as_bytes().to_vec()));
+    }
+    Ok(Zeroizing::new(read_input(STDIO_PATH, what)?.to_vec()))
+}
+
+/// What `text encrypt --json` and `text decrypt --json` report. Written to stdout, the
+/// message or text goes into the report.
+#[derive(Serialize)]
+struct TextReport {
+    operation: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mode: Option<&'static str>,
+    input_size: usize,
+    output_size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+}
+
+/// Handles `text encrypt`: armors the message unless `binary`.
+pub async fn encrypt_command(
+    text: Option<String>,
+    credentials: MessageCredentials,
+    output: &str,
+    force: bool,
+    binary: bool,
+    config: &Config,
+    json: bool,
+) -> Result<(), CliError> {
+    let to_stdout = output == STDIO_PATH;
+    if !to_stdout {
+        check_output_file(output, force)?;
+    } else if binary && (json || io::stdout().is_terminal()) {
+        return Err(CliError::InvalidInput(
+            "--binary writes a bare encrypted file; give it an --output file".to_string(),
+        ));
+    }
+    let (password, key) = resolve_credentials(credentials, config, "text encrypt").await?;
+    let plaintext = read_text(text, &tr!("prompt-text-encrypt"), "text")?;
+    if plaintext.is_empty() {
+        return Err(CliError::InvalidInput(
+            "There is no text to encrypt".to_string(),
+        ));
+    }
+
+    let (encrypted, mode) = seal(
+        &plaintext,
+        password.as_deref().map(|p| p.as_str()),
+        key.as_ref(),
+    )
+    .await?;
+    let message = if binary {
+        encrypted
+    } else {
+        armor(&encrypted).into_bytes()
+    };
+
+    if json {
+        if !to_stdout {
+            write_output(output, &message, "message")?;
+        }
+        print_json_report(&TextReport {
+            operation: "text-encrypt",
+            mode: Some(mode),
+            input_size: plaintext.len(),
+            output_size: message.len(),
+            output: (!to_stdout).then(|| output.to_string()),
+            message: to_stdout.then(|| String::from_utf8_lossy(&message).into_owned()),
+            text: None,
+        });
+    } else {
+        write_output(output, &message, "message")?;
+        status(format!(
+            "🔐 {} bytes of text encrypted into a {}-byte message{}",
+            plaintext.len(),
+            message.len(),
+            if to_stdout {
+                String::new()
+            } else {
+                format!(" in '{output}'")
+            }
+        ));
+    }
+    Ok(())
+}
+
+/// Handles `text decrypt`: takes an armored message or a bare encrypted file.
+pub async fn decrypt_command(
+    message: Option<String>,
+    credentials: MessageCredentials,
+    output: &str,
+    force: bool,
+    config: &Config,
+    json: bool,
+) -> Result<(), CliError> {
+    let to_stdout = output == STDIO_PATH;
+    if !to_stdout {
+        check_output_file(output, force)?;
+    }
+    let (password, key) = resolve_credentials(credentials, config, "text decrypt").await?;
+    let input = read_text(message, &tr!("prompt-text-decrypt"), "message")?;
+    let encrypted = if is_armored(&input) {
+        dearmor(&String::from_utf8_lossy(&input))?
+    } else {
+        input.to_vec()
+    };
+    let plaintext = open(
+        &encrypted,
+        password.as_deref().map(|p| p.as_str()),
+        key.as_ref(),
+        config,
+    )
+    .await?;
+
+    if json {
+        if !to_stdout {
+            write_output(output, &plaintext, "text")?;
+        }
+        print_json_report(&TextReport {
+            operation: "text-decrypt",
+            mode: None,
+            input_size: input.len(),
+            output_size: plaintext.len(),
+            output: (!to_stdout).then(|| output.to_string()),
+            message: None,
+            text: to_stdout.then(|| String::from_utf8_lossy(&plaintext).into_owned()),
+        });
+    } else {
+        write_output(output, &plaintext, "text")?;
+        // A terminal gets the text on a line of its own
+        if to_stdout && io::stdout().is_terminal() && !plaintext.ends_with(b"\n") {
+            println!();
+        }
+        if !to_stdout {
+            status(format!(
+                "🔓 {} bytes of text decrypted into '{output}'",
+                plaintext.len()
+            ));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,261 @@
+//!
+//! `token` subcommand and helpers for FIDO2 hardware security keys (YubiKey, SoloKey, ...).
+//!
+//! Tokens are driven through the `fido2-token`, `fido2-cred` and `fido2-assert` tools from
+//! libfido2, the way the hook drives git, so no USB code is linked in. Set
+//! `ENCRYPTX_FIDO2_TOOLS` to the directory holding them if they aren't on `PATH`.
+//!
+//! `token enroll` creates a credential with the `hmac-secret` extension and saves its ID in a
+//! token file. The ID isn't secret: only the token that created it can answer for it.
+//! `keyslot add --new-token` then binds a slot to that credential, and `decrypt --token` asks
+//! the connected token for each fido2 slot's `hmac-secret`, which takes a touch.
+//!
+use super::{CliError, check_output_file, print_json_report, status};
+use crate::locale::tr;
+use base64::{Engine, engine::general_purpose};
+use rand::RngCore;
+use serde::Serialize;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use zeroize::Zeroizing;
+
+/// Relying party the credentials are created for.
+const RP_ID: &str = "encryptx";
+
+/// Prefix of the credential line in a token file.
+pub const TOKEN_PREFIX: &str = "xdfido1:";
+
+/// Environment variable naming the directory of the libfido2 tools.
+const TOOLS_ENV: &str = "ENCRYPTX_FIDO2_TOOLS";
+
+/// A connected FIDO2 device, as listed by `fido2-token -L`.
+#[derive(Serialize)]
+pub struct TokenDevice {
+    pub path: String,
+    pub description: String,
+}
+
+/// Result of `token list`, printed with `--json`.
+#[derive(Serialize)]
+pub struct TokenListReport {
+    pub operation: &'static str,
+    pub devices: Vec<TokenDevice>,
+}
+
+/// Result of `token enroll`, printed with `--json`.
+#[derive(Serialize)]
+pub struct TokenEnrollReport {
+    pub operation: &'static str,
+    pub output: String,
+    /// Device the credential was created on
+    pub device: String,
+}
+
+fn tool_path(name: &str) -> PathBuf {
+    match std::env::var_os(TOOLS_ENV) {
+        Some(dir) => PathBuf::from(dir).join(name),
+        None => PathBuf::from(name),
+    }
+}
+
+/// Runs a libfido2 tool with `input` on its stdin.
+fn run_tool(name: &str, args: &[&str], input: &str) -> Result<Output, CliError> {
+    let mut child = Command::new(tool_path(name))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => CliError::InvalidInput(format!(
+                "{name} not found; install the libfido2 tools or set {TOOLS_ENV}"
+            )),
+            _ => CliError::Io(io::Error::new(
+                e.kind(),
+                format!("Failed to run {name}: {e}"),
+            )),
+        })?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes())?;
+    }
+    Ok(child.wait_with_output()?)
+}
+
+fn tool_failed(name: &str, output: &Output) -> CliError {
+    CliError::InvalidInput(format!(
+        "{name} failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+    ))
+}
+
+fn random_base64(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    general_purpose::STANDARD.encode(bytes)
+}
+
+/// Lists the connected FIDO2 devices.
+pub fn list_devices() -> Result<Vec<TokenDevice>, CliError> {
+    let output = run_tool("fido2-token", &["-L"], "")?;
+    if !output.status.success() {
+        return Err(tool_failed("fido2-token", &output));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (path, description) = line.split_once(": ")?;
+            Some(TokenDevice {
+                path: path.trim().to_string(),
+                description: description.trim().to_string(),
+            })
+        })
+        .collect())
+}
+
+/// Returns `device`, or the only connected device if none is given.
+pub fn pick_device(device: Option<&str>) -> Result<String, CliError> {
+    if let Some(device) = device {
+        return Ok(device.to_string());
+    }
+    match list_devices()?.as_slice() {
+        [] => Err(CliError::InvalidInput(
+            "No FIDO2 token found; plug one in".to_string(),
+        )),
+        [device] => Ok(device.path.clone()),
+        _ => Err(CliError::InvalidInput(
+            "Several FIDO2 tokens are connected; choose one with --token-device (see `token list`)"
+                .to_string(),
+        )),
+    }
+}
+
+/// Creates a new `hmac-secret` credential on `device` and returns its ID.
+pub fn enroll(device: &str) -> Result<Vec<u8>, CliError> {
+    // Attestation isn't checked, so the client data hash is just random
+    let input = format!(
+        "{}\n{RP_ID}\nencryptx\n{}\n",
+        random_base64(32),
+        random_base64(16)
+    );
+    let output = run_tool("fido2-cred", &["-M", "-h", device], &input)?;
+    if !output.status.success() {
+        return Err(tool_failed("fido2-cred", &output));
+    }
+    // client data hash, rp id, format, authenticator data, credential id, ...
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .nth(4)
+        .and_then(|line| general_purpose::STANDARD.decode(line.trim()).ok())
+        .ok_or_else(|| CliError::Format("fido2-cred printed no credential ID".to_string()))
+}
+
+/// Asks `device` for the `hmac-secret` of `salt` under `credential_id`, which takes a touch.
+///
+/// Returns `None` if the token refuses, e.g. because it doesn't hold the credential.
+pub fn hmac_secret(
+    device: &str,
+    credential_id: &[u8],
+    salt: &[u8],
+) -> Result<Option<Zeroizing<Vec<u8>>>, CliError> {
+    let input = format!(
+        "{}\n{RP_ID}\n{}\n{}\n",
+        random_base64(32),
+        general_purpose::STANDARD.encode(credential_id),
+        general_purpose::STANDARD.encode(salt)
+    );
+    let output = run_tool("fido2-assert", &["-G", "-h", "-p", device], &input)?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    // The hmac-secret is the last line of the assertion
+    let stdout = Zeroizing::new(output.stdout);
+    Ok(String::from_utf8_lossy(&stdout)
+        .lines()
+        .last()
+        .and_then(|line| general_purpose::STANDARD.decode(line.trim()).ok())
+        .filter(|secret| secret.len() == 32)
+        .map(Zeroizing::new))
+}
+
+/// Returns a callback for [`crate::crypto::decrypt_with_token`] that asks `device`.
+pub fn asker(device: &str) -> impl FnMut(&[u8], &[u8]) -> Option<Zeroizing<Vec<u8>>> + '_ {
+    move |credential_id, salt| {
+        status(tr!("token-touch"));
+        hmac_secret(device, credential_id, salt).ok().flatten()
+    }
+}
+
+/// Reads the credential ID from a token file written by `token enroll`.
+pub fn read_token_file(path: &str) -> Result<Vec<u8>, CliError> {
+    let text = fs::read_to_string(path).map_err(|e| {
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to read token file '{path}': {e}"),
+        ))
+    })?;
+    text.lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with('#'))
+        .and_then(|l| l.strip_prefix(TOKEN_PREFIX))
+        .and_then(|encoded| general_purpose::STANDARD.decode(encoded).ok())
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| CliError::InvalidInput(format!("'{path}' is not a valid token file")))
+}
+
+/// Handles `token list`.
+pub fn list_command(json: bool) -> Result<(), CliError> {
+    let devices = list_devices()?;
+    if json {
+        print_json_report(&TokenListReport {
+            operation: "token-list",
+            devices,
+        });
+        return Ok(());
+    }
+    if devices.is_empty() {
+        println!("{}", tr!("token-none"));
+    }
+    for device in &devices {
+        println!(
+            "{}",
+            tr!(
+                "token-device",
+                path = device.path,
+                description = device.description
+            )
+        );
+    }
+    Ok(())
+}
+
+/// Handles `token enroll`.
+pub fn enroll_command(
+    output: &str,
+    device: Option<&str>,
+    force: bool,
+    json: bool,
+) -> Result<(), CliError> {
+    check_output_file(output, force)?;
+    let device = pick_device(device)?;
+    status(tr!("token-touch-enroll"));
+    let credential_id = enroll(&device)?;
+    let contents = format!(
+        "# EncryptX FIDO2 credential (useless without the token that created it)\n{TOKEN_PREFIX}{}\n",
+        general_purpose::STANDARD.encode(&credential_id)
+    );
+    fs::write(output, contents)?;
+
+    if json {
+        print_json_report(&TokenEnrollReport {
+            operation: "token-enroll",
+            output: output.to_string(),
+            device,
+        });
+        return Ok(());
+    }
+    status(tr!("token-written", output = output));
+    status(tr!("token-hint", output = output));
+    Ok(())
+}
@@ -0,0 +1,247 @@
+//!
+//! TPM 2.0 sealing: `encrypt --tpm` seals a keyslot's key-encryption key to this machine's
+//! TPM, and `decrypt` unseals it again without asking for a credential.
+//!
+//! The TPM is driven through the tpm2-tools (`tpm2_createprimary`, `tpm2_createpolicy`,
+//! `tpm2_create`, `tpm2_load`, `tpm2_unseal`), which reach it through their usual TCTI
+//! (`/dev/tpmrm0` on Linux; set `TPM2TOOLS_TCTI` to use another). Set `ENCRYPTX_TPM2_TOOLS`
+//! to the directory holding them if they aren't on `PATH`.
+//!
+//! The sealed object is created under the owner hierarchy's primary storage key, which the
+//! TPM derives from its seed the same way every time, so the slot only needs the object's
+//! public and private parts. With `--tpm-pcrs`, the object's policy requires the selected
+//! PCRs to hold the values they had when it was sealed (e.g. PCR 7 for the Secure Boot state).
+//!
+use super::{CliError, status};
+use crate::crypto::{keyslot::KIND_TPM2, keyslot::TpmObject};
+use crate::format::XdReader;
+use rand::RngCore;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use zeroize::Zeroizing;
+
+/// Environment variable naming the directory of the tpm2-tools.
+const TOOLS_ENV: &str = "ENCRYPTX_TPM2_TOOLS";
+
+/// Hash banks a PCR selection may name.
+const PCR_BANKS: [&str; 4] = ["sha1", "sha256", "sha384", "sha512"];
+
+/// An object sealed by the TPM.
+pub struct Sealed {
+    pub public: Vec<u8>,
+    pub private: Vec<u8>,
+}
+
+/// Scratch directory for the tools' context files, removed when dropped.
+///
+/// Only TPM-protected blobs and context handles are stored there; secrets go through pipes.
+struct Workdir(PathBuf);
+
+impl Workdir {
+    fn new() -> Result<Self, CliError> {
+        let path = std::env::temp_dir().join(format!(
+            "encryptx-tpm-{}-{:016x}",
+            std::process::id(),
+            rand::rngs::OsRng.next_u64()
+        ));
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&path)?;
+        Ok(Self(path))
+    }
+
+    fn file(&self, name: &str) -> String {
+        self.0.join(name).to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for Workdir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn tool_path(name: &str) -> PathBuf {
+    match std::env::var_os(TOOLS_ENV) {
+        Some(dir) => PathBuf::from(dir).join(name),
+        None => PathBuf::from(name),
+    }
+}
+
+/// Runs a tpm2-tools command with `input` on its stdin.
+fn run_tool(name: &str, args: &[&str], input: &[u8]) -> Result<Output, CliError> {
+    let mut child = Command::new(tool_path(name))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => CliError::InvalidInput(format!(
+                "{name} not found; install tpm2-tools or set {TOOLS_ENV}"
+            )),
+            _ => CliError::Io(io::Error::new(
+                e.kind(),
+                format!("Failed to run {name}: {e}"),
+            )),
+        })?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // The tool may fail before reading its input; its stderr says why
+        match stdin.write_all(input) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(child.wait_with_output()?)
+}
+
+/// Runs a tool that has no input, returning its stdout or its error message.
+fn run_step(name: &str, args: &[&str]) -> Result<Result<Vec<u8>, String>, CliError> {
+    let output = run_tool(name, args, &[])?;
+    if !output.status.success() {
+        return Ok(Err(format!(
+            "{name} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(Ok(output.stdout))
+}
+
+/// Checks a `--tpm-pcrs` selection, e.g. `0,7` or `sha256:0,7`, and returns it with its bank.
+pub fn validate_pcrs(spec: &str) -> Result<String, CliError> {
+    let (bank, indices) = spec.split_once(':').unwrap_or(("sha256", spec));
+    let valid = PCR_BANKS.contains(&bank)
+        && !indices.is_empty()
+        && indices
+            .split(',')
+            .all(|i| i.parse::<u8>().is_ok_and(|i| i < 24));
+    if !valid {
+        return Err(CliError::InvalidInput(format!(
+            "--tpm-pcrs '{spec}' must be PCR indices 0-23, optionally after a bank, e.g. sha256:0,7"
+        )));
+    }
+    Ok(format!("{bank}:{indices}"))
+}
+
+/// Creates the primary storage key the sealed objects live under.
+fn create_primary(dir: &Workdir) -> Result<Result<String, String>, CliError> {
+    let primary = dir.file("primary.ctx");
+    Ok(run_step(
+        "tpm2_createprimary",
+        &["-Q", "-C", "o", "-g", "sha256", "-G", "ecc", "-c", &primary],
+    )?
+    .map(|_| primary))
+}
+
+/// Seals `secret` to the TPM, under a policy on `pcrs` if given.
+pub fn seal(secret: &[u8], pcrs: Option<&str>) -> Result<Sealed, CliError> {
+    let failed =
+        |message: String| CliError::Crypto(format!("Sealing to the TPM failed: {message}"));
+    let dir = Workdir::new()?;
+    let primary = create_primary(&dir)?.map_err(failed)?;
+    let (public, private, policy) = (
+        dir.file("seal.pub"),
+        dir.file("seal.priv"),
+        dir.file("policy.digest"),
+    );
+    let mut args = vec![
+        "-Q", "-C", &primary, "-i", "-", "-u", &public, "-r", &private,
+    ];
+    if let Some(pcrs) = pcrs {
+        run_step(
+            "tpm2_createpolicy",
+            &["-Q", "--policy-pcr", "-l", pcrs, "-L", &policy],
+        )?
+        .map_err(failed)?;
+        // Without userwithauth the object can only be used by satisfying the policy
+        args.extend(["-L", &policy, "-a", "fixedtpm|fixedparent"]);
+    }
+    let output = run_tool("tpm2_create", &args, secret)?;
+    if !output.status.success() {
+        return Err(failed(format!(
+            "tpm2_create failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(Sealed {
+        public: fs::read(&public)?,
+        private: fs::read(&private)?,
+    })
+}
+
+/// Unseals an object created by [`seal`], returning the secret or the TPM's reason for
+/// refusing.
+fn unseal(object: &TpmObject<'_>) -> Result<Result<Zeroizing<Vec<u8>>, String>, CliError> {
+    let dir = Workdir::new()?;
+    let primary = match create_primary(&dir)? {
+        Ok(primary) => primary,
+        Err(message) => return Ok(Err(message)),
+    };
+    let (public_file, private_file, context) = (
+        dir.file("seal.pub"),
+        dir.file("seal.priv"),
+        dir.file("seal.ctx"),
+    );
+    fs::write(&public_file, object.public)?;
+    fs::write(&private_file, object.private)?;
+    if let Err(message) = run_step(
+        "tpm2_load",
+        &[
+            "-Q",
+            "-C",
+            &primary,
+            "-u",
+            &public_file,
+            "-r",
+            &private_file,
+            "-c",
+            &context,
+        ],
+    )? {
+        return Ok(Err(message));
+    }
+    let policy = object.pcrs.map(|pcrs| format!("pcr:{pcrs}"));
+    let mut args = vec!["-c", context.as_str()];
+    if let Some(policy) = &policy {
+        args.extend(["-p", policy.as_str()]);
+    }
+    Ok(run_step("tpm2_unseal", &args)?.map(Zeroizing::new))
+}
+
+/// Whether `data` is a keyslot file with a slot sealed to a TPM.
+pub fn sealed_to_tpm(data: &[u8]) -> bool {
+    XdReader::parse(data)
+        .ok()
+        .and_then(|file| {
+            let (slots, _) = file.header.keyslots()?;
+            Some(slots.iter().any(|slot| slot.kind == KIND_TPM2))
+        })
+        .unwrap_or(false)
+}
+
+/// Returns a callback for [`crypto::decrypt_with_tpm`].
+///
+/// Slots the TPM won't unseal are skipped; its reason is shown, since changed PCR values or
+/// another machine's slot would otherwise look like a wrong key.
+pub fn asker() -> impl FnMut(&TpmObject<'_>) -> Option<Zeroizing<Vec<u8>>> {
+    move |object| {
+        match object.pcrs {
+            Some(pcrs) => status(format!("🔐 Unsealing with the TPM (PCRs {pcrs})...")),
+            None => status("🔐 Unsealing with the TPM..."),
+        }
+        match unseal(object) {
+            Ok(Ok(secret)) => Some(secret),
+            Ok(Err(message)) => {
+                status(format!("⚠️  The TPM refused: {message}"));
+                None
+            }
+            Err(e) => {
+                status(format!("⚠️  {e}"));
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,603 @@
+//!
+//! `encrypt --dir`: encrypts every file below a directory on its own, into the same tree
+//! below the output directory, so single files can be restored or synced without the rest.
+//! An encrypted manifest of the files (see [`super::manifest`]) is written alongside.
+//!
+//! With `--incremental`, a state file records the size, modification time and SHA-256 hash
+//! of each file encrypted. A later run skips the files whose size and time are unchanged
+//! without reading them, and those whose contents hash the same, so repeated backups of a
+//! large tree only pay for what changed. The state lives with the plaintext (by default
+//! in the directory itself), never with the encrypted copies, since the hashes would let
+//! anyone holding it confirm guesses of the contents.
+//!
+//! Runs into the same output directory may overlap, e.g. a scheduled backup and one started
+//! by hand. The manifest and the state file are therefore replaced under a lock beside the
+//! manifest, and what another run changed in them since this one started is merged in, so
+//! neither run drops the entries of the other.
+//!
+use super::lock;
+use super::manifest::{
+    MANIFEST_FILE, MANIFEST_NAME, Manifest, ManifestEntry, hash_file, open_manifest,
+};
+use super::{
+    CliError, EncryptArgs, OutputFormat, STATUS_MUTED, STDIO_PATH, check_password_strength,
+    encrypt_command, keystore, print_json_report, show_secret, staging_dir, status, validate_key,
+    walk_filter, write_atomic,
+};
+use crate::config::Config;
+use crate::walk::{self, EntryKind, SymlinkPolicy};
+use base64::{Engine, engine::general_purpose};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::{Instant, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+/// Name of the state file `--incremental` keeps in the directory unless `--state` is given.
+const STATE_FILE: &str = ".encryptx-state.json";
+
+/// Version of the state file layout.
+const STATE_VERSION: u32 = 1;
+
+/// What `--incremental` knows about the last run.
+#[derive(PartialEq, Serialize, Deserialize)]
+struct State {
+    version: u32,
+    /// Output directory the files were encrypted into; a run into another one starts over
+    output: String,
+    /// Files by their path relative to the directory, using `/` separators
+    files: BTreeMap<String, Record>,
+}
+
+/// A file as it was when it was last encrypted.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+struct Record {
+    size: u64,
+    /// Modification time, in nanoseconds since the Unix epoch
+    modified_ns: u64,
+    sha256: String,
+}
+
+/// What happened to one file.
+#[derive(Serialize)]
+struct FileResult {
+    file: String,
+    /// "encrypted", "removed", "skipped" or "failed"
+    status: &'static str,
+    /// Why the file was skipped or failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+/// Result of an `encrypt --dir` run, printed with `--json`. Unchanged files are only
+/// counted.
+#[derive(Serialize)]
+struct DirReport {
+    operation: &'static str,
+    mode: &'static str,
+    input: String,
+    output: String,
+    encrypted: usize,
+    unchanged: usize,
+    removed: usize,
+    skipped: usize,
+    /// Bytes of plaintext encrypted by this run
+    input_size: usize,
+    /// Bytes of ciphertext written by this run
+    output_size: usize,
+    /// Randomly generated key, base64 (only when one was generated)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    generated_key: Option<String>,
+    files: Vec<FileResult>,
+    duration_ms: u128,
+}
+
+/// Handles `encrypt --dir`. Each file goes through the ordinary single-file encryption with
+/// the same options; a failure on one file doesn't stop the others.
+pub async fn encrypt_dir(args: EncryptArgs, config: &Config, json: bool) -> Result<(), CliError> {
+    let started = Instant::now();
+    let dir = args.dir.clone().unwrap_or_default();
+    let output = args.output.clone().unwrap_or_default();
+    let incremental = args.incremental;
+    if args.format != OutputFormat::Xd || output == STDIO_PATH {
+        return Err(CliError::InvalidInput(
+            "--dir needs an output directory and --format xd".to_string(),
+        ));
+    }
+    let symlinks = if args.follow_symlinks {
+        SymlinkPolicy::Follow
+    } else {
+        SymlinkPolicy::Skip
+    };
+    let filter = walk_filter(&args.exclude_from, &args.exclude, &args.include)?;
+
+    let root = fs::canonicalize(&dir).map_err(|e| {
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to resolve '{dir}': {e}"),
+        ))
+    })?;
+    if !root.is_dir() {
+        return Err(CliError::InvalidInput(format!(
+            "--dir needs a directory, and '{dir}' is not one"
+        )));
+    }
+    let out_root = output_dir(&output, &root)?;
+
+    // Credentials are settled once for the whole tree, not once per file
+    let (key, generated_key) = settle_credentials(&args, &dir, config).await?;
+
+    let state_path = match &args.state {
+        Some(path) => PathBuf::from(path),
+        None => root.join(STATE_FILE),
+    };
+    let out_name = out_root.to_string_lossy().into_owned();
+    // As they are now, to tell what another run changes in them meanwhile
+    let manifest_path = out_root.join(MANIFEST_FILE);
+    let manifest_base = fs::read(&manifest_path).ok();
+    let state_base = if incremental {
+        load_state(&state_path)?
+    } else {
+        None
+    };
+    let previous = state_base.as_ref().filter(|state| state.output == out_name);
+    let mut state = State {
+        version: STATE_VERSION,
+        output: out_name,
+        files: BTreeMap::new(),
+    };
+    // The state file may be in the directory itself, but is no file to encrypt
+    let state_entry = fs::canonicalize(staging_dir(&state_path))
+        .ok()
+        .zip(state_path.file_name())
+        .map(|(parent, name)| parent.join(name));
+
+    let entries = walk::walk(&root, symlinks, &filter).map_err(|e| {
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to walk '{dir}': {e}"),
+        ))
+    })?;
+    let template = EncryptArgs {
+        dir: None,
+        incremental: false,
+        state: None,
+        follow_symlinks: false,
+        skip_symlinks: false,
+        exclude: Vec::new(),
+        exclude_from: Vec::new(),
+        include: Vec::new(),
+        key: key.as_deref().cloned(),
+        key_name: None,
+        enforce_strong_password: false,
+        // Outputs the state vouches for are this command's to replace
+        force: args.force || incremental,
+        ..args
+    };
+
+    let mut results = Vec::new();
+    let mut failures = Vec::new();
+    let mut manifest = Vec::new();
+    // Files this run settled, which win over what another run recorded for them
+    let mut touched = BTreeSet::new();
+    let (mut unchanged, mut input_size, mut output_size) = (0, 0, 0);
+    for entry in entries {
+        if entry.kind == EntryKind::Dir || state_entry.as_ref() == Some(&entry.path) {
+            continue;
+        }
+        if entry.kind != EntryKind::File {
+            let result = FileResult {
+                file: entry.name,
+                status: "skipped",
+                detail: Some("not a regular file".to_string()),
+            };
+            push_result(&mut results, result, json);
+            continue;
+        }
+        let output_name = format!("{}.xd", entry.name);
+        let target = out_root.join(&output_name);
+        // Hashed for the manifest in any case; incrementally, a file the state vouches for
+        // isn't even read
+        let known = previous
+            .as_ref()
+            .and_then(|p| p.files.get(&entry.name))
+            .filter(|_| target.is_file());
+        let (record, same) = match current_record(&entry.path, known) {
+            Ok(current) => current,
+            Err(e) => {
+                let e = CliError::Io(io::Error::new(
+                    e.kind(),
+                    format!("Failed to read '{}': {e}", entry.path.display()),
+                ));
+                fail(&mut results, &mut failures, entry.name, e, json);
+                continue;
+            }
+        };
+        let listed = ManifestEntry {
+            path: entry.name.clone(),
+            size: record.size,
+            sha256: record.sha256.clone(),
+            output: output_name,
+        };
+        if same {
+            touched.insert(entry.name.clone());
+            state.files.insert(entry.name, record);
+            manifest.push(listed);
+            unchanged += 1;
+            continue;
+        }
+
+        let file_args = EncryptArgs {
+            file: Some(entry.path.to_string_lossy().into_owned()),
+            output: Some(target.to_string_lossy().into_owned()),
+            ..template.clone()
+        };
+        let result = match target.parent().map(fs::create_dir_all).transpose() {
+            Ok(_) => {
+                STATUS_MUTED.store(true, Ordering::Relaxed);
+                let result = encrypt_command(file_args, config, json).await;
+                STATUS_MUTED.store(false, Ordering::Relaxed);
+                result
+            }
+            Err(e) => Err(CliError::Io(io::Error::new(
+                e.kind(),
+                format!(
+                    "Failed to create a directory for '{}': {e}",
+                    target.display()
+                ),
+            ))),
+        };
+        match result {
+            Ok(report) => {
+                input_size += report.input_size;
+                output_size += report.output_size;
+                touched.insert(entry.name.clone());
+                state.files.insert(entry.name.clone(), record);
+                manifest.push(listed);
+                let result = FileResult {
+                    file: entry.name,
+                    status: "encrypted",
+                    detail: None,
+                };
+                push_result(&mut results, result, json);
+            }
+            // Left out of the state, so the next run tries again
+            Err(e) => fail(&mut results, &mut failures, entry.name, e, json),
+        }
+    }
+
+    // Files gone from the directory take their encrypted copies with them
+    if let Some(previous) = previous {
+        let failed: Vec<String> = failures.iter().map(|(name, _)| name.clone()).collect();
+        for name in previous.files.keys() {
+            if state.files.contains_key(name) || failed.contains(name) {
+                continue;
+            }
+            let target = out_root.join(format!("{name}.xd"));
+            let result = match fs::remove_file(&target) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(CliError::Io(io::Error::new(
+                    e.kind(),
+                    format!("Failed to remove '{}': {e}", target.display()),
+                ))),
+                _ => Ok(()),
+            };
+            touched.insert(name.clone());
+            match result {
+                Ok(()) => {
+                    let result = FileResult {
+                        file: name.clone(),
+                        status: "removed",
+                        detail: None,
+                    };
+                    push_result(&mut results, result, json);
+                }
+                Err(e) => {
+                    // Still recorded, so the next run tries again
+                    state
+                        .files
+                        .insert(name.clone(), previous.files[name].clone());
+                    fail(&mut results, &mut failures, name.clone(), e, json);
+                }
+            }
+        }
+    }
+    // The manifest lists what the output directory holds now, failures excepted
+    let _lock = lock::lock_beside(&manifest_path)?;
+    let manifest =
+        merged_manifest(manifest, manifest_base, &manifest_path, &touched, &template).await;
+    let manifest = serde_json::to_vec(&Manifest::new(manifest))
+        .map_err(|e| CliError::Crypto(format!("Failed to serialize the manifest: {e}")))?;
+    let manifest_args = EncryptArgs {
+        file: Some(MANIFEST_NAME.to_string()),
+        output: Some(manifest_path.to_string_lossy().into_owned()),
+        contents: Some(Zeroizing::new(manifest)),
+        force: true,
+        ..template
+    };
+    STATUS_MUTED.store(true, Ordering::Relaxed);
+    let written = encrypt_command(manifest_args, config, json).await;
+    STATUS_MUTED.store(false, Ordering::Relaxed);
+    if let Err(e) = written {
+        fail(
+            &mut results,
+            &mut failures,
+            MANIFEST_FILE.to_string(),
+            e,
+            json,
+        );
+    }
+
+    if incremental {
+        // Another run's state is only merged if it is for the same output directory
+        let same_output = |s: &State| s.output == state.output;
+        if let Some(current) = load_state(&state_path)?.filter(same_output)
+            && Some(&current) != state_base.as_ref()
+        {
+            let base = state_base.filter(same_output).map(|s| s.files);
+            merge(
+                &mut state.files,
+                &base.unwrap_or_default(),
+                current.files,
+                &touched,
+            );
+        }
+        let contents = serde_json::to_string_pretty(&state)
+            .map_err(|e| CliError::Crypto(format!("Failed to serialize the state: {e}")))?;
+        write_atomic(&state_path.to_string_lossy(), contents.as_bytes())?;
+    }
+
+    let count = |status| results.iter().filter(|r| r.status == status).count();
+    let (encrypted, removed, skipped) = (count("encrypted"), count("removed"), count("skipped"));
+    if failures.len() == 1 && encrypted + removed + unchanged == 0 {
+        // A single file keeps its own exit code, e.g. 3 for an unreadable file
+        let (_, error) = failures.remove(0);
+        return Err(error);
+    }
+    if !failures.is_empty() {
+        let names: Vec<_> = failures.iter().map(|(name, _)| name.as_str()).collect();
+        return Err(CliError::Crypto(format!(
+            "{} file(s) of '{dir}' could not be encrypted: {}",
+            failures.len(),
+            names.join(", ")
+        )));
+    }
+    if json {
+        print_json_report(&DirReport {
+            operation: "encrypt",
+            mode: "dir",
+            input: dir,
+            output,
+            encrypted,
+            unchanged,
+            removed,
+            skipped,
+            input_size,
+            output_size,
+            generated_key,
+            files: results,
+            duration_ms: started.elapsed().as_millis(),
+        });
+    } else {
+        status(format!(
+            "✅ {encrypted} file(s) encrypted into '{output}', {unchanged} unchanged, {removed} removed, {skipped} skipped"
+        ));
+    }
+    Ok(())
+}
+
+/// `ours` with the changes another run made to the manifest at `path` since it read as
+/// `base` carried over (see [`merge`]). A manifest this run can't open, e.g. one encrypted
+/// with other credentials, is replaced as it was before.
+async fn merged_manifest(
+    ours: Vec<ManifestEntry>,
+    base: Option<Vec<u8>>,
+    path: &Path,
+    touched: &BTreeSet<String>,
+    args: &EncryptArgs,
+) -> Vec<ManifestEntry> {
+    let current = fs::read(path).ok();
+    let Some(current) = current.filter(|current| Some(current) != base.as_ref()) else {
+        return ours;
+    };
+    let Ok(key) = args.key.as_deref().map(validate_key).transpose() else {
+        return ours;
+    };
+    let open = async |data: &[u8]| {
+        open_manifest(
+            data,
+            MANIFEST_FILE,
+            args.password.as_deref(),
+            key.as_deref(),
+        )
+        .await
+        .map(Manifest::into_files)
+    };
+    let theirs = open(&current).await;
+    let base = match &base {
+        Some(base) => open(base).await,
+        None => Ok(BTreeMap::new()),
+    };
+    let (theirs, base) = match (theirs, base) {
+        (Ok(theirs), Ok(base)) => (theirs, base),
+        (Err(e), _) | (_, Err(e)) => {
+            tracing::debug!("Replacing '{}' without merging: {e}", path.display());
+            return ours;
+        }
+    };
+    let mut merged = ours
+        .into_iter()
+        .map(|entry| (entry.path.clone(), entry))
+        .collect();
+    merge(&mut merged, &base, theirs, touched);
+    merged.into_values().collect()
+}
+
+/// Carries over into `ours` what another run changed between `base` and `theirs`: entries
+/// it added or replaced, and entries it dropped, unless this run `touched` them itself.
+fn merge<T: Clone + PartialEq>(
+    ours: &mut BTreeMap<String, T>,
+    base: &BTreeMap<String, T>,
+    theirs: BTreeMap<String, T>,
+    touched: &BTreeSet<String>,
+) {
+    for name in base.keys() {
+        if !theirs.contains_key(name) && !touched.contains(name) {
+            ours.remove(name);
+        }
+    }
+    for (name, entry) in theirs {
+        if !touched.contains(&name) && base.get(&name) != Some(&entry) {
+            ours.insert(name, entry);
+        }
+    }
+}
+
+/// Settles the credentials of a run over many files once, rather than once per file: the
+/// password is checked (against `input`'s name), a named key is loaded, and without any
+/// credential a random key is generated and shown. Returns the key, base64, and the same
+/// again if it was generated.
+pub(super) async fn settle_credentials(
+    args: &EncryptArgs,
+    input: &str,
+    config: &Config,
+) -> Result<(Option<Zeroizing<String>>, Option<String>), CliError> {
+    if let Some(password) = &args.password {
+        check_password_strength(password, input, args.enforce_strong_password)?;
+    }
+    let key = match &args.key_name {
+        Some(name) => Some(keystore::load_key(name, config).await?),
+        None => args.key.clone().map(Zeroizing::new),
+    };
+    let other_credential = !args.recipients.is_empty()
+        || args.quorum.is_some()
+        || args.tpm
+        || args.kms.is_some()
+        || args.pkcs11.pkcs11_module.is_some();
+    if key.is_some() || args.password.is_some() || other_credential {
+        return Ok((key, None));
+    }
+    let mut bytes = Zeroizing::new([0u8; 32]);
+    rand::rngs::OsRng
+        .try_fill_bytes(&mut bytes[..])
+        .map_err(|e| CliError::Crypto(format!("Failed to generate key: {e}")))?;
+    let key_b64 = general_purpose::STANDARD.encode(&bytes[..]);
+    show_secret(format!("✨ Generated random key (base64): {key_b64}"));
+    show_secret("💡 Save this key somewhere safe! You'll need it to decrypt every file.");
+    show_secret("⚠️  This key will NOT be shown again!");
+    Ok((Some(Zeroizing::new(key_b64.clone())), Some(key_b64)))
+}
+
+/// Creates the output directory, refusing one inside the directory being encrypted, which
+/// would encrypt its own output on the next run.
+pub(super) fn output_dir(output: &str, root: &Path) -> Result<PathBuf, CliError> {
+    let existed = Path::new(output).exists();
+    let out_root = fs::create_dir_all(output)
+        .and_then(|()| fs::canonicalize(output))
+        .map_err(|e| {
+            CliError::Io(io::Error::new(
+                e.kind(),
+                format!("Failed to create output directory '{output}': {e}"),
+            ))
+        })?;
+    if out_root.starts_with(root) {
+        if !existed {
+            let _ = fs::remove_dir(&out_root);
+        }
+        return Err(CliError::InvalidInput(format!(
+            "The output directory '{output}' is inside the directory being encrypted"
+        )));
+    }
+    Ok(out_root)
+}
+
+/// Reads the state of the last run, if there was one.
+fn load_state(path: &Path) -> Result<Option<State>, CliError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(CliError::Io(io::Error::new(
+                e.kind(),
+                format!("Failed to read state file '{}': {e}", path.display()),
+            )));
+        }
+    };
+    let state: State = serde_json::from_str(&contents).map_err(|e| {
+        CliError::Format(format!(
+            "'{}' is not a valid state file: {e}",
+            path.display()
+        ))
+    })?;
+    if state.version != STATE_VERSION {
+        return Err(CliError::Format(format!(
+            "State file '{}' has unsupported version {}",
+            path.display(),
+            state.version
+        )));
+    }
+    Ok(Some(state))
+}
+
+/// The record of a file as it is now, and whether it matches `known`. A file whose size
+/// and modification time match isn't read.
+fn current_record(path: &Path, known: Option<&Record>) -> io::Result<(Record, bool)> {
+    let meta = fs::metadata(path)?;
+    let modified_ns = meta
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos() as u64);
+    if let Some(known) = known
+        && known.size == meta.len()
+        && known.modified_ns == modified_ns
+    {
+        return Ok((known.clone(), true));
+    }
+    let sha256 = hash_file(path)?;
+    // Touched but not edited: nothing to encrypt, only the new time to record
+    let same = known.is_some_and(|known| known.sha256 == sha256);
+    let record = Record {
+        size: meta.len(),
+        modified_ns,
+        sha256,
+    };
+    Ok((record, same))
+}
+
+/// Adds the outcome of a file, printing it as it happens unless the report is JSON.
+fn push_result(results: &mut Vec<FileResult>, result: FileResult, json: bool) {
+    if !json {
+        print_result(&result);
+    }
+    results.push(result);
+}
+
+fn fail(
+    results: &mut Vec<FileResult>,
+    failures: &mut Vec<(String, CliError)>,
+    name: String,
+    error: CliError,
+    json: bool,
+) {
+    let result = FileResult {
+        file: name.clone(),
+        status: "failed",
+        detail: Some(error.to_string()),
+    };
+    push_result(results, result, json);
+    failures.push((name, error));
+}
+
+fn print_result(result: &FileResult) {
+    let file = &result.file;
+    let detail = result.detail.as_deref().unwrap_or_default();
+    match result.status {
+        "encrypted" => status(format!("🔐 '{file}' encrypted")),
+        "removed" => status(format!("🗑️  '{file}' removed (no longer in the directory)")),
+        "skipped" => status(format!("⏭️  '{file}' skipped: {detail}")),
+        _ => tracing::warn!("❌ '{file}' failed: {detail}"),
+    }
+}
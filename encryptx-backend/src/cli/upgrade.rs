@@ -0,0 +1,315 @@
+//!
+//! `upgrade` subcommand: rewrites files from older format versions in the current format.
+//!
+//! A file is legacy if its header is older than this build writes for its mode (see
+//! [`CURRENT_VERSION`]), or its password key is derived with PBKDF2 or with less memory
+//! than the configured KDF. Such files are decrypted in memory and encrypted again under
+//! the same password or key with the configured KDF, in the chunked layout, which
+//! authenticates the header. Files that are already current are left alone, so a whole
+//! directory can be passed in one go; a failure on one file doesn't stop the others.
+//!
+//! Keyslot, master-key and convergent files are reported and skipped: their keys are tied
+//! to other secrets, so `rekey` is the way to move them.
+//!
+use super::{
+    CliError, STDIO_PATH, UpgradeArgs, lock, print_json_report, read_input, status, validate_key,
+    with_kdf_profile,
+};
+use crate::config::Config;
+use crate::crypto::{self, PasswordKdf};
+use crate::format::{CURRENT_VERSION, ParsedHeader, XdReader};
+use crate::{interop, stego};
+use rand::RngCore;
+use serde::Serialize;
+use std::time::Instant;
+use zeroize::Zeroizing;
+
+/// Plaintext bytes per chunk for files that were a single message, unless the config sets
+/// `chunk_size`.
+const DEFAULT_CHUNK_SIZE: u32 = 1024 * 1024;
+
+/// What happened to one file.
+#[derive(Serialize)]
+struct FileResult {
+    file: String,
+    /// "upgraded", "current", "skipped", "failed", or "outdated" in a dry run
+    status: &'static str,
+    /// Why the file counts as legacy
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    reasons: Vec<String>,
+    /// Why the file was skipped or failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+/// Result of an `upgrade` run, printed with `--json`.
+#[derive(Serialize)]
+struct UpgradeReport {
+    operation: &'static str,
+    dry_run: bool,
+    upgraded: usize,
+    current: usize,
+    skipped: usize,
+    files: Vec<FileResult>,
+    duration_ms: u128,
+}
+
+/// Handles the `upgrade` subcommand.
+pub async fn upgrade_command(
+    args: UpgradeArgs,
+    config: &Config,
+    json: bool,
+) -> Result<(), CliError> {
+    let started = Instant::now();
+    let UpgradeArgs {
+        files,
+        password,
+        key,
+        kdf_profile,
+        dry_run,
+    } = args;
+    if files.iter().any(|file| file == STDIO_PATH) {
+        return Err(CliError::InvalidInput(
+            "upgrade rewrites files in place and cannot read from stdin".to_string(),
+        ));
+    }
+    let config = &*with_kdf_profile(config, kdf_profile)?;
+    let key = key.as_deref().map(validate_key).transpose()?;
+    let password = password.map(Zeroizing::new);
+    let kdf = config
+        .password_kdf()
+        .map_err(|e| CliError::InvalidInput(e.to_string()))?;
+    let chunk_size = config
+        .chunk_size()
+        .map_err(|e| CliError::InvalidInput(e.to_string()))?
+        .unwrap_or(DEFAULT_CHUNK_SIZE);
+
+    let mut results = Vec::with_capacity(files.len());
+    let mut failures = Vec::new();
+    for file in files {
+        let result = upgrade_file(
+            &file,
+            password.as_deref().map(String::as_str),
+            key.as_deref(),
+            kdf,
+            chunk_size,
+            dry_run,
+        )
+        .await;
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                let result = FileResult {
+                    file: file.clone(),
+                    status: "failed",
+                    reasons: Vec::new(),
+                    detail: Some(e.to_string()),
+                };
+                failures.push((file, e));
+                result
+            }
+        };
+        if !json {
+            print_result(&result);
+        }
+        results.push(result);
+    }
+
+    if failures.len() == 1 && results.len() == 1 {
+        // A single file keeps its own exit code, e.g. 4 for a wrong password
+        let (_, error) = failures.remove(0);
+        return Err(error);
+    }
+    if !failures.is_empty() {
+        let names: Vec<_> = failures.iter().map(|(file, _)| file.as_str()).collect();
+        return Err(CliError::Crypto(format!(
+            "{} of {} file(s) could not be upgraded: {}",
+            failures.len(),
+            results.len(),
+            names.join(", ")
+        )));
+    }
+
+    let count = |status| results.iter().filter(|r| r.status == status).count();
+    let upgraded = count(if dry_run { "outdated" } else { "upgraded" });
+    let (current, skipped) = (count("current"), count("skipped"));
+    if json {
+        print_json_report(&UpgradeReport {
+            operation: "upgrade",
+            dry_run,
+            upgraded,
+            current,
+            skipped,
+            files: results,
+            duration_ms: started.elapsed().as_millis(),
+        });
+    } else if dry_run {
+        status(format!(
+            "🔎 {upgraded} file(s) to upgrade, {current} current, {skipped} skipped"
+        ));
+    } else {
+        status(format!(
+            "✅ {upgraded} file(s) upgraded, {current} already current, {skipped} skipped"
+        ));
+    }
+    Ok(())
+}
+
+fn print_result(result: &FileResult) {
+    let file = &result.file;
+    let reasons = result.reasons.join(", ");
+    let detail = result.detail.as_deref().unwrap_or_default();
+    match result.status {
+        "upgraded" => status(format!("⬆️  '{file}' upgraded ({reasons})")),
+        "outdated" => status(format!("⬆️  '{file}' would be upgraded ({reasons})")),
+        "current" => status(format!("✔️  '{file}' is already current")),
+        "skipped" => status(format!("⏭️  '{file}' skipped: {detail}")),
+        _ => status(format!("❌ '{file}': {detail}")),
+    }
+}
+
+/// Upgrades one file in place, or only checks it with `dry_run`.
+async fn upgrade_file(
+    file: &str,
+    password: Option<&str>,
+    key: Option<&[u8]>,
+    kdf: PasswordKdf,
+    chunk_size: u32,
+    dry_run: bool,
+) -> Result<FileResult, CliError> {
+    let result = |status, reasons, detail: Option<&str>| FileResult {
+        file: file.to_string(),
+        status,
+        reasons,
+        detail: detail.map(str::to_string),
+    };
+
+    let data = read_input(file, "encrypted file")?;
+    if stego::is_png(&data) || interop::archive::detect(&data).is_some() {
+        return Ok(result("skipped", Vec::new(), Some("not a plain .xd file")));
+    }
+    let parsed = XdReader::parse(&data)
+        .map_err(|_| CliError::Format(format!("'{file}' is not a valid .xd file")))?;
+    if let ParsedHeader::Key(h) = &parsed.header {
+        let bound = if h.keyslots.is_some() {
+            Some("keyslot file; use rekey")
+        } else if h.subkey_salt.is_some() {
+            Some("master-key file; re-encrypt it with the master key")
+        } else if h.convergent.is_some() {
+            Some("convergent file; re-encrypt it with --convergent")
+        } else {
+            None
+        };
+        if bound.is_some() {
+            return Ok(result("skipped", Vec::new(), bound));
+        }
+    }
+
+    // Keep a file's own KDF when it is at least as strong as the configured one
+    let file_kdf = match &parsed.header {
+        ParsedHeader::Password(h) => Some(
+            PasswordKdf::from_header(h).map_err(|e| CliError::from_crypto("Invalid header", e))?,
+        ),
+        ParsedHeader::Key(_) => None,
+    };
+    let reasons = legacy_reasons(&parsed, file_kdf, kdf);
+    if reasons.is_empty() {
+        return Ok(result("current", reasons, None));
+    }
+    if dry_run {
+        return Ok(result("outdated", reasons, None));
+    }
+
+    let (password, key) = match parsed.header {
+        ParsedHeader::Password(_) => match password {
+            Some(password) => (Some(password.to_string()), None),
+            None => {
+                return Err(CliError::InvalidInput(format!(
+                    "'{file}' is password-encrypted; use --password"
+                )));
+            }
+        },
+        // Plain key files fall back to their embedded key
+        ParsedHeader::Key(_) => (None, key),
+    };
+    let file_key = crypto::file_key(&parsed, password.clone(), key)
+        .await
+        .map_err(|e| CliError::from_crypto("Decryption failed", e))?;
+    let plaintext = Zeroizing::new(
+        crypto::decrypt_parsed(&parsed, &file_key)
+            .map_err(|e| CliError::from_crypto("Decryption failed", e))?,
+    );
+
+    let filename = parsed.header.filename();
+    let chunk_size = parsed.header.chunk_size().unwrap_or(chunk_size);
+    let upgraded = match password {
+        Some(password) => {
+            let kdf = match file_kdf {
+                Some(own)
+                    if !matches!(own, PasswordKdf::Pbkdf2 { .. })
+                        && own.memory_cost() >= kdf.memory_cost() =>
+                {
+                    own
+                }
+                _ => kdf,
+            };
+            let mut salt = [0u8; 32];
+            rand::rngs::OsRng
+                .try_fill_bytes(&mut salt)
+                .map_err(|e| CliError::Crypto(format!("Failed to generate salt: {e}")))?;
+            crypto::encrypt_with_password_kdf_async(
+                &plaintext,
+                password,
+                filename,
+                salt.to_vec(),
+                kdf,
+                Some(chunk_size),
+            )
+            .await
+            .map_err(|e| CliError::from_crypto("Password encryption failed", e))?
+        }
+        None => crypto::encrypt_with_header_chunked(
+            &plaintext,
+            file_key.as_slice(),
+            filename,
+            Some(chunk_size),
+        )
+        .map_err(|e| CliError::from_crypto("Key encryption failed", e))?,
+    };
+
+    // Never silently drop a change another process made while we were working
+    if !lock::replace_if_unchanged(file, &data, &upgraded)? {
+        return Err(lock::conflict(file));
+    }
+    Ok(result("upgraded", reasons, None))
+}
+
+/// Why a parsed file counts as legacy; empty if it is current.
+fn legacy_reasons(
+    parsed: &XdReader<'_>,
+    file_kdf: Option<PasswordKdf>,
+    kdf: PasswordKdf,
+) -> Vec<String> {
+    let mut reasons = Vec::new();
+    let version = parsed.header.version();
+    let current = match parsed.header {
+        ParsedHeader::Password(_) => CURRENT_VERSION,
+        // Plain key files are written at version 2
+        ParsedHeader::Key(_) => 2,
+    };
+    if version < current {
+        reasons.push(format!("format version {version}"));
+    }
+    match file_kdf {
+        Some(PasswordKdf::Pbkdf2 { .. }) => reasons.push("PBKDF2 key derivation".to_string()),
+        Some(own) if own.memory_cost() < kdf.memory_cost() => {
+            reasons.push(format!(
+                "{} with {} KB of memory",
+                own.name(),
+                own.memory_cost()
+            ));
+        }
+        _ => {}
+    }
+    reasons
+}
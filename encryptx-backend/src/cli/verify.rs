@@ -0,0 +1,183 @@
+//!
+//! `verify` subcommand: authenticates an encrypted file without writing any plaintext.
+//!
+use super::{CliError, print_json_report, read_input, status, unwrap_input, validate_key};
+use crate::crypto::{self, RegionCheck, SecureKey};
+use crate::format::{ParsedHeader, XdReader};
+use crate::interop;
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Instant;
+
+/// Outcome of a successful `verify` run.
+#[derive(Serialize)]
+pub struct VerifyReport {
+    pub operation: &'static str,
+    pub file: String,
+    /// "key" or "password"
+    pub mode: &'static str,
+    pub regions: Vec<RegionCheck>,
+    pub duration_ms: u128,
+}
+
+/// Handles the `verify` subcommand.
+///
+/// Every authenticated region is checked, so a damaged file reports all failing regions,
+/// not just the first one.
+pub async fn verify_command(
+    file: &str,
+    password: Option<String>,
+    key: Option<String>,
+    json: bool,
+) -> Result<(), CliError> {
+    let started = Instant::now();
+    if password.is_some() && key.is_some() {
+        return Err(CliError::InvalidInput(
+            "Cannot specify both password and key. Choose one.".to_string(),
+        ));
+    }
+    let key = key.as_deref().map(validate_key).transpose()?;
+
+    let data = unwrap_input(file, read_input(file, "encrypted file")?)?;
+    if let Some(kind) = interop::archive::detect(&data) {
+        return Err(CliError::Format(format!(
+            "'{file}' is a {} archive, not an .xd file",
+            kind.name()
+        )));
+    }
+    if interop::openpgp::detect(&data) {
+        return Err(CliError::Format(format!(
+            "'{file}' is an OpenPGP message, not an .xd file"
+        )));
+    }
+    let parsed = XdReader::parse(&data)
+        .map_err(|_| CliError::Format(format!("'{file}': header is missing or corrupted")))?;
+    let mode = match parsed.header {
+        ParsedHeader::Key(_) => "key",
+        ParsedHeader::Password(_) => "password",
+    };
+
+    status(format!("🔍 Verifying '{file}'..."));
+    let file_key = crypto::file_key(&parsed, password, key.as_deref())
+        .await
+        .map_err(|e| CliError::from_crypto("Verification failed", e))?;
+    let regions = crypto::verify_regions(&parsed, &file_key)
+        .map_err(|e| CliError::from_crypto("Verification failed", e))?;
+
+    for region in &regions {
+        let end = region.offset + region.length;
+        if region.ok {
+            status(format!(
+                "✅ Region {} (bytes {}-{end}): OK",
+                region.index, region.offset
+            ));
+        } else {
+            status(format!(
+                "❌ Region {} (bytes {}-{end}): corrupt",
+                region.index, region.offset
+            ));
+        }
+    }
+
+    let failed: Vec<String> = regions
+        .iter()
+        .filter(|r| !r.ok)
+        .map(|r| format!("{} (bytes {}-{})", r.index, r.offset, r.offset + r.length))
+        .collect();
+    if !failed.is_empty() {
+        return Err(CliError::Authentication(format!(
+            "'{file}' failed verification in region(s) {}: wrong key/password or corrupted data",
+            failed.join(", ")
+        )));
+    }
+
+    if json {
+        print_json_report(&VerifyReport {
+            operation: "verify",
+            file: file.to_string(),
+            mode,
+            regions,
+            duration_ms: started.elapsed().as_millis(),
+        });
+    } else {
+        status(format!("✅ '{file}' is intact"));
+    }
+    Ok(())
+}
+
+/// How `encrypt --verify` gets the key of the file it has just written.
+pub enum WrittenKey<'a> {
+    /// The key the body was sealed with; for a keyslot file it must match the header's key
+    /// check
+    FileKey(SecureKey),
+    /// The password or key the file was encrypted with, opened the way `decrypt` would
+    Credential {
+        password: Option<String>,
+        key: Option<&'a [u8]>,
+    },
+}
+
+/// Reads back the file `encrypt` has just written to `path` (for `name`) and authenticates
+/// every region of it, so success is only reported for a file that decrypts. Returns the
+/// number of regions.
+///
+/// The file is synced first and, on Linux, evicted from the page cache, so the check reads
+/// what reached the disk rather than the buffers it was written from.
+pub async fn verify_written(
+    name: &str,
+    path: &Path,
+    written_key: WrittenKey<'_>,
+) -> Result<usize, CliError> {
+    let read_back = |e: io::Error| {
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to read back '{name}': {e}"),
+        ))
+    };
+    let file = fs::File::open(path).map_err(read_back)?;
+    file.sync_all().map_err(read_back)?;
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::fd::AsRawFd;
+        // SAFETY: the descriptor is open for the duration of the call; this is only advice
+        unsafe {
+            libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
+        }
+    }
+    drop(file);
+
+    let data = unwrap_input(name, read_input(&path.to_string_lossy(), "encrypted file")?)?;
+    let failed = |reason: &str| {
+        CliError::Authentication(format!(
+            "'{name}' was written but does not verify: {reason}"
+        ))
+    };
+    crypto::signing::verify(&data).map_err(|e| failed(&e.to_string()))?;
+    let parsed = XdReader::parse(&data).map_err(|_| failed("the header can't be parsed"))?;
+    let file_key = match written_key {
+        WrittenKey::FileKey(file_key) => {
+            if let Some((_, check)) = parsed.header.keyslots()
+                && check != crypto::keyslot::key_check(&file_key)
+            {
+                return Err(failed("the header doesn't match the file key"));
+            }
+            file_key
+        }
+        WrittenKey::Credential { password, key } => crypto::file_key(&parsed, password, key)
+            .await
+            .map_err(|e| failed(&e.to_string()))?,
+    };
+    let regions = crypto::verify_regions(&parsed, &file_key)
+        .map_err(|e| CliError::from_crypto("Verification failed", e))?;
+    if let Some(region) = regions.iter().find(|r| !r.ok) {
+        return Err(failed(&format!(
+            "region {} (bytes {}-{}) is corrupt",
+            region.index,
+            region.offset,
+            region.offset + region.length
+        )));
+    }
+    Ok(regions.len())
+}
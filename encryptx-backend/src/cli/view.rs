@@ -0,0 +1,406 @@
+//!
+//! Read-only view of an encrypted archive, or of a directory written by `encrypt --dir`,
+//! as a tree of directories, files and symlinks. `mount` and `webdav` serve it.
+//!
+//! An archive (`encrypt --tar-from`) is decrypted into memory once, when the view is
+//! opened, and its tar entries are served from there; any other encrypted file shows up
+//! as the single file it decrypts to. In a directory, every `<name>.xd` shows up as
+//! `<name>` and is only decrypted the first time its contents or size are asked for.
+//! Decrypted data stays in zeroized memory as long as the view lives, and never touches
+//! the disk.
+//!
+use super::manifest::MANIFEST_FILE;
+use super::{CliError, read_input, status, unwrap_input};
+use crate::api;
+use crate::crypto::{self, secret::SecretBuffer};
+use crate::walk::{self, EntryKind, Filter, SymlinkPolicy};
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::ops::{Deref, Range};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+/// Inode of the view's root directory.
+pub(super) const ROOT: u64 = 1;
+
+/// Most symlinks followed in a row before giving up on a chain.
+const MAX_LINKS: usize = 8;
+
+/// What a node is, and where its contents come from.
+pub(super) enum NodeKind {
+    /// Child inodes, sorted by name
+    Dir(Vec<u64>),
+    /// Contents of a file inside the archive's plaintext
+    File(Range<usize>),
+    /// A `.xd` file of a directory, decrypted on first access
+    Encrypted(PathBuf),
+    Symlink(PathBuf),
+}
+
+/// A directory, file or symlink of the view; its inode is its position in the tree.
+pub(super) struct Node {
+    pub name: OsString,
+    pub parent: u64,
+    pub kind: NodeKind,
+    /// Permission bits, without the write bits
+    pub perm: u16,
+    pub mtime: SystemTime,
+}
+
+/// Why a file's contents can't be served.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum Unavailable {
+    NotFound,
+    NotAFile,
+    /// Reading or decrypting it failed; the reason has been printed
+    Failed,
+}
+
+/// Contents of a file, borrowed from the archive or decrypted on demand.
+pub(super) enum Contents<'a> {
+    Archive(&'a [u8]),
+    Decrypted(Arc<Zeroizing<Vec<u8>>>),
+}
+
+impl Deref for Contents<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Contents::Archive(data) => data,
+            Contents::Decrypted(data) => data,
+        }
+    }
+}
+
+/// What the files are decrypted with.
+struct Credentials {
+    password: Option<String>,
+    key: Option<SecretBuffer>,
+    max_size: u64,
+}
+
+impl Credentials {
+    /// Decrypts an encrypted file, checking its signature if it has one.
+    async fn decrypt(
+        &self,
+        data: &[u8],
+        path: &str,
+    ) -> Result<(Vec<u8>, api::DecryptMetadata), CliError> {
+        crypto::signing::verify(data)
+            .map_err(|e| CliError::from_crypto("Signature check failed", e))?;
+        api::decrypt_file_with_limit(
+            data,
+            self.password.as_deref(),
+            self.key.as_deref(),
+            self.max_size,
+        )
+        .await
+        .map_err(|e| CliError::from_api(&format!("Decrypting '{path}' failed"), e))
+    }
+}
+
+/// The tree of an opened archive or directory.
+pub(super) struct View {
+    /// Inode `n` is `nodes[n - 1]`
+    nodes: Vec<Node>,
+    names: HashMap<(u64, OsString), u64>,
+    /// Plaintext of an archive; empty for a directory
+    plaintext: Zeroizing<Vec<u8>>,
+    /// Decrypted files of a directory, by inode
+    opened: Mutex<HashMap<u64, Arc<Zeroizing<Vec<u8>>>>>,
+    credentials: Credentials,
+}
+
+impl View {
+    /// Opens `source`: an encrypted file, decrypted right away, or a directory written by
+    /// `encrypt --dir`, whose files are decrypted as they are read.
+    pub(super) async fn open(
+        source: &str,
+        password: Option<String>,
+        key: Option<SecretBuffer>,
+        max_size: u64,
+    ) -> Result<Self, CliError> {
+        let source_path = Path::new(source);
+        let mtime = std::fs::metadata(source_path)
+            .and_then(|meta| meta.modified())
+            .unwrap_or(UNIX_EPOCH);
+        let root = Node {
+            name: OsString::new(),
+            parent: ROOT,
+            kind: NodeKind::Dir(Vec::new()),
+            perm: 0o555,
+            mtime,
+        };
+        let mut view = View {
+            nodes: vec![root],
+            names: HashMap::new(),
+            plaintext: Zeroizing::new(Vec::new()),
+            opened: Mutex::new(HashMap::new()),
+            credentials: Credentials {
+                password,
+                key,
+                max_size,
+            },
+        };
+
+        if source_path.is_dir() {
+            let entries = walk::walk(source_path, SymlinkPolicy::Store, &Filter::default())
+                .map_err(|e| {
+                    CliError::Io(io::Error::new(
+                        e.kind(),
+                        format!("Failed to walk '{source}': {e}"),
+                    ))
+                })?;
+            let mut files = 0;
+            for entry in entries {
+                let meta = std::fs::symlink_metadata(&entry.path);
+                let mode = meta.as_ref().map_or(0o755, mode_of);
+                let modified = meta.and_then(|meta| meta.modified()).unwrap_or(mtime);
+                match entry.kind {
+                    EntryKind::Dir => {
+                        view.insert(&entry.name, NodeKind::Dir(Vec::new()), mode, modified)
+                    }
+                    EntryKind::Symlink(target) => {
+                        view.insert(&entry.name, NodeKind::Symlink(target), mode, modified)
+                    }
+                    EntryKind::File if entry.name != MANIFEST_FILE => {
+                        if let Some(name) = entry.name.strip_suffix(".xd") {
+                            view.insert(name, NodeKind::Encrypted(entry.path), mode, modified);
+                            files += 1;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            status(format!(
+                "🔓 {files} encrypted file(s) in '{source}', decrypted as they are read"
+            ));
+        } else {
+            let data = unwrap_input(source, read_input(source, "file")?)?;
+            let (decrypted, metadata) = view.credentials.decrypt(&data, source).await?;
+            view.plaintext = Zeroizing::new(decrypted);
+            if metadata.filename.ends_with(".tar") {
+                view.insert_archive()?;
+            } else {
+                let name = match metadata.filename.as_str() {
+                    "" => source_path
+                        .file_stem()
+                        .map_or("decrypted".to_string(), |stem| {
+                            stem.to_string_lossy().into_owned()
+                        }),
+                    name => name.to_string(),
+                };
+                let len = view.plaintext.len();
+                view.insert(&name, NodeKind::File(0..len), 0o644, mtime);
+            }
+            status(format!(
+                "🔓 Decrypted '{source}' ({} bytes) into memory",
+                view.plaintext.len()
+            ));
+        }
+
+        // Sort every directory's children by name, for a stable listing
+        let names: Vec<OsString> = view.nodes.iter().map(|node| node.name.clone()).collect();
+        for node in &mut view.nodes {
+            if let NodeKind::Dir(children) = &mut node.kind {
+                children.sort_by(|a, b| names[*a as usize - 1].cmp(&names[*b as usize - 1]));
+            }
+        }
+        Ok(view)
+    }
+
+    /// Adds the entries of the tar archive in the plaintext.
+    fn insert_archive(&mut self) -> Result<(), CliError> {
+        let invalid = |e: io::Error| CliError::Format(format!("Invalid tar archive: {e}"));
+        let mut entries = Vec::new();
+        let mut archive = tar::Archive::new(self.plaintext.as_slice());
+        for entry in archive.entries().map_err(CliError::Io)? {
+            let entry = entry.map_err(invalid)?;
+            let header = entry.header();
+            let path = entry
+                .path()
+                .map_err(invalid)?
+                .to_string_lossy()
+                .into_owned();
+            let mode = header.mode().unwrap_or(0o644);
+            let modified = UNIX_EPOCH + Duration::from_secs(header.mtime().unwrap_or(0));
+            let kind = header.entry_type();
+            let node = if kind.is_dir() {
+                NodeKind::Dir(Vec::new())
+            } else if kind.is_symlink() {
+                match entry.link_name().ok().flatten() {
+                    Some(target) => NodeKind::Symlink(target.into_owned()),
+                    None => continue,
+                }
+            } else if kind.is_file() {
+                let start = entry.raw_file_position() as usize;
+                NodeKind::File(start..start + entry.size() as usize)
+            } else {
+                continue;
+            };
+            entries.push((path, node, mode, modified));
+        }
+        for (path, node, mode, modified) in entries {
+            self.insert(&path, node, mode, modified);
+        }
+        Ok(())
+    }
+
+    /// Adds `kind` at the `/`-separated `path`, creating missing parent directories. An
+    /// entry already there is replaced, except that a directory keeps its children.
+    fn insert(&mut self, path: &str, kind: NodeKind, mode: u32, mtime: SystemTime) {
+        let mut parts: Vec<&str> = path
+            .split('/')
+            .filter(|part| !part.is_empty() && *part != ".")
+            .collect();
+        let Some(name) = parts.pop() else {
+            return;
+        };
+        let mut parent = ROOT;
+        for part in parts {
+            parent = match self.lookup(parent, OsStr::new(part)) {
+                Some(ino) => ino,
+                None => self.add(parent, part, NodeKind::Dir(Vec::new()), 0o755, mtime),
+            };
+        }
+        match self.lookup(parent, OsStr::new(name)) {
+            Some(ino) => {
+                let node = &mut self.nodes[ino as usize - 1];
+                node.perm = read_only(mode);
+                node.mtime = mtime;
+                if !matches!((&node.kind, &kind), (NodeKind::Dir(_), NodeKind::Dir(_))) {
+                    node.kind = kind;
+                }
+            }
+            None => {
+                self.add(parent, name, kind, mode, mtime);
+            }
+        }
+    }
+
+    fn add(
+        &mut self,
+        parent: u64,
+        name: &str,
+        kind: NodeKind,
+        mode: u32,
+        mtime: SystemTime,
+    ) -> u64 {
+        self.nodes.push(Node {
+            name: OsString::from(name),
+            parent,
+            kind,
+            perm: read_only(mode),
+            mtime,
+        });
+        let ino = self.nodes.len() as u64;
+        self.names.insert((parent, OsString::from(name)), ino);
+        if let NodeKind::Dir(children) = &mut self.nodes[parent as usize - 1].kind {
+            children.push(ino);
+        }
+        ino
+    }
+
+    pub(super) fn node(&self, ino: u64) -> Option<&Node> {
+        self.nodes.get(usize::try_from(ino).ok()?.checked_sub(1)?)
+    }
+
+    pub(super) fn lookup(&self, parent: u64, name: &OsStr) -> Option<u64> {
+        self.names.get(&(parent, name.to_os_string())).copied()
+    }
+
+    /// Inode at a `/`-separated path below the root, following symlinks on the way and at
+    /// the end (see [`View::follow`]).
+    pub(super) fn resolve(&self, path: &str) -> Option<u64> {
+        path.split('/')
+            .filter(|part| !part.is_empty())
+            .try_fold(ROOT, |parent, part| {
+                self.follow(self.lookup(parent, OsStr::new(part))?)
+            })
+    }
+
+    /// The node a symlink leads to, if its target is relative and stays inside the view;
+    /// any other node leads to itself. Chains of more than a few links lead nowhere.
+    pub(super) fn follow(&self, mut ino: u64) -> Option<u64> {
+        for _ in 0..MAX_LINKS {
+            let node = self.node(ino)?;
+            let NodeKind::Symlink(target) = &node.kind else {
+                return Some(ino);
+            };
+            let components: Vec<Component> = target.components().collect();
+            let mut current = node.parent;
+            for (i, component) in components.iter().enumerate() {
+                current = match component {
+                    Component::Normal(name) => self.lookup(current, name)?,
+                    Component::CurDir => current,
+                    Component::ParentDir if current != ROOT => self.node(current)?.parent,
+                    _ => return None,
+                };
+                // Only the last component may be something other than a directory
+                let is_dir = matches!(self.node(current)?.kind, NodeKind::Dir(_));
+                if !is_dir && i + 1 < components.len() {
+                    return None;
+                }
+            }
+            ino = current;
+        }
+        None
+    }
+
+    /// The contents of a file, decrypting a directory's `.xd` file first if it hasn't
+    /// been yet.
+    pub(super) async fn contents(&self, ino: u64) -> Result<Contents<'_>, Unavailable> {
+        let path = match &self.node(ino).ok_or(Unavailable::NotFound)?.kind {
+            NodeKind::File(range) => return Ok(Contents::Archive(&self.plaintext[range.clone()])),
+            NodeKind::Encrypted(path) => path,
+            _ => return Err(Unavailable::NotAFile),
+        };
+        if let Some(contents) = self.opened().get(&ino) {
+            return Ok(Contents::Decrypted(Arc::clone(contents)));
+        }
+        let display = path.display().to_string();
+        let data = std::fs::read(path).map_err(|e| {
+            status(format!("⚠️  Failed to read '{display}': {e}"));
+            Unavailable::Failed
+        })?;
+        let (decrypted, _) = self
+            .credentials
+            .decrypt(&data, &display)
+            .await
+            .map_err(|e| {
+                status(format!("⚠️  {e}"));
+                Unavailable::Failed
+            })?;
+        // Two readers may race to decrypt the same file; the first one's copy is kept
+        let contents = Arc::clone(
+            self.opened()
+                .entry(ino)
+                .or_insert_with(|| Arc::new(Zeroizing::new(decrypted))),
+        );
+        Ok(Contents::Decrypted(contents))
+    }
+
+    fn opened(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Arc<Zeroizing<Vec<u8>>>>> {
+        self.opened.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Permission bits of an entry, as the view shows them.
+fn read_only(mode: u32) -> u16 {
+    (mode & 0o7555) as u16
+}
+
+#[cfg(unix)]
+fn mode_of(meta: &std::fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode()
+}
+
+#[cfg(not(unix))]
+fn mode_of(meta: &std::fs::Metadata) -> u32 {
+    if meta.is_dir() { 0o755 } else { 0o644 }
+}
@@ -0,0 +1,146 @@
+//!
+//! Fixed-size volumes: `encrypt --volume-size 1G` writes the encrypted file as
+//! `<output>.001`, `<output>.002`, ... so it fits on FAT32 drives or upload services with
+//! size caps. Every volume but the last holds exactly the volume size.
+//!
+//! The volumes are plain slices of the file, so `cat file.xd.0* > file.xd` rebuilds it as
+//! well as `join` does. `decrypt` joins them by itself when given the first volume, or the
+//! name without a number whose volumes lie next to it.
+//!
+use super::{
+    CliError, STDIO_PATH, check_output_file, print_json_report, read_input, status, write_output,
+};
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Largest number of volumes; the suffix has three digits.
+const MAX_VOLUMES: usize = 999;
+
+/// Path of volume `index` (starting at 1) of `output`.
+pub fn volume_path(output: &str, index: usize) -> String {
+    format!("{output}.{index:03}")
+}
+
+/// The name a set of volumes was split from: `file` without its `.001` suffix, or `file`
+/// itself if it doesn't exist but its first volume does. `None` for anything else.
+pub fn volume_base(file: &str) -> Option<String> {
+    if file == STDIO_PATH {
+        return None;
+    }
+    if let Some(base) = file.strip_suffix(".001")
+        && !base.is_empty()
+    {
+        return Some(base.to_string());
+    }
+    (!Path::new(file).exists() && Path::new(&volume_path(file, 1)).is_file())
+        .then(|| file.to_string())
+}
+
+/// Writes `data` as volumes of `volume_size` bytes and returns their paths.
+///
+/// Without `force`, an existing volume fails the write before anything is written. With it,
+/// higher-numbered volumes left over from an earlier, longer split are deleted, so a later
+/// join doesn't pick them up.
+pub fn write_volumes(
+    output: &str,
+    data: &[u8],
+    volume_size: u64,
+    force: bool,
+) -> Result<Vec<String>, CliError> {
+    let count = data.len().div_ceil(volume_size as usize).max(1);
+    if count > MAX_VOLUMES {
+        return Err(CliError::InvalidInput(format!(
+            "--volume-size {volume_size} would need {count} volumes; at most {MAX_VOLUMES} are supported"
+        )));
+    }
+    let paths: Vec<String> = (1..=count)
+        .map(|index| volume_path(output, index))
+        .collect();
+    for path in &paths {
+        check_output_file(path, force)?;
+    }
+    let mut volumes = data.chunks(volume_size as usize);
+    for path in &paths {
+        write_output(path, volumes.next().unwrap_or_default(), "volume")?;
+    }
+    for index in count + 1..=MAX_VOLUMES {
+        let stale = volume_path(output, index);
+        match fs::remove_file(&stale) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+            Err(e) => {
+                return Err(CliError::Io(io::Error::new(
+                    e.kind(),
+                    format!("Failed to remove the stale volume '{stale}': {e}"),
+                )));
+            }
+        }
+    }
+    Ok(paths)
+}
+
+/// Reads the volumes split from `base` in order, up to the first missing number, and
+/// returns the whole file with the number of volumes.
+pub fn join(base: &str) -> Result<(Vec<u8>, usize), CliError> {
+    let mut data = Vec::new();
+    let mut count = 0;
+    while count < MAX_VOLUMES && Path::new(&volume_path(base, count + 1)).is_file() {
+        count += 1;
+        data.extend_from_slice(&read_input(&volume_path(base, count), "volume")?);
+    }
+    if count == 0 {
+        return Err(CliError::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "No volumes found: '{}' does not exist",
+                volume_path(base, 1)
+            ),
+        )));
+    }
+    Ok((data, count))
+}
+
+/// What `join --json` reports.
+#[derive(Serialize)]
+struct JoinReport {
+    operation: &'static str,
+    volumes: usize,
+    output: String,
+    size: usize,
+}
+
+/// Handles the `join` subcommand.
+pub fn join_command(
+    file: &str,
+    output: Option<String>,
+    force: bool,
+    json: bool,
+) -> Result<(), CliError> {
+    let base = volume_base(file).unwrap_or_else(|| file.to_string());
+    let output = output.unwrap_or_else(|| base.clone());
+    if output == STDIO_PATH && json {
+        return Err(CliError::InvalidInput(
+            "--json cannot be combined with writing the file to stdout".to_string(),
+        ));
+    }
+    if output != STDIO_PATH {
+        check_output_file(&output, force)?;
+    }
+    let (data, volumes) = join(&base)?;
+    write_output(&output, &data, "joined file")?;
+
+    if json {
+        print_json_report(&JoinReport {
+            operation: "join",
+            volumes,
+            output,
+            size: data.len(),
+        });
+    } else if output != STDIO_PATH {
+        status(format!("🧩 Joined {volumes} volume(s) into '{output}'"));
+        status(format!("📊 Size: {} bytes", data.len()));
+    }
+    Ok(())
+}
@@ -0,0 +1,352 @@
+//!
+//! `watch`: keeps a directory encrypted into another one, for drop folders that should
+//! never hold plaintext for long. On start, every file whose encrypted copy is missing or
+//! older than the file itself is encrypted; after that, each file created or changed is
+//! encrypted once writes to it have settled for `--debounce`, so a file being copied in is
+//! encrypted once rather than for every block written.
+//!
+//! A file that can't be encrypted (still locked by the program writing it, unreadable)
+//! goes into a failure queue and is tried again after `--retry-after`, twice as long after
+//! each further failure, until `--max-retries` attempts have failed; a new change to it
+//! starts over. Deleting a file leaves its encrypted copy in place. The encrypted copies
+//! mirror the tree as `encrypt --dir` lays it out, `<name>.xd` next to where `<name>` is.
+//!
+use super::tree::output_dir;
+use super::{
+    CliError, EncryptArgs, STATUS_MUTED, WatchArgs, encrypt_command, keystore, status,
+    validate_key, walk_filter,
+};
+use crate::config::Config;
+use crate::walk::{self, EntryKind, Filter, SymlinkPolicy};
+use notify::{EventKind, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+/// Longest wait between two attempts at a failing file.
+const MAX_BACKOFF: Duration = Duration::from_secs(3600);
+
+/// A file waiting to be encrypted.
+struct Job {
+    due: Instant,
+    /// Failed attempts since the file last changed
+    failures: u32,
+}
+
+/// What `watch --json` prints for each file, one line at a time.
+#[derive(Serialize)]
+struct WatchEvent<'a> {
+    operation: &'static str,
+    file: &'a str,
+    /// "encrypted", "failed" (to be retried) or "abandoned"
+    result: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_in_secs: Option<u64>,
+}
+
+impl WatchEvent<'_> {
+    fn print(&self, json: bool) {
+        if json {
+            if let Ok(line) = serde_json::to_string(self) {
+                println!("{line}");
+            }
+            return;
+        }
+        let file = self.file;
+        let error = self.error.as_deref().unwrap_or_default();
+        match (self.result, self.retry_in_secs) {
+            ("encrypted", _) => status(format!("🔐 '{file}' encrypted")),
+            (_, Some(secs)) => status(format!("⚠️  '{file}' failed, retrying in {secs}s: {error}")),
+            _ => status(format!(
+                "❌ '{file}' failed too often, left until it changes: {error}"
+            )),
+        }
+    }
+}
+
+/// The path of `path` below `root`, with `/` separators.
+fn relative_name(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let parts: Vec<_> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+/// Whether the encrypted copy of `source` is missing or older than it.
+fn is_stale(source: &Path, target: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    match (modified(source), modified(target)) {
+        (Some(source), Some(target)) => source > target,
+        _ => true,
+    }
+}
+
+/// The watched directory and the files waiting to be encrypted from it.
+struct Watch {
+    root: PathBuf,
+    out_root: PathBuf,
+    filter: Filter,
+    /// Options every file is encrypted with
+    template: EncryptArgs,
+    queue: HashMap<PathBuf, Job>,
+    debounce: Duration,
+    retry_after: Duration,
+    max_retries: u32,
+    encrypted: usize,
+    abandoned: usize,
+}
+
+impl Watch {
+    /// Queues every file the filter keeps below `dir`, e.g. a directory moved into the
+    /// watched one in a single rename, which brings no events for what it holds. With
+    /// `only_stale`, files whose encrypted copy is up to date are left out.
+    fn queue_tree(&mut self, dir: &Path, due: Instant, only_stale: bool) -> io::Result<()> {
+        let prefix = relative_name(&self.root, dir);
+        for entry in walk::walk(dir, SymlinkPolicy::Skip, &Filter::default())? {
+            let name = match &prefix {
+                Some(prefix) => format!("{prefix}/{}", entry.name),
+                None => entry.name,
+            };
+            let target = self.out_root.join(format!("{name}.xd"));
+            if entry.kind != EntryKind::File
+                || !self.filter.keeps_file(&name)
+                || (only_stale && !is_stale(&entry.path, &target))
+            {
+                continue;
+            }
+            self.queue.insert(entry.path, Job { due, failures: 0 });
+        }
+        Ok(())
+    }
+
+    /// Queues the files a change was reported for, `--debounce` from now.
+    fn schedule(&mut self, event: notify::Event) {
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+            return;
+        }
+        let due = Instant::now() + self.debounce;
+        for path in event.paths {
+            let Ok(metadata) = fs::symlink_metadata(&path) else {
+                continue;
+            };
+            if metadata.is_dir() {
+                let _ = self.queue_tree(&path, due, false);
+            } else if metadata.is_file()
+                && relative_name(&self.root, &path)
+                    .is_some_and(|name| self.filter.keeps_file(&name))
+            {
+                // Every write pushes the file back, and a change forgives past failures
+                self.queue.insert(path, Job { due, failures: 0 });
+            }
+        }
+    }
+
+    /// When the next queued file is due.
+    fn next_due(&self) -> Instant {
+        let next = self.queue.values().map(|job| job.due).min();
+        next.unwrap_or_else(|| Instant::now() + MAX_BACKOFF)
+    }
+
+    /// Encrypts the queued files that are due; failures are queued again for later.
+    async fn encrypt_due(&mut self, config: &Config, json: bool) {
+        let now = Instant::now();
+        let ready: Vec<PathBuf> = self
+            .queue
+            .iter()
+            .filter(|(_, job)| job.due <= now)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in ready {
+            // Gone before its turn came, e.g. a temporary file
+            let name = relative_name(&self.root, &path).filter(|_| path.is_file());
+            let Some(name) = name else {
+                self.queue.remove(&path);
+                continue;
+            };
+            let target = self.out_root.join(format!("{name}.xd"));
+            let result = self.encrypt(&path, &target, config, json).await;
+            let mut event = WatchEvent {
+                operation: "watch",
+                file: &name,
+                result: "encrypted",
+                output: None,
+                error: None,
+                retry_in_secs: None,
+            };
+            match result {
+                Ok(()) => {
+                    self.queue.remove(&path);
+                    self.encrypted += 1;
+                    event.output = Some(target.to_string_lossy().into_owned());
+                }
+                Err(e) => {
+                    event.error = Some(e.to_string());
+                    let job = self.queue.entry(path.clone()).or_insert(Job {
+                        due: now,
+                        failures: 0,
+                    });
+                    job.failures += 1;
+                    if job.failures > self.max_retries {
+                        self.queue.remove(&path);
+                        self.abandoned += 1;
+                        event.result = "abandoned";
+                    } else {
+                        let backoff = self
+                            .retry_after
+                            .saturating_mul(1 << (job.failures - 1).min(16))
+                            .min(MAX_BACKOFF);
+                        job.due = Instant::now() + backoff;
+                        event.result = "failed";
+                        event.retry_in_secs = Some(backoff.as_secs());
+                    }
+                }
+            }
+            event.print(json);
+        }
+    }
+
+    /// Encrypts one file through the ordinary single-file encryption.
+    async fn encrypt(
+        &self,
+        path: &Path,
+        target: &Path,
+        config: &Config,
+        json: bool,
+    ) -> Result<(), CliError> {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| {
+                CliError::Io(io::Error::new(
+                    e.kind(),
+                    format!(
+                        "Failed to create a directory for '{}': {e}",
+                        target.display()
+                    ),
+                ))
+            })?;
+        }
+        let file_args = EncryptArgs {
+            file: Some(path.to_string_lossy().into_owned()),
+            output: Some(target.to_string_lossy().into_owned()),
+            ..self.template.clone()
+        };
+        STATUS_MUTED.store(true, Ordering::Relaxed);
+        let result = encrypt_command(file_args, config, json).await;
+        STATUS_MUTED.store(false, Ordering::Relaxed);
+        result.map(|_| ())
+    }
+}
+
+/// Handles `watch`, until Ctrl-C.
+pub async fn watch_command(args: WatchArgs, config: &Config, json: bool) -> Result<(), CliError> {
+    let WatchArgs {
+        dir,
+        output,
+        password,
+        key,
+        key_name,
+        exclude,
+        debounce,
+        retry_after,
+        max_retries,
+    } = args;
+    if password.is_none() && key.is_none() && key_name.is_none() {
+        return Err(CliError::InvalidInput(
+            "watch needs --password, --key or --key-name".to_string(),
+        ));
+    }
+    let root = fs::canonicalize(&dir).map_err(|e| {
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to resolve '{dir}': {e}"),
+        ))
+    })?;
+    if !root.is_dir() {
+        return Err(CliError::InvalidInput(format!(
+            "watch needs a directory, and '{dir}' is not one"
+        )));
+    }
+    let out_root = output_dir(&output, &root)?;
+    let filter = walk_filter(&[], &exclude, &[])?;
+
+    // Credentials are settled once, not once per file
+    let key = match key_name {
+        Some(name) => Some(keystore::load_key(&name, config).await?),
+        None => key.map(Zeroizing::new),
+    };
+    if let Some(key) = &key {
+        validate_key(key)?;
+    }
+    let template = EncryptArgs {
+        password,
+        key: key.as_deref().cloned(),
+        force: true,
+        ..Default::default()
+    };
+
+    // Watching starts before the first scan, so nothing written during it is missed
+    let (tx, mut events) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = tx.send(event);
+    })
+    .and_then(|mut watcher| {
+        watcher.watch(&root, RecursiveMode::Recursive)?;
+        Ok(watcher)
+    })
+    .map_err(|e| CliError::Io(io::Error::other(format!("Failed to watch '{dir}': {e}"))))?;
+
+    let mut watch = Watch {
+        root: root.clone(),
+        out_root,
+        filter,
+        template,
+        queue: HashMap::new(),
+        debounce: Duration::from_millis(debounce),
+        retry_after: Duration::from_secs(retry_after),
+        max_retries,
+        encrypted: 0,
+        abandoned: 0,
+    };
+    watch.queue_tree(&root, Instant::now(), true).map_err(|e| {
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to walk '{dir}': {e}"),
+        ))
+    })?;
+    status(format!(
+        "👀 Watching '{dir}', encrypting into '{output}' ({} file(s) to catch up on); press Ctrl-C to stop",
+        watch.queue.len()
+    ));
+
+    loop {
+        let wake = watch.next_due();
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            event = events.recv() => match event {
+                Some(Ok(event)) => watch.schedule(event),
+                Some(Err(e)) => status(format!("⚠️  Watch error: {e}")),
+                None => break,
+            },
+            _ = tokio::time::sleep_until(wake.into()) => watch.encrypt_due(config, json).await,
+        }
+    }
+    let _ = watcher.unwatch(&root);
+
+    status(format!(
+        "🛑 Stopped watching '{dir}': {} file(s) encrypted, {} given up on, {} still queued",
+        watch.encrypted,
+        watch.abandoned,
+        watch.queue.len()
+    ));
+    Ok(())
+}
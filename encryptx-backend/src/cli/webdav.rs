@@ -0,0 +1,316 @@
+//!
+//! `webdav` subcommand: serves an encrypted archive or a directory written by
+//! `encrypt --dir` (see [`View`]) over read-only WebDAV, so file managers can browse and
+//! open it without extracting it.
+//!
+//! Only what browsing needs is implemented: `OPTIONS`, `PROPFIND` with depth 0 or 1, and
+//! `GET`/`HEAD` with single byte ranges. Everything that would change the tree answers
+//! `405 Method Not Allowed`. A symlink is served as what it points to, if that is inside
+//! the tree too, and left out otherwise. There is no authentication, so the
+//! server only listens on loopback addresses unless `--allow-remote` is given.
+//!
+use super::view::{NodeKind, Unavailable, View};
+use super::{CliError, status, validate_key};
+use crate::config::Config;
+use actix_web::http::header::{self, HttpDate};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, web};
+use std::ffi::OsString;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+
+/// Methods the server answers.
+const ALLOW: &str = "OPTIONS, GET, HEAD, PROPFIND";
+
+/// Handles the `webdav` subcommand: serves `source` on `listen` until Ctrl-C.
+pub async fn webdav_command(
+    source: &str,
+    listen: &str,
+    allow_remote: bool,
+    password: Option<String>,
+    key: Option<String>,
+    config: &Config,
+) -> Result<(), CliError> {
+    if password.is_some() && key.is_some() {
+        return Err(CliError::InvalidInput(
+            "Cannot specify both password and key. Choose one.".to_string(),
+        ));
+    }
+    let addrs: Vec<SocketAddr> = listen
+        .to_socket_addrs()
+        .map_err(|e| CliError::InvalidInput(format!("Invalid address '{listen}': {e}")))?
+        .collect();
+    if !allow_remote && addrs.iter().any(|addr| !addr.ip().is_loopback()) {
+        return Err(CliError::InvalidInput(format!(
+            "'{listen}' is reachable from other machines, and the server has no authentication. \
+             Use --allow-remote to serve the decrypted files there anyway"
+        )));
+    }
+    let max_size = config
+        .max_decompressed_size()
+        .map_err(|e| CliError::InvalidInput(e.to_string()))?;
+    let key = key.as_deref().map(validate_key).transpose()?;
+    let view = web::Data::new(View::open(source, password, key, max_size).await?);
+
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(view.clone())
+            .default_service(web::to(handle))
+    })
+    .bind(addrs.as_slice())
+    .map_err(|e| {
+        CliError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to listen on '{listen}': {e}"),
+        ))
+    })?;
+    for addr in server.addrs() {
+        status(format!(
+            "🌐 Serving '{source}' read-only at http://{addr}/; press Ctrl-C to stop"
+        ));
+    }
+    server.run().await.map_err(CliError::Io)?;
+    status("🔒 Stopped serving");
+    Ok(())
+}
+
+/// Answers one WebDAV request.
+async fn handle(req: HttpRequest, view: web::Data<View>) -> HttpResponse {
+    let Some(path) = percent_decode(req.path()) else {
+        return HttpResponse::BadRequest().finish();
+    };
+    let method = req.method();
+    if method == Method::OPTIONS {
+        return HttpResponse::Ok()
+            .insert_header(("DAV", "1"))
+            .insert_header((header::ALLOW, ALLOW))
+            .finish();
+    }
+    if !matches!(method.as_str(), "PROPFIND" | "GET" | "HEAD") {
+        return HttpResponse::MethodNotAllowed()
+            .insert_header((header::ALLOW, ALLOW))
+            .finish();
+    }
+    let Some(ino) = view.resolve(&path) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if method.as_str() == "PROPFIND" {
+        propfind(&req, &view, ino).await
+    } else {
+        get(&req, &view, ino).await
+    }
+}
+
+/// The entries of a directory to list: each name, and the inode it is served as (the
+/// target of a symlink). Symlinks leading outside the tree are left out.
+fn entries(view: &View, ino: u64) -> Vec<(OsString, u64)> {
+    match view.node(ino).map(|node| &node.kind) {
+        Some(NodeKind::Dir(children)) => children
+            .iter()
+            .filter_map(|&child| Some((view.node(child)?.name.clone(), view.follow(child)?)))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn is_collection(view: &View, ino: u64) -> bool {
+    view.node(ino)
+        .is_some_and(|node| matches!(node.kind, NodeKind::Dir(_)))
+}
+
+/// `PROPFIND`: the properties of a node and, unless `Depth: 0`, of its children.
+async fn propfind(req: &HttpRequest, view: &View, ino: u64) -> HttpResponse {
+    let depth = req
+        .headers()
+        .get("Depth")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("infinity");
+    let dir = collection_href(req.path());
+    let Some(node) = view.node(ino) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let is_dir = matches!(node.kind, NodeKind::Dir(_));
+    let own_href = if is_dir {
+        dir.clone()
+    } else {
+        req.path().to_string()
+    };
+    let mut targets = vec![(own_href, node.name.to_string_lossy().into_owned(), ino)];
+    if depth != "0" {
+        // `Depth: infinity` is answered like 1, which is all file managers ask for
+        for (name, served) in entries(view, ino) {
+            let name = name.to_string_lossy().into_owned();
+            let mut href = format!("{dir}{}", percent_encode(&name));
+            if is_collection(view, served) {
+                href.push('/');
+            }
+            targets.push((href, name, served));
+        }
+    }
+
+    let mut body =
+        String::from(r#"<?xml version="1.0" encoding="utf-8"?><D:multistatus xmlns:D="DAV:">"#);
+    for (href, name, served) in targets {
+        let Some(node) = view.node(served) else {
+            continue;
+        };
+        let props = match &node.kind {
+            NodeKind::Dir(_) => "<D:resourcetype><D:collection/></D:resourcetype>".to_string(),
+            _ => match view.contents(served).await {
+                Ok(contents) => format!(
+                    "<D:resourcetype/><D:getcontentlength>{}</D:getcontentlength>\
+                     <D:getcontenttype>application/octet-stream</D:getcontenttype>",
+                    contents.len()
+                ),
+                // Listed files that can't be decrypted are left out; the reason was printed
+                Err(_) if served != ino => continue,
+                Err(_) => return HttpResponse::InternalServerError().finish(),
+            },
+        };
+        body.push_str(&format!(
+            "<D:response><D:href>{}</D:href><D:propstat><D:prop>\
+             <D:displayname>{}</D:displayname>{props}\
+             <D:getlastmodified>{}</D:getlastmodified>\
+             </D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>",
+            xml_escape(&href),
+            xml_escape(&name),
+            HttpDate::from(node.mtime),
+        ));
+    }
+    body.push_str("</D:multistatus>");
+    HttpResponse::build(StatusCode::MULTI_STATUS)
+        .content_type("application/xml; charset=utf-8")
+        .body(body)
+}
+
+/// `GET`/`HEAD`: a file's contents, or part of them for a `Range` request; a directory
+/// gets a plain HTML listing for browsers.
+async fn get(req: &HttpRequest, view: &View, ino: u64) -> HttpResponse {
+    let Some(node) = view.node(ino) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let modified = HttpDate::from(node.mtime);
+    if let NodeKind::Dir(_) = &node.kind {
+        let dir = collection_href(req.path());
+        let mut body = String::from("<!DOCTYPE html><html><body><ul>");
+        for (name, served) in entries(view, ino) {
+            let name = name.to_string_lossy();
+            let slash = if is_collection(view, served) { "/" } else { "" };
+            body.push_str(&format!(
+                "<li><a href=\"{}\">{}{slash}</a></li>",
+                xml_escape(&format!("{dir}{}{slash}", percent_encode(&name))),
+                xml_escape(&name)
+            ));
+        }
+        body.push_str("</ul></body></html>");
+        return HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .insert_header((header::LAST_MODIFIED, modified))
+            .body(body);
+    }
+
+    let contents = match view.contents(ino).await {
+        Ok(contents) => contents,
+        Err(Unavailable::NotFound) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let len = contents.len();
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type("application/octet-stream")
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header((header::LAST_MODIFIED, modified));
+    let range = req
+        .headers()
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok());
+    let range = match range.map(|range| byte_range(range, len)) {
+        None => 0..len,
+        Some(Some(range)) => {
+            response.status(StatusCode::PARTIAL_CONTENT).insert_header((
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{len}", range.start, range.end - 1),
+            ));
+            range
+        }
+        Some(None) => {
+            return HttpResponse::RangeNotSatisfiable()
+                .insert_header((header::CONTENT_RANGE, format!("bytes */{len}")))
+                .finish();
+        }
+    };
+    response.body(web::Bytes::copy_from_slice(&contents[range]))
+}
+
+/// Parses a single-range `Range: bytes=...` header against a body of `len` bytes;
+/// `None` if it can't be satisfied.
+fn byte_range(header: &str, len: usize) -> Option<std::ops::Range<usize>> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: usize = suffix.parse().ok()?;
+            (len.checked_sub(suffix.min(len))?, len)
+        }
+        (start, "") => (start.parse().ok()?, len),
+        (start, end) => (
+            start.parse().ok()?,
+            end.parse::<usize>().ok()?.saturating_add(1).min(len),
+        ),
+    };
+    (start < end).then_some(start..end)
+}
+
+/// The request path with a trailing `/`, to which the encoded names of a directory's
+/// entries are appended.
+fn collection_href(path: &str) -> String {
+    if path.ends_with('/') {
+        path.to_string()
+    } else {
+        format!("{path}/")
+    }
+}
+
+fn percent_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Decodes a request path; `None` if it isn't valid UTF-8 once decoded.
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
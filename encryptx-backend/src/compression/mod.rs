@@ -0,0 +1,237 @@
+//!
+//! zstd compression settings shared by the CLI, the server and the library API.
+//!
+//! A compressed body is the `0x01` flag followed by zstd frames. Its first frame is a
+//! skippable frame recording the compression level, for diagnostics: `decrypt --json` and
+//! [`DecryptMetadata`](crate::api::DecryptMetadata) report it. zstd decoders skip such
+//! frames, so older readers open these bodies, and bodies written before the level was
+//! recorded simply report none.
+//!
+//! Data that zstd can't shrink, such as video, images and archives, is stored as is behind a
+//! `0x00` flag instead: see [`worth_compressing`].
+//!
+//! A body compressed with a trained dictionary (`encrypt --dict`) carries it in a second
+//! skippable frame after the level, so the dictionary is encrypted with the data it
+//! describes. Readers from before dictionaries can't decode such bodies.
+//!
+//! Large inputs can be compressed by several zstd workers, and with long-distance matching;
+//! see [`Tuning`]. Neither changes what a reader needs.
+//!
+use crate::crypto::padding;
+use std::io::{self, Write};
+use std::path::Path;
+use zstd::stream::write::Encoder;
+
+/// Magic number of the frame that records the level: one of the 16 values zstd reserves
+/// for skippable frames.
+pub const LEVEL_FRAME_MAGIC: u32 = 0x184D_2A5E;
+
+/// Length of that frame: magic, payload length and level, each 4 bytes little-endian.
+pub const LEVEL_FRAME_LEN: usize = 12;
+
+/// Magic number of the frame holding a dictionary, right after the level frame.
+pub const DICTIONARY_FRAME_MAGIC: u32 = 0x184D_2A5F;
+
+/// Length of a skippable frame's magic and payload length.
+pub const SKIPPABLE_HEADER_LEN: usize = 8;
+
+/// Largest size dictionaries are trained to: zstd's own default.
+pub const DICTIONARY_LEN: usize = 110 * 1024;
+
+/// Largest dictionary a reader accepts, so a hostile body can't make it allocate more.
+pub const MAX_DICTIONARY_LEN: usize = 1 << 20;
+
+/// Extensions of formats that are compressed already, so zstd would only cost time.
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "7z", "aac", "age", "apk", "avif", "br", "bz2", "docx", "flac", "gif", "gpg", "gz", "heic",
+    "jar", "jpeg", "jpg", "lz4", "m4a", "m4v", "mkv", "mov", "mp3", "mp4", "odt", "ogg", "opus",
+    "png", "pptx", "rar", "tgz", "webm", "webp", "xd", "xlsx", "xz", "zip", "zst",
+];
+
+/// Bytes from the start of the input that are trial-compressed.
+const SAMPLE_LEN: usize = 128 * 1024;
+
+/// Inputs smaller than this are compressed on the calling thread: zstd hands its workers
+/// jobs of several MiB each, so smaller inputs wouldn't keep two of them busy.
+pub const MULTITHREAD_MIN_INPUT: usize = 8 << 20;
+
+/// Most zstd workers one compression can use.
+pub const MAX_THREADS: u32 = 256;
+
+/// How zstd compresses, besides the level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tuning {
+    /// Worker threads for inputs of at least [`MULTITHREAD_MIN_INPUT`] bytes; 1 compresses on
+    /// the calling thread
+    pub threads: u32,
+    /// Find repeats up to 128 MiB apart rather than within the level's window, at the cost
+    /// of that much memory when compressing
+    pub long_distance_matching: bool,
+}
+
+impl Default for Tuning {
+    /// Single-threaded, without long-distance matching: what zstd does on its own.
+    fn default() -> Self {
+        Self {
+            threads: 1,
+            long_distance_matching: false,
+        }
+    }
+}
+
+impl Tuning {
+    /// Sets up `encoder` for an input of `input_len` bytes.
+    pub fn apply<W: Write>(self, encoder: &mut Encoder<'_, W>, input_len: usize) -> io::Result<()> {
+        if self.threads > 1 && input_len >= MULTITHREAD_MIN_INPUT {
+            encoder.multithread(self.threads)?;
+        }
+        if self.long_distance_matching {
+            encoder.long_distance_matching(true)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks a thread count from a flag or config file; 0 stands for every available core.
+pub fn validate_threads(threads: u32) -> Result<u32, String> {
+    if threads > MAX_THREADS {
+        return Err(format!("Thread count must be at most {MAX_THREADS}"));
+    }
+    Ok(threads)
+}
+
+/// The number of workers a thread count of `threads` means: 0 is every core the process
+/// may use, which on Linux takes its CPU affinity and cgroup CPU quota into account (a
+/// container limited to 2 CPUs gets 2, not the host's count).
+pub fn resolve_threads(threads: u32) -> u32 {
+    match threads {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get() as u32),
+        n => n,
+    }
+}
+
+/// Checks a level from a flag, header or config file against the range zstd accepts
+/// (negative levels trade ratio for speed).
+pub fn validate_level(level: i32) -> Result<i32, String> {
+    let range = zstd::compression_level_range();
+    if !range.contains(&level) {
+        return Err(format!(
+            "Compression level must be between {} and {}",
+            range.start(),
+            range.end()
+        ));
+    }
+    Ok(level)
+}
+
+/// The skippable frame recording `level`, written right after the compression flag.
+pub fn level_frame(level: i32) -> [u8; LEVEL_FRAME_LEN] {
+    let mut frame = [0u8; LEVEL_FRAME_LEN];
+    frame[..4].copy_from_slice(&LEVEL_FRAME_MAGIC.to_le_bytes());
+    frame[4..8].copy_from_slice(&4u32.to_le_bytes());
+    frame[8..].copy_from_slice(&level.to_le_bytes());
+    frame
+}
+
+/// Trains a dictionary on `samples`, such as the files of a directory, of about 1% of
+/// their size as zstd recommends. `None` if zstd can't train one, which happens when there
+/// are too few samples or too little data.
+pub fn train_dictionary(samples: &[&[u8]]) -> Option<Vec<u8>> {
+    let total: usize = samples.iter().map(|sample| sample.len()).sum();
+    let len = (total / 100).clamp(1024, DICTIONARY_LEN);
+    zstd::dict::from_samples(samples, len).ok()
+}
+
+/// The skippable frame carrying `dictionary`, written right after the level frame.
+pub fn dictionary_frame(dictionary: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(SKIPPABLE_HEADER_LEN + dictionary.len());
+    frame.extend_from_slice(&DICTIONARY_FRAME_MAGIC.to_le_bytes());
+    frame.extend_from_slice(&(dictionary.len() as u32).to_le_bytes());
+    frame.extend_from_slice(dictionary);
+    frame
+}
+
+/// The length of the dictionary whose frame starts with `header`, if these 8 bytes start
+/// a dictionary frame.
+pub fn dictionary_len(header: &[u8]) -> Option<usize> {
+    let header = header.get(..SKIPPABLE_HEADER_LEN)?;
+    if header[..4] != DICTIONARY_FRAME_MAGIC.to_le_bytes() {
+        return None;
+    }
+    Some(u32::from_le_bytes(header[4..].try_into().ok()?) as usize)
+}
+
+/// What a zstd stream (a body after its flag byte) holds besides its data frames.
+pub struct StreamStart<'a> {
+    /// The recorded level, if any
+    pub level: Option<i32>,
+    /// The dictionary the data frames were compressed with, if any
+    pub dictionary: Option<&'a [u8]>,
+    /// The data frames
+    pub frames: &'a [u8],
+}
+
+/// Splits the level and dictionary frames off the start of `stream`. A dictionary frame
+/// that is cut off or over [`MAX_DICTIONARY_LEN`] is left in `frames`, where zstd skips it.
+pub fn stream_start(stream: &[u8]) -> StreamStart<'_> {
+    let level = level_in_stream(stream);
+    let rest = match level {
+        Some(_) => &stream[LEVEL_FRAME_LEN..],
+        None => stream,
+    };
+    let dictionary = level
+        .and(dictionary_len(rest))
+        .filter(|&len| len <= MAX_DICTIONARY_LEN)
+        .and_then(|len| rest.get(SKIPPABLE_HEADER_LEN..SKIPPABLE_HEADER_LEN + len));
+    let frames = match dictionary {
+        Some(dictionary) => &rest[SKIPPABLE_HEADER_LEN + dictionary.len()..],
+        None => rest,
+    };
+    StreamStart {
+        level,
+        dictionary,
+        frames,
+    }
+}
+
+/// The level recorded at the start of a zstd stream (the body after its flag byte), if
+/// the stream starts with a level frame.
+pub fn level_in_stream(stream: &[u8]) -> Option<i32> {
+    let frame = stream.get(..LEVEL_FRAME_LEN)?;
+    if frame[..8] != level_frame(0)[..8] {
+        return None;
+    }
+    Some(i32::from_le_bytes(frame[8..].try_into().ok()?))
+}
+
+/// The level recorded in a decrypted body, padded or not; `None` if it isn't compressed
+/// or predates recorded levels.
+pub fn recorded_level(body: &[u8]) -> Option<i32> {
+    let body = match padding::body_range(body) {
+        Some(range) => body.get(range)?,
+        None => body,
+    };
+    match body.split_first() {
+        Some((0x01, stream)) => stream_start(stream).level,
+        _ => None,
+    }
+}
+
+/// Whether compressing `data` (named `name`) is likely to pay off: not for a known
+/// compressed format, nor when a quick trial compression of its first 128 KiB saves less
+/// than about 3%.
+pub fn worth_compressing(name: &str, data: &[u8]) -> bool {
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    if extension.is_some_and(|e| COMPRESSED_EXTENSIONS.contains(&e.as_str())) {
+        return false;
+    }
+    let sample = &data[..data.len().min(SAMPLE_LEN)];
+    match zstd::bulk::compress(sample, 1) {
+        Ok(compressed) => compressed.len() + sample.len() / 32 < sample.len(),
+        // Leave it to the real compression to report the problem
+        Err(_) => true,
+    }
+}
@@ -0,0 +1,357 @@
+//!
+//! Configuration file support shared by the CLI and the server.
+//!
+//! Defaults are read from `~/.config/encryptx/config.toml` (or `$XDG_CONFIG_HOME`), or from
+//! an explicit `--config` path. Every value is optional; command-line flags always win over
+//! values from the file.
+//!
+//! ```toml
+//! compression_level = 3
+//! kdf_profile = "moderate"
+//! kdf = "argon2id"
+//! output_dir = "/home/me/encrypted"
+//! chunk_size = "8MiB"
+//! max_decompressed_size = "4GiB"
+//! keystore = "/home/me/.config/encryptx/keys.xd"
+//! identities = "/home/me/.config/encryptx/identities.txt"
+//! agent_ttl = "15m"
+//!
+//! [server]
+//! host = "0.0.0.0"
+//! port = 8080
+//! allowed_origins = ["http://localhost:3000"]
+//! max_payload_mb = 1024
+//! ```
+use crate::compression;
+use crate::crypto::{self, KdfProfile, PasswordKdf, chunked};
+use base64::{Engine as _, engine::general_purpose};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+/// Default zstd level used when nothing else is configured.
+pub const DEFAULT_COMPRESSION_LEVEL: i32 = 3;
+
+/// Error types for loading configuration.
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed to read config file '{0}': {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("Invalid config file '{0}': {1}")]
+    Parse(PathBuf, String),
+    #[error("Invalid config value: {0}")]
+    Invalid(String),
+}
+
+impl From<ConfigError> for std::io::Error {
+    fn from(error: ConfigError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, error.to_string())
+    }
+}
+
+/// Server section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Interface to bind to
+    pub host: String,
+    /// Port to listen on
+    pub port: u16,
+    /// CORS origins allowed to call the API (overridden by `ALLOWED_ORIGIN`)
+    pub allowed_origins: Vec<String>,
+    /// Maximum request body size in megabytes
+    pub max_payload_mb: usize,
+    /// p95 Argon2 latency (milliseconds) the `/kdf/advice` endpoint tunes for
+    pub kdf_target_ms: u64,
+    /// Process memory (RSS, megabytes) above which password-mode requests are turned away
+    /// with 503; unset disables the watchdog
+    pub memory_limit_mb: Option<u64>,
+    /// `Retry-After` seconds sent with those 503 responses
+    pub retry_after_secs: u64,
+    /// Failed `/decrypt` attempts a client may make before it is blocked with 429
+    pub throttle_free_failures: u32,
+    /// Longest block (seconds) for a client that keeps failing to decrypt; 0 disables
+    /// throttling
+    pub throttle_max_backoff_secs: u64,
+    /// Identify clients by `Forwarded`/`X-Forwarded-For` instead of the peer address; only
+    /// safe behind a reverse proxy that sets these headers
+    pub trust_forwarded_for: bool,
+    /// Write file names to the log instead of redacting them
+    pub log_filenames: bool,
+    /// Directory of encrypted files that `GET /stream/{name}` decrypts by byte range;
+    /// unset disables the endpoint
+    pub stream_dir: Option<PathBuf>,
+    /// Base64 32-byte secret that share links for `stream_dir` files are sealed under; unset
+    /// disables `/share`
+    pub share_secret: Option<String>,
+    /// Longest lifetime (seconds) of a share link, and the lifetime of one that asks for none
+    pub share_max_ttl_secs: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "0.0.0.0".to_string(),
+            port: 8080,
+            allowed_origins: vec!["http://localhost:3000".to_string()],
+            max_payload_mb: 1024,
+            kdf_target_ms: 500,
+            memory_limit_mb: None,
+            retry_after_secs: 5,
+            throttle_free_failures: 5,
+            throttle_max_backoff_secs: 900,
+            trust_forwarded_for: false,
+            log_filenames: false,
+            stream_dir: None,
+            share_secret: None,
+            share_max_ttl_secs: 86_400,
+        }
+    }
+}
+
+/// Top-level EncryptX configuration.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// zstd compression level applied before encryption
+    pub compression_level: i32,
+    /// Threads for compressing inputs of 8 MiB or more, sealing and opening chunks, password
+    /// key derivations and `encrypt` over several files; 0 uses every core the process may use
+    pub threads: u32,
+    /// Let zstd find repeats up to 128 MiB apart in large inputs, at the cost of memory
+    pub long_distance_matching: bool,
+    /// Argon2 profile name for password-based encryption
+    pub kdf_profile: String,
+    /// Password KDF for new password files ("argon2id" or "scrypt"), at the strength of
+    /// `kdf_profile`
+    pub kdf: String,
+    /// Directory where output files go when `--output` is not given
+    pub output_dir: Option<PathBuf>,
+    /// Size of each encrypted chunk (e.g. "8MiB", matching the S3 multipart part size);
+    /// unset keeps the single-message layout
+    pub chunk_size: Option<String>,
+    /// Largest size a decrypted body may decompress to (e.g. "4GiB"); files that expand
+    /// further are refused rather than decompressed
+    pub max_decompressed_size: String,
+    /// Encrypted keystore file for named keys (`key add`, `--key-name`); unset keeps them
+    /// in the OS secret store
+    pub keystore: Option<PathBuf>,
+    /// Identities file tried when a file encrypted to recipients is decrypted without a
+    /// credential; unset uses `identities.txt` next to the default config file
+    pub identities: Option<PathBuf>,
+    /// How long `agent start` keeps keys (e.g. "15m", "1h")
+    pub agent_ttl: String,
+    /// Server settings
+    pub server: ServerConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            compression_level: DEFAULT_COMPRESSION_LEVEL,
+            threads: 0,
+            long_distance_matching: false,
+            kdf_profile: KdfProfile::default().name().to_string(),
+            kdf: crypto::PASSWORD_KDFS[0].to_string(),
+            output_dir: None,
+            chunk_size: None,
+            max_decompressed_size: "4GiB".to_string(),
+            keystore: None,
+            identities: None,
+            agent_ttl: "15m".to_string(),
+            server: ServerConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Default config location: `$XDG_CONFIG_HOME/encryptx/config.toml`,
+    /// falling back to `~/.config/encryptx/config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        Some(Self::config_dir()?.join("config.toml"))
+    }
+
+    /// Identities file scanned by `decrypt` when no credential is given: `identities` from
+    /// the config, else `identities.txt` in the default config directory.
+    pub fn identities_path(&self) -> Option<PathBuf> {
+        self.identities
+            .clone()
+            .or_else(|| Some(Self::config_dir()?.join("identities.txt")))
+    }
+
+    /// Directory of the default config file.
+    pub fn config_dir() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(base.join("encryptx"))
+    }
+
+    /// Loads the configuration.
+    ///
+    /// An explicit path must exist; a missing file at the default location just yields
+    /// the built-in defaults.
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let (path, required) = match path {
+            Some(p) => (p.to_path_buf(), true),
+            None => match Self::default_path() {
+                Some(p) => (p, false),
+                None => return Ok(Self::default()),
+            },
+        };
+
+        if !required && !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content =
+            std::fs::read_to_string(&path).map_err(|e| ConfigError::Io(path.clone(), e))?;
+        Self::from_toml(&content).map_err(|e| match e {
+            ConfigError::Parse(_, msg) => ConfigError::Parse(path, msg),
+            other => other,
+        })
+    }
+
+    /// Parses and validates configuration from TOML text.
+    pub fn from_toml(content: &str) -> Result<Self, ConfigError> {
+        let config: Config = toml::from_str(content)
+            .map_err(|e| ConfigError::Parse(PathBuf::new(), e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks that values are within supported ranges.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        compression::validate_level(self.compression_level)
+            .map_err(|e| ConfigError::Invalid(format!("compression_level: {e}")))?;
+        compression::validate_threads(self.threads)
+            .map_err(|e| ConfigError::Invalid(format!("threads: {e}")))?;
+        self.password_kdf()?;
+        self.chunk_size()?;
+        self.max_decompressed_size()?;
+        self.agent_ttl()?;
+        self.share_secret()?;
+        if self.server.max_payload_mb == 0 {
+            return Err(ConfigError::Invalid(
+                "server.max_payload_mb must be greater than 0".to_string(),
+            ));
+        }
+        if self.server.memory_limit_mb == Some(0) {
+            return Err(ConfigError::Invalid(
+                "server.memory_limit_mb must be greater than 0".to_string(),
+            ));
+        }
+        if self.server.kdf_target_ms == 0 {
+            return Err(ConfigError::Invalid(
+                "server.kdf_target_ms must be greater than 0".to_string(),
+            ));
+        }
+        if self.server.share_max_ttl_secs == 0 {
+            return Err(ConfigError::Invalid(
+                "server.share_max_ttl_secs must be greater than 0".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the configured zstd threads and long-distance matching.
+    pub fn compression_tuning(&self) -> compression::Tuning {
+        compression::Tuning {
+            threads: compression::resolve_threads(self.threads),
+            long_distance_matching: self.long_distance_matching,
+        }
+    }
+
+    /// Returns the configured Argon2 profile.
+    pub fn kdf_profile(&self) -> Result<KdfProfile, ConfigError> {
+        self.kdf_profile
+            .parse()
+            .map_err(|e: crate::crypto::CryptoError| ConfigError::Invalid(e.to_string()))
+    }
+
+    /// Returns the configured password KDF with the parameters of the configured profile.
+    pub fn password_kdf(&self) -> Result<PasswordKdf, ConfigError> {
+        PasswordKdf::for_profile(&self.kdf, self.kdf_profile()?)
+            .map_err(|e| ConfigError::Invalid(e.to_string()))
+    }
+
+    /// Returns the plaintext chunk size for the configured encrypted chunk size, if any.
+    pub fn chunk_size(&self) -> Result<Option<u32>, ConfigError> {
+        self.chunk_size
+            .as_deref()
+            .map(|size| {
+                let bytes = parse_size(size).map_err(ConfigError::Invalid)?;
+                chunked::chunk_size_for_part(bytes)
+                    .map_err(|e| ConfigError::Invalid(format!("chunk_size: {e}")))
+            })
+            .transpose()
+    }
+
+    /// Returns the largest size, in bytes, a decrypted body may decompress to.
+    pub fn max_decompressed_size(&self) -> Result<u64, ConfigError> {
+        match parse_size(&self.max_decompressed_size) {
+            Ok(0) => Err(ConfigError::Invalid(
+                "max_decompressed_size must be greater than 0".to_string(),
+            )),
+            Ok(bytes) => Ok(bytes),
+            Err(e) => Err(ConfigError::Invalid(format!("max_decompressed_size: {e}"))),
+        }
+    }
+
+    /// Returns how long the agent keeps keys.
+    pub fn agent_ttl(&self) -> Result<std::time::Duration, ConfigError> {
+        humantime::parse_duration(&self.agent_ttl)
+            .map_err(|e| ConfigError::Invalid(format!("agent_ttl: {e}")))
+    }
+
+    /// Returns the decoded secret share links are sealed under, if sharing is enabled.
+    pub fn share_secret(&self) -> Result<Option<Zeroizing<Vec<u8>>>, ConfigError> {
+        let Some(secret) = &self.server.share_secret else {
+            return Ok(None);
+        };
+        match general_purpose::STANDARD.decode(secret.trim()) {
+            Ok(bytes) if bytes.len() == 32 => Ok(Some(Zeroizing::new(bytes))),
+            _ => Err(ConfigError::Invalid(
+                "server.share_secret must be a base64 32-byte key".to_string(),
+            )),
+        }
+    }
+
+    /// Places a default output file name inside `output_dir`, if one is configured.
+    pub fn output_path(&self, file_name: &str) -> String {
+        match &self.output_dir {
+            Some(dir) => dir.join(file_name).to_string_lossy().into_owned(),
+            None => file_name.to_string(),
+        }
+    }
+}
+
+/// Parses a byte size such as `65536`, `64KiB`, `8MiB`, `5MB` or `1GiB`.
+///
+/// Binary (`KiB`, `MiB`, `GiB`) and decimal (`KB`, `MB`, `GB`) suffixes are accepted,
+/// case-insensitively; `K`, `M` and `G` alone are binary.
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("Invalid size '{text}'"))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        _ => return Err(format!("Invalid size unit in '{text}'")),
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("Size '{text}' is too large"))
+}
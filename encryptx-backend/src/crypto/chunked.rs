@@ -0,0 +1,506 @@
+//!
+//! Chunked (segmented) ciphertext layout.
+//!
+//! Instead of one AES-GCM message, the body is split into fixed-size plaintext chunks that
+//! are sealed independently: `[chunk 0 + tag][chunk 1 + tag]...`. Every chunk except the
+//! last one holds exactly `chunk_size` plaintext bytes, so chunk boundaries follow from the
+//! header alone. That lets uploaders send each encrypted chunk as its own multipart part
+//! and lets readers authenticate or decrypt chunks independently.
+//!
+//! Chunk nonces are derived from the file nonce by XORing the chunk index (4 bytes,
+//! big-endian) and a final-chunk flag into its last 5 bytes, so chunks cannot be reordered,
+//! dropped from the end or spliced between files. The header JSON is bound to every chunk
+//! as associated data.
+//!
+//! [`seal`] and [`open`] work on all chunks at once, spread over rayon's thread pool (see
+//! [`set_threads`]); each chunk lands at its fixed offset, so the output doesn't depend on
+//! the thread count. [`ChunkWriter`] and [`ChunkReader`] work one chunk at a time over
+//! `std::io` streams, so only one chunk of plaintext is ever buffered.
+//!
+use super::CryptoError;
+use crate::format::XdReader;
+use aes_gcm::{
+    Aes256Gcm, Nonce, Tag,
+    aead::{Aead, AeadInPlace, Payload},
+};
+use rayon::prelude::*;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use zeroize::{Zeroize, Zeroizing};
+
+/// Size of the AES-GCM authentication tag appended to every chunk.
+pub const TAG_LEN: usize = 16;
+
+/// Smallest accepted plaintext chunk size.
+pub const MIN_CHUNK_SIZE: u32 = 1024;
+
+/// Largest accepted plaintext chunk size.
+pub const MAX_CHUNK_SIZE: u32 = 1024 * 1024 * 1024;
+
+/// Smallest part size S3 accepts for every part except the last one.
+pub const S3_MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+
+/// Returns the plaintext chunk size whose encrypted chunks are exactly `part_size` bytes.
+pub fn chunk_size_for_part(part_size: u64) -> Result<u32, CryptoError> {
+    let chunk = part_size.saturating_sub(TAG_LEN as u64);
+    if chunk < MIN_CHUNK_SIZE as u64 || chunk > MAX_CHUNK_SIZE as u64 {
+        return Err(CryptoError::EncryptionError(format!(
+            "Chunk size must be between {} and {} bytes",
+            MIN_CHUNK_SIZE as usize + TAG_LEN,
+            MAX_CHUNK_SIZE as usize + TAG_LEN
+        )));
+    }
+    Ok(chunk as u32)
+}
+
+/// Checks a chunk size read from a header or supplied by a caller.
+pub fn validate_chunk_size(chunk_size: u32) -> Result<(), CryptoError> {
+    if !(MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&chunk_size) {
+        return Err(CryptoError::FormatError);
+    }
+    Ok(())
+}
+
+/// Sizes the thread pool [`seal`] and [`open`] run on: `threads` workers, or one per
+/// available core for 0. Only the first call takes effect, and only before any chunk was
+/// sealed or opened, so call it at startup.
+pub fn set_threads(threads: u32) {
+    let threads = crate::compression::resolve_threads(threads) as usize;
+    // Fails if the pool already exists, which leaves it as it is
+    let _ = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global();
+}
+
+/// Nonce for chunk `index`, derived from the file nonce.
+fn chunk_nonce(base: &[u8], index: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce.copy_from_slice(base);
+    for (byte, counter) in nonce[7..11].iter_mut().zip(index.to_be_bytes()) {
+        *byte ^= counter;
+    }
+    nonce[11] ^= last as u8;
+    nonce
+}
+
+/// Byte ranges of the encrypted chunks within `ciphertext`.
+///
+/// An empty plaintext still produces one (tag-only) chunk.
+fn chunk_ranges(ciphertext_len: usize, chunk_size: u32) -> Vec<Range<usize>> {
+    let sealed = chunk_size as usize + TAG_LEN;
+    let mut ranges = Vec::with_capacity(ciphertext_len / sealed + 1);
+    let mut start = 0;
+    while ciphertext_len - start > sealed {
+        ranges.push(start..start + sealed);
+        start += sealed;
+    }
+    ranges.push(start..ciphertext_len);
+    ranges
+}
+
+/// Encrypts `data` as a sequence of chunks.
+pub fn seal(
+    cipher: &Aes256Gcm,
+    base_nonce: &[u8],
+    aad: &[u8],
+    data: &[u8],
+    chunk_size: u32,
+) -> Result<Vec<u8>, CryptoError> {
+    validate_chunk_size(chunk_size)?;
+    let count = data.len().div_ceil(chunk_size as usize).max(1);
+    if count > u32::MAX as usize {
+        return Err(CryptoError::EncryptionError(
+            "Too many chunks for this chunk size".to_string(),
+        ));
+    }
+
+    // Each chunk is copied to its place in the output and sealed there
+    let mut out = vec![0u8; data.len() + count * TAG_LEN];
+    let sealed_size = chunk_size as usize + TAG_LEN;
+    let last_index = count - 1;
+    out.par_chunks_mut(sealed_size)
+        .enumerate()
+        .try_for_each(|(index, sealed)| {
+            let (chunk, tag) = sealed.split_at_mut(sealed.len() - TAG_LEN);
+            let start = index * chunk_size as usize;
+            chunk.copy_from_slice(&data[start..start + chunk.len()]);
+            let nonce = chunk_nonce(base_nonce, index as u32, index == last_index);
+            let computed = cipher
+                .encrypt_in_place_detached(Nonce::from_slice(&nonce), aad, chunk)
+                .map_err(|_| {
+                    CryptoError::EncryptionError("Authenticated encryption failed".to_string())
+                })?;
+            tag.copy_from_slice(&computed);
+            Ok(())
+        })?;
+    Ok(out)
+}
+
+/// Decrypts a chunked body, failing on the first chunk that does not authenticate.
+pub fn open(
+    cipher: &Aes256Gcm,
+    base_nonce: &[u8],
+    aad: &[u8],
+    ciphertext: &[u8],
+    chunk_size: u32,
+) -> Result<Vec<u8>, CryptoError> {
+    validate_chunk_size(chunk_size)?;
+    let ranges = chunk_ranges(ciphertext.len(), chunk_size);
+    if ranges.iter().any(|range| range.len() < TAG_LEN) {
+        return Err(CryptoError::AuthenticationError);
+    }
+    let last_index = ranges.len() - 1;
+    // Each chunk is decrypted in its place in the output; a tag-only chunk gets an empty
+    // slot but is still authenticated
+    let mut out = vec![0u8; ciphertext.len() - ranges.len() * TAG_LEN];
+    let mut rest = &mut out[..];
+    let mut slots = Vec::with_capacity(ranges.len());
+    for range in &ranges {
+        let (slot, tail) = std::mem::take(&mut rest).split_at_mut(range.len() - TAG_LEN);
+        slots.push(slot);
+        rest = tail;
+    }
+    let opened = slots
+        .into_par_iter()
+        .zip(ranges.par_iter())
+        .enumerate()
+        .try_for_each(|(index, (chunk, range))| {
+            let (sealed, tag) = ciphertext[range.clone()].split_at(range.len() - TAG_LEN);
+            chunk.copy_from_slice(sealed);
+            let nonce = chunk_nonce(base_nonce, index as u32, index == last_index);
+            cipher
+                .decrypt_in_place_detached(
+                    Nonce::from_slice(&nonce),
+                    aad,
+                    chunk,
+                    Tag::from_slice(tag),
+                )
+                .map_err(|_| CryptoError::AuthenticationError)
+        });
+    if let Err(e) = opened {
+        // Chunks before the bad one were decrypted already
+        out.zeroize();
+        return Err(e);
+    }
+    Ok(out)
+}
+
+/// Decrypts chunk `index` of a body on its own, for readers that jump between chunks.
+///
+/// `chunk` holds the sealed chunk with its tag and is left holding the plaintext; `last`
+/// says whether it is the body's final chunk.
+pub fn open_chunk(
+    cipher: &Aes256Gcm,
+    base_nonce: &[u8],
+    aad: &[u8],
+    index: u32,
+    last: bool,
+    chunk: &mut Vec<u8>,
+) -> Result<(), CryptoError> {
+    if base_nonce.len() != 12 {
+        return Err(CryptoError::FormatError);
+    }
+    let nonce = chunk_nonce(base_nonce, index, last);
+    cipher
+        .decrypt_in_place(Nonce::from_slice(&nonce), aad, chunk)
+        .map_err(|_| CryptoError::AuthenticationError)
+}
+
+/// Authenticates each chunk independently, returning the file offset, length and
+/// result for every chunk. Plaintext only lives in a scratch buffer that is zeroized.
+pub fn check(
+    cipher: &Aes256Gcm,
+    file: &XdReader<'_>,
+    chunk_size: u32,
+) -> Result<Vec<(usize, usize, bool)>, CryptoError> {
+    validate_chunk_size(chunk_size)?;
+    let ranges = chunk_ranges(file.ciphertext.len(), chunk_size);
+    let last_index = ranges.len() - 1;
+    let mut results = Vec::with_capacity(ranges.len());
+    let mut scratch = Vec::with_capacity(chunk_size as usize + TAG_LEN);
+    let aad = file.aad();
+    for (index, range) in ranges.into_iter().enumerate() {
+        let nonce = chunk_nonce(file.nonce, index as u32, index == last_index);
+        scratch.clear();
+        scratch.extend_from_slice(&file.ciphertext[range.clone()]);
+        let ok = cipher
+            .decrypt_in_place(Nonce::from_slice(&nonce), &aad, &mut scratch)
+            .is_ok();
+        scratch.zeroize();
+        results.push((file.ciphertext_offset + range.start, range.len(), ok));
+    }
+    Ok(results)
+}
+
+/// Ciphertext ranges of a damaged body with their plaintext, `None` where it was lost.
+pub type Salvaged = Vec<(Range<usize>, Option<Vec<u8>>)>;
+
+/// Decrypts every chunk that still authenticates, for recovering damaged files.
+///
+/// Returns each chunk's range within the ciphertext with its plaintext (`None` if lost),
+/// and whether the final chunk was found, i.e. the file is not truncated. Chunks sit at
+/// fixed offsets, so bit rot and truncation are recoverable but inserted or removed bytes
+/// lose everything after them.
+pub fn salvage(
+    cipher: &Aes256Gcm,
+    file: &XdReader<'_>,
+    chunk_size: u32,
+) -> Result<(Salvaged, bool), CryptoError> {
+    validate_chunk_size(chunk_size)?;
+    let ranges = chunk_ranges(file.ciphertext.len(), chunk_size);
+    let last_index = ranges.len() - 1;
+    let mut complete = false;
+    let mut results = Vec::with_capacity(ranges.len());
+    let aad = file.aad();
+    for (index, range) in ranges.into_iter().enumerate() {
+        let open = |last: bool| {
+            let nonce = chunk_nonce(file.nonce, index as u32, last);
+            cipher
+                .decrypt(
+                    Nonce::from_slice(&nonce),
+                    Payload {
+                        msg: &file.ciphertext[range.clone()],
+                        aad: &aad,
+                    },
+                )
+                .ok()
+        };
+        // Only the physically last chunk can be the final one; if it isn't, the file was
+        // cut at a chunk boundary
+        let mut plain = open(false);
+        if plain.is_none() && index == last_index {
+            plain = open(true);
+            complete = plain.is_some();
+        }
+        results.push((range, plain));
+    }
+    Ok((results, complete))
+}
+
+/// Streaming [`seal`]: plaintext written to it is sealed chunk by chunk into `out`.
+///
+/// A full chunk is only sealed once more data arrives, since the final chunk is marked in
+/// its nonce; [`finish`](Self::finish) seals the last one. Dropping the writer without
+/// finishing leaves a body that fails to authenticate.
+pub struct ChunkWriter<W: Write> {
+    out: W,
+    cipher: Aes256Gcm,
+    base_nonce: [u8; 12],
+    aad: Vec<u8>,
+    chunk_size: usize,
+    index: u32,
+    /// Plaintext of the chunk being filled; sealed in place
+    buffer: Zeroizing<Vec<u8>>,
+    written: u64,
+}
+
+impl<W: Write> ChunkWriter<W> {
+    /// Starts a body under `cipher`, `base_nonce` and `aad` (the header associated data)
+    /// that is written to `out` after whatever `out` already holds.
+    pub fn new(
+        out: W,
+        cipher: Aes256Gcm,
+        base_nonce: &[u8],
+        aad: Vec<u8>,
+        chunk_size: u32,
+    ) -> Result<Self, CryptoError> {
+        validate_chunk_size(chunk_size)?;
+        let base_nonce = base_nonce.try_into().map_err(|_| {
+            CryptoError::EncryptionError("Nonce must be exactly 12 bytes".to_string())
+        })?;
+        Ok(Self {
+            out,
+            cipher,
+            base_nonce,
+            aad,
+            chunk_size: chunk_size as usize,
+            index: 0,
+            buffer: Zeroizing::new(Vec::with_capacity(chunk_size as usize + TAG_LEN)),
+            written: 0,
+        })
+    }
+
+    /// Plaintext bytes written so far.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Seals the buffered chunk and writes it out.
+    fn seal_buffer(&mut self, last: bool) -> Result<(), CryptoError> {
+        let nonce = chunk_nonce(&self.base_nonce, self.index, last);
+        self.cipher
+            .encrypt_in_place(Nonce::from_slice(&nonce), &self.aad, &mut *self.buffer)
+            .map_err(|_| {
+                CryptoError::EncryptionError("Authenticated encryption failed".to_string())
+            })?;
+        self.out
+            .write_all(&self.buffer)
+            .map_err(|e| CryptoError::EncryptionError(format!("Failed to write chunk: {e}")))?;
+        self.buffer.clear();
+        self.index = self.index.checked_add(1).ok_or_else(|| {
+            CryptoError::EncryptionError("Too many chunks for this chunk size".to_string())
+        })?;
+        Ok(())
+    }
+
+    /// Seals the final chunk and returns `out`.
+    pub fn finish(mut self) -> Result<W, CryptoError> {
+        self.seal_buffer(true)?;
+        self.out
+            .flush()
+            .map_err(|e| CryptoError::EncryptionError(format!("Failed to write chunk: {e}")))?;
+        Ok(self.out)
+    }
+}
+
+impl<W: Write> Write for ChunkWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if data.is_empty() {
+            return Ok(0);
+        }
+        if self.buffer.len() == self.chunk_size {
+            self.seal_buffer(false).map_err(io::Error::other)?;
+        }
+        let take = data.len().min(self.chunk_size - self.buffer.len());
+        self.buffer.extend_from_slice(&data[..take]);
+        self.written += take as u64;
+        Ok(take)
+    }
+
+    /// Flushes `out`; the chunk being filled stays buffered until it is full.
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Streaming [`open`]: reads a chunked body from `input` and yields its plaintext.
+///
+/// Each chunk is authenticated before any of its plaintext is returned. A chunk that fails,
+/// or a body cut off before its final chunk, is an [`io::ErrorKind::InvalidData`] error
+/// wrapping [`CryptoError::AuthenticationError`]; plaintext already returned is then not
+/// to be trusted as complete.
+pub struct ChunkReader<R: Read> {
+    input: R,
+    cipher: Aes256Gcm,
+    base_nonce: [u8; 12],
+    aad: Vec<u8>,
+    chunk_size: usize,
+    index: u32,
+    /// Ciphertext read ahead: one sealed chunk plus a byte to tell whether it is the last
+    pending: Vec<u8>,
+    /// Plaintext of the current chunk and how much of it was returned
+    plain: Zeroizing<Vec<u8>>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> ChunkReader<R> {
+    /// Starts reading a body sealed under `cipher`, `base_nonce` and `aad`.
+    pub fn new(
+        input: R,
+        cipher: Aes256Gcm,
+        base_nonce: &[u8],
+        aad: Vec<u8>,
+        chunk_size: u32,
+    ) -> Result<Self, CryptoError> {
+        validate_chunk_size(chunk_size)?;
+        let base_nonce = base_nonce
+            .try_into()
+            .map_err(|_| CryptoError::FormatError)?;
+        Ok(Self {
+            input,
+            cipher,
+            base_nonce,
+            aad,
+            chunk_size: chunk_size as usize,
+            index: 0,
+            pending: Vec::new(),
+            plain: Zeroizing::new(Vec::new()),
+            pos: 0,
+            done: false,
+        })
+    }
+
+    /// Reads and opens the next chunk into `plain`.
+    fn next_chunk(&mut self) -> io::Result<()> {
+        let sealed = self.chunk_size + TAG_LEN;
+        let mut filled = self.pending.len();
+        self.pending.resize(sealed + 1, 0);
+        while filled < self.pending.len() {
+            match self.input.read(&mut self.pending[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.pending.truncate(filled);
+
+        // Only a chunk with nothing after it may be the final one
+        let last = filled <= sealed;
+        let len = filled.min(sealed);
+        let nonce = chunk_nonce(&self.base_nonce, self.index, last);
+        let authentication_failed =
+            || io::Error::new(io::ErrorKind::InvalidData, CryptoError::AuthenticationError);
+        self.plain.clear();
+        self.plain.extend_from_slice(&self.pending[..len]);
+        self.cipher
+            .decrypt_in_place(Nonce::from_slice(&nonce), &self.aad, &mut *self.plain)
+            .map_err(|_| authentication_failed())?;
+        self.pending.drain(..len);
+        self.pos = 0;
+        self.done = last;
+        self.index = self
+            .index
+            .checked_add(1)
+            .ok_or_else(authentication_failed)?;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for ChunkReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.plain.len() {
+            if self.done || buf.is_empty() {
+                return Ok(0);
+            }
+            self.next_chunk()?;
+        }
+        let n = buf.len().min(self.plain.len() - self.pos);
+        buf[..n].copy_from_slice(&self.plain[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Returns the byte ranges of a chunked `.xd` file that can be uploaded as separate parts.
+///
+/// The first range also covers the header and nonce that precede chunk 0; every other
+/// range is exactly one encrypted chunk, except that the last one also covers a signature
+/// trailer. Non-chunked files yield a single range.
+pub fn part_boundaries(data: &[u8]) -> Result<Vec<Range<usize>>, CryptoError> {
+    let file = XdReader::parse(data)?;
+    let mut ranges = match file.header.chunk_size() {
+        Some(chunk_size) if file.header.delta_id().is_some() => {
+            validate_chunk_size(chunk_size)?;
+            super::delta::part_ranges(&file, chunk_size)?
+        }
+        Some(chunk_size) => {
+            validate_chunk_size(chunk_size)?;
+            chunk_ranges(file.ciphertext.len(), chunk_size)
+                .into_iter()
+                .map(|r| file.ciphertext_offset + r.start..file.ciphertext_offset + r.end)
+                .collect()
+        }
+        None => {
+            let whole = file.ciphertext_offset..data.len();
+            vec![whole]
+        }
+    };
+    ranges[0].start = 0;
+    // A signature trailer (see `signing`) travels with the last part
+    if let Some(last) = ranges.last_mut() {
+        last.end = data.len();
+    }
+    Ok(ranges)
+}
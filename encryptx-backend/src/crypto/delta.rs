@@ -0,0 +1,575 @@
+//!
+//! Delta-friendly ciphertext layout.
+//!
+//! Used when a new version of a file is encrypted from an older one (`--delta-from`). Every
+//! chunk carries its own random nonce, so chunks whose plaintext did not change can be copied
+//! verbatim from the previous version instead of being re-encrypted. Unchanged regions then
+//! produce identical bytes, which rsync and deduplicating backup tools transfer cheaply.
+//!
+//! Body layout after the file nonce:
+//!
+//! ```text
+//! [u32 BE length][12-byte nonce][ciphertext + tag]   (one record per chunk)
+//! ...
+//! [16-byte trailer tag]
+//! ```
+//!
+//! Chunks are bound to the file's stable `delta_id` (associated data), so they can only be
+//! reused within versions of the same file under the same key. The trailer is a GCM tag over
+//! the header JSON and the ordered list of chunk tags, sealed with the per-version file nonce;
+//! it rejects reordered, dropped or spliced chunks and authenticates the header.
+//!
+//! Unchanged chunks are found rsync-style: a rolling checksum slides over the new plaintext
+//! and candidate matches against the previous version's chunks are confirmed with SHA-256,
+//! so reuse also works when data was inserted or removed before a chunk. When the caller
+//! supplies content-defined cut points (`--rsyncable`), chunks follow those instead and are
+//! matched by SHA-256 alone, so chunk boundaries survive edits as well.
+//!
+use super::{CryptoError, SecureKey};
+use crate::format::XdReader;
+use aes_gcm::{
+    Aes256Gcm, KeyInit, Nonce, Tag,
+    aead::{Aead, AeadCore, AeadInPlace, OsRng, Payload, rand_core::RngCore},
+};
+use base64::engine::Engine;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::ops::Range;
+use zeroize::Zeroize;
+
+/// Domain separator mixed into all associated data of this layout.
+const DOMAIN: &[u8] = b"encryptx-delta-v1";
+
+/// Size of the AES-GCM authentication tag.
+const TAG_LEN: usize = 16;
+
+/// Size of the per-chunk nonce.
+const NONCE_LEN: usize = 12;
+
+/// Length prefix of every chunk record.
+const LEN_PREFIX: usize = 4;
+
+/// How much of the new version was copied from the previous one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DeltaStats {
+    /// Chunks copied unchanged from the previous version
+    pub reused_chunks: usize,
+    /// Chunks that had to be encrypted again
+    pub new_chunks: usize,
+    /// Plaintext bytes covered by reused chunks
+    pub reused_bytes: usize,
+}
+
+/// Generates a fresh identifier for a new chain of file versions.
+pub fn new_delta_id() -> String {
+    let mut id = [0u8; 16];
+    OsRng.fill_bytes(&mut id);
+    base64::engine::general_purpose::STANDARD.encode(id)
+}
+
+fn chunk_aad(delta_id: &str) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(DOMAIN.len() + delta_id.len());
+    aad.extend_from_slice(DOMAIN);
+    aad.extend_from_slice(delta_id.as_bytes());
+    aad
+}
+
+fn trailer_aad<'a>(header_json: &[u8], tags: impl Iterator<Item = &'a [u8]>) -> Vec<u8> {
+    let mut aad = Vec::new();
+    aad.extend_from_slice(DOMAIN);
+    aad.extend_from_slice(&(header_json.len() as u64).to_be_bytes());
+    aad.extend_from_slice(header_json);
+    let mut count = 0u64;
+    let mut list = Vec::new();
+    for tag in tags {
+        list.extend_from_slice(tag);
+        count += 1;
+    }
+    aad.extend_from_slice(&count.to_be_bytes());
+    aad.extend_from_slice(&list);
+    aad
+}
+
+/// One chunk record located inside a body.
+struct Record {
+    /// Whole record (length prefix, nonce, ciphertext) within the body
+    range: Range<usize>,
+    nonce: Range<usize>,
+    ciphertext: Range<usize>,
+}
+
+impl Record {
+    fn tag<'a>(&self, body: &'a [u8]) -> &'a [u8] {
+        &body[self.ciphertext.end - TAG_LEN..self.ciphertext.end]
+    }
+}
+
+/// Splits a body into chunk records and the trailer tag.
+fn records(body: &[u8], chunk_size: u32) -> Result<(Vec<Record>, Range<usize>), CryptoError> {
+    if body.len() < TAG_LEN {
+        return Err(CryptoError::FormatError);
+    }
+    let end = body.len() - TAG_LEN;
+    Ok((parse_records(&body[..end], chunk_size)?, end..body.len()))
+}
+
+/// Parses consecutive chunk records that fill `bytes` exactly.
+fn parse_records(bytes: &[u8], chunk_size: u32) -> Result<Vec<Record>, CryptoError> {
+    let max_len = chunk_size as usize + TAG_LEN;
+    let mut records = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        if bytes.len() - pos < LEN_PREFIX + NONCE_LEN {
+            return Err(CryptoError::FormatError);
+        }
+        let len = u32::from_be_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]])
+            as usize;
+        let nonce_start = pos + LEN_PREFIX;
+        let ct_start = nonce_start + NONCE_LEN;
+        if !(TAG_LEN..=max_len).contains(&len) || bytes.len() - ct_start < len {
+            return Err(CryptoError::FormatError);
+        }
+        records.push(Record {
+            range: pos..ct_start + len,
+            nonce: nonce_start..ct_start,
+            ciphertext: ct_start..ct_start + len,
+        });
+        pos = ct_start + len;
+    }
+    Ok(records)
+}
+
+/// Previous version of a file, decrypted and indexed for chunk reuse.
+pub struct PreviousVersion {
+    delta_id: String,
+    body: Vec<u8>,
+    /// Record range in `body` for each reusable chunk, keyed by plaintext SHA-256
+    chunks: HashMap<[u8; 32], Range<usize>>,
+    /// Rolling checksum of each reusable full-size chunk, pointing at its SHA-256
+    weak: HashMap<u32, Vec<[u8; 32]>>,
+    chunk_size: u32,
+}
+
+impl PreviousVersion {
+    /// Indexes a previous version.
+    ///
+    /// Files in another layout yield an empty index: nothing can be reused, but the next
+    /// version starts a delta chain. `key` must be the key that opens `file`.
+    pub fn load(
+        file: &XdReader<'_>,
+        key: &SecureKey,
+        delta_id: Option<&str>,
+        chunk_size: u32,
+    ) -> Result<Self, CryptoError> {
+        let mut previous = Self {
+            delta_id: delta_id.map(str::to_string).unwrap_or_else(new_delta_id),
+            body: Vec::new(),
+            chunks: HashMap::new(),
+            weak: HashMap::new(),
+            chunk_size,
+        };
+        let Some(delta_id) = delta_id else {
+            return Ok(previous);
+        };
+        if file.header.chunk_size() != Some(chunk_size) {
+            return Ok(previous);
+        }
+
+        let cipher = Aes256Gcm::new_from_slice(key.as_slice())
+            .map_err(|_| CryptoError::DecryptionError("Failed to create cipher".to_string()))?;
+        let (records, trailer) = records(file.ciphertext, chunk_size)?;
+        verify_trailer(&cipher, file, &records, trailer)?;
+
+        let aad = chunk_aad(delta_id);
+        for record in &records {
+            let mut plain = cipher
+                .decrypt(
+                    Nonce::from_slice(&file.ciphertext[record.nonce.clone()]),
+                    Payload {
+                        msg: &file.ciphertext[record.ciphertext.clone()],
+                        aad: &aad,
+                    },
+                )
+                .map_err(|_| CryptoError::AuthenticationError)?;
+            let strong: [u8; 32] = Sha256::digest(&plain).into();
+            if !plain.is_empty() && !previous.chunks.contains_key(&strong) {
+                let start = previous.body.len();
+                previous
+                    .body
+                    .extend_from_slice(&file.ciphertext[record.range.clone()]);
+                previous.chunks.insert(strong, start..previous.body.len());
+                if plain.len() == chunk_size as usize {
+                    previous
+                        .weak
+                        .entry(Rolling::new(&plain).value())
+                        .or_default()
+                        .push(strong);
+                }
+            }
+            plain.zeroize();
+        }
+        Ok(previous)
+    }
+
+    /// Identifier shared by all versions in this chain.
+    pub fn delta_id(&self) -> &str {
+        &self.delta_id
+    }
+
+    /// Returns the stored record for a full-size chunk with this content, if any.
+    fn find(&self, weak: u32, chunk: &[u8]) -> Option<&[u8]> {
+        let candidates = self.weak.get(&weak)?;
+        let strong: [u8; 32] = Sha256::digest(chunk).into();
+        if !candidates.contains(&strong) {
+            return None;
+        }
+        self.chunks.get(&strong).map(|r| &self.body[r.clone()])
+    }
+
+    /// Returns the stored record for a chunk of any size with exactly this content.
+    fn find_exact(&self, chunk: &[u8]) -> Option<&[u8]> {
+        let strong: [u8; 32] = Sha256::digest(chunk).into();
+        self.chunks.get(&strong).map(|r| &self.body[r.clone()])
+    }
+}
+
+/// rsync-style rolling checksum over a fixed window.
+struct Rolling {
+    a: u32,
+    b: u32,
+    len: u32,
+}
+
+impl Rolling {
+    fn new(window: &[u8]) -> Self {
+        let len = window.len() as u32;
+        let mut a = 0u32;
+        let mut b = 0u32;
+        for (i, &byte) in window.iter().enumerate() {
+            a = a.wrapping_add(byte as u32);
+            b = b.wrapping_add((len - i as u32).wrapping_mul(byte as u32));
+        }
+        Self { a, b, len }
+    }
+
+    fn roll(&mut self, out: u8, input: u8) {
+        self.a = self.a.wrapping_sub(out as u32).wrapping_add(input as u32);
+        self.b = self
+            .b
+            .wrapping_sub(self.len.wrapping_mul(out as u32))
+            .wrapping_add(self.a);
+    }
+
+    fn value(&self) -> u32 {
+        (self.a & 0xFFFF) | (self.b << 16)
+    }
+}
+
+/// Encrypts `literal` as fresh chunks of at most `chunk_size` bytes and appends the records.
+///
+/// An empty literal still produces one (empty) chunk.
+fn seal_literal(
+    cipher: &Aes256Gcm,
+    aad: &[u8],
+    literal: &[u8],
+    chunk_size: usize,
+    body: &mut Vec<u8>,
+    stats: &mut DeltaStats,
+) -> Result<(), CryptoError> {
+    let pieces: Vec<&[u8]> = if literal.is_empty() {
+        vec![&[]]
+    } else {
+        literal.chunks(chunk_size).collect()
+    };
+    for piece in pieces {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, Payload { msg: piece, aad })
+            .map_err(|_| {
+                CryptoError::EncryptionError("Authenticated encryption failed".to_string())
+            })?;
+        body.extend_from_slice(&(ciphertext.len() as u32).to_be_bytes());
+        body.extend_from_slice(&nonce);
+        body.extend_from_slice(&ciphertext);
+        stats.new_chunks += 1;
+    }
+    Ok(())
+}
+
+/// Encrypts `data` in the delta layout, reusing chunks from `previous` where possible.
+///
+/// `header_json` must be the header of the new file as authenticated (see [`XdReader::aad`]),
+/// carrying the same `delta_id` and chunk size as `previous`; `key` must be the key `previous` was loaded with.
+///
+/// With `cuts` (ascending offsets into `data`), chunks end at those offsets (pieces longer
+/// than the chunk size are split further) and are reused only on an exact content match.
+pub fn seal(
+    key: &SecureKey,
+    file_nonce: &[u8],
+    header_json: &[u8],
+    data: &[u8],
+    previous: &PreviousVersion,
+    cuts: Option<&[usize]>,
+) -> Result<(Vec<u8>, DeltaStats), CryptoError> {
+    let cipher = Aes256Gcm::new_from_slice(key.as_slice()).map_err(|_| {
+        CryptoError::EncryptionError("Failed to initialize AES-256-GCM cipher".to_string())
+    })?;
+    let aad = chunk_aad(&previous.delta_id);
+    let chunk_size = previous.chunk_size as usize;
+    let mut body = Vec::with_capacity(data.len() + (data.len() / chunk_size + 1) * 32 + TAG_LEN);
+    let mut stats = DeltaStats::default();
+
+    // Slide over the new data looking for chunks the previous version already holds
+    let mut literal_start = 0;
+    let mut pos = 0;
+    if let Some(cuts) = cuts {
+        let ends = cuts
+            .iter()
+            .copied()
+            .filter(|&cut| cut > 0 && cut < data.len())
+            .chain([data.len()]);
+        for end in ends {
+            if end <= literal_start {
+                continue;
+            }
+            for piece in data[literal_start..end].chunks(chunk_size) {
+                match previous.find_exact(piece) {
+                    Some(record) => {
+                        body.extend_from_slice(record);
+                        stats.reused_chunks += 1;
+                        stats.reused_bytes += piece.len();
+                    }
+                    None => seal_literal(&cipher, &aad, piece, chunk_size, &mut body, &mut stats)?,
+                }
+            }
+            literal_start = end;
+        }
+    } else if !previous.chunks.is_empty() && data.len() >= chunk_size {
+        let mut rolling = Rolling::new(&data[..chunk_size]);
+        loop {
+            if let Some(record) = previous.find(rolling.value(), &data[pos..pos + chunk_size]) {
+                if literal_start < pos {
+                    seal_literal(
+                        &cipher,
+                        &aad,
+                        &data[literal_start..pos],
+                        chunk_size,
+                        &mut body,
+                        &mut stats,
+                    )?;
+                }
+                body.extend_from_slice(record);
+                stats.reused_chunks += 1;
+                stats.reused_bytes += chunk_size;
+                pos += chunk_size;
+                literal_start = pos;
+                if pos + chunk_size > data.len() {
+                    break;
+                }
+                rolling = Rolling::new(&data[pos..pos + chunk_size]);
+            } else {
+                if pos + chunk_size >= data.len() {
+                    break;
+                }
+                rolling.roll(data[pos], data[pos + chunk_size]);
+                pos += 1;
+            }
+        }
+    }
+    if literal_start < data.len() || stats.reused_chunks + stats.new_chunks == 0 {
+        seal_literal(
+            &cipher,
+            &aad,
+            &data[literal_start..],
+            chunk_size,
+            &mut body,
+            &mut stats,
+        )?;
+    }
+
+    let records = parse_records(&body, previous.chunk_size)?;
+    let trailer = cipher
+        .encrypt(
+            Nonce::from_slice(file_nonce),
+            Payload {
+                msg: &[],
+                aad: &trailer_aad(header_json, records.iter().map(|r| r.tag(&body))),
+            },
+        )
+        .map_err(|_| CryptoError::EncryptionError("Authenticated encryption failed".to_string()))?;
+    body.extend_from_slice(&trailer);
+    Ok((body, stats))
+}
+
+fn verify_trailer(
+    cipher: &Aes256Gcm,
+    file: &XdReader<'_>,
+    records: &[Record],
+    trailer: Range<usize>,
+) -> Result<(), CryptoError> {
+    let aad = trailer_aad(&file.aad(), records.iter().map(|r| r.tag(file.ciphertext)));
+    let mut empty = Vec::new();
+    cipher
+        .decrypt_in_place_detached(
+            Nonce::from_slice(file.nonce),
+            &aad,
+            &mut empty,
+            Tag::from_slice(&file.ciphertext[trailer]),
+        )
+        .map_err(|_| CryptoError::AuthenticationError)
+}
+
+/// Decrypts a delta-layout body.
+pub fn open(
+    cipher: &Aes256Gcm,
+    file: &XdReader<'_>,
+    delta_id: &str,
+    chunk_size: u32,
+) -> Result<Vec<u8>, CryptoError> {
+    let (records, trailer) = records(file.ciphertext, chunk_size)?;
+    verify_trailer(cipher, file, &records, trailer)?;
+    let aad = chunk_aad(delta_id);
+    let mut out = Vec::with_capacity(file.ciphertext.len());
+    for record in &records {
+        let plain = cipher
+            .decrypt(
+                Nonce::from_slice(&file.ciphertext[record.nonce.clone()]),
+                Payload {
+                    msg: &file.ciphertext[record.ciphertext.clone()],
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| CryptoError::AuthenticationError)?;
+        out.extend_from_slice(&plain);
+    }
+    Ok(out)
+}
+
+/// Authenticates each chunk and the trailer independently, returning the file offset,
+/// length and result for each region (the trailer is the last region).
+pub fn check(
+    cipher: &Aes256Gcm,
+    file: &XdReader<'_>,
+    delta_id: &str,
+    chunk_size: u32,
+) -> Result<Vec<(usize, usize, bool)>, CryptoError> {
+    let (records, trailer) = records(file.ciphertext, chunk_size)?;
+    let aad = chunk_aad(delta_id);
+    let mut results = Vec::with_capacity(records.len() + 1);
+    let mut scratch = Vec::with_capacity(chunk_size as usize + TAG_LEN);
+    for record in &records {
+        scratch.clear();
+        scratch.extend_from_slice(&file.ciphertext[record.ciphertext.clone()]);
+        let ok = cipher
+            .decrypt_in_place(
+                Nonce::from_slice(&file.ciphertext[record.nonce.clone()]),
+                &aad,
+                &mut scratch,
+            )
+            .is_ok();
+        scratch.zeroize();
+        results.push((
+            file.ciphertext_offset + record.range.start,
+            record.range.len(),
+            ok,
+        ));
+    }
+    let ok = verify_trailer(cipher, file, &records, trailer.clone()).is_ok();
+    results.push((file.ciphertext_offset + trailer.start, trailer.len(), ok));
+    Ok(results)
+}
+
+/// Decrypts every chunk record that still authenticates, for recovering damaged files.
+///
+/// Records are self-delimiting, so after a damaged region the body is scanned byte by byte
+/// for the next record that parses and authenticates. Returns the ranges within the
+/// ciphertext with their plaintext (`None` for lost regions), and whether the whole body
+/// up to the trailer was intact and the trailer verified.
+pub fn salvage(
+    cipher: &Aes256Gcm,
+    file: &XdReader<'_>,
+    delta_id: &str,
+    chunk_size: u32,
+) -> (super::chunked::Salvaged, bool) {
+    let body = file.ciphertext;
+    let aad = chunk_aad(delta_id);
+    let max_len = chunk_size as usize + TAG_LEN;
+    let try_record = |pos: usize| {
+        let ct_start = pos + LEN_PREFIX + NONCE_LEN;
+        if body.len() < ct_start {
+            return None;
+        }
+        let len =
+            u32::from_be_bytes([body[pos], body[pos + 1], body[pos + 2], body[pos + 3]]) as usize;
+        if !(TAG_LEN..=max_len).contains(&len) || body.len() - ct_start < len {
+            return None;
+        }
+        let record = Record {
+            range: pos..ct_start + len,
+            nonce: pos + LEN_PREFIX..ct_start,
+            ciphertext: ct_start..ct_start + len,
+        };
+        let plain = cipher
+            .decrypt(
+                Nonce::from_slice(&body[record.nonce.clone()]),
+                Payload {
+                    msg: &body[record.ciphertext.clone()],
+                    aad: &aad,
+                },
+            )
+            .ok()?;
+        Some((record, plain))
+    };
+
+    let mut results = Vec::new();
+    let mut found = Vec::new();
+    let mut lost_start = None;
+    let mut pos = 0;
+    while pos < body.len() {
+        if let Some((record, plain)) = try_record(pos) {
+            if let Some(start) = lost_start.take() {
+                results.push((start..pos, None));
+            }
+            pos = record.range.end;
+            results.push((record.range.clone(), Some(plain)));
+            found.push(record);
+        } else if lost_start.is_none() && body.len() - pos == TAG_LEN {
+            break;
+        } else {
+            lost_start.get_or_insert(pos);
+            pos += 1;
+        }
+    }
+
+    let complete = match lost_start {
+        Some(start) => {
+            results.push((start..body.len(), None));
+            false
+        }
+        None if pos < body.len() => {
+            let ok = verify_trailer(cipher, file, &found, pos..body.len()).is_ok();
+            if !ok {
+                results.push((pos..body.len(), None));
+            }
+            ok
+        }
+        None => false,
+    };
+    (results, complete)
+}
+
+/// Byte ranges of the chunk records within the file; the trailer is part of the last range.
+pub fn part_ranges(file: &XdReader<'_>, chunk_size: u32) -> Result<Vec<Range<usize>>, CryptoError> {
+    let (records, trailer) = records(file.ciphertext, chunk_size)?;
+    let offset = file.ciphertext_offset;
+    let mut ranges: Vec<Range<usize>> = records
+        .iter()
+        .map(|r| offset + r.range.start..offset + r.range.end)
+        .collect();
+    match ranges.last_mut() {
+        Some(last) => last.end = offset + trailer.end,
+        None => return Err(CryptoError::FormatError),
+    }
+    Ok(ranges)
+}
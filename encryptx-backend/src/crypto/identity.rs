@@ -0,0 +1,260 @@
+//!
+//! X25519 identities and recipients for public-key encryption.
+//!
+//! An identity is a private key, kept in an identity file; its recipient (public key) can
+//! be shared freely. Files encrypted to recipients carry one keyslot per recipient (see
+//! [`keyslot`](super::keyslot)), so only the matching identities can open them.
+//!
+//! Post-quantum identities add an ML-KEM-768 key to the X25519 one. Their recipients get a
+//! hybrid slot that stays confidential as long as either of the two key agreements holds.
+//!
+//! Text forms: recipients are `xdpub1:<base64>`, identities `xdsec1:<base64>`; the hybrid
+//! forms are `xdpq1:` (X25519 public key followed by the ML-KEM encapsulation key) and
+//! `xdpqsec1:` (X25519 secret followed by the 64-byte ML-KEM seed). Identity files may
+//! contain `#` comment lines, which [`Identity::to_file`] uses to record the recipient.
+//! An identities file holds several identities, one per line, like age's identity files;
+//! [`Identity::parse_identities`] reads them all.
+//!
+use super::CryptoError;
+use aes_gcm::aead::{OsRng, rand_core::RngCore};
+use base64::engine::Engine;
+use ml_kem::{B32, EncodedSizeUser, KemCore, MlKem768};
+use std::fmt;
+use std::str::FromStr;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::{Zeroize, Zeroizing};
+
+/// Prefix of a recipient (public key) string.
+pub const RECIPIENT_PREFIX: &str = "xdpub1:";
+
+/// Prefix of an identity (private key) string.
+pub const IDENTITY_PREFIX: &str = "xdsec1:";
+
+/// Prefix of a hybrid X25519 + ML-KEM-768 recipient string.
+pub const PQ_RECIPIENT_PREFIX: &str = "xdpq1:";
+
+/// Prefix of a hybrid X25519 + ML-KEM-768 identity string.
+pub const PQ_IDENTITY_PREFIX: &str = "xdpqsec1:";
+
+/// ML-KEM-768 encapsulation key, as used by hybrid recipients.
+pub(crate) type MlKemPublicKey = <MlKem768 as KemCore>::EncapsulationKey;
+
+/// ML-KEM-768 decapsulation key, as used by hybrid identities.
+pub(crate) type MlKemSecretKey = <MlKem768 as KemCore>::DecapsulationKey;
+
+/// Length of an encoded ML-KEM-768 encapsulation key.
+const MLKEM_PUBLIC_LEN: usize = 1184;
+
+/// Length of an ML-KEM seed (`d` followed by `z`).
+const MLKEM_SEED_LEN: usize = 64;
+
+fn decode_32(text: &str) -> Option<[u8; 32]> {
+    let mut bytes = base64::engine::general_purpose::STANDARD
+        .decode(text.trim())
+        .ok()?;
+    let key = <[u8; 32]>::try_from(bytes.as_slice()).ok();
+    bytes.zeroize();
+    key
+}
+
+/// A public key that files can be encrypted to.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Recipient {
+    x25519: PublicKey,
+    /// Encoded ML-KEM-768 encapsulation key (hybrid recipients only)
+    mlkem: Option<Vec<u8>>,
+}
+
+impl Recipient {
+    pub(crate) fn public_key(&self) -> &PublicKey {
+        &self.x25519
+    }
+
+    /// The ML-KEM-768 encapsulation key of a hybrid recipient.
+    pub(crate) fn mlkem_key(&self) -> Option<MlKemPublicKey> {
+        let bytes = self.mlkem.as_deref()?;
+        Some(MlKemPublicKey::from_bytes(bytes.try_into().ok()?))
+    }
+
+    /// Whether this recipient also has a post-quantum (ML-KEM-768) key.
+    pub fn is_hybrid(&self) -> bool {
+        self.mlkem.is_some()
+    }
+}
+
+impl fmt::Display for Recipient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.mlkem {
+            None => write!(
+                f,
+                "{RECIPIENT_PREFIX}{}",
+                base64::engine::general_purpose::STANDARD.encode(self.x25519.as_bytes())
+            ),
+            Some(mlkem) => write!(
+                f,
+                "{PQ_RECIPIENT_PREFIX}{}",
+                base64::engine::general_purpose::STANDARD
+                    .encode([self.x25519.as_bytes().as_slice(), mlkem].concat())
+            ),
+        }
+    }
+}
+
+impl FromStr for Recipient {
+    type Err = CryptoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || CryptoError::EncryptionError("Invalid recipient".to_string());
+        let s = s.trim();
+        if let Some(encoded) = s.strip_prefix(PQ_RECIPIENT_PREFIX) {
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .map_err(|_| invalid())?;
+            if bytes.len() != 32 + MLKEM_PUBLIC_LEN {
+                return Err(invalid());
+            }
+            let x25519: [u8; 32] = bytes[..32].try_into().map_err(|_| invalid())?;
+            return Ok(Recipient {
+                x25519: PublicKey::from(x25519),
+                mlkem: Some(bytes[32..].to_vec()),
+            });
+        }
+        s.strip_prefix(RECIPIENT_PREFIX)
+            .and_then(decode_32)
+            .map(|bytes| Recipient {
+                x25519: PublicKey::from(bytes),
+                mlkem: None,
+            })
+            .ok_or_else(invalid)
+    }
+}
+
+fn invalid_identity_file() -> CryptoError {
+    CryptoError::DecryptionError("Invalid identity file".to_string())
+}
+
+/// The lines of an identity file that aren't blank or comments.
+fn identity_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+}
+
+/// A private key able to open files encrypted to its [`Recipient`].
+pub struct Identity {
+    x25519: StaticSecret,
+    /// ML-KEM-768 seed (hybrid identities only)
+    mlkem_seed: Option<Zeroizing<[u8; MLKEM_SEED_LEN]>>,
+}
+
+impl Identity {
+    /// Generates a new random identity.
+    pub fn generate() -> Self {
+        Identity {
+            x25519: StaticSecret::random_from_rng(OsRng),
+            mlkem_seed: None,
+        }
+    }
+
+    /// Generates a new random hybrid identity with an additional ML-KEM-768 key.
+    pub fn generate_hybrid() -> Self {
+        let mut seed = Zeroizing::new([0u8; MLKEM_SEED_LEN]);
+        OsRng.fill_bytes(seed.as_mut());
+        Identity {
+            x25519: StaticSecret::random_from_rng(OsRng),
+            mlkem_seed: Some(seed),
+        }
+    }
+
+    /// The recipient (public key) belonging to this identity.
+    pub fn recipient(&self) -> Recipient {
+        Recipient {
+            x25519: PublicKey::from(&self.x25519),
+            mlkem: self
+                .mlkem_key()
+                .map(|key| key.encapsulation_key().as_bytes().to_vec()),
+        }
+    }
+
+    pub(crate) fn secret(&self) -> &StaticSecret {
+        &self.x25519
+    }
+
+    /// The ML-KEM-768 decapsulation key of a hybrid identity, expanded from its seed.
+    pub(crate) fn mlkem_key(&self) -> Option<MlKemSecretKey> {
+        let seed = self.mlkem_seed.as_ref()?;
+        let d = B32::try_from(&seed[..32]).ok()?;
+        let z = B32::try_from(&seed[32..]).ok()?;
+        Some(MlKem768::generate_deterministic(&d, &z).0)
+    }
+
+    /// Serializes the identity as the contents of an identity file.
+    pub fn to_file(&self) -> String {
+        let mut secret = self.x25519.to_bytes().to_vec();
+        let prefix = match &self.mlkem_seed {
+            Some(seed) => {
+                secret.extend_from_slice(seed.as_ref());
+                PQ_IDENTITY_PREFIX
+            }
+            None => IDENTITY_PREFIX,
+        };
+        let text = format!(
+            "# EncryptX identity\n# recipient: {}\n{prefix}{}\n",
+            self.recipient(),
+            base64::engine::general_purpose::STANDARD.encode(&secret)
+        );
+        secret.zeroize();
+        text
+    }
+
+    /// Parses the contents of an identity file (comments and blank lines are skipped).
+    pub fn parse_file(text: &str) -> Result<Self, CryptoError> {
+        let line = identity_lines(text)
+            .next()
+            .ok_or_else(invalid_identity_file)?;
+        Self::parse_line(line)
+    }
+
+    /// Parses every identity in an identities file, in order. Fails if any line is not an
+    /// identity, or if there are none.
+    pub fn parse_identities(text: &str) -> Result<Vec<Self>, CryptoError> {
+        let identities = identity_lines(text)
+            .map(Self::parse_line)
+            .collect::<Result<Vec<_>, _>>()?;
+        if identities.is_empty() {
+            return Err(invalid_identity_file());
+        }
+        Ok(identities)
+    }
+
+    fn parse_line(line: &str) -> Result<Self, CryptoError> {
+        let invalid = invalid_identity_file;
+        if let Some(encoded) = line.strip_prefix(PQ_IDENTITY_PREFIX) {
+            let bytes = Zeroizing::new(
+                base64::engine::general_purpose::STANDARD
+                    .decode(encoded)
+                    .map_err(|_| invalid())?,
+            );
+            if bytes.len() != 32 + MLKEM_SEED_LEN {
+                return Err(invalid());
+            }
+            let mut x25519 = [0u8; 32];
+            x25519.copy_from_slice(&bytes[..32]);
+            let mut seed = Zeroizing::new([0u8; MLKEM_SEED_LEN]);
+            seed.copy_from_slice(&bytes[32..]);
+            let identity = Identity {
+                x25519: StaticSecret::from(x25519),
+                mlkem_seed: Some(seed),
+            };
+            x25519.zeroize();
+            return Ok(identity);
+        }
+        line.strip_prefix(IDENTITY_PREFIX)
+            .and_then(decode_32)
+            .map(|bytes| Identity {
+                x25519: StaticSecret::from(bytes),
+                mlkem_seed: None,
+            })
+            .ok_or_else(invalid)
+    }
+}
@@ -0,0 +1,835 @@
+//!
+//! LUKS-style keyslots: several credentials that each unlock the same data key.
+//!
+//! A keyslot file is a key-mode `.xd` file whose body is encrypted with a random data key.
+//! The header carries no key; instead every slot holds the data key wrapped (AES-256-GCM)
+//! with a key-encryption key derived from one credential: an Argon2id-stretched password,
+//! a raw 32-byte key, or an X25519 agreement with a recipient's public key (see
+//! [`identity`](super::identity)). A slot only unlocks the file if its GCM tag verifies *and* the
+//! unwrapped key matches the header's `key_check`, so a forged slot can't substitute
+//! another data key.
+//!
+//! Hybrid recipients get a `kem` slot instead, which names its key encapsulation suite so
+//! new suites can be added later; readers skip slots whose suite they don't know. The only
+//! suite so far, `x25519-mlkem768`, feeds both the ML-KEM-768 and the X25519 shared secret
+//! into the key derivation, so the slot stays confidential if either one is broken.
+//!
+//! `fido2` slots are bound to a hardware security key. The slot stores the token's credential
+//! ID and a random salt; the key-encryption key is derived from the token's `hmac-secret`
+//! answer to that salt, which it only gives when touched. Talking to the token is left to the
+//! caller (see [`unlock_with_token`]).
+//!
+//! `pkcs11` slots keep their key-encryption key on an HSM or smartcard: the slot stores the
+//! random key-encryption key as wrapped by the device (with a key that never leaves it) and
+//! the ID of that device key, so only the device can unwrap it (see [`unlock_with_hsm`]).
+//!
+//! `tpm2` slots seal their key-encryption key to one machine's TPM 2.0, optionally under a
+//! policy on its PCR values. The slot stores the sealed object's public and private parts;
+//! the private part is encrypted by the TPM's storage key, so the object only loads on that
+//! TPM (see [`unlock_with_tpm`]).
+//!
+//! `password+keyfile` slots need two factors: the key-encryption key is HKDF-SHA256 over
+//! the Argon2id hash of the password and the SHA-256 digest of a keyfile, so neither the
+//! password nor the keyfile alone unlocks them (see [`unlock_with_password_and_keyfile`]).
+//!
+//! `quorum` slots implement an N-of-M policy: the data key is split with Shamir's scheme
+//! (see [`shamir`](super::shamir)) and each slot wraps one share under one raw 32-byte key.
+//! Every such slot records the `threshold`, so any that many keys together unlock the file
+//! and fewer reveal nothing (see [`create_quorum`] and [`unlock_with_quorum`]).
+//!
+//! `kms` slots keep their key-encryption key in a cloud key management service: the slot
+//! stores the URI of the service's key (e.g. `gcpkms://projects/...`) and the key-encryption
+//! key as wrapped by it, so only someone the service lets use that key can unwrap it (see
+//! [`unlock_with_kms`]).
+//!
+use super::identity::{Identity, MlKemSecretKey, Recipient};
+use super::{Argon2Params, CryptoError, SecureKey, derive_key_with_params_async, shamir};
+use aes_gcm::{
+    Aes256Gcm, KeyInit, Nonce,
+    aead::{Aead, AeadCore, OsRng, Payload, rand_core::RngCore},
+};
+use base64::engine::Engine;
+use hkdf::Hkdf;
+use ml_kem::kem::{Decapsulate, Encapsulate};
+use ml_kem::{Ciphertext, MlKem768};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey, SharedSecret, StaticSecret};
+use zeroize::{Zeroize, Zeroizing};
+
+/// Domain separator for slot wrapping and the key check.
+const DOMAIN: &[u8] = b"encryptx-keyslot-v1";
+
+/// Kind of credential a slot is bound to.
+pub const KIND_PASSWORD: &str = "password";
+pub const KIND_KEY: &str = "key";
+pub const KIND_X25519: &str = "x25519";
+pub const KIND_KEM: &str = "kem";
+pub const KIND_FIDO2: &str = "fido2";
+pub const KIND_PKCS11: &str = "pkcs11";
+pub const KIND_TPM2: &str = "tpm2";
+pub const KIND_KMS: &str = "kms";
+pub const KIND_PASSWORD_KEYFILE: &str = "password+keyfile";
+pub const KIND_QUORUM: &str = "quorum";
+
+/// Key encapsulation suite of hybrid X25519 + ML-KEM-768 slots.
+pub const SUITE_X25519_MLKEM768: &str = "x25519-mlkem768";
+
+/// One credential able to unlock the data key.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Keyslot {
+    /// "password", "key", "x25519", "kem", "fido2", "pkcs11", "tpm2", "kms",
+    /// "password+keyfile" or "quorum"
+    pub kind: String,
+    /// Free-form label, e.g. "recovery"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Argon2 salt (password and password+keyfile slots) or hmac-secret salt (fido2 slots)
+    /// in base64
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub salt: Option<String>,
+    /// Argon2 memory cost in KB (password and password+keyfile slots only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_cost: Option<u32>,
+    /// Argon2 time cost (password and password+keyfile slots only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_cost: Option<u32>,
+    /// Argon2 parallelism (password and password+keyfile slots only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parallelism: Option<u32>,
+    /// Ephemeral X25519 public key in base64 (x25519 and kem slots)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ephemeral: Option<String>,
+    /// Key encapsulation suite, e.g. "x25519-mlkem768" (kem slots only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suite: Option<String>,
+    /// ML-KEM ciphertext in base64 (kem slots only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kem_ciphertext: Option<String>,
+    /// FIDO2 credential ID in base64 (fido2 slots only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential_id: Option<String>,
+    /// Hex ID of the device key that wrapped `sealed_key` (pkcs11 slots only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+    /// Key-encryption key wrapped by the device (pkcs11 slots) or service (kms slots), or the
+    /// private part of the sealed object (tpm2 slots), base64
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealed_key: Option<String>,
+    /// Public part of the sealed object, base64 (tpm2 slots only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tpm_public: Option<String>,
+    /// PCR selection the object is sealed to, e.g. "sha256:0,7" (tpm2 slots only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pcrs: Option<String>,
+    /// URI of the key management service key that wrapped `sealed_key` (kms slots only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kms_uri: Option<String>,
+    /// Number of quorum slots that must be unlocked together (quorum slots only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<u8>,
+    /// Nonce followed by the wrapped data key and tag, base64
+    pub wrapped_key: String,
+}
+
+/// A secret presented to unlock or create a slot.
+pub enum Credential<'a> {
+    Password(Zeroizing<String>),
+    Key(&'a [u8]),
+    Recipient(&'a Recipient),
+    Token(TokenSecret<'a>),
+    Hsm(HsmSecret<'a>),
+    Tpm(TpmSecret<'a>),
+    Kms(KmsSecret<'a>),
+    TwoFactor(TwoFactorSecret<'a>),
+}
+
+/// A hardware token's `hmac-secret` answer for a new fido2 slot.
+pub struct TokenSecret<'a> {
+    /// Credential the token answered with
+    pub credential_id: &'a [u8],
+    /// Salt the token was asked to answer
+    pub salt: &'a [u8],
+    /// The token's `hmac-secret` output
+    pub secret: &'a [u8],
+}
+
+impl Credential<'_> {
+    fn kind(&self) -> &'static str {
+        match self {
+            Credential::Password(_) => KIND_PASSWORD,
+            Credential::Key(_) => KIND_KEY,
+            Credential::Recipient(recipient) if recipient.is_hybrid() => KIND_KEM,
+            Credential::Recipient(_) => KIND_X25519,
+            Credential::Token(_) => KIND_FIDO2,
+            Credential::Hsm(_) => KIND_PKCS11,
+            Credential::Tpm(_) => KIND_TPM2,
+            Credential::Kms(_) => KIND_KMS,
+            Credential::TwoFactor(_) => KIND_PASSWORD_KEYFILE,
+        }
+    }
+}
+
+/// A key-encryption key for a new pkcs11 slot, and the same key as wrapped by the device.
+pub struct HsmSecret<'a> {
+    /// Hex ID of the device key that wrapped it
+    pub key_id: &'a str,
+    /// The key-encryption key as wrapped by the device
+    pub sealed: &'a [u8],
+    /// The key-encryption key itself
+    pub secret: &'a [u8],
+}
+
+/// An object sealed by a TPM, as stored in a tpm2 slot.
+pub struct TpmObject<'a> {
+    /// Public part of the sealed object
+    pub public: &'a [u8],
+    /// Private part of the sealed object, encrypted by the TPM
+    pub private: &'a [u8],
+    /// PCR selection of the object's policy, if it has one
+    pub pcrs: Option<&'a str>,
+}
+
+/// A key-encryption key for a new tpm2 slot, and the object it was sealed into.
+pub struct TpmSecret<'a> {
+    pub object: TpmObject<'a>,
+    /// The key-encryption key itself
+    pub secret: &'a [u8],
+}
+
+/// A key-encryption key for a new kms slot, and the same key as wrapped by the service.
+pub struct KmsSecret<'a> {
+    /// URI of the service key that wrapped it
+    pub uri: &'a str,
+    /// The key-encryption key as wrapped by the service
+    pub sealed: &'a [u8],
+    /// The key-encryption key itself
+    pub secret: &'a [u8],
+}
+
+/// A password and the contents of a keyfile, both needed for a password+keyfile slot.
+pub struct TwoFactorSecret<'a> {
+    pub password: Zeroizing<String>,
+    /// Contents of the keyfile (any file, e.g. random bytes kept on a USB stick)
+    pub keyfile: &'a [u8],
+}
+
+/// Generates a fresh random data key.
+pub fn generate_data_key() -> SecureKey {
+    let mut key = [0u8; 32];
+    OsRng.fill_bytes(&mut key);
+    let data_key = SecureKey::new(key);
+    key.zeroize();
+    data_key
+}
+
+/// Short commitment to the data key stored in the header, base64.
+pub fn key_check(data_key: &SecureKey) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(DOMAIN);
+    hasher.update(b"check");
+    hasher.update(data_key.as_slice());
+    base64::engine::general_purpose::STANDARD.encode(&hasher.finalize()[..16])
+}
+
+fn slot_aad(kind: &str) -> Vec<u8> {
+    [DOMAIN, kind.as_bytes()].concat()
+}
+
+fn kek_cipher(key: &[u8]) -> Result<Aes256Gcm, CryptoError> {
+    Aes256Gcm::new_from_slice(key)
+        .map_err(|_| CryptoError::KeyDerivationError("Key must be exactly 32 bytes".to_string()))
+}
+
+/// Derives the key-encryption key for a password slot.
+async fn password_kek(
+    mut password: Zeroizing<String>,
+    salt: Vec<u8>,
+    params: Argon2Params,
+) -> Result<SecureKey, CryptoError> {
+    // Moved out rather than copied; the derivation zeroizes it in turn
+    let password = std::mem::take(&mut *password);
+    Ok(SecureKey::new(
+        derive_key_with_params_async(password, salt, params).await?,
+    ))
+}
+
+/// Derives the key-encryption key for a password+keyfile slot.
+///
+/// The keyfile's digest and the Argon2 hash are both HKDF input, so each factor is needed;
+/// the Argon2 salt doubles as the HKDF salt.
+async fn two_factor_kek(
+    secret: TwoFactorSecret<'_>,
+    salt: Vec<u8>,
+    params: Argon2Params,
+) -> Result<SecureKey, CryptoError> {
+    let password_key = password_kek(secret.password, salt.clone(), params).await?;
+    let mut ikm = Zeroizing::new(password_key.as_slice().to_vec());
+    ikm.extend_from_slice(&Sha256::digest(secret.keyfile));
+    let mut kek = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&salt), &ikm)
+        .expand(
+            &[DOMAIN, b" ", KIND_PASSWORD_KEYFILE.as_bytes()].concat(),
+            &mut kek,
+        )
+        .map_err(|_| CryptoError::KeyDerivationError("HKDF expansion failed".to_string()))?;
+    let kek_key = SecureKey::new(kek);
+    kek.zeroize();
+    Ok(kek_key)
+}
+
+/// Derives the key-encryption key for an x25519 or kem slot from the agreed shared secrets.
+///
+/// Both X25519 public keys go into the HKDF salt so the key is bound to this exact exchange.
+/// `kem_shared` is the ML-KEM shared secret of hybrid slots; it is prepended to the X25519
+/// secret and the suite name goes into the HKDF info.
+fn recipient_kek(
+    shared: SharedSecret,
+    kem_shared: Option<&[u8]>,
+    ephemeral: &PublicKey,
+    recipient: &PublicKey,
+) -> Result<SecureKey, CryptoError> {
+    if !shared.was_contributory() {
+        return Err(CryptoError::KeyDerivationError(
+            "Recipient public key is invalid".to_string(),
+        ));
+    }
+    let salt = [ephemeral.as_bytes().as_slice(), recipient.as_bytes()].concat();
+    let (mut ikm, info) = match kem_shared {
+        Some(kem_shared) => (
+            [kem_shared, shared.as_bytes()].concat(),
+            [DOMAIN, b" ", SUITE_X25519_MLKEM768.as_bytes()].concat(),
+        ),
+        None => (shared.as_bytes().to_vec(), [DOMAIN, b" x25519"].concat()),
+    };
+    let mut kek = [0u8; 32];
+    let expanded = Hkdf::<Sha256>::new(Some(&salt), &ikm).expand(&info, &mut kek);
+    ikm.zeroize();
+    expanded.map_err(|_| CryptoError::KeyDerivationError("HKDF expansion failed".to_string()))?;
+    let kek_key = SecureKey::new(kek);
+    kek.zeroize();
+    Ok(kek_key)
+}
+
+/// Derives the key-encryption key for a fido2, pkcs11, tpm2 or kms slot from the secret the
+/// device or service gave.
+///
+/// `binding` (the credential ID, key ID, sealed object's public part or service key URI) is
+/// the HKDF salt, so one credential's secret can't open another's slot.
+fn device_kek(secret: &[u8], binding: &[u8], kind: &str) -> Result<SecureKey, CryptoError> {
+    let mut kek = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(binding), secret)
+        .expand(&[DOMAIN, b" ", kind.as_bytes()].concat(), &mut kek)
+        .map_err(|_| CryptoError::KeyDerivationError("HKDF expansion failed".to_string()))?;
+    let kek_key = SecureKey::new(kek);
+    kek.zeroize();
+    Ok(kek_key)
+}
+
+/// Creates a slot that wraps `data_key` under `credential`.
+///
+/// `params` sets the Argon2 cost for password and password+keyfile slots and is ignored for
+/// the others.
+pub async fn create(
+    data_key: &SecureKey,
+    credential: Credential<'_>,
+    params: Argon2Params,
+    label: Option<String>,
+) -> Result<Keyslot, CryptoError> {
+    let kind = credential.kind();
+    let mut slot = Keyslot {
+        kind: kind.to_string(),
+        label,
+        salt: None,
+        memory_cost: None,
+        time_cost: None,
+        parallelism: None,
+        ephemeral: None,
+        suite: None,
+        kem_ciphertext: None,
+        credential_id: None,
+        key_id: None,
+        sealed_key: None,
+        tpm_public: None,
+        pcrs: None,
+        kms_uri: None,
+        threshold: None,
+        wrapped_key: String::new(),
+    };
+    let kek = match credential {
+        Credential::Password(password) => {
+            let mut salt = [0u8; 32];
+            OsRng.fill_bytes(&mut salt);
+            slot.salt = Some(base64::engine::general_purpose::STANDARD.encode(salt));
+            slot.memory_cost = Some(params.memory_cost);
+            slot.time_cost = Some(params.time_cost);
+            slot.parallelism = Some(params.parallelism);
+            password_kek(password, salt.to_vec(), params).await?
+        }
+        Credential::Key(key) => raw_kek(key)?,
+        Credential::Recipient(recipient) => {
+            let secret = StaticSecret::random_from_rng(OsRng);
+            let ephemeral = PublicKey::from(&secret);
+            slot.ephemeral =
+                Some(base64::engine::general_purpose::STANDARD.encode(ephemeral.as_bytes()));
+            let kem_shared = match recipient.mlkem_key() {
+                Some(key) => {
+                    let (ciphertext, shared) = key.encapsulate(&mut OsRng).map_err(|_| {
+                        CryptoError::EncryptionError("ML-KEM encapsulation failed".to_string())
+                    })?;
+                    slot.suite = Some(SUITE_X25519_MLKEM768.to_string());
+                    slot.kem_ciphertext =
+                        Some(base64::engine::general_purpose::STANDARD.encode(ciphertext));
+                    Some(Zeroizing::new(shared.to_vec()))
+                }
+                None => None,
+            };
+            recipient_kek(
+                secret.diffie_hellman(recipient.public_key()),
+                kem_shared.as_deref().map(Vec::as_slice),
+                &ephemeral,
+                recipient.public_key(),
+            )?
+        }
+        Credential::Token(token) => {
+            slot.salt = Some(base64::engine::general_purpose::STANDARD.encode(token.salt));
+            slot.credential_id =
+                Some(base64::engine::general_purpose::STANDARD.encode(token.credential_id));
+            device_kek(token.secret, token.credential_id, KIND_FIDO2)?
+        }
+        Credential::Hsm(hsm) => {
+            slot.key_id = Some(hsm.key_id.to_string());
+            slot.sealed_key = Some(base64::engine::general_purpose::STANDARD.encode(hsm.sealed));
+            device_kek(hsm.secret, hsm.key_id.as_bytes(), KIND_PKCS11)?
+        }
+        Credential::Tpm(tpm) => {
+            let object = tpm.object;
+            slot.sealed_key =
+                Some(base64::engine::general_purpose::STANDARD.encode(object.private));
+            slot.tpm_public = Some(base64::engine::general_purpose::STANDARD.encode(object.public));
+            slot.pcrs = object.pcrs.map(str::to_string);
+            device_kek(tpm.secret, object.public, KIND_TPM2)?
+        }
+        Credential::Kms(kms) => {
+            slot.kms_uri = Some(kms.uri.to_string());
+            slot.sealed_key = Some(base64::engine::general_purpose::STANDARD.encode(kms.sealed));
+            device_kek(kms.secret, kms.uri.as_bytes(), KIND_KMS)?
+        }
+        Credential::TwoFactor(secret) => {
+            let mut salt = [0u8; 32];
+            OsRng.fill_bytes(&mut salt);
+            slot.salt = Some(base64::engine::general_purpose::STANDARD.encode(salt));
+            slot.memory_cost = Some(params.memory_cost);
+            slot.time_cost = Some(params.time_cost);
+            slot.parallelism = Some(params.parallelism);
+            two_factor_kek(secret, salt.to_vec(), params).await?
+        }
+    };
+
+    slot.wrapped_key = wrap(&kek, kind, data_key.as_slice())?;
+    Ok(slot)
+}
+
+/// Uses a raw 32-byte key as a key-encryption key.
+fn raw_kek(key: &[u8]) -> Result<SecureKey, CryptoError> {
+    let key: [u8; 32] = key
+        .try_into()
+        .map_err(|_| CryptoError::KeyDerivationError("Key must be exactly 32 bytes".to_string()))?;
+    Ok(SecureKey::new(key))
+}
+
+/// Encrypts `secret` for a slot of `kind`, returning nonce, ciphertext and tag as base64.
+fn wrap(kek: &SecureKey, kind: &str, secret: &[u8]) -> Result<String, CryptoError> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let wrapped = kek_cipher(kek.as_slice())?
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: secret,
+                aad: &slot_aad(kind),
+            },
+        )
+        .map_err(|_| CryptoError::EncryptionError("Failed to wrap data key".to_string()))?;
+    Ok(base64::engine::general_purpose::STANDARD.encode([&nonce[..], &wrapped].concat()))
+}
+
+/// Decrypts whatever one slot wraps with an already derived key-encryption key.
+fn unwrap_secret(slot: &Keyslot, kek: &SecureKey) -> Option<Zeroizing<Vec<u8>>> {
+    let wrapped = base64::engine::general_purpose::STANDARD
+        .decode(&slot.wrapped_key)
+        .ok()?;
+    if wrapped.len() < 12 + 16 {
+        return None;
+    }
+    kek_cipher(kek.as_slice())
+        .ok()?
+        .decrypt(
+            Nonce::from_slice(&wrapped[..12]),
+            Payload {
+                msg: &wrapped[12..],
+                aad: &slot_aad(&slot.kind),
+            },
+        )
+        .ok()
+        .map(Zeroizing::new)
+}
+
+/// Tries to unwrap the data key from one slot with an already derived key-encryption key.
+fn unwrap(slot: &Keyslot, kek: &SecureKey, check: &str) -> Option<SecureKey> {
+    let plain = unwrap_secret(slot, kek)?;
+    let mut key: [u8; 32] = plain.as_slice().try_into().ok()?;
+    let data_key = SecureKey::new(key);
+    key.zeroize();
+    (key_check(&data_key) == check).then_some(data_key)
+}
+
+/// Creates one quorum slot per key, any `threshold` of which unlock `data_key` together.
+///
+/// Each slot wraps a Shamir share of the data key under one raw 32-byte key; the keys must
+/// be distinct, or one key holder would get several shares.
+pub fn create_quorum(
+    data_key: &SecureKey,
+    keys: &[&[u8]],
+    threshold: u8,
+) -> Result<Vec<Keyslot>, CryptoError> {
+    let count = u8::try_from(keys.len()).map_err(|_| {
+        CryptoError::EncryptionError("At most 255 quorum keys are supported".to_string())
+    })?;
+    if (1..keys.len()).any(|i| keys[..i].contains(&keys[i])) {
+        return Err(CryptoError::EncryptionError(
+            "Quorum keys must be distinct".to_string(),
+        ));
+    }
+    let shares = shamir::split(data_key.as_slice(), threshold, count)?;
+    keys.iter()
+        .zip(&shares)
+        .map(|(key, share)| {
+            let share = Zeroizing::new(share.to_string());
+            Ok(Keyslot {
+                kind: KIND_QUORUM.to_string(),
+                threshold: Some(threshold),
+                wrapped_key: wrap(&raw_kek(key)?, KIND_QUORUM, share.as_bytes())?,
+                ..Keyslot::default()
+            })
+        })
+        .collect()
+}
+
+/// Number of keys the file's quorum slots need together, if it has any.
+pub fn quorum_threshold(slots: &[Keyslot]) -> Option<u8> {
+    slots
+        .iter()
+        .find(|slot| slot.kind == KIND_QUORUM)
+        .and_then(|slot| slot.threshold)
+}
+
+/// Unlocks the data key with a quorum of raw 32-byte keys.
+///
+/// Each key opens the quorum slots it wrapped, and the shares they hold are combined once
+/// there are enough. Fails with [`CryptoError::AuthenticationError`] if fewer keys than the
+/// threshold match, or the shares don't combine to the data key.
+pub fn unlock_with_quorum(
+    slots: &[Keyslot],
+    check: &str,
+    keys: &[&[u8]],
+) -> Result<SecureKey, CryptoError> {
+    let threshold = quorum_threshold(slots).ok_or_else(|| {
+        CryptoError::WrongDecryptionMethod("This file has no quorum keyslots.".to_string())
+    })?;
+    let keks = keys
+        .iter()
+        .map(|key| raw_kek(key))
+        .collect::<Result<Vec<_>, _>>()?;
+    let shares: Vec<shamir::Share> = slots
+        .iter()
+        .filter(|slot| slot.kind == KIND_QUORUM)
+        .filter_map(|slot| keks.iter().find_map(|kek| unwrap_secret(slot, kek)))
+        .filter_map(|plain| std::str::from_utf8(&plain).ok()?.parse().ok())
+        .collect();
+    if shares.len() < threshold as usize {
+        return Err(CryptoError::AuthenticationError);
+    }
+    let data_key = shamir::combine(&shares)?;
+    if key_check(&data_key) != check {
+        return Err(CryptoError::AuthenticationError);
+    }
+    Ok(data_key)
+}
+
+/// Unlocks the data key with a raw 32-byte key, returning it and the matching slot index.
+///
+/// Fails with [`CryptoError::AuthenticationError`] if no key slot accepts `key`, or with
+/// [`CryptoError::WrongDecryptionMethod`] if the file only has quorum slots for keys.
+pub fn unlock_with_key(
+    slots: &[Keyslot],
+    check: &str,
+    key: &[u8],
+) -> Result<(SecureKey, usize), CryptoError> {
+    let key: [u8; 32] = key
+        .try_into()
+        .map_err(|_| CryptoError::DecryptionError("Key must be exactly 32 bytes".to_string()))?;
+    let kek = SecureKey::new(key);
+    if let Some(threshold) = quorum_threshold(slots)
+        && !slots.iter().any(|slot| slot.kind == KIND_KEY)
+    {
+        return Err(CryptoError::WrongDecryptionMethod(format!(
+            "This file needs {threshold} quorum keys together for decryption."
+        )));
+    }
+    slots
+        .iter()
+        .enumerate()
+        .filter(|(_, slot)| slot.kind == KIND_KEY)
+        .find_map(|(index, slot)| unwrap(slot, &kek, check).map(|k| (k, index)))
+        .ok_or(CryptoError::AuthenticationError)
+}
+
+/// Argon2 salt and parameters of a password or password+keyfile slot.
+fn argon2_inputs(slot: &Keyslot) -> Result<(Vec<u8>, Argon2Params), CryptoError> {
+    let salt = slot
+        .salt
+        .as_deref()
+        .and_then(|s| base64::engine::general_purpose::STANDARD.decode(s).ok())
+        .ok_or_else(|| CryptoError::DecryptionError("Invalid keyslot salt".to_string()))?;
+    let params = Argon2Params::checked(slot.memory_cost, slot.time_cost, slot.parallelism)?;
+    Ok((salt, params))
+}
+
+/// Unlocks the data key with a password, returning it and the matching slot index.
+///
+/// Every password slot costs one Argon2 derivation, so slots are tried in order until one
+/// accepts. Fails with [`CryptoError::AuthenticationError`] if none does, or with
+/// [`CryptoError::WrongDecryptionMethod`] if the password only appears together with a
+/// keyfile.
+pub async fn unlock_with_password(
+    slots: &[Keyslot],
+    check: &str,
+    password: String,
+) -> Result<(SecureKey, usize), CryptoError> {
+    let password = Zeroizing::new(password);
+    for (index, slot) in slots.iter().enumerate() {
+        if slot.kind != KIND_PASSWORD {
+            continue;
+        }
+        let (salt, params) = argon2_inputs(slot)?;
+        let kek = password_kek(password.clone(), salt, params).await?;
+        if let Some(data_key) = unwrap(slot, &kek, check) {
+            return Ok((data_key, index));
+        }
+    }
+    let needs_keyfile = |kind: &str| kind == KIND_PASSWORD_KEYFILE;
+    if slots.iter().any(|slot| needs_keyfile(&slot.kind))
+        && !slots.iter().any(|slot| slot.kind == KIND_PASSWORD)
+    {
+        return Err(CryptoError::WrongDecryptionMethod(
+            "This file needs both a password and a keyfile for decryption.".to_string(),
+        ));
+    }
+    Err(CryptoError::AuthenticationError)
+}
+
+/// Unlocks the data key with a password and keyfile, returning it and the matching slot
+/// index.
+///
+/// Only password+keyfile slots are tried, each costing one Argon2 derivation. Fails with
+/// [`CryptoError::AuthenticationError`] if none accepts.
+pub async fn unlock_with_password_and_keyfile(
+    slots: &[Keyslot],
+    check: &str,
+    password: String,
+    keyfile: &[u8],
+) -> Result<(SecureKey, usize), CryptoError> {
+    let password = Zeroizing::new(password);
+    for (index, slot) in slots.iter().enumerate() {
+        if slot.kind != KIND_PASSWORD_KEYFILE {
+            continue;
+        }
+        let (salt, params) = argon2_inputs(slot)?;
+        let secret = TwoFactorSecret {
+            password: password.clone(),
+            keyfile,
+        };
+        let kek = two_factor_kek(secret, salt, params).await?;
+        if let Some(data_key) = unwrap(slot, &kek, check) {
+            return Ok((data_key, index));
+        }
+    }
+    Err(CryptoError::AuthenticationError)
+}
+
+/// Decapsulates the ML-KEM shared secret of a kem slot.
+fn decapsulate(slot: &Keyslot, key: &MlKemSecretKey) -> Option<Zeroizing<Vec<u8>>> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(slot.kem_ciphertext.as_deref()?)
+        .ok()?;
+    let ciphertext = Ciphertext::<MlKem768>::try_from(bytes.as_slice()).ok()?;
+    let shared = key.decapsulate(&ciphertext).ok()?;
+    Some(Zeroizing::new(shared.to_vec()))
+}
+
+/// Unlocks the data key with an identity, returning it and the matching slot index.
+///
+/// Slots don't name their recipient, so every x25519 slot is tried, and every kem slot too
+/// if `identity` is hybrid. Fails with [`CryptoError::AuthenticationError`] if none was made
+/// for `identity`, or with [`CryptoError::DecryptionError`] if the only candidates use a key
+/// encapsulation suite this version doesn't know.
+pub fn unlock_with_identity(
+    slots: &[Keyslot],
+    check: &str,
+    identity: &Identity,
+) -> Result<(SecureKey, usize), CryptoError> {
+    let recipient = identity.recipient();
+    let mlkem_key = identity.mlkem_key();
+    let unlocked = slots.iter().enumerate().find_map(|(index, slot)| {
+        let kem_shared = match (slot.kind.as_str(), &mlkem_key) {
+            (KIND_X25519, _) => None,
+            (KIND_KEM, Some(key)) if slot.suite.as_deref() == Some(SUITE_X25519_MLKEM768) => {
+                Some(decapsulate(slot, key)?)
+            }
+            _ => return None,
+        };
+        let bytes: [u8; 32] = base64::engine::general_purpose::STANDARD
+            .decode(slot.ephemeral.as_deref()?)
+            .ok()?
+            .try_into()
+            .ok()?;
+        let ephemeral = PublicKey::from(bytes);
+        let kek = recipient_kek(
+            identity.secret().diffie_hellman(&ephemeral),
+            kem_shared.as_deref().map(Vec::as_slice),
+            &ephemeral,
+            recipient.public_key(),
+        )
+        .ok()?;
+        unwrap(slot, &kek, check).map(|k| (k, index))
+    });
+    if let Some(unlocked) = unlocked {
+        return Ok(unlocked);
+    }
+    let unknown = slots
+        .iter()
+        .find(|slot| slot.kind == KIND_KEM && slot.suite.as_deref() != Some(SUITE_X25519_MLKEM768));
+    match unknown {
+        Some(slot) => Err(CryptoError::DecryptionError(format!(
+            "Unsupported key encapsulation suite '{}'",
+            slot.suite.as_deref().unwrap_or_default()
+        ))),
+        None => Err(CryptoError::AuthenticationError),
+    }
+}
+
+/// Unlocks the data key with a hardware token, returning it and the matching slot index.
+///
+/// `ask` is called with the credential ID and salt of each fido2 slot in turn and returns the
+/// token's `hmac-secret` output, or `None` if the connected token doesn't hold that credential
+/// (or wasn't touched). Fails with [`CryptoError::AuthenticationError`] if no slot unlocks.
+pub fn unlock_with_token(
+    slots: &[Keyslot],
+    check: &str,
+    mut ask: impl FnMut(&[u8], &[u8]) -> Option<Zeroizing<Vec<u8>>>,
+) -> Result<(SecureKey, usize), CryptoError> {
+    slots
+        .iter()
+        .enumerate()
+        .filter(|(_, slot)| slot.kind == KIND_FIDO2)
+        .find_map(|(index, slot)| {
+            let decode = |field: Option<&str>| {
+                base64::engine::general_purpose::STANDARD
+                    .decode(field?)
+                    .ok()
+            };
+            let credential_id = decode(slot.credential_id.as_deref())?;
+            let salt = decode(slot.salt.as_deref())?;
+            let secret = ask(&credential_id, &salt)?;
+            let kek = device_kek(&secret, &credential_id, KIND_FIDO2).ok()?;
+            unwrap(slot, &kek, check).map(|k| (k, index))
+        })
+        .ok_or(CryptoError::AuthenticationError)
+}
+
+/// Unlocks the data key with an HSM, returning it and the matching slot index.
+///
+/// `ask` is called with the key ID and sealed key of each pkcs11 slot in turn and returns the
+/// key-encryption key unwrapped by the device, or `None` if the device can't unwrap it.
+/// Fails with [`CryptoError::AuthenticationError`] if no slot unlocks.
+pub fn unlock_with_hsm(
+    slots: &[Keyslot],
+    check: &str,
+    mut ask: impl FnMut(&str, &[u8]) -> Option<Zeroizing<Vec<u8>>>,
+) -> Result<(SecureKey, usize), CryptoError> {
+    slots
+        .iter()
+        .enumerate()
+        .filter(|(_, slot)| slot.kind == KIND_PKCS11)
+        .find_map(|(index, slot)| {
+            let key_id = slot.key_id.as_deref()?;
+            let sealed = base64::engine::general_purpose::STANDARD
+                .decode(slot.sealed_key.as_deref()?)
+                .ok()?;
+            let secret = ask(key_id, &sealed)?;
+            let kek = device_kek(&secret, key_id.as_bytes(), KIND_PKCS11).ok()?;
+            unwrap(slot, &kek, check).map(|k| (k, index))
+        })
+        .ok_or(CryptoError::AuthenticationError)
+}
+
+/// Unlocks the data key with the TPM, returning it and the matching slot index.
+///
+/// `ask` is called with the sealed object of each tpm2 slot in turn and returns the unsealed key-encryption key, or `None` if the TPM refuses (another
+/// machine's object, or PCR values that no longer match). Fails with
+/// [`CryptoError::AuthenticationError`] if no slot unlocks.
+pub fn unlock_with_tpm(
+    slots: &[Keyslot],
+    check: &str,
+    mut ask: impl FnMut(&TpmObject<'_>) -> Option<Zeroizing<Vec<u8>>>,
+) -> Result<(SecureKey, usize), CryptoError> {
+    slots
+        .iter()
+        .enumerate()
+        .filter(|(_, slot)| slot.kind == KIND_TPM2)
+        .find_map(|(index, slot)| {
+            let decode = |field: Option<&str>| {
+                base64::engine::general_purpose::STANDARD
+                    .decode(field?)
+                    .ok()
+            };
+            let public = decode(slot.tpm_public.as_deref())?;
+            let private = decode(slot.sealed_key.as_deref())?;
+            let secret = ask(&TpmObject {
+                public: &public,
+                private: &private,
+                pcrs: slot.pcrs.as_deref(),
+            })?;
+            let kek = device_kek(&secret, &public, KIND_TPM2).ok()?;
+            unwrap(slot, &kek, check).map(|k| (k, index))
+        })
+        .ok_or(CryptoError::AuthenticationError)
+}
+
+/// Unlocks the data key with a key management service, returning it and the matching slot
+/// index.
+///
+/// `ask` is called with the key URI and sealed key of each kms slot in turn and returns the
+/// key-encryption key unwrapped by the service, or `None` if the service won't unwrap it
+/// (no access, or a disabled key). Fails with [`CryptoError::AuthenticationError`] if no slot
+/// unlocks.
+pub fn unlock_with_kms(
+    slots: &[Keyslot],
+    check: &str,
+    mut ask: impl FnMut(&str, &[u8]) -> Option<Zeroizing<Vec<u8>>>,
+) -> Result<(SecureKey, usize), CryptoError> {
+    slots
+        .iter()
+        .enumerate()
+        .filter(|(_, slot)| slot.kind == KIND_KMS)
+        .find_map(|(index, slot)| {
+            let uri = slot.kms_uri.as_deref()?;
+            let sealed = base64::engine::general_purpose::STANDARD
+                .decode(slot.sealed_key.as_deref()?)
+                .ok()?;
+            let secret = ask(uri, &sealed)?;
+            let kek = device_kek(&secret, uri.as_bytes(), KIND_KMS).ok()?;
+            unwrap(slot, &kek, check).map(|k| (k, index))
+        })
+        .ok_or(CryptoError::AuthenticationError)
+}
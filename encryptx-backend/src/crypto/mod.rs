@@ -1,16 +1,36 @@
+use crate::format::{
+    CURRENT_VERSION, ENVELOPE_VERSION, MAX_KEYSLOTS, PASSWORD_MARKER, ParsedHeader, XdHeader,
+    XdPasswordHeader, XdReader, XdWriter, key_header_aad,
+};
 use aes_gcm::{
     Aes256Gcm, Nonce,
     aead::{Aead, AeadCore, KeyInit, OsRng},
 };
-use argon2::{
-    Algorithm, Argon2, Params, Version,
-    password_hash::{PasswordHasher, SaltString},
-};
+use argon2::{Algorithm, Argon2, Params, Version};
 use base64::engine::Engine;
-use serde::{Deserialize, Serialize};
+use chunked::{ChunkReader, ChunkWriter};
+use secret::SecretBuffer;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
 use thiserror::Error;
 use tokio::task;
-use zeroize::ZeroizeOnDrop;
+use zeroize::{Zeroize, Zeroizing};
+
+pub mod chunked;
+pub mod delta;
+pub mod identity;
+pub mod keyslot;
+pub mod padding;
+pub mod paper;
+pub mod repair;
+pub mod secret;
+pub mod shamir;
+pub mod signing;
+pub mod split;
+pub mod strength;
 
 /// Error types for cryptographic operations in EncryptX.
 /// These cover all failure modes from key derivation to authentication failures.
@@ -30,62 +50,54 @@ pub enum CryptoError {
     WrongDecryptionMethod(String),
     #[error("Async task error: {0}")]
     AsyncError(String),
+    #[error("Signature verification failed: {0}")]
+    SignatureError(String),
 }
 
 /// Memory-safe key container that automatically zeros on drop.
 /// This prevents keys from lingering in memory after use, reducing attack surface.
-#[derive(ZeroizeOnDrop)]
+/// The key lives in a [`SecretBuffer`], so it is also kept out of swap where possible.
 pub struct SecureKey {
-    key: [u8; 32],
+    key: SecretBuffer,
 }
 
 impl SecureKey {
     /// Creates a new `SecureKey` instance containing the provided 32-byte key.
     ///
-    /// The key will be securely zeroized from memory when the `SecureKey` is dropped.
-    pub fn new(key: [u8; 32]) -> Self {
-        Self { key }
+    /// The key is moved into locked memory and the array passed in is zeroized; it will be
+    /// securely zeroized from memory when the `SecureKey` is dropped.
+    pub fn new(mut key: [u8; 32]) -> Self {
+        let secure_key = Self {
+            key: SecretBuffer::from_slice(&key),
+        };
+        key.zeroize();
+        secure_key
     }
 
     /// Returns a reference to the underlying 32-byte key as a byte slice.
     pub fn as_slice(&self) -> &[u8] {
-        &self.key
-    }
-}
-
-/// File header for standard key-based encryption.
-/// Contains metadata and optionally embeds the key for convenience.
-#[derive(Serialize, Deserialize)]
-pub struct XdHeader {
-    pub filename: String,
-    pub key: Option<String>,
-    /// Format version for backward compatibility
-    pub version: u8,
-    /// Unix timestamp when file was encrypted
-    pub timestamp: u64,
-}
-
-/// File header for password-based encryption with Argon2 key derivation.
-/// Stores all parameters needed to reproduce the key derivation process.
-#[derive(Serialize, Deserialize)]
-pub struct XdPasswordHeader {
-    pub filename: String,
-    /// Argon2 salt encoded in base64
-    pub salt: String,
-    /// Key derivation function used ("argon2id" or "pbkdf2")
-    pub kdf: String,
-    /// Argon2 memory cost in KB - affects both security and performance
-    pub memory_cost: Option<u32>,
-    /// Argon2 time cost (number of iterations)
-    pub time_cost: Option<u32>,
-    /// Argon2 parallelism factor
-    pub parallelism: Option<u32>,
-    /// PBKDF2 iterations for backward compatibility with older files
-    pub iterations: Option<u32>,
-    /// Format version
-    pub version: u8,
-    /// Unix timestamp when file was encrypted
-    pub timestamp: u64,
+        self.key.as_slice()
+    }
+
+    /// Whether the key's memory is locked against swapping.
+    pub fn is_locked(&self) -> bool {
+        self.key.is_locked()
+    }
+}
+
+// The key's buffer wipes itself on drop
+impl zeroize::ZeroizeOnDrop for SecureKey {}
+
+/// Unix time to record for a file being encrypted as `filename`. Anonymous files (empty
+/// filename) record no timestamp either.
+fn encryption_time(filename: &str) -> u64 {
+    if filename.is_empty() {
+        return 0;
+    }
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
 }
 
 /// Argon2 parameters chosen for good security/performance balance.
@@ -95,6 +107,425 @@ const ARGON2_TIME_COST: u32 = 3; // 3 iterations
 const ARGON2_PARALLELISM: u32 = 1; // Single thread to avoid complexity
 const SALT_LENGTH: usize = 32;
 
+/// Upper bounds accepted from file headers, so a hostile file can't make us allocate
+/// gigabytes or spin for minutes before the password is even checked.
+pub const ARGON2_MAX_MEMORY_COST: u32 = 1024 * 1024; // 1 GB
+pub const ARGON2_MAX_TIME_COST: u32 = 16;
+pub const ARGON2_MAX_PARALLELISM: u32 = 16;
+
+/// Argon2id cost parameters used for a single key derivation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Argon2Params {
+    /// Memory cost in KB
+    pub memory_cost: u32,
+    /// Number of iterations
+    pub time_cost: u32,
+    /// Number of lanes
+    pub parallelism: u32,
+}
+
+impl Default for Argon2Params {
+    fn default() -> Self {
+        KdfProfile::Moderate.params()
+    }
+}
+
+impl Argon2Params {
+    /// Reads the parameters recorded in a password header, falling back to the defaults
+    /// for files that predate per-file parameters.
+    ///
+    /// Rejects values above sane limits so untrusted headers can't exhaust resources.
+    pub fn from_header(header: &XdPasswordHeader) -> Result<Self, CryptoError> {
+        Self::checked(header.memory_cost, header.time_cost, header.parallelism)
+    }
+
+    /// Builds parameters from optional header values, applying defaults and the same
+    /// limits as [`Argon2Params::from_header`].
+    pub fn checked(
+        memory_cost: Option<u32>,
+        time_cost: Option<u32>,
+        parallelism: Option<u32>,
+    ) -> Result<Self, CryptoError> {
+        let defaults = Self::default();
+        let params = Self {
+            memory_cost: memory_cost.unwrap_or(defaults.memory_cost),
+            time_cost: time_cost.unwrap_or(defaults.time_cost),
+            parallelism: parallelism.unwrap_or(defaults.parallelism),
+        };
+        if params.memory_cost > ARGON2_MAX_MEMORY_COST
+            || params.time_cost > ARGON2_MAX_TIME_COST
+            || params.parallelism > ARGON2_MAX_PARALLELISM
+        {
+            return Err(CryptoError::KeyDerivationError(
+                "Argon2 parameters in header exceed allowed limits".to_string(),
+            ));
+        }
+        Ok(params)
+    }
+}
+
+/// Named Argon2id parameter sets, so callers can pick a strength without knowing the numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KdfProfile {
+    /// 19 MB, 2 iterations - fast enough for interactive use on small machines
+    Interactive,
+    /// 64 MB, 3 iterations - the historical EncryptX default
+    #[default]
+    Moderate,
+    /// 256 MB, 4 iterations - for long-term archives where a slow unlock is acceptable
+    Paranoid,
+}
+
+impl KdfProfile {
+    /// Every profile, weakest first.
+    pub const ALL: [KdfProfile; 3] = [
+        KdfProfile::Interactive,
+        KdfProfile::Moderate,
+        KdfProfile::Paranoid,
+    ];
+
+    /// Returns the Argon2 parameters for this profile.
+    pub fn params(&self) -> Argon2Params {
+        match self {
+            KdfProfile::Interactive => Argon2Params {
+                memory_cost: 19 * 1024,
+                time_cost: 2,
+                parallelism: 1,
+            },
+            KdfProfile::Moderate => Argon2Params {
+                memory_cost: ARGON2_MEMORY_COST,
+                time_cost: ARGON2_TIME_COST,
+                parallelism: ARGON2_PARALLELISM,
+            },
+            KdfProfile::Paranoid => Argon2Params {
+                memory_cost: 256 * 1024,
+                time_cost: 4,
+                parallelism: 1,
+            },
+        }
+    }
+
+    /// Profile name as used in config files, CLI flags and headers.
+    pub fn name(&self) -> &'static str {
+        match self {
+            KdfProfile::Interactive => "interactive",
+            KdfProfile::Moderate => "moderate",
+            KdfProfile::Paranoid => "paranoid",
+        }
+    }
+}
+
+impl std::str::FromStr for KdfProfile {
+    type Err = CryptoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "interactive" => Ok(KdfProfile::Interactive),
+            "moderate" => Ok(KdfProfile::Moderate),
+            "paranoid" => Ok(KdfProfile::Paranoid),
+            other => Err(CryptoError::KeyDerivationError(format!(
+                "Unknown KDF profile '{other}' (expected interactive, moderate or paranoid)"
+            ))),
+        }
+    }
+}
+
+/// Upper bounds on scrypt parameters accepted from file headers; memory is also capped at
+/// [`ARGON2_MAX_MEMORY_COST`].
+pub const SCRYPT_MAX_LOG_N: u8 = 22;
+pub const SCRYPT_MAX_BLOCK_SIZE: u32 = 32;
+pub const SCRYPT_MAX_PARALLELISM: u32 = 16;
+
+/// scrypt cost parameters used for a single key derivation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScryptParams {
+    /// CPU/memory cost as a power of two (`N = 2^log_n`)
+    pub log_n: u8,
+    /// Block size (`r`)
+    pub block_size: u32,
+    /// Parallelization (`p`)
+    pub parallelism: u32,
+}
+
+impl ScryptParams {
+    /// Builds parameters from the values in a header, rejecting values above the limits.
+    pub fn checked(
+        log_n: Option<u8>,
+        block_size: Option<u32>,
+        parallelism: Option<u32>,
+    ) -> Result<Self, CryptoError> {
+        let (Some(log_n), Some(block_size), Some(parallelism)) = (log_n, block_size, parallelism)
+        else {
+            return Err(CryptoError::DecryptionError(
+                "scrypt header is missing its parameters".to_string(),
+            ));
+        };
+        let params = Self {
+            log_n,
+            block_size,
+            parallelism,
+        };
+        if log_n > SCRYPT_MAX_LOG_N
+            || block_size > SCRYPT_MAX_BLOCK_SIZE
+            || parallelism > SCRYPT_MAX_PARALLELISM
+            || params.memory_cost() > u64::from(ARGON2_MAX_MEMORY_COST)
+        {
+            return Err(CryptoError::KeyDerivationError(
+                "scrypt parameters in header exceed allowed limits".to_string(),
+            ));
+        }
+        Ok(params)
+    }
+
+    /// Memory the derivation takes, in KB (`128 * r * N` bytes).
+    pub fn memory_cost(&self) -> u64 {
+        (128 * u64::from(self.block_size)) << self.log_n >> 10
+    }
+}
+
+impl KdfProfile {
+    /// Returns the scrypt parameters for this profile, using as much memory as the Argon2
+    /// parameters of the same profile.
+    pub fn scrypt_params(&self) -> ScryptParams {
+        let log_n = match self {
+            KdfProfile::Interactive => 14,
+            KdfProfile::Moderate => 16,
+            KdfProfile::Paranoid => 18,
+        };
+        ScryptParams {
+            log_n,
+            block_size: 8,
+            parallelism: 1,
+        }
+    }
+}
+
+/// Upper bound on PBKDF2 iterations accepted from file headers.
+pub const PBKDF2_MAX_ITERATIONS: u32 = 10_000_000;
+
+/// Password key derivation function of a password-mode file, with its parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordKdf {
+    Argon2id(Argon2Params),
+    Scrypt(ScryptParams),
+    /// PBKDF2-HMAC-SHA256 of older files; only read, never chosen for new files
+    Pbkdf2 {
+        iterations: u32,
+    },
+}
+
+/// Names accepted for [`PasswordKdf::for_profile`], default first.
+pub const PASSWORD_KDFS: [&str; 2] = ["argon2id", "scrypt"];
+
+impl Default for PasswordKdf {
+    fn default() -> Self {
+        PasswordKdf::Argon2id(Argon2Params::default())
+    }
+}
+
+impl PasswordKdf {
+    /// The KDF called `name` (see [`PASSWORD_KDFS`]) with the parameters of `profile`.
+    pub fn for_profile(name: &str, profile: KdfProfile) -> Result<Self, CryptoError> {
+        match name.to_ascii_lowercase().as_str() {
+            "argon2id" => Ok(PasswordKdf::Argon2id(profile.params())),
+            "scrypt" => Ok(PasswordKdf::Scrypt(profile.scrypt_params())),
+            other => Err(CryptoError::KeyDerivationError(format!(
+                "Unknown KDF '{other}' (expected argon2id or scrypt)"
+            ))),
+        }
+    }
+
+    /// Reads the KDF and parameters recorded in a password header, with the same limits as
+    /// [`Argon2Params::from_header`].
+    pub fn from_header(header: &XdPasswordHeader) -> Result<Self, CryptoError> {
+        match header.kdf.as_str() {
+            "argon2id" => Ok(PasswordKdf::Argon2id(Argon2Params::from_header(header)?)),
+            "scrypt" => Ok(PasswordKdf::Scrypt(ScryptParams::checked(
+                header.log_n,
+                header.block_size,
+                header.parallelism,
+            )?)),
+            "pbkdf2" => match header.iterations {
+                Some(iterations @ 1..=PBKDF2_MAX_ITERATIONS) => {
+                    Ok(PasswordKdf::Pbkdf2 { iterations })
+                }
+                Some(_) => Err(CryptoError::KeyDerivationError(
+                    "PBKDF2 iterations in header exceed allowed limits".to_string(),
+                )),
+                None => Err(CryptoError::DecryptionError(
+                    "PBKDF2 header is missing its iterations".to_string(),
+                )),
+            },
+            other => Err(CryptoError::DecryptionError(format!(
+                "Unsupported KDF '{other}'"
+            ))),
+        }
+    }
+
+    /// Name recorded in the header's `kdf` field.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PasswordKdf::Argon2id(_) => "argon2id",
+            PasswordKdf::Scrypt(_) => "scrypt",
+            PasswordKdf::Pbkdf2 { .. } => "pbkdf2",
+        }
+    }
+
+    /// Memory one derivation takes, in KB.
+    pub fn memory_cost(&self) -> u64 {
+        match self {
+            PasswordKdf::Argon2id(params) => u64::from(params.memory_cost),
+            PasswordKdf::Scrypt(params) => params.memory_cost(),
+            PasswordKdf::Pbkdf2 { .. } => 0,
+        }
+    }
+
+    /// Derives a 32-byte key from `password` and a 32-byte `salt`.
+    pub fn derive(&self, password: &str, salt: &[u8]) -> Result<[u8; 32], CryptoError> {
+        match self {
+            PasswordKdf::Argon2id(params) => derive_key_with_params(password, salt, params),
+            PasswordKdf::Scrypt(params) => derive_key_scrypt(password, salt, params),
+            PasswordKdf::Pbkdf2 { iterations } => derive_key_pbkdf2(password, salt, *iterations),
+        }
+    }
+
+    /// Header for a password-mode file derived with this KDF; the layout fields are left
+    /// for the caller.
+    fn header(&self, filename: &str, salt: &[u8]) -> XdPasswordHeader {
+        let mut header = XdPasswordHeader {
+            filename: filename.to_string(),
+            salt: base64::engine::general_purpose::STANDARD.encode(salt),
+            kdf: self.name().to_string(),
+            memory_cost: None,
+            time_cost: None,
+            parallelism: None,
+            log_n: None,
+            block_size: None,
+            iterations: None,
+            version: CURRENT_VERSION,
+            timestamp: encryption_time(filename),
+            chunk_size: None,
+            delta_id: None,
+        };
+        match *self {
+            PasswordKdf::Argon2id(p) => {
+                header.memory_cost = Some(p.memory_cost);
+                header.time_cost = Some(p.time_cost);
+                header.parallelism = Some(p.parallelism);
+            }
+            PasswordKdf::Scrypt(p) => {
+                header.parallelism = Some(p.parallelism);
+                header.log_n = Some(p.log_n);
+                header.block_size = Some(p.block_size);
+            }
+            PasswordKdf::Pbkdf2 { iterations } => {
+                // Files from before Argon2 were version 2
+                header.iterations = Some(iterations);
+                header.version = 2;
+            }
+        }
+        header
+    }
+}
+
+/// How many Argon2 and scrypt derivations may run at once; 0 (until [`set_kdf_threads`]
+/// is called) leaves them unlimited.
+static KDF_LIMIT: AtomicUsize = AtomicUsize::new(0);
+static KDF_RUNNING: Mutex<usize> = Mutex::new(0);
+static KDF_FINISHED: Condvar = Condvar::new();
+
+/// Caps how many Argon2 and scrypt derivations run at once at `threads`, or one per
+/// available core for 0. A derivation keeps one core busy (the argon2 crate computes a
+/// file's lanes one after another), so a server answering many password requests, or a
+/// batch over many files, queues derivations beyond the cap instead of running all of them
+/// at a fraction of a core each. Unlike [`chunked::set_threads`], a later call replaces the
+/// cap.
+pub fn set_kdf_threads(threads: u32) {
+    let threads = crate::compression::resolve_threads(threads) as usize;
+    KDF_LIMIT.store(threads, Ordering::Relaxed);
+    KDF_FINISHED.notify_all();
+}
+
+/// A place among the derivations allowed to run at once, given back on drop.
+struct KdfSlot;
+
+impl KdfSlot {
+    /// Waits until fewer derivations than the cap are running.
+    fn acquire() -> Self {
+        let mut running = KDF_RUNNING.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            let limit = KDF_LIMIT.load(Ordering::Relaxed);
+            if limit == 0 || *running < limit {
+                break;
+            }
+            running = KDF_FINISHED
+                .wait(running)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        *running += 1;
+        KdfSlot
+    }
+}
+
+impl Drop for KdfSlot {
+    fn drop(&mut self) {
+        *KDF_RUNNING.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        KDF_FINISHED.notify_one();
+    }
+}
+
+/// Asynchronously derives a 32-byte key from a password and salt with any [`PasswordKdf`].
+///
+/// Like [`derive_key_with_params_async`], the derivation runs on the blocking thread pool.
+pub async fn derive_password_key_async(
+    password: String,
+    salt: Vec<u8>,
+    kdf: PasswordKdf,
+) -> Result<[u8; 32], CryptoError> {
+    let password = Zeroizing::new(password);
+    task::spawn_blocking(move || kdf.derive(&password, &salt))
+        .await
+        .map_err(|e| CryptoError::AsyncError(format!("Async task join error: {e}")))?
+}
+
+/// Derives a 32-byte key from a password and salt with PBKDF2-HMAC-SHA256, as files from
+/// before Argon2 did.
+pub fn derive_key_pbkdf2(
+    password: &str,
+    salt: &[u8],
+    iterations: u32,
+) -> Result<[u8; 32], CryptoError> {
+    if salt.is_empty() || iterations == 0 {
+        return Err(CryptoError::KeyDerivationError(
+            "Invalid PBKDF2 salt or iteration count".to_string(),
+        ));
+    }
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, iterations, &mut key);
+    Ok(key)
+}
+
+/// Derives a 32-byte key from a password and a 32-byte salt with scrypt.
+pub fn derive_key_scrypt(
+    password: &str,
+    salt: &[u8],
+    params: &ScryptParams,
+) -> Result<[u8; 32], CryptoError> {
+    if salt.len() != SALT_LENGTH {
+        return Err(CryptoError::KeyDerivationError(
+            "Invalid salt length".to_string(),
+        ));
+    }
+    let scrypt_params =
+        scrypt::Params::new(params.log_n, params.block_size, params.parallelism, 32)
+            .map_err(|e| CryptoError::KeyDerivationError(format!("scrypt params error: {e}")))?;
+    let _slot = KdfSlot::acquire();
+    let mut key = [0u8; 32];
+    scrypt::scrypt(password.as_bytes(), salt, &scrypt_params, &mut key)
+        .map_err(|e| CryptoError::KeyDerivationError(format!("scrypt error: {e}")))?;
+    Ok(key)
+}
+
 /// Derives encryption key from password using Argon2 in async context.
 /// Asynchronously derives a 32-byte encryption key from a password and salt using Argon2id.
 ///
@@ -110,6 +541,18 @@ pub async fn derive_key_from_password_async(
     password: String,
     salt: Vec<u8>,
 ) -> Result<[u8; 32], CryptoError> {
+    derive_key_with_params_async(password, salt, Argon2Params::default()).await
+}
+
+/// Asynchronously derives a 32-byte key with explicit Argon2id parameters.
+///
+/// Same as [`derive_key_from_password_async`], but uses `params` instead of the defaults.
+pub async fn derive_key_with_params_async(
+    password: String,
+    salt: Vec<u8>,
+    params: Argon2Params,
+) -> Result<[u8; 32], CryptoError> {
+    let password = Zeroizing::new(password);
     if salt.len() != SALT_LENGTH {
         return Err(CryptoError::KeyDerivationError(
             "Invalid salt length".to_string(),
@@ -117,7 +560,7 @@ pub async fn derive_key_from_password_async(
     }
 
     // Run Argon2 computation in blocking task since it's CPU-intensive
-    let key = task::spawn_blocking(move || derive_key_from_password_argon2(&password, &salt))
+    let key = task::spawn_blocking(move || derive_key_with_params(&password, &salt, &params))
         .await
         .map_err(|e| CryptoError::AsyncError(format!("Async task join error: {e}")))??;
 
@@ -138,6 +581,15 @@ pub async fn derive_key_from_password_async(
 pub fn derive_key_from_password_argon2(
     password: &str,
     salt: &[u8],
+) -> Result<[u8; 32], CryptoError> {
+    derive_key_with_params(password, salt, &Argon2Params::default())
+}
+
+/// Derives a 32-byte key from a password and salt using Argon2id with explicit parameters.
+pub fn derive_key_with_params(
+    password: &str,
+    salt: &[u8],
+    argon2_params: &Argon2Params,
 ) -> Result<[u8; 32], CryptoError> {
     if salt.len() != SALT_LENGTH {
         return Err(CryptoError::KeyDerivationError(
@@ -147,33 +599,23 @@ pub fn derive_key_from_password_argon2(
 
     // Configure Argon2 with security-focused parameters
     let params = Params::new(
-        ARGON2_MEMORY_COST, // memory cost (64 MB)
-        ARGON2_TIME_COST,   // time cost (3 iterations)
-        ARGON2_PARALLELISM, // parallelism (1 thread)
-        Some(32),           // output length matches AES-256 key size
+        argon2_params.memory_cost,
+        argon2_params.time_cost,
+        argon2_params.parallelism,
+        Some(32), // output length matches AES-256 key size
     )
     .map_err(|e| CryptoError::KeyDerivationError(format!("Argon2 params error: {e}")))?;
 
     let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
 
-    // Convert raw salt bytes to the format expected by argon2 crate
-    let salt_string = SaltString::encode_b64(salt)
-        .map_err(|e| CryptoError::KeyDerivationError(format!("Salt encoding error: {e}")))?;
-
-    let hash = argon2
-        .hash_password(password.as_bytes(), &salt_string)
-        .map_err(|e| CryptoError::KeyDerivationError(format!("Argon2 hashing error: {e}")))?;
-
-    let hash_value = hash.hash.unwrap();
-    let hash_bytes = hash_value.as_bytes();
-    if hash_bytes.len() < 32 {
-        return Err(CryptoError::KeyDerivationError(
-            "Hash too short".to_string(),
-        ));
-    }
-
+    // Hash straight into the key, so no intermediate copy of it is left behind
+    let _slot = KdfSlot::acquire();
+    let started = std::time::Instant::now();
     let mut key = [0u8; 32];
-    key.copy_from_slice(&hash_bytes[..32]);
+    argon2
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| CryptoError::KeyDerivationError(format!("Argon2 hashing error: {e}")))?;
+    crate::metrics::kdf().record(argon2_params, started.elapsed());
     Ok(key)
 }
 
@@ -194,6 +636,19 @@ pub fn encrypt_with_header(
     data: &[u8],
     key: &[u8],
     filename: &str,
+) -> Result<Vec<u8>, CryptoError> {
+    encrypt_with_header_chunked(data, key, filename, None)
+}
+
+/// Encrypts data with a 32-byte key, optionally using the chunked layout.
+///
+/// Same as [`encrypt_with_header`]; with `chunk_size` set, the body is split into chunks of
+/// that many plaintext bytes (see [`chunked`]).
+pub fn encrypt_with_header_chunked(
+    data: &[u8],
+    key: &[u8],
+    filename: &str,
+    chunk_size: Option<u32>,
 ) -> Result<Vec<u8>, CryptoError> {
     if key.len() != 32 {
         return Err(CryptoError::EncryptionError(
@@ -207,40 +662,323 @@ pub fn encrypt_with_header(
         k
     });
 
-    let cipher = Aes256Gcm::new_from_slice(secure_key.as_slice()).map_err(|_| {
-        CryptoError::EncryptionError("Failed to initialize AES-256-GCM cipher".to_string())
-    })?;
+    seal_key_file(
+        data,
+        &secure_key,
+        &embedded_key_header(key, filename, chunk_size),
+    )
+}
 
-    // Generate cryptographically secure random nonce for this encryption
+/// Streaming counterpart of [`encrypt_with_header_chunked`] for the chunked layout.
+///
+/// Writes the header and nonce to `out` and returns a [`ChunkWriter`] that seals the
+/// plaintext written to it; [`ChunkWriter::finish`] completes the file.
+pub fn key_file_writer<W: Write>(
+    out: W,
+    key: &[u8],
+    filename: &str,
+    chunk_size: u32,
+) -> Result<ChunkWriter<W>, CryptoError> {
+    let cipher = Aes256Gcm::new_from_slice(key)
+        .map_err(|_| CryptoError::EncryptionError("Key must be exactly 32 bytes".to_string()))?;
+    let header = embedded_key_header(key, filename, Some(chunk_size));
+    let header_json = serde_json::to_vec(&header)
+        .map_err(|_| CryptoError::EncryptionError("Header serialization failed".to_string()))?;
+    let aad = key_header_aad(header.is_envelope(), &header_json);
+    start_file(
+        out,
+        cipher,
+        XdWriter::key(&header_json),
+        aad.into_owned(),
+        chunk_size,
+    )
+}
+
+/// Header of a plain key-mode file, which embeds its key.
+fn embedded_key_header(key: &[u8], filename: &str, chunk_size: Option<u32>) -> XdHeader {
+    XdHeader {
+        filename: filename.to_string(),
+        key: Some(base64::engine::general_purpose::STANDARD.encode(key)),
+        version: 2,
+        timestamp: encryption_time(filename),
+        chunk_size,
+        delta_id: None,
+        keyslots: None,
+        key_check: None,
+        subkey_salt: None,
+        convergent: None,
+    }
+}
+
+/// Writes the part of `file` before the body to `out` with a fresh nonce, and returns the
+/// writer for a chunked body.
+fn start_file<W: Write>(
+    mut out: W,
+    cipher: Aes256Gcm,
+    file: XdWriter<'_>,
+    aad: Vec<u8>,
+    chunk_size: u32,
+) -> Result<ChunkWriter<W>, CryptoError> {
     let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let prefix = file.nonce(&nonce).prefix()?;
+    out.write_all(&prefix)
+        .map_err(|e| CryptoError::EncryptionError(format!("Failed to write header: {e}")))?;
+    ChunkWriter::new(out, cipher, &nonce, aad, chunk_size)
+}
 
-    // AES-GCM provides both confidentiality and authenticity
-    let ciphertext = cipher
-        .encrypt(&nonce, data)
-        .map_err(|_| CryptoError::EncryptionError("Authenticated encryption failed".to_string()))?;
+/// HKDF info string for per-file subkeys of a master key.
+const SUBKEY_INFO: &[u8] = b"EncryptX file subkey v1";
 
+/// Derives a file's key from a master key and the file's random subkey salt with
+/// HKDF-SHA256.
+///
+/// Every file gets its own key, so one file's key reveals nothing about the master key or
+/// other files, and random nonces can't collide across files.
+pub fn derive_subkey(master_key: &[u8], salt: &[u8]) -> Result<SecureKey, CryptoError> {
+    if master_key.len() != 32 {
+        return Err(CryptoError::DecryptionError(
+            "Key must be exactly 32 bytes".to_string(),
+        ));
+    }
+    let mut key = [0u8; 32];
+    hkdf::Hkdf::<Sha256>::new(Some(salt), master_key)
+        .expand(SUBKEY_INFO, &mut key)
+        .map_err(|_| CryptoError::KeyDerivationError("HKDF expansion failed".to_string()))?;
+    let subkey = SecureKey::new(key);
+    zeroize::Zeroize::zeroize(&mut key);
+    Ok(subkey)
+}
+
+/// The key of a master-key file, derived from `master_key` with the header's salt.
+fn master_key_file_key(
+    salt_b64: &str,
+    master_key: Option<&[u8]>,
+) -> Result<SecureKey, CryptoError> {
+    let master_key = master_key.ok_or_else(|| {
+        CryptoError::WrongDecryptionMethod(
+            "This file's key is derived from a master key. The master key is required for decryption."
+                .to_string(),
+        )
+    })?;
+    let salt = base64::engine::general_purpose::STANDARD
+        .decode(salt_b64)
+        .map_err(|_| CryptoError::DecryptionError("Invalid subkey salt format".to_string()))?;
+    derive_subkey(master_key, &salt)
+}
+
+/// Encrypts data under a key derived from `master_key` with a fresh random salt.
+///
+/// The salt is recorded in the header (`subkey_salt`) and no key is embedded, so the file
+/// opens only with the master key.
+pub fn encrypt_with_master_key(
+    data: &[u8],
+    master_key: &[u8],
+    filename: &str,
+    chunk_size: Option<u32>,
+) -> Result<Vec<u8>, CryptoError> {
+    use aes_gcm::aead::rand_core::RngCore;
+    let mut salt = [0u8; 32];
+    OsRng.fill_bytes(&mut salt);
+    let file_key = derive_subkey(master_key, &salt)
+        .map_err(|_| CryptoError::EncryptionError("Key must be exactly 32 bytes".to_string()))?;
     let header = XdHeader {
         filename: filename.to_string(),
-        key: Some(base64::engine::general_purpose::STANDARD.encode(key)),
+        key: None,
         version: 2,
-        timestamp: std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs(),
+        timestamp: encryption_time(filename),
+        chunk_size,
+        delta_id: None,
+        keyslots: None,
+        key_check: None,
+        subkey_salt: Some(base64::engine::general_purpose::STANDARD.encode(salt)),
+        convergent: None,
     };
 
+    seal_key_file(data, &file_key, &header)
+}
+
+/// HKDF info strings for the salt and nonce of convergent files.
+const CONVERGENT_SALT_INFO: &[u8] = b"EncryptX convergent salt v1";
+const CONVERGENT_NONCE_INFO: &[u8] = b"EncryptX convergent nonce v1";
+
+/// Encrypts data deterministically: the same plaintext, secret, filename and chunk size
+/// always give the same file, so deduplicating storage keeps one copy.
+///
+/// This is master-key mode (see [`encrypt_with_master_key`]) with the salt derived from the
+/// plaintext instead of drawn at random: the salt is HKDF-SHA256 of the plaintext's SHA-256
+/// keyed by `secret`, and the nonce is derived from the resulting file key. The timestamp is
+/// recorded as 0. Anyone holding two files can tell whether they hold the same plaintext,
+/// and anyone holding `secret` can confirm a guessed plaintext; that is the price of
+/// deduplication. Without `secret`, the salt reveals nothing else about the plaintext.
+pub fn encrypt_convergent(
+    data: &[u8],
+    secret: &[u8],
+    filename: &str,
+    chunk_size: Option<u32>,
+) -> Result<Vec<u8>, CryptoError> {
+    let key_error = || CryptoError::EncryptionError("Key must be exactly 32 bytes".to_string());
+    if secret.len() != 32 {
+        return Err(key_error());
+    }
+    let mut salt = [0u8; 32];
+    hkdf::Hkdf::<Sha256>::new(Some(secret), &Sha256::digest(data))
+        .expand(CONVERGENT_SALT_INFO, &mut salt)
+        .map_err(|_| CryptoError::KeyDerivationError("HKDF expansion failed".to_string()))?;
+    let file_key = derive_subkey(secret, &salt).map_err(|_| key_error())?;
+    // Each plaintext gets its own key, so a nonce fixed per key is never reused for
+    // different data
+    let mut nonce = [0u8; 12];
+    hkdf::Hkdf::<Sha256>::new(None, file_key.as_slice())
+        .expand(CONVERGENT_NONCE_INFO, &mut nonce)
+        .map_err(|_| CryptoError::KeyDerivationError("HKDF expansion failed".to_string()))?;
+    let header = XdHeader {
+        filename: filename.to_string(),
+        key: None,
+        version: 2,
+        timestamp: 0,
+        chunk_size,
+        delta_id: None,
+        keyslots: None,
+        key_check: None,
+        subkey_salt: Some(base64::engine::general_purpose::STANDARD.encode(salt)),
+        convergent: Some(true),
+    };
+
+    seal_key_file_with_nonce(data, &file_key, &header, &nonce)
+}
+
+/// Encrypts data under a random data key that is only stored wrapped in `keyslots`.
+///
+/// `data_key` must come from [`keyslot::generate_data_key`] (or an unlocked file) and every
+/// slot must wrap it (see [`keyslot::create`]). The header carries no key, so the file
+/// can only be opened through one of the slots. The result is an envelope file: its slots
+/// can later be changed with [`rewrap`] without re-encrypting the content.
+pub fn encrypt_with_keyslots(
+    data: &[u8],
+    data_key: &SecureKey,
+    filename: &str,
+    keyslots: Vec<keyslot::Keyslot>,
+    chunk_size: Option<u32>,
+) -> Result<Vec<u8>, CryptoError> {
+    let header = envelope_header(data_key, filename, keyslots, chunk_size)?;
+    seal_key_file(data, data_key, &header)
+}
+
+/// Header of an envelope file whose `data_key` is wrapped in `keyslots`.
+fn envelope_header(
+    data_key: &SecureKey,
+    filename: &str,
+    keyslots: Vec<keyslot::Keyslot>,
+    chunk_size: Option<u32>,
+) -> Result<XdHeader, CryptoError> {
+    if keyslots.is_empty() {
+        return Err(CryptoError::EncryptionError(
+            "At least one keyslot is required".to_string(),
+        ));
+    }
+    if keyslots.len() > MAX_KEYSLOTS {
+        return Err(CryptoError::EncryptionError(format!(
+            "At most {MAX_KEYSLOTS} keyslots are supported"
+        )));
+    }
+    Ok(XdHeader {
+        filename: filename.to_string(),
+        key: None,
+        version: ENVELOPE_VERSION,
+        timestamp: encryption_time(filename),
+        chunk_size,
+        delta_id: None,
+        keyslots: Some(keyslots),
+        key_check: Some(keyslot::key_check(data_key)),
+        subkey_salt: None,
+        convergent: None,
+    })
+}
+
+/// Encrypts data under a random data key wrapped by `key` in a single keyslot.
+///
+/// Unlike [`encrypt_with_header_chunked`], `key` never touches the content and isn't
+/// embedded, so it can be replaced (or joined by other credentials) with [`rewrap`] alone.
+pub async fn encrypt_with_wrapped_key(
+    data: &[u8],
+    key: &[u8],
+    filename: &str,
+    chunk_size: Option<u32>,
+) -> Result<Vec<u8>, CryptoError> {
+    let data_key = keyslot::generate_data_key();
+    let slot = keyslot::create(
+        &data_key,
+        keyslot::Credential::Key(key),
+        Argon2Params::default(),
+        None,
+    )
+    .await?;
+    encrypt_with_keyslots(data, &data_key, filename, vec![slot], chunk_size)
+}
+
+/// Streaming counterpart of [`encrypt_with_wrapped_key`] for the chunked layout.
+///
+/// Writes the header and nonce to `out` and returns a [`ChunkWriter`] that seals the
+/// plaintext written to it; [`ChunkWriter::finish`] completes the file.
+pub async fn wrapped_key_file_writer<W: Write>(
+    out: W,
+    key: &[u8],
+    filename: &str,
+    chunk_size: u32,
+) -> Result<ChunkWriter<W>, CryptoError> {
+    let data_key = keyslot::generate_data_key();
+    let slot = keyslot::create(
+        &data_key,
+        keyslot::Credential::Key(key),
+        Argon2Params::default(),
+        None,
+    )
+    .await?;
+    let header = envelope_header(&data_key, filename, vec![slot], Some(chunk_size))?;
+    let cipher = Aes256Gcm::new_from_slice(data_key.as_slice()).map_err(|_| {
+        CryptoError::EncryptionError("Failed to initialize AES-256-GCM cipher".to_string())
+    })?;
     let header_json = serde_json::to_vec(&header)
         .map_err(|_| CryptoError::EncryptionError("Header serialization failed".to_string()))?;
+    let aad = key_header_aad(true, &header_json);
+    start_file(
+        out,
+        cipher,
+        XdWriter::key(&header_json),
+        aad.into_owned(),
+        chunk_size,
+    )
+}
 
-    // Construct file format: length prefix allows parsing without knowing header size
-    let header_len = (header_json.len() as u32).to_be_bytes();
-    let mut result = Vec::with_capacity(4 + header_json.len() + 12 + ciphertext.len());
-    result.extend_from_slice(&header_len);
-    result.extend_from_slice(&header_json);
-    result.extend_from_slice(&nonce);
-    result.extend_from_slice(&ciphertext);
+/// Encrypts the body under `key` and frames it after a key-mode header.
+fn seal_key_file(data: &[u8], key: &SecureKey, header: &XdHeader) -> Result<Vec<u8>, CryptoError> {
+    // Generate cryptographically secure random nonce for this encryption
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    seal_key_file_with_nonce(data, key, header, &nonce)
+}
 
-    Ok(result)
+/// Same as [`seal_key_file`] with a given nonce, which must never repeat under `key`.
+fn seal_key_file_with_nonce(
+    data: &[u8],
+    key: &SecureKey,
+    header: &XdHeader,
+    nonce: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
+    let cipher = Aes256Gcm::new_from_slice(key.as_slice()).map_err(|_| {
+        CryptoError::EncryptionError("Failed to initialize AES-256-GCM cipher".to_string())
+    })?;
+
+    let header_json = serde_json::to_vec(header)
+        .map_err(|_| CryptoError::EncryptionError("Header serialization failed".to_string()))?;
+
+    // AES-GCM provides both confidentiality and authenticity
+    let aad = key_header_aad(header.is_envelope(), &header_json);
+    let ciphertext = seal_body(&cipher, nonce, &aad, data, header.chunk_size)?;
+
+    XdWriter::key(&header_json)
+        .nonce(nonce)
+        .body(&ciphertext)
+        .finish()
 }
 
 /// Encrypts data with password-based key derivation using Argon2.
@@ -262,10 +1000,55 @@ pub async fn encrypt_with_password_async(
     filename: &str,
     salt: Vec<u8>,
 ) -> Result<Vec<u8>, CryptoError> {
-    // Derive 256-bit key from password using Argon2
-    let derived_key = derive_key_from_password_async(password, salt.clone()).await?;
+    encrypt_with_password_params_async(
+        data,
+        password,
+        filename,
+        salt,
+        Argon2Params::default(),
+        None,
+    )
+    .await
+}
+
+/// Asynchronously encrypts data with a password using explicit Argon2id parameters.
+///
+/// Same as [`encrypt_with_password_async`]; the parameters are recorded in the header so
+/// decryption reproduces the same derivation. With `chunk_size` set, the chunked layout is
+/// used (see [`chunked`]).
+pub async fn encrypt_with_password_params_async(
+    data: &[u8],
+    password: String,
+    filename: &str,
+    salt: Vec<u8>,
+    params: Argon2Params,
+    chunk_size: Option<u32>,
+) -> Result<Vec<u8>, CryptoError> {
+    encrypt_with_password_kdf_async(
+        data,
+        password,
+        filename,
+        salt,
+        PasswordKdf::Argon2id(params),
+        chunk_size,
+    )
+    .await
+}
 
-    let secure_key = SecureKey::new(derived_key);
+/// Asynchronously encrypts data with a password using any [`PasswordKdf`].
+///
+/// Same as [`encrypt_with_password_params_async`]; the KDF and its parameters are recorded
+/// in the header.
+pub async fn encrypt_with_password_kdf_async(
+    data: &[u8],
+    password: String,
+    filename: &str,
+    salt: Vec<u8>,
+    kdf: PasswordKdf,
+    chunk_size: Option<u32>,
+) -> Result<Vec<u8>, CryptoError> {
+    // Derive 256-bit key from the password
+    let secure_key = SecureKey::new(derive_password_key_async(password, salt.clone(), kdf).await?);
 
     let cipher = Aes256Gcm::new_from_slice(secure_key.as_slice()).map_err(|_| {
         CryptoError::EncryptionError("Failed to initialize AES-256-GCM cipher".to_string())
@@ -273,39 +1056,56 @@ pub async fn encrypt_with_password_async(
 
     let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
 
-    let ciphertext = cipher.encrypt(&nonce, data).map_err(|_| {
-        CryptoError::EncryptionError("Password-based encryption failed".to_string())
-    })?;
-
     let header = XdPasswordHeader {
-        filename: filename.to_string(),
-        salt: base64::engine::general_purpose::STANDARD.encode(&salt),
-        kdf: "argon2id".to_string(),
-        memory_cost: Some(ARGON2_MEMORY_COST),
-        time_cost: Some(ARGON2_TIME_COST),
-        parallelism: Some(ARGON2_PARALLELISM),
-        iterations: None, // Not applicable for Argon2
-        version: 3,       // Version 3 indicates Argon2 usage
-        timestamp: std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs(),
+        chunk_size,
+        ..kdf.header(filename, &salt)
     };
 
     let header_json = serde_json::to_vec(&header).map_err(|_| {
         CryptoError::EncryptionError("Password header serialization failed".to_string())
     })?;
 
-    // Password-based files start with 0xFF marker for easy identification
-    let header_len = (header_json.len() as u32).to_be_bytes();
-    let mut result = Vec::with_capacity(1 + 4 + header_json.len() + 12 + ciphertext.len());
-    result.push(0xFF); // Magic byte identifying password-based encryption
-    result.extend_from_slice(&header_len);
-    result.extend_from_slice(&header_json);
-    result.extend_from_slice(&nonce);
-    result.extend_from_slice(&ciphertext);
+    let ciphertext = seal_body(&cipher, &nonce, &header_json, data, chunk_size).map_err(|_| {
+        CryptoError::EncryptionError("Password-based encryption failed".to_string())
+    })?;
 
-    Ok(result)
+    // Password-based files start with a marker byte for easy identification
+    XdWriter::password(&header_json)
+        .nonce(&nonce)
+        .body(&ciphertext)
+        .finish()
+}
+
+/// Streaming counterpart of [`encrypt_with_password_kdf_async`] for the chunked layout.
+///
+/// Derives the key, writes the header and nonce to `out` and returns a [`ChunkWriter`] that
+/// seals the plaintext written to it; [`ChunkWriter::finish`] completes the file.
+pub async fn password_file_writer<W: Write>(
+    out: W,
+    password: String,
+    filename: &str,
+    salt: Vec<u8>,
+    kdf: PasswordKdf,
+    chunk_size: u32,
+) -> Result<ChunkWriter<W>, CryptoError> {
+    let secure_key = SecureKey::new(derive_password_key_async(password, salt.clone(), kdf).await?);
+    let cipher = Aes256Gcm::new_from_slice(secure_key.as_slice()).map_err(|_| {
+        CryptoError::EncryptionError("Failed to initialize AES-256-GCM cipher".to_string())
+    })?;
+    let header = XdPasswordHeader {
+        chunk_size: Some(chunk_size),
+        ..kdf.header(filename, &salt)
+    };
+    let header_json = serde_json::to_vec(&header).map_err(|_| {
+        CryptoError::EncryptionError("Password header serialization failed".to_string())
+    })?;
+    start_file(
+        out,
+        cipher,
+        XdWriter::password(&header_json),
+        header_json.clone(),
+        chunk_size,
+    )
 }
 
 /// Decrypts key-based encrypted files with authentication verification.
@@ -326,34 +1126,35 @@ pub fn decrypt_with_header(
     encrypted_data: &[u8],
     key: Option<&[u8]>,
 ) -> Result<(Vec<u8>, String), CryptoError> {
-    if encrypted_data.len() < 4 {
-        return Err(CryptoError::FormatError);
-    }
-
     // Detect password-based format and provide helpful error
-    if encrypted_data[0] == 0xFF {
+    if encrypted_data.first() == Some(&PASSWORD_MARKER) {
         return Err(CryptoError::WrongDecryptionMethod(
             "This is a password-encrypted file. A password is required for decryption.".to_string(),
         ));
     }
 
-    let header_len = u32::from_be_bytes([
-        encrypted_data[0],
-        encrypted_data[1],
-        encrypted_data[2],
-        encrypted_data[3],
-    ]) as usize;
-
-    if encrypted_data.len() < 4 + header_len + 12 {
+    let file = XdReader::parse(encrypted_data)?;
+    let ParsedHeader::Key(header) = &file.header else {
         return Err(CryptoError::FormatError);
-    }
-
-    let header_json = &encrypted_data[4..4 + header_len];
-    let header: XdHeader = serde_json::from_slice(header_json)
-        .map_err(|_| CryptoError::DecryptionError("Invalid or corrupted header".to_string()))?;
+    };
 
-    let nonce = &encrypted_data[4 + header_len..4 + header_len + 12];
-    let ciphertext = &encrypted_data[4 + header_len + 12..];
+    // Keyslot files never embed the data key; the given key must unwrap it
+    if let (Some(slots), Some(check)) = (&header.keyslots, &header.key_check) {
+        let key = key.ok_or_else(|| {
+            CryptoError::WrongDecryptionMethod(
+                "This file is protected by keyslots. A password or key is required for decryption."
+                    .to_string(),
+            )
+        })?;
+        let (data_key, _) = keyslot::unlock_with_key(slots, check, key)?;
+        let decrypted_data = decrypt_parsed(&file, &data_key)?;
+        return Ok((decrypted_data, header.filename.clone()));
+    }
+    if let Some(salt) = &header.subkey_salt {
+        let file_key = master_key_file_key(salt, key)?;
+        let decrypted_data = decrypt_parsed(&file, &file_key)?;
+        return Ok((decrypted_data, header.filename.clone()));
+    }
 
     // Use provided key or fall back to embedded key from header
     let final_key = if let Some(k) = key {
@@ -389,79 +1190,632 @@ pub fn decrypt_with_header(
         .map_err(|_| CryptoError::DecryptionError("Failed to create cipher".to_string()))?;
 
     // AES-GCM automatically verifies authenticity during decryption
-    let decrypted_data = cipher
-        .decrypt(Nonce::from_slice(nonce), ciphertext)
-        .map_err(|_| CryptoError::AuthenticationError)?;
+    let decrypted_data = open_body(&cipher, &file)?;
 
-    Ok((decrypted_data, header.filename))
+    Ok((decrypted_data, header.filename.clone()))
 }
 
 /// Decrypts password-based encrypted files using Argon2 key derivation.
 /// Asynchronously decrypts data encrypted with a password-derived key using AES-256-GCM.
 ///
-/// Automatically detects and rejects files not encrypted with a password, returning a helpful error if a key-based file is provided. Parses the password-based file header, derives the decryption key using Argon2id with parameters from the header, and decrypts the ciphertext, verifying authenticity. Returns the decrypted data and the original filename on success.
+/// Automatically detects and rejects files not encrypted with a password, returning a helpful error if a key-based file is provided. Parses the password-based file header, derives the decryption key with the KDF and parameters recorded in the header (Argon2id, scrypt or legacy PBKDF2), and decrypts the ciphertext, verifying authenticity. Returns the decrypted data and the original filename on success.
 ///
 /// # Errors
-/// Returns a `CryptoError` if the file format is invalid, the decryption method is incorrect, key derivation fails, or authentication fails during decryption. PBKDF2-encrypted files are not supported in async mode.
+/// Returns a `CryptoError` if the file format is invalid, the decryption method is incorrect, key derivation fails, or authentication fails during decryption.
 pub async fn decrypt_with_password_async(
     encrypted_data: &[u8],
     password: String,
 ) -> Result<(Vec<u8>, String), CryptoError> {
-    if encrypted_data.is_empty() {
-        return Err(CryptoError::FormatError);
-    }
-
-    // Ensure this is actually a password-based file
-    if encrypted_data[0] != 0xFF {
+    let password = Zeroizing::new(password);
+    // Ensure this is actually a password-based file (or has a password keyslot)
+    if encrypted_data.first() != Some(&PASSWORD_MARKER) {
+        if let Ok(file) = XdReader::parse(encrypted_data)
+            && file.header.keyslots().is_some()
+        {
+            let key = file_key(&file, Some(password.to_string()), None).await?;
+            return Ok((
+                decrypt_parsed(&file, &key)?,
+                file.header.filename().to_string(),
+            ));
+        }
         return Err(CryptoError::WrongDecryptionMethod("This file was not encrypted with a password. Please decrypt without providing a password.".to_string()));
     }
 
-    if encrypted_data.len() < 5 {
-        return Err(CryptoError::FormatError);
-    }
-
-    let header_len = u32::from_be_bytes([
-        encrypted_data[1],
-        encrypted_data[2],
-        encrypted_data[3],
-        encrypted_data[4],
-    ]) as usize;
-
-    if encrypted_data.len() < 5 + header_len + 12 {
+    let file = XdReader::parse(encrypted_data)?;
+    let ParsedHeader::Password(header) = &file.header else {
         return Err(CryptoError::FormatError);
-    }
-
-    let header_json = &encrypted_data[5..5 + header_len];
-    let header: XdPasswordHeader = serde_json::from_slice(header_json)
-        .map_err(|_| CryptoError::DecryptionError("Invalid password-based header".to_string()))?;
+    };
 
     let salt = base64::engine::general_purpose::STANDARD
         .decode(&header.salt)
         .map_err(|_| CryptoError::DecryptionError("Invalid salt format".to_string()))?;
 
     // Use the same KDF that was used for encryption
-    let derived_key = if header.kdf == "argon2id" {
-        derive_key_from_password_async(password, salt).await?
-    } else {
-        // Legacy PBKDF2 support would go here if needed
-        return Err(CryptoError::DecryptionError(
-            "PBKDF2 decryption not supported in async mode".to_string(),
+    let kdf = PasswordKdf::from_header(header)?;
+    let secure_key =
+        SecureKey::new(derive_password_key_async(password.to_string(), salt, kdf).await?);
+
+    let cipher = Aes256Gcm::new_from_slice(secure_key.as_slice()).map_err(|_| {
+        CryptoError::DecryptionError("Failed to create cipher with derived key".to_string())
+    })?;
+
+    // Decrypt and verify authenticity in one operation
+    let decrypted_data = open_body(&cipher, &file)?;
+
+    Ok((decrypted_data, header.filename.clone()))
+}
+
+/// Encrypts a file body as one AES-GCM message, or as chunks when `chunk_size` is set.
+///
+/// Single-message bodies keep the original layout without associated data, so older
+/// readers can still open them.
+fn seal_body(
+    cipher: &Aes256Gcm,
+    nonce: &[u8],
+    header_json: &[u8],
+    data: &[u8],
+    chunk_size: Option<u32>,
+) -> Result<Vec<u8>, CryptoError> {
+    match chunk_size {
+        Some(size) => chunked::seal(cipher, nonce, header_json, data, size),
+        None => cipher.encrypt(Nonce::from_slice(nonce), data).map_err(|_| {
+            CryptoError::EncryptionError("Authenticated encryption failed".to_string())
+        }),
+    }
+}
+
+/// Decrypts the body of a parsed file in whichever layout its header declares.
+fn open_body(cipher: &Aes256Gcm, file: &XdReader<'_>) -> Result<Vec<u8>, CryptoError> {
+    match (file.header.chunk_size(), file.header.delta_id()) {
+        (Some(size), Some(delta_id)) => delta::open(cipher, file, delta_id, size),
+        (Some(size), None) => chunked::open(cipher, file.nonce, &file.aad(), file.ciphertext, size),
+        (None, Some(_)) => Err(CryptoError::FormatError),
+        (None, None) => cipher
+            .decrypt(Nonce::from_slice(file.nonce), file.ciphertext)
+            .map_err(|_| CryptoError::AuthenticationError),
+    }
+}
+
+/// Replaces the keyslots of an envelope file, keeping its body as it is.
+///
+/// Nothing is decrypted, so the caller must already have unlocked the file and every new
+/// slot must wrap its data key (see [`keyslot::create`]). A signature trailer is dropped,
+/// since it covered the old header.
+pub fn rewrap(data: &[u8], keyslots: Vec<keyslot::Keyslot>) -> Result<Vec<u8>, CryptoError> {
+    if keyslots.is_empty() {
+        return Err(CryptoError::EncryptionError(
+            "At least one keyslot is required".to_string(),
+        ));
+    }
+    if keyslots.len() > MAX_KEYSLOTS {
+        return Err(CryptoError::EncryptionError(format!(
+            "At most {MAX_KEYSLOTS} keyslots are supported"
+        )));
+    }
+    let file = XdReader::parse(data)?;
+    let ParsedHeader::Key(mut header) = file.header else {
+        return Err(CryptoError::WrongDecryptionMethod(
+            "Only key-mode envelope files can be rewrapped".to_string(),
         ));
     };
+    if !header.is_envelope() {
+        return Err(CryptoError::WrongDecryptionMethod(
+            "File is not an envelope file; re-encrypt it instead".to_string(),
+        ));
+    }
+    header.keyslots = Some(keyslots);
+    let header_json = serde_json::to_vec(&header)
+        .map_err(|_| CryptoError::EncryptionError("Header serialization failed".to_string()))?;
 
-    let secure_key = SecureKey::new(derived_key);
+    XdWriter::key(&header_json)
+        .nonce(file.nonce)
+        .body(file.ciphertext)
+        .finish()
+}
 
-    let nonce = &encrypted_data[5 + header_len..5 + header_len + 12];
-    let ciphertext = &encrypted_data[5 + header_len + 12..];
+/// Checks whether the given bytes look like a well-formed `.xd` file.
+///
+/// Only the framing and header JSON are validated; nothing is decrypted, so no key or
+/// password is needed. Used by the pre-commit hook to tell ciphertext apart from plaintext.
+pub fn is_valid_xd(data: &[u8]) -> bool {
+    XdReader::parse(data).is_ok()
+}
 
-    let cipher = Aes256Gcm::new_from_slice(secure_key.as_slice()).map_err(|_| {
-        CryptoError::DecryptionError("Failed to create cipher with derived key".to_string())
+/// Returns a short, non-secret fingerprint of a key for display and comparison.
+///
+/// The fingerprint is the first 8 bytes of SHA-256 over the key, hex-encoded in
+/// colon-separated pairs (e.g. `3f:a1:...`).
+pub fn key_fingerprint(key: &[u8]) -> String {
+    Sha256::digest(key)[..8]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Derives or resolves the AES key for a parsed `.xd` file.
+///
+/// Password files need `password`; key files use `key`, falling back to the embedded key.
+/// Keyslot files accept either and return the unwrapped data key.
+/// Supplying the wrong kind of secret yields [`CryptoError::WrongDecryptionMethod`].
+pub async fn file_key(
+    file: &XdReader<'_>,
+    password: Option<String>,
+    key: Option<&[u8]>,
+) -> Result<SecureKey, CryptoError> {
+    let password = password.map(Zeroizing::new);
+    if let Some((slots, check)) = file.header.keyslots() {
+        let (data_key, _) = match (&password, key) {
+            (Some(password), _) => {
+                keyslot::unlock_with_password(slots, check, password.to_string()).await?
+            }
+            (None, Some(key)) => keyslot::unlock_with_key(slots, check, key)?,
+            (None, None) => {
+                return Err(CryptoError::WrongDecryptionMethod(
+                    "This file is protected by keyslots. A password or key is required for decryption.".to_string(),
+                ));
+            }
+        };
+        return Ok(data_key);
+    }
+    match (&file.header, &password) {
+        (ParsedHeader::Password(header), Some(password)) => {
+            let kdf = PasswordKdf::from_header(header)?;
+            let salt = base64::engine::general_purpose::STANDARD
+                .decode(&header.salt)
+                .map_err(|_| CryptoError::DecryptionError("Invalid salt format".to_string()))?;
+            Ok(SecureKey::new(
+                derive_password_key_async(password.to_string(), salt, kdf).await?,
+            ))
+        }
+        (ParsedHeader::Password(_), None) => Err(CryptoError::WrongDecryptionMethod(
+            "This is a password-encrypted file. A password is required for decryption.".to_string(),
+        )),
+        (ParsedHeader::Key(_), Some(_)) => Err(CryptoError::WrongDecryptionMethod(
+            "This file was not encrypted with a password. Please decrypt without providing a password.".to_string(),
+        )),
+        (ParsedHeader::Key(header), None) if header.subkey_salt.is_some() => {
+            master_key_file_key(header.subkey_salt.as_deref().unwrap_or_default(), key)
+        }
+        (ParsedHeader::Key(header), None) => {
+            let bytes = match (key, &header.key) {
+                (Some(k), _) => k.to_vec(),
+                (None, Some(key_b64)) => base64::engine::general_purpose::STANDARD
+                    .decode(key_b64)
+                    .map_err(|_| {
+                        CryptoError::DecryptionError("Invalid embedded key format".to_string())
+                    })?,
+                (None, None) => {
+                    return Err(CryptoError::DecryptionError(
+                        "No decryption key available".to_string(),
+                    ));
+                }
+            };
+            let key: [u8; 32] = bytes.as_slice().try_into().map_err(|_| {
+                CryptoError::DecryptionError("Key must be exactly 32 bytes".to_string())
+            })?;
+            Ok(SecureKey::new(key))
+        }
+    }
+}
+
+/// Unlocks a keyslot file with the first of `identities` it was encrypted to, returning its
+/// data key.
+///
+/// Fails with [`CryptoError::WrongDecryptionMethod`] if the file wasn't encrypted to
+/// recipients, and [`CryptoError::AuthenticationError`] if none of them is one of
+/// `identities`.
+pub fn identity_file_key(
+    file: &XdReader<'_>,
+    identities: &[identity::Identity],
+) -> Result<SecureKey, CryptoError> {
+    let (slots, check) = file.header.keyslots().ok_or_else(|| {
+        CryptoError::WrongDecryptionMethod(
+            "This file was not encrypted to recipients. Use its password or key instead."
+                .to_string(),
+        )
     })?;
+    let mut error = CryptoError::AuthenticationError;
+    for identity in identities {
+        match keyslot::unlock_with_identity(slots, check, identity) {
+            Ok((data_key, _)) => return Ok(data_key),
+            // An unsupported suite explains the failure better than a wrong key
+            Err(CryptoError::AuthenticationError) => {}
+            Err(e) => error = e,
+        }
+    }
+    Err(error)
+}
 
-    // Decrypt and verify authenticity in one operation
-    let decrypted_data = cipher
-        .decrypt(Nonce::from_slice(nonce), ciphertext)
-        .map_err(|_| CryptoError::AuthenticationError)?;
+/// Decrypts a file encrypted to recipients with one of their identities.
+///
+/// # Returns
+/// A tuple containing the decrypted data and the original filename.
+pub fn decrypt_with_identity(
+    encrypted_data: &[u8],
+    identity: &identity::Identity,
+) -> Result<(Vec<u8>, String), CryptoError> {
+    decrypt_with_identities(encrypted_data, std::slice::from_ref(identity))
+}
+
+/// Decrypts a file encrypted to recipients with whichever of `identities` it was encrypted
+/// to, as read from an identities file.
+///
+/// # Returns
+/// A tuple containing the decrypted data and the original filename.
+pub fn decrypt_with_identities(
+    encrypted_data: &[u8],
+    identities: &[identity::Identity],
+) -> Result<(Vec<u8>, String), CryptoError> {
+    let file = XdReader::parse(encrypted_data)?;
+    let key = identity_file_key(&file, identities)?;
+    Ok((
+        decrypt_parsed(&file, &key)?,
+        file.header.filename().to_string(),
+    ))
+}
+
+/// Decrypts a file with a keyslot bound to a hardware token.
+///
+/// `ask` talks to the token, as described for [`keyslot::unlock_with_token`]. Fails with
+/// [`CryptoError::WrongDecryptionMethod`] if the file has no keyslots.
+///
+/// # Returns
+/// A tuple containing the decrypted data and the original filename.
+pub fn decrypt_with_token(
+    encrypted_data: &[u8],
+    ask: impl FnMut(&[u8], &[u8]) -> Option<Zeroizing<Vec<u8>>>,
+) -> Result<(Vec<u8>, String), CryptoError> {
+    decrypt_with_device(encrypted_data, "token", |slots, check| {
+        keyslot::unlock_with_token(slots, check, ask)
+    })
+}
+
+/// Decrypts a file with a keyslot whose key is held by a PKCS#11 device.
+///
+/// `ask` talks to the device, as described for [`keyslot::unlock_with_hsm`]. Fails with
+/// [`CryptoError::WrongDecryptionMethod`] if the file has no keyslots.
+///
+/// # Returns
+/// A tuple containing the decrypted data and the original filename.
+pub fn decrypt_with_hsm(
+    encrypted_data: &[u8],
+    ask: impl FnMut(&str, &[u8]) -> Option<Zeroizing<Vec<u8>>>,
+) -> Result<(Vec<u8>, String), CryptoError> {
+    decrypt_with_device(encrypted_data, "HSM", |slots, check| {
+        keyslot::unlock_with_hsm(slots, check, ask)
+    })
+}
+
+/// Decrypts a file with a keyslot sealed to this machine's TPM.
+///
+/// `ask` talks to the TPM, as described for [`keyslot::unlock_with_tpm`]. Fails with
+/// [`CryptoError::WrongDecryptionMethod`] if the file has no keyslots.
+///
+/// # Returns
+/// A tuple containing the decrypted data and the original filename.
+pub fn decrypt_with_tpm(
+    encrypted_data: &[u8],
+    ask: impl FnMut(&keyslot::TpmObject<'_>) -> Option<Zeroizing<Vec<u8>>>,
+) -> Result<(Vec<u8>, String), CryptoError> {
+    decrypt_with_device(encrypted_data, "TPM", |slots, check| {
+        keyslot::unlock_with_tpm(slots, check, ask)
+    })
+}
+
+/// Decrypts a file with a keyslot whose key is held by a cloud key management service.
+///
+/// `ask` talks to the service, as described for [`keyslot::unlock_with_kms`]. Fails with
+/// [`CryptoError::WrongDecryptionMethod`] if the file has no keyslots.
+///
+/// # Returns
+/// A tuple containing the decrypted data and the original filename.
+pub fn decrypt_with_kms(
+    encrypted_data: &[u8],
+    ask: impl FnMut(&str, &[u8]) -> Option<Zeroizing<Vec<u8>>>,
+) -> Result<(Vec<u8>, String), CryptoError> {
+    decrypt_with_device(encrypted_data, "key management service", |slots, check| {
+        keyslot::unlock_with_kms(slots, check, ask)
+    })
+}
+
+/// Decrypts a file with a password+keyfile keyslot, i.e. with both factors.
+///
+/// Fails with [`CryptoError::WrongDecryptionMethod`] if the file has no keyslots.
+///
+/// # Returns
+/// A tuple containing the decrypted data and the original filename.
+pub async fn decrypt_with_password_and_keyfile(
+    encrypted_data: &[u8],
+    password: String,
+    keyfile: &[u8],
+) -> Result<(Vec<u8>, String), CryptoError> {
+    let password = Zeroizing::new(password);
+    let file = XdReader::parse(encrypted_data)?;
+    let (slots, check) = file.header.keyslots().ok_or_else(|| {
+        CryptoError::WrongDecryptionMethod(
+            "This file has no keyslots, so it doesn't use a keyfile. Use its password or key alone."
+                .to_string(),
+        )
+    })?;
+    let (key, _) =
+        keyslot::unlock_with_password_and_keyfile(slots, check, password.to_string(), keyfile)
+            .await?;
+    Ok((
+        decrypt_parsed(&file, &key)?,
+        file.header.filename().to_string(),
+    ))
+}
+
+/// Decrypts a file protected by quorum keyslots with at least its threshold of keys.
+///
+/// See [`keyslot::unlock_with_quorum`]. Fails with [`CryptoError::WrongDecryptionMethod`]
+/// if the file has no quorum slots.
+///
+/// # Returns
+/// A tuple containing the decrypted data and the original filename.
+pub fn decrypt_with_quorum(
+    encrypted_data: &[u8],
+    keys: &[&[u8]],
+) -> Result<(Vec<u8>, String), CryptoError> {
+    let file = XdReader::parse(encrypted_data)?;
+    let (slots, check) = file.header.keyslots().ok_or_else(|| {
+        CryptoError::WrongDecryptionMethod(
+            "This file has no keyslots, so it has no quorum. Use its password or key instead."
+                .to_string(),
+        )
+    })?;
+    let key = keyslot::unlock_with_quorum(slots, check, keys)?;
+    Ok((
+        decrypt_parsed(&file, &key)?,
+        file.header.filename().to_string(),
+    ))
+}
+
+/// Shared part of [`decrypt_with_token`], [`decrypt_with_hsm`], [`decrypt_with_tpm`] and
+/// [`decrypt_with_kms`].
+fn decrypt_with_device(
+    encrypted_data: &[u8],
+    device: &str,
+    unlock: impl FnOnce(&[keyslot::Keyslot], &str) -> Result<(SecureKey, usize), CryptoError>,
+) -> Result<(Vec<u8>, String), CryptoError> {
+    let file = XdReader::parse(encrypted_data)?;
+    let (slots, check) = file.header.keyslots().ok_or_else(|| {
+        CryptoError::WrongDecryptionMethod(format!(
+            "This file has no keyslots, so no {device} can open it. Use its password or key instead."
+        ))
+    })?;
+    let (key, _) = unlock(slots, check)?;
+    Ok((
+        decrypt_parsed(&file, &key)?,
+        file.header.filename().to_string(),
+    ))
+}
+
+/// Decrypts a parsed `.xd` file with a key obtained from [`file_key`].
+///
+/// Returns the raw plaintext body (compression flag included), exactly as it was passed
+/// to the encryption functions.
+pub fn decrypt_parsed(file: &XdReader<'_>, key: &SecureKey) -> Result<Vec<u8>, CryptoError> {
+    let cipher = Aes256Gcm::new_from_slice(key.as_slice())
+        .map_err(|_| CryptoError::DecryptionError("Failed to create cipher".to_string()))?;
+    open_body(&cipher, file)
+}
+
+/// Streaming counterpart of [`decrypt_parsed`] for files in the chunked layout, with a key
+/// obtained from [`file_key`].
+///
+/// Returns `None` for single-message and delta files, which [`decrypt_parsed`] opens
+/// whole.
+pub fn chunk_reader<'a>(
+    file: &XdReader<'a>,
+    key: &SecureKey,
+) -> Result<Option<ChunkReader<&'a [u8]>>, CryptoError> {
+    let (Some(chunk_size), None) = (file.header.chunk_size(), file.header.delta_id()) else {
+        return Ok(None);
+    };
+    let cipher = Aes256Gcm::new_from_slice(key.as_slice())
+        .map_err(|_| CryptoError::DecryptionError("Failed to create cipher".to_string()))?;
+    ChunkReader::new(
+        file.ciphertext,
+        cipher,
+        file.nonce,
+        file.aad().into_owned(),
+        chunk_size,
+    )
+    .map(Some)
+}
+
+/// Result of authenticating one region of an `.xd` file.
+#[derive(Debug, Clone, Serialize)]
+pub struct RegionCheck {
+    /// Region number, starting at 0
+    pub index: usize,
+    /// Byte offset of the region within the file
+    pub offset: usize,
+    /// Region length in bytes, including the GCM tag
+    pub length: usize,
+    /// Whether the GCM tag verified
+    pub ok: bool,
+}
+
+/// Authenticates every ciphertext region of a file without returning any plaintext.
+///
+/// Chunked files report one region per chunk; single-message files have one region.
+/// The decrypted bytes only live in a scratch buffer that is zeroized before returning.
+pub fn verify_regions(
+    file: &XdReader<'_>,
+    key: &SecureKey,
+) -> Result<Vec<RegionCheck>, CryptoError> {
+    use aes_gcm::aead::AeadInPlace;
+    use zeroize::Zeroize;
+
+    let cipher = Aes256Gcm::new_from_slice(key.as_slice())
+        .map_err(|_| CryptoError::DecryptionError("Failed to create cipher".to_string()))?;
+
+    if let Some(chunk_size) = file.header.chunk_size() {
+        let results = match file.header.delta_id() {
+            Some(delta_id) => delta::check(&cipher, file, delta_id, chunk_size)?,
+            None => chunked::check(&cipher, file, chunk_size)?,
+        };
+        return Ok(results
+            .into_iter()
+            .enumerate()
+            .map(|(index, (offset, length, ok))| RegionCheck {
+                index,
+                offset,
+                length,
+                ok,
+            })
+            .collect());
+    }
+
+    let mut scratch = file.ciphertext.to_vec();
+    let ok = cipher
+        .decrypt_in_place(Nonce::from_slice(file.nonce), b"", &mut scratch)
+        .is_ok();
+    scratch.zeroize();
+
+    Ok(vec![RegionCheck {
+        index: 0,
+        offset: file.ciphertext_offset,
+        length: file.ciphertext.len(),
+        ok,
+    }])
+}
+
+/// One region of a damaged `.xd` file, as recovered by [`salvage_regions`].
+pub struct SalvagedRegion {
+    /// Byte offset of the region within the file
+    pub offset: usize,
+    /// Region length in bytes
+    pub length: usize,
+    /// Decrypted bytes, or `None` if the region did not authenticate
+    pub plaintext: Option<Zeroizing<Vec<u8>>>,
+}
+
+/// Decrypts whatever still authenticates in a damaged (corrupted or truncated) file.
+///
+/// Returns the file's regions in order, covering the whole ciphertext, and whether the end
+/// of the file was authenticated. Chunked and delta files recover chunk by chunk; a
+/// single-message file is either intact or lost as a whole.
+pub fn salvage_regions(
+    file: &XdReader<'_>,
+    key: &SecureKey,
+) -> Result<(Vec<SalvagedRegion>, bool), CryptoError> {
+    let cipher = Aes256Gcm::new_from_slice(key.as_slice())
+        .map_err(|_| CryptoError::DecryptionError("Failed to create cipher".to_string()))?;
+
+    let (regions, complete) = match (file.header.chunk_size(), file.header.delta_id()) {
+        (Some(chunk_size), Some(delta_id)) => {
+            chunked::validate_chunk_size(chunk_size)?;
+            delta::salvage(&cipher, file, delta_id, chunk_size)
+        }
+        (Some(chunk_size), None) => chunked::salvage(&cipher, file, chunk_size)?,
+        (None, _) => {
+            let plain = cipher
+                .decrypt(Nonce::from_slice(file.nonce), file.ciphertext)
+                .ok();
+            let complete = plain.is_some();
+            (vec![(0..file.ciphertext.len(), plain)], complete)
+        }
+    };
+    Ok((
+        regions
+            .into_iter()
+            .map(|(range, plain)| SalvagedRegion {
+                offset: file.ciphertext_offset + range.start,
+                length: range.len(),
+                plaintext: plain.map(Zeroizing::new),
+            })
+            .collect(),
+        complete,
+    ))
+}
+
+/// Default plaintext chunk size for delta-layout files.
+pub const DEFAULT_DELTA_CHUNK_SIZE: u32 = 64 * 1024;
+
+/// Encrypts a new version of `previous` in the delta layout (see [`delta`]).
+///
+/// The new file keeps the previous version's secret (same key, or same password salt and
+/// Argon2 parameters), so unchanged chunks are copied from `previous` instead of being
+/// encrypted again. `key` must be the key that opens `previous` (see [`file_key`]).
+/// Without `chunk_size`, the previous chunk size (or [`DEFAULT_DELTA_CHUNK_SIZE`]) is used.
+/// `cuts` optionally gives content-defined chunk boundaries in `data` (see [`delta::seal`]).
+pub fn encrypt_delta(
+    data: &[u8],
+    filename: &str,
+    previous: &XdReader<'_>,
+    key: &SecureKey,
+    chunk_size: Option<u32>,
+    cuts: Option<&[usize]>,
+) -> Result<(Vec<u8>, delta::DeltaStats), CryptoError> {
+    let chunk_size = chunk_size
+        .or(previous.header.chunk_size())
+        .unwrap_or(DEFAULT_DELTA_CHUNK_SIZE);
+    chunked::validate_chunk_size(chunk_size)
+        .map_err(|_| CryptoError::EncryptionError("Chunk size out of range".to_string()))?;
+    // Delta files are authenticated while indexing; anything else is checked here so a
+    // wrong secret can't silently start a new chain
+    if previous.header.delta_id().is_none() {
+        use zeroize::Zeroize;
+        decrypt_parsed(previous, key)?.zeroize();
+    }
+    let index =
+        delta::PreviousVersion::load(previous, key, previous.header.delta_id(), chunk_size)?;
+
+    let timestamp = encryption_time(filename);
+    let (password_mode, header_json, envelope) = match &previous.header {
+        ParsedHeader::Key(old) => (
+            false,
+            serde_json::to_vec(&XdHeader {
+                filename: filename.to_string(),
+                // Keyslot and master-key files never embed their key
+                key: (old.keyslots.is_none() && old.subkey_salt.is_none())
+                    .then(|| base64::engine::general_purpose::STANDARD.encode(key.as_slice())),
+                version: old.version.max(2),
+                timestamp,
+                chunk_size: Some(chunk_size),
+                delta_id: Some(index.delta_id().to_string()),
+                keyslots: old.keyslots.clone(),
+                key_check: old.key_check.clone(),
+                subkey_salt: old.subkey_salt.clone(),
+                // A new version is no longer deterministic
+                convergent: None,
+            }),
+            old.is_envelope(),
+        ),
+        ParsedHeader::Password(old) => (
+            true,
+            serde_json::to_vec(&XdPasswordHeader {
+                filename: filename.to_string(),
+                salt: old.salt.clone(),
+                kdf: old.kdf.clone(),
+                memory_cost: old.memory_cost,
+                time_cost: old.time_cost,
+                parallelism: old.parallelism,
+                log_n: old.log_n,
+                block_size: old.block_size,
+                iterations: old.iterations,
+                version: CURRENT_VERSION,
+                timestamp,
+                chunk_size: Some(chunk_size),
+                delta_id: Some(index.delta_id().to_string()),
+            }),
+            false,
+        ),
+    };
+    let header_json = header_json
+        .map_err(|_| CryptoError::EncryptionError("Header serialization failed".to_string()))?;
+    let aad = key_header_aad(envelope, &header_json);
+
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let (body, stats) = delta::seal(key, &nonce, &aad, data, &index, cuts)?;
 
-    Ok((decrypted_data, header.filename))
+    let result = XdWriter::new(password_mode, &header_json)
+        .nonce(&nonce)
+        .body(&body)
+        .finish()?;
+    Ok((result, stats))
 }
@@ -0,0 +1,103 @@
+//!
+//! Size-hiding padding of file bodies, applied before encryption.
+//!
+//! A padded body is the [`PADDED_FLAG`] byte, the length of the original body as a
+//! big-endian `u64`, the original body (which starts with its own compression flag) and
+//! zeros up to the padded size. The flag takes the place of the compression flag, so
+//! readers that know it unpad first and then decompress as usual.
+//!
+//! [`Padding::Padme`] rounds the size up to a Padmé length: only the top `⌊log2 E⌋ + 1` bits
+//! of a size `2^E..2^(E+1)` are kept, which leaks `O(log log n)` bits about the size at
+//! under 12% overhead. [`Padding::Bucket`] rounds it up to a multiple of a fixed size.
+//!
+use super::CryptoError;
+use std::ops::Range;
+use zeroize::Zeroizing;
+
+/// Flag byte starting a padded body.
+pub const PADDED_FLAG: u8 = 0x02;
+
+/// Length of the flag byte and the body length that precede the original body.
+pub const PREFIX_LEN: usize = 9;
+
+/// How a body is padded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Padding {
+    /// Round up to the next Padmé length
+    Padme,
+    /// Round up to a multiple of this many bytes (non-zero)
+    Bucket(u64),
+}
+
+impl Padding {
+    /// Size a body of `len` bytes (including the prefix) is padded to.
+    pub fn padded_len(self, len: u64) -> u64 {
+        match self {
+            Padding::Padme => padme(len),
+            Padding::Bucket(size) => len.div_ceil(size.max(1)).saturating_mul(size.max(1)),
+        }
+    }
+}
+
+/// Padmé length for `len`: `len` rounded up so that only its top `⌊log2 E⌋ + 1` bits may
+/// be set, where `E = ⌊log2 len⌋`.
+pub fn padme(len: u64) -> u64 {
+    if len < 2 {
+        return len;
+    }
+    let e = 63 - u64::from(len.leading_zeros());
+    let s = 64 - u64::from(e.leading_zeros());
+    let mask = (1u64 << (e - s)) - 1;
+    len.saturating_add(mask) & !mask
+}
+
+/// Pads `body` as described in the [module documentation](self).
+pub fn pad(body: &[u8], padding: Padding) -> Vec<u8> {
+    let unpadded = (PREFIX_LEN + body.len()) as u64;
+    let total = padding.padded_len(unpadded) as usize;
+    let mut padded = Vec::with_capacity(total);
+    padded.push(PADDED_FLAG);
+    padded.extend_from_slice(&(body.len() as u64).to_be_bytes());
+    padded.extend_from_slice(body);
+    padded.resize(total, 0);
+    padded
+}
+
+/// Whether a decrypted body is padded.
+pub fn is_padded(body: &[u8]) -> bool {
+    body.first() == Some(&PADDED_FLAG)
+}
+
+/// Returns the original body inside a padded one.
+///
+/// Fails with [`CryptoError::FormatError`] if the recorded length doesn't fit or the
+/// padding isn't all zeros.
+pub fn unpad(body: &[u8]) -> Result<&[u8], CryptoError> {
+    let range = body_range(body).ok_or(CryptoError::FormatError)?;
+    if range.end > body.len() || body[range.end..].iter().any(|&b| b != 0) {
+        return Err(CryptoError::FormatError);
+    }
+    Ok(&body[range])
+}
+
+/// Returns the original body of a decrypted body if it is padded, or the body itself.
+/// A padded body is zeroized once the original is copied out of it.
+pub fn strip(body: Vec<u8>) -> Result<Vec<u8>, CryptoError> {
+    if is_padded(&body) {
+        let body = Zeroizing::new(body);
+        Ok(unpad(&body)?.to_vec())
+    } else {
+        Ok(body)
+    }
+}
+
+/// Where the original body lies in a padded body, read from its first bytes. `None` if
+/// `prefix` doesn't start a padded body or is too short to tell.
+pub fn body_range(prefix: &[u8]) -> Option<Range<usize>> {
+    if !is_padded(prefix) || prefix.len() < PREFIX_LEN {
+        return None;
+    }
+    let len = u64::from_be_bytes(prefix[1..PREFIX_LEN].try_into().ok()?);
+    let end = PREFIX_LEN.checked_add(usize::try_from(len).ok()?)?;
+    Some(PREFIX_LEN..end)
+}
@@ -0,0 +1,412 @@
+//!
+//! Paper backups of 32-byte keys, meant to be printed and typed back in by hand.
+//!
+//! The key is followed by 18 bytes of Reed-Solomon parity and the 50 bytes are written as
+//! 10 numbered lines of 8 Crockford base32 characters, each with a 2-character checksum:
+//!
+//! ```text
+//! EncryptX paper key v1
+//! Fingerprint: 4b:b0:6f:8e:4e:3a:77:15
+//!
+//! 01  ABCD EFGH  K7
+//! ```
+//!
+//! Crockford base32 has no `I`, `L`, `O` or `U`, and reads `I`/`L` as `1` and `O` as `0`, so
+//! the usual misreadings decode to what was printed. A line whose checksum fails, or that
+//! is missing, is handed to the decoder as an erasure; the parity fills in up to three such
+//! lines. When more lines fail their checksums, they are read as typed instead, and the
+//! parity corrects up to 9 wrong bytes (a mistyped character touches one or two bytes).
+//!
+use super::{CryptoError, SecureKey, key_fingerprint};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroize;
+
+/// First line of a paper key, naming its format version.
+pub const PAPER_HEADER: &str = "EncryptX paper key v1";
+
+/// Label of the line recording the key's fingerprint.
+const FINGERPRINT_LABEL: &str = "Fingerprint:";
+
+/// Crockford's base32 alphabet.
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Reed-Solomon parity bytes after the key.
+const PARITY: usize = 18;
+
+/// Key bytes plus parity.
+const CODEWORD: usize = 32 + PARITY;
+
+/// Bytes per line: 5 bytes are exactly 8 base32 characters.
+const LINE_BYTES: usize = 5;
+
+/// Number of lines on a paper key.
+pub const LINES: usize = CODEWORD / LINE_BYTES;
+
+/// A key recovered from paper.
+pub struct PaperRecovery {
+    pub key: SecureKey,
+    /// Numbers (from 1) of the lines that were missing or had to be corrected
+    pub repaired_lines: Vec<usize>,
+}
+
+/// Log and antilog tables of GF(2^8) with the polynomial 0x11d, where 2 generates the
+/// multiplicative group (the AES polynomial `shamir` uses has no such generator). The
+/// antilog table is doubled so products need no reduction.
+static GF: ([u8; 512], [u8; 256]) = {
+    let (mut exp, mut log) = ([0u8; 512], [0u8; 256]);
+    let mut x = 1u16;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = x as u8;
+        log[x as usize] = i as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= 0x11d;
+        }
+        i += 1;
+    }
+    while i < 512 {
+        exp[i] = exp[i - 255];
+        i += 1;
+    }
+    (exp, log)
+};
+
+fn gf_mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    GF.0[GF.1[a as usize] as usize + GF.1[b as usize] as usize]
+}
+
+/// `a^-1`; `a` must be non-zero.
+fn gf_inv(a: u8) -> u8 {
+    GF.0[255 - GF.1[a as usize] as usize]
+}
+
+/// `2^power`, for any power.
+fn gf_exp(power: i64) -> u8 {
+    GF.0[power.rem_euclid(255) as usize]
+}
+
+// Polynomials below are coefficient vectors, highest degree first.
+
+fn poly_mul(p: &[u8], q: &[u8]) -> Vec<u8> {
+    let mut product = vec![0u8; p.len() + q.len() - 1];
+    for (i, a) in p.iter().enumerate() {
+        for (j, b) in q.iter().enumerate() {
+            product[i + j] ^= gf_mul(*a, *b);
+        }
+    }
+    product
+}
+
+fn poly_eval(p: &[u8], x: u8) -> u8 {
+    p.iter().fold(0, |acc, c| gf_mul(acc, x) ^ c)
+}
+
+/// Appends `PARITY` Reed-Solomon parity bytes to `data` (generator roots `2^0..2^17`).
+fn rs_encode(data: &[u8]) -> Vec<u8> {
+    let generator = (0..PARITY).fold(vec![1u8], |g, i| poly_mul(&g, &[1, gf_exp(i as i64)]));
+    let mut out = data.to_vec();
+    out.resize(data.len() + PARITY, 0);
+    for i in 0..data.len() {
+        let coef = out[i];
+        if coef != 0 {
+            for (j, g) in generator.iter().enumerate().skip(1) {
+                out[i + j] ^= gf_mul(*g, coef);
+            }
+        }
+    }
+    out[..data.len()].copy_from_slice(data);
+    out
+}
+
+/// Syndromes of `codeword`, with a leading 0 so `synd[i + 1]` is the value at `2^i`.
+fn syndromes(codeword: &[u8]) -> Vec<u8> {
+    let mut synd = vec![0u8; PARITY + 1];
+    for (i, s) in synd.iter_mut().skip(1).enumerate() {
+        *s = poly_eval(codeword, gf_exp(i as i64));
+    }
+    synd
+}
+
+/// Corrects `codeword` in place, given the positions of known erasures, with the
+/// Berlekamp-Massey and Forney algorithms. Corrects `2 * errors + erasures <= PARITY`;
+/// `None` when the damage is beyond that.
+fn rs_correct(codeword: &mut [u8], erasures: &[usize]) -> Option<()> {
+    let n = codeword.len();
+    if erasures.len() > PARITY {
+        return None;
+    }
+    for &p in erasures {
+        codeword[p] = 0;
+    }
+    let synd = syndromes(codeword);
+    if synd.iter().all(|s| *s == 0) {
+        return Some(());
+    }
+
+    // Syndromes with the erasures' contribution removed, to find the remaining errors
+    let mut forney = synd[1..].to_vec();
+    for &p in erasures {
+        let x = gf_exp((n - 1 - p) as i64);
+        for j in 0..forney.len() - 1 {
+            forney[j] = gf_mul(forney[j], x) ^ forney[j + 1];
+        }
+    }
+    let (mut locator, mut old) = (vec![1u8], vec![1u8]);
+    for k in 0..PARITY - erasures.len() {
+        let mut delta = forney[k];
+        for j in 1..locator.len().min(k + 1) {
+            delta ^= gf_mul(locator[locator.len() - 1 - j], forney[k - j]);
+        }
+        old.push(0);
+        if delta != 0 {
+            if old.len() > locator.len() {
+                let scaled: Vec<u8> = old.iter().map(|c| gf_mul(*c, delta)).collect();
+                old = locator.iter().map(|c| gf_mul(*c, gf_inv(delta))).collect();
+                locator = scaled;
+            }
+            let offset = locator.len() - old.len();
+            for (i, c) in old.iter().enumerate() {
+                locator[offset + i] ^= gf_mul(*c, delta);
+            }
+        }
+    }
+    let start = locator.iter().position(|c| *c != 0)?;
+    let locator = &locator[start..];
+    let errors = locator.len() - 1;
+    if 2 * errors + erasures.len() > PARITY {
+        return None;
+    }
+    // Chien search: roots of the locator give the error positions
+    let reversed: Vec<u8> = locator.iter().rev().copied().collect();
+    let found: Vec<usize> = (0..n)
+        .filter(|&i| poly_eval(&reversed, gf_exp(i as i64)) == 0)
+        .map(|i| n - 1 - i)
+        .collect();
+    if found.len() != errors {
+        return None;
+    }
+
+    // Forney: the magnitude of each erasure and error
+    let positions: Vec<usize> = erasures.iter().chain(&found).copied().collect();
+    let xs: Vec<u8> = positions
+        .iter()
+        .map(|p| gf_exp((n - 1 - p) as i64))
+        .collect();
+    let errata_locator = xs.iter().fold(vec![1u8], |l, x| poly_mul(&l, &[*x, 1]));
+    let synd_reversed: Vec<u8> = synd.iter().rev().copied().collect();
+    let product = poly_mul(&synd_reversed, &errata_locator);
+    let evaluator: Vec<u8> = product[product.len().saturating_sub(errata_locator.len())..].to_vec();
+    for (i, (&p, &x)) in positions.iter().zip(&xs).enumerate() {
+        let x_inv = gf_inv(x);
+        let derivative = xs
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .fold(1u8, |acc, (_, xj)| gf_mul(acc, 1 ^ gf_mul(x_inv, *xj)));
+        if derivative == 0 {
+            return None;
+        }
+        let y = gf_mul(x, poly_eval(&evaluator, x_inv));
+        codeword[p] ^= gf_mul(y, gf_inv(derivative));
+    }
+    syndromes(codeword).iter().all(|s| *s == 0).then_some(())
+}
+
+/// The 10-bit checksum of line `number`, as two base32 characters. The line number is
+/// included, so a line typed under the wrong number fails its check.
+fn line_checksum(number: usize, bytes: &[u8]) -> [u8; 2] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"encryptx-paper-v1");
+    hasher.update([number as u8]);
+    hasher.update(bytes);
+    let digest = hasher.finalize();
+    let bits = (u16::from(digest[0]) << 2) | u16::from(digest[1] >> 6);
+    [
+        ALPHABET[(bits >> 5) as usize],
+        ALPHABET[(bits & 31) as usize],
+    ]
+}
+
+/// Encodes 5 bytes as 8 base32 characters.
+fn encode_line(bytes: &[u8]) -> [u8; 8] {
+    let mut value = bytes.iter().fold(0u64, |acc, b| (acc << 8) | u64::from(*b));
+    let mut chars = [0u8; 8];
+    for c in chars.iter_mut().rev() {
+        *c = ALPHABET[(value & 31) as usize];
+        value >>= 5;
+    }
+    chars
+}
+
+/// Decodes one base32 character, forgiving lower case and the look-alikes Crockford
+/// reserves for that.
+fn decode_char(c: char) -> Option<u64> {
+    let c = match c.to_ascii_uppercase() {
+        'O' => '0',
+        'I' | 'L' => '1',
+        c => c,
+    };
+    ALPHABET
+        .iter()
+        .position(|&a| a as char == c)
+        .map(|p| p as u64)
+}
+
+/// Decodes 8 base32 characters into 5 bytes.
+fn decode_line(chars: &[char]) -> Option<[u8; LINE_BYTES]> {
+    let mut value = 0u64;
+    for c in chars {
+        value = (value << 5) | decode_char(*c)?;
+    }
+    let mut bytes = [0u8; LINE_BYTES];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = (value >> (8 * (LINE_BYTES - 1 - i))) as u8;
+    }
+    Some(bytes)
+}
+
+/// Renders `key` as a paper key: the header, the fingerprint and the numbered lines.
+pub fn render(key: &[u8]) -> Result<String, CryptoError> {
+    if key.len() != 32 {
+        return Err(CryptoError::EncryptionError(
+            "Key must be exactly 32 bytes".to_string(),
+        ));
+    }
+    let mut codeword = rs_encode(key);
+    let mut sheet = format!(
+        "{PAPER_HEADER}\n{FINGERPRINT_LABEL} {}\n\n",
+        key_fingerprint(key)
+    );
+    for (i, bytes) in codeword.chunks(LINE_BYTES).enumerate() {
+        let chars = encode_line(bytes);
+        let check = line_checksum(i + 1, bytes);
+        let text = |c: &[u8]| String::from_utf8_lossy(c).into_owned();
+        sheet.push_str(&format!(
+            "{:02}  {} {}  {}\n",
+            i + 1,
+            text(&chars[..4]),
+            text(&chars[4..]),
+            text(&check)
+        ));
+    }
+    codeword.zeroize();
+    Ok(sheet)
+}
+
+/// What was read for one line of the sheet.
+enum Line {
+    /// Not found, or unreadable
+    Missing,
+    /// Readable, but its checksum fails
+    Suspect([u8; LINE_BYTES]),
+    Valid([u8; LINE_BYTES]),
+}
+
+/// Reads the numbered lines of a sheet; anything else (the header, notes) is ignored.
+fn parse_lines(text: &str) -> Vec<Line> {
+    let mut lines: Vec<Line> = (0..LINES).map(|_| Line::Missing).collect();
+    for raw in text.lines() {
+        let mut tokens = raw.split_whitespace();
+        let Some(number) = tokens
+            .next()
+            .and_then(|t| t.trim_end_matches([':', '.']).parse::<usize>().ok())
+            .filter(|n| (1..=LINES).contains(n))
+        else {
+            continue;
+        };
+        let chars: Vec<char> = tokens.flat_map(str::chars).filter(|c| *c != '-').collect();
+        if chars.len() != 10 {
+            continue;
+        }
+        let Some(bytes) = decode_line(&chars[..8]) else {
+            continue;
+        };
+        let check = line_checksum(number, &bytes);
+        let valid = chars[8..]
+            .iter()
+            .zip(check)
+            .all(|(c, expected)| decode_char(*c) == decode_char(expected as char));
+        let slot = &mut lines[number - 1];
+        match (valid, &slot) {
+            (true, _) => *slot = Line::Valid(bytes),
+            (false, Line::Missing) => *slot = Line::Suspect(bytes),
+            // A copy that checks out beats one that doesn't
+            (false, _) => {}
+        }
+    }
+    lines
+}
+
+/// Recovers a key from the text of a paper key, tolerating typos and lost lines.
+///
+/// Fails with [`CryptoError::AuthenticationError`] if the sheet records a fingerprint and
+/// the corrected key doesn't match it.
+pub fn recover(text: &str) -> Result<PaperRecovery, CryptoError> {
+    let lines = parse_lines(text);
+    let mut codeword = [0u8; CODEWORD];
+    let (mut missing, mut suspect) = (Vec::new(), Vec::new());
+    for (i, line) in lines.iter().enumerate() {
+        let positions = i * LINE_BYTES..(i + 1) * LINE_BYTES;
+        match line {
+            Line::Missing => missing.extend(positions),
+            Line::Suspect(bytes) => {
+                codeword[i * LINE_BYTES..(i + 1) * LINE_BYTES].copy_from_slice(bytes);
+                suspect.extend(positions);
+            }
+            Line::Valid(bytes) => {
+                codeword[i * LINE_BYTES..(i + 1) * LINE_BYTES].copy_from_slice(bytes)
+            }
+        }
+    }
+    if missing.len() == CODEWORD {
+        return Err(CryptoError::DecryptionError(
+            "No paper key lines found".to_string(),
+        ));
+    }
+
+    // Lines failing their checksum are erased first; if that's too many, they are kept as
+    // data whose typos the parity corrects as errors
+    let erased: Vec<usize> = missing.iter().chain(&suspect).copied().collect();
+    let mut corrected = [erased, missing]
+        .iter()
+        .find_map(|erasures| {
+            let mut attempt = codeword;
+            rs_correct(&mut attempt, erasures).map(|()| attempt)
+        })
+        .ok_or_else(|| {
+            CryptoError::DecryptionError(
+                "Too many lines of the paper key are missing or damaged to recover it".to_string(),
+            )
+        })?;
+
+    let repaired_lines = (0..LINES)
+        .filter(|&i| {
+            let range = i * LINE_BYTES..(i + 1) * LINE_BYTES;
+            matches!(lines[i], Line::Missing) || corrected[range.clone()] != codeword[range]
+        })
+        .map(|i| i + 1)
+        .collect();
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&corrected[..32]);
+    codeword.zeroize();
+    corrected.zeroize();
+
+    let expected = text.lines().find_map(|line| {
+        line.trim()
+            .strip_prefix(FINGERPRINT_LABEL)
+            .map(|f| f.trim().to_ascii_lowercase())
+    });
+    let matches = expected.is_none_or(|f| f == key_fingerprint(&key));
+    let key = SecureKey::new(key);
+    if !matches {
+        return Err(CryptoError::AuthenticationError);
+    }
+    Ok(PaperRecovery {
+        key,
+        repaired_lines,
+    })
+}
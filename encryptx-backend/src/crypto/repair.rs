@@ -0,0 +1,195 @@
+//!
+//! Header repair for `.xd` files whose JSON header was damaged.
+//!
+//! Single-message bodies don't depend on the header at all, so any header carrying the right
+//! key material opens them. Chunked and delta bodies authenticate the exact header JSON, but
+//! headers are written by serde in a fixed field order, so re-serializing the original field
+//! values reproduces the original bytes. Repair therefore keeps every field it can still
+//! read from the damaged JSON, takes the rest from the caller, and rebuilds the header.
+//! The result must be checked against the ciphertext (see [`verify_regions`]) before it is
+//! trusted.
+//!
+//! [`verify_regions`]: super::verify_regions
+//!
+use super::CryptoError;
+use crate::format::{
+    CURRENT_VERSION, MAX_HEADER_LEN, NONCE_LEN, PASSWORD_MARKER, TAG_LEN, XdHeader,
+    XdPasswordHeader, XdWriter,
+};
+use serde_json::{Map, Value};
+
+/// Fields of key-based headers.
+const KEY_FIELDS: &[&str] = &[
+    "filename",
+    "key",
+    "version",
+    "timestamp",
+    "chunk_size",
+    "delta_id",
+    "keyslots",
+    "key_check",
+    "subkey_salt",
+];
+
+/// Fields of password-based headers.
+const PASSWORD_FIELDS: &[&str] = &[
+    "filename",
+    "salt",
+    "kdf",
+    "memory_cost",
+    "time_cost",
+    "parallelism",
+    "log_n",
+    "block_size",
+    "iterations",
+    "version",
+    "timestamp",
+    "chunk_size",
+    "delta_id",
+];
+
+/// Fields that have no default and must be recovered or supplied.
+const REQUIRED_KEY_FIELDS: &[&str] = &["filename", "timestamp"];
+
+/// Same for password-based headers, which also need the salt.
+const REQUIRED_PASSWORD_FIELDS: &[&str] = &["filename", "salt", "timestamp"];
+
+/// Bytes of framing, nonce and tag around the header.
+const MIN_BODY_LEN: usize = NONCE_LEN + TAG_LEN;
+
+/// A possibly damaged `.xd` file split at its header.
+pub struct Damaged<'a> {
+    pub password_mode: bool,
+    pub header_json: &'a [u8],
+    /// Nonce followed by the ciphertext
+    pub body: &'a [u8],
+}
+
+/// Finds the header of a file whose header JSON (or its length prefix) may be damaged.
+///
+/// `password_mode` decides the mode when the leading marker byte itself is damaged.
+pub fn locate(data: &[u8], password_mode: Option<bool>) -> Result<Damaged<'_>, CryptoError> {
+    let password_mode = match data.first() {
+        Some(&PASSWORD_MARKER) => true,
+        Some(0x00) => false,
+        Some(_) => password_mode.ok_or(CryptoError::FormatError)?,
+        None => return Err(CryptoError::FormatError),
+    };
+    let start = password_mode as usize + 4;
+    if data.len() < start + MIN_BODY_LEN {
+        return Err(CryptoError::FormatError);
+    }
+
+    let stated = u32::from_be_bytes([
+        data[start - 4],
+        data[start - 3],
+        data[start - 2],
+        data[start - 1],
+    ]) as usize;
+    let fits =
+        stated > 0 && stated <= MAX_HEADER_LEN && data.len() - start - MIN_BODY_LEN >= stated;
+    // The header ends at the first top-level '}': nested objects (keyslots) are always
+    // followed by ',' or ']'
+    let scan_end = (data.len() - MIN_BODY_LEN).min(start + MAX_HEADER_LEN);
+    let scanned = data[start..scan_end]
+        .windows(2)
+        .position(|w| w[0] == b'}' && w[1] != b',' && w[1] != b']')
+        .map(|p| p + 1);
+    let len = match (fits, scanned) {
+        (true, _) if data[start + stated - 1] == b'}' => stated,
+        (_, Some(len)) => len,
+        (true, None) => stated,
+        (false, None) => return Err(CryptoError::FormatError),
+    };
+    Ok(Damaged {
+        password_mode,
+        header_json: &data[start..start + len],
+        body: &data[start + len..],
+    })
+}
+
+/// Reads whatever header fields can still be parsed out of damaged header JSON.
+///
+/// Returns the readable fields and the names of fields that are present but unreadable.
+/// Fields whose name itself was damaged are simply absent.
+pub fn recover_fields(
+    header_json: &[u8],
+    password_mode: bool,
+) -> (Map<String, Value>, Vec<String>) {
+    if let Ok(Value::Object(fields)) = serde_json::from_slice(header_json) {
+        return (fields, Vec::new());
+    }
+    let text = String::from_utf8_lossy(header_json);
+    let mut fields = Map::new();
+    let mut unreadable = Vec::new();
+    for &name in field_names(password_mode) {
+        let Some(at) = text.find(&format!("\"{name}\":")) else {
+            continue;
+        };
+        let rest = &text[at + name.len() + 3..];
+        match serde_json::Deserializer::from_str(rest)
+            .into_iter::<Value>()
+            .next()
+        {
+            Some(Ok(value)) => {
+                fields.insert(name.to_string(), value);
+            }
+            _ => unreadable.push(name.to_string()),
+        }
+    }
+    (fields, unreadable)
+}
+
+fn field_names(password_mode: bool) -> &'static [&'static str] {
+    if password_mode {
+        PASSWORD_FIELDS
+    } else {
+        KEY_FIELDS
+    }
+}
+
+/// Required fields missing from `fields`.
+pub fn missing_fields(fields: &Map<String, Value>, password_mode: bool) -> Vec<&'static str> {
+    let required = if password_mode {
+        REQUIRED_PASSWORD_FIELDS
+    } else {
+        REQUIRED_KEY_FIELDS
+    };
+    required
+        .iter()
+        .copied()
+        .filter(|name| !fields.contains_key(*name))
+        .collect()
+}
+
+/// Rebuilds the file around a header made from `fields`.
+///
+/// Missing optional fields take their defaults (the current format version, Argon2id).
+/// Fails with [`CryptoError::FormatError`] if a required field is missing or has the
+/// wrong type.
+pub fn rebuild(
+    damaged: &Damaged<'_>,
+    mut fields: Map<String, Value>,
+) -> Result<Vec<u8>, CryptoError> {
+    let header_json = if damaged.password_mode {
+        fields
+            .entry("version")
+            .or_insert(Value::from(CURRENT_VERSION));
+        fields.entry("kdf").or_insert(Value::from("argon2id"));
+        let header: XdPasswordHeader =
+            serde_json::from_value(Value::Object(fields)).map_err(|_| CryptoError::FormatError)?;
+        serde_json::to_vec(&header)
+    } else {
+        fields.entry("version").or_insert(Value::from(2));
+        let header: XdHeader =
+            serde_json::from_value(Value::Object(fields)).map_err(|_| CryptoError::FormatError)?;
+        serde_json::to_vec(&header)
+    }
+    .map_err(|_| CryptoError::FormatError)?;
+
+    let (nonce, body) = damaged.body.split_at(NONCE_LEN);
+    XdWriter::new(damaged.password_mode, &header_json)
+        .nonce(nonce)
+        .body(body)
+        .finish()
+}
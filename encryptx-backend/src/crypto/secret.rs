@@ -0,0 +1,201 @@
+//!
+//! Heap buffers for secrets, kept out of swap and core dumps where the OS allows.
+//!
+//! A [`SecretBuffer`] owns whole pages that nothing else shares, so locking and unlocking
+//! them never affects other memory. The pages are `mlock`ed (`VirtualLock` on Windows) and
+//! on Linux also excluded from core dumps. When the buffer is dropped its contents are
+//! zeroized before the pages are unlocked and freed.
+//!
+//! Locking is best effort. If the OS refuses, for example because `RLIMIT_MEMLOCK` is used
+//! up, the buffer works unlocked and [`SecretBuffer::is_locked`] reports it.
+//!
+use std::alloc::{self, Layout};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+use zeroize::Zeroize;
+
+/// Fixed-size secret held in locked pages of its own.
+pub struct SecretBuffer {
+    ptr: NonNull<u8>,
+    len: usize,
+    layout: Layout,
+    locked: bool,
+}
+
+// The buffer owns its allocation exclusively, like a `Box<[u8]>`
+unsafe impl Send for SecretBuffer {}
+unsafe impl Sync for SecretBuffer {}
+
+impl SecretBuffer {
+    /// Allocates a zeroed, locked buffer of `len` bytes.
+    pub fn new(len: usize) -> Self {
+        let page = sys::page_size();
+        let size = len.max(1).div_ceil(page) * page;
+        let layout = Layout::from_size_align(size, page).expect("secret buffer too large");
+        // SAFETY: `layout` has a non-zero size
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let Some(ptr) = NonNull::new(ptr) else {
+            alloc::handle_alloc_error(layout);
+        };
+        // SAFETY: `ptr` points to `size` bytes allocated above
+        let locked = unsafe { sys::lock(ptr.as_ptr(), size) };
+        Self {
+            ptr,
+            len,
+            layout,
+            locked,
+        }
+    }
+
+    /// Allocates a locked buffer holding a copy of `secret`.
+    pub fn from_slice(secret: &[u8]) -> Self {
+        let mut buffer = Self::new(secret.len());
+        buffer.as_mut_slice().copy_from_slice(secret);
+        buffer
+    }
+
+    /// The secret bytes.
+    pub fn as_slice(&self) -> &[u8] {
+        // SAFETY: the allocation holds at least `len` initialized bytes
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+
+    /// The secret bytes, for filling the buffer in place.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: as above, and `&mut self` makes the access exclusive
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+
+    /// Length of the secret in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the secret is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether the OS agreed to keep the buffer's pages out of swap.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+}
+
+impl Deref for SecretBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl DerefMut for SecretBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+}
+
+impl Drop for SecretBuffer {
+    fn drop(&mut self) {
+        self.as_mut_slice().zeroize();
+        // SAFETY: the pages were allocated with `layout` in `new` and are freed only here
+        unsafe {
+            if self.locked {
+                sys::unlock(self.ptr.as_ptr(), self.layout.size());
+            }
+            alloc::dealloc(self.ptr.as_ptr(), self.layout);
+        }
+    }
+}
+
+impl zeroize::ZeroizeOnDrop for SecretBuffer {}
+
+impl fmt::Debug for SecretBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretBuffer")
+            .field("len", &self.len)
+            .field("locked", &self.locked)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    /// Size of a memory page.
+    pub fn page_size() -> usize {
+        // SAFETY: sysconf has no preconditions
+        match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+            size if size > 0 => size as usize,
+            _ => 4096,
+        }
+    }
+
+    /// Locks `len` bytes of pages at `ptr` into RAM; returns whether it worked.
+    ///
+    /// # Safety
+    /// `ptr` must be page-aligned and point to `len` allocated bytes.
+    pub unsafe fn lock(ptr: *mut u8, len: usize) -> bool {
+        // SAFETY: guaranteed by the caller
+        unsafe {
+            #[cfg(target_os = "linux")]
+            libc::madvise(ptr.cast(), len, libc::MADV_DONTDUMP);
+            libc::mlock(ptr.cast(), len) == 0
+        }
+    }
+
+    /// Unlocks pages locked by [`lock`].
+    ///
+    /// # Safety
+    /// As for [`lock`].
+    pub unsafe fn unlock(ptr: *mut u8, len: usize) {
+        // SAFETY: guaranteed by the caller
+        unsafe {
+            libc::munlock(ptr.cast(), len);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use windows_sys::Win32::System::Memory::{VirtualLock, VirtualUnlock};
+
+    /// Size of a memory page.
+    pub fn page_size() -> usize {
+        4096
+    }
+
+    /// Locks `len` bytes of pages at `ptr` into the working set; returns whether it worked.
+    ///
+    /// # Safety
+    /// `ptr` must be page-aligned and point to `len` allocated bytes.
+    pub unsafe fn lock(ptr: *mut u8, len: usize) -> bool {
+        // SAFETY: guaranteed by the caller
+        unsafe { VirtualLock(ptr.cast(), len) != 0 }
+    }
+
+    /// Unlocks pages locked by [`lock`].
+    ///
+    /// # Safety
+    /// As for [`lock`].
+    pub unsafe fn unlock(ptr: *mut u8, len: usize) {
+        // SAFETY: guaranteed by the caller
+        unsafe {
+            VirtualUnlock(ptr.cast(), len);
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod sys {
+    pub fn page_size() -> usize {
+        4096
+    }
+
+    pub unsafe fn lock(_ptr: *mut u8, _len: usize) -> bool {
+        false
+    }
+
+    pub unsafe fn unlock(_ptr: *mut u8, _len: usize) {}
+}
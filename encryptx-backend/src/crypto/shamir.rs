@@ -0,0 +1,224 @@
+//!
+//! Shamir secret sharing of 32-byte file keys over GF(2^8).
+//!
+//! Each key byte is the constant term of its own random polynomial of degree
+//! `threshold - 1`; share `i` holds every polynomial evaluated at `x = i`. Any `threshold`
+//! shares recover the key by Lagrange interpolation at `x = 0`, fewer reveal nothing.
+//!
+//! Shares are written as `xdshare1:<threshold>:<index>:<fingerprint>:<base64 value>`. The
+//! fingerprint is the key's [`key_fingerprint`](super::key_fingerprint) without colons, so
+//! combining shares of different keys (or too few shares) is detected instead of silently
+//! yielding a wrong key.
+//!
+use super::{CryptoError, SecureKey, key_fingerprint};
+use aes_gcm::aead::{OsRng, rand_core::RngCore};
+use base64::engine::Engine;
+use std::fmt;
+use std::str::FromStr;
+use zeroize::Zeroize;
+
+/// Prefix identifying a share string and its format version.
+pub const SHARE_PREFIX: &str = "xdshare1";
+
+/// One share of a split key.
+#[derive(Clone)]
+pub struct Share {
+    /// Number of shares needed to recover the key
+    pub threshold: u8,
+    /// Evaluation point, 1..=255
+    pub index: u8,
+    /// Fingerprint of the original key, hex without separators
+    pub fingerprint: String,
+    value: [u8; 32],
+}
+
+impl Drop for Share {
+    fn drop(&mut self) {
+        self.value.zeroize();
+    }
+}
+
+impl fmt::Display for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{SHARE_PREFIX}:{}:{}:{}:{}",
+            self.threshold,
+            self.index,
+            self.fingerprint,
+            base64::engine::general_purpose::STANDARD.encode(self.value)
+        )
+    }
+}
+
+impl FromStr for Share {
+    type Err = CryptoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || CryptoError::DecryptionError("Invalid key share".to_string());
+        let mut parts = s.trim().split(':');
+        if parts.next() != Some(SHARE_PREFIX) {
+            return Err(invalid());
+        }
+        let threshold: u8 = parts
+            .next()
+            .and_then(|p| p.parse().ok())
+            .ok_or_else(invalid)?;
+        let index: u8 = parts
+            .next()
+            .and_then(|p| p.parse().ok())
+            .ok_or_else(invalid)?;
+        let fingerprint = parts.next().ok_or_else(invalid)?.to_string();
+        let mut bytes = base64::engine::general_purpose::STANDARD
+            .decode(parts.next().ok_or_else(invalid)?)
+            .map_err(|_| invalid())?;
+        if parts.next().is_some() || threshold < 2 || index == 0 || bytes.len() != 32 {
+            bytes.zeroize();
+            return Err(invalid());
+        }
+        let mut value = [0u8; 32];
+        value.copy_from_slice(&bytes);
+        bytes.zeroize();
+        Ok(Self {
+            threshold,
+            index,
+            fingerprint,
+            value,
+        })
+    }
+}
+
+/// Multiplication in GF(2^8) with the AES polynomial.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// Multiplicative inverse in GF(2^8) (`a^254`); `a` must be non-zero.
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1u8;
+    let mut base = a;
+    let mut exp = 254u8;
+    while exp != 0 {
+        if exp & 1 != 0 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+fn fingerprint_id(key: &[u8]) -> String {
+    key_fingerprint(key).replace(':', "")
+}
+
+/// Splits `key` into `shares` shares, any `threshold` of which recover it.
+pub fn split(key: &[u8], threshold: u8, shares: u8) -> Result<Vec<Share>, CryptoError> {
+    if key.len() != 32 {
+        return Err(CryptoError::EncryptionError(
+            "Key must be exactly 32 bytes".to_string(),
+        ));
+    }
+    if threshold < 2 || shares < threshold {
+        return Err(CryptoError::EncryptionError(
+            "Threshold must be at least 2 and at most the number of shares".to_string(),
+        ));
+    }
+
+    let fingerprint = fingerprint_id(key);
+    let mut coefficients = vec![0u8; 32 * (threshold as usize - 1)];
+    OsRng.fill_bytes(&mut coefficients);
+    let result = (1..=shares)
+        .map(|x| {
+            let mut value = [0u8; 32];
+            for (i, byte) in value.iter_mut().enumerate() {
+                // Horner's rule over coefficients of degree threshold-1 down to 1
+                let mut acc = 0u8;
+                for degree in (0..threshold as usize - 1).rev() {
+                    acc = gf_mul(acc, x) ^ coefficients[degree * 32 + i];
+                }
+                *byte = gf_mul(acc, x) ^ key[i];
+            }
+            Share {
+                threshold,
+                index: x,
+                fingerprint: fingerprint.clone(),
+                value,
+            }
+        })
+        .collect();
+    coefficients.zeroize();
+    Ok(result)
+}
+
+/// Recovers the key from at least `threshold` shares of the same split.
+///
+/// Fails with [`CryptoError::AuthenticationError`] if the recovered key doesn't match the
+/// fingerprint recorded in the shares.
+pub fn combine(shares: &[Share]) -> Result<SecureKey, CryptoError> {
+    let first = shares
+        .first()
+        .ok_or_else(|| CryptoError::DecryptionError("No key shares given".to_string()))?;
+    if shares
+        .iter()
+        .any(|s| s.threshold != first.threshold || s.fingerprint != first.fingerprint)
+    {
+        return Err(CryptoError::DecryptionError(
+            "Key shares belong to different keys".to_string(),
+        ));
+    }
+    let mut indices: Vec<u8> = shares.iter().map(|s| s.index).collect();
+    indices.sort_unstable();
+    indices.dedup();
+    if indices.len() != shares.len() {
+        return Err(CryptoError::DecryptionError(
+            "The same key share was given twice".to_string(),
+        ));
+    }
+    if shares.len() < first.threshold as usize {
+        return Err(CryptoError::DecryptionError(format!(
+            "{} of {} required key shares given",
+            shares.len(),
+            first.threshold
+        )));
+    }
+
+    // Lagrange interpolation at x = 0 over the first `threshold` shares
+    let used = &shares[..first.threshold as usize];
+    let mut key = [0u8; 32];
+    for (j, share) in used.iter().enumerate() {
+        let mut basis = 1u8;
+        for (m, other) in used.iter().enumerate() {
+            if m != j {
+                // x_m / (x_m - x_j); subtraction is XOR in GF(2^8)
+                basis = gf_mul(
+                    basis,
+                    gf_mul(other.index, gf_inv(other.index ^ share.index)),
+                );
+            }
+        }
+        for (out, byte) in key.iter_mut().zip(&share.value) {
+            *out ^= gf_mul(basis, *byte);
+        }
+    }
+
+    let matches = fingerprint_id(&key) == first.fingerprint;
+    let secure_key = SecureKey::new(key);
+    key.zeroize();
+    if !matches {
+        return Err(CryptoError::AuthenticationError);
+    }
+    Ok(secure_key)
+}
@@ -0,0 +1,274 @@
+//!
+//! Ed25519 signatures over encrypted files.
+//!
+//! A signed file is the `.xd` file followed by a trailer:
+//! `[64-byte signature][32-byte signer public key][8-byte magic]`. The signature covers
+//! everything before the trailer (header, nonce and ciphertext), so it proves who produced
+//! the file on top of the AEAD integrity, and can be checked without the decryption secret.
+//! [`parse_xd`](super::parse_xd) leaves the trailer out, so signed files decrypt like any other.
+//!
+//! Detached signatures ([`DetachedSignature`]) cover a whole file as it is, and live in a
+//! separate `.sig` file, so provenance can be checked by tools that never open the file.
+//!
+//! Text forms: signing keys are `xdsigsec1:<base64>` (in a key file that may contain `#`
+//! comment lines, like identities), verifying keys `xdsigpub1:<base64>` and detached
+//! signatures `xdsig1:<base64>` (the signature followed by the signer's public key).
+//!
+use super::CryptoError;
+use aes_gcm::aead::OsRng;
+use base64::engine::Engine;
+use ed25519_dalek::{Signature, Signer};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+use zeroize::Zeroizing;
+
+/// Prefix of a verifying (public) key string.
+pub const VERIFYING_KEY_PREFIX: &str = "xdsigpub1:";
+
+/// Prefix of a signing (private) key string.
+pub const SIGNING_KEY_PREFIX: &str = "xdsigsec1:";
+
+/// Prefix of a detached signature string.
+pub const SIGNATURE_PREFIX: &str = "xdsig1:";
+
+/// Marks the end of a signed file.
+const MAGIC: &[u8; 8] = b"XDSIG\0v1";
+
+/// Length of the signature trailer.
+pub const TRAILER_LEN: usize = 64 + 32 + MAGIC.len();
+
+/// Domain separation, so a file signature can't be replayed as any other Ed25519 signature.
+const DOMAIN: &[u8] = b"EncryptX file signature v1";
+
+/// A public key that verifies signatures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyingKey {
+    key: ed25519_dalek::VerifyingKey,
+}
+
+impl VerifyingKey {
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; 32] = bytes.try_into().ok()?;
+        ed25519_dalek::VerifyingKey::from_bytes(bytes)
+            .ok()
+            .map(|key| Self { key })
+    }
+
+    /// Short fingerprint of the key, as shown when a signature is verified.
+    pub fn fingerprint(&self) -> String {
+        super::key_fingerprint(self.key.as_bytes())
+    }
+}
+
+impl fmt::Display for VerifyingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{VERIFYING_KEY_PREFIX}{}",
+            base64::engine::general_purpose::STANDARD.encode(self.key.as_bytes())
+        )
+    }
+}
+
+impl FromStr for VerifyingKey {
+    type Err = CryptoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim()
+            .strip_prefix(VERIFYING_KEY_PREFIX)
+            .and_then(|encoded| {
+                base64::engine::general_purpose::STANDARD
+                    .decode(encoded)
+                    .ok()
+            })
+            .and_then(|bytes| Self::from_bytes(&bytes))
+            .ok_or_else(|| CryptoError::SignatureError("Invalid verifying key".to_string()))
+    }
+}
+
+/// A private key that signs files.
+pub struct SigningKey {
+    key: ed25519_dalek::SigningKey,
+}
+
+impl SigningKey {
+    /// Generates a new random signing key.
+    pub fn generate() -> Self {
+        Self {
+            key: ed25519_dalek::SigningKey::generate(&mut OsRng),
+        }
+    }
+
+    /// The verifying key that checks this key's signatures.
+    pub fn verifying_key(&self) -> VerifyingKey {
+        VerifyingKey {
+            key: self.key.verifying_key(),
+        }
+    }
+
+    /// Serializes the key as the contents of a signing key file.
+    pub fn to_file(&self) -> String {
+        let secret = Zeroizing::new(self.key.to_bytes());
+        format!(
+            "# EncryptX signing key\n# verifying key: {}\n{SIGNING_KEY_PREFIX}{}\n",
+            self.verifying_key(),
+            base64::engine::general_purpose::STANDARD.encode(secret.as_slice())
+        )
+    }
+
+    /// Parses the contents of a signing key file (comments and blank lines are skipped).
+    pub fn parse_file(text: &str) -> Result<Self, CryptoError> {
+        let invalid = || CryptoError::SignatureError("Invalid signing key file".to_string());
+        let line = text
+            .lines()
+            .map(str::trim)
+            .find(|l| !l.is_empty() && !l.starts_with('#'))
+            .ok_or_else(invalid)?;
+        let encoded = line.strip_prefix(SIGNING_KEY_PREFIX).ok_or_else(invalid)?;
+        let bytes = Zeroizing::new(
+            base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .map_err(|_| invalid())?,
+        );
+        let secret: &[u8; 32] = bytes.as_slice().try_into().map_err(|_| invalid())?;
+        Ok(Self {
+            key: ed25519_dalek::SigningKey::from_bytes(secret),
+        })
+    }
+}
+
+/// What actually gets signed: the domain and a digest of the content.
+fn message(content: &[u8]) -> Vec<u8> {
+    [DOMAIN, Sha256::digest(content).as_slice()].concat()
+}
+
+/// Splits a file into the signed content and its signature trailer, if it has one.
+pub fn split(data: &[u8]) -> (&[u8], Option<&[u8]>) {
+    if data.len() >= TRAILER_LEN && data.ends_with(MAGIC) {
+        let (content, trailer) = data.split_at(data.len() - TRAILER_LEN);
+        (content, Some(trailer))
+    } else {
+        (data, None)
+    }
+}
+
+/// Signs an encrypted file, replacing any signature it already has.
+pub fn sign(data: &[u8], key: &SigningKey) -> Vec<u8> {
+    let (content, _) = split(data);
+    let signature = key.key.sign(&message(content));
+    let mut signed = Vec::with_capacity(content.len() + TRAILER_LEN);
+    signed.extend_from_slice(content);
+    signed.extend_from_slice(&signature.to_bytes());
+    signed.extend_from_slice(key.key.verifying_key().as_bytes());
+    signed.extend_from_slice(MAGIC);
+    signed
+}
+
+/// The key a signed file claims to be signed with, without verifying the signature.
+pub fn claimed_signer(data: &[u8]) -> Option<VerifyingKey> {
+    split(data)
+        .1
+        .and_then(|trailer| VerifyingKey::from_bytes(&trailer[64..96]))
+}
+
+/// Verifies the signature of a file, returning the signer's key if it is signed.
+///
+/// Unsigned files yield `Ok(None)`; a signature that doesn't match the content fails with
+/// [`CryptoError::SignatureError`].
+pub fn verify(data: &[u8]) -> Result<Option<VerifyingKey>, CryptoError> {
+    let (content, Some(trailer)) = split(data) else {
+        return Ok(None);
+    };
+    let signer = VerifyingKey::from_bytes(&trailer[64..96]).ok_or_else(|| {
+        CryptoError::SignatureError("Invalid signer key in signature".to_string())
+    })?;
+    let signature = Signature::from_slice(&trailer[..64])
+        .map_err(|_| CryptoError::SignatureError("Malformed signature".to_string()))?;
+    signer
+        .key
+        .verify_strict(&message(content), &signature)
+        .map_err(|_| {
+            CryptoError::SignatureError(format!(
+                "signature by {} does not match the file",
+                signer.fingerprint()
+            ))
+        })?;
+    Ok(Some(signer))
+}
+
+/// A signature kept apart from the file it signs.
+pub struct DetachedSignature {
+    signer: VerifyingKey,
+    signature: Signature,
+}
+
+impl DetachedSignature {
+    /// Signs `data` as a whole (including any signature trailer it has).
+    pub fn sign(data: &[u8], key: &SigningKey) -> Self {
+        Self {
+            signer: key.verifying_key(),
+            signature: key.key.sign(&message(data)),
+        }
+    }
+
+    /// The key that made this signature.
+    pub fn signer(&self) -> VerifyingKey {
+        self.signer
+    }
+
+    /// Checks that this signature covers `data` and was made by `expected`.
+    pub fn verify(&self, data: &[u8], expected: &VerifyingKey) -> Result<(), CryptoError> {
+        if self.signer != *expected {
+            return Err(CryptoError::SignatureError(format!(
+                "signed by {}, not by the expected key {}",
+                self.signer.fingerprint(),
+                expected.fingerprint()
+            )));
+        }
+        self.signer
+            .key
+            .verify_strict(&message(data), &self.signature)
+            .map_err(|_| {
+                CryptoError::SignatureError(format!(
+                    "signature by {} does not match the file",
+                    self.signer.fingerprint()
+                ))
+            })
+    }
+
+    /// Serializes the signature as the contents of a `.sig` file.
+    pub fn to_file(&self) -> String {
+        let bytes = [
+            self.signature.to_bytes().as_slice(),
+            self.signer.key.as_bytes(),
+        ]
+        .concat();
+        format!(
+            "# EncryptX signature\n# signer: {}\n{SIGNATURE_PREFIX}{}\n",
+            self.signer.fingerprint(),
+            base64::engine::general_purpose::STANDARD.encode(bytes)
+        )
+    }
+
+    /// Parses the contents of a `.sig` file (comments and blank lines are skipped).
+    pub fn parse_file(text: &str) -> Result<Self, CryptoError> {
+        let invalid = || CryptoError::SignatureError("Invalid signature file".to_string());
+        let encoded = text
+            .lines()
+            .map(str::trim)
+            .find(|l| !l.is_empty() && !l.starts_with('#'))
+            .and_then(|l| l.strip_prefix(SIGNATURE_PREFIX))
+            .ok_or_else(invalid)?;
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|_| invalid())?;
+        if bytes.len() != 96 {
+            return Err(invalid());
+        }
+        Ok(Self {
+            signature: Signature::from_slice(&bytes[..64]).map_err(|_| invalid())?,
+            signer: VerifyingKey::from_bytes(&bytes[64..]).ok_or_else(invalid)?,
+        })
+    }
+}
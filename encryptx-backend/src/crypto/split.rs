@@ -0,0 +1,123 @@
+//!
+//! Split credentials: two independent key parts that are both needed to open a file.
+//!
+//! Each part is 32 random bytes; the file key is HKDF-SHA256 over both, so either part on
+//! its own says nothing about the key. Parts are meant to travel over different channels
+//! (e.g. one by mail, one by chat).
+//!
+//! Parts are written as `xdpart1:<index>:<id>:<base64 value>`. Both parts of a pair share a
+//! random id, so mixing up parts of different pairs is reported instead of silently
+//! deriving a wrong key.
+//!
+use super::{CryptoError, SecureKey};
+use aes_gcm::aead::{OsRng, rand_core::RngCore};
+use base64::engine::Engine;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::fmt;
+use std::str::FromStr;
+use zeroize::Zeroize;
+
+/// Prefix identifying a key part and its format version.
+pub const PART_PREFIX: &str = "xdpart1";
+
+/// HKDF info string binding derived keys to this scheme.
+const INFO: &[u8] = b"encryptx-split-v1";
+
+/// One of the two parts of a split credential.
+pub struct Part {
+    /// 1 or 2
+    pub index: u8,
+    /// Random id shared by both parts of a pair, hex
+    pub id: String,
+    value: [u8; 32],
+}
+
+impl Drop for Part {
+    fn drop(&mut self) {
+        self.value.zeroize();
+    }
+}
+
+impl fmt::Display for Part {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{PART_PREFIX}:{}:{}:{}",
+            self.index,
+            self.id,
+            base64::engine::general_purpose::STANDARD.encode(self.value)
+        )
+    }
+}
+
+impl FromStr for Part {
+    type Err = CryptoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || CryptoError::DecryptionError("Invalid key part".to_string());
+        let mut parts = s.trim().split(':');
+        if parts.next() != Some(PART_PREFIX) {
+            return Err(invalid());
+        }
+        let index: u8 = parts
+            .next()
+            .and_then(|p| p.parse().ok())
+            .ok_or_else(invalid)?;
+        let id = parts.next().ok_or_else(invalid)?.to_string();
+        let mut bytes = base64::engine::general_purpose::STANDARD
+            .decode(parts.next().ok_or_else(invalid)?)
+            .map_err(|_| invalid())?;
+        if parts.next().is_some() || !(1..=2).contains(&index) || bytes.len() != 32 {
+            bytes.zeroize();
+            return Err(invalid());
+        }
+        let mut value = [0u8; 32];
+        value.copy_from_slice(&bytes);
+        bytes.zeroize();
+        Ok(Self { index, id, value })
+    }
+}
+
+/// Generates a fresh pair of parts.
+pub fn generate() -> (Part, Part) {
+    let mut id = [0u8; 8];
+    OsRng.fill_bytes(&mut id);
+    let id: String = id.iter().map(|b| format!("{b:02x}")).collect();
+    let part = |index| {
+        let mut value = [0u8; 32];
+        OsRng.fill_bytes(&mut value);
+        Part {
+            index,
+            id: id.clone(),
+            value,
+        }
+    };
+    (part(1), part(2))
+}
+
+/// Derives the file key from both parts of a pair, given in any order.
+pub fn combine(a: &Part, b: &Part) -> Result<SecureKey, CryptoError> {
+    if a.id != b.id {
+        return Err(CryptoError::DecryptionError(
+            "Key parts belong to different pairs".to_string(),
+        ));
+    }
+    let (first, second) = match (a.index, b.index) {
+        (1, 2) => (a, b),
+        (2, 1) => (b, a),
+        _ => {
+            return Err(CryptoError::DecryptionError(
+                "Both key parts (1 and 2) are required".to_string(),
+            ));
+        }
+    };
+    let mut ikm = [first.value, second.value].concat();
+    let mut key = [0u8; 32];
+    let expanded = Hkdf::<Sha256>::new(Some(first.id.as_bytes()), &ikm).expand(INFO, &mut key);
+    ikm.zeroize();
+    expanded.map_err(|_| CryptoError::KeyDerivationError("HKDF expansion failed".to_string()))?;
+    let secure_key = SecureKey::new(key);
+    key.zeroize();
+    Ok(secure_key)
+}
@@ -0,0 +1,59 @@
+//!
+//! Password strength estimation with zxcvbn.
+//!
+//! Argon2 and scrypt only multiply the cost of each guess; a password found in the first
+//! thousand guesses of a dictionary attack falls in seconds whatever the profile. The
+//! estimate scores a password from 0 to 4 by the guesses an attacker is expected to need
+//! (under 10^3, 10^6, 10^8, 10^10, or more), and explains what makes it weak.
+//!
+
+/// Lowest score accepted as strong: about 10^10 guesses, hours at the `interactive` profile
+/// on a large cracking rig.
+pub const MIN_SCORE: u8 = 3;
+
+/// Estimated strength of a password.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PasswordStrength {
+    /// zxcvbn score, 0 (guessable) to 4 (very unguessable)
+    pub score: u8,
+    /// What makes the password weak, if anything in particular
+    pub warning: Option<String>,
+    /// How to make it stronger
+    pub suggestions: Vec<String>,
+}
+
+impl PasswordStrength {
+    /// Whether the score is below [`MIN_SCORE`].
+    pub fn is_weak(&self) -> bool {
+        self.score < MIN_SCORE
+    }
+
+    /// One-line explanation of the score, with the warning and suggestions if any.
+    pub fn describe(&self) -> String {
+        let mut text = format!("score {}/4", self.score);
+        if let Some(warning) = &self.warning {
+            text.push_str(&format!(": {warning}"));
+        }
+        for suggestion in &self.suggestions {
+            text.push(' ');
+            text.push_str(suggestion);
+        }
+        text
+    }
+}
+
+/// Estimates the strength of `password`. `user_inputs` are words an attacker would try
+/// first, such as the file name, and make passwords built from them score lower.
+pub fn estimate(password: &str, user_inputs: &[&str]) -> PasswordStrength {
+    let entropy = zxcvbn::zxcvbn(password, user_inputs);
+    let feedback = entropy.feedback();
+    PasswordStrength {
+        score: entropy.score().into(),
+        warning: feedback
+            .and_then(|f| f.warning())
+            .map(|warning| warning.to_string()),
+        suggestions: feedback
+            .map(|f| f.suggestions().iter().map(ToString::to_string).collect())
+            .unwrap_or_default(),
+    }
+}
@@ -0,0 +1,439 @@
+//!
+//! The `.xd` container format: framing and headers, without any cryptography.
+//!
+//! A file is laid out as `[0xFF, password mode only][header length, u32 big-endian]
+//! [header JSON][nonce, 12 bytes][body]`, optionally followed by a signature trailer. The
+//! body is AES-256-GCM ciphertext in the layout the header declares: one message, chunks
+//! or delta chunks. [`XdReader`] splits a file into these parts and [`XdWriter`] joins
+//! them, so tools such as indexers and GUIs can inspect or assemble files without keys;
+//! sealing and opening bodies stays in [`crate::crypto`].
+//!
+//! [`detect`] summarizes a file as a [`FormatInfo`], for callers that only need to know
+//! which mode, version and KDF they are dealing with, and [`CURRENT_VERSION`] says which
+//! versions this build understands.
+//!
+use crate::crypto::{CryptoError, PasswordKdf, keyslot, signing};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// First byte of password-mode files.
+pub const PASSWORD_MARKER: u8 = 0xFF;
+
+/// Length of the nonce that follows the header.
+pub const NONCE_LEN: usize = 12;
+
+/// Length of an AES-GCM tag, and so of the shortest possible body.
+pub const TAG_LEN: usize = 16;
+
+/// Largest header JSON accepted, in bytes.
+///
+/// The length prefix comes straight from the file, so it is checked against this before
+/// anything is sliced or parsed. Headers stay far below it: even the maximum number of
+/// hybrid recipient slots takes a few hundred KB.
+pub const MAX_HEADER_LEN: usize = 1024 * 1024;
+
+/// Most keyslots a header may carry. Every password slot costs a key derivation when
+/// unlocking, so without a bound one file could tie up a reader for hours.
+pub const MAX_KEYSLOTS: usize = 256;
+
+/// Newest header `version` this build writes and reads.
+///
+/// Password-mode files and key-mode envelope files are written at this version; plain
+/// key-mode files are still written at version 2. A file with a higher version was made
+/// by a newer release and may rely on features this one lacks (see
+/// [`FormatInfo::is_supported`]).
+pub const CURRENT_VERSION: u8 = 3;
+
+/// Cipher of every `.xd` body.
+pub const CIPHER: &str = "aes-256-gcm";
+
+/// File header for standard key-based encryption.
+/// Contains metadata and optionally embeds the key for convenience.
+#[derive(Serialize, Deserialize)]
+pub struct XdHeader {
+    /// Original file name; empty (and omitted) for anonymous files
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub filename: String,
+    pub key: Option<String>,
+    /// Format version for backward compatibility
+    pub version: u8,
+    /// Unix timestamp when file was encrypted; 0 (and omitted) if not recorded
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub timestamp: u64,
+    /// Plaintext bytes per chunk for the chunked layout; absent for single-message files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_size: Option<u32>,
+    /// Identifier shared by all versions of a delta-layout file (see [`delta`](crate::crypto::delta))
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta_id: Option<String>,
+    /// Credentials that each wrap the data key (see [`keyslot`]); `key` is absent when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyslots: Option<Vec<keyslot::Keyslot>>,
+    /// Commitment to the data key that keyslots must unwrap to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_check: Option<String>,
+    /// Random salt from which this file's key is derived from a master key (see
+    /// [`derive_subkey`](crate::crypto::derive_subkey)); `key` is absent when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subkey_salt: Option<String>,
+    /// Set for convergent files, whose salt and nonce are derived from the plaintext (see
+    /// [`encrypt_convergent`](crate::crypto::encrypt_convergent))
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub convergent: Option<bool>,
+}
+
+/// File header for password-based encryption with Argon2 key derivation.
+/// Stores all parameters needed to reproduce the key derivation process.
+#[derive(Serialize, Deserialize)]
+pub struct XdPasswordHeader {
+    /// Original file name; empty (and omitted) for anonymous files
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub filename: String,
+    /// Argon2 salt encoded in base64
+    pub salt: String,
+    /// Key derivation function used ("argon2id", "scrypt" or "pbkdf2")
+    pub kdf: String,
+    /// Argon2 memory cost in KB - affects both security and performance
+    pub memory_cost: Option<u32>,
+    /// Argon2 time cost (number of iterations)
+    pub time_cost: Option<u32>,
+    /// Argon2 parallelism factor, or scrypt's `p`
+    pub parallelism: Option<u32>,
+    /// scrypt CPU/memory cost as a power of two (`N = 2^log_n`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_n: Option<u8>,
+    /// scrypt block size (`r`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_size: Option<u32>,
+    /// PBKDF2 iterations for backward compatibility with older files
+    pub iterations: Option<u32>,
+    /// Format version
+    pub version: u8,
+    /// Unix timestamp when file was encrypted; 0 (and omitted) if not recorded
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub timestamp: u64,
+    /// Plaintext bytes per chunk for the chunked layout; absent for single-message files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_size: Option<u32>,
+    /// Identifier shared by all versions of a delta-layout file (see [`delta`](crate::crypto::delta))
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta_id: Option<String>,
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+/// Key-mode header `version` of envelope files, whose body is bound to the header without
+/// its keyslots (see [`XdReader::aad`]), so slots can be rewrapped without re-encrypting
+/// the content (see [`rewrap`](crate::crypto::rewrap)).
+pub const ENVELOPE_VERSION: u8 = 3;
+
+impl XdHeader {
+    /// Whether this is an envelope file, i.e. its keyslots can be replaced by [`rewrap`](crate::crypto::rewrap).
+    pub fn is_envelope(&self) -> bool {
+        self.version >= ENVELOPE_VERSION && self.keyslots.is_some()
+    }
+}
+
+/// Header of an `.xd` file, for either encryption mode.
+pub enum ParsedHeader {
+    /// Key-based file
+    Key(XdHeader),
+    /// Password-based file (starts with the 0xFF marker)
+    Password(XdPasswordHeader),
+}
+
+impl ParsedHeader {
+    /// Original filename recorded at encryption time.
+    pub fn filename(&self) -> &str {
+        match self {
+            ParsedHeader::Key(h) => &h.filename,
+            ParsedHeader::Password(h) => &h.filename,
+        }
+    }
+
+    /// Format version recorded in the header.
+    pub fn version(&self) -> u8 {
+        match self {
+            ParsedHeader::Key(h) => h.version,
+            ParsedHeader::Password(h) => h.version,
+        }
+    }
+
+    /// Unix timestamp of encryption.
+    pub fn timestamp(&self) -> u64 {
+        match self {
+            ParsedHeader::Key(h) => h.timestamp,
+            ParsedHeader::Password(h) => h.timestamp,
+        }
+    }
+
+    /// Plaintext chunk size, if the file uses the chunked layout.
+    pub fn chunk_size(&self) -> Option<u32> {
+        match self {
+            ParsedHeader::Key(h) => h.chunk_size,
+            ParsedHeader::Password(h) => h.chunk_size,
+        }
+    }
+
+    /// Keyslots and the data key commitment, if the file is protected by keyslots.
+    pub fn keyslots(&self) -> Option<(&[keyslot::Keyslot], &str)> {
+        match self {
+            ParsedHeader::Key(h) => Some((h.keyslots.as_deref()?, h.key_check.as_deref()?)),
+            ParsedHeader::Password(_) => None,
+        }
+    }
+
+    /// Delta chain identifier, if the file uses the delta layout.
+    pub fn delta_id(&self) -> Option<&str> {
+        match self {
+            ParsedHeader::Key(h) => h.delta_id.as_deref(),
+            ParsedHeader::Password(h) => h.delta_id.as_deref(),
+        }
+    }
+}
+
+/// An `.xd` file split into its parts, without decrypting anything (see [`XdReader::parse`]).
+pub struct XdReader<'a> {
+    pub header: ParsedHeader,
+    /// Raw header JSON as stored in the file
+    pub header_json: &'a [u8],
+    pub nonce: &'a [u8],
+    /// Ciphertext including the 16-byte GCM tag
+    pub ciphertext: &'a [u8],
+    /// Byte offset of the ciphertext within the file
+    pub ciphertext_offset: usize,
+}
+
+impl<'a> XdReader<'a> {
+    /// Associated data that binds a chunked or delta body to the header.
+    ///
+    /// This is the raw header, except for envelope files (see [`XdHeader::is_envelope`]),
+    /// whose keyslots are left out so [`rewrap`](crate::crypto::rewrap) can replace them.
+    pub fn aad(&self) -> Cow<'a, [u8]> {
+        match &self.header {
+            ParsedHeader::Key(h) => key_header_aad(h.is_envelope(), self.header_json),
+            ParsedHeader::Password(_) => Cow::Borrowed(self.header_json),
+        }
+    }
+
+    /// [`detect`] for an already parsed file.
+    pub fn info(&self) -> Result<FormatInfo, CryptoError> {
+        let (mode, kdf) = match &self.header {
+            ParsedHeader::Key(_) => (Mode::Key, None),
+            ParsedHeader::Password(h) => (Mode::Password, Some(PasswordKdf::from_header(h)?)),
+        };
+        Ok(FormatInfo {
+            version: self.header.version(),
+            mode,
+            cipher: CIPHER,
+            kdf,
+        })
+    }
+
+    /// Parses the framing and header of an `.xd` file without decrypting it.
+    ///
+    /// No key or password is needed, so this is safe to use for inspection and validation.
+    /// A signature trailer (see [`signing`]) is not part of the ciphertext and is left out.
+    pub fn parse(data: &'a [u8]) -> Result<Self, CryptoError> {
+        let (data, _) = signing::split(data);
+        let (offset, password_mode) = match data.first() {
+            Some(&PASSWORD_MARKER) => (1, true),
+            Some(_) => (0, false),
+            None => return Err(CryptoError::FormatError),
+        };
+
+        if data.len() < offset + 4 {
+            return Err(CryptoError::FormatError);
+        }
+        let header_len = u32::from_be_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ]) as usize;
+
+        let body_start = offset + 4;
+        // Header, nonce and at least the GCM tag must be present
+        if header_len == 0
+            || header_len > MAX_HEADER_LEN
+            || data.len().saturating_sub(body_start + NONCE_LEN + TAG_LEN) < header_len
+        {
+            return Err(CryptoError::FormatError);
+        }
+
+        let header_json = &data[body_start..body_start + header_len];
+        let header = if password_mode {
+            ParsedHeader::Password(
+                serde_json::from_slice(header_json).map_err(|_| CryptoError::FormatError)?,
+            )
+        } else {
+            ParsedHeader::Key(
+                serde_json::from_slice(header_json).map_err(|_| CryptoError::FormatError)?,
+            )
+        };
+
+        if header
+            .keyslots()
+            .is_some_and(|(slots, _)| slots.len() > MAX_KEYSLOTS)
+        {
+            return Err(CryptoError::FormatError);
+        }
+
+        let nonce_start = body_start + header_len;
+        Ok(XdReader {
+            header,
+            header_json,
+            nonce: &data[nonce_start..nonce_start + NONCE_LEN],
+            ciphertext: &data[nonce_start + NONCE_LEN..],
+            ciphertext_offset: nonce_start + NONCE_LEN,
+        })
+    }
+}
+
+/// Header bytes authenticated by the body of a key-mode file (see [`XdReader::aad`]).
+pub(crate) fn key_header_aad(envelope: bool, header_json: &[u8]) -> Cow<'_, [u8]> {
+    if !envelope {
+        return Cow::Borrowed(header_json);
+    }
+    // Re-serializing through a map sorts the fields, so the result doesn't depend on how
+    // the slots were written
+    match serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(header_json) {
+        Ok(mut fields) => {
+            fields.remove("keyslots");
+            Cow::Owned(serde_json::to_vec(&fields).unwrap_or_default())
+        }
+        Err(_) => Cow::Borrowed(header_json),
+    }
+}
+
+/// Builder that frames a serialized header, a nonce and a sealed body as an `.xd` file.
+///
+/// The header JSON is taken as bytes because chunked and delta bodies authenticate the exact
+/// header, so it has to be serialized before the body is sealed.
+pub struct XdWriter<'a> {
+    password_mode: bool,
+    header_json: &'a [u8],
+    nonce: &'a [u8],
+    body: &'a [u8],
+}
+
+impl<'a> XdWriter<'a> {
+    /// Starts a key-mode file with the given [`XdHeader`] JSON.
+    pub fn key(header_json: &'a [u8]) -> Self {
+        Self::new(false, header_json)
+    }
+
+    /// Starts a password-mode file with the given [`XdPasswordHeader`] JSON.
+    pub fn password(header_json: &'a [u8]) -> Self {
+        Self::new(true, header_json)
+    }
+
+    /// Starts a file in either mode.
+    pub fn new(password_mode: bool, header_json: &'a [u8]) -> Self {
+        Self {
+            password_mode,
+            header_json,
+            nonce: &[],
+            body: &[],
+        }
+    }
+
+    /// Sets the file nonce, which must be [`NONCE_LEN`] bytes.
+    pub fn nonce(mut self, nonce: &'a [u8]) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
+    /// Sets the sealed body, tags included.
+    pub fn body(mut self, body: &'a [u8]) -> Self {
+        self.body = body;
+        self
+    }
+
+    /// Assembles the file. Fails if the nonce has the wrong length, the body is shorter than
+    /// a tag or the header is empty or longer than [`MAX_HEADER_LEN`].
+    pub fn finish(self) -> Result<Vec<u8>, CryptoError> {
+        if self.body.len() < TAG_LEN {
+            return Err(CryptoError::FormatError);
+        }
+        let mut file = self.prefix()?;
+        file.extend_from_slice(self.body);
+        Ok(file)
+    }
+
+    /// Everything that precedes the body, for writers that stream the body after it. Checks
+    /// the header and nonce like [`finish`](Self::finish); the body is not looked at.
+    pub fn prefix(&self) -> Result<Vec<u8>, CryptoError> {
+        if self.header_json.is_empty()
+            || self.header_json.len() > MAX_HEADER_LEN
+            || self.nonce.len() != NONCE_LEN
+        {
+            return Err(CryptoError::FormatError);
+        }
+        let header_len = (self.header_json.len() as u32).to_be_bytes();
+        let mut file = Vec::with_capacity(
+            usize::from(self.password_mode)
+                + 4
+                + self.header_json.len()
+                + NONCE_LEN
+                + self.body.len(),
+        );
+        if self.password_mode {
+            file.push(PASSWORD_MARKER);
+        }
+        file.extend_from_slice(&header_len);
+        file.extend_from_slice(self.header_json);
+        file.extend_from_slice(self.nonce);
+        Ok(file)
+    }
+}
+
+/// Which credential a file is encrypted for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Key-based file, including keyslot, master-key and envelope files
+    Key,
+    /// Password-based file (starts with [`PASSWORD_MARKER`])
+    Password,
+}
+
+impl Mode {
+    /// `"key"` or `"password"`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Mode::Key => "key",
+            Mode::Password => "password",
+        }
+    }
+}
+
+/// What a file's framing and header say about how to open it (see [`detect`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatInfo {
+    /// Header format version
+    pub version: u8,
+    pub mode: Mode,
+    /// Content cipher, always [`CIPHER`]
+    pub cipher: &'static str,
+    /// Key derivation of a password-mode file, with its parameters; `None` for key-mode
+    /// files, including keyslot files whose slots each record their own KDF
+    pub kdf: Option<PasswordKdf>,
+}
+
+impl FormatInfo {
+    /// Whether this build can read the file, i.e. its version is at most
+    /// [`CURRENT_VERSION`].
+    pub fn is_supported(&self) -> bool {
+        self.version <= CURRENT_VERSION
+    }
+}
+
+/// Detects the mode, version, cipher and KDF of an `.xd` file without decrypting it.
+///
+/// Fails with [`CryptoError::FormatError`] if the framing or header doesn't parse, and
+/// with the KDF's error if a password header names an unknown KDF or bad parameters.
+pub fn detect(data: &[u8]) -> Result<FormatInfo, CryptoError> {
+    XdReader::parse(data)?.info()
+}
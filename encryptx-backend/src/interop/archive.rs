@@ -0,0 +1,186 @@
+//! Detection and extraction of password-protected ZIP and 7z archives on decrypt.
+//!
+//! Detection is always available so the CLI can give a helpful message; extraction needs
+//! the `archive-ingest` feature.
+use super::{ArchiveEntry, InteropError};
+use std::path::{Component, Path, PathBuf};
+
+/// Foreign archive formats recognised by their magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    SevenZip,
+}
+
+impl ArchiveKind {
+    /// Human-readable format name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArchiveKind::Zip => "ZIP",
+            ArchiveKind::SevenZip => "7z",
+        }
+    }
+}
+
+/// Identifies a ZIP or 7z archive from its leading bytes.
+pub fn detect(data: &[u8]) -> Option<ArchiveKind> {
+    if data.starts_with(b"PK\x03\x04") {
+        Some(ArchiveKind::Zip)
+    } else if data.starts_with(&[b'7', b'z', 0xBC, 0xAF, 0x27, 0x1C]) {
+        Some(ArchiveKind::SevenZip)
+    } else {
+        None
+    }
+}
+
+/// Decrypts and reads every file from an archive into memory, failing with
+/// [`InteropError::TooLarge`] once the entries together pass `max_size` bytes. The sizes
+/// the archive declares are not trusted; reading simply stops at the limit.
+#[cfg(feature = "archive-ingest")]
+pub fn read_entries(
+    kind: ArchiveKind,
+    data: &[u8],
+    password: &str,
+    max_size: u64,
+) -> Result<Vec<ArchiveEntry>, InteropError> {
+    match kind {
+        ArchiveKind::Zip => read_zip(data, password, max_size),
+        ArchiveKind::SevenZip => read_7z(data, password, max_size),
+    }
+}
+
+/// Reads `content` whole, unless it holds more than the `remaining` bytes left of the limit;
+/// returns `None` then.
+#[cfg(feature = "archive-ingest")]
+fn read_bounded(
+    content: impl std::io::Read,
+    remaining: &mut u64,
+) -> std::io::Result<Option<Vec<u8>>> {
+    use std::io::Read;
+
+    let mut buf = Vec::new();
+    content
+        .take(remaining.saturating_add(1))
+        .read_to_end(&mut buf)?;
+    match remaining.checked_sub(buf.len() as u64) {
+        Some(left) => {
+            *remaining = left;
+            Ok(Some(buf))
+        }
+        None => Ok(None),
+    }
+}
+
+#[cfg(feature = "archive-ingest")]
+fn read_zip(data: &[u8], password: &str, max_size: u64) -> Result<Vec<ArchiveEntry>, InteropError> {
+    let mut remaining = max_size;
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data))
+        .map_err(|e| InteropError::Archive(e.to_string()))?;
+    let mut entries = Vec::new();
+    for i in 0..archive.len() {
+        let file = match archive.by_index_decrypt(i, password.as_bytes()) {
+            Ok(file) => file,
+            Err(zip::result::ZipError::InvalidPassword) => return Err(InteropError::WrongPassword),
+            Err(e) => return Err(InteropError::Archive(e.to_string())),
+        };
+        if file.is_dir() {
+            continue;
+        }
+        let name = file.name().to_string();
+        let buf = read_bounded(file, &mut remaining).map_err(|e| {
+            // Authentication failures in AES entries surface as read errors
+            if e.kind() == std::io::ErrorKind::InvalidData {
+                InteropError::WrongPassword
+            } else {
+                InteropError::Io(e)
+            }
+        })?;
+        let buf = buf.ok_or(InteropError::TooLarge { limit: max_size })?;
+        entries.push(ArchiveEntry { name, data: buf });
+    }
+    Ok(entries)
+}
+
+#[cfg(feature = "archive-ingest")]
+fn read_7z(data: &[u8], password: &str, max_size: u64) -> Result<Vec<ArchiveEntry>, InteropError> {
+    use sevenz_rust::{Error, Password, SevenZReader};
+
+    let map_err = |e: Error| match e {
+        Error::PasswordRequired | Error::MaybeBadPassword(_) => InteropError::WrongPassword,
+        other => InteropError::Archive(other.to_string()),
+    };
+
+    let mut reader = SevenZReader::new(
+        std::io::Cursor::new(data),
+        data.len() as u64,
+        Password::from(password),
+    )
+    .map_err(map_err)?;
+
+    let mut remaining = max_size;
+    let mut too_large = false;
+    let mut entries = Vec::new();
+    reader
+        .for_each_entries(|entry, content| {
+            let Some(buf) = read_bounded(content, &mut remaining)? else {
+                too_large = true;
+                return Ok(false);
+            };
+            if !entry.is_directory() {
+                entries.push(ArchiveEntry {
+                    name: entry.name().to_string(),
+                    data: buf,
+                });
+            }
+            Ok(true)
+        })
+        .map_err(map_err)?;
+    if too_large {
+        return Err(InteropError::TooLarge { limit: max_size });
+    }
+    Ok(entries)
+}
+
+/// Resolves an entry name inside `target`, rejecting absolute paths and `..` components.
+pub fn safe_join(target: &Path, name: &str) -> Result<PathBuf, InteropError> {
+    let relative = Path::new(name);
+    if relative
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(InteropError::Archive(format!(
+            "Refusing to extract unsafe path '{name}'"
+        )));
+    }
+    Ok(target.join(relative))
+}
+
+/// Writes extracted entries below `target`.
+///
+/// All paths are checked first so nothing is written if any entry is unsafe or would
+/// overwrite an existing file without `force`.
+pub fn write_entries(
+    entries: &[ArchiveEntry],
+    target: &Path,
+    force: bool,
+) -> Result<(), InteropError> {
+    let mut paths = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let dest = safe_join(target, &entry.name)?;
+        if !force && dest.exists() {
+            return Err(InteropError::Archive(format!(
+                "'{}' already exists. Use --force to overwrite",
+                dest.display()
+            )));
+        }
+        paths.push(dest);
+    }
+
+    for (entry, dest) in entries.iter().zip(paths) {
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&dest, &entry.data)?;
+    }
+    Ok(())
+}
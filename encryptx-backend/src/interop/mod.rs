@@ -0,0 +1,34 @@
+//!
+//! Interoperability with encrypted formats produced and read by other tools.
+//!
+//! `.xd` stays the native format; these helpers exist for recipients who can't install
+//! EncryptX (or senders who don't use it).
+//!
+pub mod archive;
+pub mod openpgp;
+pub mod zip_aes;
+
+use thiserror::Error;
+
+/// Error types for foreign archive and message formats.
+#[derive(Error, Debug)]
+pub enum InteropError {
+    #[error("Archive error: {0}")]
+    Archive(String),
+    #[error("OpenPGP error: {0}")]
+    OpenPgp(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Wrong password or corrupted archive")]
+    WrongPassword,
+    /// The entries expand past the allowed total; reading stopped there
+    #[error("Archive contents exceed the limit of {limit} bytes")]
+    TooLarge { limit: u64 },
+}
+
+/// A single file stored in (or extracted from) a foreign archive.
+pub struct ArchiveEntry {
+    /// Path inside the archive, using `/` separators
+    pub name: String,
+    pub data: Vec<u8>,
+}
@@ -0,0 +1,145 @@
+//! OpenPGP messages, for exchanging files with GnuPG users.
+//!
+//! Messages are written as SEIPD v1 (AES-256 with a modification detection code), the
+//! encrypted-data packet every gpg release can read, to a password and/or public keys.
+//! Detection is always available so the CLI can give a helpful message; reading and writing
+//! need the `openpgp` feature.
+#[cfg(feature = "openpgp")]
+use super::InteropError;
+#[cfg(feature = "openpgp")]
+use pgp::{
+    composed::{
+        ArmorOptions, Deserializable, Message, MessageBuilder, SignedPublicKey, SignedSecretKey,
+    },
+    crypto::sym::SymmetricKeyAlgorithm,
+    types::{KeyDetails, Password, StringToKey},
+};
+
+/// First line of an ASCII-armored message.
+const ARMOR_HEADER: &[u8] = b"-----BEGIN PGP MESSAGE-----";
+
+/// Packet tags of the session key packets an encrypted message starts with.
+const TAG_PKESK: u8 = 1;
+const TAG_SKESK: u8 = 3;
+
+/// Identifies an encrypted OpenPGP message, armored or binary, from its leading bytes.
+pub fn detect(data: &[u8]) -> bool {
+    if data.trim_ascii_start().starts_with(ARMOR_HEADER) {
+        return true;
+    }
+    let tag = match data.first() {
+        // New packet format: the low six bits are the tag
+        Some(&b) if b & 0xC0 == 0xC0 => b & 0x3F,
+        // Old packet format: bits 5-2 are the tag
+        Some(&b) if b & 0xC0 == 0x80 => (b >> 2) & 0x0F,
+        _ => return false,
+    };
+    tag == TAG_PKESK || tag == TAG_SKESK
+}
+
+/// Reads an OpenPGP certificate (public key), armored or binary.
+#[cfg(feature = "openpgp")]
+pub fn read_public_key(data: &[u8]) -> Result<SignedPublicKey, InteropError> {
+    let (cert, _) = SignedPublicKey::from_reader_single(data)
+        .map_err(|e| InteropError::OpenPgp(e.to_string()))?;
+    cert.verify_bindings()
+        .map_err(|e| InteropError::OpenPgp(format!("invalid key binding: {e}")))?;
+    Ok(cert)
+}
+
+/// Reads an OpenPGP secret key, armored or binary.
+#[cfg(feature = "openpgp")]
+pub fn read_secret_key(data: &[u8]) -> Result<SignedSecretKey, InteropError> {
+    let (key, _) = SignedSecretKey::from_reader_single(data)
+        .map_err(|e| InteropError::OpenPgp(e.to_string()))?;
+    key.verify_bindings()
+        .map_err(|e| InteropError::OpenPgp(format!("invalid key binding: {e}")))?;
+    Ok(key)
+}
+
+/// Encrypts `data` as an OpenPGP message that opens with `password` or with the secret key
+/// of any of `recipients`.
+///
+/// The file name is not stored (RFC 9580 recommends leaving it empty). Each certificate is
+/// encrypted to its first encryption-capable subkey, falling back to the primary key.
+#[cfg(feature = "openpgp")]
+pub fn encrypt(
+    data: &[u8],
+    password: Option<&str>,
+    recipients: &[SignedPublicKey],
+    armor: bool,
+) -> Result<Vec<u8>, InteropError> {
+    let map_err = |e: pgp::errors::Error| InteropError::OpenPgp(e.to_string());
+    let mut rng = rand::thread_rng();
+    let mut builder = MessageBuilder::from_bytes("", data.to_vec())
+        .seipd_v1(&mut rng, SymmetricKeyAlgorithm::AES256);
+    if let Some(password) = password {
+        builder
+            .encrypt_with_password(
+                StringToKey::new_default(&mut rng),
+                &Password::from(password),
+            )
+            .map_err(map_err)?;
+    }
+    for cert in recipients {
+        if let Some(subkey) = cert
+            .public_subkeys
+            .iter()
+            .find(|k| k.algorithm().can_encrypt())
+        {
+            builder.encrypt_to_key(&mut rng, subkey).map_err(map_err)?;
+        } else if cert.algorithm().can_encrypt() {
+            builder.encrypt_to_key(&mut rng, cert).map_err(map_err)?;
+        } else {
+            return Err(InteropError::OpenPgp(format!(
+                "key {} has no encryption subkey",
+                cert.fingerprint()
+            )));
+        }
+    }
+
+    if armor {
+        builder
+            .to_armored_string(&mut rng, ArmorOptions::default())
+            .map(String::into_bytes)
+            .map_err(map_err)
+    } else {
+        builder.to_vec(&mut rng).map_err(map_err)
+    }
+}
+
+/// Decrypts an OpenPGP message with a password, or with a secret key unlocked by
+/// `password` (empty if the key is not protected).
+///
+/// Returns the plaintext and the file name stored in the message (usually empty).
+#[cfg(feature = "openpgp")]
+pub fn decrypt(
+    data: &[u8],
+    password: Option<&str>,
+    secret_key: Option<&SignedSecretKey>,
+) -> Result<(Vec<u8>, String), InteropError> {
+    let (message, _) =
+        Message::from_reader(data).map_err(|e| InteropError::OpenPgp(e.to_string()))?;
+    let password = Password::from(password.unwrap_or_default());
+    let mut message = match secret_key {
+        Some(key) => message.decrypt(&password, key),
+        None => message.decrypt_with_password(&password),
+    }
+    .map_err(|_| InteropError::WrongPassword)?;
+    if message.is_compressed() {
+        message = message
+            .decompress()
+            .map_err(|e| InteropError::OpenPgp(e.to_string()))?;
+    }
+
+    let name = message
+        .literal_data_header()
+        .map(|h| String::from_utf8_lossy(h.file_name()).into_owned())
+        .unwrap_or_default();
+    // The modification detection code is checked when the end of the data is read
+    let plaintext = message.as_data_vec().map_err(|e| match e.kind() {
+        std::io::ErrorKind::InvalidData => InteropError::WrongPassword,
+        _ => InteropError::Io(e),
+    })?;
+    Ok((plaintext, name))
+}
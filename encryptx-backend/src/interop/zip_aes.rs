@@ -0,0 +1,59 @@
+//! AES-256 encrypted ZIP archives (WinZip AE-2), readable by 7-Zip, WinZip and most
+//! desktop archive tools.
+use super::{ArchiveEntry, InteropError};
+use crate::walk::{self, EntryKind, Filter, SymlinkPolicy};
+use std::io::{Cursor, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{AesMode, CompressionMethod, ZipWriter};
+
+/// Builds an AES-256 encrypted ZIP archive containing `entries`.
+///
+/// Entries are deflated and then encrypted individually with the password, as required by
+/// the WinZip AES specification.
+pub fn write_archive(entries: &[ArchiveEntry], password: &str) -> Result<Vec<u8>, InteropError> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .with_aes_encryption(AesMode::Aes256, password);
+
+    for entry in entries {
+        writer
+            .start_file(entry.name.as_str(), options)
+            .map_err(|e| InteropError::Archive(e.to_string()))?;
+        writer.write_all(&entry.data)?;
+    }
+
+    let cursor = writer
+        .finish()
+        .map_err(|e| InteropError::Archive(e.to_string()))?;
+    Ok(cursor.into_inner())
+}
+
+/// Collects every regular file below `dir` that `filter` keeps as archive entries with
+/// relative names.
+///
+/// Links to files and directories are followed with [`SymlinkPolicy::Follow`] and left out
+/// with [`SymlinkPolicy::Skip`]. The entries written here have no way to mark a link, so
+/// [`SymlinkPolicy::Store`] is refused.
+pub fn collect_dir(
+    dir: &Path,
+    symlinks: SymlinkPolicy,
+    filter: &Filter,
+) -> Result<Vec<ArchiveEntry>, InteropError> {
+    if symlinks == SymlinkPolicy::Store {
+        return Err(InteropError::Archive(
+            "ZIP archives can't store symlinks; follow or skip them instead".to_string(),
+        ));
+    }
+    let mut entries = Vec::new();
+    for entry in walk::walk(dir, symlinks, filter)? {
+        if entry.kind == EntryKind::File {
+            entries.push(ArchiveEntry {
+                name: entry.name,
+                data: std::fs::read(&entry.path)?,
+            });
+        }
+    }
+    Ok(entries)
+}
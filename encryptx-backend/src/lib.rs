@@ -1,77 +1,527 @@
+pub mod backup;
+pub mod capabilities;
+pub mod cdc;
 pub mod cli;
+pub mod compression;
+pub mod config;
 pub mod crypto;
+pub mod format;
+pub mod interop;
+pub mod locale;
+pub mod logging;
+pub mod metrics;
+pub mod seekable;
+pub mod share;
+pub mod stego;
+pub mod throttle;
+pub mod walk;
+pub mod watchdog;
 
 pub mod api {
-    use crate::crypto;
+    use crate::compression;
+    use crate::config::DEFAULT_COMPRESSION_LEVEL;
+    use crate::crypto::{self, CryptoError};
+    use crate::format::XdReader;
     use rand::RngCore;
-    use zstd::stream::{decode_all, encode_all};
+    use std::io::{self, Read, Write};
+    use std::ops::Range;
+    use thiserror::Error;
+    use zeroize::Zeroizing;
 
-    /// Encrypts file bytes with password or key, compressing before encryption.
+    /// Largest size [`decode_body`] decompresses a body to, so a small hostile file can't
+    /// expand without bound.
+    pub const DEFAULT_MAX_DECOMPRESSED_SIZE: u64 = 4 << 30;
+
+    /// Errors returned by the library API.
+    #[derive(Error, Debug)]
+    pub enum ApiError {
+        /// Encryption, decryption or key derivation failed; `AuthenticationError` means a
+        /// wrong password or key, or a tampered file
+        #[error(transparent)]
+        Crypto(#[from] CryptoError),
+        #[error("Compression error: {0}")]
+        Compression(std::io::Error),
+        #[error("Decompression error: {0}")]
+        Decompression(std::io::Error),
+        /// The compressed body expands past the allowed size; it was not fully decompressed
+        #[error("Decompressed data exceeds the limit of {limit} bytes")]
+        DecompressedTooLarge { limit: u64 },
+        #[error("Invalid padding in decrypted data")]
+        InvalidPadding,
+        /// The arguments can't be used, such as a key of the wrong length
+        #[error("{0}")]
+        InvalidInput(String),
+        /// Reading the encrypted file failed
+        #[error("I/O error: {0}")]
+        Io(std::io::Error),
+    }
+
+    /// Result of [`encrypt_file`].
+    pub struct EncryptOutcome {
+        /// The encrypted file
+        pub ciphertext: Vec<u8>,
+        /// The key generated when neither a password nor a key was given; the file can't be
+        /// decrypted by the caller's own means without it
+        pub generated_key: Option<crypto::SecureKey>,
+        /// What was encrypted, and how
+        pub metadata: EncryptMetadata,
+    }
+
+    /// Description of an encryption done by [`encrypt_file`].
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct EncryptMetadata {
+        /// Name stored in the header
+        pub filename: String,
+        /// `"password"` or `"key"`
+        pub mode: &'static str,
+        /// Size of the input in bytes
+        pub original_size: usize,
+        /// Size of the compressed body in bytes, including the flag byte
+        pub compressed_size: usize,
+        /// zstd level the body was compressed at; `None` if it was stored uncompressed
+        /// because compression wouldn't have helped
+        pub compression_level: Option<i32>,
+    }
+
+    /// Encrypts file bytes with password or key, compressing before encryption unless
+    /// [`compression::worth_compressing`] says it wouldn't help.
     /// - If password is Some, uses password-based encryption (Argon2id).
     /// - If key is Some, uses key-based encryption (AES-256-GCM, 32 bytes).
-    /// - If both are None, generates a random key and returns it in the error.
-    pub async fn encrypt_file_bytes(
+    /// - If both are None, generates a random key and returns it in
+    ///   [`EncryptOutcome::generated_key`].
+    pub async fn encrypt_file(
         input: &[u8],
         password: Option<&str>,
         key: Option<&[u8]>,
         filename: &str,
-    ) -> Result<Vec<u8>, String> {
-        // Compress input
-        let compressed = encode_all(input, 3).map_err(|e| format!("Compression error: {e}"))?;
-        let mut compressed_with_flag = Vec::with_capacity(1 + compressed.len());
-        compressed_with_flag.push(0x01);
-        compressed_with_flag.extend_from_slice(&compressed);
+    ) -> Result<EncryptOutcome, ApiError> {
+        // Compress input; the compressed plaintext is zeroized once encrypted
+        let level =
+            compression::worth_compressing(filename, input).then_some(DEFAULT_COMPRESSION_LEVEL);
+        let mut compressed_with_flag = Zeroizing::new(Vec::new());
+        encode_into(
+            input,
+            level,
+            compression::Tuning::default(),
+            &mut *compressed_with_flag,
+        )?;
+        let mut metadata = EncryptMetadata {
+            filename: filename.to_string(),
+            mode: "key",
+            original_size: input.len(),
+            compressed_size: compressed_with_flag.len(),
+            compression_level: level,
+        };
 
         if let Some(password) = password {
             // Password-based encryption
-            let mut salt = [0u8; 32];
-            rand::rngs::OsRng
-                .try_fill_bytes(&mut salt)
-                .map_err(|e| format!("Salt gen error: {e}"))?;
-            crypto::encrypt_with_password_async(
+            let ciphertext = crypto::encrypt_with_password_async(
                 &compressed_with_flag,
                 password.to_string(),
                 filename,
-                salt.to_vec(),
+                new_salt()?,
             )
-            .await
-            .map_err(|e| format!("Encryption error: {e}"))
-        } else if let Some(key) = key {
-            // Key-based encryption
-            if key.len() != 32 {
-                return Err("Key must be 32 bytes".to_string());
-            }
-            crypto::encrypt_with_header(&compressed_with_flag, key, filename)
-                .map_err(|e| format!("Encryption error: {e}"))
+            .await?;
+            metadata.mode = "password";
+            Ok(EncryptOutcome {
+                ciphertext,
+                generated_key: None,
+                metadata,
+            })
+        } else {
+            // Key-based encryption, with a fresh key unless one was given
+            let generated_key = key.is_none().then(crypto::keyslot::generate_data_key);
+            let key = checked_key(key, &generated_key)?;
+            let ciphertext = crypto::encrypt_with_header(&compressed_with_flag, key, filename)?;
+            Ok(EncryptOutcome {
+                ciphertext,
+                generated_key,
+                metadata,
+            })
+        }
+    }
+
+    /// [`encrypt_file`] in the chunked layout, with `chunk_size` plaintext bytes per chunk.
+    ///
+    /// The input is compressed and encrypted as one stream, so apart from the output only the
+    /// zstd window and one chunk are held in memory, however large the input is.
+    pub async fn encrypt_file_chunked(
+        input: &[u8],
+        password: Option<&str>,
+        key: Option<&[u8]>,
+        filename: &str,
+        chunk_size: u32,
+    ) -> Result<EncryptOutcome, ApiError> {
+        let (writer, generated_key, mode) = if let Some(password) = password {
+            let writer = crypto::password_file_writer(
+                Vec::new(),
+                password.to_string(),
+                filename,
+                new_salt()?,
+                crypto::PasswordKdf::default(),
+                chunk_size,
+            )
+            .await?;
+            (writer, None, "password")
         } else {
-            Err("Must provide password or key".to_string())
+            let generated_key = key.is_none().then(crypto::keyslot::generate_data_key);
+            let key = checked_key(key, &generated_key)?;
+            let writer = crypto::key_file_writer(Vec::new(), key, filename, chunk_size)?;
+            (writer, generated_key, "key")
+        };
+        let level =
+            compression::worth_compressing(filename, input).then_some(DEFAULT_COMPRESSION_LEVEL);
+        let writer = encode_into(input, level, compression::Tuning::default(), writer)?;
+        let metadata = EncryptMetadata {
+            filename: filename.to_string(),
+            mode,
+            original_size: input.len(),
+            compressed_size: writer.written() as usize,
+            compression_level: level,
+        };
+        Ok(EncryptOutcome {
+            ciphertext: writer.finish()?,
+            generated_key,
+            metadata,
+        })
+    }
+
+    /// A random Argon2 salt for a new password file.
+    fn new_salt() -> Result<Vec<u8>, ApiError> {
+        let mut salt = [0u8; 32];
+        rand::rngs::OsRng
+            .try_fill_bytes(&mut salt)
+            .map_err(|e| CryptoError::EncryptionError(format!("Salt gen error: {e}")))?;
+        Ok(salt.to_vec())
+    }
+
+    /// The generated key if there is one, else the given key, which must be 32 bytes.
+    fn checked_key<'a>(
+        key: Option<&'a [u8]>,
+        generated_key: &'a Option<crypto::SecureKey>,
+    ) -> Result<&'a [u8], ApiError> {
+        let key = match generated_key {
+            Some(generated) => generated.as_slice(),
+            None => key.unwrap_or_default(),
+        };
+        if key.len() != 32 {
+            return Err(ApiError::InvalidInput("Key must be 32 bytes".to_string()));
         }
+        Ok(key)
+    }
+
+    /// Compresses `input` at zstd `level` into a body that starts with the `0x01`
+    /// compression flag, ready to be encrypted. The body is zeroized when dropped.
+    pub fn compress_body(input: &[u8], level: i32) -> Result<Zeroizing<Vec<u8>>, ApiError> {
+        let mut body = Zeroizing::new(Vec::new());
+        compress_into(input, level, &mut *body)?;
+        Ok(body)
     }
 
-    /// Decrypts file bytes with password or key, decompressing after decryption.
+    /// Streams the `0x01` compression flag and `input` compressed at zstd `level` into
+    /// `out`, and returns `out`. Only the encoder's window is buffered. The level is recorded
+    /// (see [`compression`]), and the frame records the input size so readers can refuse
+    /// oversized bodies up front.
+    pub fn compress_into<W: Write>(input: &[u8], level: i32, out: W) -> Result<W, ApiError> {
+        compress_into_tuned(input, level, compression::Tuning::default(), out)
+    }
+
+    /// [`compress_into`] with zstd workers and long-distance matching as `tuning` says.
+    pub fn compress_into_tuned<W: Write>(
+        input: &[u8],
+        level: i32,
+        tuning: compression::Tuning,
+        mut out: W,
+    ) -> Result<W, ApiError> {
+        let compressing = |e| stream_error(e, ApiError::Compression);
+        out.write_all(&[0x01]).map_err(compressing)?;
+        out.write_all(&compression::level_frame(level))
+            .map_err(compressing)?;
+        let mut encoder =
+            zstd::stream::write::Encoder::new(out, level).map_err(ApiError::Compression)?;
+        tuning
+            .apply(&mut encoder, input.len())
+            .map_err(ApiError::Compression)?;
+        encoder
+            .set_pledged_src_size(Some(input.len() as u64))
+            .map_err(ApiError::Compression)?;
+        encoder.write_all(input).map_err(compressing)?;
+        encoder.finish().map_err(compressing)
+    }
+
+    /// Streams `input` into `out` as a body: compressed at `level` with
+    /// [`compress_into_tuned`], or with no level stored as is behind the `0x00` flag.
+    /// Returns `out`.
+    pub fn encode_into<W: Write>(
+        input: &[u8],
+        level: Option<i32>,
+        tuning: compression::Tuning,
+        mut out: W,
+    ) -> Result<W, ApiError> {
+        if let Some(level) = level {
+            return compress_into_tuned(input, level, tuning, out);
+        }
+        let storing = |e| stream_error(e, ApiError::Compression);
+        out.write_all(&[0x00]).map_err(storing)?;
+        out.write_all(input).map_err(storing)?;
+        Ok(out)
+    }
+
+    /// Maps an I/O error from an encrypting writer or decrypting reader back to the
+    /// [`CryptoError`] it carries, and any other with `other`.
+    pub(crate) fn stream_error(error: io::Error, other: fn(io::Error) -> ApiError) -> ApiError {
+        match error.downcast::<CryptoError>() {
+            Ok(e) => ApiError::Crypto(e),
+            Err(e) => other(e),
+        }
+    }
+
+    /// Encrypts file bytes with password or key, compressing before encryption.
+    /// - If password is Some, uses password-based encryption (Argon2id).
+    /// - If key is Some, uses key-based encryption (AES-256-GCM, 32 bytes).
+    /// - If both are None, fails; [`encrypt_file`] generates a key instead.
+    #[deprecated(note = "use `encrypt_file`, which also returns generated keys and metadata")]
+    pub async fn encrypt_file_bytes(
+        input: &[u8],
+        password: Option<&str>,
+        key: Option<&[u8]>,
+        filename: &str,
+    ) -> Result<Vec<u8>, ApiError> {
+        if password.is_none() && key.is_none() {
+            return Err(ApiError::InvalidInput(
+                "Must provide password or key".to_string(),
+            ));
+        }
+        encrypt_file(input, password, key, filename)
+            .await
+            .map(|outcome| outcome.ciphertext)
+    }
+
+    /// Provenance of a file decrypted by [`decrypt_file_bytes`], from its header and body.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct DecryptMetadata {
+        /// Original file name; empty for anonymous files
+        pub filename: String,
+        /// Header format version
+        pub version: u8,
+        /// Unix time of encryption, if recorded
+        pub timestamp: Option<u64>,
+        /// Content cipher
+        pub cipher: &'static str,
+        /// Key derivation of a password-mode file, with its parameters; `None` for key-mode
+        /// files, including keyslot files whose slots each record their own KDF
+        pub kdf: Option<crypto::PasswordKdf>,
+        /// Plaintext bytes per chunk, for chunked files
+        pub chunk_size: Option<u32>,
+        /// `"zstd"` if the body was compressed
+        pub compression: Option<&'static str>,
+        /// zstd level the body was compressed at, if recorded
+        pub compression_level: Option<i32>,
+        /// Whether the body was padded to hide its size
+        pub padded: bool,
+    }
+
+    /// Decrypts file bytes with password or key, removing padding and decompressing after
+    /// decryption. Returns the plaintext and what the file says about how it was made.
     /// - If password is Some, uses password-based decryption.
     /// - If key is Some, uses key-based decryption.
+    ///
+    /// Decompression stops at [`DEFAULT_MAX_DECOMPRESSED_SIZE`].
     pub async fn decrypt_file_bytes(
         input: &[u8],
         password: Option<&str>,
         key: Option<&[u8]>,
-    ) -> Result<(Vec<u8>, String), String> {
-        let (decrypted, filename) = if let Some(password) = password {
-            crypto::decrypt_with_password_async(input, password.to_string())
-                .await
-                .map_err(|e| format!("Decryption error: {e}"))?
-        } else {
-            let key_ref = key;
-            crypto::decrypt_with_header(input, key_ref)
-                .map_err(|e| format!("Decryption error: {e}"))?
+    ) -> Result<(Vec<u8>, DecryptMetadata), ApiError> {
+        decrypt_file_with_limit(input, password, key, DEFAULT_MAX_DECOMPRESSED_SIZE).await
+    }
+
+    /// [`decrypt_file_bytes`], failing with [`ApiError::DecompressedTooLarge`] once the
+    /// plaintext passes `max_size` bytes.
+    ///
+    /// Chunked files are decrypted and decompressed as one stream, so the compressed
+    /// plaintext is never held in full.
+    pub async fn decrypt_file_with_limit(
+        input: &[u8],
+        password: Option<&str>,
+        key: Option<&[u8]>,
+        max_size: u64,
+    ) -> Result<(Vec<u8>, DecryptMetadata), ApiError> {
+        let file = XdReader::parse(input)?;
+        let info = file.info()?;
+        let file_key = crypto::file_key(&file, password.map(str::to_string), key).await?;
+        let (mut plaintext, encoding) = match crypto::chunk_reader(&file, &file_key)? {
+            Some(reader) => decode_stream(reader, max_size)?,
+            None => decode(crypto::decrypt_parsed(&file, &file_key)?, max_size)?,
         };
-        // Decompress if flagged
-        if decrypted.first() == Some(&0x01) {
-            let decompressed =
-                decode_all(&decrypted[1..]).map_err(|e| format!("Decompression error: {e}"))?;
-            Ok((decompressed, filename))
+        let metadata = DecryptMetadata {
+            filename: file.header.filename().to_string(),
+            version: info.version,
+            timestamp: Some(file.header.timestamp()).filter(|&t| t != 0),
+            cipher: info.cipher,
+            kdf: info.kdf,
+            chunk_size: file.header.chunk_size(),
+            compression: encoding.compressed.then_some("zstd"),
+            compression_level: encoding.level,
+            padded: encoding.padded,
+        };
+        Ok((std::mem::take(&mut *plaintext), metadata))
+    }
+
+    /// Removes padding from a decrypted body and decompresses it if its flag byte says it
+    /// is compressed, or drops the `0x00` flag of a stored one; other bodies are returned as
+    /// is. Intermediate copies are zeroized.
+    /// Decompression stops at [`DEFAULT_MAX_DECOMPRESSED_SIZE`].
+    pub fn decode_body(decrypted: Vec<u8>) -> Result<Zeroizing<Vec<u8>>, ApiError> {
+        decode_body_with_limit(decrypted, DEFAULT_MAX_DECOMPRESSED_SIZE)
+    }
+
+    /// [`decode_body`], failing with [`ApiError::DecompressedTooLarge`] once the output
+    /// passes `max_size` bytes.
+    pub fn decode_body_with_limit(
+        decrypted: Vec<u8>,
+        max_size: u64,
+    ) -> Result<Zeroizing<Vec<u8>>, ApiError> {
+        decode(decrypted, max_size).map(|(body, _)| body)
+    }
+
+    /// How a decrypted body was encoded before encryption.
+    struct BodyEncoding {
+        padded: bool,
+        compressed: bool,
+        level: Option<i32>,
+    }
+
+    /// [`decode_body`], also reporting what it undid.
+    fn decode(
+        decrypted: Vec<u8>,
+        max_size: u64,
+    ) -> Result<(Zeroizing<Vec<u8>>, BodyEncoding), ApiError> {
+        let padded = crypto::padding::is_padded(&decrypted);
+        let body = Zeroizing::new(
+            crypto::padding::strip(decrypted).map_err(|_| ApiError::InvalidPadding)?,
+        );
+        let compressed = body.first() == Some(&0x01);
+        let level = compression::recorded_level(&body);
+        let body = if compressed {
+            let start = compression::stream_start(&body[1..]);
+            let stated = zstd::zstd_safe::get_frame_content_size(start.frames).unwrap_or(None);
+            let decoder = zstd::stream::read::Decoder::with_dictionary(
+                start.frames,
+                start.dictionary.unwrap_or_default(),
+            )
+            .map_err(ApiError::Decompression)?;
+            decompress(decoder, stated, max_size)?
+        } else if body.first() == Some(&0x00) {
+            // Stored uncompressed: only the flag goes
+            let mut body = body;
+            body.remove(0);
+            body
         } else {
-            Ok((decrypted, filename))
+            body
+        };
+        let encoding = BodyEncoding {
+            padded,
+            compressed,
+            level,
+        };
+        Ok((body, encoding))
+    }
+
+    /// [`decode`] for a body read from a stream. Compressed bodies are decompressed as they
+    /// are read; other bodies are read in full first.
+    fn decode_stream(
+        mut body: impl Read,
+        max_size: u64,
+    ) -> Result<(Zeroizing<Vec<u8>>, BodyEncoding), ApiError> {
+        let reading = |e| stream_error(e, ApiError::Decompression);
+        let mut flag = [0u8; 1];
+        body.read_exact(&mut flag).map_err(reading)?;
+        if flag[0] != 0x01 {
+            let mut whole = Zeroizing::new(flag.to_vec());
+            body.read_to_end(&mut whole).map_err(reading)?;
+            return decode(std::mem::take(&mut *whole), max_size);
+        }
+        // The level frame, if any, comes first; the decoder would skip it anyway
+        let mut start = Vec::with_capacity(compression::LEVEL_FRAME_LEN);
+        body.by_ref()
+            .take(compression::LEVEL_FRAME_LEN as u64)
+            .read_to_end(&mut start)
+            .map_err(reading)?;
+        let level = compression::level_in_stream(&start);
+        // Then the dictionary, if the body was compressed with one
+        let mut dictionary = Zeroizing::new(Vec::new());
+        if level.is_some() {
+            start.clear();
+            body.by_ref()
+                .take(compression::SKIPPABLE_HEADER_LEN as u64)
+                .read_to_end(&mut start)
+                .map_err(reading)?;
+            if let Some(len) = compression::dictionary_len(&start) {
+                if len > compression::MAX_DICTIONARY_LEN {
+                    return Err(ApiError::Decompression(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "dictionary exceeds the size limit",
+                    )));
+                }
+                dictionary.resize(len, 0);
+                body.read_exact(&mut dictionary).map_err(reading)?;
+                start.clear();
+            }
         }
+        let decoder = zstd::stream::read::Decoder::with_dictionary(
+            io::BufReader::new(start.chain(body)),
+            &dictionary,
+        )
+        .map_err(ApiError::Decompression)?;
+        let body = decompress(decoder, None, max_size)?;
+        let encoding = BodyEncoding {
+            padded: false,
+            compressed: true,
+            level,
+        };
+        Ok((body, encoding))
+    }
+
+    /// Reads decompressed output, giving up as soon as it passes `max_size`. `stated` is
+    /// the size the first zstd frame declares, if any; a larger one is refused before
+    /// decoding anything.
+    fn decompress(
+        decoder: impl Read,
+        stated: Option<u64>,
+        max_size: u64,
+    ) -> Result<Zeroizing<Vec<u8>>, ApiError> {
+        let too_large = ApiError::DecompressedTooLarge { limit: max_size };
+        if stated.is_some_and(|size| size > max_size) {
+            return Err(too_large);
+        }
+        let mut out = Zeroizing::new(Vec::new());
+        decoder
+            .take(max_size.saturating_add(1))
+            .read_to_end(&mut out)
+            .map_err(|e| stream_error(e, ApiError::Decompression))?;
+        if out.len() as u64 > max_size {
+            return Err(too_large);
+        }
+        Ok(out)
+    }
+
+    /// Opens a chunked file for random access with password or key: the returned reader
+    /// decrypts only the chunks a read or seek lands in. The file's body must be stored
+    /// uncompressed (see [`crate::seekable`]).
+    pub async fn open_seekable<R>(
+        input: R,
+        password: Option<&str>,
+        key: Option<&[u8]>,
+    ) -> Result<crate::seekable::SeekableReader<R>, ApiError>
+    where
+        R: tokio::io::AsyncRead + tokio::io::AsyncSeek + Unpin,
+    {
+        crate::seekable::open(input, password, key).await
+    }
+
+    /// Returns the byte ranges of an encrypted file that can be uploaded as separate
+    /// multipart parts (one per chunk for chunked files, the first range including the header).
+    pub fn part_boundaries(encrypted: &[u8]) -> Result<Vec<Range<usize>>, ApiError> {
+        Ok(crypto::chunked::part_boundaries(encrypted)?)
     }
 }
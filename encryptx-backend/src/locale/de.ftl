@@ -0,0 +1,165 @@
+# Deutsche Meldungen der encryptx-Kommandozeile. Fehlende Meldungen erscheinen auf Englisch.
+
+## Fehler
+
+error = Fehler: { $message }
+error-io = Dateioperation fehlgeschlagen: { $detail }
+error-crypto = Kryptografische Operation fehlgeschlagen: { $detail }
+error-invalid-input = Ungültige Eingabe: { $detail }
+error-authentication = Authentifizierung fehlgeschlagen: { $detail }
+error-format = Ungültiges Dateiformat: { $detail }
+locale-unknown = Keine Meldungen für die Sprache '{ $locale }' (verfügbar: { $available })
+password-too-weak = Das Passwort ist zu schwach ({ $reason })
+password-weak = ⚠️  Schwaches Passwort ({ $reason }); --enforce-strong-password lehnt solche ab
+
+## Eingabeaufforderungen
+
+prompt-text-encrypt = Den zu verschlüsselnden Text eingeben, dann Strg-D drücken:
+prompt-text-decrypt = Die Nachricht einfügen, dann Strg-D drücken:
+prompt-paper-key = Die nummerierten Zeilen des Papierschlüssels eingeben (Reihenfolge beliebig, Tippfehler sind in Ordnung), dann Strg-D drücken:
+prompt-keystore-passphrase = 🔑 Passphrase für den Schlüsselspeicher '{ $path }':
+prompt-keystore-repeat = 🔑 Passphrase wiederholen:
+
+## Verschlüsselung
+
+collecting-dir = 📦 Sammle Verzeichnis '{ $dir }'...
+packing-dir = 📦 Packe Verzeichnis '{ $dir }'...
+encrypting-file = 🔐 Verschlüssele Datei '{ $file }'...
+encrypting-zip = 🔐 Verschlüssele '{ $file }' als AES-256-ZIP...
+encrypting-pgp = 🔐 Verschlüssele '{ $file }' als OpenPGP-Nachricht...
+zip-written = ✅ Verschlüsseltes ZIP nach '{ $output }' geschrieben
+pgp-written = ✅ OpenPGP-Nachricht nach '{ $output }' geschrieben
+encrypted-written = ✅ Verschlüsselte Datei nach '{ $output }' geschrieben
+encrypted-in-place = ✅ '{ $output }' an Ort und Stelle verschlüsselt
+encrypted-volumes = ✅ Verschlüsselte Datei als { $count } Teil(e) geschrieben: '{ $first }' bis '{ $last }'
+readme-written = 📝 Anleitung für den Empfänger nach '{ $path }' geschrieben
+qr-written = 📱 QR-Code nach '{ $path }' geschrieben
+original-size = 📊 Originalgröße: { $bytes } Bytes
+encrypted-size = 📊 Verschlüsselte Größe: { $bytes } Bytes
+s3-chunk-too-small = ⚠️  Blöcke unter 5 MiB können nicht als einzelne S3-Multipart-Teile hochgeladen werden
+stored-uncompressed = 🗜️  '{ $file }' lässt sich nicht komprimieren; wird unkomprimiert gespeichert
+dictionary-trained = 📚 { $bytes }-Byte-Wörterbuch auf { $files } Dateien trainiert
+dictionary-too-little-data = 📚 Zu wenige Daten für ein Wörterbuch; komprimiere ohne
+dictionary-saves = 📚 Das Wörterbuch spart { $bytes } Bytes
+dictionary-not-worth-it = 📚 Das Wörterbuch lohnt sich nicht; komprimiere ohne
+delta-reused = ♻️  { $reused } von { $total } Blöcken ({ $bytes } Bytes) aus '{ $previous }' wiederverwendet
+key-part-written = 🔑 Schlüsselteil { $index } nach '{ $path }' geschrieben
+key-parts-hint = 💡 Die beiden Schlüsselteile über verschiedene Kanäle senden; zum Entschlüsseln werden beide gebraucht.
+quorum-hint = 👥 Zum Entschlüsseln werden { $threshold } der { $count } Quorum-Schlüssel gebraucht.
+wrapped-pkcs11 = 🔐 Dateischlüssel mit PKCS#11-Schlüssel { $key } verpackt
+sealed-tpm = 🔐 Dateischlüssel an das TPM dieses Rechners gebunden
+sealed-tpm-pcrs = 🔐 Dateischlüssel an das TPM dieses Rechners gebunden (PCRs { $pcrs })
+wrapped-kms = 🔐 Dateischlüssel mit { $uri } verpackt
+encrypted-to-recipients = 📮 Für { $count } Empfänger verschlüsselt
+keyfile-hint = 🗝️  Zum Entschlüsseln werden Passwort und Schlüsseldatei gebraucht.
+recovery-key-saved = 🛟 Wiederherstellungsschlüssel nach '{ $path }' gespeichert
+recovery-key-shown = 🛟 Wiederherstellungsschlüssel (Base64): { $key }
+recovery-key-once = ⚠️  Dieser Wiederherstellungsschlüssel wird NICHT noch einmal angezeigt!
+recovery-key-hint = 💡 Bei vergessenem Passwort mit --key <Wiederherstellungsschlüssel> entschlüsseln.
+generated-key = ✨ Zufälliger Schlüssel erzeugt (Base64): { $key }
+generated-key-hint = 💡 Diesen Schlüssel sicher aufbewahren! Er wird zum Entschlüsseln der Datei gebraucht.
+generated-key-once = ⚠️  Dieser Schlüssel wird NICHT noch einmal angezeigt!
+signing-with = ✍️  Signiere mit Schlüssel { $fingerprint }
+hiding-in-cover = 🖼️  Verstecke verschlüsselte Daten in '{ $cover }'...
+verified-regions = 🔍 Alle { $regions } Bereich(e) zurückgelesen und geprüft
+wiped-original = 🧹 Die { $bytes } Bytes des Originals mit Nullen überschrieben
+shredded-original = 🧹 Die { $bytes } Bytes von '{ $file }' mit Nullen überschrieben und die Datei gelöscht
+caveat = ⚠️  { $caveat }
+
+## Entschlüsselung
+
+joined-volumes = 🧩 { $count } Teil(e) von '{ $base }' zusammengefügt
+using-identities = 🪪 Verwende die Identitäten in '{ $path }'
+decrypting-stdin = 🔓 Entschlüssele Daten von stdin...
+decrypting-file = 🔓 Entschlüssele Datei '{ $file }'...
+signature-valid = ✍️  Gültige Signatur von Schlüssel { $fingerprint }
+extracted-entries = ✅ { $count } Einträge nach '{ $target }' entpackt
+extracted-archive = ✅ { $count } Dateien aus dem { $kind }-Archiv nach '{ $target }' entpackt
+decrypted-written = ✅ Entschlüsselte Datei nach '{ $output }' geschrieben
+decrypted-size = 📊 Entschlüsselte Größe: { $bytes } Bytes
+
+## Inspektion
+
+inspect-file = 📄 Datei: { $file }
+inspect-version = 🔖 Formatversion: { $version }
+inspect-mode = 🔐 Modus: { $mode }
+inspect-kdf = 🧮 KDF: { $name }
+inspect-kdf-argon2 = 🧮 KDF: { $name } (Speicher { $memory } KB, Zeit { $time }, Parallelität { $parallelism })
+inspect-kdf-pbkdf2 = 🧮 KDF: { $name } ({ $iterations } Iterationen)
+inspect-kdf-scrypt = 🧮 KDF: { $name } (N = 2^{ $log_n }, r = { $block_size }, p = { $parallelism })
+inspect-time = 🕒 Verschlüsselt: { $time } (Unix { $unix })
+inspect-time-unrecorded = 🕒 Verschlüsselt: nicht aufgezeichnet
+inspect-filename = 📝 Ursprünglicher Dateiname: { $filename }
+inspect-filename-unrecorded = 📝 Ursprünglicher Dateiname: nicht aufgezeichnet
+inspect-embedded-key = 🔑 Eingebetteter Schlüssel: ja (Fingerabdruck { $fingerprint })
+inspect-no-embedded-key = 🔑 Eingebetteter Schlüssel: nein
+inspect-quorum-slot = { $kind } (beliebige { $threshold } gemeinsam)
+inspect-keyslot = 🗝️ Schlüsselplatz { $index }: { $kind }
+inspect-labeled-keyslot = 🗝️ Schlüsselplatz { $index }: { $kind } ({ $label })
+inspect-signed = ✍️ Signiert von: { $signer } (gültig)
+inspect-signed-invalid = ✍️ Signiert von: { $signer } (UNGÜLTIGE Signatur)
+inspect-invalid-signer = einem ungültigen Schlüssel
+inspect-ciphertext = 📦 Chiffretext: { $bytes } Bytes
+inspect-delta = 🧩 Delta-Layout: { $chunks } Block/Blöcke von bis zu { $chunk_size } Bytes (Kette { $delta_id })
+inspect-chunked = 🧩 In Blöcken: { $chunks } Block/Blöcke von { $chunk_size } Bytes ({ $encrypted } Bytes verschlüsselt)
+
+## Fähigkeiten und Konfiguration
+
+capabilities-version = 🔧 EncryptX { $version }
+capabilities-ciphers = 🔒 Chiffren: { $ciphers }
+capabilities-kdf = 🔑 KDF: { $name } (Profile { $profiles }; Standard { $default })
+capabilities-default-kdf = 🔑 KDF: { $name }, für neue Dateien verwendet (Profile { $profiles }; Standard { $default })
+capabilities-formats = 📄 Formatversionen: Schlüssel { $key }, Passwort { $password }; Layouts { $layouts }
+capabilities-outputs = 📦 Ausgabeformate: { $formats }
+capabilities-compression = 🗜️  Kompression: { $name } (Stufen { $min } bis { $max }; verwendet { $level } mit { $threads } Thread(s))
+capabilities-compression-ldm = 🗜️  Kompression: { $name } (Stufen { $min } bis { $max }; verwendet { $level } mit { $threads } Thread(s) und Long-Distance-Matching)
+capabilities-keyslots = 🗝️  Schlüsselplätze: { $kinds }
+capabilities-signatures = ✍️  Signaturen: { $signatures }
+capabilities-limits = 📏 Maximaler Upload: { $megabytes } MB; Blockgröße { $min } bis { $max } Bytes
+capabilities-features = 🧩 TLS { $tls }, Authentifizierung { $auth }, Speicher { $storage }, OpenPGP { $openpgp }, Archivimport { $archive_ingest }, Speicherwächter { $watchdog }, Freigabelinks { $share_links }
+capabilities-no-storage = keiner
+enabled = aktiviert
+disabled = deaktiviert
+check-config-source = 🔧 Prüfe die Konfiguration aus { $source }
+check-config-defaults = 🔧 Prüfe die eingebaute Standardkonfiguration
+check-config-failed = Konfigurationsprüfung fehlgeschlagen: { $errors }
+check-config-valid = ✅ Die Konfiguration ist gültig
+
+## Sicherung und Wiederherstellung
+
+backup-created-repository = 📦 Repository '{ $repo }' angelegt
+backup-started = 💾 Sichere '{ $dir }' in '{ $repo }'...
+backup-skipped-special = ⏭️  '{ $entry }' übersprungen: keine reguläre Datei
+backup-snapshot = ✅ Snapshot { $snapshot } von '{ $dir }': { $files } Datei(en), { $bytes } Bytes
+backup-chunks = 📊 { $new } von { $total } Block/Blöcken neu, { $bytes } Bytes hinzugefügt
+restore-started = ♻️  Stelle { $entries } Einträge von Snapshot { $snapshot } in '{ $target }' wieder her...
+restore-skipped-symlink = ⏭️  '{ $entry }' übersprungen: symbolische Links brauchen Unix
+restore-done = ✅ Snapshot { $snapshot } in '{ $target }' wiederhergestellt: { $files } Datei(en), { $dirs } Verzeichnis(se), { $symlinks } symbolische(r) Link(s), { $bytes } Bytes
+restore-no-snapshots = 📭 Keine Snapshots in '{ $repo }'
+restore-snapshots = 📚 { $count } Snapshot(s) in '{ $repo }':
+restore-snapshot = { $snapshot }{ $name }: { $files } Datei(en), { $bytes } Bytes aus '{ $source }'
+
+## Stapelverarbeitung
+
+batch-file-encrypted = 🔐 '{ $file }' nach '{ $output }' verschlüsselt
+batch-file-failed = ❌ '{ $file }' fehlgeschlagen: { $detail }
+batch-done = ✅ { $count } Datei(en) mit { $jobs } Worker(n) verschlüsselt
+batch-failed = { $failed } von { $total } Datei(en) konnten nicht verschlüsselt werden: { $files }
+
+## Agent, Token und Zwischenablage
+
+agent-status = 🕵️  Der Agent an '{ $socket }' hält { $keys } Schlüssel für je bis zu { $ttl }s
+agent-not-running = 🕵️  An '{ $socket }' läuft kein Agent
+agent-stopped = 🛑 Agent beendet
+agent-cleared = 🧹 Agent-Cache geleert
+agent-listening = 🕵️  Agent lauscht an '{ $socket }' und behält Schlüssel für { $ttl }
+agent-export-hint = 💡 export { $variable }={ $socket }
+token-none = Keine FIDO2-Token angeschlossen
+token-device = 🔑 { $path }: { $description }
+token-touch = 👆 Sicherheitsschlüssel berühren...
+token-touch-enroll = 👆 Sicherheitsschlüssel zum Registrieren berühren...
+token-written = 🔑 Token-Berechtigung nach '{ $output }' geschrieben
+token-hint = 💡 Dateien mit `keyslot add DATEI --new-token { $output }` daran binden.
+clip-encrypted = 🔐 Zwischenablage verschlüsselt: { $bytes } Bytes Text durch eine { $armored } Bytes lange ASCII-Nachricht ersetzt
+clip-decrypted = 🔓 Zwischenablage entschlüsselt: { $bytes } Bytes Text
+clip-clear-scheduled = 🧹 Die Zwischenablage wird in { $seconds }s geleert
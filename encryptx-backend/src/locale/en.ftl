@@ -0,0 +1,166 @@
+# English messages of the encryptx CLI, and the fallback for every other language.
+# A translation copies this file, keeps each id and { $placeable } and translates the rest.
+
+## Errors
+
+error = Error: { $message }
+error-io = File operation failed: { $detail }
+error-crypto = Cryptographic operation failed: { $detail }
+error-invalid-input = Invalid input: { $detail }
+error-authentication = Authentication failed: { $detail }
+error-format = Invalid file format: { $detail }
+locale-unknown = No messages for the locale '{ $locale }' (available: { $available })
+password-too-weak = The password is too weak ({ $reason })
+password-weak = ⚠️  Weak password ({ $reason }); --enforce-strong-password refuses these
+
+## Prompts
+
+prompt-text-encrypt = Type the text to encrypt, then press Ctrl-D:
+prompt-text-decrypt = Paste the message, then press Ctrl-D:
+prompt-paper-key = Type the numbered lines of the paper key (any order, typos are fine), then press Ctrl-D:
+prompt-keystore-passphrase = 🔑 Passphrase for the keystore '{ $path }':
+prompt-keystore-repeat = 🔑 Repeat the passphrase:
+
+## Encryption
+
+collecting-dir = 📦 Collecting directory '{ $dir }'...
+packing-dir = 📦 Packing directory '{ $dir }'...
+encrypting-file = 🔐 Encrypting file '{ $file }'...
+encrypting-zip = 🔐 Encrypting '{ $file }' as AES-256 ZIP...
+encrypting-pgp = 🔐 Encrypting '{ $file }' as OpenPGP message...
+zip-written = ✅ Encrypted ZIP written to '{ $output }'
+pgp-written = ✅ OpenPGP message written to '{ $output }'
+encrypted-written = ✅ Encrypted file written to '{ $output }'
+encrypted-in-place = ✅ '{ $output }' encrypted in place
+encrypted-volumes = ✅ Encrypted file written as { $count } volume(s): '{ $first }' to '{ $last }'
+readme-written = 📝 Recipient instructions written to '{ $path }'
+qr-written = 📱 QR code written to '{ $path }'
+original-size = 📊 Original size: { $bytes } bytes
+encrypted-size = 📊 Encrypted size: { $bytes } bytes
+s3-chunk-too-small = ⚠️  Chunks smaller than 5 MiB cannot be uploaded as individual S3 multipart parts
+stored-uncompressed = 🗜️  '{ $file }' doesn't compress; storing it uncompressed
+dictionary-trained = 📚 Trained a { $bytes }-byte dictionary on { $files } files
+dictionary-too-little-data = 📚 Too little data to train a dictionary; compressing without one
+dictionary-saves = 📚 The dictionary saves { $bytes } bytes
+dictionary-not-worth-it = 📚 The dictionary doesn't pay for itself; compressing without it
+delta-reused = ♻️  Reused { $reused } of { $total } chunks ({ $bytes } bytes) from '{ $previous }'
+key-part-written = 🔑 Key part { $index } written to '{ $path }'
+key-parts-hint = 💡 Send the two key parts over different channels; both are needed to decrypt.
+quorum-hint = 👥 Any { $threshold } of the { $count } quorum keys are needed to decrypt.
+wrapped-pkcs11 = 🔐 File key wrapped by PKCS#11 key { $key }
+sealed-tpm = 🔐 File key sealed to this machine's TPM
+sealed-tpm-pcrs = 🔐 File key sealed to this machine's TPM (PCRs { $pcrs })
+wrapped-kms = 🔐 File key wrapped by { $uri }
+encrypted-to-recipients = 📮 Encrypted to { $count } recipient(s)
+keyfile-hint = 🗝️  Decrypting will need both the password and the keyfile.
+recovery-key-saved = 🛟 Recovery key saved to '{ $path }'
+recovery-key-shown = 🛟 Recovery key (base64): { $key }
+recovery-key-once = ⚠️  This recovery key will NOT be shown again!
+recovery-key-hint = 💡 If you forget the password, decrypt with --key <recovery key>.
+generated-key = ✨ Generated random key (base64): { $key }
+generated-key-hint = 💡 Save this key somewhere safe! You'll need it to decrypt your file.
+generated-key-once = ⚠️  This key will NOT be shown again!
+signing-with = ✍️  Signing with key { $fingerprint }
+hiding-in-cover = 🖼️  Hiding encrypted data in '{ $cover }'...
+verified-regions = 🔍 Read back and verified all { $regions } region(s)
+wiped-original = 🧹 Overwrote the original's { $bytes } bytes with zeros
+shredded-original = 🧹 Overwrote the { $bytes } bytes of '{ $file }' with zeros and deleted it
+caveat = ⚠️  { $caveat }
+
+## Decryption
+
+joined-volumes = 🧩 Joined { $count } volume(s) of '{ $base }'
+using-identities = 🪪 Using the identities in '{ $path }'
+decrypting-stdin = 🔓 Decrypting data from stdin...
+decrypting-file = 🔓 Decrypting file '{ $file }'...
+signature-valid = ✍️  Valid signature by key { $fingerprint }
+extracted-entries = ✅ Extracted { $count } entries into '{ $target }'
+extracted-archive = ✅ Extracted { $count } files from { $kind } archive into '{ $target }'
+decrypted-written = ✅ Decrypted file written to '{ $output }'
+decrypted-size = 📊 Decrypted size: { $bytes } bytes
+
+## Inspect
+
+inspect-file = 📄 File: { $file }
+inspect-version = 🔖 Format version: { $version }
+inspect-mode = 🔐 Mode: { $mode }
+inspect-kdf = 🧮 KDF: { $name }
+inspect-kdf-argon2 = 🧮 KDF: { $name } (memory { $memory } KB, time { $time }, parallelism { $parallelism })
+inspect-kdf-pbkdf2 = 🧮 KDF: { $name } ({ $iterations } iterations)
+inspect-kdf-scrypt = 🧮 KDF: { $name } (N = 2^{ $log_n }, r = { $block_size }, p = { $parallelism })
+inspect-time = 🕒 Encrypted: { $time } (unix { $unix })
+inspect-time-unrecorded = 🕒 Encrypted: not recorded
+inspect-filename = 📝 Original filename: { $filename }
+inspect-filename-unrecorded = 📝 Original filename: not recorded
+inspect-embedded-key = 🔑 Embedded key: yes (fingerprint { $fingerprint })
+inspect-no-embedded-key = 🔑 Embedded key: no
+inspect-quorum-slot = { $kind } (any { $threshold } together)
+inspect-keyslot = 🗝️ Keyslot { $index }: { $kind }
+inspect-labeled-keyslot = 🗝️ Keyslot { $index }: { $kind } ({ $label })
+inspect-signed = ✍️ Signed by: { $signer } (valid)
+inspect-signed-invalid = ✍️ Signed by: { $signer } (INVALID signature)
+inspect-invalid-signer = an invalid key
+inspect-ciphertext = 📦 Ciphertext: { $bytes } bytes
+inspect-delta = 🧩 Delta layout: { $chunks } chunk(s) of up to { $chunk_size } bytes (chain { $delta_id })
+inspect-chunked = 🧩 Chunked: { $chunks } chunk(s) of { $chunk_size } bytes ({ $encrypted } bytes encrypted)
+
+## Capabilities and configuration
+
+capabilities-version = 🔧 EncryptX { $version }
+capabilities-ciphers = 🔒 Ciphers: { $ciphers }
+capabilities-kdf = 🔑 KDF: { $name } (profiles { $profiles }; default { $default })
+capabilities-default-kdf = 🔑 KDF: { $name }, used for new files (profiles { $profiles }; default { $default })
+capabilities-formats = 📄 Format versions: key { $key }, password { $password }; layouts { $layouts }
+capabilities-outputs = 📦 Output formats: { $formats }
+capabilities-compression = 🗜️  Compression: { $name } (levels { $min } to { $max }; using { $level } with { $threads } thread(s))
+capabilities-compression-ldm = 🗜️  Compression: { $name } (levels { $min } to { $max }; using { $level } with { $threads } thread(s) and long-distance matching)
+capabilities-keyslots = 🗝️  Keyslots: { $kinds }
+capabilities-signatures = ✍️  Signatures: { $signatures }
+capabilities-limits = 📏 Max upload: { $megabytes } MB; chunk size { $min } to { $max } bytes
+capabilities-features = 🧩 TLS { $tls }, auth { $auth }, storage { $storage }, OpenPGP { $openpgp }, archive ingest { $archive_ingest }, memory watchdog { $watchdog }, share links { $share_links }
+capabilities-no-storage = none
+enabled = enabled
+disabled = disabled
+check-config-source = 🔧 Checking configuration from { $source }
+check-config-defaults = 🔧 Checking configuration from built-in defaults
+check-config-failed = Configuration check failed: { $errors }
+check-config-valid = ✅ Configuration is valid
+
+## Backup and restore
+
+backup-created-repository = 📦 Created repository '{ $repo }'
+backup-started = 💾 Backing up '{ $dir }' into '{ $repo }'...
+backup-skipped-special = ⏭️  '{ $entry }' skipped: not a regular file
+backup-snapshot = ✅ Snapshot { $snapshot } of '{ $dir }': { $files } file(s), { $bytes } bytes
+backup-chunks = 📊 { $new } of { $total } chunk(s) new, { $bytes } bytes added
+restore-started = ♻️  Restoring { $entries } entries of snapshot { $snapshot } into '{ $target }'...
+restore-skipped-symlink = ⏭️  '{ $entry }' skipped: symlinks need Unix
+restore-done = ✅ Restored snapshot { $snapshot } into '{ $target }': { $files } file(s), { $dirs } dir(s), { $symlinks } symlink(s), { $bytes } bytes
+restore-no-snapshots = 📭 No snapshots in '{ $repo }'
+restore-snapshots = 📚 { $count } snapshot(s) in '{ $repo }':
+restore-snapshot = { $snapshot }{ $name }: { $files } file(s), { $bytes } bytes of '{ $source }'
+
+## Batches
+
+batch-file-encrypted = 🔐 '{ $file }' encrypted to '{ $output }'
+batch-file-failed = ❌ '{ $file }' failed: { $detail }
+batch-done = ✅ { $count } file(s) encrypted with { $jobs } worker(s)
+batch-failed = { $failed } of { $total } file(s) could not be encrypted: { $files }
+
+## Agent, tokens and clipboard
+
+agent-status = 🕵️  Agent at '{ $socket }' holds { $keys } key(s) for up to { $ttl }s each
+agent-not-running = 🕵️  No agent is running at '{ $socket }'
+agent-stopped = 🛑 Agent stopped
+agent-cleared = 🧹 Agent cache cleared
+agent-listening = 🕵️  Agent listening on '{ $socket }', keeping keys for { $ttl }
+agent-export-hint = 💡 export { $variable }={ $socket }
+token-none = No FIDO2 tokens connected
+token-device = 🔑 { $path }: { $description }
+token-touch = 👆 Touch your security key...
+token-touch-enroll = 👆 Touch your security key to enroll it...
+token-written = 🔑 Token credential written to '{ $output }'
+token-hint = 💡 Bind files to it with `keyslot add FILE --new-token { $output }`.
+clip-encrypted = 🔐 Clipboard encrypted: { $bytes } bytes of text replaced by a { $armored }-byte armored message
+clip-decrypted = 🔓 Clipboard decrypted: { $bytes } bytes of text
+clip-clear-scheduled = 🧹 The clipboard will be cleared in { $seconds }s
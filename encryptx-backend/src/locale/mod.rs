@@ -0,0 +1,158 @@
+//!
+//! Message catalog for the CLI, so what it tells the user can be translated.
+//!
+//! `encrypt` and `decrypt`, the prompts, the kind of error named in the `Error: ...` line
+//! and the summaries of `inspect`, `capabilities`, `check-config`, `backup`, `restore`,
+//! batch `encrypt`, `agent`, `token` and `clip` go through it so far; other subcommands
+//! and most error details still use English strings in place.
+//!
+//! Messages live in Fluent files compiled into the binary, one per language (`en.ftl`,
+//! `de.ftl`, ...): `id = text`, with `{ $name }` where an argument goes and `#` starting a
+//! comment. Only that subset of Fluent is read, without selectors, terms or values spanning
+//! lines, so the files stay valid for the full Fluent tooling translators use.
+//!
+//! The language is picked once at startup from `--locale`, or else from the first of
+//! `LC_ALL`, `LC_MESSAGES` and `LANG` that is set. A message a translation lacks comes out
+//! in English, and an id missing from English too comes out as the id itself, as in Fluent.
+//!
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+/// Every language with a catalog and its Fluent source, English (the fallback) first.
+pub const LOCALES: &[(&str, &str)] = &[
+    ("en", include_str!("en.ftl")),
+    ("de", include_str!("de.ftl")),
+];
+
+/// Environment variables naming the user's locale, most specific first.
+const LOCALE_ENV: [&str; 3] = ["LC_ALL", "LC_MESSAGES", "LANG"];
+
+type Messages = HashMap<&'static str, &'static str>;
+
+/// The selected language's messages, set by [`init`].
+static SELECTED: OnceLock<Messages> = OnceLock::new();
+
+/// The English messages, the fallback for everything else.
+fn english() -> &'static Messages {
+    static ENGLISH: OnceLock<Messages> = OnceLock::new();
+    ENGLISH.get_or_init(|| parse(LOCALES[0].1))
+}
+
+/// Reads the messages of a Fluent source, id to text. Comments, blank lines and lines that
+/// aren't `id = text` are skipped.
+pub fn parse(source: &str) -> HashMap<&str, &str> {
+    source
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(id, text)| (id.trim(), text.trim()))
+        .filter(|(id, text)| {
+            !text.is_empty()
+                && id.starts_with(|c: char| c.is_ascii_alphabetic())
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+        .collect()
+}
+
+/// The names of the arguments `text` takes, in order of appearance.
+pub fn placeables(text: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        if let Some(name) = rest[start + 1..start + end].trim().strip_prefix('$') {
+            names.push(name);
+        }
+        rest = &rest[start + end + 1..];
+    }
+    names
+}
+
+/// The catalog language for a locale such as `de_DE.UTF-8`, `de-AT` or `de`, if there is
+/// one. `C` and `POSIX` are English.
+pub fn negotiate(locale: &str) -> Option<&'static str> {
+    let language = locale
+        .split(['_', '-', '.', '@'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match language.as_str() {
+        "c" | "posix" => Some(LOCALES[0].0),
+        language => LOCALES
+            .iter()
+            .map(|(code, _)| *code)
+            .find(|code| *code == language),
+    }
+}
+
+/// Selects the language of messages: `locale` (from `--locale`) if given, else the one the
+/// environment names, else English. Returns the language chosen. A `locale` without a
+/// catalog is an error listing those there are; one from the environment just means
+/// English. Only the first call takes effect.
+pub fn init(locale: Option<&str>) -> Result<&'static str, String> {
+    let language = match locale {
+        Some(locale) => negotiate(locale).ok_or_else(|| {
+            let available: Vec<_> = LOCALES.iter().map(|(code, _)| *code).collect();
+            message(
+                "locale-unknown",
+                &[("locale", &locale), ("available", &available.join(", "))],
+            )
+        })?,
+        None => LOCALE_ENV
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| negotiate(&value))
+            .unwrap_or(LOCALES[0].0),
+    };
+    let source = LOCALES
+        .iter()
+        .find(|(code, _)| *code == language)
+        .map_or(LOCALES[0].1, |(_, source)| source);
+    let _ = SELECTED.set(parse(source));
+    Ok(language)
+}
+
+/// The message `id` in the selected language (English before [`init`]), with each
+/// `{ $name }` replaced by the argument of that name. Placeables without an argument are
+/// left as they are.
+pub fn message(id: &str, args: &[(&str, &dyn Display)]) -> String {
+    let text = SELECTED
+        .get()
+        .and_then(|messages| messages.get(id))
+        .or_else(|| english().get(id))
+        .copied()
+        .unwrap_or(id);
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let name = rest[start + 1..end].trim().strip_prefix('$');
+        match args.iter().find(|(arg, _)| Some(*arg) == name) {
+            Some((_, value)) => out.push_str(&value.to_string()),
+            None => out.push_str(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// `tr!("id")` or `tr!("id", name = value, ...)`: [`message`] with named arguments.
+macro_rules! tr {
+    ($id:literal $(, $name:ident = $value:expr)* $(,)?) => {
+        $crate::locale::message(
+            $id,
+            &[$((stringify!($name), &$value as &dyn std::fmt::Display)),*],
+        )
+    };
+}
+pub(crate) use tr;
@@ -0,0 +1,227 @@
+//!
+//! Server logging through `tracing`, with secrets redacted before anything is written.
+//!
+//! Events pass their data as fields, and the field name decides whether the value may be
+//! shown. Keys, passwords, tokens and other secrets are always written as `[redacted]`.
+//! File names are redacted too unless `server.log_filenames` is set, since a name can say
+//! as much about a file as its contents.
+//!
+//! As a second line of defence, any word in a message or field value that decodes as a
+//! base64 AES-256 key is redacted as well, so a key interpolated into a message by mistake
+//! still never reaches the log. `actix-web`'s request log goes through the same filter.
+//!
+//! CLI commands log through [`init_cli`] instead: what the user is told goes to stderr as
+//! plain lines, so stdout only ever carries data and `--json` results, and `--log-file`
+//! keeps a timestamped, redacted copy.
+//!
+use base64::{Engine as _, engine::general_purpose};
+use std::fmt::{self, Debug};
+use std::fs::OpenOptions;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::fmt::FormatFields;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// What redacted values are replaced with.
+pub const REDACTED: &str = "[redacted]";
+
+/// Field names whose values are always secret.
+const SECRET_FIELDS: &[&str] = &["key", "password", "passphrase", "secret", "token"];
+
+/// Field names that hold file names.
+const FILENAME_FIELDS: &[&str] = &["filename", "file", "path"];
+
+/// Field formatter that applies the redaction policy.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Redactor {
+    /// Write file names as they are instead of redacting them
+    pub show_filenames: bool,
+}
+
+impl Redactor {
+    /// Whether the value of `field` must not be written.
+    pub fn redacts(&self, field: &str) -> bool {
+        is_secret_field(field) || (!self.show_filenames && is_filename_field(field))
+    }
+}
+
+impl<'writer> FormatFields<'writer> for Redactor {
+    fn format_fields<R: RecordFields>(&self, writer: Writer<'writer>, fields: R) -> fmt::Result {
+        let mut visitor = RedactingVisitor {
+            redactor: self,
+            writer,
+            first: true,
+            result: Ok(()),
+        };
+        fields.record(&mut visitor);
+        visitor.result
+    }
+}
+
+/// Writes each field of an event, or `[redacted]` in place of its value.
+struct RedactingVisitor<'a, 'writer> {
+    redactor: &'a Redactor,
+    writer: Writer<'writer>,
+    first: bool,
+    result: fmt::Result,
+}
+
+impl RedactingVisitor<'_, '_> {
+    fn write(&mut self, field: &Field, value: &str) {
+        if self.result.is_err() {
+            return;
+        }
+        let separator = if self.first { "" } else { " " };
+        self.first = false;
+        let name = field.name();
+        self.result = if self.redactor.redacts(name) {
+            write!(self.writer, "{separator}{name}={REDACTED}")
+        } else if name == "message" {
+            write!(self.writer, "{separator}{}", redact_keys(value))
+        } else {
+            write!(self.writer, "{separator}{name}={}", redact_keys(value))
+        };
+    }
+}
+
+impl Visit for RedactingVisitor<'_, '_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        // Where a bridged `log` record came from, which the default formatter hides too
+        if field.name().starts_with("log.") {
+            return;
+        }
+        // Skip formatting values that are redacted anyway
+        if self.redactor.redacts(field.name()) {
+            self.write(field, "");
+        } else {
+            self.write(field, &format!("{value:?}"));
+        }
+    }
+}
+
+/// Whether `field` names a secret: one of the secret names, or one ending in `_` and one.
+pub fn is_secret_field(field: &str) -> bool {
+    let field = field.to_ascii_lowercase();
+    SECRET_FIELDS.iter().any(|secret| {
+        field == *secret
+            || field
+                .strip_suffix(secret)
+                .is_some_and(|prefix| prefix.ends_with('_'))
+    })
+}
+
+/// Whether `field` names a file name.
+fn is_filename_field(field: &str) -> bool {
+    let field = field.to_ascii_lowercase();
+    FILENAME_FIELDS.iter().any(|name| {
+        field == *name
+            || field
+                .strip_suffix(name)
+                .is_some_and(|prefix| prefix.ends_with('_'))
+    })
+}
+
+/// `text` with every word that decodes as a base64 32-byte key replaced by `[redacted]`.
+pub fn redact_keys(text: &str) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(is_base64_char) {
+        let (before, word) = rest.split_at(start);
+        redacted.push_str(before);
+        let end = word.find(|c| !is_base64_char(c)).unwrap_or(word.len());
+        let (word, after) = word.split_at(end);
+        if looks_like_key(word) {
+            redacted.push_str(REDACTED);
+        } else {
+            redacted.push_str(word);
+        }
+        rest = after;
+    }
+    redacted.push_str(rest);
+    redacted
+}
+
+fn is_base64_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '=' | '-' | '_')
+}
+
+/// Whether `word` is a standard or URL-safe base64 encoding of 32 bytes.
+fn looks_like_key(word: &str) -> bool {
+    word.len() >= 43
+        && [
+            &general_purpose::STANDARD,
+            &general_purpose::URL_SAFE,
+            &general_purpose::URL_SAFE_NO_PAD,
+            &general_purpose::STANDARD_NO_PAD,
+        ]
+        .iter()
+        .any(|engine| engine.decode(word).is_ok_and(|bytes| bytes.len() == 32))
+}
+
+/// Installs the server's global subscriber: events at `info` and above (or as set by
+/// `RUST_LOG`) go to stdout through a [`Redactor`].
+pub fn init(redactor: Redactor) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .fmt_fields(redactor)
+        .try_init();
+}
+
+/// The filter for CLI commands: EncryptX's own events at `level`, other crates' at no more
+/// than `warn` unless `level` is `trace`. Without a level, `RUST_LOG` applies if set, and
+/// `info` otherwise.
+fn cli_filter(level: Option<LevelFilter>) -> EnvFilter {
+    let directives = |level: LevelFilter| {
+        let others = if level == LevelFilter::TRACE {
+            LevelFilter::DEBUG
+        } else {
+            level.min(LevelFilter::WARN)
+        };
+        EnvFilter::new(format!("{others},encryptx_backend={level}"))
+    };
+    match level {
+        Some(level) => directives(level),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| directives(LevelFilter::INFO)),
+    }
+}
+
+/// Installs the global subscriber for CLI commands. Events at `level` and above (see
+/// [`cli_filter`]) go to stderr, as bare messages at the default level and with their level
+/// and origin when more verbose. They aren't redacted there, since a generated key shown on
+/// the terminal is the point. With `log_file`, they are also appended to that file with
+/// timestamps, secrets redacted as in the server's log.
+pub fn init_cli(level: Option<LevelFilter>, log_file: Option<&Path>) -> io::Result<()> {
+    let verbose = level.is_some_and(|level| level > LevelFilter::INFO);
+    let stderr = tracing_subscriber::fmt::layer()
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .without_time()
+        .with_level(verbose)
+        .with_target(verbose);
+    let file = log_file
+        .map(|path| OpenOptions::new().create(true).append(true).open(path))
+        .transpose()?
+        .map(|file| {
+            tracing_subscriber::fmt::layer()
+                .with_writer(Mutex::new(file))
+                .with_ansi(false)
+                .fmt_fields(Redactor {
+                    show_filenames: true,
+                })
+        });
+    // Fails if a subscriber is already installed, which is then kept
+    let _ = tracing_subscriber::registry()
+        .with(cli_filter(level))
+        .with(stderr)
+        .with(file)
+        .try_init();
+    Ok(())
+}
@@ -6,112 +6,194 @@
 //! Endpoints:
 //! - POST /encrypt: Encrypts uploaded file data
 //! - POST /decrypt: Decrypts .xd file and returns original content
+//! - GET /stream/{name}: Decrypts the requested byte range of a file in `stream_dir`
+//! - POST /share/{name}: Creates a passcode-guarded share link for a file in `stream_dir`
+//! - GET /share/{token}: Serves a shared file to a recipient with its current passcode
 //! - GET /health: Server status and crypto info
+//! - GET /metrics: Argon2 latency histograms in the Prometheus text format
+//! - GET /kdf/advice: Argon2 parameter suggestions when p95 latency exceeds the target
+//! - GET /capabilities: Supported algorithms, format versions, limits and enabled features
 //!
 //! Security approach:
 //! - AES-256-GCM for authenticated encryption (prevents tampering)
 //! - Argon2id for password-based key derivation (GPU-resistant)
 //! - Async processing to keep server responsive under load
 //! - Memory-safe key handling with automatic cleanup
+//! - Optional memory watchdog that sheds password-mode requests (503) before an OOM kill
 //! - Cryptographically secure random number generation
 
 use actix_cors::Cors;
-use actix_web::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
-use actix_web::web::Bytes;
+use actix_web::body::SizedStream;
+use actix_web::http::header::{
+    ACCEPT_RANGES, CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_RANGE, CONTENT_TYPE, Header,
+    RETRY_AFTER, Range,
+};
+use actix_web::middleware::from_fn;
+use actix_web::web::{self, Bytes};
 use actix_web::{App, HttpRequest, HttpResponse, HttpServer, Responder, get, post};
 use base64::{Engine as _, engine::general_purpose};
-use clap::{Parser, Subcommand};
+use clap::Parser;
+use encryptx_backend::api::{self, ApiError};
+use encryptx_backend::capabilities::Capabilities;
+use encryptx_backend::config::{self, Config};
+use encryptx_backend::crypto::CryptoError;
+use encryptx_backend::crypto::chunked::ChunkWriter;
+use encryptx_backend::crypto::secret::SecretBuffer;
+use encryptx_backend::seekable::SeekableReader;
+use encryptx_backend::share::{self, Share, ShareError};
+use encryptx_backend::throttle::{DecryptThrottle, Throttle, throttle_decrypt};
+use encryptx_backend::watchdog::{MemoryWatchdog, Reservation};
+use encryptx_backend::{cli, compression, crypto, format, locale, logging, metrics};
 use rand::RngCore;
 use rand::rngs::OsRng;
-use zeroize::Zeroize;
-use zstd::stream::{decode_all, encode_all};
-pub mod cli;
-pub mod crypto;
-
-/// EncryptX Backend CLI
-#[derive(Parser)]
-#[command(author, version, about, long_about = None)]
-struct Cli {
-    #[command(subcommand)]
-    command: Option<Commands>,
-}
-
-#[derive(Subcommand)]
-enum Commands {
-    /// Encrypt a file
-    Encrypt {
-        /// Path to the file to encrypt
-        #[arg(long)]
-        filename: String,
-        /// Password to use for encryption (optional)
-        #[arg(long)]
-        pass: Option<String>,
-        /// Key to use for encryption (base64, optional; if not provided, random key is generated)
-        #[arg(long)]
-        key: Option<String>,
-    },
-    /// Decrypt a file
-    Decrypt {
-        /// Path to the file to decrypt
-        #[arg(long)]
-        filename: String,
-        /// Password to use for decryption (optional)
-        #[arg(long)]
-        pass: Option<String>,
-        /// Key to use for decryption (base64, optional)
-        #[arg(long)]
-        key: Option<String>,
-    },
-}
+use serde::Deserialize;
+use std::io::SeekFrom;
+use std::path::{Component, Path};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+use zeroize::Zeroizing;
 
 /// Generates a cryptographically secure 256-bit encryption key.
 /// Generates a cryptographically secure 256-bit (32-byte) random encryption key using the system's secure random number generator.
 ///
 /// # Returns
-/// A 32-byte array containing the generated encryption key.
+/// A 32-byte buffer in locked memory containing the generated encryption key.
 ///
 /// # Panics
 /// Panics if the system random number generator fails.
-fn generate_secure_key() -> [u8; 32] {
-    let mut key = [0u8; 32];
+fn generate_secure_key() -> SecretBuffer {
+    let mut key = SecretBuffer::new(32);
     OsRng
         .try_fill_bytes(&mut key)
         .expect("Failed to generate secure key");
     key
 }
 
+/// Memory watchdog shared by the handlers; `None` when `server.memory_limit_mb` is unset.
+type Watchdog = Option<Arc<MemoryWatchdog>>;
+
+/// Password KDF for an `/encrypt` request: the configured KDF with the profile named by the
+/// `x-kdf-profile` header, or with `kdf_profile` from the config file.
+fn request_kdf(
+    req: &HttpRequest,
+    config: &Config,
+) -> Result<crypto::PasswordKdf, Box<HttpResponse>> {
+    let Some(value) = req.headers().get("x-kdf-profile") else {
+        return config
+            .password_kdf()
+            .map_err(|e| Box::new(HttpResponse::InternalServerError().body(e.to_string())));
+    };
+    let profile = match value.to_str().map(str::parse::<crypto::KdfProfile>) {
+        Ok(Ok(profile)) => profile,
+        Ok(Err(e)) => return Err(Box::new(HttpResponse::BadRequest().body(e.to_string()))),
+        Err(_) => {
+            return Err(Box::new(
+                HttpResponse::BadRequest().body("Invalid KDF profile header"),
+            ));
+        }
+    };
+    crypto::PasswordKdf::for_profile(&config.kdf, profile)
+        .map_err(|e| Box::new(HttpResponse::InternalServerError().body(e.to_string())))
+}
+
+/// Reserves the Argon2 memory of a password-mode request.
+///
+/// Under memory pressure the request is refused with 503 and `Retry-After`, so clients back
+/// off instead of the process being OOM-killed; key-mode requests never come through here.
+fn admit_password_request(
+    watchdog: &Watchdog,
+    kdf: &crypto::PasswordKdf,
+    config: &Config,
+) -> Result<Option<Reservation>, Box<HttpResponse>> {
+    let Some(watchdog) = watchdog else {
+        return Ok(None);
+    };
+    match watchdog.try_reserve(kdf.memory_cost() * 1024) {
+        Some(reservation) => Ok(Some(reservation)),
+        None => {
+            tracing::warn!(
+                rss_mb = watchdog.rss() / (1024 * 1024),
+                "Memory watchdog: refusing password-mode request"
+            );
+            Err(Box::new(
+                HttpResponse::ServiceUnavailable()
+                    .insert_header((RETRY_AFTER, config.server.retry_after_secs.to_string()))
+                    .body("Server is under memory pressure; retry password-based requests later"),
+            ))
+        }
+    }
+}
+
 /// File encryption endpoint supporting both key-based and password-based modes.
 /// Mode is determined by presence of x-password header.
 #[post("/encrypt")]
 /// Handles file encryption requests for the `/encrypt` endpoint.
 ///
 /// Supports both password-based and key-based encryption modes, determined by the presence of the `x-password` header.
-/// - **Password-based encryption:** Requires an `x-password` header and derives a key using Argon2id with a random 32-byte salt. The original filename can be specified via the `x-orig-filename` header.
+/// - **Password-based encryption:** Requires an `x-password` header and derives a key using the configured KDF (Argon2id by default) with a random 32-byte salt; `x-kdf-profile` picks its strength. The original filename can be specified via the `x-orig-filename` header.
 /// - **Key-based encryption:** Uses a base64-encoded 256-bit key from the `x-enc-key` header, or generates a secure random key if not provided. The original filename can be specified via the `x-orig-filename` header.
 ///
+/// `x-chunk-size` selects the chunked layout and `x-compression-level` the zstd level; both default to the config file.
+///
 /// # Returns
 /// An encrypted file as a binary stream with appropriate headers, or an error response if encryption fails or headers are invalid.
-async fn encrypt_file(req: HttpRequest, body: Bytes) -> impl Responder {
-    // Compress the file bytes before encryption
-    let original_size = body.len();
-    let compressed = match encode_all(&body[..], 3) {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().body(format!("Compression error: {e}"));
+async fn encrypt_file(
+    req: HttpRequest,
+    body: Bytes,
+    config: web::Data<Config>,
+    watchdog: web::Data<Watchdog>,
+) -> impl Responder {
+    // Refuse password-mode work before compressing anything if memory is short
+    let _reservation = if req.headers().contains_key("x-password") {
+        let kdf = match request_kdf(&req, &config) {
+            Ok(kdf) => kdf,
+            Err(response) => return *response,
+        };
+        match admit_password_request(&watchdog, &kdf, &config) {
+            Ok(reservation) => reservation,
+            Err(response) => return *response,
+        }
+    } else {
+        None
+    };
+
+    // Optional chunked layout: per-request header wins over the config file
+    let chunk_size = match req.headers().get("x-chunk-size") {
+        Some(value) => {
+            let part_size = match value.to_str().map(config::parse_size) {
+                Ok(Ok(size)) => size,
+                Ok(Err(e)) => return HttpResponse::BadRequest().body(e),
+                Err(_) => return HttpResponse::BadRequest().body("Invalid chunk size header"),
+            };
+            match crypto::chunked::chunk_size_for_part(part_size) {
+                Ok(size) => Some(size),
+                Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
+            }
         }
+        None => match config.chunk_size() {
+            Ok(size) => size,
+            Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+        },
+    };
+
+    // zstd level: per-request header wins over the config file
+    let level = match req.headers().get("x-compression-level") {
+        Some(value) => match value.to_str().ok().and_then(|v| v.trim().parse().ok()) {
+            Some(level) => match compression::validate_level(level) {
+                Ok(level) => level,
+                Err(e) => return HttpResponse::BadRequest().body(e),
+            },
+            None => return HttpResponse::BadRequest().body("Invalid compression level header"),
+        },
+        None => config.compression_level,
     };
-    // Add a header byte to indicate compression (0x01)
-    let mut compressed_with_flag = Vec::with_capacity(1 + compressed.len());
-    compressed_with_flag.push(0x01);
-    compressed_with_flag.extend_from_slice(&compressed);
-    let compressed_size = compressed_with_flag.len();
-    println!("Original size: {original_size} bytes");
-    println!("Compressed size: {compressed_size} bytes");
 
     // Check for password-based encryption request
     if let Some(password_header) = req.headers().get("x-password") {
         let password = match password_header.to_str() {
-            Ok(p) => p.to_string(), // Need owned String for async operation
+            Ok(p) => Zeroizing::new(p.to_string()), // Need owned String for async operation
             Err(_) => return HttpResponse::BadRequest().body("Invalid password header encoding"),
         };
 
@@ -127,60 +209,76 @@ async fn encrypt_file(req: HttpRequest, body: Bytes) -> impl Responder {
             .and_then(|v| v.to_str().ok())
             .unwrap_or("file.bin");
 
-        println!("Encrypting file with password-based encryption: {orig_name}");
+        tracing::info!(
+            filename = orig_name,
+            "Encrypting file with password-based encryption"
+        );
+
+        let kdf = match request_kdf(&req, &config) {
+            Ok(kdf) => kdf,
+            Err(response) => return *response,
+        };
 
         // Use async encryption to avoid blocking the server thread
-        match crypto::encrypt_with_password_async(
-            &compressed_with_flag,
-            password,
-            orig_name,
-            salt.to_vec(),
-        )
-        .await
-        {
+        let encrypted = match chunk_size {
+            Some(chunk_size) => {
+                let writer = crypto::password_file_writer(
+                    Vec::new(),
+                    password.to_string(),
+                    orig_name,
+                    salt.to_vec(),
+                    kdf,
+                    chunk_size,
+                )
+                .await;
+                stream_upload(&body, orig_name, level, config.compression_tuning(), writer)
+            }
+            None => match compress_upload(&body, orig_name, level, config.compression_tuning()) {
+                Ok(compressed) => crypto::encrypt_with_password_kdf_async(
+                    &compressed,
+                    password.to_string(),
+                    orig_name,
+                    salt.to_vec(),
+                    kdf,
+                    None,
+                )
+                .await
+                .map_err(ApiError::from),
+                Err(e) => Err(e),
+            },
+        };
+        match encrypted {
             Ok(encrypted) => HttpResponse::Ok()
                 .insert_header((CONTENT_TYPE, "application/octet-stream"))
                 .insert_header((CONTENT_DISPOSITION, "attachment; filename=\"encrypted.xd\""))
                 .body(encrypted),
-            Err(e) => HttpResponse::InternalServerError().body(format!("Encryption error: {e}")),
+            Err(e) => encrypt_error_response(e),
         }
     } else {
         // Key-based encryption mode
-        let generate_and_log_key = || {
-            let random_key = generate_secure_key();
-            let key_b64_str = general_purpose::STANDARD.encode(random_key);
-            println!("Generated random encryption key: {key_b64_str}");
-            random_key
-        };
+        // A missing or empty key header asks the server to generate the key
+        let key_b64 = req
+            .headers()
+            .get("x-enc-key")
+            .map(|val| val.to_str().unwrap_or(""))
+            .filter(|key_b64| !key_b64.is_empty());
+        let generated = key_b64.is_none();
 
-        let mut final_key = if let Some(val) = req.headers().get("x-enc-key") {
-            let key_b64 = val.to_str().unwrap_or("");
-            if key_b64.is_empty() {
-                // No key provided, generate a secure random one
-                generate_and_log_key()
-            } else {
-                // Decode provided base64 key
-                match general_purpose::STANDARD.decode(key_b64) {
-                    Ok(k) if k.len() == 32 => {
-                        let mut arr = [0u8; 32];
-                        arr.copy_from_slice(&k);
-                        arr
-                    }
-                    Ok(k) => {
-                        return HttpResponse::BadRequest().body(format!(
-                            "Key is {} bytes after base64 decode, expected 32",
-                            k.len()
-                        ));
-                    }
-                    Err(e) => {
-                        return HttpResponse::BadRequest()
-                            .body(format!("Base64 decode error: {e}"));
-                    }
+        let final_key = match key_b64 {
+            // Decode provided base64 key
+            Some(key_b64) => match general_purpose::STANDARD.decode(key_b64) {
+                Ok(k) if k.len() == 32 => SecretBuffer::from_slice(&Zeroizing::new(k)),
+                Ok(k) => {
+                    return HttpResponse::BadRequest().body(format!(
+                        "Key is {} bytes after base64 decode, expected 32",
+                        k.len()
+                    ));
                 }
-            }
-        } else {
-            // No key header at all, generate random key
-            generate_and_log_key()
+                Err(e) => {
+                    return HttpResponse::BadRequest().body(format!("Base64 decode error: {e}"));
+                }
+            },
+            None => generate_secure_key(),
         };
 
         let orig_name = req
@@ -189,140 +287,590 @@ async fn encrypt_file(req: HttpRequest, body: Bytes) -> impl Responder {
             .and_then(|v| v.to_str().ok())
             .unwrap_or("file.bin");
 
-        println!("Encrypting file with key-based encryption: {orig_name}");
+        tracing::info!(
+            filename = orig_name,
+            "Encrypting file with key-based encryption"
+        );
 
-        match crypto::encrypt_with_header(&compressed_with_flag, &final_key, orig_name) {
+        let encrypted = match chunk_size {
+            Some(chunk_size) => {
+                let writer = crypto::key_file_writer(Vec::new(), &final_key, orig_name, chunk_size);
+                stream_upload(&body, orig_name, level, config.compression_tuning(), writer)
+            }
+            None => compress_upload(&body, orig_name, level, config.compression_tuning()).and_then(
+                |compressed| {
+                    crypto::encrypt_with_header(&compressed, &final_key, orig_name)
+                        .map_err(ApiError::from)
+                },
+            ),
+        };
+        match encrypted {
+            // The key is zeroized when `final_key` drops
             Ok(encrypted) => {
-                final_key.zeroize(); // Clear key from memory
-                HttpResponse::Ok()
+                let mut response = HttpResponse::Ok();
+                response
                     .insert_header((CONTENT_TYPE, "application/octet-stream"))
-                    .insert_header((CONTENT_DISPOSITION, "attachment; filename=\"encrypted.xd\""))
-                    .body(encrypted)
-            }
-            Err(e) => {
-                final_key.zeroize();
-                HttpResponse::InternalServerError().body(format!("Encryption error: {e}"))
+                    .insert_header((CONTENT_DISPOSITION, "attachment; filename=\"encrypted.xd\""));
+                if generated {
+                    // The caller is the only one to ever see a generated key; it is not logged
+                    // and must not be cached on the way
+                    let key_b64 = Zeroizing::new(general_purpose::STANDARD.encode(&*final_key));
+                    response
+                        .insert_header(("x-generated-key", key_b64.as_str()))
+                        .insert_header((CACHE_CONTROL, "no-store"));
+                }
+                response.body(encrypted)
             }
+            Err(e) => encrypt_error_response(e),
         }
     }
 }
 
+/// Compresses an upload as a single body, for the single-message layout. Uploads that
+/// wouldn't shrink are stored uncompressed.
+fn compress_upload(
+    body: &[u8],
+    filename: &str,
+    level: i32,
+    tuning: compression::Tuning,
+) -> Result<Zeroizing<Vec<u8>>, ApiError> {
+    let level = compression::worth_compressing(filename, body).then_some(level);
+    let mut compressed = Zeroizing::new(Vec::new());
+    api::encode_into(body, level, tuning, &mut *compressed)?;
+    tracing::info!(
+        original_size = body.len(),
+        compressed_size = compressed.len(),
+        compressed = level.is_some(),
+        "Compressed upload"
+    );
+    Ok(compressed)
+}
+
+/// Streams an upload through zstd into `writer` for the chunked layout, so neither the
+/// whole compressed body nor a second copy of it is ever held. Uploads that wouldn't shrink
+/// are stored uncompressed.
+fn stream_upload(
+    body: &[u8],
+    filename: &str,
+    level: i32,
+    tuning: compression::Tuning,
+    writer: Result<ChunkWriter<Vec<u8>>, CryptoError>,
+) -> Result<Vec<u8>, ApiError> {
+    let level = compression::worth_compressing(filename, body).then_some(level);
+    let writer = api::encode_into(body, level, tuning, writer?)?;
+    tracing::info!(
+        original_size = body.len(),
+        compressed_size = writer.written(),
+        compressed = level.is_some(),
+        "Compressed upload"
+    );
+    Ok(writer.finish()?)
+}
+
+/// Maps a failed encryption to 500, naming the step that failed.
+fn encrypt_error_response(error: ApiError) -> HttpResponse {
+    match error {
+        ApiError::Crypto(e) => {
+            HttpResponse::InternalServerError().body(format!("Encryption error: {e}"))
+        }
+        e => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
 /// File decryption endpoint with automatic format detection.
 /// Detects password vs key-based encryption and routes accordingly.
-#[post("/decrypt")]
+#[post("/decrypt", wrap = "from_fn(throttle_decrypt)")]
 /// Handles file decryption requests for the `/decrypt` endpoint.
 ///
 /// Supports both password-based and key-based decryption modes, determined by the presence of the `x-password` or `x-enc-key` headers. Returns the decrypted file as a binary stream with the original filename, or an appropriate HTTP error response if decryption fails.
-async fn decrypt_file(req: HttpRequest, body: Bytes) -> impl Responder {
+async fn decrypt_file(
+    req: HttpRequest,
+    body: Bytes,
+    config: web::Data<Config>,
+    watchdog: web::Data<Watchdog>,
+) -> impl Responder {
+    // Malformed files are left to the decrypt functions, which report them as such
+    let info = format::detect(&body).ok();
+    if let Some(info) = &info
+        && !info.is_supported()
+    {
+        return HttpResponse::BadRequest().body(format!(
+            "File format version {} is newer than this server supports (up to {})",
+            info.version,
+            format::CURRENT_VERSION
+        ));
+    }
+
+    // Validated when the config was loaded
+    let max_size = config
+        .max_decompressed_size()
+        .unwrap_or(api::DEFAULT_MAX_DECOMPRESSED_SIZE);
+
     // Check for password-based decryption request
     if let Some(password_header) = req.headers().get("x-password") {
+        // The file's own KDF parameters decide how much memory the derivation takes
+        let kdf = info.and_then(|info| info.kdf).unwrap_or_default();
+        let _reservation = match admit_password_request(&watchdog, &kdf, &config) {
+            Ok(reservation) => reservation,
+            Err(response) => return *response,
+        };
         let password = match password_header.to_str() {
-            Ok(p) => p.to_string(), // Need owned String for async operation
+            Ok(p) => Zeroizing::new(p.to_string()), // Need owned String for async operation
             Err(_) => return HttpResponse::BadRequest().body("Invalid password header encoding"),
         };
 
         // Use async decryption for Argon2 key derivation (CPU-intensive)
-        match crypto::decrypt_with_password_async(&body, password).await {
-            Ok((decrypted, filename)) => {
-                // Check for compression flag
-                if decrypted.first() == Some(&0x01) {
-                    match decode_all(&decrypted[1..]) {
-                        Ok(decompressed) => HttpResponse::Ok()
-                            .insert_header((CONTENT_TYPE, "application/octet-stream"))
-                            .insert_header((
-                                CONTENT_DISPOSITION,
-                                format!("attachment; filename=\"{filename}\""),
-                            ))
-                            .body(decompressed),
-                        Err(e) => HttpResponse::InternalServerError()
-                            .body(format!("Decompression error: {e}")),
-                    }
-                } else {
-                    // No compression flag, return as is
-                    HttpResponse::Ok()
-                        .insert_header((CONTENT_TYPE, "application/octet-stream"))
-                        .insert_header((
-                            CONTENT_DISPOSITION,
-                            format!("attachment; filename=\"{filename}\""),
-                        ))
-                        .body(decrypted)
-                }
-            }
-            Err(e) => match e {
-                crypto::CryptoError::WrongDecryptionMethod(msg) => {
-                    HttpResponse::BadRequest().body(msg)
-                }
-                crypto::CryptoError::AuthenticationError => {
-                    HttpResponse::Unauthorized().body("Wrong password or file is corrupt")
-                }
-                crypto::CryptoError::FormatError => HttpResponse::BadRequest()
-                    .body("Invalid file format. The file may be corrupt or not a valid .xd file."),
-                crypto::CryptoError::AsyncError(msg) => HttpResponse::InternalServerError()
-                    .body(format!("Async processing error: {msg}")),
-                _ => HttpResponse::InternalServerError().body(format!("Decryption error: {e}")),
-            },
-        }
+        let decrypted =
+            api::decrypt_file_with_limit(&body, Some(password.as_str()), None, max_size).await;
+        decrypted_response(decrypted, "password")
     } else {
         // Key-based decryption mode
-        let key_opt = match req.headers().get("x-enc-key") {
-            Some(val) => {
-                let key_b64 = val.to_str().unwrap_or("");
-                match general_purpose::STANDARD.decode(key_b64) {
-                    Ok(k) if k.len() == 32 => Some(k),
-                    Ok(k) => {
-                        return HttpResponse::BadRequest().body(format!(
-                            "Key is {} bytes after base64 decode, expected 32",
-                            k.len()
-                        ));
-                    }
-                    Err(e) => {
-                        return HttpResponse::BadRequest()
-                            .body(format!("Base64 decode error: {e}"));
-                    }
-                }
+        let key_opt = match request_key(&req) {
+            Ok(key) => key,
+            Err(response) => return *response,
+        };
+
+        let key_ref = key_opt.as_deref().map(Vec::as_slice);
+        let decrypted = api::decrypt_file_with_limit(&body, None, key_ref, max_size).await;
+        decrypted_response(decrypted, "key")
+    }
+}
+
+/// The base64 key of the `x-enc-key` header of a decryption request; `None` without one, so
+/// a key embedded in the file header is tried.
+fn request_key(req: &HttpRequest) -> Result<Option<Zeroizing<Vec<u8>>>, Box<HttpResponse>> {
+    let Some(val) = req.headers().get("x-enc-key") else {
+        return Ok(None);
+    };
+    let key_b64 = val.to_str().unwrap_or("");
+    match general_purpose::STANDARD.decode(key_b64) {
+        Ok(k) if k.len() == 32 => Ok(Some(Zeroizing::new(k))),
+        Ok(k) => Err(Box::new(HttpResponse::BadRequest().body(format!(
+            "Key is {} bytes after base64 decode, expected 32",
+            k.len()
+        )))),
+        Err(e) => Err(Box::new(
+            HttpResponse::BadRequest().body(format!("Base64 decode error: {e}")),
+        )),
+    }
+}
+
+/// Response carrying a decrypted body, or the error that stopped it.
+/// `credential` ("password" or "key") names what the caller supplied, for error messages.
+fn decrypted_response(
+    decrypted: Result<(Vec<u8>, api::DecryptMetadata), ApiError>,
+    credential: &str,
+) -> HttpResponse {
+    match decrypted {
+        Ok((plaintext, metadata)) => HttpResponse::Ok()
+            .insert_header((CONTENT_TYPE, "application/octet-stream"))
+            .insert_header((
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", metadata.filename),
+            ))
+            .body(plaintext),
+        Err(e) => decrypt_error_response(e, credential),
+    }
+}
+
+/// Maps a failed decryption to its status: 401 for a wrong credential (which the throttle
+/// counts), 400 for a bad file or request, 413 for a body that decompresses past
+/// `max_decompressed_size`, 500 for anything else.
+fn decrypt_error_response(error: ApiError, credential: &str) -> HttpResponse {
+    match error {
+        ApiError::Crypto(CryptoError::WrongDecryptionMethod(msg)) | ApiError::InvalidInput(msg) => {
+            HttpResponse::BadRequest().body(msg)
+        }
+        ApiError::Crypto(CryptoError::AuthenticationError) => {
+            HttpResponse::Unauthorized().body(format!("Wrong {credential} or file is corrupt"))
+        }
+        ApiError::Crypto(CryptoError::FormatError) => HttpResponse::BadRequest()
+            .body("Invalid file format. The file may be corrupt or not a valid .xd file."),
+        ApiError::InvalidPadding => HttpResponse::BadRequest().body(error.to_string()),
+        ApiError::DecompressedTooLarge { .. } => {
+            HttpResponse::PayloadTooLarge().body(error.to_string())
+        }
+        ApiError::Crypto(CryptoError::AsyncError(msg)) => {
+            HttpResponse::InternalServerError().body(format!("Async processing error: {msg}"))
+        }
+        ApiError::Crypto(e) => {
+            HttpResponse::InternalServerError().body(format!("Decryption error: {e}"))
+        }
+        ApiError::Compression(_) | ApiError::Decompression(_) | ApiError::Io(_) => {
+            HttpResponse::InternalServerError().body(error.to_string())
+        }
+    }
+}
+
+/// Ranged decryption endpoint for files in `server.stream_dir`, so players can seek
+/// through encrypted media.
+#[get("/stream/{name:.*}", wrap = "from_fn(throttle_decrypt)")]
+/// Handles `GET /stream/{name}`: decrypts the part of a stored chunked file that the `Range`
+/// header asks for, with the credential in `x-password` or `x-enc-key` as for `/decrypt`.
+///
+/// Only the chunks covering the range are read and decrypted (see `api::open_seekable`),
+/// and the plaintext is streamed out as it is decrypted. A single satisfiable range gets
+/// 206 with `Content-Range`, a request without one (or with several) gets the whole file,
+/// and a range past the end gets 416. A chunk that fails to authenticate mid-response aborts
+/// the response.
+///
+/// # Returns
+/// The requested plaintext; 404 for an unknown file or when `stream_dir` is unset; or the
+/// status `/decrypt` would answer for the same file and credential.
+async fn stream_file(
+    req: HttpRequest,
+    name: web::Path<String>,
+    config: web::Data<Config>,
+    watchdog: web::Data<Watchdog>,
+) -> impl Responder {
+    let mut file = match open_stored(&config, &name).await {
+        Ok(file) => file,
+        Err(response) => return *response,
+    };
+    let credential = match request_stored_credential(&req, &mut file, &watchdog, &config).await {
+        Ok(credential) => credential,
+        Err(response) => return *response,
+    };
+    let reader = match credential.open(file).await {
+        Ok(reader) => reader,
+        Err(response) => return *response,
+    };
+    serve_plaintext(&req, Path::new(name.as_str()), reader).await
+}
+
+/// Opens `name` below `server.stream_dir`: 404 when it is unset or the file is missing, 400
+/// for a name that would leave the directory.
+async fn open_stored(config: &Config, name: &str) -> Result<tokio::fs::File, Box<HttpResponse>> {
+    let Some(dir) = &config.server.stream_dir else {
+        return Err(Box::new(
+            HttpResponse::NotFound().body("Streaming is not enabled on this server"),
+        ));
+    };
+    // Only names below the directory: no `..`, roots or drive prefixes
+    let name = Path::new(name);
+    if !name
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(Box::new(
+            HttpResponse::BadRequest().body("Invalid file name"),
+        ));
+    }
+    let path = dir.join(name);
+    match tokio::fs::File::open(&path).await {
+        Ok(file) if path.is_file() => Ok(file),
+        _ => Err(Box::new(HttpResponse::NotFound().body("No such file"))),
+    }
+}
+
+/// The credential a stored file is opened with, and the memory reserved for its KDF.
+struct StoredCredential {
+    password: Option<Zeroizing<String>>,
+    key: Option<Zeroizing<Vec<u8>>>,
+    _reservation: Option<Reservation>,
+}
+
+impl StoredCredential {
+    /// Reserves the memory a password derivation for `file` takes, sized by the file's own
+    /// KDF parameters; under memory pressure this is the 503 of `admit_password_request`.
+    async fn password(
+        password: Zeroizing<String>,
+        file: &mut tokio::fs::File,
+        watchdog: &Watchdog,
+        config: &Config,
+    ) -> Result<Self, Box<HttpResponse>> {
+        let mut start = Vec::new();
+        let kdf = match file
+            .take(format::MAX_HEADER_LEN as u64 + 64)
+            .read_to_end(&mut start)
+            .await
+        {
+            Ok(_) => format::detect(&start).ok().and_then(|info| info.kdf),
+            Err(e) => {
+                return Err(Box::new(
+                    HttpResponse::InternalServerError().body(e.to_string()),
+                ));
             }
-            None => None, // Will try to use embedded key from file header
         };
+        let reservation = admit_password_request(watchdog, &kdf.unwrap_or_default(), config)?;
+        Ok(Self {
+            password: Some(password),
+            key: None,
+            _reservation: reservation,
+        })
+    }
+
+    /// A key credential, or none for a file carrying its own key.
+    fn key(key: Option<Zeroizing<Vec<u8>>>) -> Self {
+        Self {
+            password: None,
+            key,
+            _reservation: None,
+        }
+    }
+
+    /// Opens `file` for ranged reads; a wrong credential is the 401 `/decrypt` would answer.
+    async fn open(
+        &self,
+        file: tokio::fs::File,
+    ) -> Result<SeekableReader<tokio::fs::File>, Box<HttpResponse>> {
+        let credential = if self.password.is_some() {
+            "password"
+        } else {
+            "key"
+        };
+        api::open_seekable(
+            file,
+            self.password.as_deref().map(String::as_str),
+            self.key.as_deref().map(Vec::as_slice),
+        )
+        .await
+        .map_err(|e| Box::new(decrypt_error_response(e, credential)))
+    }
+}
 
-        let key_ref = key_opt.as_deref();
-        match crypto::decrypt_with_header(&body, key_ref) {
-            Ok((decrypted, filename)) => {
-                // Check for compression flag
-                if decrypted.first() == Some(&0x01) {
-                    match decode_all(&decrypted[1..]) {
-                        Ok(decompressed) => HttpResponse::Ok()
-                            .insert_header((CONTENT_TYPE, "application/octet-stream"))
-                            .insert_header((
-                                CONTENT_DISPOSITION,
-                                format!("attachment; filename=\"{filename}\""),
-                            ))
-                            .body(decompressed),
-                        Err(e) => HttpResponse::InternalServerError()
-                            .body(format!("Decompression error: {e}")),
-                    }
-                } else {
-                    // No compression flag, return as is
-                    HttpResponse::Ok()
-                        .insert_header((CONTENT_TYPE, "application/octet-stream"))
-                        .insert_header((
-                            CONTENT_DISPOSITION,
-                            format!("attachment; filename=\"{filename}\""),
-                        ))
-                        .body(decrypted)
+/// The credential in the `x-password` or `x-enc-key` header of a request for a stored file.
+async fn request_stored_credential(
+    req: &HttpRequest,
+    file: &mut tokio::fs::File,
+    watchdog: &Watchdog,
+    config: &Config,
+) -> Result<StoredCredential, Box<HttpResponse>> {
+    match req.headers().get("x-password") {
+        Some(password_header) => {
+            let password = match password_header.to_str() {
+                Ok(p) => Zeroizing::new(p.to_string()),
+                Err(_) => {
+                    return Err(Box::new(
+                        HttpResponse::BadRequest().body("Invalid password header encoding"),
+                    ));
                 }
+            };
+            StoredCredential::password(password, file, watchdog, config).await
+        }
+        None => Ok(StoredCredential::key(request_key(req)?)),
+    }
+}
+
+/// Streams the plaintext of an opened stored file: the single satisfiable range the
+/// `Range` header asks for with 206, otherwise the whole file, or 416 past the end.
+async fn serve_plaintext(
+    req: &HttpRequest,
+    name: &Path,
+    mut reader: SeekableReader<tokio::fs::File>,
+) -> HttpResponse {
+    let total = reader.len();
+    // Several ranges would need a multipart body; the whole file is a valid answer too
+    let range = match Range::parse(req) {
+        Ok(Range::Bytes(specs)) if specs.len() == 1 => match specs[0].to_satisfiable_range(total) {
+            Some(range) => Some(range),
+            None => {
+                return HttpResponse::RangeNotSatisfiable()
+                    .insert_header((CONTENT_RANGE, format!("bytes */{total}")))
+                    .finish();
+            }
+        },
+        _ => None,
+    };
+    let mut response = match range {
+        Some((first, last)) => {
+            let mut response = HttpResponse::PartialContent();
+            response.insert_header((CONTENT_RANGE, format!("bytes {first}-{last}/{total}")));
+            response
+        }
+        None => HttpResponse::Ok(),
+    };
+    let (first, len) = range.map_or((0, total), |(first, last)| (first, last - first + 1));
+    if let Err(e) = reader.seek(SeekFrom::Start(first)).await {
+        return HttpResponse::InternalServerError().body(e.to_string());
+    }
+    response
+        .insert_header((CONTENT_TYPE, media_type(name)))
+        .insert_header((ACCEPT_RANGES, "bytes"))
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .body(SizedStream::new(len, ReaderStream::new(reader.take(len))))
+}
+
+#[derive(Deserialize)]
+struct ShareQuery {
+    /// Lifetime of the link; capped at, and defaulting to, `server.share_max_ttl_secs`
+    ttl_secs: Option<u64>,
+}
+
+/// The secret share tokens are sealed under; `None` (and 404) unless both it and
+/// `server.stream_dir` are configured.
+fn share_secret(config: &Config) -> Result<Zeroizing<Vec<u8>>, Box<HttpResponse>> {
+    match (config.share_secret(), &config.server.stream_dir) {
+        (Ok(Some(secret)), Some(_)) => Ok(secret),
+        _ => Err(Box::new(
+            HttpResponse::NotFound().body("Share links are not enabled on this server"),
+        )),
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Share link creation for files in `server.stream_dir`.
+#[post("/share/{name:.*}", wrap = "from_fn(throttle_decrypt)")]
+/// Handles `POST /share/{name}`: checks that the credential in `x-password` or `x-enc-key`
+/// opens the stored file, then seals it with a fresh TOTP seed into a link (see
+/// `encryptx_backend::share`).
+///
+/// # Returns
+/// JSON with the `link`, its `expires` time and the seed as `totp_secret` (base32) and
+/// `otpauth_uri`, which is shown only this once; 404 when sharing is off or the file is
+/// unknown; or the status `/stream` would answer for the same file and credential.
+async fn create_share(
+    req: HttpRequest,
+    name: web::Path<String>,
+    query: web::Query<ShareQuery>,
+    config: web::Data<Config>,
+    watchdog: web::Data<Watchdog>,
+) -> impl Responder {
+    let secret = match share_secret(&config) {
+        Ok(secret) => secret,
+        Err(response) => return *response,
+    };
+    let mut file = match open_stored(&config, &name).await {
+        Ok(file) => file,
+        Err(response) => return *response,
+    };
+    let credential = match request_stored_credential(&req, &mut file, &watchdog, &config).await {
+        Ok(credential) => credential,
+        Err(response) => return *response,
+    };
+    if let Err(response) = credential.open(file).await {
+        return *response;
+    }
+
+    let ttl_secs = query
+        .ttl_secs
+        .filter(|&secs| secs > 0)
+        .map_or(config.server.share_max_ttl_secs, |secs| {
+            secs.min(config.server.share_max_ttl_secs)
+        });
+    let share = Share {
+        file: name.into_inner(),
+        password: credential.password.as_deref().cloned(),
+        key: credential
+            .key
+            .as_deref()
+            .map(|key| general_purpose::STANDARD.encode(key)),
+        seed: share::new_seed(),
+        expires: unix_now() + ttl_secs,
+    };
+    let token = match share::seal(&secret, &share) {
+        Ok(token) => token,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+    tracing::info!(
+        file = share.file,
+        expires = share.expires,
+        "Share link created"
+    );
+    HttpResponse::Ok()
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .json(serde_json::json!({
+            "link": format!("/share/{token}"),
+            "expires": share.expires,
+            "totp_secret": share::base32(&share.seed),
+            "otpauth_uri": share::otpauth_uri(&share.seed, &share.file),
+            "period_secs": share::PERIOD_SECS,
+            "digits": share::DIGITS,
+        }))
+}
+
+/// Passcode-guarded download of a shared file.
+#[get("/share/{token}", wrap = "from_fn(throttle_decrypt)")]
+/// Handles `GET /share/{token}`: serves the shared file like `/stream`, ranges included,
+/// once the `x-share-passcode` header holds its current passcode.
+///
+/// # Returns
+/// The requested plaintext; 401 for a missing or wrong passcode, which the throttle counts;
+/// 410 once the link has expired; 404 for an unknown token or when sharing is off.
+async fn open_share(
+    req: HttpRequest,
+    token: web::Path<String>,
+    config: web::Data<Config>,
+    watchdog: web::Data<Watchdog>,
+) -> impl Responder {
+    let secret = match share_secret(&config) {
+        Ok(secret) => secret,
+        Err(response) => return *response,
+    };
+    let now = unix_now();
+    let share = match share::open(&secret, &token, now) {
+        Ok(share) => share,
+        Err(ShareError::Expired) => {
+            return HttpResponse::Gone().body("This share link has expired");
+        }
+        Err(ShareError::Invalid) => return HttpResponse::NotFound().body("No such share link"),
+    };
+    let passcode = req
+        .headers()
+        .get("x-share-passcode")
+        .and_then(|value| value.to_str().ok());
+    match passcode {
+        None => {
+            return HttpResponse::Unauthorized()
+                .body("A passcode is required in the x-share-passcode header");
+        }
+        Some(passcode) if !share::verify_passcode(&share.seed, passcode.trim(), now) => {
+            return HttpResponse::Unauthorized().body("Wrong or expired passcode");
+        }
+        Some(_) => {}
+    }
+
+    let mut file = match open_stored(&config, &share.file).await {
+        Ok(file) => file,
+        Err(response) => return *response,
+    };
+    let credential = match (&share.password, &share.key) {
+        (Some(password), _) => {
+            let password = Zeroizing::new(password.clone());
+            match StoredCredential::password(password, &mut file, &watchdog, &config).await {
+                Ok(credential) => credential,
+                Err(response) => return *response,
             }
-            Err(e) => match e {
-                crypto::CryptoError::WrongDecryptionMethod(msg) => {
-                    HttpResponse::BadRequest().body(msg)
-                }
-                crypto::CryptoError::AuthenticationError => {
-                    HttpResponse::Unauthorized().body("Wrong key or file is corrupt")
-                }
-                crypto::CryptoError::FormatError => HttpResponse::BadRequest()
-                    .body("Invalid file format. The file may be corrupt or not a valid .xd file."),
-                _ => HttpResponse::InternalServerError().body(format!("Decryption error: {e}")),
-            },
         }
+        (None, key) => StoredCredential::key(
+            key.as_deref()
+                .and_then(|key| general_purpose::STANDARD.decode(key).ok())
+                .map(Zeroizing::new),
+        ),
+    };
+    let reader = match credential.open(file).await {
+        Ok(reader) => reader,
+        Err(response) => return *response,
+    };
+    serve_plaintext(&req, Path::new(&share.file), reader).await
+}
+
+/// Content type for a stored file, from the name it had before encryption, so browsers
+/// play media inline; anything unknown is `application/octet-stream`.
+fn media_type(name: &Path) -> &'static str {
+    let name = name.with_extension("");
+    let extension = name
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    match extension.to_ascii_lowercase().as_str() {
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "mkv" => "video/x-matroska",
+        "mov" => "video/quicktime",
+        "mp3" => "audio/mpeg",
+        "m4a" => "audio/mp4",
+        "ogg" | "oga" => "audio/ogg",
+        "opus" => "audio/opus",
+        "flac" => "audio/flac",
+        "wav" => "audio/wav",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
     }
 }
 
@@ -336,6 +884,42 @@ async fn health_check() -> impl Responder {
     HttpResponse::Ok().body("EncryptX backend server api is running")
 }
 
+/// Prometheus scrape endpoint with the Argon2 latency histograms.
+#[get("/metrics")]
+async fn metrics_endpoint() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics::kdf().render())
+}
+
+#[derive(Deserialize)]
+struct AdviceQuery {
+    /// Overrides `server.kdf_target_ms` for this request
+    target_ms: Option<u64>,
+}
+
+/// Tuning advisor: reports the p95 Argon2 latency of every parameter set in use and
+/// suggests lighter parameters for those above the target.
+#[get("/kdf/advice")]
+async fn kdf_advice(query: web::Query<AdviceQuery>, config: web::Data<Config>) -> impl Responder {
+    let target_ms = query
+        .target_ms
+        .filter(|&ms| ms > 0)
+        .unwrap_or(config.server.kdf_target_ms);
+    let advice = metrics::kdf().advise(Duration::from_millis(target_ms));
+    HttpResponse::Ok().json(serde_json::json!({
+        "target_ms": target_ms,
+        "kdf_profile": config.kdf_profile,
+        "parameter_sets": advice,
+    }))
+}
+
+/// Capability discovery: what this deployment supports, so clients can adapt to it.
+#[get("/capabilities")]
+async fn capabilities(config: web::Data<Config>) -> impl Responder {
+    HttpResponse::Ok().json(Capabilities::new(&config))
+}
+
 /// Main server entry point with CORS configuration and request logging.
 #[actix_web::main]
 /// Starts the EncryptX backend server with Actix Web, configuring CORS, logging, and REST endpoints for file encryption, decryption, and health checks.
@@ -348,19 +932,61 @@ async fn main() -> std::io::Result<()> {
     #[cfg(feature = "dhat-heap")]
     let _profiler = dhat::Profiler::new_heap();
     dotenvy::dotenv().ok();
-    if cli::run_cli().await? {
-        return Ok(());
-    }
-    println!("Starting EncryptX Backend Server...");
-    println!("Listening on http://127.0.0.1:8080");
-    HttpServer::new(|| {
-        let allowed_origins = std::env::var("ALLOWED_ORIGIN")
-            .unwrap_or_else(|_| "http://localhost:3000".to_string())
-            .split(',')
-            .map(|s| s.trim().to_string())
-            .collect::<Vec<_>>();
+    let args = cli::Cli::parse();
+    let json = args.json();
+    let result = match args.load_config() {
+        Ok(config) => cli::run(args, &config).await.map(|ran| (ran, config)),
+        Err(e) => Err(cli::CliError::InvalidInput(e.to_string())),
+    };
+    let config = match result {
+        Ok((true, _)) => return Ok(()),
+        Ok((false, config)) => config,
+        Err(e) => {
+            // Distinct exit codes let wrappers react without parsing messages
+            if json {
+                cli::print_json_error(&e);
+            } else {
+                eprintln!("{}", locale::message("error", &[("message", &e)]));
+            }
+            std::process::exit(e.exit_code());
+        }
+    };
+    let host = config.server.host.clone();
+    let port = config.server.port;
+    let watchdog: Watchdog = config
+        .server
+        .memory_limit_mb
+        .map(|mb| MemoryWatchdog::spawn(mb * 1024 * 1024));
+    let watchdog = web::Data::new(watchdog);
+    let throttle: DecryptThrottle = (config.server.throttle_max_backoff_secs > 0).then(|| {
+        Arc::new(Throttle::new(
+            config.server.throttle_free_failures,
+            Duration::from_secs(config.server.throttle_max_backoff_secs),
+        ))
+    });
+    let throttle = web::Data::new(throttle);
+    let config = web::Data::new(config);
+    logging::init(logging::Redactor {
+        show_filenames: config.server.log_filenames,
+    });
+    tracing::info!("Starting EncryptX Backend Server...");
+    tracing::info!("Listening on http://{host}:{port}");
+    HttpServer::new(move || {
+        // ALLOWED_ORIGIN from the environment takes precedence over the config file
+        let allowed_origins = match std::env::var("ALLOWED_ORIGIN") {
+            Ok(origins) => origins
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .collect::<Vec<_>>(),
+            Err(_) => config.server.allowed_origins.clone(),
+        };
         App::new()
-            .app_data(actix_web::web::PayloadConfig::new(1024 * 1024 * 1024)) // 1GB max file size
+            .app_data(config.clone())
+            .app_data(watchdog.clone())
+            .app_data(throttle.clone())
+            .app_data(web::PayloadConfig::new(
+                config.server.max_payload_mb * 1024 * 1024,
+            )) // 1GB max file size by default
             .wrap({
                 let mut cors = Cors::default();
                 for origin in &allowed_origins {
@@ -371,10 +997,21 @@ async fn main() -> std::io::Result<()> {
                         "x-enc-key",
                         "x-password",
                         "x-orig-filename",
+                        "x-chunk-size",
+                        "x-compression-level",
+                        "x-kdf-profile",
                         "content-type",
+                        "range",
+                        "x-share-passcode",
                     ])
                     .send_wildcard()
-                    .expose_headers(vec!["Content-Disposition"])
+                    .expose_headers(vec![
+                        "Content-Disposition",
+                        "Content-Range",
+                        "Accept-Ranges",
+                        "Retry-After",
+                        "X-Generated-Key",
+                    ])
                     .supports_credentials()
             })
             .wrap(
@@ -382,9 +1019,15 @@ async fn main() -> std::io::Result<()> {
             )
             .service(encrypt_file)
             .service(decrypt_file)
+            .service(stream_file)
+            .service(create_share)
+            .service(open_share)
             .service(health_check)
+            .service(metrics_endpoint)
+            .service(kdf_advice)
+            .service(capabilities)
     })
-    .bind(("0.0.0.0", 8080))?
+    .bind((host.as_str(), port))?
     .run()
     .await
 }
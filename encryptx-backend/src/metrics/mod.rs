@@ -0,0 +1,280 @@
+//!
+//! Argon2 latency metrics and the parameter tuning advisor.
+//!
+//! Every key derivation is timed and recorded per parameter set: as a Prometheus histogram
+//! (served by the API at `GET /metrics`) and as a window of recent samples. The advisor
+//! (`GET /kdf/advice`) computes the p95 latency of each set over that window and, when it
+//! exceeds the target, suggests lighter parameters that should bring it back in range.
+//!
+use crate::crypto::{Argon2Params, KdfProfile};
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+/// Upper bounds (seconds) of the latency histogram buckets.
+pub const LATENCY_BUCKETS: [f64; 10] = [0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Number of recent samples per parameter set the advisor looks at.
+const WINDOW: usize = 1024;
+
+/// Samples needed before the advisor judges a parameter set.
+pub const MIN_SAMPLES: usize = 20;
+
+/// Name of the histogram metric.
+const METRIC: &str = "encryptx_kdf_duration_seconds";
+
+/// An Argon2 parameter set, as used for labels and advice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct ParamSet {
+    pub memory_cost: u32,
+    pub time_cost: u32,
+    pub parallelism: u32,
+}
+
+impl From<&Argon2Params> for ParamSet {
+    fn from(params: &Argon2Params) -> Self {
+        Self {
+            memory_cost: params.memory_cost,
+            time_cost: params.time_cost,
+            parallelism: params.parallelism,
+        }
+    }
+}
+
+impl ParamSet {
+    fn labels(&self) -> String {
+        format!(
+            "memory_cost=\"{}\",time_cost=\"{}\",parallelism=\"{}\"",
+            self.memory_cost, self.time_cost, self.parallelism
+        )
+    }
+
+    /// Relative amount of work; Argon2 runtime grows linearly with memory times passes.
+    fn cost(&self) -> u64 {
+        u64::from(self.memory_cost) * u64::from(self.time_cost)
+    }
+}
+
+#[derive(Default)]
+struct Series {
+    /// Per-bucket counts (not cumulative)
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+    recent: VecDeque<f64>,
+}
+
+impl Series {
+    fn record(&mut self, seconds: f64) {
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|&le| seconds <= le) {
+            self.buckets[i] += 1;
+        }
+        self.count += 1;
+        self.sum += seconds;
+        if self.recent.len() == WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(seconds);
+    }
+
+    /// Nearest-rank 95th percentile of the recent samples.
+    fn p95(&self) -> Option<f64> {
+        if self.recent.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f64> = self.recent.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let rank = (sorted.len() * 95).div_ceil(100);
+        Some(sorted[rank.saturating_sub(1)])
+    }
+}
+
+/// Verdict of the advisor for one parameter set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdviceStatus {
+    /// p95 is within the target
+    Ok,
+    /// p95 exceeds the target
+    OverTarget,
+    /// Fewer than [`MIN_SAMPLES`] recent samples
+    InsufficientSamples,
+}
+
+/// Advisor output for one parameter set.
+#[derive(Debug, Clone, Serialize)]
+pub struct KdfAdvice {
+    pub params: ParamSet,
+    /// Samples in the advisor window
+    pub samples: usize,
+    /// Derivations recorded since startup
+    pub total: u64,
+    pub p95_ms: Option<f64>,
+    pub target_ms: u64,
+    pub status: AdviceStatus,
+    /// Lighter parameters expected to meet the target
+    pub suggested: Option<ParamSet>,
+    /// Strongest built-in profile expected to meet the target, if it is lighter than `params`
+    pub suggested_profile: Option<&'static str>,
+    pub message: String,
+}
+
+/// Argon2 latency recorder.
+pub struct KdfMetrics {
+    series: Mutex<BTreeMap<ParamSet, Series>>,
+}
+
+impl Default for KdfMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KdfMetrics {
+    pub const fn new() -> Self {
+        Self {
+            series: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Records the duration of one key derivation with `params`.
+    pub fn record(&self, params: &Argon2Params, elapsed: Duration) {
+        self.series
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(ParamSet::from(params))
+            .or_default()
+            .record(elapsed.as_secs_f64());
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let series = self.series.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "# HELP {METRIC} Argon2id key derivation latency by parameter set."
+        );
+        let _ = writeln!(out, "# TYPE {METRIC} histogram");
+        for (set, s) in series.iter() {
+            let labels = set.labels();
+            let mut cumulative = 0;
+            for (le, n) in LATENCY_BUCKETS.iter().zip(s.buckets) {
+                cumulative += n;
+                let _ = writeln!(out, "{METRIC}_bucket{{{labels},le=\"{le}\"}} {cumulative}");
+            }
+            let _ = writeln!(out, "{METRIC}_bucket{{{labels},le=\"+Inf\"}} {}", s.count);
+            let _ = writeln!(out, "{METRIC}_sum{{{labels}}} {}", s.sum);
+            let _ = writeln!(out, "{METRIC}_count{{{labels}}} {}", s.count);
+        }
+        let _ = writeln!(
+            out,
+            "# HELP encryptx_kdf_p95_seconds p95 Argon2id latency over the last {WINDOW} derivations."
+        );
+        let _ = writeln!(out, "# TYPE encryptx_kdf_p95_seconds gauge");
+        for (set, s) in series.iter() {
+            if let Some(p95) = s.p95() {
+                let _ = writeln!(out, "encryptx_kdf_p95_seconds{{{}}} {p95}", set.labels());
+            }
+        }
+        out
+    }
+
+    /// Judges every recorded parameter set against a p95 latency `target`.
+    pub fn advise(&self, target: Duration) -> Vec<KdfAdvice> {
+        let series = self.series.lock().unwrap_or_else(PoisonError::into_inner);
+        series
+            .iter()
+            .map(|(set, s)| advise_one(*set, s, target))
+            .collect()
+    }
+}
+
+fn advise_one(params: ParamSet, series: &Series, target: Duration) -> KdfAdvice {
+    let target_ms = target.as_millis() as u64;
+    let samples = series.recent.len();
+    let p95 = series.p95();
+    let mut advice = KdfAdvice {
+        params,
+        samples,
+        total: series.count,
+        p95_ms: p95.map(|s| s * 1000.0),
+        target_ms,
+        status: AdviceStatus::Ok,
+        suggested: None,
+        suggested_profile: None,
+        message: String::new(),
+    };
+    let p95 = match p95 {
+        Some(p95) if samples >= MIN_SAMPLES => p95,
+        _ => {
+            advice.status = AdviceStatus::InsufficientSamples;
+            advice.message = format!("Only {samples} samples; need {MIN_SAMPLES} to judge");
+            return advice;
+        }
+    };
+    let target = target.as_secs_f64();
+    if p95 <= target {
+        advice.message = "p95 latency is within the target".to_string();
+        return advice;
+    }
+
+    advice.status = AdviceStatus::OverTarget;
+    // Work budget that should land at the target, assuming latency scales with cost
+    let budget = (params.cost() as f64 * target / p95) as u64;
+    let floor = KdfProfile::Interactive.params().memory_cost;
+    let suggested = suggest(params, budget, floor);
+    advice.suggested_profile = [KdfProfile::Moderate, KdfProfile::Interactive]
+        .into_iter()
+        .find(|p| {
+            let set = ParamSet::from(&p.params());
+            set.cost() <= budget && set.cost() < params.cost()
+        })
+        .map(|p| p.name());
+    advice.message = match suggested {
+        Some(s) => format!(
+            "p95 {:.0} ms exceeds the {target_ms} ms target; try memory_cost {} and time_cost {}",
+            p95 * 1000.0,
+            s.memory_cost,
+            s.time_cost
+        ),
+        None => format!(
+            "p95 {:.0} ms exceeds the {target_ms} ms target even at the minimum recommended \
+             parameters; add CPU capacity or raise the target",
+            p95 * 1000.0
+        ),
+    };
+    advice.suggested = suggested;
+    advice
+}
+
+/// Picks lighter parameters within `budget`: passes are dropped first so the memory
+/// hardness is kept, then memory is reduced (in whole MiB), but never below `floor`.
+fn suggest(params: ParamSet, budget: u64, floor: u32) -> Option<ParamSet> {
+    let memory = u64::from(params.memory_cost);
+    let passes = (budget / memory.max(1)).max(1);
+    if passes < u64::from(params.time_cost) && memory * passes <= budget {
+        return Some(ParamSet {
+            time_cost: passes as u32,
+            ..params
+        });
+    }
+    let memory_cost = (budget.min(memory) / 1024 * 1024) as u32;
+    if memory_cost < floor {
+        return None;
+    }
+    let suggested = ParamSet {
+        memory_cost,
+        time_cost: 1,
+        ..params
+    };
+    (suggested.cost() < params.cost()).then_some(suggested)
+}
+
+/// The process-wide recorder fed by [`crate::crypto::derive_key_with_params`].
+pub fn kdf() -> &'static KdfMetrics {
+    static KDF: KdfMetrics = KdfMetrics::new();
+    &KDF
+}
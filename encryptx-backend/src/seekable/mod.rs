@@ -0,0 +1,330 @@
+//!
+//! Random access to chunked `.xd` files: [`SeekableReader`] is an [`AsyncRead`] and
+//! [`AsyncSeek`] over a file's plaintext that reads and decrypts only the chunks a read
+//! touches, so media players and partial reads of huge files don't decrypt the whole file.
+//!
+//! Chunks sit at fixed offsets (see [`chunked`]), so the chunk holding any plaintext byte
+//! follows from the header. That only maps to the original data when the body was stored
+//! uncompressed behind the `0x00` flag, as incompressible media is by default and any file
+//! is with `encrypt --no-compress`; a zstd stream can't be entered in the middle, so
+//! compressed bodies are refused. Padded bodies work too, and the padding is never read.
+//!
+//! Opening the file authenticates its first and final chunks, so a file cut off at a chunk
+//! boundary is refused up front. Every other chunk is authenticated when it is first read,
+//! and a chunk that fails is an [`io::ErrorKind::InvalidData`] error wrapping
+//! [`CryptoError::AuthenticationError`]. Only the last chunk read is kept, in zeroized
+//! memory.
+//!
+use crate::api::{self, ApiError};
+use crate::crypto::{self, CryptoError, chunked, padding, signing};
+use crate::format::{MAX_HEADER_LEN, NONCE_LEN, PASSWORD_MARKER, TAG_LEN, XdReader};
+use aes_gcm::{Aes256Gcm, KeyInit};
+use std::io::{self, SeekFrom};
+use std::ops::Range;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, ReadBuf};
+use zeroize::Zeroizing;
+
+/// How far fetching a chunk from the underlying file has got.
+enum Fetch {
+    Idle,
+    /// Seeking to the start of the chunk with this index
+    Seeking(u64),
+    /// Reading the chunk with this index; `filled` bytes of it are in the buffer
+    Reading {
+        index: u64,
+        filled: usize,
+    },
+}
+
+/// Plaintext of a chunked `.xd` file, decrypted a chunk at a time as it is read (see the
+/// module docs). Made by [`api::open_seekable`].
+pub struct SeekableReader<R> {
+    input: R,
+    cipher: Aes256Gcm,
+    nonce: [u8; NONCE_LEN],
+    aad: Vec<u8>,
+    chunk_size: u64,
+    /// File offset of the first chunk
+    body_offset: u64,
+    /// Ciphertext length, without a signature trailer
+    body_len: u64,
+    chunks: u64,
+    /// Where the original data lies in the decrypted body
+    data: Range<u64>,
+    /// Read position within the data
+    pos: u64,
+    /// Index and plaintext of the chunk last decrypted
+    current: Option<u64>,
+    plain: Zeroizing<Vec<u8>>,
+    fetch: Fetch,
+    /// The chunk being read, decrypted in place
+    sealed: Zeroizing<Vec<u8>>,
+}
+
+/// Opens `input` for random access with a password or key, as [`api::decrypt_file_bytes`]
+/// takes them.
+pub async fn open<R>(
+    mut input: R,
+    password: Option<&str>,
+    key: Option<&[u8]>,
+) -> Result<SeekableReader<R>, ApiError>
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+{
+    let reading = |e| api::stream_error(e, ApiError::Io);
+    let file_len = input.seek(SeekFrom::End(0)).await.map_err(reading)?;
+    // A signature trailer (see `signing`) follows the body; it is not checked here
+    let mut content_len = file_len;
+    if file_len >= signing::TRAILER_LEN as u64 {
+        let mut tail = [0u8; signing::TRAILER_LEN];
+        input
+            .seek(SeekFrom::End(-(signing::TRAILER_LEN as i64)))
+            .await
+            .map_err(reading)?;
+        input.read_exact(&mut tail).await.map_err(reading)?;
+        if signing::split(&tail).1.is_some() {
+            content_len -= signing::TRAILER_LEN as u64;
+        }
+    }
+
+    // Everything up to the first chunk, plus a tag's worth so the framing parses
+    let mut start = [0u8; 5];
+    if content_len < start.len() as u64 {
+        return Err(CryptoError::FormatError.into());
+    }
+    input.seek(SeekFrom::Start(0)).await.map_err(reading)?;
+    input.read_exact(&mut start).await.map_err(reading)?;
+    let offset = usize::from(start[0] == PASSWORD_MARKER);
+    let header_len = u32::from_be_bytes([
+        start[offset],
+        start[offset + 1],
+        start[offset + 2],
+        start[offset + 3],
+    ]) as usize;
+    let prefix_len = offset + 4 + header_len + NONCE_LEN + TAG_LEN;
+    if header_len > MAX_HEADER_LEN || prefix_len as u64 > content_len {
+        return Err(CryptoError::FormatError.into());
+    }
+    let mut prefix = vec![0u8; prefix_len];
+    input.seek(SeekFrom::Start(0)).await.map_err(reading)?;
+    input.read_exact(&mut prefix).await.map_err(reading)?;
+    let file = XdReader::parse(&prefix)?;
+
+    let chunk_size = match (file.header.chunk_size(), file.header.delta_id()) {
+        (Some(chunk_size), None) => chunk_size,
+        (Some(_), Some(_)) => {
+            return Err(ApiError::InvalidInput(
+                "Delta files can't be read at random; decrypt them whole".to_string(),
+            ));
+        }
+        (None, _) => {
+            return Err(ApiError::InvalidInput(
+                "Random access needs a chunked file (encrypt with --chunk-size)".to_string(),
+            ));
+        }
+    };
+    chunked::validate_chunk_size(chunk_size)?;
+    let file_key = crypto::file_key(&file, password.map(str::to_string), key).await?;
+    let cipher = Aes256Gcm::new_from_slice(file_key.as_slice())
+        .map_err(|_| CryptoError::DecryptionError("Failed to create cipher".to_string()))?;
+
+    let body_offset = file.ciphertext_offset as u64;
+    let body_len = content_len - body_offset;
+    let sealed_size = u64::from(chunk_size) + TAG_LEN as u64;
+    let chunks = body_len.div_ceil(sealed_size).max(1);
+    if body_len - (chunks - 1) * sealed_size < TAG_LEN as u64 {
+        return Err(CryptoError::AuthenticationError.into());
+    }
+    if chunks > u64::from(u32::MAX) + 1 {
+        return Err(CryptoError::FormatError.into());
+    }
+    let mut reader = SeekableReader {
+        nonce: file
+            .nonce
+            .try_into()
+            .map_err(|_| CryptoError::FormatError)?,
+        aad: file.aad().into_owned(),
+        input,
+        cipher,
+        chunk_size: u64::from(chunk_size),
+        body_offset,
+        body_len,
+        chunks,
+        data: 0..0,
+        pos: 0,
+        current: None,
+        plain: Zeroizing::new(Vec::new()),
+        fetch: Fetch::Idle,
+        sealed: Zeroizing::new(Vec::new()),
+    };
+
+    // The final chunk proves the file isn't truncated
+    let last = chunks - 1;
+    std::future::poll_fn(|cx| reader.poll_fetch(cx, last))
+        .await
+        .map_err(reading)?;
+    std::future::poll_fn(|cx| reader.poll_fetch(cx, 0))
+        .await
+        .map_err(reading)?;
+    // The first chunk says how the body is encoded
+    let body_len = body_len - chunks * TAG_LEN as u64;
+    let body = if padding::is_padded(&reader.plain) {
+        match padding::body_range(&reader.plain) {
+            Some(range) if range.end as u64 <= body_len => range.start as u64..range.end as u64,
+            _ => return Err(ApiError::InvalidPadding),
+        }
+    } else {
+        0..body_len
+    };
+    reader.data = match reader.plain.get(body.start as usize) {
+        Some(0x00) => body.start + 1..body.end,
+        Some(0x01) => {
+            return Err(ApiError::InvalidInput(
+                "The file is compressed and can't be read at random; encrypt it with --no-compress"
+                    .to_string(),
+            ));
+        }
+        // A body written by the crypto functions alone, without a flag, is the data itself
+        _ => body,
+    };
+    Ok(reader)
+}
+
+impl<R> SeekableReader<R> {
+    /// Size of the decrypted data in bytes.
+    pub fn len(&self) -> u64 {
+        self.data.end - self.data.start
+    }
+
+    /// Whether the decrypted data is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sealed length of chunk `index`, tag included.
+    fn sealed_len(&self, index: u64) -> usize {
+        let sealed_size = self.chunk_size + TAG_LEN as u64;
+        if index + 1 == self.chunks {
+            (self.body_len - index * sealed_size) as usize
+        } else {
+            sealed_size as usize
+        }
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> SeekableReader<R> {
+    /// Reads and decrypts chunk `index` into `plain`, unless it is there already.
+    ///
+    /// A fetch left unfinished for another chunk is dropped, once any seek it started has
+    /// completed.
+    fn poll_fetch(&mut self, cx: &mut Context<'_>, index: u64) -> Poll<io::Result<()>> {
+        loop {
+            if self.current == Some(index) {
+                return Poll::Ready(Ok(()));
+            }
+            match self.fetch {
+                Fetch::Idle => {
+                    let offset = self.body_offset + index * (self.chunk_size + TAG_LEN as u64);
+                    Pin::new(&mut self.input).start_seek(SeekFrom::Start(offset))?;
+                    self.fetch = Fetch::Seeking(index);
+                }
+                Fetch::Seeking(seeking) => {
+                    ready!(Pin::new(&mut self.input).poll_complete(cx))?;
+                    self.fetch = if seeking == index {
+                        let len = self.sealed_len(index);
+                        self.sealed.clear();
+                        self.sealed.resize(len, 0);
+                        Fetch::Reading { index, filled: 0 }
+                    } else {
+                        Fetch::Idle
+                    };
+                }
+                Fetch::Reading { index: reading, .. } if reading != index => {
+                    self.fetch = Fetch::Idle;
+                }
+                Fetch::Reading { filled, .. } if filled < self.sealed.len() => {
+                    let mut buf = ReadBuf::new(&mut self.sealed[filled..]);
+                    ready!(Pin::new(&mut self.input).poll_read(cx, &mut buf))?;
+                    let read = buf.filled().len();
+                    if read == 0 {
+                        self.fetch = Fetch::Idle;
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "the encrypted file ended inside a chunk",
+                        )));
+                    }
+                    self.fetch = Fetch::Reading {
+                        index,
+                        filled: filled + read,
+                    };
+                }
+                Fetch::Reading { .. } => {
+                    self.fetch = Fetch::Idle;
+                    self.current = None;
+                    chunked::open_chunk(
+                        &self.cipher,
+                        &self.nonce,
+                        &self.aad,
+                        index as u32,
+                        index + 1 == self.chunks,
+                        &mut self.sealed,
+                    )
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                    std::mem::swap(&mut self.plain, &mut self.sealed);
+                    self.current = Some(index);
+                }
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncRead for SeekableReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.pos >= this.len() || buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        // One chunk per call; callers read again for more
+        let offset = this.data.start + this.pos;
+        let index = offset / this.chunk_size;
+        ready!(this.poll_fetch(cx, index))?;
+        let within = (offset - index * this.chunk_size) as usize;
+        let n = buf
+            .remaining()
+            .min(this.plain.len() - within)
+            .min((this.len() - this.pos) as usize);
+        buf.put_slice(&this.plain[within..within + n]);
+        this.pos += n as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncSeek for SeekableReader<R> {
+    /// Moves the read position within the decrypted data; nothing is read until the next
+    /// read. Positions past the end read as end of file.
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        let target = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => this.len().checked_add_signed(delta),
+            SeekFrom::Current(delta) => this.pos.checked_add_signed(delta),
+        };
+        this.pos = target.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(self.pos))
+    }
+}
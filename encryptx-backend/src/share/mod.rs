@@ -0,0 +1,201 @@
+//!
+//! Share links for files in the server's `stream_dir`, guarded by rotating passcodes.
+//!
+//! A link's token seals the file name, the credential that opens the file, a TOTP seed and
+//! an expiry time under the server's `share_secret` (AES-256-GCM), so the server keeps no
+//! record of the links it hands out and any instance with the same secret can serve them.
+//! A forged or altered token fails to open, and an expired one is refused before its
+//! passcode is checked.
+//!
+//! The creator is shown the seed once, as base32 and as an `otpauth://` URI for an
+//! authenticator app, and passes passcodes on separately from the link. Passcodes follow
+//! RFC 6238 (HMAC-SHA1, 30-second steps, 6 digits) and one step of clock drift either way
+//! is accepted, so a leaked link and passcode pair stops working within about 90 seconds
+//! instead of at the link's expiry.
+//!
+use aes_gcm::{
+    Aes256Gcm, KeyInit, Nonce,
+    aead::{Aead, AeadCore, OsRng, Payload, rand_core::RngCore},
+};
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use thiserror::Error;
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+/// Seconds each passcode is current for.
+pub const PERIOD_SECS: u64 = 30;
+
+/// Digits in a passcode.
+pub const DIGITS: u32 = 6;
+
+/// Steps before and after the current one whose passcodes are still accepted.
+const DRIFT_STEPS: u64 = 1;
+
+/// Bytes of a TOTP seed, the 160 bits RFC 4226 recommends.
+const SEED_LEN: usize = 20;
+
+/// Associated data binding tokens to their purpose.
+const AAD: &[u8] = b"encryptx share v1";
+
+/// Nonce length of AES-256-GCM.
+const NONCE_LEN: usize = 12;
+
+/// Errors opening a share token.
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ShareError {
+    #[error("Invalid share token")]
+    Invalid,
+    #[error("Share link has expired")]
+    Expired,
+}
+
+/// What a share link grants: one stored file and the credential that opens it.
+#[derive(Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+pub struct Share {
+    /// Name of the file below `stream_dir`
+    pub file: String,
+    /// Password of a password file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Base64 key of a key file; neither is set for a file carrying its own key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// TOTP seed the recipient's passcodes are derived from
+    pub seed: Vec<u8>,
+    /// Unix time after which the link is refused
+    pub expires: u64,
+}
+
+/// Returns a fresh random TOTP seed.
+pub fn new_seed() -> Vec<u8> {
+    let mut seed = vec![0u8; SEED_LEN];
+    OsRng.fill_bytes(&mut seed);
+    seed
+}
+
+/// Seals `share` into a URL-safe token under the 32-byte server `secret`.
+pub fn seal(secret: &[u8], share: &Share) -> Result<String, ShareError> {
+    let cipher = Aes256Gcm::new_from_slice(secret).map_err(|_| ShareError::Invalid)?;
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let payload = Zeroizing::new(serde_json::to_vec(share).map_err(|_| ShareError::Invalid)?);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: &payload,
+                aad: AAD,
+            },
+        )
+        .map_err(|_| ShareError::Invalid)?;
+    Ok(URL_SAFE_NO_PAD.encode([nonce.as_slice(), &ciphertext].concat()))
+}
+
+/// Opens a token sealed by [`seal`], refusing it once `now` (Unix seconds) is past its expiry.
+pub fn open(secret: &[u8], token: &str, now: u64) -> Result<Share, ShareError> {
+    let cipher = Aes256Gcm::new_from_slice(secret).map_err(|_| ShareError::Invalid)?;
+    let sealed = URL_SAFE_NO_PAD
+        .decode(token)
+        .map_err(|_| ShareError::Invalid)?;
+    if sealed.len() < NONCE_LEN {
+        return Err(ShareError::Invalid);
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let payload = Zeroizing::new(
+        cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: AAD,
+                },
+            )
+            .map_err(|_| ShareError::Invalid)?,
+    );
+    let share: Share = serde_json::from_slice(&payload).map_err(|_| ShareError::Invalid)?;
+    if now > share.expires {
+        return Err(ShareError::Expired);
+    }
+    Ok(share)
+}
+
+/// The passcode for `seed` at Unix time `now` (RFC 6238 with HMAC-SHA1).
+pub fn passcode(seed: &[u8], now: u64) -> String {
+    step_passcode(seed, now / PERIOD_SECS)
+}
+
+fn step_passcode(seed: &[u8], step: u64) -> String {
+    let mut mac = <Hmac<Sha1> as Mac>::new_from_slice(seed).expect("HMAC takes keys of any length");
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    // Dynamic truncation (RFC 4226, section 5.3)
+    let offset = usize::from(digest[digest.len() - 1] & 0x0f);
+    let bits = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        bits % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+/// Checks a recipient's passcode against the current step and its neighbours.
+pub fn verify_passcode(seed: &[u8], passcode: &str, now: u64) -> bool {
+    let step = now / PERIOD_SECS;
+    // Every candidate is compared, in constant time, so timing doesn't tell which matched
+    (step.saturating_sub(DRIFT_STEPS)..=step + DRIFT_STEPS).fold(false, |matched, step| {
+        constant_time_eq(step_passcode(seed, step).as_bytes(), passcode.as_bytes()) | matched
+    })
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Encodes `bytes` as unpadded RFC 4648 base32, the form authenticator apps take seeds in.
+pub fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let (mut buffer, mut bits) = (0u16, 0);
+    for &byte in bytes {
+        buffer = (buffer << 8) | u16::from(byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(char::from(ALPHABET[usize::from((buffer >> bits) & 0x1f)]));
+        }
+    }
+    if bits > 0 {
+        encoded.push(char::from(
+            ALPHABET[usize::from((buffer << (5 - bits)) & 0x1f)],
+        ));
+    }
+    encoded
+}
+
+/// An `otpauth://totp/` URI for `seed`, which authenticator apps import (often as a QR code).
+pub fn otpauth_uri(seed: &[u8], label: &str) -> String {
+    format!(
+        "otpauth://totp/EncryptX:{}?secret={}&issuer=EncryptX&algorithm=SHA1&digits={DIGITS}\
+         &period={PERIOD_SECS}",
+        percent_encode(label),
+        base32(seed)
+    )
+}
+
+/// Percent-encodes everything but RFC 3986 unreserved characters.
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(byte).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
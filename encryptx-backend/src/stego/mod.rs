@@ -0,0 +1,171 @@
+//!
+//! Steganographic wrapping of encrypted files inside PNG cover images.
+//!
+//! The payload is hidden in the least-significant bit of every colour channel (alpha is
+//! left untouched), so the image looks unchanged. The embedded stream is
+//! `[magic "XDSG"][payload length (4 bytes, big-endian)][payload]`, written MSB first.
+//!
+//! This hides *that* something was sent, not *what*: the payload is already an `.xd`
+//! file, so confidentiality still comes from the encryption.
+//!
+use png::{BitDepth, ColorType, Transformations};
+use thiserror::Error;
+
+/// Marker written before the payload so decryption can auto-detect wrapped files.
+const STEGO_MAGIC: &[u8; 4] = b"XDSG";
+
+/// PNG file signature.
+const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+
+/// Error types for steganographic wrapping.
+#[derive(Error, Debug)]
+pub enum StegoError {
+    #[error("Invalid cover image: {0}")]
+    InvalidImage(String),
+    #[error("Cover image too small: needs {needed} bytes of capacity, has {available}")]
+    InsufficientCapacity { needed: usize, available: usize },
+    #[error("Image does not contain an EncryptX payload")]
+    NoPayload,
+}
+
+/// Decoded 8-bit image plus the information needed to write it back.
+struct Image {
+    width: u32,
+    height: u32,
+    color_type: ColorType,
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    /// Number of channels per pixel and whether the last one is alpha.
+    fn layout(&self) -> (usize, bool) {
+        match self.color_type {
+            ColorType::Grayscale => (1, false),
+            ColorType::GrayscaleAlpha => (2, true),
+            ColorType::Rgb => (3, false),
+            ColorType::Rgba => (4, true),
+            // Palette images are expanded on decode
+            ColorType::Indexed => (3, false),
+        }
+    }
+
+    /// Indices of the bytes whose LSBs carry data (every non-alpha channel).
+    fn carrier_indices(&self) -> impl Iterator<Item = usize> + '_ {
+        let (channels, has_alpha) = self.layout();
+        (0..self.pixels.len()).filter(move |i| !(has_alpha && i % channels == channels - 1))
+    }
+
+    /// Payload bytes that fit in this image, excluding the magic and length prefix.
+    fn capacity(&self) -> usize {
+        (self.carrier_indices().count() / 8).saturating_sub(STEGO_MAGIC.len() + 4)
+    }
+}
+
+/// Returns `true` if the data starts with the PNG signature.
+pub fn is_png(data: &[u8]) -> bool {
+    data.starts_with(PNG_SIGNATURE)
+}
+
+fn decode(data: &[u8]) -> Result<Image, StegoError> {
+    let mut decoder = png::Decoder::new(data);
+    // Normalise palette/low-bit/16-bit images to plain 8-bit channels
+    decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| StegoError::InvalidImage(e.to_string()))?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut pixels)
+        .map_err(|e| StegoError::InvalidImage(e.to_string()))?;
+    pixels.truncate(info.buffer_size());
+    if info.bit_depth != BitDepth::Eight {
+        return Err(StegoError::InvalidImage(
+            "unsupported bit depth".to_string(),
+        ));
+    }
+    Ok(Image {
+        width: info.width,
+        height: info.height,
+        color_type: info.color_type,
+        pixels,
+    })
+}
+
+fn encode(image: &Image) -> Result<Vec<u8>, StegoError> {
+    let mut out = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut out, image.width, image.height);
+        encoder.set_color(image.color_type);
+        encoder.set_depth(BitDepth::Eight);
+        let mut writer = encoder
+            .write_header()
+            .map_err(|e| StegoError::InvalidImage(e.to_string()))?;
+        writer
+            .write_image_data(&image.pixels)
+            .map_err(|e| StegoError::InvalidImage(e.to_string()))?;
+    }
+    Ok(out)
+}
+
+/// Returns how many payload bytes can be hidden in the given cover PNG.
+pub fn png_capacity(cover: &[u8]) -> Result<usize, StegoError> {
+    Ok(decode(cover)?.capacity())
+}
+
+/// Hides `payload` in the cover PNG and returns the new PNG file.
+pub fn embed_png(cover: &[u8], payload: &[u8]) -> Result<Vec<u8>, StegoError> {
+    let mut image = decode(cover)?;
+    let available = image.capacity();
+    if payload.len() > available || payload.len() > u32::MAX as usize {
+        return Err(StegoError::InsufficientCapacity {
+            needed: payload.len(),
+            available,
+        });
+    }
+
+    let mut stream = Vec::with_capacity(STEGO_MAGIC.len() + 4 + payload.len());
+    stream.extend_from_slice(STEGO_MAGIC);
+    stream.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    stream.extend_from_slice(payload);
+
+    let bits = stream
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |shift| (byte >> shift) & 1));
+    let indices: Vec<usize> = image.carrier_indices().collect();
+    for (index, bit) in indices.into_iter().zip(bits) {
+        image.pixels[index] = (image.pixels[index] & 0xFE) | bit;
+    }
+
+    encode(&image)
+}
+
+/// Extracts a payload previously hidden with [`embed_png`].
+pub fn extract_png(data: &[u8]) -> Result<Vec<u8>, StegoError> {
+    let image = decode(data)?;
+    let mut bytes = image.carrier_indices();
+    let mut next_byte = || -> Option<u8> {
+        let mut value = 0u8;
+        for _ in 0..8 {
+            value = (value << 1) | (image.pixels[bytes.next()?] & 1);
+        }
+        Some(value)
+    };
+
+    let mut prefix = [0u8; 8];
+    for slot in prefix.iter_mut() {
+        *slot = next_byte().ok_or(StegoError::NoPayload)?;
+    }
+    if &prefix[..4] != STEGO_MAGIC {
+        return Err(StegoError::NoPayload);
+    }
+    let len = u32::from_be_bytes([prefix[4], prefix[5], prefix[6], prefix[7]]) as usize;
+    if len > image.capacity() {
+        return Err(StegoError::NoPayload);
+    }
+
+    let mut payload = Vec::with_capacity(len);
+    for _ in 0..len {
+        payload.push(next_byte().ok_or(StegoError::NoPayload)?);
+    }
+    Ok(payload)
+}
@@ -0,0 +1,229 @@
+//!
+//! Brute-force throttling for the API server's `/decrypt` endpoint.
+//!
+//! Each failed decryption (a wrong password or key) counts against the client that sent
+//! it. The first few failures are free; after that every further failure blocks the client
+//! for twice as long as the one before, from one second up to a maximum. Blocked clients
+//! are answered with 429 and `Retry-After` before their upload is even read, so `/decrypt`
+//! can't be used to test passwords at the rate the server can run Argon2.
+//!
+//! Attempts are admitted under the same lock that counts them, so parallel guesses can't
+//! all slip past the check before the first failure is recorded: a client may have only as
+//! many decryptions in flight as it has free failures left, and just one once those are
+//! used up, which the next failure then blocks.
+//!
+//! A successful decryption doesn't reset the count, or an attacker could interleave a file
+//! they hold the password for. Instead a client is forgotten once it has stayed quiet for
+//! the maximum backoff after its last block. IPv6 clients are counted per /64, which is
+//! what a single host is usually given.
+//!
+use crate::config::Config;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::middleware::Next;
+use actix_web::{HttpResponse, web};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Clients tracked before forgotten ones are pruned.
+const MAX_TRACKED_CLIENTS: usize = 65_536;
+
+/// How long a client turned away for having too many decryptions in flight is asked to wait.
+const IN_FLIGHT_RETRY: Duration = Duration::from_secs(1);
+
+/// Failure history of one client.
+struct Client {
+    failures: u32,
+    blocked_until: Instant,
+    /// Decryptions admitted and not yet finished
+    in_flight: u32,
+}
+
+/// Tracks failed decryptions per client and decides how long each must wait.
+pub struct Throttle {
+    /// Failures allowed before backoff starts
+    free_failures: u32,
+    /// Longest a client is blocked for, and how long it must stay quiet to be forgotten
+    max_backoff: Duration,
+    clients: Mutex<HashMap<IpAddr, Client>>,
+}
+
+impl Throttle {
+    /// Creates a throttle allowing `free_failures` failures before blocking clients for
+    /// up to `max_backoff`.
+    pub fn new(free_failures: u32, max_backoff: Duration) -> Self {
+        Self {
+            free_failures,
+            max_backoff,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns how much longer `client` is blocked at `now`, or `None` if it may proceed.
+    pub fn blocked_for(&self, client: IpAddr, now: Instant) -> Option<Duration> {
+        let clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        clients
+            .get(&key(client))
+            .map(|c| c.blocked_until.saturating_duration_since(now))
+            .filter(|wait| !wait.is_zero())
+    }
+
+    /// Admits a decryption by `client` at `now`, or returns how long it must wait: while it
+    /// is blocked, or while it already has as many decryptions in flight as failures may
+    /// still go unpunished. The attempt counts as in flight until the returned guard drops.
+    pub fn begin(&self, client: IpAddr, now: Instant) -> Result<Attempt<'_>, Duration> {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = self.entry(&mut clients, client, now);
+        let wait = entry.blocked_until.saturating_duration_since(now);
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+        let free_left = self.free_failures.saturating_sub(entry.failures);
+        if entry.in_flight > 0 && entry.in_flight >= free_left {
+            return Err(IN_FLIGHT_RETRY);
+        }
+        entry.in_flight += 1;
+        Ok(Attempt {
+            throttle: self,
+            client,
+        })
+    }
+
+    /// Records a failed decryption by `client` at `now` and returns how long it is now
+    /// blocked for, if at all.
+    pub fn record_failure(&self, client: IpAddr, now: Instant) -> Option<Duration> {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        let client = self.entry(&mut clients, client, now);
+        client.failures = client.failures.saturating_add(1);
+        // Free failures block for nothing, but still count as the last activity
+        let backoff = self.backoff(client.failures);
+        client.blocked_until = client.blocked_until.max(now + backoff.unwrap_or_default());
+        backoff
+    }
+
+    /// The record of `client`, started over if it was forgotten; forgotten clients are
+    /// pruned first when too many are tracked.
+    fn entry<'a>(
+        &self,
+        clients: &'a mut HashMap<IpAddr, Client>,
+        client: IpAddr,
+        now: Instant,
+    ) -> &'a mut Client {
+        if clients.len() >= MAX_TRACKED_CLIENTS {
+            clients.retain(|_, c| !self.forgotten(c, now));
+        }
+        let client = clients.entry(key(client)).or_insert(Client {
+            failures: 0,
+            blocked_until: now,
+            in_flight: 0,
+        });
+        if self.forgotten(client, now) {
+            client.failures = 0;
+        }
+        client
+    }
+
+    /// Block imposed for a client's `failures`th failure.
+    fn backoff(&self, failures: u32) -> Option<Duration> {
+        let excess = failures.checked_sub(self.free_failures)?.checked_sub(1)?;
+        let backoff = Duration::from_secs(1u64.checked_shl(excess).unwrap_or(u64::MAX));
+        Some(backoff.min(self.max_backoff))
+    }
+
+    /// Whether `client` has stayed quiet long enough since its last block to start over.
+    fn forgotten(&self, client: &Client, now: Instant) -> bool {
+        client.in_flight == 0
+            && now.saturating_duration_since(client.blocked_until) >= self.max_backoff
+    }
+}
+
+/// A decryption admitted by [`Throttle::begin`], in flight until dropped.
+pub struct Attempt<'a> {
+    throttle: &'a Throttle,
+    client: IpAddr,
+}
+
+impl Attempt<'_> {
+    /// Ends the attempt as a failed decryption; see [`Throttle::record_failure`].
+    pub fn failed(self, now: Instant) -> Option<Duration> {
+        self.throttle.record_failure(self.client, now)
+    }
+}
+
+impl Drop for Attempt<'_> {
+    fn drop(&mut self) {
+        let mut clients = self
+            .throttle
+            .clients
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some(client) = clients.get_mut(&key(self.client)) {
+            client.in_flight = client.in_flight.saturating_sub(1);
+        }
+    }
+}
+
+/// The key a client is counted under: its IPv4 address, or the /64 of its IPv6 address.
+fn key(client: IpAddr) -> IpAddr {
+    match client {
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(Ipv6Addr::from(u128::from(ip) & !u128::from(u64::MAX))),
+        },
+        v4 => v4,
+    }
+}
+
+/// Failed-decryption throttle shared by the handlers; `None` when
+/// `server.throttle_max_backoff_secs` is 0.
+pub type DecryptThrottle = Option<Arc<Throttle>>;
+
+/// Address a request is throttled under, from the proxy headers if they are trusted.
+fn client_ip(req: &ServiceRequest, trust_forwarded_for: bool) -> Option<IpAddr> {
+    if !trust_forwarded_for {
+        return req.peer_addr().map(|addr| addr.ip());
+    }
+    let info = req.connection_info();
+    let addr = info.realip_remote_addr()?;
+    addr.parse::<IpAddr>()
+        .ok()
+        .or_else(|| addr.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
+
+/// Middleware for `/decrypt`: refuses clients blocked after failed decryptions, or with too
+/// many decryptions in flight, with 429 and `Retry-After` before their upload is read, and
+/// counts the 401s of everyone else.
+pub async fn throttle_decrypt(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let throttle = req
+        .app_data::<web::Data<DecryptThrottle>>()
+        .and_then(|throttle| throttle.get_ref().clone());
+    let client = req
+        .app_data::<web::Data<Config>>()
+        .and_then(|config| client_ip(&req, config.server.trust_forwarded_for));
+    let (Some(throttle), Some(client)) = (throttle, client) else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+
+    let attempt = match throttle.begin(client, Instant::now()) {
+        Ok(attempt) => attempt,
+        Err(wait) => {
+            let retry_after = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+            let response = HttpResponse::TooManyRequests()
+                .insert_header((RETRY_AFTER, retry_after.to_string()))
+                .body("Too many failed decryptions; retry later");
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+    };
+    let response = next.call(req).await?;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        attempt.failed(Instant::now());
+    }
+    Ok(response.map_into_left_body())
+}
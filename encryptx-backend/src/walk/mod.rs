@@ -0,0 +1,288 @@
+//!
+//! Directory traversal for the commands that encrypt whole directories.
+//!
+//! Entries come out in a fixed order (depth first, names sorted), so packing the same tree
+//! twice gives the same archive, and symbolic links are handled by an explicit
+//! [`SymlinkPolicy`] rather than by whatever the archive writer does by default. A
+//! [`Filter`] leaves out entries by gitignore-style patterns.
+//!
+use globset::{GlobBuilder, GlobMatcher};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What to do with the symbolic links met while walking a directory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Record each link as an entry of its own, holding the link target
+    #[default]
+    Store,
+    /// Walk what a link points at as if it were in its place; a link leading back into a
+    /// directory being walked is an error
+    Follow,
+    /// Leave links out
+    Skip,
+}
+
+/// What an [`Entry`] is, after the [`SymlinkPolicy`] has been applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    Dir,
+    File,
+    /// A stored link and its target, as read from the link
+    Symlink(PathBuf),
+    /// Devices, FIFOs and sockets
+    Other,
+}
+
+/// One item below the walked directory.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    /// Where the item is on disk (below the link it was reached through, when following)
+    pub path: PathBuf,
+    /// Path relative to the walked directory, using `/` separators
+    pub name: String,
+    pub kind: EntryKind,
+}
+
+/// Which entries a walk keeps, from gitignore-style patterns matched against the names
+/// relative to the walked directory.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    excludes: Vec<Rule>,
+    includes: Vec<Rule>,
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    matcher: GlobMatcher,
+    /// `!pattern`: keeps what earlier patterns excluded
+    negated: bool,
+    /// `pattern/`: only matches directories
+    dir_only: bool,
+}
+
+impl Filter {
+    /// Builds a filter from exclude patterns, in order, and include patterns.
+    ///
+    /// Excludes follow .gitignore: a pattern without a `/` matches a name at any depth, one
+    /// with a `/` is relative to the walked directory, a trailing `/` only matches
+    /// directories, and `*` doesn't cross a `/` while `**` does. The last matching pattern
+    /// decides, so `!pattern` keeps something an earlier pattern excluded. An excluded
+    /// directory isn't walked at all. If there are includes, only files matching one of
+    /// them, or inside a directory matching one, are kept, and directories left empty are
+    /// dropped; excludes still apply to them.
+    pub fn new(excludes: &[String], includes: &[String]) -> Result<Self, String> {
+        let excludes = excludes
+            .iter()
+            .map(|pattern| Rule::parse(pattern))
+            .collect::<Result<_, _>>()?;
+        let includes = includes
+            .iter()
+            .map(|pattern| match Rule::parse(pattern)? {
+                rule if rule.negated => Err(format!(
+                    "Include pattern '{pattern}' can't be negated; exclude it instead"
+                )),
+                rule => Ok(rule),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Filter { excludes, includes })
+    }
+
+    /// The patterns of an ignore file such as `.encryptxignore`: one per line, skipping
+    /// blank lines and `#` comments.
+    pub fn patterns_in(contents: &str) -> Vec<String> {
+        contents
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Whether a walk would list the file `name` (relative to the walked directory, with `/`
+    /// separators): neither it nor a directory it is in is excluded, and the includes keep it.
+    pub fn keeps_file(&self, name: &str) -> bool {
+        let mut parents = name.match_indices('/').map(|(at, _)| &name[..at]);
+        !parents.any(|dir| self.excludes(dir, true))
+            && !self.excludes(name, false)
+            && self.includes(name)
+    }
+
+    fn excludes(&self, name: &str, is_dir: bool) -> bool {
+        let mut excluded = false;
+        for rule in &self.excludes {
+            if rule.matches(name, is_dir) {
+                excluded = !rule.negated;
+            }
+        }
+        excluded
+    }
+
+    /// Whether a file is kept by the includes, itself or through a directory it is in.
+    fn includes(&self, name: &str) -> bool {
+        if self.includes.is_empty() {
+            return true;
+        }
+        let parents = name.match_indices('/').map(|(at, _)| &name[..at]);
+        self.includes.iter().any(|rule| {
+            rule.matches(name, false) || parents.clone().any(|dir| rule.matches(dir, true))
+        })
+    }
+}
+
+impl Rule {
+    fn parse(pattern: &str) -> Result<Self, String> {
+        let (negated, glob) = match pattern.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            // `\!` and `\#` start a pattern with a literal `!` or `#`
+            None => (false, pattern.strip_prefix('\\').unwrap_or(pattern)),
+        };
+        let dir_only = glob.ends_with('/');
+        let glob = glob.trim_end_matches('/');
+        let glob = if glob.contains('/') {
+            glob.trim_start_matches('/').to_string()
+        } else {
+            format!("**/{glob}")
+        };
+        let matcher = GlobBuilder::new(&glob)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| format!("Invalid pattern '{pattern}': {e}"))?
+            .compile_matcher();
+        Ok(Rule {
+            matcher,
+            negated,
+            dir_only,
+        })
+    }
+
+    fn matches(&self, name: &str, is_dir: bool) -> bool {
+        (is_dir || !self.dir_only) && self.matcher.is_match(name)
+    }
+}
+
+/// Lists everything below `root` that `filter` keeps, each directory before its contents.
+pub fn walk(root: &Path, symlinks: SymlinkPolicy, filter: &Filter) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    // Only followed links can lead back up the tree
+    let mut ancestors = Vec::new();
+    if symlinks == SymlinkPolicy::Follow {
+        ancestors.push(fs::canonicalize(root)?);
+    }
+    let walk = Walk {
+        root,
+        symlinks,
+        filter,
+    };
+    walk.dir(root, &mut ancestors, &mut entries)?;
+    Ok(entries)
+}
+
+struct Walk<'a> {
+    root: &'a Path,
+    symlinks: SymlinkPolicy,
+    filter: &'a Filter,
+}
+
+impl Walk<'_> {
+    fn dir(
+        &self,
+        dir: &Path,
+        ancestors: &mut Vec<PathBuf>,
+        entries: &mut Vec<Entry>,
+    ) -> io::Result<()> {
+        let Walk {
+            root,
+            symlinks,
+            filter,
+        } = *self;
+        let mut items = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+        items.sort_by_key(|item| item.file_name());
+        for item in items {
+            let path = item.path();
+            let name = relative_name(root, &path);
+            let file_type = item.file_type()?;
+            let kind = if file_type.is_symlink() {
+                match symlinks {
+                    SymlinkPolicy::Skip => continue,
+                    SymlinkPolicy::Store => EntryKind::Symlink(fs::read_link(&path)?),
+                    SymlinkPolicy::Follow => match fs::metadata(&path) {
+                        Ok(target) => kind_of(&target.file_type()),
+                        // A broken link that is excluded anyway needn't stop the walk
+                        Err(_) if filter.excludes(&name, false) => continue,
+                        Err(e) => {
+                            return Err(io::Error::new(
+                                e.kind(),
+                                format!("can't follow symlink '{}': {e}", path.display()),
+                            ));
+                        }
+                    },
+                }
+            } else {
+                kind_of(&file_type)
+            };
+
+            if filter.excludes(&name, kind == EntryKind::Dir) {
+                continue;
+            }
+            if kind != EntryKind::Dir {
+                if filter.includes(&name) {
+                    entries.push(Entry { path, name, kind });
+                }
+                continue;
+            }
+            let canonical = if symlinks == SymlinkPolicy::Follow {
+                let canonical = fs::canonicalize(&path)?;
+                if ancestors.contains(&canonical) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "symlink loop: '{}' leads back to '{}'",
+                            path.display(),
+                            canonical.display()
+                        ),
+                    ));
+                }
+                Some(canonical)
+            } else {
+                None
+            };
+            entries.push(Entry {
+                path: path.clone(),
+                name,
+                kind,
+            });
+            let kept = entries.len();
+            ancestors.extend(canonical);
+            self.dir(&path, ancestors, entries)?;
+            if symlinks == SymlinkPolicy::Follow {
+                ancestors.pop();
+            }
+            // Directories only hold what the includes keep
+            if !filter.includes.is_empty() && entries.len() == kept {
+                entries.pop();
+            }
+        }
+        Ok(())
+    }
+}
+
+fn kind_of(file_type: &fs::FileType) -> EntryKind {
+    if file_type.is_dir() {
+        EntryKind::Dir
+    } else if file_type.is_file() {
+        EntryKind::File
+    } else {
+        EntryKind::Other
+    }
+}
+
+fn relative_name(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
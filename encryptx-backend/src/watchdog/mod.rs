@@ -0,0 +1,109 @@
+//!
+//! Memory watchdog for the API server.
+//!
+//! A background thread samples the process's resident set size (RSS). Before an Argon2
+//! derivation, a password-mode request reserves the memory it is about to allocate. If the
+//! last sample plus all outstanding reservations would exceed the limit, the request is
+//! turned away (the server answers 503 with `Retry-After`) rather than letting the OOM killer
+//! take down every request in flight. Key-mode requests allocate little and are never held back.
+//!
+//! RSS is read from `/proc/self/status`, so the watchdog only acts on Linux. Elsewhere it
+//! has no samples and admits everything.
+//!
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// How often RSS is sampled.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Reads the current resident set size in bytes.
+pub fn current_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kib: u64 = line
+        .trim_start_matches("VmRSS:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+/// Admission control for memory-hungry requests.
+pub struct MemoryWatchdog {
+    /// Limit in bytes
+    limit: u64,
+    /// Last RSS sample in bytes (0 until the first sample)
+    rss: AtomicU64,
+    /// Memory promised to requests that are still running
+    reserved: Arc<AtomicU64>,
+}
+
+/// Memory held for an admitted request; released when dropped.
+pub struct Reservation {
+    bytes: u64,
+    reserved: Arc<AtomicU64>,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.reserved.fetch_sub(self.bytes, Ordering::AcqRel);
+    }
+}
+
+impl MemoryWatchdog {
+    /// Creates a watchdog for `limit` bytes without starting the sampler.
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            rss: AtomicU64::new(0),
+            reserved: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Creates a watchdog and starts sampling RSS every [`SAMPLE_INTERVAL`].
+    pub fn spawn(limit: u64) -> Arc<Self> {
+        let watchdog = Arc::new(Self::new(limit));
+        let weak = Arc::downgrade(&watchdog);
+        std::thread::Builder::new()
+            .name("memory-watchdog".to_string())
+            .spawn(move || {
+                while let Some(watchdog) = weak.upgrade() {
+                    if let Some(rss) = current_rss() {
+                        watchdog.record_rss(rss);
+                    }
+                    drop(watchdog);
+                    std::thread::sleep(SAMPLE_INTERVAL);
+                }
+            })
+            .expect("Failed to start memory watchdog");
+        watchdog
+    }
+
+    /// Stores an RSS sample.
+    pub fn record_rss(&self, rss: u64) {
+        self.rss.store(rss, Ordering::Release);
+    }
+
+    /// Last RSS sample in bytes.
+    pub fn rss(&self) -> u64 {
+        self.rss.load(Ordering::Acquire)
+    }
+
+    /// Reserves `bytes` for a request, or returns `None` if that would cross the limit.
+    pub fn try_reserve(&self, bytes: u64) -> Option<Reservation> {
+        let rss = self.rss();
+        self.reserved
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |reserved| {
+                let total = rss.saturating_add(reserved).saturating_add(bytes);
+                (total <= self.limit).then_some(reserved + bytes)
+            })
+            .ok()?;
+        Some(Reservation {
+            bytes,
+            reserved: Arc::clone(&self.reserved),
+        })
+    }
+}
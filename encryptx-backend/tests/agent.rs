@@ -0,0 +1,80 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::cli;
+use std::fs;
+use std::path::Path;
+use std::process::{Output, Stdio};
+use std::time::Duration;
+use tempfile::tempdir;
+
+fn run(dir: &Path, args: &[&str]) -> Output {
+    cli()
+        .args(args)
+        .current_dir(dir)
+        .env("ENCRYPTX_AGENT_SOCK", dir.join("agent.sock"))
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn agent_reopens_files_without_the_password() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("ledger.txt"), b"cached\n").unwrap();
+    let out = run(
+        dir.path(),
+        &["encrypt", "--file", "ledger.txt", "--password", "pw"],
+    );
+    assert!(out.status.success(), "{out:?}");
+    let decrypt = |credential: &[&str]| {
+        let mut args = vec![
+            "--json",
+            "decrypt",
+            "--file",
+            "ledger.xd",
+            "-o",
+            "out.txt",
+            "--force",
+        ];
+        args.extend(credential);
+        run(dir.path(), &args)
+    };
+
+    // Without an agent, nothing is cached
+    assert_eq!(decrypt(&["--password", "pw"]).status.code(), Some(0));
+    assert_eq!(decrypt(&[]).status.code(), Some(2));
+
+    let mut agent = cli()
+        .args(["agent", "start", "--ttl", "1h"])
+        .current_dir(dir.path())
+        .env("ENCRYPTX_AGENT_SOCK", dir.path().join("agent.sock"))
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    for _ in 0..100 {
+        if dir.path().join("agent.sock").exists() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    assert!(decrypt(&["--password", "pw"]).status.success());
+    let out = decrypt(&[]);
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["mode"], "agent");
+    assert_eq!(fs::read(dir.path().join("out.txt")).unwrap(), b"cached\n");
+
+    let out = run(dir.path(), &["--json", "agent", "status"]);
+    let status: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(status["entries"], 1);
+    assert_eq!(status["ttl_secs"], 3600);
+
+    assert!(run(dir.path(), &["agent", "clear"]).status.success());
+    assert_eq!(decrypt(&[]).status.code(), Some(2));
+
+    assert!(run(dir.path(), &["agent", "stop"]).status.success());
+    assert!(agent.wait().unwrap().success());
+    assert!(!dir.path().join("agent.sock").exists());
+}
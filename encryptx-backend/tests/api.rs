@@ -0,0 +1,196 @@
+use encryptx_backend::api;
+use encryptx_backend::crypto::{self, CryptoError, PasswordKdf, padding};
+
+#[tokio::test]
+async fn encrypt_file_returns_the_generated_key() {
+    let outcome = api::encrypt_file(b"keep this", None, None, "note.txt")
+        .await
+        .unwrap();
+    let key = outcome.generated_key.expect("a key is generated");
+    assert_eq!(outcome.metadata.mode, "key");
+    assert_eq!(outcome.metadata.filename, "note.txt");
+    assert_eq!(outcome.metadata.original_size, 9);
+    let (plain, _) = api::decrypt_file_bytes(&outcome.ciphertext, None, Some(key.as_slice()))
+        .await
+        .unwrap();
+    assert_eq!(plain, b"keep this");
+
+    // Given credentials, nothing is generated
+    let outcome = api::encrypt_file(b"keep this", None, Some(&[9u8; 32]), "note.txt")
+        .await
+        .unwrap();
+    assert!(outcome.generated_key.is_none());
+
+    // Errors are typed, so a wrong key is told apart from a broken file
+    let result = api::decrypt_file_bytes(&outcome.ciphertext, None, Some(&[8u8; 32])).await;
+    assert!(matches!(
+        result,
+        Err(api::ApiError::Crypto(CryptoError::AuthenticationError))
+    ));
+    let result = api::decrypt_file_bytes(b"not an xd file", None, Some(&[9u8; 32])).await;
+    assert!(matches!(result, Err(api::ApiError::Crypto(_))));
+
+    // The old entry point still refuses to encrypt without credentials
+    #[allow(deprecated)]
+    let result = api::encrypt_file_bytes(b"keep this", None, None, "note.txt").await;
+    assert!(matches!(result, Err(api::ApiError::InvalidInput(_))));
+}
+
+#[tokio::test]
+async fn decrypt_reports_how_the_file_was_made() {
+    let log = b"provenance\n".repeat(64);
+    let outcome = api::encrypt_file(&log, Some("pw"), None, "log.txt")
+        .await
+        .unwrap();
+    let (plain, metadata) = api::decrypt_file_bytes(&outcome.ciphertext, Some("pw"), None)
+        .await
+        .unwrap();
+    assert_eq!(plain, log);
+    assert_eq!(metadata.filename, "log.txt");
+    assert_eq!(metadata.version, 3);
+    assert_eq!(metadata.cipher, "aes-256-gcm");
+    assert!(matches!(metadata.kdf, Some(PasswordKdf::Argon2id(_))));
+    assert_eq!(metadata.compression, Some("zstd"));
+    assert!(!metadata.padded);
+    assert!(metadata.timestamp.is_some());
+
+    // A padded body that was never compressed, in a key-mode file
+    let body = padding::pad(b"raw", padding::Padding::Bucket(64));
+    let encrypted = crypto::encrypt_with_header(&body, &[3u8; 32], "").unwrap();
+    let (plain, metadata) = api::decrypt_file_bytes(&encrypted, None, None)
+        .await
+        .unwrap();
+    assert_eq!(plain, b"raw");
+    assert_eq!(metadata.kdf, None);
+    assert_eq!(metadata.compression, None);
+    assert!(metadata.padded);
+    // Anonymous files record no timestamp
+    assert_eq!(metadata.timestamp, None);
+}
+
+#[test]
+fn decompression_stops_at_the_limit() {
+    // A megabyte of zeros compresses to a few dozen bytes
+    let zeros = vec![0u8; 1 << 20];
+    let mut body = vec![0x01];
+    body.extend(zstd::encode_all(&zeros[..], 19).unwrap());
+    assert!(body.len() < 1024);
+
+    let plain = api::decode_body_with_limit(body.clone(), 1 << 20).unwrap();
+    assert_eq!(plain.len(), 1 << 20);
+    let result = api::decode_body_with_limit(body.clone(), (1 << 20) - 1);
+    assert!(matches!(
+        result,
+        Err(api::ApiError::DecompressedTooLarge { limit }) if limit == (1 << 20) - 1
+    ));
+
+    // A stream that doesn't state its size is cut off while decoding
+    let mut encoder = zstd::stream::write::Encoder::new(vec![0x01], 3).unwrap();
+    std::io::Write::write_all(&mut encoder, &zeros).unwrap();
+    let unsized_body = encoder.finish().unwrap();
+    assert!(zstd::zstd_safe::get_frame_content_size(&unsized_body[1..]).is_ok_and(|s| s.is_none()));
+    let result = api::decode_body_with_limit(unsized_body, 4096);
+    assert!(matches!(
+        result,
+        Err(api::ApiError::DecompressedTooLarge { .. })
+    ));
+}
+
+#[tokio::test]
+async fn chunked_files_are_compressed_and_decrypted_as_streams() {
+    let data: Vec<u8> = (0..200_000u32)
+        .flat_map(|i| (i % 97).to_be_bytes())
+        .collect();
+    let outcome = api::encrypt_file_chunked(&data, None, None, "s.bin", 4096)
+        .await
+        .unwrap();
+    assert!(outcome.metadata.compressed_size < data.len());
+    let key = outcome.generated_key.unwrap();
+    let (plain, metadata) =
+        api::decrypt_file_bytes(&outcome.ciphertext, None, Some(key.as_slice()))
+            .await
+            .unwrap();
+    assert_eq!(plain, data);
+    assert_eq!(metadata.chunk_size, Some(4096));
+    assert_eq!(metadata.compression, Some("zstd"));
+
+    // A tampered chunk is an authentication failure, not a decompression error
+    let mut tampered = outcome.ciphertext.clone();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    let result = api::decrypt_file_bytes(&tampered, None, Some(key.as_slice())).await;
+    assert!(matches!(
+        result,
+        Err(api::ApiError::Crypto(CryptoError::AuthenticationError))
+    ));
+
+    // The limit applies while streaming
+    let result =
+        api::decrypt_file_with_limit(&outcome.ciphertext, None, Some(key.as_slice()), 1000).await;
+    assert!(matches!(
+        result,
+        Err(api::ApiError::DecompressedTooLarge { limit: 1000 })
+    ));
+
+    let outcome = api::encrypt_file_chunked(b"by password", Some("pw"), None, "p.txt", 4096)
+        .await
+        .unwrap();
+    assert_eq!(outcome.metadata.mode, "password");
+    let (plain, _) = api::decrypt_file_bytes(&outcome.ciphertext, Some("pw"), None)
+        .await
+        .unwrap();
+    assert_eq!(plain, b"by password");
+}
+
+#[tokio::test]
+async fn seekable_reader_decrypts_only_the_chunks_it_reads() {
+    use std::io::{Cursor, SeekFrom};
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    // Media is stored uncompressed, so its chunks map straight to its bytes
+    let data: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+    let outcome = api::encrypt_file_chunked(&data, None, Some(&[7u8; 32]), "clip.mp4", 4096)
+        .await
+        .unwrap();
+    let mut reader = api::open_seekable(Cursor::new(&outcome.ciphertext), None, Some(&[7u8; 32]))
+        .await
+        .unwrap();
+    assert_eq!(reader.len(), data.len() as u64);
+    reader.seek(SeekFrom::Start(10_000)).await.unwrap();
+    let mut range = vec![0u8; 5000];
+    reader.read_exact(&mut range).await.unwrap();
+    assert_eq!(range, data[10_000..15_000]);
+    reader.seek(SeekFrom::End(-10)).await.unwrap();
+    let mut tail = Vec::new();
+    reader.read_to_end(&mut tail).await.unwrap();
+    assert_eq!(tail, data[data.len() - 10..]);
+
+    // A damaged chunk fails when it is read, and not before
+    let parts = api::part_boundaries(&outcome.ciphertext).unwrap();
+    let mut tampered = outcome.ciphertext.clone();
+    tampered[parts[3].start + 1] ^= 1;
+    let mut reader = api::open_seekable(Cursor::new(&tampered), None, Some(&[7u8; 32]))
+        .await
+        .unwrap();
+    let mut head = vec![0u8; 100];
+    reader.read_exact(&mut head).await.unwrap();
+    assert_eq!(head, data[..100]);
+    reader.seek(SeekFrom::Start(13_000)).await.unwrap();
+    let error = reader.read_exact(&mut head).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+    // A file cut at a chunk boundary is refused up front
+    let cut = &outcome.ciphertext[..parts[3].end];
+    let result = api::open_seekable(Cursor::new(cut), None, Some(&[7u8; 32])).await;
+    assert!(matches!(
+        result,
+        Err(api::ApiError::Crypto(CryptoError::AuthenticationError))
+    ));
+
+    // A zstd stream can't be entered in the middle
+    let outcome = api::encrypt_file_chunked(&[b'a'; 20_000], None, Some(&[7u8; 32]), "a.txt", 4096)
+        .await
+        .unwrap();
+    let result = api::open_seekable(Cursor::new(&outcome.ciphertext), None, Some(&[7u8; 32])).await;
+    assert!(matches!(result, Err(api::ApiError::InvalidInput(_))));
+}
@@ -0,0 +1,97 @@
+#![cfg(feature = "archive-ingest")]
+
+use encryptx_backend::interop::archive::{self, ArchiveKind};
+use encryptx_backend::interop::{ArchiveEntry, InteropError, zip_aes};
+use sevenz_rust::{AesEncoderOptions, Password, SevenZArchiveEntry, SevenZMethod, SevenZWriter};
+
+const LIMIT: u64 = 1024 * 1024;
+
+#[test]
+fn zip_aes_archive_round_trips() {
+    let entries = vec![ArchiveEntry {
+        name: "notes/todo.txt".to_string(),
+        data: b"buy milk".to_vec(),
+    }];
+    let zip = zip_aes::write_archive(&entries, "hunter2").unwrap();
+    assert_eq!(archive::detect(&zip), Some(ArchiveKind::Zip));
+
+    let read = archive::read_entries(ArchiveKind::Zip, &zip, "hunter2", LIMIT).unwrap();
+    assert_eq!(read.len(), 1);
+    assert_eq!(read[0].name, "notes/todo.txt");
+    assert_eq!(read[0].data, b"buy milk");
+
+    assert!(matches!(
+        archive::read_entries(ArchiveKind::Zip, &zip, "wrong", LIMIT),
+        Err(InteropError::WrongPassword)
+    ));
+}
+
+#[test]
+fn encrypted_7z_archive_is_extracted() {
+    let mut writer = SevenZWriter::new(std::io::Cursor::new(Vec::new())).unwrap();
+    writer.set_content_methods(vec![
+        AesEncoderOptions::new(Password::from("hunter2")).into(),
+        SevenZMethod::LZMA2.into(),
+    ]);
+    let mut entry = SevenZArchiveEntry::new();
+    entry.name = "secret.txt".to_string();
+    entry.has_stream = true;
+    writer
+        .push_archive_entry(entry, Some(&b"seven zip payload"[..]))
+        .unwrap();
+    let data = writer.finish().unwrap().into_inner();
+    assert_eq!(archive::detect(&data), Some(ArchiveKind::SevenZip));
+
+    let read = archive::read_entries(ArchiveKind::SevenZip, &data, "hunter2", LIMIT).unwrap();
+    assert_eq!(read.len(), 1);
+    assert_eq!(read[0].data, b"seven zip payload");
+}
+
+#[test]
+fn archives_expanding_past_the_limit_are_refused() {
+    let entries: Vec<ArchiveEntry> = ["a.bin", "b.bin"]
+        .iter()
+        .map(|name| ArchiveEntry {
+            name: name.to_string(),
+            data: vec![0u8; 600],
+        })
+        .collect();
+    let zip = zip_aes::write_archive(&entries, "hunter2").unwrap();
+    // Each entry fits on its own, but the limit holds across all of them
+    assert!(matches!(
+        archive::read_entries(ArchiveKind::Zip, &zip, "hunter2", 1000),
+        Err(InteropError::TooLarge { limit: 1000 })
+    ));
+    assert_eq!(
+        archive::read_entries(ArchiveKind::Zip, &zip, "hunter2", 1200)
+            .unwrap()
+            .len(),
+        2
+    );
+
+    let mut writer = SevenZWriter::new(std::io::Cursor::new(Vec::new())).unwrap();
+    writer.set_content_methods(vec![SevenZMethod::LZMA2.into()]);
+    for entry in &entries {
+        let mut archive_entry = SevenZArchiveEntry::new();
+        archive_entry.name = entry.name.clone();
+        archive_entry.has_stream = true;
+        writer
+            .push_archive_entry(archive_entry, Some(&entry.data[..]))
+            .unwrap();
+    }
+    let data = writer.finish().unwrap().into_inner();
+    assert!(matches!(
+        archive::read_entries(ArchiveKind::SevenZip, &data, "", 1000),
+        Err(InteropError::TooLarge { limit: 1000 })
+    ));
+}
+
+#[test]
+fn unsafe_entry_paths_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let entries = vec![ArchiveEntry {
+        name: "../escape.txt".to_string(),
+        data: Vec::new(),
+    }];
+    assert!(archive::write_entries(&entries, dir.path(), false).is_err());
+}
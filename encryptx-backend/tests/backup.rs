@@ -0,0 +1,175 @@
+mod common;
+
+use common::run;
+use encryptx_backend::backup::{Chunker, EntryType, Repository};
+use std::fs;
+use tempfile::tempdir;
+
+// The repository is opened with these bytes directly
+const KEY: &str = "BAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQ=";
+
+#[tokio::test]
+async fn snapshots_share_unchanged_chunks() {
+    let dir = tempdir().unwrap();
+    let src = dir.path().join("src");
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(src.join("docs")).unwrap();
+    fs::write(src.join("docs/notes.txt"), b"remember the milk").unwrap();
+    fs::write(src.join("data.bin"), vec![7u8; 3 << 20]).unwrap();
+    let (src_path, repo_path) = (src.to_str().unwrap(), repo.to_str().unwrap());
+    let backup = |name: &str| {
+        let output = run(&[
+            "--json", "backup", src_path, "--repo", repo_path, "--key", KEY, "--name", name,
+        ]);
+        assert!(output.status.success(), "{output:?}");
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()
+    };
+
+    let first = backup("first");
+    assert_eq!(
+        (first["files"].clone(), first["dirs"].clone()),
+        (2.into(), 1.into())
+    );
+    assert!(first["new_chunks"].as_u64().unwrap() > 0);
+    let second = backup("second");
+    assert_eq!(second["new_chunks"], 0);
+    assert_eq!(second["added_bytes"], 0);
+    assert_ne!(first["snapshot"], second["snapshot"]);
+
+    let repository = Repository::open(&repo, None, Some(&[4u8; 32]))
+        .await
+        .unwrap();
+    let ids = repository.snapshots().unwrap();
+    assert_eq!(ids.len(), 2);
+    let snapshot = repository.load_snapshot(&ids[1]).await.unwrap();
+    assert_eq!(snapshot.name.as_deref(), Some("second"));
+    let notes = snapshot
+        .entries
+        .iter()
+        .find(|e| e.path == "docs/notes.txt")
+        .unwrap();
+    assert_eq!(notes.kind, EntryType::File);
+    let chunk = repository.load_chunk(&notes.chunks[0]).await.unwrap();
+    assert_eq!(chunk.as_slice(), b"remember the milk");
+
+    // A wrong credential can't open the repository
+    let wrong = run(&[
+        "backup",
+        src_path,
+        "--repo",
+        repo_path,
+        "--password",
+        "not the right one 42!",
+    ]);
+    assert_eq!(wrong.status.code(), Some(4));
+}
+
+#[test]
+fn restore_brings_back_a_snapshot_or_part_of_it() {
+    let dir = tempdir().unwrap();
+    let src = dir.path().join("src");
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(src.join("photos/2023")).unwrap();
+    fs::write(src.join("photos/2023/beach.jpg"), b"sand").unwrap();
+    fs::write(src.join("todo.txt"), b"first draft").unwrap();
+    let (src_path, repo_path) = (src.to_str().unwrap(), repo.to_str().unwrap());
+    let backup = |name: &str| {
+        let output = run(&[
+            "backup", src_path, "--repo", repo_path, "--key", KEY, "--name", name,
+        ]);
+        assert!(output.status.success(), "{output:?}");
+    };
+    backup("monday");
+    fs::write(src.join("todo.txt"), b"second draft").unwrap();
+    backup("tuesday");
+
+    let list = run(&[
+        "--json", "restore", "--repo", repo_path, "--list", "--key", KEY,
+    ]);
+    let list: serde_json::Value = serde_json::from_slice(&list.stdout).unwrap();
+    assert_eq!(list["snapshots"][0]["name"], "monday");
+    assert_eq!(list["snapshots"].as_array().unwrap().len(), 2);
+
+    let restore = |snapshot: &str, target: &str, extra: &[&str]| {
+        let mut args = vec![
+            "restore",
+            "--repo",
+            repo_path,
+            "--snapshot",
+            snapshot,
+            "--target",
+            target,
+            "--key",
+            KEY,
+        ];
+        args.extend(extra);
+        run(&args)
+    };
+    let monday = dir.path().join("monday");
+    let output = restore("monday", monday.to_str().unwrap(), &[]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(fs::read(monday.join("todo.txt")).unwrap(), b"first draft");
+    assert_eq!(
+        fs::read(monday.join("photos/2023/beach.jpg")).unwrap(),
+        b"sand"
+    );
+
+    // Nothing is overwritten without --force
+    let output = restore("latest", monday.to_str().unwrap(), &[]);
+    assert_eq!(output.status.code(), Some(2));
+    let output = restore("latest", monday.to_str().unwrap(), &["--force"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(fs::read(monday.join("todo.txt")).unwrap(), b"second draft");
+
+    let partial = dir.path().join("partial");
+    let output = restore("tuesday", partial.to_str().unwrap(), &["--path", "photos"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(partial.join("photos/2023/beach.jpg").is_file());
+    assert!(!partial.join("todo.txt").exists());
+
+    let output = restore("friday", partial.to_str().unwrap(), &[]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[tokio::test]
+async fn an_insertion_only_stores_the_chunks_around_it() {
+    let dir = tempdir().unwrap();
+    let src = dir.path().join("src");
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(&src).unwrap();
+    // Pseudo-random, so the content alone decides the cut points
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let mut data: Vec<u8> = (0..8 << 20)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect();
+    fs::write(src.join("disk.img"), &data).unwrap();
+    let (src_path, repo_path) = (src.to_str().unwrap(), repo.to_str().unwrap());
+    let backup = || {
+        let output = run(&[
+            "--json", "backup", src_path, "--repo", repo_path, "--key", KEY,
+        ]);
+        assert!(output.status.success(), "{output:?}");
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()
+    };
+    backup();
+
+    data.splice(1000..1000, b"a few inserted bytes".iter().copied());
+    fs::write(src.join("disk.img"), &data).unwrap();
+    let report = backup();
+    let (chunks, new_chunks) = (
+        report["chunks"].as_u64().unwrap(),
+        report["new_chunks"].as_u64().unwrap(),
+    );
+    assert!(chunks >= 4, "{report}");
+    assert!(new_chunks <= 2, "{report}");
+
+    let repository = Repository::open(&repo, None, Some(&[4u8; 32]))
+        .await
+        .unwrap();
+    assert_eq!(repository.chunker(), Chunker::Cdc);
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use common::{KEY, run_in};
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn plaintext(dir: &Path, encrypted: &str) -> String {
+    let out = run_in(dir, &["cat", encrypted, "--key", KEY]);
+    assert!(out.status.success(), "{out:?}");
+    String::from_utf8(out.stdout).unwrap()
+}
+
+#[test]
+fn several_files_and_patterns_are_encrypted_in_parallel() {
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("docs/2024/q1")).unwrap();
+    fs::write(dir.path().join("a.txt"), "alpha").unwrap();
+    fs::write(dir.path().join("b.txt"), "beta").unwrap();
+    fs::write(dir.path().join("docs/readme.txt"), "readme").unwrap();
+    fs::write(dir.path().join("docs/2024/q1/report.txt"), "report").unwrap();
+    fs::write(dir.path().join("docs/2024/q1/chart.png"), "chart").unwrap();
+
+    // --file given more than once, each output next to its file
+    let out = run_in(
+        dir.path(),
+        &[
+            "encrypt", "--file", "a.txt", "--file", "b.txt", "--key", KEY, "--jobs", "2",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("with 2 worker(s)"));
+    assert_eq!(plaintext(dir.path(), "a.txt.xd"), "alpha");
+    assert_eq!(plaintext(dir.path(), "b.txt.xd"), "beta");
+
+    // A pattern is expanded by encryptx, keeping the tree below --output
+    let out = run_in(
+        dir.path(),
+        &[
+            "encrypt",
+            "docs/**/*.txt",
+            "--key",
+            KEY,
+            "--output",
+            "vault",
+            "--json",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["mode"], "batch");
+    assert_eq!(report["encrypted"], 2);
+    assert_eq!(plaintext(dir.path(), "vault/docs/readme.txt.xd"), "readme");
+    assert_eq!(
+        plaintext(dir.path(), "vault/docs/2024/q1/report.txt.xd"),
+        "report"
+    );
+    assert!(!dir.path().join("vault/docs/2024/q1/chart.png.xd").exists());
+
+    // Without --jobs, the global --threads sets the number of workers
+    let out = run_in(
+        dir.path(),
+        &[
+            "encrypt",
+            "a.txt",
+            "b.txt",
+            "--key",
+            KEY,
+            "--output",
+            "single",
+            "--threads",
+            "1",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("with 1 worker(s)"));
+
+    let out = run_in(dir.path(), &["encrypt", "nothing/*.pdf", "--key", KEY]);
+    assert_eq!(out.status.code(), Some(2), "{out:?}");
+}
+
+#[test]
+fn a_failing_file_does_not_stop_the_others() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("one.txt"), "one").unwrap();
+    fs::write(dir.path().join("two.txt"), "two").unwrap();
+
+    let out = run_in(
+        dir.path(),
+        &["encrypt", "one.txt", "missing.txt", "two.txt", "--key", KEY],
+    );
+    assert_eq!(out.status.code(), Some(1), "{out:?}");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("'missing.txt' failed"), "{stderr}");
+    assert!(stderr.contains("1 of 3 file(s)"), "{stderr}");
+    assert_eq!(plaintext(dir.path(), "one.txt.xd"), "one");
+    assert_eq!(plaintext(dir.path(), "two.txt.xd"), "two");
+
+    // Alone, a failing file keeps its own exit code
+    let out = run_in(dir.path(), &["encrypt", "o*.txt", "--key", KEY]);
+    assert_eq!(out.status.code(), Some(2), "{out:?}");
+    assert!(
+        String::from_utf8_lossy(&out.stderr).contains("already exists"),
+        "{out:?}"
+    );
+}
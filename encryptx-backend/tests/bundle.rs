@@ -0,0 +1,82 @@
+mod common;
+
+use common::{KEY, run_in};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+/// The lowercase hex SHA-256 of the file at `path`.
+fn fingerprint(path: &Path) -> String {
+    Sha256::digest(fs::read(path).unwrap())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[test]
+fn the_readme_carries_the_fingerprint_but_no_secret() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("report.pdf"), "quarterly numbers").unwrap();
+
+    let out = run_in(
+        dir.path(),
+        &[
+            "encrypt",
+            "-f",
+            "report.pdf",
+            "--key",
+            KEY,
+            "--bundle-readme",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    let readme = fs::read_to_string(dir.path().join("report.xd.readme.html")).unwrap();
+    assert!(readme.contains(&fingerprint(&dir.path().join("report.xd"))));
+    assert!(readme.contains("report.xd"));
+    assert!(!readme.contains(KEY), "{readme}");
+
+    let password = "correct horse battery staple";
+    let out = run_in(
+        dir.path(),
+        &[
+            "encrypt",
+            "-f",
+            "report.pdf",
+            "--format",
+            "zip-aes",
+            "--password",
+            password,
+            "--bundle-readme",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    let readme = fs::read_to_string(dir.path().join("report.zip.readme.html")).unwrap();
+    assert!(readme.contains(&fingerprint(&dir.path().join("report.zip"))));
+    assert!(!readme.contains(password), "{readme}");
+    assert!(!readme.contains("correct horse"), "{readme}");
+}
+
+#[test]
+fn an_existing_readme_needs_force() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "a").unwrap();
+    fs::write(dir.path().join("a.xd.readme.html"), "mine").unwrap();
+
+    let args = ["encrypt", "-f", "a.txt", "--key", KEY, "--bundle-readme"];
+    let out = run_in(dir.path(), &args);
+    assert_eq!(out.status.code(), Some(2), "{out:?}");
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.xd.readme.html")).unwrap(),
+        "mine"
+    );
+    // Nothing is encrypted when the readme can't be written
+    assert!(!dir.path().join("a.xd").exists());
+
+    let out = run_in(dir.path(), &[&args[..], &["--force"]].concat());
+    assert!(out.status.success(), "{out:?}");
+    assert_ne!(
+        fs::read_to_string(dir.path().join("a.xd.readme.html")).unwrap(),
+        "mine"
+    );
+}
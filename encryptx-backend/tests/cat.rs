@@ -0,0 +1,107 @@
+mod common;
+
+use common::{KEY, OTHER_KEY, cli};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use tempfile::tempdir;
+
+fn command(dir: &Path, args: &[&str]) -> Command {
+    let mut command = cli();
+    command.args(args).current_dir(dir);
+    command
+}
+
+fn run(dir: &Path, args: &[&str]) -> Output {
+    command(dir, args).output().unwrap()
+}
+
+/// Runs with stdout on a pseudo-terminal, as if typed at a shell.
+#[cfg(unix)]
+fn run_on_terminal(dir: &Path, args: &[&str]) -> Output {
+    use std::os::fd::{FromRawFd, OwnedFd};
+
+    let (mut master, mut slave) = (0, 0);
+    let opened = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            std::ptr::null(),
+        )
+    };
+    assert_eq!(opened, 0);
+    let (_master, slave) = unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+    command(dir, args)
+        .stdout(Stdio::from(slave))
+        .stderr(Stdio::piped())
+        .output()
+        .unwrap()
+}
+
+fn encrypt(dir: &Path, name: &str, contents: &[u8]) -> String {
+    fs::write(dir.join(name), contents).unwrap();
+    let encrypted = format!("{name}.xd");
+    let out = run(
+        dir,
+        &[
+            "encrypt", "--file", name, "--key", KEY, "--output", &encrypted,
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    fs::remove_file(dir.join(name)).unwrap();
+    encrypted
+}
+
+#[test]
+fn cat_prints_plaintext_without_writing_files() {
+    let dir = tempdir().unwrap();
+    let notes = encrypt(dir.path(), "notes.txt", b"line one\nline two\n");
+    let image = encrypt(
+        dir.path(),
+        "image.bin",
+        &[0x89, b'P', b'N', b'G', 0, 0, 0, 13],
+    );
+
+    let out = run(dir.path(), &["cat", &notes, "--key", KEY]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(out.stdout, b"line one\nline two\n");
+
+    // Piped, binary data passes through untouched
+    let out = run(dir.path(), &["cat", &image, "--key", KEY]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(out.stdout, [0x89, b'P', b'N', b'G', 0, 0, 0, 13]);
+
+    let out = run(dir.path(), &["cat", &notes, "--key", OTHER_KEY]);
+    assert_eq!(out.status.code(), Some(4), "{out:?}");
+
+    let mut files: Vec<_> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    files.sort();
+    assert_eq!(files, ["image.bin.xd", "notes.txt.xd"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn cat_refuses_binary_data_on_a_terminal() {
+    let dir = tempdir().unwrap();
+    let notes = encrypt(dir.path(), "notes.txt", "crème brûlée\n".as_bytes());
+    let image = encrypt(
+        dir.path(),
+        "image.bin",
+        &[0x89, b'P', b'N', b'G', 0, 0, 0, 13],
+    );
+
+    let out = run_on_terminal(dir.path(), &["cat", &notes, "--key", KEY]);
+    assert!(out.status.success(), "{out:?}");
+
+    let out = run_on_terminal(dir.path(), &["cat", &image, "--key", KEY]);
+    assert_eq!(out.status.code(), Some(2), "{out:?}");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("binary"));
+
+    let out = run_on_terminal(dir.path(), &["cat", &image, "--key", KEY, "--force"]);
+    assert!(out.status.success(), "{out:?}");
+}
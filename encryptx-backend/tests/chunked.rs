@@ -0,0 +1,134 @@
+use encryptx_backend::crypto::{self, chunked};
+use encryptx_backend::format::XdReader;
+
+const PART_SIZE: u64 = 4096;
+
+fn sample(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn chunked_round_trip_and_part_boundaries() {
+    let key = [3u8; 32];
+    let chunk_size = chunked::chunk_size_for_part(PART_SIZE).unwrap();
+    let data = sample(chunk_size as usize * 2 + 100);
+
+    let encrypted =
+        crypto::encrypt_with_header_chunked(&data, &key, "big.bin", Some(chunk_size)).unwrap();
+    let (decrypted, filename) = crypto::decrypt_with_header(&encrypted, Some(&key)).unwrap();
+    assert_eq!(decrypted, data);
+    assert_eq!(filename, "big.bin");
+
+    // Every part after the first is exactly one encrypted chunk
+    let parts = chunked::part_boundaries(&encrypted).unwrap();
+    assert_eq!(parts.len(), 3);
+    assert_eq!(parts[0].start, 0);
+    assert_eq!(parts[1].len() as u64, PART_SIZE);
+    assert_eq!(parts[2].end, encrypted.len());
+    assert!(parts.windows(2).all(|w| w[0].end == w[1].start));
+}
+
+#[tokio::test]
+async fn chunked_truncation_and_tampering_are_detected() {
+    let key = [5u8; 32];
+    let chunk_size = chunked::chunk_size_for_part(PART_SIZE).unwrap();
+    let data = sample(chunk_size as usize * 3);
+    let encrypted =
+        crypto::encrypt_with_header_chunked(&data, &key, "f.bin", Some(chunk_size)).unwrap();
+    let parts = chunked::part_boundaries(&encrypted).unwrap();
+
+    // Dropping the last chunk must not yield a shorter but valid file
+    let truncated = &encrypted[..parts[1].end];
+    assert!(matches!(
+        crypto::decrypt_with_header(truncated, Some(&key)),
+        Err(crypto::CryptoError::AuthenticationError)
+    ));
+
+    // Corrupting the middle chunk is reported for that chunk only
+    let mut tampered = encrypted.clone();
+    tampered[parts[1].start + 10] ^= 0x80;
+    let parsed = XdReader::parse(&tampered).unwrap();
+    let file_key = crypto::file_key(&parsed, None, None).await.unwrap();
+    let regions = crypto::verify_regions(&parsed, &file_key).unwrap();
+    let ok: Vec<bool> = regions.iter().map(|r| r.ok).collect();
+    assert_eq!(ok, [true, false, true]);
+}
+
+#[test]
+fn streamed_files_match_the_whole_buffer_layout() {
+    let key = [7u8; 32];
+    let chunk_size = chunked::chunk_size_for_part(PART_SIZE).unwrap();
+    // Empty, exact multiples and ragged ends all round-trip both ways
+    for len in [0, 1, chunk_size as usize, chunk_size as usize * 2, 10_000] {
+        let data = sample(len);
+        let mut writer = crypto::key_file_writer(Vec::new(), &key, "s.bin", chunk_size).unwrap();
+        // Odd-sized writes straddle chunk boundaries
+        for piece in data.chunks(1000) {
+            std::io::Write::write_all(&mut writer, piece).unwrap();
+        }
+        assert_eq!(writer.written(), len as u64);
+        let encrypted = writer.finish().unwrap();
+        let (decrypted, _) = crypto::decrypt_with_header(&encrypted, Some(&key)).unwrap();
+        assert_eq!(decrypted, data);
+
+        let whole =
+            crypto::encrypt_with_header_chunked(&data, &key, "s.bin", Some(chunk_size)).unwrap();
+        let file = XdReader::parse(&whole).unwrap();
+        let file_key = crypto::SecureKey::new(key);
+        let mut streamed = Vec::new();
+        let mut reader = crypto::chunk_reader(&file, &file_key).unwrap().unwrap();
+        std::io::Read::read_to_end(&mut reader, &mut streamed).unwrap();
+        assert_eq!(streamed, data);
+    }
+
+    // A stream cut at a chunk boundary fails instead of ending early
+    let data = sample(chunk_size as usize * 3);
+    let whole =
+        crypto::encrypt_with_header_chunked(&data, &key, "s.bin", Some(chunk_size)).unwrap();
+    let parts = chunked::part_boundaries(&whole).unwrap();
+    let truncated = &whole[..parts[2].start];
+    let file = XdReader::parse(truncated).unwrap();
+    let mut reader = crypto::chunk_reader(&file, &crypto::SecureKey::new(key))
+        .unwrap()
+        .unwrap();
+    let error = std::io::Read::read_to_end(&mut reader, &mut Vec::new()).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+    // Single-message files are left to `decrypt_parsed`
+    let single = crypto::encrypt_with_header(&data, &key, "s.bin").unwrap();
+    let file = XdReader::parse(&single).unwrap();
+    assert!(
+        crypto::chunk_reader(&file, &crypto::SecureKey::new(key))
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn chunks_opened_in_parallel_are_each_authenticated() {
+    let key = [9u8; 32];
+    let chunk_size = chunked::chunk_size_for_part(PART_SIZE).unwrap();
+    let data = sample(chunk_size as usize * 40);
+    let encrypted =
+        crypto::encrypt_with_header_chunked(&data, &key, "f.bin", Some(chunk_size)).unwrap();
+    let (decrypted, _) = crypto::decrypt_with_header(&encrypted, Some(&key)).unwrap();
+    assert_eq!(decrypted, data);
+
+    // A forged tag-only chunk after a full final chunk gets no output but is still checked
+    let mut extended = encrypted.clone();
+    extended.extend_from_slice(&[0u8; chunked::TAG_LEN]);
+    assert!(crypto::decrypt_with_header(&extended, Some(&key)).is_err());
+    let mut tampered = encrypted;
+    let in_chunk_33 = tampered.len() - PART_SIZE as usize * 7;
+    tampered[in_chunk_33] ^= 1;
+    assert!(crypto::decrypt_with_header(&tampered, Some(&key)).is_err());
+
+    // An empty file is a single tag-only chunk
+    let empty = crypto::encrypt_with_header_chunked(b"", &key, "e.bin", Some(chunk_size)).unwrap();
+    let (decrypted, _) = crypto::decrypt_with_header(&empty, Some(&key)).unwrap();
+    assert!(decrypted.is_empty());
+    let mut forged = empty.clone();
+    let last = forged.len() - 1;
+    forged[last] ^= 1;
+    assert!(crypto::decrypt_with_header(&forged, Some(&key)).is_err());
+}
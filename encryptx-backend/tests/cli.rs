@@ -12,17 +12,18 @@ async fn encrypt_and_decrypt_example_txt() {
 
     // Encrypt using the public API
     let password = "testpassword";
-    let encrypted = api::encrypt_file_bytes(content, Some(password), None, "example.txt")
+    let encrypted = api::encrypt_file(content, Some(password), None, "example.txt")
         .await
-        .unwrap();
+        .unwrap()
+        .ciphertext;
     let encrypted_path = dir.path().join("example.xd");
     fs::write(&encrypted_path, &encrypted).unwrap();
 
     // Decrypt using the public API
     let encrypted_bytes = fs::read(&encrypted_path).unwrap();
-    let (decrypted, filename) = api::decrypt_file_bytes(&encrypted_bytes, Some(password), None)
+    let (decrypted, metadata) = api::decrypt_file_bytes(&encrypted_bytes, Some(password), None)
         .await
         .unwrap();
     assert_eq!(decrypted, content);
-    assert_eq!(filename, "example.txt");
+    assert_eq!(metadata.filename, "example.txt");
 }
@@ -0,0 +1,100 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{KEY, OTHER_KEY, cli};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Output;
+use std::thread::sleep;
+use std::time::Duration;
+use tempfile::tempdir;
+
+/// Stand-in clipboard keeping its text in `$FAKE_CLIPBOARD`.
+const FAKE_CLIPBOARD_TOOL: &str = r#"#!/bin/sh
+case $1 in
+    copy) cat > "$FAKE_CLIPBOARD" ;;
+    paste) cat "$FAKE_CLIPBOARD" ;;
+esac
+"#;
+
+fn run(dir: &Path, args: &[&str]) -> Output {
+    cli()
+        .args(args)
+        .env("ENCRYPTX_CLIPBOARD_TOOL", dir.join("clipboard"))
+        .env("FAKE_CLIPBOARD", dir.join("contents"))
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn clipboard_text_is_encrypted_and_decrypted_in_place() {
+    let dir = tempdir().unwrap();
+    let tool = dir.path().join("clipboard");
+    fs::write(&tool, FAKE_CLIPBOARD_TOOL).unwrap();
+    fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+    let contents = dir.path().join("contents");
+    let secret = "the vault code is 4711";
+    fs::write(&contents, secret).unwrap();
+
+    let out = run(dir.path(), &["clip", "encrypt", "--key", KEY]);
+    assert!(out.status.success(), "{out:?}");
+    let armored = fs::read_to_string(&contents).unwrap();
+    assert!(armored.starts_with("-----BEGIN ENCRYPTX MESSAGE-----\n"));
+    assert!(armored.ends_with("-----END ENCRYPTX MESSAGE-----\n"));
+    assert!(!armored.contains(secret));
+
+    // The key isn't in the message, so it takes the right one to decrypt
+    let out = run(dir.path(), &["clip", "decrypt", "--clear-after", "0"]);
+    assert_eq!(out.status.code(), Some(2), "{out:?}");
+    let out = run(dir.path(), &["clip", "decrypt", "--key", OTHER_KEY]);
+    assert_eq!(out.status.code(), Some(4), "{out:?}");
+    assert_eq!(fs::read_to_string(&contents).unwrap(), armored);
+
+    // Quoted in a reply, the message still decrypts
+    let quoted: String = armored.lines().map(|line| format!("> {line}\n")).collect();
+    fs::write(&contents, format!("See below:\n{quoted}")).unwrap();
+    let out = run(
+        dir.path(),
+        &[
+            "--json",
+            "clip",
+            "decrypt",
+            "--key",
+            KEY,
+            "--clear-after",
+            "1",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["operation"], "clip-decrypt");
+    assert_eq!(report["clear_after"], 1);
+    assert_eq!(fs::read_to_string(&contents).unwrap(), secret);
+    sleep(Duration::from_secs(3));
+    assert_eq!(fs::read_to_string(&contents).unwrap(), "");
+
+    // Password messages; text copied after decrypting survives the clearing
+    fs::write(&contents, secret).unwrap();
+    let out = run(
+        dir.path(),
+        &["clip", "encrypt", "--password", "correct horse"],
+    );
+    assert!(out.status.success(), "{out:?}");
+    let out = run(
+        dir.path(),
+        &[
+            "clip",
+            "decrypt",
+            "--password",
+            "correct horse",
+            "--clear-after",
+            "1",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    fs::write(&contents, "copied later").unwrap();
+    sleep(Duration::from_secs(3));
+    assert_eq!(fs::read_to_string(&contents).unwrap(), "copied later");
+}
@@ -0,0 +1,27 @@
+//! Helpers shared by the integration tests that run the CLI.
+// Each test crate uses only some of them
+#![allow(dead_code)]
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// A valid key for tests that need one (32 bytes of `0x01`, base64).
+pub const KEY: &str = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+
+/// A second key, for checking that files refuse the wrong one (32 bytes of `0x02`).
+pub const OTHER_KEY: &str = "AgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgI=";
+
+/// The CLI binary built for the tests, ready for arguments.
+pub fn cli() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_encryptx-backend"))
+}
+
+/// Runs the CLI with `args` and waits for it to finish.
+pub fn run(args: &[&str]) -> Output {
+    cli().args(args).output().unwrap()
+}
+
+/// Runs the CLI with `args` in `dir` and waits for it to finish.
+pub fn run_in(dir: &Path, args: &[&str]) -> Output {
+    cli().args(args).current_dir(dir).output().unwrap()
+}
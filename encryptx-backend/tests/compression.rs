@@ -0,0 +1,200 @@
+mod common;
+
+use common::{KEY, cli, run_in};
+use encryptx_backend::{api, compression, crypto};
+use rand::RngCore;
+use std::fs;
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn the_level_is_recorded_and_older_bodies_still_open() {
+    let body = api::compress_body(b"level nine", 9).unwrap();
+    assert_eq!(compression::recorded_level(&body), Some(9));
+    let encrypted = crypto::encrypt_with_header(&body, &[4u8; 32], "a.txt").unwrap();
+    let (plain, metadata) = api::decrypt_file_bytes(&encrypted, None, None)
+        .await
+        .unwrap();
+    assert_eq!(plain, b"level nine");
+    assert_eq!(metadata.compression_level, Some(9));
+
+    // A body from before levels were recorded is a bare zstd frame
+    let mut legacy = vec![0x01];
+    legacy.extend(zstd::encode_all(&b"no level"[..], 3).unwrap());
+    assert_eq!(compression::recorded_level(&legacy), None);
+    let encrypted = crypto::encrypt_with_header(&legacy, &[4u8; 32], "b.txt").unwrap();
+    let (plain, metadata) = api::decrypt_file_bytes(&encrypted, None, None)
+        .await
+        .unwrap();
+    assert_eq!(plain, b"no level");
+    assert_eq!(metadata.compression_level, None);
+
+    // Plain zstd readers skip the level frame
+    assert_eq!(zstd::decode_all(&body[1..]).unwrap(), b"level nine");
+    assert!(compression::validate_level(23).is_err());
+    assert!(compression::validate_level(-5).is_ok());
+}
+
+#[test]
+fn cli_compression_level_overrides_the_config() {
+    let dir = tempdir().unwrap();
+    let run = |args: &[&str]| run_in(dir.path(), args);
+    fs::write(dir.path().join("config.toml"), "compression_level = 5\n").unwrap();
+    fs::write(dir.path().join("a.txt"), vec![b'a'; 10_000]).unwrap();
+
+    let out = run(&[
+        "--config",
+        "config.toml",
+        "--json",
+        "encrypt",
+        "-f",
+        "a.txt",
+        "--key",
+        KEY,
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["compression_level"], 5);
+
+    let out = run(&[
+        "--config",
+        "config.toml",
+        "encrypt",
+        "-f",
+        "a.txt",
+        "--key",
+        KEY,
+        "--compression-level",
+        "19",
+        "--force",
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    let out = run(&[
+        "--json", "decrypt", "-f", "a.xd", "--key", KEY, "-o", "b.txt",
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["compression_level"], 19);
+    assert_eq!(
+        fs::read(dir.path().join("b.txt")).unwrap(),
+        vec![b'a'; 10_000]
+    );
+
+    let out = run(&[
+        "encrypt",
+        "-f",
+        "a.txt",
+        "--key",
+        KEY,
+        "--compression-level",
+        "99",
+        "-o",
+        "c.xd",
+    ]);
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[tokio::test]
+async fn incompressible_data_is_stored_uncompressed() {
+    let mut noise = vec![0u8; 200_000];
+    rand::rngs::OsRng.fill_bytes(&mut noise);
+    assert!(!compression::worth_compressing("clip.bin", &noise));
+    let text = b"compress me ".repeat(1000);
+    assert!(compression::worth_compressing("notes.txt", &text));
+    assert!(!compression::worth_compressing("photo.JPG", &text));
+
+    let outcome = api::encrypt_file(&noise, None, Some(&[4u8; 32]), "clip.bin")
+        .await
+        .unwrap();
+    assert_eq!(outcome.metadata.compression_level, None);
+    assert_eq!(outcome.metadata.compressed_size, noise.len() + 1);
+    let (plain, metadata) = api::decrypt_file_bytes(&outcome.ciphertext, None, Some(&[4u8; 32]))
+        .await
+        .unwrap();
+    assert_eq!(plain, noise);
+    assert_eq!(metadata.compression, None);
+
+    // --no-compress stores even text
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), &text).unwrap();
+    let out = cli()
+        .args([
+            "--json",
+            "encrypt",
+            "-f",
+            "a.txt",
+            "--key",
+            KEY,
+            "--no-compress",
+        ])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert!(report.get("compression_level").is_none());
+    assert!(report["output_size"].as_u64().unwrap() > text.len() as u64);
+    let encrypted = fs::read(dir.path().join("a.xd")).unwrap();
+    let key = [1u8; 32];
+    let (body, _) = crypto::decrypt_with_header(&encrypted, Some(&key)).unwrap();
+    assert_eq!(body[0], 0x00);
+    assert_eq!(&body[1..], text);
+}
+
+#[test]
+fn workers_and_long_matching_write_ordinary_zstd() {
+    let input = b"a line that repeats far apart\n".repeat(compression::MULTITHREAD_MIN_INPUT / 16);
+    let tuning = compression::Tuning {
+        threads: 4,
+        long_distance_matching: true,
+    };
+    let body = api::compress_into_tuned(&input, 1, tuning, Vec::new()).unwrap();
+    assert_eq!(compression::recorded_level(&body), Some(1));
+    assert_eq!(*api::decode_body(body).unwrap(), input);
+    assert_eq!(compression::resolve_threads(3), 3);
+    assert!(compression::resolve_threads(0) >= 1);
+}
+
+#[tokio::test]
+async fn a_trained_dictionary_travels_in_the_file() {
+    let dir = tempdir().unwrap();
+    let configs = dir.path().join("configs");
+    fs::create_dir(&configs).unwrap();
+    for i in 0..300 {
+        let config = format!(
+            "{{\n  \"service\": \"svc-{i}\",\n  \"replicas\": {},\n  \"image\": \"registry.example.com/team/svc-{i}:{}\",\n  \"log_level\": \"info\"\n}}\n",
+            i % 7,
+            i * 31 % 97
+        );
+        fs::write(configs.join(format!("svc-{i}.json")), config).unwrap();
+    }
+    let encrypt = |dict: bool, output: &str| {
+        let out = cli()
+            .args(["encrypt", "--tar-from", "configs", "--key", KEY])
+            .args(["--chunk-size", "4KiB", "-o", output])
+            .args(dict.then_some("--dict"))
+            .current_dir(dir.path())
+            .output()
+            .unwrap();
+        assert!(out.status.success(), "{out:?}");
+        fs::read(dir.path().join(output)).unwrap()
+    };
+    let plain = encrypt(false, "plain.xd");
+    let with_dict = encrypt(true, "dict.xd");
+    assert!(with_dict.len() < plain.len());
+
+    // The dictionary frame follows the level frame inside the encrypted body
+    let key = [1u8; 32];
+    let (body, _) = crypto::decrypt_with_header(&with_dict, Some(&key)).unwrap();
+    let start = compression::stream_start(&body[1..]);
+    assert!(start.level.is_some());
+    assert!(start.dictionary.is_some());
+
+    let (expected, _) = api::decrypt_file_bytes(&plain, None, Some(&key))
+        .await
+        .unwrap();
+    let (unpacked, _) = api::decrypt_file_bytes(&with_dict, None, Some(&key))
+        .await
+        .unwrap();
+    assert_eq!(unpacked, expected);
+    assert_eq!(*api::decode_body(body).unwrap(), expected);
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use common::cli;
+use encryptx_backend::config::Config;
+use encryptx_backend::crypto::KdfProfile;
+
+#[test]
+fn config_file_values_override_defaults() {
+    let config = Config::from_toml(
+        r#"
+        compression_level = 9
+        kdf_profile = "paranoid"
+
+        [server]
+        port = 9090
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.compression_level, 9);
+    assert_eq!(config.kdf_profile().unwrap(), KdfProfile::Paranoid);
+    assert_eq!(config.server.port, 9090);
+    // Untouched values keep their defaults
+    assert_eq!(config.server.host, "0.0.0.0");
+}
+
+#[test]
+fn config_rejects_invalid_values() {
+    assert!(Config::from_toml("compression_level = 99").is_err());
+    assert!(Config::from_toml("threads = 1000").is_err());
+    assert!(Config::from_toml("kdf_profile = \"extreme\"").is_err());
+    assert!(Config::from_toml("kdf = \"bcrypt\"").is_err());
+    assert!(Config::from_toml("unknown_key = 1").is_err());
+    assert!(Config::from_toml("max_decompressed_size = \"0\"").is_err());
+    assert!(Config::from_toml("max_decompressed_size = \"lots\"").is_err());
+}
+
+#[test]
+fn check_config_reports_problems_without_starting_the_server() {
+    let dir = tempfile::tempdir().unwrap();
+    let check = |toml: &str| {
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, toml).unwrap();
+        cli()
+            .args(["--json", "--check-config", "--config"])
+            .arg(&path)
+            .env_remove("ALLOWED_ORIGIN")
+            .output()
+            .unwrap()
+    };
+
+    let out = check("[server]\nport = 0\nallowed_origins = [\"https://app.example.com\"]\n");
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    let listen = &report["checks"][2];
+    assert_eq!(listen["name"], "listen");
+    assert_eq!(listen["status"], "warning");
+
+    // Browsers send origins without a trailing slash, so this one would never match
+    let out = check("[server]\nallowed_origins = [\"https://app.example.com/\"]\n");
+    assert_eq!(out.status.code(), Some(2));
+    let out = check("[server]\nmemory_limit_mb = 16\n");
+    assert_eq!(out.status.code(), Some(2));
+    let out = check("compression_level = 99\n");
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn capabilities_reflect_the_loaded_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    std::fs::write(
+        &path,
+        "compression_level = 9\nkdf_profile = \"paranoid\"\n\n[server]\nmax_payload_mb = 8\nmemory_limit_mb = 512\n",
+    )
+    .unwrap();
+    let out = cli()
+        .args(["--json", "capabilities", "--config"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{out:?}");
+
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["ciphers"][0], "aes-256-gcm");
+    assert_eq!(report["kdfs"][0]["default_profile"], "paranoid");
+    assert_eq!(report["kdfs"][0]["profiles"].as_array().unwrap().len(), 3);
+    assert_eq!(report["compression"][0]["level"], 9);
+    assert_eq!(report["limits"]["max_payload_bytes"], 8 * 1024 * 1024);
+    assert_eq!(report["features"]["memory_watchdog"], true);
+    assert!(
+        report["keyslot_kinds"]
+            .as_array()
+            .unwrap()
+            .contains(&"tpm2".into())
+    );
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use common::cli;
+use encryptx_backend::crypto;
+use encryptx_backend::format::XdReader;
+
+const CHUNK: u32 = 4096;
+
+fn sample(len: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 24) as u8
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn delta_reuses_unchanged_chunks_after_insert() {
+    let key = [9u8; 32];
+    let v1 = sample(CHUNK as usize * 8, 1);
+    let first = crypto::encrypt_with_header_chunked(&v1, &key, "data.bin", Some(CHUNK)).unwrap();
+
+    // Converting a plain chunked file starts the chain; nothing is reusable yet
+    let parsed = XdReader::parse(&first).unwrap();
+    let file_key = crypto::file_key(&parsed, None, Some(&key)).await.unwrap();
+    let (base, stats) =
+        crypto::encrypt_delta(&v1, "data.bin", &parsed, &file_key, None, None).unwrap();
+    assert_eq!(stats.reused_chunks, 0);
+
+    // Insert bytes in the middle: chunks before and after the insertion are found again
+    let mut v2 = v1.clone();
+    v2.splice(10_000..10_000, b"new bytes".iter().copied());
+    let parsed = XdReader::parse(&base).unwrap();
+    let (updated, stats) =
+        crypto::encrypt_delta(&v2, "data.bin", &parsed, &file_key, None, None).unwrap();
+    // Only the chunk containing the insertion (bytes 8192..12288) is new
+    assert_eq!(stats.reused_chunks, 7);
+
+    let (decrypted, _) = crypto::decrypt_with_header(&updated, Some(&key)).unwrap();
+    assert_eq!(decrypted, v2);
+}
+
+#[tokio::test]
+async fn delta_rejects_reordered_chunks() {
+    let key = [4u8; 32];
+    let data = sample(CHUNK as usize * 3, 2);
+    let first = crypto::encrypt_with_header_chunked(&data, &key, "f.bin", Some(CHUNK)).unwrap();
+    let parsed = XdReader::parse(&first).unwrap();
+    let file_key = crypto::file_key(&parsed, None, None).await.unwrap();
+    let (encrypted, _) =
+        crypto::encrypt_delta(&data, "f.bin", &parsed, &file_key, None, None).unwrap();
+
+    // Swap the first two (equal-sized) chunk records
+    let parts = crypto::chunked::part_boundaries(&encrypted).unwrap();
+    let record_len = parts[1].len();
+    let first_record = parts[1].start - record_len..parts[1].start;
+    let mut swapped = encrypted.clone();
+    swapped[first_record.clone()].copy_from_slice(&encrypted[parts[1].clone()]);
+    swapped[parts[1].clone()].copy_from_slice(&encrypted[first_record]);
+
+    assert!(matches!(
+        crypto::decrypt_with_header(&swapped, Some(&key)),
+        Err(crypto::CryptoError::AuthenticationError)
+    ));
+}
+
+#[test]
+fn rsyncable_chunks_survive_insertions() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = "CQkJCQkJCQkJCQkJCQkJCQkJCQkJCQkJCQkJCQkJCQk=";
+    let encrypt = |input: &std::path::Path, output: &str, previous: Option<&str>| {
+        let mut command = cli();
+        command
+            .args(["--json", "encrypt", "--key", key, "--force"])
+            .args(["--file", input.to_str().unwrap()])
+            .args(["--output", dir.path().join(output).to_str().unwrap()]);
+        if let Some(previous) = previous {
+            let previous = dir.path().join(previous);
+            command.args(["--delta-from", previous.to_str().unwrap(), "--rsyncable"]);
+        }
+        let out = command.output().unwrap();
+        assert!(out.status.success());
+        serde_json::from_slice::<serde_json::Value>(&out.stdout).unwrap()
+    };
+
+    let input = dir.path().join("data.bin");
+    let mut data = sample(512 * 1024, 3);
+    std::fs::write(&input, &data).unwrap();
+    encrypt(&input, "v1.xd", None);
+    encrypt(&input, "v2.xd", Some("v1.xd"));
+
+    data.splice(200_000..200_000, b"inserted".iter().copied());
+    std::fs::write(&input, &data).unwrap();
+    let report = encrypt(&input, "v3.xd", Some("v2.xd"));
+    let reused = report["delta"]["reused_chunks"].as_u64().unwrap();
+    let new = report["delta"]["new_chunks"].as_u64().unwrap();
+    // Only the chunks around the insertion change, wherever they sit in the file
+    assert!(new <= 3, "{new} new chunks");
+    assert!(reused >= 8, "{reused} reused chunks");
+}
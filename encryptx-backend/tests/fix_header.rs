@@ -0,0 +1,137 @@
+mod common;
+
+use common::{KEY, run};
+use encryptx_backend::format::XdReader;
+use std::fs;
+use tempfile::tempdir;
+
+/// Overwrites the first occurrence of `from` in the file with `to` (same length).
+fn corrupt(data: &mut [u8], from: &[u8], to: &[u8]) {
+    let at = data.windows(from.len()).position(|w| w == from).unwrap();
+    data[at..at + to.len()].copy_from_slice(to);
+}
+
+#[test]
+fn fix_header_restores_chunked_file_from_supplied_fields() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("ledger.csv");
+    let original: Vec<u8> = (0..5000u32).flat_map(|i| i.to_le_bytes()).collect();
+    fs::write(&input, &original).unwrap();
+    let path = dir.path().join("ledger.xd");
+    let path = path.to_str().unwrap();
+    let out = run(&[
+        "encrypt",
+        "--file",
+        input.to_str().unwrap(),
+        "--output",
+        path,
+        "--key",
+        KEY,
+        "--chunk-size",
+        "4112",
+    ]);
+    assert!(out.status.success());
+    let intact = fs::read(path).unwrap();
+    let timestamp = XdReader::parse(&intact)
+        .unwrap()
+        .header
+        .timestamp()
+        .to_string();
+
+    // Flipped bytes break the JSON around the filename and inside the timestamp
+    let mut damaged = intact.clone();
+    corrupt(&mut damaged, b"ledger.csv\"", b"ledger.c\x03v\x7f");
+    corrupt(&mut damaged, b"\"timestamp\":1", b"\"timestamp\":?");
+    fs::write(path, &damaged).unwrap();
+    assert!(
+        !run(&["decrypt", "--file", path, "--key", KEY])
+            .status
+            .success()
+    );
+
+    // Without the timestamp the header can't be rebuilt; a wrong filename doesn't verify
+    let out = run(&["fix-header", path, "--key", KEY, "--filename", "ledger.csv"]);
+    assert_eq!(out.status.code(), Some(2));
+    let out = run(&[
+        "fix-header",
+        path,
+        "--key",
+        KEY,
+        "--filename",
+        "ledger.tsv",
+        "--timestamp",
+        &timestamp,
+    ]);
+    assert_eq!(out.status.code(), Some(4));
+    assert_eq!(fs::read(path).unwrap(), damaged);
+
+    let out = run(&[
+        "--json",
+        "fix-header",
+        path,
+        "--key",
+        KEY,
+        "--filename",
+        "ledger.csv",
+        "--timestamp",
+        &timestamp,
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["changed"], true);
+    assert_eq!(fs::read(path).unwrap(), intact);
+}
+
+#[test]
+fn fix_header_restamps_password_file() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("notes.txt");
+    fs::write(&input, b"meeting at noon").unwrap();
+    let path = dir.path().join("notes.xd");
+    let path = path.to_str().unwrap();
+    let out = run(&[
+        "encrypt",
+        "--file",
+        input.to_str().unwrap(),
+        "--output",
+        path,
+        "--password",
+        "pw",
+    ]);
+    assert!(out.status.success());
+
+    // The filename field name itself is damaged, so the JSON no longer parses
+    let mut damaged = fs::read(path).unwrap();
+    corrupt(&mut damaged, b"\"filename\"", b"\"filen\x01me\"");
+    fs::write(path, &damaged).unwrap();
+
+    let out = run(&["fix-header", path, "--password", "pw"]);
+    assert_eq!(out.status.code(), Some(2));
+    let out = run(&[
+        "fix-header",
+        path,
+        "--password",
+        "pw",
+        "--filename",
+        "restored.txt",
+    ]);
+    assert!(out.status.success(), "{out:?}");
+
+    let plain = dir.path().join("plain.txt");
+    let out = run(&[
+        "decrypt",
+        "--file",
+        path,
+        "--password",
+        "pw",
+        "--output",
+        plain.to_str().unwrap(),
+    ]);
+    assert!(out.status.success());
+    assert_eq!(fs::read(&plain).unwrap(), b"meeting at noon");
+    let fixed = fs::read(path).unwrap();
+    assert_eq!(
+        XdReader::parse(&fixed).unwrap().header.filename(),
+        "restored.txt"
+    );
+}
@@ -0,0 +1,85 @@
+use encryptx_backend::api;
+use encryptx_backend::crypto::{self, PasswordKdf};
+use encryptx_backend::format::{self, Mode, PASSWORD_MARKER, ParsedHeader, XdReader, XdWriter};
+
+#[test]
+fn reader_and_writer_round_trip_without_keys() {
+    let encrypted = crypto::encrypt_with_header(b"framed", &[5u8; 32], "a.txt").unwrap();
+    let file = XdReader::parse(&encrypted).unwrap();
+    assert!(matches!(file.header, ParsedHeader::Key(_)));
+    assert_eq!(file.header.filename(), "a.txt");
+    assert_eq!(
+        file.ciphertext_offset,
+        encrypted.len() - file.ciphertext.len()
+    );
+
+    // Reassembling the parts gives back the same bytes, which still decrypt
+    let rebuilt = XdWriter::key(file.header_json)
+        .nonce(file.nonce)
+        .body(file.ciphertext)
+        .finish()
+        .unwrap();
+    assert_eq!(rebuilt, encrypted);
+    let (plain, _) = crypto::decrypt_with_header(&rebuilt, Some(&[5u8; 32])).unwrap();
+    assert_eq!(plain, b"framed");
+
+    // Password mode is told apart by its marker byte
+    let header = br#"{"filename":"b.txt","salt":"c2FsdA==","kdf":"argon2id","memory_cost":8,"time_cost":1,"parallelism":1,"version":3}"#;
+    let framed = XdWriter::password(header)
+        .nonce(&[1u8; 12])
+        .body(&[2u8; 16])
+        .finish()
+        .unwrap();
+    assert_eq!(framed[0], PASSWORD_MARKER);
+    let file = XdReader::parse(&framed).unwrap();
+    assert!(matches!(&file.header, ParsedHeader::Password(h) if h.filename == "b.txt"));
+    assert_eq!(file.ciphertext, &[2u8; 16]);
+
+    // A short nonce or a body without room for a tag is refused, not framed
+    assert!(
+        XdWriter::key(b"{}")
+            .nonce(&[1u8; 8])
+            .body(&[2u8; 16])
+            .finish()
+            .is_err()
+    );
+    assert!(
+        XdWriter::key(b"{}")
+            .nonce(&[1u8; 12])
+            .body(&[2u8; 4])
+            .finish()
+            .is_err()
+    );
+    assert!(XdReader::parse(&framed[..framed.len() - 1]).is_err());
+}
+
+#[tokio::test]
+async fn detect_reports_mode_version_and_kdf() {
+    let encrypted = crypto::encrypt_with_header(b"data", &[5u8; 32], "a.txt").unwrap();
+    let info = format::detect(&encrypted).unwrap();
+    assert_eq!(info.mode, Mode::Key);
+    assert_eq!(info.cipher, "aes-256-gcm");
+    assert_eq!(info.kdf, None);
+    assert!(info.is_supported());
+
+    let encrypted = api::encrypt_file(b"data", Some("pw"), None, "b.txt")
+        .await
+        .unwrap()
+        .ciphertext;
+    let info = format::detect(&encrypted).unwrap();
+    assert_eq!(info.mode, Mode::Password);
+    assert_eq!(info.version, format::CURRENT_VERSION);
+    assert!(matches!(info.kdf, Some(PasswordKdf::Argon2id(_))));
+
+    // A file from a newer writer is recognized but not supported
+    let header = br#"{"filename":"c.txt","version":9}"#;
+    let future = XdWriter::key(header)
+        .nonce(&[1u8; 12])
+        .body(&[2u8; 16])
+        .finish()
+        .unwrap();
+    let info = format::detect(&future).unwrap();
+    assert_eq!(info.version, 9);
+    assert!(!info.is_supported());
+    assert!(format::detect(b"not an xd file").is_err());
+}
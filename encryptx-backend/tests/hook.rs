@@ -0,0 +1,98 @@
+mod common;
+
+use common::{KEY, run_in};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use tempfile::tempdir;
+
+/// Runs git in `dir`, isolated from the user's configuration.
+fn git(dir: &Path, args: &[&str]) -> Output {
+    Command::new("git")
+        .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn staged_plaintext_matching_a_protected_pattern_is_refused() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    assert!(git(repo, &["init", "-q"]).status.success());
+
+    let out = run_in(repo, &["hook", "install", "--pattern", "secrets/*"]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(
+        fs::read_to_string(repo.join(".encryptx-protect")).unwrap(),
+        "secrets/*\n"
+    );
+    let hook = fs::read_to_string(repo.join(".git/hooks/pre-commit")).unwrap();
+    assert!(hook.contains("hook check"), "{hook}");
+
+    // Files outside the patterns are never looked at
+    fs::write(repo.join("readme.txt"), "public").unwrap();
+    git(repo, &["add", "readme.txt"]);
+    assert!(run_in(repo, &["hook", "check"]).status.success());
+
+    fs::create_dir(repo.join("secrets")).unwrap();
+    fs::write(repo.join("secrets/token.txt"), "plaintext secret").unwrap();
+    git(repo, &["add", "secrets/token.txt"]);
+    let out = run_in(repo, &["hook", "check"]);
+    assert_eq!(out.status.code(), Some(2), "{out:?}");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("secrets/token.txt"));
+
+    // The installed hook stops the commit itself
+    let commit = git(repo, &["commit", "-q", "-m", "leak"]);
+    assert!(!commit.status.success(), "{commit:?}");
+
+    // The encrypted file passes in its place
+    git(repo, &["rm", "-q", "--cached", "secrets/token.txt"]);
+    let out = run_in(
+        repo,
+        &[
+            "encrypt",
+            "-f",
+            "secrets/token.txt",
+            "--key",
+            KEY,
+            "-o",
+            "secrets/token.xd",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    git(repo, &["add", "secrets/token.xd"]);
+    let out = run_in(repo, &["hook", "check"]);
+    assert!(out.status.success(), "{out:?}");
+    let commit = git(repo, &["commit", "-q", "-m", "sealed"]);
+    assert!(commit.status.success(), "{commit:?}");
+}
+
+#[test]
+fn a_foreign_hook_is_kept_without_force() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    assert!(git(repo, &["init", "-q"]).status.success());
+    fs::create_dir_all(repo.join(".git/hooks")).unwrap();
+    fs::write(repo.join(".git/hooks/pre-commit"), "#!/bin/sh\nexit 0\n").unwrap();
+
+    let out = run_in(repo, &["hook", "install", "--pattern", "*.env"]);
+    assert_eq!(out.status.code(), Some(2), "{out:?}");
+    assert_eq!(
+        fs::read_to_string(repo.join(".git/hooks/pre-commit")).unwrap(),
+        "#!/bin/sh\nexit 0\n"
+    );
+
+    let out = run_in(repo, &["hook", "install", "--pattern", "*.env", "--force"]);
+    assert!(out.status.success(), "{out:?}");
+    // Installing again adds no duplicate pattern
+    let out = run_in(repo, &["hook", "install", "--pattern", "*.env"]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(
+        fs::read_to_string(repo.join(".encryptx-protect")).unwrap(),
+        "*.env\n"
+    );
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::{KEY, run};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn in_place_replaces_the_file_and_leaves_nothing_behind() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("notes.txt");
+    let original = b"meeting notes\n".repeat(500);
+    fs::write(&input, &original).unwrap();
+    let path = input.to_str().unwrap();
+
+    let out = run(&[
+        "encrypt",
+        "--file",
+        path,
+        "--in-place",
+        "--wipe",
+        "--key",
+        KEY,
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    assert_ne!(fs::read(&input).unwrap(), original);
+    // Only the encrypted file remains: no temporary file, no separate .xd
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+    let decrypted = dir.path().join("notes.out");
+    let out = run(&[
+        "decrypt",
+        "--file",
+        path,
+        "--output",
+        decrypted.to_str().unwrap(),
+        "--key",
+        KEY,
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(fs::read(&decrypted).unwrap(), original);
+}
+
+#[cfg(unix)]
+#[test]
+fn in_place_refuses_a_file_with_other_hard_links() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("notes.txt");
+    fs::write(&input, b"meeting notes").unwrap();
+    fs::hard_link(&input, dir.path().join("copy.txt")).unwrap();
+
+    let out = run(&[
+        "encrypt",
+        "--file",
+        input.to_str().unwrap(),
+        "--in-place",
+        "--key",
+        KEY,
+    ]);
+    assert_eq!(out.status.code(), Some(2));
+    assert_eq!(fs::read(&input).unwrap(), b"meeting notes");
+}
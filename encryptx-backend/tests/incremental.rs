@@ -0,0 +1,151 @@
+mod common;
+
+use common::{KEY, cli, run};
+use std::fs;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::{Duration, Instant, SystemTime};
+use tempfile::tempdir;
+
+fn backup(src: &str, out: &str) -> serde_json::Value {
+    let output = run(&[
+        "--json",
+        "encrypt",
+        "--dir",
+        src,
+        "--output",
+        out,
+        "--key",
+        KEY,
+        "--incremental",
+    ]);
+    assert!(output.status.success(), "{output:?}");
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn incremental_runs_only_encrypt_what_changed() {
+    let dir = tempdir().unwrap();
+    let src = dir.path().join("src");
+    let out = dir.path().join("out");
+    fs::create_dir_all(src.join("docs")).unwrap();
+    fs::write(src.join("a.txt"), b"alpha").unwrap();
+    fs::write(src.join("docs/b.txt"), b"bravo").unwrap();
+    fs::write(src.join("docs/c.txt"), b"charlie").unwrap();
+    let (src_path, out_path) = (src.to_str().unwrap(), out.to_str().unwrap());
+
+    let report = backup(src_path, out_path);
+    assert_eq!(report["encrypted"], 3);
+    assert!(out.join("docs/b.txt.xd").is_file());
+    assert!(src.join(".encryptx-state.json").is_file());
+    assert!(!out.join(".encryptx-state.json.xd").exists());
+
+    let report = backup(src_path, out_path);
+    assert_eq!(
+        (report["encrypted"].clone(), report["unchanged"].clone()),
+        (0.into(), 3.into())
+    );
+
+    // An edit, a touch that changes nothing, and a deletion
+    fs::write(src.join("docs/b.txt"), b"bravo, edited").unwrap();
+    let later = SystemTime::now() + Duration::from_secs(5);
+    fs::File::options()
+        .write(true)
+        .open(src.join("a.txt"))
+        .unwrap()
+        .set_modified(later)
+        .unwrap();
+    fs::remove_file(src.join("docs/c.txt")).unwrap();
+    let report = backup(src_path, out_path);
+    assert_eq!(report["encrypted"], 1);
+    assert_eq!(report["unchanged"], 1);
+    assert_eq!(report["removed"], 1);
+    assert!(!out.join("docs/c.txt.xd").exists());
+
+    let decrypted = dir.path().join("b.out");
+    let output = run(&[
+        "decrypt",
+        "--file",
+        out.join("docs/b.txt.xd").to_str().unwrap(),
+        "--output",
+        decrypted.to_str().unwrap(),
+        "--key",
+        KEY,
+    ]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(fs::read(&decrypted).unwrap(), b"bravo, edited");
+}
+
+/// The files listed in the manifest of `out`.
+fn manifest_files(out: &Path) -> Vec<String> {
+    let output = run(&[
+        "decrypt",
+        "--file",
+        out.join(".encryptx-manifest").to_str().unwrap(),
+        "--output",
+        "-",
+        "--key",
+        KEY,
+    ]);
+    assert!(output.status.success(), "{output:?}");
+    let manifest: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    manifest["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|file| file["path"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn overlapping_runs_keep_each_others_entries() {
+    let dir = tempdir().unwrap();
+    let src = dir.path().join("src");
+    let out = dir.path().join("out");
+    let other = dir.path().join("other");
+    fs::create_dir_all(&src).unwrap();
+    fs::create_dir_all(&out).unwrap();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        fs::write(src.join(name), name).unwrap();
+    }
+    let state = dir.path().join("state.json");
+    let encrypt = |out: &Path, include: &str| {
+        let mut command = cli();
+        command
+            .args(["encrypt", "--dir", src.to_str().unwrap(), "--output"])
+            .args([out.to_str().unwrap(), "--key", KEY, "--include", include])
+            .args(["--incremental", "--state", state.to_str().unwrap()])
+            .stderr(Stdio::piped());
+        command
+    };
+    // What a concurrent run over a.txt and c.txt writes, prepared elsewhere
+    let output = encrypt(&other, "[ac].txt").output().unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let their_manifest = fs::read(other.join(".encryptx-manifest")).unwrap();
+    let their_state = fs::read_to_string(&state).unwrap().replace(
+        fs::canonicalize(&other).unwrap().to_str().unwrap(),
+        fs::canonicalize(&out).unwrap().to_str().unwrap(),
+    );
+    fs::remove_file(&state).unwrap();
+
+    // A run over b.txt starts, then finds the manifest lock held by the other run...
+    let lock = fs::File::create(out.join(".encryptx-manifest.lock")).unwrap();
+    lock.lock().unwrap();
+    let run_b = encrypt(&out, "b.txt").spawn().unwrap();
+    let deadline = Instant::now() + Duration::from_secs(20);
+    while !out.join("b.txt.xd").exists() {
+        assert!(Instant::now() < deadline, "b.txt was never encrypted");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    // ...which finishes meanwhile
+    fs::write(out.join(".encryptx-manifest"), their_manifest).unwrap();
+    fs::write(&state, their_state).unwrap();
+    drop(lock);
+    let output = run_b.wait_with_output().unwrap();
+    assert!(output.status.success(), "{output:?}");
+
+    assert_eq!(manifest_files(&out), ["a.txt", "b.txt", "c.txt"]);
+    let state: serde_json::Value = serde_json::from_slice(&fs::read(&state).unwrap()).unwrap();
+    let recorded: Vec<&String> = state["files"].as_object().unwrap().keys().collect();
+    assert_eq!(recorded, ["a.txt", "b.txt", "c.txt"]);
+}
@@ -0,0 +1,165 @@
+mod common;
+
+use common::{KEY, run_in};
+use encryptx_backend::crypto;
+use encryptx_backend::interop::{ArchiveEntry, zip_aes};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+/// The `--json inspect` report of `file`.
+fn inspect(dir: &Path, file: &str) -> Value {
+    let out = run_in(dir, &["--json", "inspect", file]);
+    assert!(out.status.success(), "{out:?}");
+    serde_json::from_slice(&out.stdout).unwrap()
+}
+
+/// Checks that the parts cover the whole file, in order and without gaps.
+fn assert_parts_cover(report: &Value, len: usize) {
+    let mut next = 0;
+    for part in report["parts"].as_array().unwrap() {
+        assert_eq!(part["offset"], next, "{report}");
+        next += part["length"].as_u64().unwrap() as usize;
+    }
+    assert_eq!(next, len, "{report}");
+}
+
+#[test]
+fn key_files_report_their_slots_and_layout() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("note.txt"), "inspect me").unwrap();
+    let out = run_in(dir.path(), &["encrypt", "-f", "note.txt", "--key", KEY]);
+    assert!(out.status.success(), "{out:?}");
+
+    let report = inspect(dir.path(), "note.xd");
+    assert_eq!(report["status"], "ok");
+    assert_eq!(report["file"], "note.xd");
+    assert_eq!(report["version"], 3);
+    assert_eq!(report["mode"], "keyslots");
+    assert_eq!(report["filename"], "note.txt");
+    assert_eq!(report["key_embedded"], false);
+    assert!(report["timestamp"].as_u64().unwrap() > 0);
+    assert_eq!(report["keyslots"][0]["index"], 0);
+    assert_eq!(report["keyslots"][0]["kind"], "key");
+    assert!(report["keyslots"][0].get("label").is_none(), "{report}");
+    for absent in ["kdf", "chunk_size", "delta_id", "signature"] {
+        assert!(report.get(absent).is_none(), "{absent}: {report}");
+    }
+    let len = fs::metadata(dir.path().join("note.xd")).unwrap().len() as usize;
+    assert_parts_cover(&report, len);
+    // The sealed body, tag included, is all of the file but the header
+    let ciphertext = report["ciphertext_size"].as_u64().unwrap() as usize;
+    assert!(ciphertext > 16 && ciphertext < len, "{report}");
+
+    // The text form shows the same facts
+    let out = run_in(dir.path(), &["inspect", "note.xd"]);
+    let text = String::from_utf8_lossy(&out.stdout);
+    assert!(text.contains("Mode: keyslots"), "{text}");
+    assert!(text.contains("Original filename: note.txt"), "{text}");
+    assert!(text.contains("Keyslot 0: key\n"), "{text}");
+}
+
+#[test]
+fn embedded_keys_are_fingerprinted_never_shown() {
+    let dir = tempdir().unwrap();
+    // The bytes KEY encodes
+    let key = [1u8; 32];
+    let encrypted = crypto::encrypt_with_header(b"legacy", &key, "old.txt").unwrap();
+    fs::write(dir.path().join("old.xd"), &encrypted).unwrap();
+
+    let report = inspect(dir.path(), "old.xd");
+    assert_eq!(report["mode"], "key");
+    assert_eq!(report["key_embedded"], true);
+    assert_eq!(
+        report["embedded_key_fingerprint"],
+        crypto::key_fingerprint(&key)
+    );
+    assert!(!report.to_string().contains(KEY), "{report}");
+}
+
+#[test]
+fn password_files_report_the_kdf_and_chunks() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("big.bin"), vec![7u8; 200_000]).unwrap();
+    let out = run_in(
+        dir.path(),
+        &[
+            "encrypt",
+            "-f",
+            "big.bin",
+            "--password",
+            "pw",
+            "--chunk-size",
+            "64KiB",
+            "--no-compress",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+
+    let report = inspect(dir.path(), "big.xd");
+    assert_eq!(report["mode"], "password");
+    assert_eq!(report["kdf"]["name"], "argon2id");
+    assert!(report["kdf"]["memory_cost"].as_u64().unwrap() > 0);
+    assert!(report["kdf"]["time_cost"].as_u64().unwrap() > 0);
+    assert!(report["kdf"]["parallelism"].as_u64().unwrap() > 0);
+    assert!(report["chunk_size"].as_u64().unwrap() > 0);
+    assert!(report.get("keyslots").is_none(), "{report}");
+    let len = fs::metadata(dir.path().join("big.xd")).unwrap().len() as usize;
+    assert_parts_cover(&report, len);
+    assert!(report["parts"].as_array().unwrap().len() > 1, "{report}");
+
+    let out = run_in(dir.path(), &["inspect", "big.xd"]);
+    let text = String::from_utf8_lossy(&out.stdout);
+    assert!(text.contains("KDF: argon2id (memory"), "{text}");
+    assert!(text.contains("Chunked:"), "{text}");
+}
+
+#[test]
+fn anonymous_files_record_no_name_or_time() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("quiet.txt"), "shh").unwrap();
+    let out = run_in(
+        dir.path(),
+        &[
+            "encrypt",
+            "-f",
+            "quiet.txt",
+            "--key",
+            KEY,
+            "--no-metadata",
+            "-o",
+            "quiet.xd",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+
+    let report = inspect(dir.path(), "quiet.xd");
+    assert_eq!(report["filename"], "");
+    assert_eq!(report["timestamp"], 0);
+    let out = run_in(dir.path(), &["inspect", "quiet.xd"]);
+    let text = String::from_utf8_lossy(&out.stdout);
+    assert!(text.contains("Encrypted: not recorded"), "{text}");
+    assert!(text.contains("Original filename: not recorded"), "{text}");
+}
+
+#[test]
+fn other_files_are_format_errors() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("plain.xd"), "not encrypted").unwrap();
+    let entries = [ArchiveEntry {
+        name: "a.txt".to_string(),
+        data: b"a".to_vec(),
+    }];
+    fs::write(
+        dir.path().join("a.zip"),
+        zip_aes::write_archive(&entries, "pw").unwrap(),
+    )
+    .unwrap();
+
+    let out = run_in(dir.path(), &["--json", "inspect", "plain.xd"]);
+    assert_eq!(out.status.code(), Some(5), "{out:?}");
+    let out = run_in(dir.path(), &["inspect", "a.zip"]);
+    assert_eq!(out.status.code(), Some(5), "{out:?}");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("archive, not an .xd file"));
+}
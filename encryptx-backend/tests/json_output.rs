@@ -0,0 +1,120 @@
+mod common;
+
+use common::{KEY, OTHER_KEY, run_in};
+use encryptx_backend::cli::CliError;
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::process::Output;
+use tempfile::tempdir;
+
+/// The single JSON line `--json` prints on stdout.
+fn report(out: &Output) -> Value {
+    serde_json::from_slice(&out.stdout).unwrap_or_else(|e| panic!("{e}: {out:?}"))
+}
+
+/// The keys of a JSON object, sorted.
+fn keys(value: &Value) -> Vec<&str> {
+    let mut keys: Vec<_> = value
+        .as_object()
+        .unwrap()
+        .keys()
+        .map(String::as_str)
+        .collect();
+    keys.sort_unstable();
+    keys
+}
+
+#[test]
+fn each_error_kind_has_its_own_exit_code() {
+    let errors = [
+        (CliError::Crypto("c".into()), 1, "crypto"),
+        (CliError::InvalidInput("i".into()), 2, "invalid_input"),
+        (CliError::Io(io::Error::other("o")), 3, "io"),
+        (CliError::Authentication("a".into()), 4, "authentication"),
+        (CliError::Format("f".into()), 5, "format"),
+    ];
+    for (error, code, kind) in errors {
+        assert_eq!(error.exit_code(), code, "{error}");
+        assert_eq!(error.kind(), kind, "{error}");
+    }
+}
+
+#[test]
+fn success_reports_have_stable_keys() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("note.txt"), "report me").unwrap();
+
+    let out = run_in(
+        dir.path(),
+        &["--json", "encrypt", "-f", "note.txt", "--key", KEY],
+    );
+    assert!(out.status.success(), "{out:?}");
+    let encrypted = report(&out);
+    assert_eq!(
+        keys(&encrypted),
+        [
+            "duration_ms",
+            "input",
+            "input_size",
+            "key_fingerprint",
+            "mode",
+            "operation",
+            "output",
+            "output_size",
+            "status",
+        ]
+    );
+    assert_eq!(encrypted["status"], "ok");
+    assert_eq!(encrypted["operation"], "encrypt");
+    assert_eq!(encrypted["mode"], "key");
+    assert_eq!(encrypted["input"], "note.txt");
+    assert_eq!(encrypted["output"], "note.xd");
+    assert_eq!(encrypted["input_size"], 9);
+
+    let out = run_in(
+        dir.path(),
+        &[
+            "--json", "decrypt", "-f", "note.xd", "--key", KEY, "-o", "back.txt",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    let decrypted = report(&out);
+    assert_eq!(decrypted["status"], "ok");
+    assert_eq!(decrypted["operation"], "decrypt");
+    assert_eq!(decrypted["output_size"], 9);
+    assert_eq!(decrypted["key_fingerprint"], encrypted["key_fingerprint"]);
+}
+
+#[test]
+fn error_reports_carry_the_kind_and_exit_code() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("note.txt"), "report me").unwrap();
+    fs::write(dir.path().join("junk.xd"), "not an encrypted file").unwrap();
+    let out = run_in(dir.path(), &["encrypt", "-f", "note.txt", "--key", KEY]);
+    assert!(out.status.success(), "{out:?}");
+
+    let cases: [(&[&str], i32, &str); 3] = [
+        (
+            &["decrypt", "-f", "missing.xd", "--key", KEY],
+            2,
+            "invalid_input",
+        ),
+        (
+            &["decrypt", "-f", "note.xd", "--key", OTHER_KEY],
+            4,
+            "authentication",
+        ),
+        (&["decrypt", "-f", "junk.xd", "--key", KEY], 5, "format"),
+    ];
+    for (args, code, kind) in cases {
+        let out = run_in(dir.path(), &[&["--json"], args].concat());
+        assert_eq!(out.status.code(), Some(code), "{out:?}");
+        let error = report(&out);
+        assert_eq!(keys(&error), ["exit_code", "kind", "message", "status"]);
+        assert_eq!(error["status"], "error");
+        assert_eq!(error["kind"], kind);
+        assert_eq!(error["exit_code"], code);
+        assert!(!error["message"].as_str().unwrap().is_empty());
+    }
+}
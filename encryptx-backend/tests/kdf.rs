@@ -0,0 +1,230 @@
+mod common;
+
+use common::{cli, run_in};
+use encryptx_backend::crypto::{self, KdfProfile, PasswordKdf, ScryptParams};
+use encryptx_backend::format::{ParsedHeader, XdReader};
+use std::fs;
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn scrypt_files_record_their_parameters() {
+    let kdf = PasswordKdf::for_profile("scrypt", KdfProfile::Interactive).unwrap();
+    let encrypted = crypto::encrypt_with_password_kdf_async(
+        b"policy",
+        "pw".to_string(),
+        "a.txt",
+        vec![3u8; 32],
+        kdf,
+        Some(4096),
+    )
+    .await
+    .unwrap();
+    let ParsedHeader::Password(header) = XdReader::parse(&encrypted).unwrap().header else {
+        unreachable!()
+    };
+    assert_eq!(header.kdf, "scrypt");
+    assert_eq!((header.log_n, header.block_size), (Some(14), Some(8)));
+    assert!(header.memory_cost.is_none() && header.time_cost.is_none());
+    assert_eq!(PasswordKdf::from_header(&header).unwrap(), kdf);
+
+    let (plain, _) = crypto::decrypt_with_password_async(&encrypted, "pw".to_string())
+        .await
+        .unwrap();
+    assert_eq!(plain, b"policy");
+    assert!(matches!(
+        crypto::decrypt_with_password_async(&encrypted, "wrong".to_string()).await,
+        Err(crypto::CryptoError::AuthenticationError)
+    ));
+    // A hostile header can't ask for more memory than Argon2 headers may
+    assert!(ScryptParams::checked(Some(23), Some(8), Some(1)).is_err());
+    assert!(ScryptParams::checked(Some(20), Some(16), Some(1)).is_err());
+    assert!(ScryptParams::checked(None, Some(8), Some(1)).is_err());
+}
+
+#[test]
+fn cli_selects_scrypt_by_flag_or_config() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("notes.txt"), b"scrypted\n").unwrap();
+    fs::write(
+        dir.path().join("config.toml"),
+        "kdf = \"scrypt\"\nkdf_profile = \"interactive\"\n",
+    )
+    .unwrap();
+    let run = |args: &[&str]| run_in(dir.path(), args);
+    let kdf_of = |file: &str| {
+        let out = run(&["--json", "inspect", file]);
+        let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+        report["kdf"].clone()
+    };
+
+    let out = run(&[
+        "encrypt",
+        "--file",
+        "notes.txt",
+        "--password",
+        "pw",
+        "--kdf",
+        "scrypt",
+        "-o",
+        "a.xd",
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(kdf_of("a.xd")["name"], "scrypt");
+    assert_eq!(kdf_of("a.xd")["log_n"], 16);
+
+    let out = run(&[
+        "--config",
+        "config.toml",
+        "encrypt",
+        "--file",
+        "notes.txt",
+        "--password",
+        "pw",
+        "-o",
+        "b.xd",
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(kdf_of("b.xd")["log_n"], 14);
+
+    let out = run(&[
+        "decrypt",
+        "--file",
+        "b.xd",
+        "--password",
+        "pw",
+        "-o",
+        "out.txt",
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(fs::read(dir.path().join("out.txt")).unwrap(), b"scrypted\n");
+    let out = run(&[
+        "encrypt",
+        "--file",
+        "notes.txt",
+        "--password",
+        "pw",
+        "--kdf",
+        "bcrypt",
+        "--force",
+    ]);
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[tokio::test]
+async fn legacy_pbkdf2_files_still_decrypt() {
+    let legacy = crypto::encrypt_with_password_kdf_async(
+        b"from before argon2",
+        "pw".to_string(),
+        "old.txt",
+        vec![5u8; 32],
+        PasswordKdf::Pbkdf2 { iterations: 10_000 },
+        None,
+    )
+    .await
+    .unwrap();
+    let ParsedHeader::Password(header) = XdReader::parse(&legacy).unwrap().header else {
+        unreachable!()
+    };
+    assert_eq!(
+        (header.kdf.as_str(), header.iterations),
+        ("pbkdf2", Some(10_000))
+    );
+
+    let (plain, name) = crypto::decrypt_with_password_async(&legacy, "pw".to_string())
+        .await
+        .unwrap();
+    assert_eq!(
+        (plain.as_slice(), name.as_str()),
+        (&b"from before argon2"[..], "old.txt")
+    );
+    assert!(matches!(
+        crypto::decrypt_with_password_async(&legacy, "wrong".to_string()).await,
+        Err(crypto::CryptoError::AuthenticationError)
+    ));
+
+    // The CLI opens it through the same path
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("old.xd"), &legacy).unwrap();
+    let out = cli()
+        .args(["decrypt", "--file", "old.xd", "--password", "pw"])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(
+        fs::read(dir.path().join("old.txt")).unwrap(),
+        b"from before argon2"
+    );
+}
+
+#[test]
+fn kdf_profile_flag_overrides_the_config() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("notes.txt"), b"profiled\n").unwrap();
+    let run = |args: &[&str]| run_in(dir.path(), args);
+    let memory_cost_of = |file: &str| {
+        let out = run(&["--json", "inspect", file]);
+        let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+        report["kdf"]["memory_cost"].clone()
+    };
+
+    let out = run(&[
+        "encrypt",
+        "--file",
+        "notes.txt",
+        "--password",
+        "pw",
+        "--kdf-profile",
+        "interactive",
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(memory_cost_of("notes.xd"), 19 * 1024);
+
+    let out = run(&[
+        "rekey",
+        "notes.xd",
+        "--old-password",
+        "pw",
+        "--new-password",
+        "pw2",
+        "--kdf-profile",
+        "paranoid",
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(memory_cost_of("notes.xd"), 256 * 1024);
+
+    let out = run(&[
+        "encrypt",
+        "--file",
+        "notes.txt",
+        "--password",
+        "pw",
+        "--kdf-profile",
+        "extreme",
+        "--force",
+    ]);
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn derivations_beyond_the_thread_cap_wait_their_turn() {
+    crypto::set_kdf_threads(2);
+    let params = crypto::Argon2Params {
+        memory_cost: 64,
+        time_cost: 1,
+        parallelism: 1,
+    };
+    let salt = [7u8; 32];
+    let expected = crypto::derive_key_with_params("pw", &salt, &params).unwrap();
+    let keys: Vec<_> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..8)
+            .map(|_| scope.spawn(|| crypto::derive_key_with_params("pw", &salt, &params)))
+            .collect();
+        workers.into_iter().map(|w| w.join().unwrap()).collect()
+    });
+    assert!(
+        keys.iter()
+            .all(|key| key.as_ref().is_ok_and(|key| *key == expected))
+    );
+    crypto::set_kdf_threads(0);
+}
@@ -0,0 +1,286 @@
+mod common;
+
+use common::{cli, run_in};
+use encryptx_backend::format::XdReader;
+use encryptx_backend::{
+    api,
+    crypto::{self, Argon2Params, keyslot},
+};
+use std::fs;
+use tempfile::tempdir;
+use zeroize::Zeroizing;
+
+fn fast_params() -> Argon2Params {
+    Argon2Params {
+        memory_cost: 8 * 1024,
+        time_cost: 1,
+        parallelism: 1,
+    }
+}
+
+#[tokio::test]
+async fn any_slot_unlocks_and_forged_slots_are_rejected() {
+    let recovery = [7u8; 32];
+    let data_key = keyslot::generate_data_key();
+    let slots = vec![
+        keyslot::create(
+            &data_key,
+            keyslot::Credential::Password(Zeroizing::new("hunter2".to_string())),
+            fast_params(),
+            None,
+        )
+        .await
+        .unwrap(),
+        keyslot::create(
+            &data_key,
+            keyslot::Credential::Key(&recovery),
+            fast_params(),
+            Some("recovery".to_string()),
+        )
+        .await
+        .unwrap(),
+    ];
+    let encrypted =
+        crypto::encrypt_with_keyslots(b"shared secret", &data_key, "s.txt", slots, None).unwrap();
+
+    let (plain, _) = api::decrypt_file_bytes(&encrypted, Some("hunter2"), None)
+        .await
+        .unwrap();
+    assert_eq!(plain, b"shared secret");
+    let (plain, _) = crypto::decrypt_with_header(&encrypted, Some(&recovery)).unwrap();
+    assert_eq!(plain, b"shared secret");
+    assert!(
+        api::decrypt_file_bytes(&encrypted, Some("wrong"), None)
+            .await
+            .is_err()
+    );
+
+    // A slot wrapping some other data key under a known key must not be accepted
+    let attacker = [1u8; 32];
+    let forged = keyslot::create(
+        &keyslot::generate_data_key(),
+        keyslot::Credential::Key(&attacker),
+        fast_params(),
+        None,
+    )
+    .await
+    .unwrap();
+    let parsed = XdReader::parse(&encrypted).unwrap();
+    let (slots, check) = parsed.header.keyslots().unwrap();
+    let mut slots = slots.to_vec();
+    slots.push(forged);
+    assert!(matches!(
+        keyslot::unlock_with_key(&slots, check, &attacker),
+        Err(crypto::CryptoError::AuthenticationError)
+    ));
+}
+
+#[tokio::test]
+async fn cli_adds_and_removes_slots() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("secret.xd");
+    let encrypted = api::encrypt_file(b"many doors", Some("first"), None, "secret.txt")
+        .await
+        .unwrap()
+        .ciphertext;
+    fs::write(&path, &encrypted).unwrap();
+    let run = |args: &[&str]| {
+        cli()
+            .arg("keyslot")
+            .args(args)
+            .arg(path.to_str().unwrap())
+            .status()
+            .unwrap()
+            .success()
+    };
+
+    assert!(run(&[
+        "add",
+        "--password",
+        "first",
+        "--new-password",
+        "second"
+    ]));
+    for password in ["first", "second"] {
+        let (plain, _) = api::decrypt_file_bytes(&fs::read(&path).unwrap(), Some(password), None)
+            .await
+            .unwrap();
+        assert_eq!(plain, b"many doors");
+    }
+
+    assert!(run(&["remove", "--slot", "0", "--password", "second"]));
+    let updated = fs::read(&path).unwrap();
+    assert!(
+        api::decrypt_file_bytes(&updated, Some("first"), None)
+            .await
+            .is_err()
+    );
+    // The last remaining slot can't be removed
+    assert!(!run(&["remove", "--slot", "0", "--password", "second"]));
+}
+
+#[tokio::test]
+async fn recovery_key_unlocks_password_file() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("notes.txt");
+    let output = dir.path().join("notes.xd");
+    let recovery = dir.path().join("notes.recovery");
+    fs::write(&input, b"forgotten password").unwrap();
+
+    let status = cli()
+        .args(["encrypt", "--file", input.to_str().unwrap()])
+        .args([
+            "--password",
+            "correct horse",
+            "--output",
+            output.to_str().unwrap(),
+        ])
+        .args(["--recovery-out", recovery.to_str().unwrap()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let encrypted = fs::read(&output).unwrap();
+    let key = base64::Engine::decode(
+        &base64::engine::general_purpose::STANDARD,
+        fs::read_to_string(&recovery).unwrap().trim(),
+    )
+    .unwrap();
+    for (password, key) in [(Some("correct horse"), None), (None, Some(key.as_slice()))] {
+        let (plain, metadata) = api::decrypt_file_bytes(&encrypted, password, key)
+            .await
+            .unwrap();
+        assert_eq!(plain, b"forgotten password");
+        assert_eq!(metadata.filename, "notes.txt");
+    }
+}
+
+#[tokio::test]
+async fn concurrent_slot_additions_are_merged() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("shared.xd");
+    let owner = [3u8; 32];
+    let encrypted = api::encrypt_file(b"shared store", None, Some(&owner), "shared.txt")
+        .await
+        .unwrap()
+        .ciphertext;
+    fs::write(&path, &encrypted).unwrap();
+    let b64 =
+        |key: &[u8; 32]| base64::Engine::encode(&base64::engine::general_purpose::STANDARD, key);
+
+    // Both writers start from the same version; the loser must re-apply its slot
+    let new_keys: Vec<[u8; 32]> = (10..16).map(|i| [i; 32]).collect();
+    let children: Vec<_> = new_keys
+        .iter()
+        .map(|new_key| {
+            cli()
+                .args(["keyslot", "add", path.to_str().unwrap()])
+                .args(["--key", &b64(&owner), "--new-key", &b64(new_key)])
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+    let succeeded: Vec<&[u8; 32]> = children
+        .into_iter()
+        .zip(&new_keys)
+        .filter_map(|(mut child, key)| child.wait().unwrap().success().then_some(key))
+        .collect();
+
+    // Every successful writer's key opens the final file; failures wrote nothing
+    let updated = fs::read(&path).unwrap();
+    let parsed = XdReader::parse(&updated).unwrap();
+    assert_eq!(
+        parsed.header.keyslots().unwrap().0.len(),
+        1 + succeeded.len()
+    );
+    assert!(!succeeded.is_empty());
+    for key in succeeded {
+        let (plain, _) = api::decrypt_file_bytes(&updated, None, Some(key))
+            .await
+            .unwrap();
+        assert_eq!(plain, b"shared store");
+    }
+}
+
+#[test]
+fn split_credentials_need_both_parts() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("deal.txt");
+    fs::write(&input, b"terms").unwrap();
+    let encrypted = dir.path().join("deal.xd");
+    let status = cli()
+        .args(["encrypt", "--file", input.to_str().unwrap()])
+        .args(["--output", encrypted.to_str().unwrap()])
+        .arg("--split-credentials")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let part = |i: u8| format!("{}.{i}.cred", encrypted.display());
+    assert!(
+        fs::read_to_string(part(1))
+            .unwrap()
+            .starts_with("xdpart1:1:")
+    );
+    // Nothing in the header opens the file on its own
+    assert!(crypto::decrypt_with_header(&fs::read(&encrypted).unwrap(), None).is_err());
+
+    let decrypt = |parts: &[String]| {
+        let mut cmd = cli();
+        cmd.args(["decrypt", "--file", encrypted.to_str().unwrap()])
+            .args(["--output", "-"]);
+        for p in parts {
+            cmd.args(["--credential", p]);
+        }
+        cmd.output().unwrap()
+    };
+    let out = decrypt(&[part(2), part(1)]);
+    assert!(out.status.success());
+    assert_eq!(out.stdout, b"terms");
+    assert_eq!(decrypt(&[part(1)]).status.code(), Some(2));
+}
+
+#[test]
+fn two_factor_files_need_password_and_keyfile() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("plans.txt"), b"both factors").unwrap();
+    fs::write(dir.path().join("usb.key"), b"random keyfile bytes").unwrap();
+    fs::write(dir.path().join("other.key"), b"another keyfile").unwrap();
+    let run = |args: &[&str]| run_in(dir.path(), args);
+
+    let out = run(&[
+        "--json",
+        "encrypt",
+        "--file",
+        "plans.txt",
+        "--password",
+        "pw",
+        "--keyfile",
+        "usb.key",
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["mode"], "two-factor");
+    let encrypted = fs::read(dir.path().join("plans.xd")).unwrap();
+    let parsed = XdReader::parse(&encrypted).unwrap();
+    let (slots, _) = parsed.header.keyslots().unwrap();
+    assert_eq!(slots.len(), 1);
+    assert_eq!(slots[0].kind, keyslot::KIND_PASSWORD_KEYFILE);
+
+    let decrypt = |extra: &[&str]| {
+        let mut args = vec!["decrypt", "--file", "plans.xd", "-o", "out.txt", "--force"];
+        args.extend(extra);
+        run(&args)
+    };
+    // The password alone names the missing factor instead of just failing
+    assert_eq!(decrypt(&["--password", "pw"]).status.code(), Some(5));
+    let wrong = ["--password", "pw", "--keyfile", "other.key"];
+    assert_eq!(decrypt(&wrong).status.code(), Some(4));
+    let wrong = ["--password", "nope", "--keyfile", "usb.key"];
+    assert_eq!(decrypt(&wrong).status.code(), Some(4));
+    let out = decrypt(&["--password", "pw", "--keyfile", "usb.key"]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(
+        fs::read(dir.path().join("out.txt")).unwrap(),
+        b"both factors"
+    );
+}
@@ -0,0 +1,201 @@
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use common::cli;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Output;
+use tempfile::tempdir;
+
+/// Stand-in for libsecret's `secret-tool`, keeping each account's secret in a file under
+/// `$FAKE_KEYRING`.
+const FAKE_SECRET_TOOL: &str = r#"#!/bin/sh
+action=$1; shift
+account=
+while [ $# -gt 0 ]; do
+    [ "$1" = account ] && { account=$2; shift; }
+    shift
+done
+case $action in
+    store) cat > "$FAKE_KEYRING/$account" ;;
+    lookup) [ -f "$FAKE_KEYRING/$account" ] || exit 1; cat "$FAKE_KEYRING/$account" ;;
+    clear) rm -f "$FAKE_KEYRING/$account" ;;
+esac
+"#;
+
+fn run(dir: &Path, args: &[&str]) -> Output {
+    cli()
+        .args(args)
+        .current_dir(dir)
+        .env("ENCRYPTX_KEYSTORE_TOOL", dir.join("secret-tool"))
+        .env("FAKE_KEYRING", dir.join("keyring"))
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn named_keys_come_from_the_os_secret_store() {
+    let dir = tempdir().unwrap();
+    let tool = dir.path().join("secret-tool");
+    fs::write(&tool, FAKE_SECRET_TOOL).unwrap();
+    fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::create_dir(dir.path().join("keyring")).unwrap();
+    fs::write(dir.path().join("plan.txt"), b"quarterly plan\n").unwrap();
+
+    let out = run(dir.path(), &["--json", "key", "add", "work"]);
+    assert!(out.status.success(), "{out:?}");
+    let added: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(added["backend"], "secret-service");
+    assert_eq!(added["generated"], true);
+    // An existing name is only replaced with --force
+    assert_eq!(
+        run(dir.path(), &["key", "add", "work"]).status.code(),
+        Some(2)
+    );
+    assert_eq!(
+        run(dir.path(), &["key", "add", "bad name"]).status.code(),
+        Some(2)
+    );
+
+    let encrypt = [
+        "--json",
+        "encrypt",
+        "--file",
+        "plan.txt",
+        "--key-name",
+        "work",
+        "--force",
+    ];
+    let out = run(dir.path(), &encrypt);
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["key_fingerprint"], added["key_fingerprint"]);
+    assert!(report["generated_key"].is_null());
+
+    let decrypt = |name: &str| {
+        let args = [
+            "--json",
+            "decrypt",
+            "--file",
+            "plan.xd",
+            "--key-name",
+            name,
+            "-o",
+            "out.txt",
+            "--force",
+        ];
+        run(dir.path(), &args)
+    };
+    let out = decrypt("work");
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(
+        fs::read(dir.path().join("out.txt")).unwrap(),
+        b"quarterly plan\n"
+    );
+
+    // Exported keys work as --key
+    let out = run(dir.path(), &["key", "export", "work"]);
+    assert!(out.status.success(), "{out:?}");
+    let key = String::from_utf8(out.stdout).unwrap();
+    let out = run(
+        dir.path(),
+        &[
+            "decrypt",
+            "--file",
+            "plan.xd",
+            "--key",
+            key.trim(),
+            "-o",
+            "out.txt",
+            "--force",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+
+    let out = run(dir.path(), &["key", "rm", "work"]);
+    assert!(out.status.success(), "{out:?}");
+    let out = decrypt("work");
+    assert_eq!(out.status.code(), Some(2));
+    let error: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert!(
+        error["message"]
+            .as_str()
+            .unwrap()
+            .contains("No key named 'work'")
+    );
+}
+
+#[test]
+fn keystore_file_holds_named_keys_under_a_passphrase() {
+    let dir = tempdir().unwrap();
+    let config = dir.path().join("config.toml");
+    fs::write(
+        &config,
+        format!(
+            "kdf_profile = \"interactive\"\nkeystore = \"{}\"\n",
+            dir.path().join("keys.xd").display()
+        ),
+    )
+    .unwrap();
+    fs::write(dir.path().join("plan.txt"), b"quarterly plan\n").unwrap();
+    let run = |passphrase: &str, args: &[&str]| {
+        cli()
+            .arg("--config")
+            .arg(&config)
+            .args(args)
+            .current_dir(dir.path())
+            .env("ENCRYPTX_KEYSTORE_PASSPHRASE", passphrase)
+            .output()
+            .unwrap()
+    };
+
+    for name in ["backups", "archive"] {
+        let out = run("master pass", &["--json", "key", "add", name]);
+        assert!(out.status.success(), "{out:?}");
+        let added: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+        assert_eq!(added["backend"], "file");
+    }
+    let out = run("master pass", &["--json", "key", "list"]);
+    assert!(out.status.success(), "{out:?}");
+    let listed: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(listed["names"], serde_json::json!(["archive", "backups"]));
+    // The keys are not readable without the passphrase
+    let stored = fs::read(dir.path().join("keys.xd")).unwrap();
+    assert!(!String::from_utf8_lossy(&stored).contains("backups"));
+    assert_eq!(run("wrong pass", &["key", "list"]).status.code(), Some(4));
+
+    let out = run(
+        "master pass",
+        &[
+            "encrypt",
+            "--file",
+            "plan.txt",
+            "--key-name",
+            "backups",
+            "--force",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    let decrypt = [
+        "decrypt",
+        "--file",
+        "plan.xd",
+        "--key-name",
+        "backups",
+        "-o",
+        "out.txt",
+        "--force",
+    ];
+    let out = run("master pass", &decrypt);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(
+        fs::read(dir.path().join("out.txt")).unwrap(),
+        b"quarterly plan\n"
+    );
+
+    let out = run("master pass", &["key", "rm", "backups"]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(run("master pass", &decrypt).status.code(), Some(2));
+}
@@ -0,0 +1,125 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::cli;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Output;
+use tempfile::tempdir;
+
+/// Stand-in for `gcloud kms`: "wraps" by tagging the data with the key name, and refuses
+/// everything while `$FAKE_KMS_DENY` is set.
+const FAKE_GCLOUD: &str = r#"#!/bin/sh
+[ -n "$FAKE_KMS_DENY" ] && { echo "ERROR: PERMISSION_DENIED on cloudkms.cryptoKeyVersions.useToDecrypt" >&2; exit 1; }
+key=${3#--key=}
+case $2 in
+    encrypt) printf 'sealed-by:%s\n' "$key"; cat ;;
+    decrypt) IFS= read -r tag; [ "$tag" = "sealed-by:$key" ] || { echo "ERROR: INVALID_ARGUMENT" >&2; exit 1; }
+             cat ;;
+esac
+"#;
+
+const FAKE_AZ: &str = "#!/bin/sh\necho fake-token\n";
+
+/// Stand-in for `curl --config -` talking to Key Vault: "wraps" by echoing the value, and
+/// only unwraps with version v2 of the key.
+const FAKE_CURL: &str = r#"#!/bin/sh
+config=$(cat)
+echo "$config" | grep -q 'Bearer fake-token' || { printf '{}\n401'; exit 0; }
+value=$(echo "$config" | sed -n 's/^data = .*value\\":\\"\([A-Za-z0-9_-]*\)\\".*/\1/p')
+case $config in
+    *"/keys/ledger/wrapkey"*)
+        printf '{"kid":"https://corp.vault.azure.net/keys/ledger/v2","value":"%s"}\n200' "$value" ;;
+    *"/keys/ledger/v2/unwrapkey"*)
+        printf '{"kid":"https://corp.vault.azure.net/keys/ledger/v2","value":"%s"}\n200' "$value" ;;
+    *) printf '{"error":{"code":"KeyNotFound","message":"A key with (name/id) was not found"}}\n404' ;;
+esac
+"#;
+
+fn run(dir: &Path, deny: bool, args: &[&str]) -> Output {
+    let mut command = cli();
+    command
+        .args(args)
+        .current_dir(dir)
+        .env("ENCRYPTX_KMS_TOOLS", dir.join("tools"));
+    if deny {
+        command.env("FAKE_KMS_DENY", "1");
+    } else {
+        command.env_remove("FAKE_KMS_DENY");
+    }
+    command.output().unwrap()
+}
+
+#[test]
+fn kms_wrapped_files_open_through_the_provider() {
+    let dir = tempdir().unwrap();
+    let tools = dir.path().join("tools");
+    fs::create_dir(&tools).unwrap();
+    for (name, script) in [
+        ("gcloud", FAKE_GCLOUD),
+        ("az", FAKE_AZ),
+        ("curl", FAKE_CURL),
+    ] {
+        fs::write(tools.join(name), script).unwrap();
+        fs::set_permissions(tools.join(name), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    fs::write(dir.path().join("budget.txt"), b"cloud wrapped\n").unwrap();
+
+    let encrypt = |uri: &str| {
+        let args = [
+            "--json",
+            "encrypt",
+            "--file",
+            "budget.txt",
+            "-o",
+            "budget.xd",
+            "--kms",
+            uri,
+            "--force",
+        ];
+        run(dir.path(), false, &args)
+    };
+    let decrypt = |deny: bool| {
+        let args = [
+            "--json",
+            "decrypt",
+            "--file",
+            "budget.xd",
+            "-o",
+            "out.txt",
+            "--force",
+        ];
+        run(dir.path(), deny, &args)
+    };
+    assert_eq!(encrypt("awskms://alias/backups").status.code(), Some(2));
+    assert_eq!(
+        encrypt("gcpkms://projects/p/keyRings/r").status.code(),
+        Some(2)
+    );
+
+    for uri in [
+        // Unwrapping needs the version the wrap response named
+        "azurekv://corp.vault.azure.net/keys/ledger",
+        "gcpkms://projects/acme/locations/global/keyRings/files/cryptoKeys/budget",
+    ] {
+        let out = encrypt(uri);
+        assert!(out.status.success(), "{out:?}");
+        let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+        assert_eq!(report["mode"], "kms");
+
+        // No local credential: the slot names the key to ask
+        let out = decrypt(false);
+        assert!(out.status.success(), "{out:?}");
+        assert_eq!(
+            fs::read(dir.path().join("out.txt")).unwrap(),
+            b"cloud wrapped\n"
+        );
+    }
+
+    // The service's refusal is shown, not just a wrong-key error
+    let out = decrypt(true);
+    assert_eq!(out.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("PERMISSION_DENIED"));
+}
@@ -0,0 +1,152 @@
+mod common;
+
+use common::{KEY, cli};
+use encryptx_backend::locale::{self, LOCALES};
+use std::fs;
+use std::path::Path;
+use std::process::Output;
+use tempfile::tempdir;
+
+/// Runs the CLI in `dir` with `lang` as the only locale variable set.
+fn run(dir: &Path, lang: Option<&str>, args: &[&str]) -> Output {
+    let mut command = cli();
+    command
+        .args(args)
+        .current_dir(dir)
+        .env_remove("LC_ALL")
+        .env_remove("LC_MESSAGES")
+        .env_remove("LANG");
+    if let Some(lang) = lang {
+        command.env("LANG", lang);
+    }
+    command.output().unwrap()
+}
+
+#[test]
+fn locales_are_matched_by_language() {
+    assert_eq!(locale::negotiate("de"), Some("de"));
+    assert_eq!(locale::negotiate("de_DE.UTF-8"), Some("de"));
+    assert_eq!(locale::negotiate("DE-at"), Some("de"));
+    assert_eq!(locale::negotiate("en_GB"), Some("en"));
+    assert_eq!(locale::negotiate("C.UTF-8"), Some("en"));
+    assert_eq!(locale::negotiate("POSIX"), Some("en"));
+    assert_eq!(locale::negotiate("xx_XX"), None);
+    assert_eq!(locale::negotiate(""), None);
+}
+
+#[test]
+fn translations_match_the_english_catalog() {
+    let english = locale::parse(LOCALES[0].1);
+    assert!(english.len() > 50);
+    for (code, source) in &LOCALES[1..] {
+        for (id, text) in locale::parse(source) {
+            let original = english
+                .get(id)
+                .unwrap_or_else(|| panic!("{code}: '{id}' is not an English message"));
+            let mut expected = locale::placeables(original);
+            let mut placeables = locale::placeables(text);
+            expected.sort_unstable();
+            placeables.sort_unstable();
+            assert_eq!(placeables, expected, "{code}: placeables of '{id}'");
+        }
+    }
+
+    // Before a language is chosen, messages are English; unknown ids come out as they are
+    assert_eq!(
+        locale::message("decrypted-size", &[("bytes", &42)]),
+        "📊 Decrypted size: 42 bytes"
+    );
+    assert_eq!(locale::message("no-such-message", &[]), "no-such-message");
+}
+
+#[test]
+fn messages_follow_the_locale() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("note.txt"), "hallo").unwrap();
+
+    let out = run(
+        dir.path(),
+        None,
+        &["--locale", "de", "encrypt", "note.txt", "--key", KEY],
+    );
+    assert!(out.status.success(), "{out:?}");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("Verschlüssele Datei 'note.txt'"),
+        "{stderr}"
+    );
+
+    // LANG picks the language when --locale is absent
+    let out = run(
+        dir.path(),
+        Some("de_DE.UTF-8"),
+        &[
+            "decrypt", "-f", "note.xd", "--key", KEY, "--output", "back.txt",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Entschlüsselte Datei nach 'back.txt'"));
+
+    let out = run(
+        dir.path(),
+        Some("de_DE.UTF-8"),
+        &["decrypt", "-f", "missing.xd", "--key", KEY],
+    );
+    assert_eq!(out.status.code(), Some(2), "{out:?}");
+    assert!(String::from_utf8_lossy(&out.stderr).starts_with("Fehler: Ungültige Eingabe"));
+
+    // --locale wins over LANG, and an unknown LANG means English
+    let out = run(
+        dir.path(),
+        Some("de_DE.UTF-8"),
+        &[
+            "--locale",
+            "en",
+            "decrypt",
+            "-f",
+            "missing.xd",
+            "--key",
+            KEY,
+        ],
+    );
+    assert!(String::from_utf8_lossy(&out.stderr).starts_with("Error: Invalid input"));
+    let out = run(
+        dir.path(),
+        Some("xx_XX"),
+        &["decrypt", "-f", "missing.xd", "--key", KEY],
+    );
+    assert!(String::from_utf8_lossy(&out.stderr).starts_with("Error: Invalid input"));
+
+    // A --locale without a catalog is refused
+    let out = run(
+        dir.path(),
+        None,
+        &["--locale", "xx", "decrypt", "-f", "note.xd"],
+    );
+    assert_eq!(out.status.code(), Some(2), "{out:?}");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("available: en, de"));
+}
+
+#[test]
+fn summaries_of_other_subcommands_follow_the_locale() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("note.txt"), "hallo").unwrap();
+    let out = run(dir.path(), None, &["encrypt", "note.txt", "--key", KEY]);
+    assert!(out.status.success(), "{out:?}");
+
+    let out = run(dir.path(), None, &["--locale", "de", "inspect", "note.xd"]);
+    assert!(out.status.success(), "{out:?}");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("📄 Datei: note.xd"), "{stdout}");
+    assert!(stdout.contains("Schlüsselplatz 0: key"), "{stdout}");
+    assert!(
+        stdout.contains("Ursprünglicher Dateiname: note.txt"),
+        "{stdout}"
+    );
+
+    let out = run(dir.path(), Some("de_DE.UTF-8"), &["capabilities"]);
+    assert!(out.status.success(), "{out:?}");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("🔒 Chiffren: "), "{stdout}");
+    assert!(!stdout.contains("enabled"), "{stdout}");
+}
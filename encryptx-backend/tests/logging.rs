@@ -0,0 +1,167 @@
+mod common;
+
+use base64::{Engine as _, engine::general_purpose};
+use common::cli;
+use encryptx_backend::logging::Redactor;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::Output;
+use std::sync::{Arc, Mutex};
+use tempfile::tempdir;
+
+/// Log output collected in memory.
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// What `log` writes through a subscriber using `redactor`.
+fn log_with(redactor: Redactor, log: impl FnOnce()) -> String {
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .fmt_fields(redactor)
+        .finish();
+    tracing::subscriber::with_default(subscriber, log);
+    String::from_utf8(captured.0.lock().unwrap().clone()).unwrap()
+}
+
+#[test]
+fn secrets_and_file_names_are_redacted() {
+    let key = general_purpose::STANDARD.encode([42u8; 32]);
+    let log = || {
+        tracing::info!(password = "hunter2", size = 512, "Encrypting");
+        tracing::info!(
+            x_enc_key = key.as_str(),
+            filename = "salaries.xlsx",
+            "Encrypting"
+        );
+        // A key formatted into the message is caught by its encoding
+        tracing::info!("Generated key {key}, other words stay");
+    };
+
+    let output = log_with(Redactor::default(), log);
+    assert!(output.contains("Encrypting password=[redacted] size=512"));
+    assert!(output.contains("x_enc_key=[redacted] filename=[redacted]"));
+    assert!(output.contains("Generated key [redacted], other words stay"));
+    assert!(!output.contains("hunter2"));
+    assert!(!output.contains(&key));
+    assert!(!output.contains("salaries"));
+
+    let output = log_with(
+        Redactor {
+            show_filenames: true,
+        },
+        log,
+    );
+    assert!(output.contains("filename=\"salaries.xlsx\""));
+    assert!(!output.contains("hunter2"));
+    assert!(!output.contains(&key));
+}
+
+fn run(dir: &Path, args: &[&str]) -> Output {
+    cli()
+        .args(args)
+        .current_dir(dir)
+        .env_remove("RUST_LOG")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn cli_messages_go_to_stderr_and_the_log_file() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "alpha").unwrap();
+
+    // stdout stays empty, so nothing informational gets mixed into piped data
+    let out = run(dir.path(), &["encrypt", "a.txt", "-o", "a.xd"]);
+    assert!(out.status.success(), "{out:?}");
+    assert!(out.stdout.is_empty(), "{out:?}");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("Encrypted file written to 'a.xd'"),
+        "{stderr}"
+    );
+    let key = stderr
+        .lines()
+        .find_map(|line| line.strip_prefix("✨ Generated random key (base64): "))
+        .unwrap()
+        .to_string();
+
+    let out = run(
+        dir.path(),
+        &[
+            "decrypt",
+            "-f",
+            "a.xd",
+            "-o",
+            "-",
+            "--key",
+            &key,
+            "-v",
+            "--log-file",
+            "cli.log",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(out.stdout, b"alpha");
+    // Verbose lines carry their level and origin
+    assert!(String::from_utf8_lossy(&out.stderr).contains("INFO encryptx_backend"));
+    let log = fs::read_to_string(dir.path().join("cli.log")).unwrap();
+    assert!(log.contains("Decrypting file 'a.xd'"), "{log}");
+
+    // A generated key is shown on the terminal only, never written to the log
+    let out = run(
+        dir.path(),
+        &["encrypt", "a.txt", "-o", "b.xd", "--log-file", "cli.log"],
+    );
+    assert!(out.status.success(), "{out:?}");
+    let log = fs::read_to_string(dir.path().join("cli.log")).unwrap();
+    assert!(log.contains("Encrypted file written to 'b.xd'"), "{log}");
+    assert!(!log.contains("Generated random key"), "{log}");
+
+    // Quieting the log hides status lines but never the only copy of a generated key
+    let out = run(
+        dir.path(),
+        &[
+            "encrypt",
+            "a.txt",
+            "-o",
+            "c.xd",
+            "--force",
+            "--log-level",
+            "error",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(!stderr.contains("Encrypted file written"), "{stderr}");
+    let key = stderr
+        .lines()
+        .find_map(|line| line.strip_prefix("✨ Generated random key (base64): "))
+        .unwrap_or_else(|| panic!("no key in {stderr}"))
+        .to_string();
+    let out = run(
+        dir.path(),
+        &["decrypt", "-f", "c.xd", "-o", "-", "--key", &key],
+    );
+    assert_eq!(out.stdout, b"alpha", "{out:?}");
+
+    let out = run(
+        dir.path(),
+        &["encrypt", "a.txt", "-v", "--log-level", "warn"],
+    );
+    assert_eq!(out.status.code(), Some(2), "{out:?}");
+}
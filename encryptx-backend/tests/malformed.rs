@@ -0,0 +1,114 @@
+use encryptx_backend::crypto::{self, SecureKey, keyslot, repair};
+use encryptx_backend::format::{self, MAX_HEADER_LEN, MAX_KEYSLOTS, XdReader, XdWriter};
+
+/// Runs `data` through every `.xd` parser; any panic fails the test.
+fn parse_everything(data: &[u8], key: &SecureKey) {
+    let _ = format::detect(data);
+    let _ = crypto::chunked::part_boundaries(data);
+    let _ = crypto::decrypt_with_header(data, Some(key.as_slice()));
+    for password_mode in [false, true] {
+        if let Ok(damaged) = repair::locate(data, Some(password_mode)) {
+            assert!(damaged.header_json.len() <= MAX_HEADER_LEN);
+            let _ = repair::recover_fields(damaged.header_json, damaged.password_mode);
+        }
+    }
+    if let Ok(file) = XdReader::parse(data) {
+        let _ = crypto::verify_regions(&file, key);
+        let _ = crypto::salvage_regions(&file, key);
+        let _ = crypto::decrypt_parsed(&file, key);
+    }
+}
+
+/// `header` framed in key mode around a dummy nonce and tag, without [`XdWriter`]'s checks.
+fn frame(header: &[u8]) -> Vec<u8> {
+    let mut data = (header.len() as u32).to_be_bytes().to_vec();
+    data.extend_from_slice(header);
+    data.extend_from_slice(&[0u8; 12 + 16]);
+    data
+}
+
+#[test]
+fn truncated_and_mangled_files_are_rejected() {
+    let key = SecureKey::new([7u8; 32]);
+    let single = crypto::encrypt_with_header(b"one message", key.as_slice(), "a.txt").unwrap();
+    let chunked =
+        crypto::encrypt_with_header_chunked(&vec![b'x'; 5000], key.as_slice(), "b.txt", Some(1024))
+            .unwrap();
+
+    for file in [&single, &chunked] {
+        // Every strict prefix either fails to parse or fails to authenticate
+        for len in 0..file.len() {
+            let truncated = &file[..len];
+            parse_everything(truncated, &key);
+            assert!(crypto::decrypt_with_header(truncated, Some(key.as_slice())).is_err());
+        }
+
+        // Length prefixes pointing anywhere are caught before slicing
+        for stated in [0, 1, 3, MAX_HEADER_LEN as u32 + 1, u32::MAX / 2, u32::MAX] {
+            let mut mangled = file.clone();
+            mangled[..4].copy_from_slice(&stated.to_be_bytes());
+            parse_everything(&mangled, &key);
+            assert!(XdReader::parse(&mangled).is_err());
+        }
+
+        // Flipping any header byte never panics
+        let header_len = u32::from_be_bytes(file[..4].try_into().unwrap()) as usize;
+        for at in 4..4 + header_len {
+            let mut mangled = file.clone();
+            mangled[at] ^= 0x80;
+            parse_everything(&mangled, &key);
+        }
+    }
+}
+
+#[test]
+fn headers_over_the_limits_are_refused() {
+    // Padded with whitespace, a header of exactly the limit still parses
+    let mut header = br#"{"filename":"a.txt","version":2}"#.to_vec();
+    header.resize(MAX_HEADER_LEN, b' ');
+    assert!(XdReader::parse(&frame(&header)).is_ok());
+    header.push(b' ');
+    assert!(XdReader::parse(&frame(&header)).is_err());
+    assert!(
+        repair::locate(&frame(&header), Some(false))
+            .is_ok_and(|damaged| damaged.header_json.len() <= MAX_HEADER_LEN)
+    );
+    assert!(
+        XdWriter::key(&header)
+            .nonce(&[0u8; 12])
+            .body(&[0u8; 16])
+            .finish()
+            .is_err()
+    );
+    assert!(
+        XdWriter::key(b"")
+            .nonce(&[0u8; 12])
+            .body(&[0u8; 16])
+            .finish()
+            .is_err()
+    );
+
+    // One keyslot too many is refused when writing and when reading
+    let data_key = keyslot::generate_data_key();
+    let slot = keyslot::Keyslot {
+        kind: keyslot::KIND_KEY.to_string(),
+        ..Default::default()
+    };
+    let slots = vec![slot; MAX_KEYSLOTS + 1];
+    assert!(
+        crypto::encrypt_with_keyslots(b"data", &data_key, "a.txt", slots.clone(), None).is_err()
+    );
+    let header = |slots: &[keyslot::Keyslot]| {
+        frame(
+            &serde_json::to_vec(&serde_json::json!({
+                "filename": "a.txt",
+                "version": 3,
+                "keyslots": slots,
+                "key_check": "",
+            }))
+            .unwrap(),
+        )
+    };
+    assert!(XdReader::parse(&header(&slots[..MAX_KEYSLOTS])).is_ok());
+    assert!(XdReader::parse(&header(&slots)).is_err());
+}
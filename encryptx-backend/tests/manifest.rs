@@ -0,0 +1,47 @@
+mod common;
+
+use common::{KEY, run};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn verify_manifest_finds_missing_and_modified_files() {
+    let dir = tempdir().unwrap();
+    let src = dir.path().join("src");
+    let out = dir.path().join("out");
+    fs::create_dir_all(src.join("sub")).unwrap();
+    fs::write(src.join("a.txt"), b"alpha").unwrap();
+    fs::write(src.join("sub/b.txt"), b"bravo").unwrap();
+    let (src_path, out_path) = (src.to_str().unwrap(), out.to_str().unwrap());
+    let output = run(&["encrypt", "--dir", src_path, "-o", out_path, "--key", KEY]);
+    assert!(output.status.success(), "{output:?}");
+
+    let manifest = out.join(".encryptx-manifest");
+    let manifest = manifest.to_str().unwrap();
+    let verify = |key: &str| {
+        run(&[
+            "--json",
+            "verify-manifest",
+            manifest,
+            src_path,
+            "--key",
+            key,
+        ])
+    };
+    let output = verify(KEY);
+    assert!(output.status.success(), "{output:?}");
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["matched"], 2);
+
+    // Same size, other contents, and a file that didn't come back
+    fs::write(src.join("a.txt"), b"ALPHA").unwrap();
+    fs::remove_file(src.join("sub/b.txt")).unwrap();
+    let output = verify(KEY);
+    assert_eq!(output.status.code(), Some(4));
+    let error = String::from_utf8_lossy(&output.stdout);
+    assert!(error.contains("a.txt, sub/b.txt"), "{error}");
+
+    // The manifest is encrypted under the directory's key
+    let output = verify("BQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQU=");
+    assert_eq!(output.status.code(), Some(4));
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use common::{KEY, run_in};
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+/// Encrypts `name` with the extra `args` and decrypts it again; returns what came back.
+fn round_trip(dir: &Path, name: &str, args: &[&str]) -> Vec<u8> {
+    let encrypted = format!("{name}.xd");
+    let mut encrypt = vec!["encrypt", name, "--key", KEY, "-o", &encrypted, "--force"];
+    encrypt.extend_from_slice(args);
+    let out = run_in(dir, &encrypt);
+    assert!(out.status.success(), "{out:?}");
+    let out = run_in(dir, &["decrypt", "-f", &encrypted, "--key", KEY, "-o", "-"]);
+    assert!(out.status.success(), "{out:?}");
+    out.stdout
+}
+
+#[test]
+fn mapped_files_round_trip() {
+    let dir = tempdir().unwrap();
+    // Several MiB, partly incompressible, so both compression paths read from the map
+    let mut data: Vec<u8> = (0u32..3 << 20)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+        .collect();
+    data.extend(b"compressible ".repeat(100_000));
+    fs::write(dir.path().join("big.bin"), &data).unwrap();
+
+    assert_eq!(round_trip(dir.path(), "big.bin", &[]), data);
+    assert_eq!(
+        round_trip(dir.path(), "big.bin", &["--chunk-size", "1MiB"]),
+        data
+    );
+    // The preallocated output is exactly as long as what was written into it
+    let out = run_in(
+        dir.path(),
+        &[
+            "decrypt",
+            "-f",
+            "big.bin.xd",
+            "--key",
+            KEY,
+            "-o",
+            "back.bin",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(fs::read(dir.path().join("back.bin")).unwrap(), data);
+}
+
+#[test]
+fn empty_files_round_trip() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("empty.txt"), b"").unwrap();
+
+    assert!(round_trip(dir.path(), "empty.txt", &[]).is_empty());
+    let out = run_in(
+        dir.path(),
+        &[
+            "decrypt",
+            "-f",
+            "empty.txt.xd",
+            "--key",
+            KEY,
+            "-o",
+            "back.txt",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(fs::metadata(dir.path().join("back.txt")).unwrap().len(), 0);
+}
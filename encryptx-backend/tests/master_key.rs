@@ -0,0 +1,141 @@
+mod common;
+
+use common::{cli, run_in};
+use encryptx_backend::crypto;
+use encryptx_backend::format::{ParsedHeader, XdReader};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn master_key_files_each_get_their_own_key() {
+    let master = [7u8; 32];
+    let first = crypto::encrypt_with_master_key(b"one", &master, "a.txt", None).unwrap();
+    let second = crypto::encrypt_with_master_key(b"two", &master, "b.txt", Some(4096)).unwrap();
+
+    let salts: Vec<String> = [&first, &second]
+        .iter()
+        .map(|data| match XdReader::parse(data).unwrap().header {
+            ParsedHeader::Key(h) => {
+                assert!(h.key.is_none());
+                h.subkey_salt.unwrap()
+            }
+            ParsedHeader::Password(_) => unreachable!(),
+        })
+        .collect();
+    assert_ne!(salts[0], salts[1]);
+
+    let (plain, name) = crypto::decrypt_with_header(&second, Some(&master)).unwrap();
+    assert_eq!((plain.as_slice(), name.as_str()), (&b"two"[..], "b.txt"));
+    // One file's key doesn't open the other, and the master key is required
+    let parsed = XdReader::parse(&first).unwrap();
+    let first_key = crypto::derive_subkey(&master, &salt_bytes(&salts[0])).unwrap();
+    assert_eq!(crypto::decrypt_parsed(&parsed, &first_key).unwrap(), b"one");
+    assert!(matches!(
+        crypto::decrypt_with_header(&second, Some(first_key.as_slice())),
+        Err(crypto::CryptoError::AuthenticationError)
+    ));
+    assert!(matches!(
+        crypto::decrypt_with_header(&first, None),
+        Err(crypto::CryptoError::WrongDecryptionMethod(_))
+    ));
+}
+
+fn salt_bytes(salt: &str) -> Vec<u8> {
+    use base64::Engine;
+    base64::engine::general_purpose::STANDARD
+        .decode(salt)
+        .unwrap()
+}
+
+#[test]
+fn cli_encrypts_with_a_master_key() {
+    let dir = tempdir().unwrap();
+    let master = "BwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwc=";
+    fs::write(dir.path().join("notes.txt"), b"derived\n").unwrap();
+    let run = |args: &[&str]| run_in(dir.path(), args);
+
+    let out = run(&[
+        "--json",
+        "encrypt",
+        "--file",
+        "notes.txt",
+        "--key",
+        master,
+        "--master-key",
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["mode"], "master-key");
+    // Without --key there is nothing to derive from
+    assert_eq!(
+        run(&["encrypt", "--file", "notes.txt", "--master-key", "--force"])
+            .status
+            .code(),
+        Some(2)
+    );
+
+    let out = run(&["--json", "inspect", "notes.xd"]);
+    let inspected: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(inspected["mode"], "master-key");
+    assert_eq!(inspected["key_embedded"], false);
+
+    let decrypt = |extra: &[&str]| {
+        let mut args = vec!["decrypt", "--file", "notes.xd", "-o", "out.txt", "--force"];
+        args.extend(extra);
+        run(&args)
+    };
+    assert_eq!(decrypt(&[]).status.code(), Some(2));
+    let out = decrypt(&["--key", master]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(fs::read(dir.path().join("out.txt")).unwrap(), b"derived\n");
+}
+
+#[test]
+fn convergent_files_repeat_for_the_same_plaintext() {
+    let secret = [9u8; 32];
+    let first = crypto::encrypt_convergent(b"backup", &secret, "a.bin", Some(4096)).unwrap();
+    let again = crypto::encrypt_convergent(b"backup", &secret, "a.bin", Some(4096)).unwrap();
+    let other = crypto::encrypt_convergent(b"backup2", &secret, "a.bin", Some(4096)).unwrap();
+    let rekeyed = crypto::encrypt_convergent(b"backup", &[8u8; 32], "a.bin", Some(4096)).unwrap();
+    assert_eq!(first, again);
+    assert_ne!(first, other);
+    assert_ne!(first, rekeyed);
+
+    let (plain, _) = crypto::decrypt_with_header(&first, Some(&secret)).unwrap();
+    assert_eq!(plain, b"backup");
+    assert!(crypto::decrypt_with_header(&first, Some(&[8u8; 32])).is_err());
+}
+
+#[test]
+fn cli_convergent_output_is_deduplicable() {
+    let dir = tempdir().unwrap();
+    let secret = "CQkJCQkJCQkJCQkJCQkJCQkJCQkJCQkJCQkJCQkJCQk=";
+    fs::write(dir.path().join("dump.sql"), b"same rows\n").unwrap();
+    let encrypt = |output: &str| {
+        cli()
+            .args(["--json", "encrypt", "--file", "dump.sql", "--key", secret])
+            .args(["--convergent", "-o", output])
+            .current_dir(dir.path())
+            .output()
+            .unwrap()
+    };
+
+    let out = encrypt("one.xd");
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["mode"], "convergent");
+    assert!(encrypt("two.xd").status.success());
+    assert_eq!(
+        fs::read(dir.path().join("one.xd")).unwrap(),
+        fs::read(dir.path().join("two.xd")).unwrap()
+    );
+
+    let out = cli()
+        .args(["--json", "inspect", "one.xd"])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    let inspected: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(inspected["mode"], "convergent");
+    assert_eq!(inspected["timestamp"], 0);
+}
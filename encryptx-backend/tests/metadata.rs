@@ -0,0 +1,41 @@
+mod common;
+
+use common::run_in;
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn no_metadata_files_record_no_name_or_time() {
+    let dir = tempdir().unwrap();
+    let key = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+    fs::write(dir.path().join("secret-plans.txt"), b"anonymous\n").unwrap();
+    let run = |args: &[&str]| run_in(dir.path(), args);
+
+    for extra in [&[][..], &["--chunk-size", "64KiB"]] {
+        let mut args = vec!["encrypt", "--file", "secret-plans.txt", "--key", key];
+        args.extend(["--no-metadata", "-o", "plans.xd", "--force"]);
+        args.extend(extra);
+        let out = run(&args);
+        assert!(out.status.success(), "{out:?}");
+        let encrypted = fs::read(dir.path().join("plans.xd")).unwrap();
+        let text = String::from_utf8_lossy(&encrypted);
+        assert!(!text.contains("secret-plans") && !text.contains("timestamp"));
+
+        let out = run(&["--json", "inspect", "plans.xd"]);
+        let inspected: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+        assert_eq!(inspected["filename"], "");
+        assert_eq!(inspected["timestamp"], 0);
+
+        // There is no name to write the output to
+        let out = run(&["decrypt", "--file", "plans.xd", "--key", key]);
+        assert_eq!(out.status.code(), Some(2));
+        let out = run(&[
+            "decrypt", "--file", "plans.xd", "--key", key, "-o", "out.txt", "--force",
+        ]);
+        assert!(out.status.success(), "{out:?}");
+        assert_eq!(
+            fs::read(dir.path().join("out.txt")).unwrap(),
+            b"anonymous\n"
+        );
+    }
+}
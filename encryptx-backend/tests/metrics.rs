@@ -0,0 +1,60 @@
+use encryptx_backend::crypto::{self, Argon2Params, KdfProfile};
+use encryptx_backend::metrics::{self, AdviceStatus, KdfMetrics, ParamSet};
+use std::time::Duration;
+
+#[test]
+fn advisor_suggests_lighter_parameters_when_p95_is_over_target() {
+    let recorder = KdfMetrics::new();
+    let moderate = KdfProfile::Moderate.params();
+    let paranoid = KdfProfile::Paranoid.params();
+    for i in 0..40 {
+        recorder.record(&moderate, Duration::from_millis(900 + i));
+        recorder.record(&paranoid, Duration::from_millis(100));
+    }
+    let advice = recorder.advise(Duration::from_millis(500));
+    assert_eq!(advice.len(), 2);
+
+    let slow = advice
+        .iter()
+        .find(|a| a.params == ParamSet::from(&moderate))
+        .unwrap();
+    assert_eq!(slow.status, AdviceStatus::OverTarget);
+    // Passes go first: 3 -> 1 halves the work without touching memory
+    let suggested = slow.suggested.unwrap();
+    assert_eq!(suggested.memory_cost, moderate.memory_cost);
+    assert_eq!(suggested.time_cost, 1);
+    assert_eq!(slow.suggested_profile, Some("interactive"));
+
+    let fast = advice
+        .iter()
+        .find(|a| a.params == ParamSet::from(&paranoid))
+        .unwrap();
+    assert_eq!(fast.status, AdviceStatus::Ok);
+    assert!(fast.suggested.is_none());
+
+    let text = recorder.render();
+    assert!(text.contains("# TYPE encryptx_kdf_duration_seconds histogram"));
+    assert!(text.contains(
+        "encryptx_kdf_duration_seconds_bucket{memory_cost=\"65536\",time_cost=\"3\",parallelism=\"1\",le=\"1\"} 40"
+    ));
+    assert!(text.contains(
+        "encryptx_kdf_duration_seconds_count{memory_cost=\"262144\",time_cost=\"4\",parallelism=\"1\"} 40"
+    ));
+}
+
+#[test]
+fn key_derivations_are_recorded() {
+    let params = Argon2Params {
+        memory_cost: 1024,
+        time_cost: 1,
+        parallelism: 2,
+    };
+    crypto::derive_key_with_params("pw", &[7u8; 32], &params).unwrap();
+    let advice = metrics::kdf().advise(Duration::from_millis(500));
+    let recorded = advice
+        .iter()
+        .find(|a| a.params == ParamSet::from(&params))
+        .unwrap();
+    assert_eq!(recorded.status, AdviceStatus::InsufficientSamples);
+    assert_eq!(recorded.total, 1);
+}
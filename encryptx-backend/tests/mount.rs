@@ -0,0 +1,58 @@
+#![cfg(all(unix, feature = "fuse"))]
+
+mod common;
+
+use common::{KEY, cli};
+use std::fs;
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::Duration;
+use tempfile::tempdir;
+
+#[test]
+fn mounted_archive_reads_back_its_files() {
+    let dir = tempdir().unwrap();
+    let src = dir.path().join("src");
+    let mnt = dir.path().join("mnt");
+    let archive = dir.path().join("src.xd");
+    fs::create_dir_all(src.join("docs")).unwrap();
+    fs::create_dir_all(&mnt).unwrap();
+    fs::write(src.join("docs/plan.txt"), b"step one").unwrap();
+    let status = cli()
+        .args(["encrypt", "--tar-from", src.to_str().unwrap(), "--key", KEY])
+        .args(["--output", archive.to_str().unwrap()])
+        .output()
+        .unwrap()
+        .status;
+    assert!(status.success());
+
+    let mut child = cli()
+        .args(["mount", archive.to_str().unwrap(), mnt.to_str().unwrap()])
+        .args(["--key", KEY])
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let plan = mnt.join("docs/plan.txt");
+    for _ in 0..100 {
+        if plan.exists() || child.try_wait().unwrap().is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(100));
+    }
+    if let Some(status) = child.try_wait().unwrap() {
+        // No FUSE device, or no permission to mount here
+        let output = child.wait_with_output().unwrap();
+        eprintln!("mount unavailable ({status}): {output:?}");
+        return;
+    }
+
+    assert_eq!(fs::read(&plan).unwrap(), b"step one");
+    assert!(fs::write(mnt.join("new.txt"), b"x").is_err());
+    Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(child.wait().unwrap().success());
+    assert!(!plan.exists());
+}
@@ -0,0 +1,139 @@
+#![cfg(feature = "openpgp")]
+
+mod common;
+
+use common::{cli, run};
+use encryptx_backend::interop::openpgp;
+use pgp::composed::{
+    ArmorOptions, EncryptionCaps, KeyType, SecretKeyParamsBuilder, SignedPublicKey,
+    SubkeyParamsBuilder,
+};
+use pgp::crypto::ecc_curve::ECCCurve;
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn password_message_round_trips_through_the_cli() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("minutes.txt");
+    fs::write(&input, b"quarterly numbers").unwrap();
+    let message = dir.path().join("minutes.asc");
+    let message = message.to_str().unwrap();
+    let out = run(&[
+        "encrypt",
+        "--file",
+        input.to_str().unwrap(),
+        "--format",
+        "pgp",
+        "--armor",
+        "--password",
+        "hunter2",
+        "--output",
+        message,
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    let data = fs::read(message).unwrap();
+    assert!(data.starts_with(b"-----BEGIN PGP MESSAGE-----"));
+    assert!(openpgp::detect(&data));
+
+    let (plaintext, name) = openpgp::decrypt(&data, Some("hunter2"), None).unwrap();
+    assert_eq!(plaintext, b"quarterly numbers");
+    assert_eq!(name, "");
+
+    let restored = dir.path().join("restored.txt");
+    let out = run(&[
+        "decrypt",
+        "--file",
+        message,
+        "--password",
+        "wrong",
+        "--output",
+        restored.to_str().unwrap(),
+    ]);
+    assert_eq!(out.status.code(), Some(4));
+    let out = run(&[
+        "decrypt",
+        "--file",
+        message,
+        "--password",
+        "hunter2",
+        "--output",
+        restored.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(fs::read(&restored).unwrap(), b"quarterly numbers");
+}
+
+#[test]
+fn message_to_public_key_opens_with_secret_key() {
+    let mut encryption = SubkeyParamsBuilder::default();
+    encryption
+        .key_type(KeyType::ECDH(ECCCurve::Curve25519Legacy))
+        .can_encrypt(EncryptionCaps::All);
+    let mut params = SecretKeyParamsBuilder::default();
+    params
+        .key_type(KeyType::Ed25519Legacy)
+        .can_certify(true)
+        .can_sign(true)
+        .primary_user_id("Alice <alice@example.com>".into())
+        .subkeys(vec![encryption.build().unwrap()]);
+    let secret_key = params
+        .build()
+        .unwrap()
+        .generate(rand::thread_rng())
+        .unwrap();
+    let public_key = SignedPublicKey::from(secret_key.clone());
+
+    let dir = tempdir().unwrap();
+    let public_path = dir.path().join("alice.pub.asc");
+    let secret_path = dir.path().join("alice.sec.asc");
+    fs::write(
+        &public_path,
+        public_key
+            .to_armored_string(ArmorOptions::default())
+            .unwrap(),
+    )
+    .unwrap();
+    fs::write(
+        &secret_path,
+        secret_key
+            .to_armored_string(ArmorOptions::default())
+            .unwrap(),
+    )
+    .unwrap();
+
+    let input = dir.path().join("plan.pdf");
+    fs::write(&input, b"%PDF-1.7 plan").unwrap();
+    let out = cli()
+        .current_dir(dir.path())
+        .args([
+            "encrypt",
+            "--file",
+            "plan.pdf",
+            "--format",
+            "pgp",
+            "--pgp-recipient",
+        ])
+        .arg(&public_path)
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{out:?}");
+    fs::remove_file(&input).unwrap();
+
+    // Without the secret key the message stays closed; like gpg, the output name drops .gpg
+    let message = dir.path().join("plan.pdf.gpg");
+    let message = message.to_str().unwrap();
+    assert!(
+        !run(&["decrypt", "--file", message, "--password", "guess"])
+            .status
+            .success()
+    );
+    let out = cli()
+        .current_dir(dir.path())
+        .args(["decrypt", "--file", message, "--pgp-key"])
+        .arg(&secret_path)
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(fs::read(&input).unwrap(), b"%PDF-1.7 plan");
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use common::{KEY, run_in};
+use encryptx_backend::crypto::{
+    CryptoError,
+    padding::{self, Padding},
+};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn padding_rounds_up_and_strips_exactly() {
+    assert_eq!(
+        [0, 1, 9, 100, 1000, 65_537].map(padding::padme),
+        [0, 1, 10, 104, 1024, 67_584]
+    );
+    assert_eq!(Padding::Bucket(4096).padded_len(4097), 8192);
+
+    let padded = padding::pad(b"\x01body", Padding::Bucket(64));
+    assert_eq!(padded.len(), 64);
+    assert_eq!(padding::unpad(&padded).unwrap(), b"\x01body");
+    // Anything but zeros after the body is rejected
+    let mut tampered = padded.clone();
+    tampered[63] = 1;
+    assert!(matches!(
+        padding::unpad(&tampered),
+        Err(CryptoError::FormatError)
+    ));
+    // Unpadded bodies pass through
+    assert_eq!(padding::strip(b"\x01plain".to_vec()).unwrap(), b"\x01plain");
+}
+
+#[test]
+fn cli_pad_hides_the_size_and_salvages_without_the_padding() {
+    let dir = tempdir().unwrap();
+    let run = |args: &[&str]| run_in(dir.path(), args);
+    fs::write(dir.path().join("short.txt"), b"short\n").unwrap();
+    fs::write(dir.path().join("large.txt"), vec![b'x'; 3000]).unwrap();
+    for name in ["short", "large"] {
+        let input = format!("{name}.txt");
+        let out = run(&["encrypt", "--file", &input, "--key", KEY, "--pad", "4KiB"]);
+        assert!(out.status.success(), "{out:?}");
+    }
+    let short = fs::read(dir.path().join("short.xd")).unwrap();
+    assert_eq!(
+        short.len(),
+        fs::read(dir.path().join("large.xd")).unwrap().len()
+    );
+    let out = run(&[
+        "decrypt", "--file", "short.xd", "--key", KEY, "-o", "out.txt",
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(fs::read(dir.path().join("out.txt")).unwrap(), b"short\n");
+    assert_eq!(
+        run(&[
+            "encrypt",
+            "--file",
+            "short.txt",
+            "--key",
+            KEY,
+            "--pad",
+            "0",
+            "--force"
+        ])
+        .status
+        .code(),
+        Some(2)
+    );
+
+    // Losing chunks that only hold padding loses nothing
+    let out = run(&[
+        "encrypt",
+        "--file",
+        "short.txt",
+        "--key",
+        KEY,
+        "--pad",
+        "16KiB",
+        "--chunk-size",
+        "1040",
+        "-o",
+        "chunked.xd",
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    let chunked = fs::read(dir.path().join("chunked.xd")).unwrap();
+    fs::write(dir.path().join("chunked.xd"), &chunked[..chunked.len() / 2]).unwrap();
+    let out = run(&[
+        "--json",
+        "salvage",
+        "chunked.xd",
+        "--key",
+        KEY,
+        "-o",
+        "salvaged.txt",
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["gaps"], serde_json::json!([]));
+    assert_eq!(
+        fs::read(dir.path().join("salvaged.txt")).unwrap(),
+        b"short\n"
+    );
+}
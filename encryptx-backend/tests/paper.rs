@@ -0,0 +1,114 @@
+mod common;
+
+use common::{KEY, cli};
+use encryptx_backend::crypto::{self, paper};
+use std::io::Write;
+use std::process::Stdio;
+
+/// The sheet with `edit` applied to each numbered line (1-based); `None` drops the line.
+fn damage(sheet: &str, edit: impl Fn(usize, &str) -> Option<String>) -> String {
+    sheet
+        .lines()
+        .filter_map(
+            |line| match line.get(..2).and_then(|n| n.parse::<usize>().ok()) {
+                Some(number) => edit(number, line),
+                None => Some(line.to_string()),
+            },
+        )
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[test]
+fn paper_keys_survive_typos_and_lost_lines() {
+    let key: Vec<u8> = (100..132).collect();
+    let sheet = paper::render(&key).unwrap();
+    assert!(sheet.starts_with(paper::PAPER_HEADER));
+    assert_eq!(
+        sheet
+            .lines()
+            .filter(|l| l.starts_with(char::is_numeric))
+            .count(),
+        paper::LINES
+    );
+
+    let recovered = paper::recover(&sheet).unwrap();
+    assert_eq!(recovered.key.as_slice(), key.as_slice());
+    assert!(recovered.repaired_lines.is_empty());
+
+    // Three lost lines, read as erasures
+    let lost = damage(&sheet, |n, line| {
+        (![2, 5, 10].contains(&n)).then(|| line.to_string())
+    });
+    let recovered = paper::recover(&lost).unwrap();
+    assert_eq!(recovered.key.as_slice(), key.as_slice());
+    assert_eq!(recovered.repaired_lines, [2, 5, 10]);
+
+    // Lower case and look-alikes read as printed. A typo in the first character of each of
+    // five lines is more than erasures can cover, so the typos are corrected as errors
+    let typed = damage(&sheet, |n, line| {
+        let (number, rest) = line.split_at(2);
+        let line = format!(
+            "{number}{}",
+            rest.to_lowercase().replace('0', "o").replace('1', "l")
+        );
+        Some(match n {
+            1 | 3 | 4 | 6 | 8 => {
+                let (head, tail) = line.split_at(4);
+                let typo = if tail.starts_with('z') { "y" } else { "z" };
+                format!("{head}{typo}{}", &tail[1..])
+            }
+            _ => line,
+        })
+    });
+    let recovered = paper::recover(&typed).unwrap();
+    assert_eq!(recovered.key.as_slice(), key.as_slice());
+    assert_eq!(recovered.repaired_lines, [1, 3, 4, 6, 8]);
+
+    // Four lost lines are more than the parity covers
+    let gone = damage(&sheet, |n, line| (n > 4).then(|| line.to_string()));
+    assert!(paper::recover(&gone).is_err());
+
+    // A fingerprint that doesn't match the corrected key is reported
+    let other = paper::render(&[9u8; 32]).unwrap();
+    let fingerprint = other.lines().nth(1).unwrap();
+    let mixed = sheet.replace(sheet.lines().nth(1).unwrap(), fingerprint);
+    assert!(matches!(
+        paper::recover(&mixed),
+        Err(crypto::CryptoError::AuthenticationError)
+    ));
+}
+
+#[test]
+fn key_paper_round_trips_through_the_cli() {
+    let run = |args: &[&str], stdin: &str| {
+        let mut child = cli()
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(stdin.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    };
+
+    let out = run(&["key", "paper", "--key", KEY], "");
+    assert!(out.status.success(), "{out:?}");
+    let sheet = String::from_utf8(out.stdout).unwrap();
+    let smudged = damage(&sheet, |n, line| (n != 7).then(|| line.to_string()));
+
+    let out = run(&["--json", "key", "recover-paper"], &smudged);
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["key"], KEY);
+    assert_eq!(report["repaired_lines"], serde_json::json!([7]));
+
+    let out = run(&["key", "recover-paper"], "01  ABCD EFGH  00\n");
+    assert_eq!(out.status.code(), Some(2));
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use common::run_in;
+use encryptx_backend::crypto::strength;
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn weak_passwords_warn_or_are_refused() {
+    let weak = strength::estimate("123456", &[]);
+    assert!(weak.is_weak() && weak.warning.is_some());
+    assert!(!strength::estimate("vT7#qLp2!xZr9@mW", &[]).is_weak());
+    // Passwords built from the file name are guessed first
+    assert!(
+        strength::estimate("zanzibarquill", &["zanzibarquill"]).score
+            < strength::estimate("zanzibarquill", &[]).score
+    );
+
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("notes.txt"), b"weak\n").unwrap();
+    let run = |args: &[&str]| run_in(dir.path(), args);
+
+    let out = run(&["encrypt", "--file", "notes.txt", "--password", "123456"]);
+    assert!(out.status.success(), "{out:?}");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Weak password"));
+
+    let out = run(&[
+        "rekey",
+        "notes.xd",
+        "--old-password",
+        "123456",
+        "--new-password",
+        "password1",
+        "--enforce-strong-password",
+    ]);
+    assert_eq!(out.status.code(), Some(2));
+    let out = run(&[
+        "encrypt",
+        "--file",
+        "notes.txt",
+        "--password",
+        "vT7#qLp2!xZr9@mW",
+        "--enforce-strong-password",
+        "--force",
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    assert!(!String::from_utf8_lossy(&out.stderr).contains("Weak password"));
+}
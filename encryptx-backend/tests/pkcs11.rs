@@ -0,0 +1,101 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::cli;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use tempfile::tempdir;
+
+/// Stand-in for `pkcs11-tool`: a device holding key 01 with PIN 1234, whose "RSA-OAEP" just
+/// prefixes the input (decryption needs the PIN, encryption with the public key doesn't).
+const FAKE_TOOL: &str = r#"#!/bin/sh
+op=; id=
+while [ $# -gt 0 ]; do
+    case "$1" in
+        --encrypt) op=enc ;;
+        --decrypt) op=dec ;;
+        --id) id=$2; shift ;;
+    esac
+    shift
+done
+[ "$id" = 01 ] || { echo "object $id not found" >&2; exit 1; }
+case $op in
+    enc) printf 'HSM:'; cat ;;
+    dec) [ "$ENCRYPTX_PKCS11_PIN" = 1234 ] || { echo CKR_PIN_INCORRECT >&2; exit 1; }
+         tail -c +5 ;;
+esac
+"#;
+
+#[test]
+fn pkcs11_slots_unwrap_on_the_device() {
+    let dir = tempdir().unwrap();
+    let tool = dir.path().join("pkcs11-tool");
+    fs::write(&tool, FAKE_TOOL).unwrap();
+    fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        dir.path().join("ledger.csv"),
+        b"date,amount\n2024-01-01,42\n",
+    )
+    .unwrap();
+    let run = |pin: &str, args: &[&str]| {
+        cli()
+            .args(args)
+            .args(["--pkcs11-module", "/usr/lib/fake-pkcs11.so"])
+            .args(["--pin-source", "env:HSM_PIN"])
+            .current_dir(dir.path())
+            .env("ENCRYPTX_PKCS11_TOOL", &tool)
+            .env("HSM_PIN", pin)
+            .output()
+            .unwrap()
+    };
+
+    let encrypt = |extra: &[&str]| {
+        let mut args = vec![
+            "--json",
+            "encrypt",
+            "--file",
+            "ledger.csv",
+            "-o",
+            "ledger.xd",
+            "--force",
+        ];
+        args.extend_from_slice(extra);
+        run("1234", &args)
+    };
+    // The device key must be named, and must exist
+    assert_eq!(encrypt(&[]).status.code(), Some(2));
+    assert_eq!(encrypt(&["--pkcs11-key-id", "02"]).status.code(), Some(1));
+    let out = encrypt(&["--pkcs11-key-id", "01"]);
+    assert!(out.status.success(), "{out:?}");
+
+    let decrypt = |pin: &str| {
+        run(
+            pin,
+            &[
+                "--json",
+                "decrypt",
+                "--file",
+                "ledger.xd",
+                "--output",
+                "out.csv",
+                "--force",
+            ],
+        )
+    };
+    let out = decrypt("1234");
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["mode"], "pkcs11");
+    assert_eq!(
+        fs::read(dir.path().join("out.csv")).unwrap(),
+        b"date,amount\n2024-01-01,42\n"
+    );
+
+    let out = decrypt("0000");
+    assert_eq!(out.status.code(), Some(4));
+    assert!(
+        String::from_utf8_lossy(&out.stderr).contains("CKR_PIN_INCORRECT"),
+        "{out:?}"
+    );
+}
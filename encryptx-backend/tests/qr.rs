@@ -0,0 +1,69 @@
+mod common;
+
+use common::run_in;
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+// The QR version asserted below depends on the exact text
+const KEY: &str = "CgoKCgoKCgoKCgoKCgoKCgoKCgoKCgoKCgoKCgoKCgo=";
+
+fn png_size(path: &Path) -> (u32, u32) {
+    let reader = png::Decoder::new(std::io::Cursor::new(fs::read(path).unwrap()))
+        .read_info()
+        .unwrap();
+    let info = reader.info();
+    (info.width, info.height)
+}
+
+#[test]
+fn keys_and_small_files_render_as_qr_codes() {
+    let dir = tempdir().unwrap();
+
+    let out = run_in(dir.path(), &["key", "qr", "--key", KEY]);
+    assert!(out.status.success(), "{out:?}");
+    let drawing = String::from_utf8(out.stdout).unwrap();
+    assert!(drawing.contains('▀') || drawing.contains('▄'));
+
+    let out = run_in(
+        dir.path(),
+        &["--json", "key", "qr", "--key", KEY, "--output", "key.png"],
+    );
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["operation"], "key-qr");
+    // A 44-character key fits version 4 (33 modules), plus the quiet zone, at 8 px a module
+    assert_eq!(report["modules"], 33);
+    assert_eq!(png_size(&dir.path().join("key.png")), (328, 328));
+    let out = run_in(dir.path(), &["key", "qr", "--key", KEY, "-o", "key.png"]);
+    assert_eq!(out.status.code(), Some(2));
+
+    fs::write(dir.path().join("pin.txt"), "1234").unwrap();
+    let out = run_in(
+        dir.path(),
+        &[
+            "encrypt", "--file", "pin.txt", "--key", KEY, "--qr", "pin.png",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    assert!(dir.path().join("pin.xd").is_file());
+    let (width, height) = png_size(&dir.path().join("pin.png"));
+    assert_eq!(width, height);
+
+    // Too big for a QR code: refused before the encrypted file is written
+    fs::write(dir.path().join("big.bin"), vec![7u8; 4096]).unwrap();
+    let out = run_in(
+        dir.path(),
+        &[
+            "encrypt",
+            "--file",
+            "big.bin",
+            "--key",
+            KEY,
+            "--no-compress",
+            "--qr",
+        ],
+    );
+    assert_eq!(out.status.code(), Some(2));
+    assert!(!dir.path().join("big.xd").exists());
+}
@@ -0,0 +1,204 @@
+mod common;
+
+use common::cli;
+use encryptx_backend::crypto::{
+    self, Argon2Params,
+    identity::{Identity, Recipient},
+    keyslot,
+};
+use std::fs;
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn every_recipient_can_decrypt() {
+    let alice = Identity::generate();
+    let bob = Identity::generate();
+    let data_key = keyslot::generate_data_key();
+    let mut slots = Vec::new();
+    for identity in [&alice, &bob] {
+        let recipient: Recipient = identity.recipient().to_string().parse().unwrap();
+        slots.push(
+            keyslot::create(
+                &data_key,
+                keyslot::Credential::Recipient(&recipient),
+                Argon2Params::default(),
+                None,
+            )
+            .await
+            .unwrap(),
+        );
+    }
+    let encrypted =
+        crypto::encrypt_with_keyslots(b"team secret", &data_key, "t.txt", slots, None).unwrap();
+
+    for identity in [&alice, &bob] {
+        let reparsed = Identity::parse_file(&identity.to_file()).unwrap();
+        let (plain, name) = crypto::decrypt_with_identity(&encrypted, &reparsed).unwrap();
+        assert_eq!(plain, b"team secret");
+        assert_eq!(name, "t.txt");
+    }
+    assert!(matches!(
+        crypto::decrypt_with_identity(&encrypted, &Identity::generate()),
+        Err(crypto::CryptoError::AuthenticationError)
+    ));
+}
+
+#[test]
+fn cli_encrypts_to_recipients() {
+    let dir = tempdir().unwrap();
+    let mut recipients = Vec::new();
+    for name in ["alice.key", "bob.key"] {
+        let out = cli()
+            .args(["--json", "identity", "generate", "--output"])
+            .arg(dir.path().join(name))
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+        recipients.push(report["recipient"].as_str().unwrap().to_string());
+    }
+    // Recipients may also come from a file
+    let team = dir.path().join("team.txt");
+    fs::write(&team, format!("# bob\n{}\n", recipients[1])).unwrap();
+
+    let input = dir.path().join("plan.txt");
+    fs::write(&input, b"launch at dawn").unwrap();
+    let encrypted = dir.path().join("plan.xd");
+    let status = cli()
+        .args(["encrypt", "--file", input.to_str().unwrap()])
+        .args(["--output", encrypted.to_str().unwrap()])
+        .args(["--recipient", &recipients[0]])
+        .args(["--recipient", team.to_str().unwrap()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let decrypt = |identity: &str| {
+        cli()
+            .args(["decrypt", "--file", encrypted.to_str().unwrap()])
+            .args(["--output", "-"])
+            .arg("--identity")
+            .arg(dir.path().join(identity))
+            .output()
+            .unwrap()
+    };
+    for identity in ["alice.key", "bob.key"] {
+        let out = decrypt(identity);
+        assert!(out.status.success());
+        assert_eq!(out.stdout, b"launch at dawn");
+    }
+
+    cli()
+        .args(["identity", "generate", "--output"])
+        .arg(dir.path().join("eve.key"))
+        .output()
+        .unwrap();
+    assert_eq!(decrypt("eve.key").status.code(), Some(4));
+}
+
+#[tokio::test]
+async fn hybrid_recipient_records_its_kem_suite() {
+    let pq = Identity::generate_hybrid();
+    let recipient: Recipient = pq.recipient().to_string().parse().unwrap();
+    assert!(recipient.is_hybrid());
+    let data_key = keyslot::generate_data_key();
+    let slot = keyslot::create(
+        &data_key,
+        keyslot::Credential::Recipient(&recipient),
+        Argon2Params::default(),
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(slot.kind, keyslot::KIND_KEM);
+    assert_eq!(slot.suite.as_deref(), Some(keyslot::SUITE_X25519_MLKEM768));
+    let encrypted =
+        crypto::encrypt_with_keyslots(b"long-lived", &data_key, "q.txt", vec![slot.clone()], None)
+            .unwrap();
+
+    let reparsed = Identity::parse_file(&pq.to_file()).unwrap();
+    let (plain, _) = crypto::decrypt_with_identity(&encrypted, &reparsed).unwrap();
+    assert_eq!(plain, b"long-lived");
+    assert!(matches!(
+        crypto::decrypt_with_identity(&encrypted, &Identity::generate_hybrid()),
+        Err(crypto::CryptoError::AuthenticationError)
+    ));
+
+    // A suite from a newer version is reported rather than treated as a wrong identity
+    let mut future = slot;
+    future.suite = Some("x448-mlkem1024".to_string());
+    let encrypted =
+        crypto::encrypt_with_keyslots(b"long-lived", &data_key, "q.txt", vec![future], None)
+            .unwrap();
+    assert!(matches!(
+        crypto::decrypt_with_identity(&encrypted, &reparsed),
+        Err(crypto::CryptoError::DecryptionError(_))
+    ));
+}
+
+#[test]
+fn identities_file_is_scanned_from_the_default_location() {
+    let dir = tempdir().unwrap();
+    let run = |args: &[&str]| {
+        cli()
+            .args(args)
+            .current_dir(dir.path())
+            .env("XDG_CONFIG_HOME", dir.path().join("xdg"))
+            .output()
+            .unwrap()
+    };
+    let (work, home) = (Identity::generate(), Identity::generate_hybrid());
+    fs::write(dir.path().join("notes.txt"), b"for home\n").unwrap();
+    let home_recipient = home.recipient().to_string();
+    let out = run(&[
+        "encrypt",
+        "--file",
+        "notes.txt",
+        "--recipient",
+        &home_recipient,
+    ]);
+    assert!(out.status.success(), "{out:?}");
+
+    let decrypt = [
+        "--json", "decrypt", "--file", "notes.xd", "-o", "out.txt", "--force",
+    ];
+    // No identities file yet: nothing to decrypt with
+    assert_eq!(run(&decrypt).status.code(), Some(2));
+
+    // Several identities in one file; the second one matches
+    let identities = format!("{}\n{}", work.to_file(), home.to_file());
+    fs::create_dir_all(dir.path().join("xdg/encryptx")).unwrap();
+    fs::write(dir.path().join("xdg/encryptx/identities.txt"), &identities).unwrap();
+    let out = run(&decrypt);
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["mode"], "recipients");
+    assert_eq!(fs::read(dir.path().join("out.txt")).unwrap(), b"for home\n");
+
+    // The same file also works as --identity
+    fs::write(dir.path().join("ids.txt"), &identities).unwrap();
+    fs::remove_file(dir.path().join("xdg/encryptx/identities.txt")).unwrap();
+    let out = run(&[
+        "decrypt",
+        "--file",
+        "notes.xd",
+        "--identity",
+        "ids.txt",
+        "-o",
+        "out.txt",
+        "--force",
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    fs::write(dir.path().join("ids.txt"), work.to_file()).unwrap();
+    let out = run(&[
+        "decrypt",
+        "--file",
+        "notes.xd",
+        "--identity",
+        "ids.txt",
+        "-o",
+        "out.txt",
+        "--force",
+    ]);
+    assert_eq!(out.status.code(), Some(4));
+}
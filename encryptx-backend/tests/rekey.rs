@@ -0,0 +1,164 @@
+mod common;
+
+use base64::Engine;
+use common::{cli, run_in};
+use encryptx_backend::api;
+use encryptx_backend::format::{ParsedHeader, XdReader};
+use std::fs;
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn rekey_replaces_password_in_place() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("secret.xd");
+    let encrypted = api::encrypt_file(b"rotate me", Some("old"), None, "secret.txt")
+        .await
+        .unwrap()
+        .ciphertext;
+    fs::write(&path, &encrypted).unwrap();
+
+    let status = cli()
+        .args(["rekey", path.to_str().unwrap()])
+        .args(["--old-password", "old", "--new-password", "new"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let rekeyed = fs::read(&path).unwrap();
+    assert!(
+        api::decrypt_file_bytes(&rekeyed, Some("old"), None)
+            .await
+            .is_err()
+    );
+    let (plain, metadata) = api::decrypt_file_bytes(&rekeyed, Some("new"), None)
+        .await
+        .unwrap();
+    assert_eq!(plain, b"rotate me");
+    assert_eq!(metadata.filename, "secret.txt");
+
+    // No temporary files are left behind
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn rekey_rewraps_envelope_files_without_touching_the_body() {
+    let dir = tempdir().unwrap();
+    let old_key = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+    let new_key = "AgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgI=";
+    fs::write(dir.path().join("report.txt"), vec![b'x'; 10_000]).unwrap();
+    let run = |args: &[&str]| run_in(dir.path(), args);
+
+    let out = run(&[
+        "encrypt",
+        "--file",
+        "report.txt",
+        "--key",
+        old_key,
+        "--chunk-size",
+        "4096",
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    let before = fs::read(dir.path().join("report.xd")).unwrap();
+    match XdReader::parse(&before).unwrap().header {
+        ParsedHeader::Key(h) => assert!(h.key.is_none() && h.is_envelope()),
+        ParsedHeader::Password(_) => unreachable!(),
+    }
+
+    let out = run(&[
+        "rekey",
+        "report.xd",
+        "--old-key",
+        old_key,
+        "--new-key",
+        new_key,
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    let after = fs::read(dir.path().join("report.xd")).unwrap();
+    let (before, after) = (
+        XdReader::parse(&before).unwrap(),
+        XdReader::parse(&after).unwrap(),
+    );
+    assert_ne!(before.header_json, after.header_json);
+    assert_eq!(
+        (before.nonce, before.ciphertext),
+        (after.nonce, after.ciphertext)
+    );
+
+    let decrypt = |key: &str| {
+        run(&[
+            "decrypt",
+            "--file",
+            "report.xd",
+            "--key",
+            key,
+            "-o",
+            "out.txt",
+            "--force",
+        ])
+    };
+    assert_eq!(decrypt(old_key).status.code(), Some(4));
+    assert!(decrypt(new_key).status.success());
+    assert_eq!(
+        fs::read(dir.path().join("out.txt")).unwrap(),
+        vec![b'x'; 10_000]
+    );
+}
+
+#[tokio::test]
+async fn rekey_streams_chunked_files_and_keeps_their_layout() {
+    let dir = tempdir().unwrap();
+    let new_key = [3u8; 32];
+    let mut content = Vec::new();
+    for i in 0..20_000u32 {
+        content.extend_from_slice(&i.to_le_bytes());
+    }
+    let encrypted = api::encrypt_file_chunked(&content, Some("old"), None, "big.bin", 4096)
+        .await
+        .unwrap()
+        .ciphertext;
+    fs::write(dir.path().join("big.xd"), &encrypted).unwrap();
+    let rekey = |args: &[&str]| {
+        cli()
+            .arg("rekey")
+            .args(args)
+            .current_dir(dir.path())
+            .output()
+            .unwrap()
+    };
+
+    let out = rekey(&["big.xd", "--old-password", "wrong", "--new-password", "new"]);
+    assert_eq!(out.status.code(), Some(4), "{out:?}");
+    assert_eq!(fs::read(dir.path().join("big.xd")).unwrap(), encrypted);
+
+    let out = rekey(&["big.xd", "--old-password", "old", "--new-password", "new"]);
+    assert!(out.status.success(), "{out:?}");
+    let rekeyed = fs::read(dir.path().join("big.xd")).unwrap();
+    let (plain, metadata) = api::decrypt_file_bytes(&rekeyed, Some("new"), None)
+        .await
+        .unwrap();
+    assert_eq!(plain, content);
+    assert_eq!(metadata.chunk_size, Some(4096));
+
+    // To a key, the chunked file becomes a chunked envelope
+    let key = base64::engine::general_purpose::STANDARD.encode(new_key);
+    let out = rekey(&[
+        "big.xd",
+        "--old-password",
+        "new",
+        "--new-key",
+        &key,
+        "-o",
+        "keyed.xd",
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    let keyed = fs::read(dir.path().join("keyed.xd")).unwrap();
+    match XdReader::parse(&keyed).unwrap().header {
+        ParsedHeader::Key(h) => assert!(h.key.is_none() && h.is_envelope()),
+        ParsedHeader::Password(_) => unreachable!(),
+    }
+    let (plain, metadata) = api::decrypt_file_bytes(&keyed, None, Some(&new_key))
+        .await
+        .unwrap();
+    assert_eq!(plain, content);
+    assert_eq!(metadata.chunk_size, Some(4096));
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use common::{KEY, OTHER_KEY, cli};
+use std::fs;
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::process::{Output, Stdio};
+use std::thread::sleep;
+use std::time::Duration;
+use tempfile::tempdir;
+
+fn run(dir: &Path, remote: &str, args: &[&str]) -> Output {
+    cli()
+        .args(["--remote", remote])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn encrypts_and_decrypts_on_a_server() {
+    let dir = tempdir().unwrap();
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let config = dir.path().join("server.toml");
+    fs::write(
+        &config,
+        format!("[server]\nhost = \"127.0.0.1\"\nport = {port}\n"),
+    )
+    .unwrap();
+    let mut server = cli()
+        .args(["--config", config.to_str().unwrap()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    for _ in 0..100 {
+        if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            break;
+        }
+        sleep(Duration::from_millis(100));
+    }
+    let remote = format!("http://127.0.0.1:{port}");
+    fs::write(dir.path().join("notes.txt"), b"kept on the server\n").unwrap();
+
+    let encrypt = run(
+        dir.path(),
+        &remote,
+        &["encrypt", "--file", "notes.txt", "--key", KEY],
+    );
+    fs::rename(dir.path().join("notes.txt"), dir.path().join("orig.txt")).unwrap();
+    // The file names itself, as when decrypted locally
+    let decrypt = run(
+        dir.path(),
+        &remote,
+        &["decrypt", "--file", "notes.xd", "--key", KEY],
+    );
+    let wrong = run(
+        dir.path(),
+        &remote,
+        &[
+            "decrypt", "--file", "notes.xd", "--key", OTHER_KEY, "-o", "-",
+        ],
+    );
+    let refused = run(
+        dir.path(),
+        &remote,
+        &["encrypt", "--file", "orig.txt", "--key", KEY, "--verify"],
+    );
+    server.kill().unwrap();
+    server.wait().unwrap();
+
+    assert!(encrypt.status.success(), "{encrypt:?}");
+    assert!(decrypt.status.success(), "{decrypt:?}");
+    assert_eq!(
+        fs::read(dir.path().join("notes.txt")).unwrap(),
+        b"kept on the server\n"
+    );
+    // Locally, the same file opens with the key too
+    let local = cli()
+        .args(["decrypt", "--file", "notes.xd", "--key", KEY, "-o", "-"])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert_eq!(local.stdout, b"kept on the server\n");
+    assert_eq!(wrong.status.code(), Some(4));
+    assert_eq!(refused.status.code(), Some(2));
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use common::cli;
+
+/// Replays the regression corpus: malformed inputs must be rejected, never panic or hang.
+#[test]
+fn corpus_replays_without_crashes() {
+    let corpus = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/corpus");
+    let out = cli()
+        .args(["--json", "replay-corpus", corpus])
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{out:?}");
+
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    let results = report["results"].as_array().unwrap();
+    assert_eq!(report["inputs"], results.len());
+    assert!(results.iter().any(|r| r["file"] == "empty"));
+    // Sorted by path, so runs are comparable
+    let files: Vec<&str> = results
+        .iter()
+        .map(|r| r["file"].as_str().unwrap())
+        .collect();
+    assert!(files.is_sorted());
+
+    // A header that parses still fails to authenticate under the replay's fixed key
+    let huge_kdf = results
+        .iter()
+        .find(|r| r["file"] == "password-huge-kdf.xd")
+        .unwrap();
+    let outcome = |target: &str| {
+        huge_kdf["targets"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|t| t["target"] == target)
+            .unwrap()["outcome"]
+            .clone()
+    };
+    assert_eq!(outcome("xd"), "accepted");
+    assert_eq!(outcome("decrypt"), "rejected");
+
+    let out = cli()
+        .args(["replay-corpus", "no-such-corpus"])
+        .output()
+        .unwrap();
+    assert_eq!(out.status.code(), Some(2));
+}
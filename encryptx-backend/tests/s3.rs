@@ -0,0 +1,123 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{KEY, cli};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Output;
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::tempdir;
+
+/// Stand-in for the AWS CLI, keeping the objects of bucket B under `$FAKE_S3/B`. With
+/// `$FAKE_S3_NOISE` set, uploads print that many bytes on stderr before reading stdin.
+const FAKE_AWS: &str = r#"#!/bin/sh
+object() { echo "$FAKE_S3/${1#s3://}"; }
+case "$1 $2" in
+    "s3 cp")
+        if [ "$3" = - ]; then
+            [ -n "$FAKE_S3_NOISE" ] && head -c "$FAKE_S3_NOISE" /dev/zero | tr '\0' . >&2
+            mkdir -p "$(dirname "$(object "$4")")" && cat > "$(object "$4")"
+        elif [ -f "$(object "$3")" ]; then
+            cat "$(object "$3")"
+        else
+            echo "fatal error: An error occurred (404) when calling the HeadObject operation: Key \"$3\" does not exist" >&2; exit 1
+        fi ;;
+    "s3api head-object")
+        [ -f "$FAKE_S3/$4/$6" ] && echo '{}' && exit 0
+        echo "An error occurred (404) when calling the HeadObject operation: Not Found" >&2; exit 254 ;;
+    *) exit 2 ;;
+esac
+"#;
+
+fn run(dir: &Path, args: &[&str]) -> Output {
+    cli()
+        .args(args)
+        .current_dir(dir)
+        .env("ENCRYPTX_S3_TOOLS", dir.join("tools"))
+        .env("FAKE_S3", dir.join("s3"))
+        .output()
+        .unwrap()
+}
+
+/// Installs the fake `aws` in `dir/tools`.
+fn install_fake_aws(dir: &Path) {
+    let tools = dir.join("tools");
+    fs::create_dir(&tools).unwrap();
+    fs::write(tools.join("aws"), FAKE_AWS).unwrap();
+    fs::set_permissions(tools.join("aws"), fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn encrypts_to_and_decrypts_from_s3() {
+    let dir = tempdir().unwrap();
+    install_fake_aws(dir.path());
+    fs::write(dir.path().join("ledger.txt"), b"quarterly numbers\n").unwrap();
+
+    let url = "s3://vault/2024/ledger.xd";
+    let encrypt = ["encrypt", "--file", "ledger.txt", "--key", KEY, "-o", url];
+    assert!(run(dir.path(), &encrypt).status.success());
+    assert!(dir.path().join("s3/vault/2024/ledger.xd").is_file());
+    // An existing object is only replaced with --force
+    let again = run(dir.path(), &encrypt);
+    assert_eq!(again.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&again.stderr).contains("already exists"));
+
+    let decrypt = ["decrypt", "--file", url, "--key", KEY, "-o", "-"];
+    let output = run(dir.path(), &decrypt);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"quarterly numbers\n");
+
+    let missing = ["decrypt", "--file", "s3://vault/nope.xd", "--key", KEY];
+    assert_eq!(run(dir.path(), &missing).status.code(), Some(2));
+    let verify = ["encrypt", "--file", "ledger.txt", "--key", KEY, "-o", url];
+    let verify = [&verify[..], &["--force", "--verify"]].concat();
+    assert_eq!(run(dir.path(), &verify).status.code(), Some(2));
+}
+
+#[test]
+fn large_uploads_do_not_deadlock_on_a_chatty_aws() {
+    let dir = tempdir().unwrap();
+    install_fake_aws(dir.path());
+    // Both well past a pipe buffer, so neither side can finish its write first
+    let data: Vec<u8> = (0..1 << 20).map(|i| (i % 251) as u8).collect();
+    fs::write(dir.path().join("big.bin"), &data).unwrap();
+
+    let url = "s3://vault/big.xd";
+    let mut child = cli()
+        .args([
+            "encrypt",
+            "--file",
+            "big.bin",
+            "--key",
+            KEY,
+            "--no-compress",
+        ])
+        .args(["-o", url])
+        .current_dir(dir.path())
+        .env("ENCRYPTX_S3_TOOLS", dir.path().join("tools"))
+        .env("FAKE_S3", dir.path().join("s3"))
+        .env("FAKE_S3_NOISE", "262144")
+        .spawn()
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(60);
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if Instant::now() > deadline {
+            child.kill().unwrap();
+            panic!("encrypt to S3 hung while aws was writing to stderr");
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+    assert!(status.success());
+
+    // The object comes back through the spooled download
+    let decrypt = ["decrypt", "--file", url, "--key", KEY, "-o", "back.bin"];
+    let output = run(dir.path(), &decrypt);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(fs::read(dir.path().join("back.bin")).unwrap(), data);
+}
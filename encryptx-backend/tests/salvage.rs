@@ -0,0 +1,160 @@
+mod common;
+
+use common::{cli, run_in};
+use encryptx_backend::crypto;
+use encryptx_backend::format::XdReader;
+use std::fs;
+use tempfile::tempdir;
+
+fn sample(len: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 24) as u8
+        })
+        .collect()
+}
+
+fn salvage(path: &std::path::Path, out: &std::path::Path) -> serde_json::Value {
+    let output = cli()
+        .args(["--json", "salvage", path.to_str().unwrap()])
+        .args(["--key", "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE="])
+        .args(["--output", out.to_str().unwrap(), "--force"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn salvage_recovers_around_damage_and_truncation() {
+    let dir = tempdir().unwrap();
+    let original = sample(16 * 1024, 3);
+    let input = dir.path().join("data.bin");
+    fs::write(&input, &original).unwrap();
+    let encrypted = dir.path().join("data.xd");
+    let status = cli()
+        .args(["encrypt", "--file", input.to_str().unwrap()])
+        .args(["--output", encrypted.to_str().unwrap()])
+        .args(["--key", "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE="])
+        .args(["--chunk-size", "1040"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let intact = fs::read(&encrypted).unwrap();
+    let recovered = dir.path().join("recovered.bin");
+
+    // Bit rot in the middle: one chunk is lost, data before and after it comes back
+    let mut damaged = intact.clone();
+    let middle = damaged.len() / 2;
+    damaged[middle] ^= 0x40;
+    fs::write(&encrypted, &damaged).unwrap();
+    let report = salvage(&encrypted, &recovered);
+    assert_eq!(report["intact"], false);
+    let lost = report["lost"].as_array().unwrap();
+    assert_eq!(lost.len(), 1);
+    let (offset, length) = (
+        lost[0]["offset"].as_u64().unwrap() as usize,
+        lost[0]["length"].as_u64().unwrap() as usize,
+    );
+    assert!((offset..offset + length).contains(&middle));
+    let data = fs::read(&recovered).unwrap();
+    let gap = report["gaps"][0].as_u64().unwrap() as usize;
+    assert!(gap > 0 && gap < data.len());
+    assert_eq!(data[..gap], original[..gap]);
+    let tail = data.len() - gap;
+    assert_eq!(data[gap..], original[original.len() - tail..]);
+
+    // Truncated download: everything before the cut is recovered
+    fs::write(&encrypted, &intact[..intact.len() - 1500]).unwrap();
+    let report = salvage(&encrypted, &recovered);
+    assert_eq!(report["intact"], false);
+    let data = fs::read(&recovered).unwrap();
+    assert!(data.len() > original.len() / 2);
+    assert_eq!(data[..], original[..data.len()]);
+
+    fs::write(&encrypted, &intact).unwrap();
+    let report = salvage(&encrypted, &recovered);
+    assert_eq!(report["intact"], true);
+    assert_eq!(fs::read(&recovered).unwrap(), original);
+}
+
+#[tokio::test]
+async fn salvage_resyncs_delta_records_after_removed_bytes() {
+    let key = [2u8; 32];
+    let data = sample(4096 * 6, 5);
+    let first = crypto::encrypt_with_header_chunked(&data, &key, "f.bin", Some(4096)).unwrap();
+    let parsed = XdReader::parse(&first).unwrap();
+    let file_key = crypto::file_key(&parsed, None, Some(&key)).await.unwrap();
+    let (delta, _) = crypto::encrypt_delta(&data, "f.bin", &parsed, &file_key, None, None).unwrap();
+
+    // Drop some bytes from the third record; the later records are found again
+    let parts = crypto::chunked::part_boundaries(&delta).unwrap();
+    let mut damaged = delta.clone();
+    damaged.drain(parts[2].start + 100..parts[2].start + 150);
+    let parsed = XdReader::parse(&damaged).unwrap();
+    let (regions, complete) = crypto::salvage_regions(&parsed, &file_key).unwrap();
+
+    assert!(!complete);
+    let recovered: Vec<_> = regions
+        .iter()
+        .filter_map(|r| r.plaintext.as_ref())
+        .collect();
+    assert_eq!(recovered.len(), 5);
+    assert_eq!(recovered[2].as_slice(), &data[4096 * 3..4096 * 4]);
+    assert_eq!(regions.iter().filter(|r| r.plaintext.is_none()).count(), 2);
+}
+
+#[test]
+fn salvage_stops_at_the_decompression_limit() {
+    let dir = tempdir().unwrap();
+    let key = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+    // Zeros compress to almost nothing, so the output dwarfs the encrypted file
+    fs::write(dir.path().join("zeros.bin"), vec![0u8; 256 * 1024]).unwrap();
+    fs::write(
+        dir.path().join("small.toml"),
+        "max_decompressed_size = \"64KiB\"\n",
+    )
+    .unwrap();
+    let run = |args: &[&str]| run_in(dir.path(), args);
+    let out = run(&[
+        "encrypt",
+        "--file",
+        "zeros.bin",
+        "--key",
+        key,
+        "--chunk-size",
+        "4096",
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    assert!(fs::metadata(dir.path().join("zeros.xd")).unwrap().len() < 64 * 1024);
+
+    let salvage = |config: &[&str]| {
+        let args = [
+            config,
+            &[
+                "salvage",
+                "zeros.xd",
+                "--key",
+                key,
+                "-o",
+                "zeros.out",
+                "--force",
+            ],
+        ]
+        .concat();
+        run(&args)
+    };
+    let out = salvage(&["--config", "small.toml"]);
+    assert_eq!(out.status.code(), Some(5), "{out:?}");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("max_decompressed_size"));
+    assert!(!dir.path().join("zeros.out").exists());
+
+    let out = salvage(&[]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(
+        fs::read(dir.path().join("zeros.out")).unwrap(),
+        vec![0u8; 256 * 1024]
+    );
+}
@@ -0,0 +1,58 @@
+use encryptx_backend::api;
+use encryptx_backend::crypto::secret::SecretBuffer;
+use encryptx_backend::crypto::{Argon2Params, SecureKey, keyslot};
+use zeroize::{ZeroizeOnDrop, Zeroizing};
+
+/// Compiles only for values that wipe their memory when dropped.
+fn wiped_on_drop<T: ZeroizeOnDrop>(_: &T) {}
+
+#[test]
+fn secrets_live_in_locked_pages() {
+    let mut buffer = SecretBuffer::new(48);
+    assert_eq!(buffer.as_slice(), [0u8; 48]);
+    buffer[..3].copy_from_slice(b"abc");
+    assert_eq!(&buffer[..4], b"abc\0");
+    assert!(SecretBuffer::new(0).is_empty());
+    // The default RLIMIT_MEMLOCK always leaves room for a few pages
+    if cfg!(target_os = "linux") {
+        assert!(buffer.is_locked());
+    }
+
+    let key = SecureKey::new([7u8; 32]);
+    assert_eq!(key.as_slice(), [7u8; 32]);
+    assert_eq!(key.is_locked(), buffer.is_locked());
+    // Debug output never shows the contents
+    assert!(!format!("{buffer:?}").contains("97"));
+}
+
+#[tokio::test]
+async fn keys_passwords_and_plaintexts_are_held_in_zeroizing_buffers() {
+    let data_key = keyslot::generate_data_key();
+    wiped_on_drop(&data_key);
+    wiped_on_drop(&SecretBuffer::new(16));
+
+    // Passwords can only be handed over already wrapped
+    let password = Zeroizing::new("hunter2".to_string());
+    wiped_on_drop(&password);
+    let params = Argon2Params {
+        memory_cost: 8 * 1024,
+        time_cost: 1,
+        parallelism: 1,
+    };
+    let credential = keyslot::Credential::Password(password);
+    let slot = keyslot::create(&data_key, credential, params, None)
+        .await
+        .unwrap();
+    let check = keyslot::key_check(&data_key);
+    let (unlocked, _) = keyslot::unlock_with_password(&[slot], &check, "hunter2".to_string())
+        .await
+        .unwrap();
+    wiped_on_drop(&unlocked);
+    assert_eq!(unlocked.as_slice(), data_key.as_slice());
+
+    let body = api::compress_body(b"plaintext", 3).unwrap();
+    wiped_on_drop(&body);
+    let plaintext = api::decode_body(body.to_vec()).unwrap();
+    wiped_on_drop(&plaintext);
+    assert_eq!(plaintext.as_slice(), b"plaintext");
+}
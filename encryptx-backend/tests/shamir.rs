@@ -0,0 +1,104 @@
+mod common;
+
+use common::{cli, run_in};
+use encryptx_backend::crypto::{self, shamir};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn any_threshold_subset_recovers_the_key() {
+    let key: Vec<u8> = (0..32).collect();
+    let shares = shamir::split(&key, 3, 5).unwrap();
+
+    for subset in [[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+        let picked: Vec<_> = subset.iter().map(|&i| shares[i].clone()).collect();
+        assert_eq!(shamir::combine(&picked).unwrap().as_slice(), key.as_slice());
+    }
+    assert!(shamir::combine(&shares[..2]).is_err());
+
+    // Round-trips through the text form; a damaged share is detected
+    let parsed: shamir::Share = shares[3].to_string().parse().unwrap();
+    let mut damaged = shares[0].to_string();
+    damaged.replace_range(damaged.len() - 6..damaged.len() - 5, "A");
+    let damaged: shamir::Share = damaged.parse().unwrap();
+    assert!(matches!(
+        shamir::combine(&[damaged, parsed, shares[4].clone()]),
+        Err(crypto::CryptoError::AuthenticationError)
+    ));
+}
+
+#[test]
+fn decrypt_accepts_shares_directly() {
+    let dir = tempdir().unwrap();
+    let key = [5u8; 32];
+    let path = dir.path().join("vault.xd");
+    fs::write(
+        &path,
+        crypto::encrypt_with_header(b"\x00custodians", &key, "vault.txt").unwrap(),
+    )
+    .unwrap();
+    let shares = shamir::split(&key, 2, 3).unwrap();
+    let share_file = dir.path().join("share3.txt");
+    fs::write(&share_file, shares[2].to_string()).unwrap();
+
+    let out = dir.path().join("vault.txt");
+    let status = cli()
+        .args(["decrypt", "--file", path.to_str().unwrap()])
+        .args(["--output", out.to_str().unwrap()])
+        .args(["--share", &shares[0].to_string()])
+        .args(["--share", share_file.to_str().unwrap()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    // The leading 0x00 is the flag of a body stored uncompressed
+    assert_eq!(fs::read(&out).unwrap(), b"custodians");
+}
+
+#[test]
+fn quorum_files_need_enough_team_keys() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("vault.txt"), b"two of three").unwrap();
+    let keys = [
+        "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=",
+        "AgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgI=",
+        "AwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwM=",
+    ];
+    let outsider = "BAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQ=";
+    let run = |args: &[&str]| run_in(dir.path(), args);
+
+    let mut args = vec!["encrypt", "--file", "vault.txt", "--quorum", "2"];
+    for key in keys {
+        args.extend(["--quorum-key", key]);
+    }
+    let out = run(&args);
+    assert!(out.status.success(), "{out:?}");
+    let out = run(&["--json", "inspect", "vault.xd"]);
+    let inspected: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(inspected["keyslots"].as_array().unwrap().len(), 3);
+    assert_eq!(inspected["keyslots"][2]["kind"], "quorum");
+    assert_eq!(inspected["keyslots"][2]["threshold"], 2);
+
+    let decrypt = |given: &[&str]| {
+        let mut args = vec!["decrypt", "--file", "vault.xd", "-o", "out.txt", "--force"];
+        for key in given {
+            args.extend(["--quorum-key", key]);
+        }
+        run(&args)
+    };
+    // One key is not enough on its own, even as a plain --key
+    assert_eq!(decrypt(&[keys[0]]).status.code(), Some(2));
+    let out = run(&[
+        "decrypt", "--file", "vault.xd", "--key", keys[0], "-o", "out.txt",
+    ]);
+    assert_eq!(out.status.code(), Some(5));
+    assert_eq!(decrypt(&[keys[1], outsider]).status.code(), Some(4));
+
+    for pair in [[keys[0], keys[2]], [keys[2], keys[1]]] {
+        let out = decrypt(&pair);
+        assert!(out.status.success(), "{out:?}");
+        assert_eq!(
+            fs::read(dir.path().join("out.txt")).unwrap(),
+            b"two of three"
+        );
+    }
+}
@@ -0,0 +1,220 @@
+mod common;
+
+use common::{KEY, OTHER_KEY, cli};
+use encryptx_backend::share::{self, Share, ShareError};
+use std::fs;
+use std::net::{TcpListener, TcpStream};
+use std::process::Stdio;
+use std::thread::sleep;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tempfile::tempdir;
+
+const SECRET: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+
+fn secret() -> Vec<u8> {
+    (0..32).collect()
+}
+
+#[test]
+fn passcodes_match_the_rfc_6238_vectors() {
+    let seed = b"12345678901234567890";
+    assert_eq!(share::passcode(seed, 59), "287082");
+    assert_eq!(share::passcode(seed, 1_111_111_109), "081804");
+    assert_eq!(share::passcode(seed, 1_234_567_890), "005924");
+    assert_eq!(share::passcode(seed, 2_000_000_000), "279037");
+
+    // One step of drift either way, no more
+    let code = share::passcode(seed, 1_000);
+    assert!(share::verify_passcode(seed, &code, 1_000));
+    assert!(share::verify_passcode(seed, &code, 1_000 + 30));
+    assert!(share::verify_passcode(seed, &code, 1_000 - 30));
+    assert!(!share::verify_passcode(seed, &code, 1_000 + 60));
+    assert!(!share::verify_passcode(seed, "", 1_000));
+
+    assert_eq!(share::base32(b"foobar"), "MZXW6YTBOI");
+    assert_eq!(share::base32(seed), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+    assert_eq!(
+        share::otpauth_uri(b"foobar", "talks/a b.mp4.xd"),
+        "otpauth://totp/EncryptX:talks%2Fa%20b.mp4.xd?secret=MZXW6YTBOI&issuer=EncryptX\
+         &algorithm=SHA1&digits=6&period=30"
+    );
+}
+
+#[test]
+fn tokens_open_only_under_their_secret_until_they_expire() {
+    let token = share::seal(
+        &secret(),
+        &Share {
+            file: "clip.mp4.xd".to_string(),
+            password: Some("pw".to_string()),
+            key: None,
+            seed: share::new_seed(),
+            expires: 1_000,
+        },
+    )
+    .unwrap();
+
+    let opened = share::open(&secret(), &token, 1_000).unwrap();
+    assert_eq!(opened.file, "clip.mp4.xd");
+    assert_eq!(opened.password.as_deref(), Some("pw"));
+    assert_eq!(opened.seed.len(), 20);
+    assert_eq!(
+        share::open(&secret(), &token, 1_001).err(),
+        Some(ShareError::Expired)
+    );
+    assert_eq!(
+        share::open(&[7; 32], &token, 0).err(),
+        Some(ShareError::Invalid)
+    );
+    let mut altered = token.into_bytes();
+    altered[20] = if altered[20] == b'A' { b'B' } else { b'A' };
+    let altered = String::from_utf8(altered).unwrap();
+    assert_eq!(
+        share::open(&secret(), &altered, 0).err(),
+        Some(ShareError::Invalid)
+    );
+    assert_eq!(
+        share::open(&secret(), "not a token", 0).err(),
+        Some(ShareError::Invalid)
+    );
+}
+
+#[test]
+fn share_links_serve_files_to_holders_of_the_current_passcode() {
+    let dir = tempdir().unwrap();
+    let media = dir.path().join("media");
+    fs::create_dir(&media).unwrap();
+    let data: Vec<u8> = (0..50_000u32).map(|i| (i % 251) as u8).collect();
+    fs::write(dir.path().join("clip.mp4"), &data).unwrap();
+    let encrypt = cli()
+        .args(["encrypt", "--file", "clip.mp4", "--key", KEY])
+        .args(["--chunk-size", "4KiB", "-o", "media/clip.mp4.xd"])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert!(encrypt.status.success(), "{encrypt:?}");
+
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let config = dir.path().join("server.toml");
+    fs::write(
+        &config,
+        format!(
+            "[server]\nhost = \"127.0.0.1\"\nport = {port}\nstream_dir = \"{}\"\n\
+             share_secret = \"{SECRET}\"\nshare_max_ttl_secs = 600\n",
+            media.display()
+        ),
+    )
+    .unwrap();
+    let mut server = cli()
+        .args(["--config", config.to_str().unwrap()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    for _ in 0..100 {
+        if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            break;
+        }
+        sleep(Duration::from_millis(100));
+    }
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .build()
+        .into();
+    let create = |name: &str, key: &str| {
+        let mut response = agent
+            .post(format!(
+                "http://127.0.0.1:{port}/share/{name}?ttl_secs=86400"
+            ))
+            .header("x-enc-key", key)
+            .send_empty()
+            .unwrap();
+        let status = response.status().as_u16();
+        let body = response.body_mut().read_to_string().unwrap();
+        (status, body)
+    };
+    let get = |link: &str, passcode: Option<&str>, range: Option<&str>| {
+        let mut request = agent.get(format!("http://127.0.0.1:{port}{link}"));
+        if let Some(passcode) = passcode {
+            request = request.header("x-share-passcode", passcode);
+        }
+        if let Some(range) = range {
+            request = request.header("Range", range);
+        }
+        let mut response = request.call().unwrap();
+        let content_range = response
+            .headers()
+            .get("content-range")
+            .map(|value| value.to_str().unwrap().to_string());
+        let status = response.status().as_u16();
+        let body = response.body_mut().read_to_vec().unwrap();
+        (status, content_range, body)
+    };
+
+    let (status, body) = create("clip.mp4.xd", KEY);
+    assert_eq!(status, 200, "{body}");
+    let created: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let link = created["link"].as_str().unwrap().to_string();
+    let token = link.strip_prefix("/share/").unwrap();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    // The requested lifetime is capped at share_max_ttl_secs
+    let expires = created["expires"].as_u64().unwrap();
+    assert!(expires > now && expires <= now + 600, "{created}");
+    let seed = share::open(&secret(), token, now).unwrap().seed.clone();
+    assert_eq!(created["totp_secret"], share::base32(&seed));
+    assert_eq!(created["digits"], 6);
+    assert_eq!(created["period_secs"], 30);
+    assert!(
+        created["otpauth_uri"]
+            .as_str()
+            .unwrap()
+            .starts_with("otpauth://totp/EncryptX:clip.mp4.xd?secret="),
+        "{created}"
+    );
+    let passcode = share::passcode(&seed, now);
+    let stale = share::passcode(&seed, now - 120);
+
+    let whole = get(&link, Some(&passcode), None);
+    let partial = get(&link, Some(&passcode), Some("bytes=10000-29999"));
+    let without_passcode = get(&link, None, None);
+    let stale_passcode = get(&link, Some(&stale), None);
+    let forged = get("/share/bm90IGEgdG9rZW4gYXQgYWxs", Some(&passcode), None);
+    let wrong_key = create("clip.mp4.xd", OTHER_KEY);
+    let missing = create("other.xd", KEY);
+    server.kill().unwrap();
+    server.wait().unwrap();
+
+    assert_eq!(whole.0, 200);
+    assert_eq!(whole.2, data);
+    assert_eq!(partial.0, 206);
+    assert_eq!(partial.1.as_deref(), Some("bytes 10000-29999/50000"));
+    assert_eq!(partial.2, data[10_000..30_000]);
+    assert_eq!(without_passcode.0, 401);
+    assert_eq!(stale_passcode.0, 401);
+    assert_eq!(forged.0, 404);
+    assert_eq!(wrong_key.0, 401);
+    assert_eq!(missing.0, 404);
+}
+
+#[test]
+fn malformed_share_secrets_fail_the_config_check() {
+    let dir = tempdir().unwrap();
+    let config = dir.path().join("server.toml");
+    fs::write(&config, "[server]\nshare_secret = \"c2hvcnQ=\"\n").unwrap();
+    let output = cli()
+        .args(["--config", config.to_str().unwrap(), "--check-config"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("share_secret"),
+        "{output:?}"
+    );
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use common::{KEY, run};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn shred_original_removes_the_input_once_the_output_verifies() {
+    let dir = tempdir().unwrap();
+    let input = dir.path().join("payroll.csv");
+    let original = b"name,salary\n".repeat(300);
+    fs::write(&input, &original).unwrap();
+    let encrypted = dir.path().join("payroll.xd");
+
+    let out = run(&[
+        "encrypt",
+        "--file",
+        input.to_str().unwrap(),
+        "--output",
+        encrypted.to_str().unwrap(),
+        "--key",
+        KEY,
+        "--shred-original",
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("verified all"));
+    assert!(!input.exists());
+
+    let decrypted = dir.path().join("payroll.out");
+    let out = run(&[
+        "decrypt",
+        "--file",
+        encrypted.to_str().unwrap(),
+        "--output",
+        decrypted.to_str().unwrap(),
+        "--key",
+        KEY,
+    ]);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(fs::read(&decrypted).unwrap(), original);
+
+    // Writing the encrypted file over the input would shred the only copy
+    let out = run(&[
+        "encrypt",
+        "--file",
+        decrypted.to_str().unwrap(),
+        "--output",
+        decrypted.to_str().unwrap(),
+        "--force",
+        "--key",
+        KEY,
+        "--shred-original",
+    ]);
+    assert_eq!(out.status.code(), Some(2));
+    assert_eq!(fs::read(&decrypted).unwrap(), original);
+}
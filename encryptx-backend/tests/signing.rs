@@ -0,0 +1,140 @@
+mod common;
+
+use common::cli;
+use encryptx_backend::crypto::{self, signing};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn signed_files_still_decrypt_and_tampering_is_detected() {
+    let key = signing::SigningKey::generate();
+    let data = vec![7u8; 3000];
+    let encrypted =
+        crypto::encrypt_with_header_chunked(&data, &[9u8; 32], "l.txt", Some(1024)).unwrap();
+    let signed = signing::sign(&encrypted, &key);
+    assert_eq!(signed.len(), encrypted.len() + signing::TRAILER_LEN);
+    assert_eq!(signing::verify(&signed).unwrap(), Some(key.verifying_key()));
+    assert_eq!(signing::verify(&encrypted).unwrap(), None);
+    let (plain, _) = crypto::decrypt_with_header(&signed, None).unwrap();
+    assert_eq!(plain, data);
+    // The last multipart part carries the trailer
+    let parts = crypto::chunked::part_boundaries(&signed).unwrap();
+    assert_eq!(parts.len(), 3);
+    assert_eq!(parts[2].end, signed.len());
+
+    // The signature covers the header too, not just the ciphertext
+    let mut tampered = signed.clone();
+    tampered[10] ^= 1;
+    assert!(matches!(
+        signing::verify(&tampered),
+        Err(crypto::CryptoError::SignatureError(_))
+    ));
+
+    let reparsed = signing::SigningKey::parse_file(&key.to_file()).unwrap();
+    let verifying: signing::VerifyingKey = key.verifying_key().to_string().parse().unwrap();
+    assert_eq!(reparsed.verifying_key(), verifying);
+}
+
+#[test]
+fn cli_signs_on_encrypt_and_reports_the_signer_on_decrypt() {
+    let dir = tempdir().unwrap();
+    let mut fingerprints = Vec::new();
+    for name in ["alice.sign", "mallory.sign"] {
+        let out = cli()
+            .args(["--json", "identity", "generate", "--signing", "--output"])
+            .arg(dir.path().join(name))
+            .output()
+            .unwrap();
+        assert!(out.status.success(), "{out:?}");
+        let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+        let verifying_key: signing::VerifyingKey =
+            report["verifying_key"].as_str().unwrap().parse().unwrap();
+        fingerprints.push(verifying_key.fingerprint());
+    }
+
+    let input = dir.path().join("memo.txt");
+    fs::write(&input, b"ship it").unwrap();
+    let encrypted = dir.path().join("memo.xd");
+    let out = cli()
+        .args(["--json", "encrypt", "--file", input.to_str().unwrap()])
+        .args(["--output", encrypted.to_str().unwrap(), "--password", "pw"])
+        .arg("--sign-key")
+        .arg(dir.path().join("alice.sign"))
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{out:?}");
+
+    let decrypt = |signer: Option<&str>| {
+        let mut cmd = cli();
+        cmd.args(["--json", "decrypt", "--file", encrypted.to_str().unwrap()])
+            .args(["--password", "pw", "--force", "--output"])
+            .arg(dir.path().join("out.txt"));
+        if let Some(signer) = signer {
+            cmd.arg("--signer").arg(dir.path().join(signer));
+        }
+        cmd.output().unwrap()
+    };
+    let out = decrypt(Some("alice.sign"));
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["signer"], fingerprints[0].as_str());
+    assert_eq!(fs::read(dir.path().join("out.txt")).unwrap(), b"ship it");
+
+    // Right password, wrong signer
+    assert_eq!(decrypt(Some("mallory.sign")).status.code(), Some(4));
+
+    // A damaged signature stops decryption even without --signer
+    let mut data = fs::read(&encrypted).unwrap();
+    let at = data.len() - signing::TRAILER_LEN;
+    data[at] ^= 1;
+    fs::write(&encrypted, data).unwrap();
+    assert_eq!(decrypt(None).status.code(), Some(4));
+}
+
+#[test]
+fn detached_signatures_verify_against_the_expected_key() {
+    let dir = tempdir().unwrap();
+    let alice = signing::SigningKey::generate();
+    let mallory = signing::SigningKey::generate();
+    let alice_key = dir.path().join("alice.sign");
+    fs::write(&alice_key, alice.to_file()).unwrap();
+
+    let file = dir.path().join("release.xd");
+    fs::write(&file, b"any bytes at all").unwrap();
+    let out = cli()
+        .arg("sign")
+        .arg(&file)
+        .arg("--key")
+        .arg(&alice_key)
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{out:?}");
+    let sig = dir.path().join("release.xd.sig");
+
+    let verify = |pubkey: String| {
+        cli()
+            .arg("verify-sig")
+            .arg(&file)
+            .arg(&sig)
+            .args(["--pubkey", &pubkey])
+            .output()
+            .unwrap()
+    };
+    assert!(verify(alice.verifying_key().to_string()).status.success());
+    // The signing key file names its verifying key, so it works as --pubkey too
+    assert!(
+        verify(alice_key.to_str().unwrap().to_string())
+            .status
+            .success()
+    );
+    assert_eq!(
+        verify(mallory.verifying_key().to_string()).status.code(),
+        Some(4)
+    );
+
+    fs::write(&file, b"any bytes at all!").unwrap();
+    assert_eq!(
+        verify(alice.verifying_key().to_string()).status.code(),
+        Some(4)
+    );
+}
@@ -0,0 +1,52 @@
+use encryptx_backend::{api, stego};
+
+/// Builds a noisy RGBA cover image of the given size.
+fn cover_png(width: u32, height: u32) -> Vec<u8> {
+    let pixels: Vec<u8> = (0..width * height * 4)
+        .map(|i| (i * 37 % 251) as u8)
+        .collect();
+    let mut out = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut out, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&pixels).unwrap();
+    }
+    out
+}
+
+#[tokio::test]
+async fn wrapped_xd_round_trips() {
+    let content = b"hidden in plain sight";
+    let encrypted = api::encrypt_file(content, Some("pw"), None, "note.txt")
+        .await
+        .unwrap()
+        .ciphertext;
+
+    let wrapped = stego::embed_png(&cover_png(64, 64), &encrypted).unwrap();
+    assert!(stego::is_png(&wrapped));
+
+    let extracted = stego::extract_png(&wrapped).unwrap();
+    assert_eq!(extracted, encrypted);
+    let (decrypted, metadata) = api::decrypt_file_bytes(&extracted, Some("pw"), None)
+        .await
+        .unwrap();
+    assert_eq!(decrypted, content);
+    assert_eq!(metadata.filename, "note.txt");
+}
+
+#[test]
+fn rejects_payload_larger_than_cover() {
+    let cover = cover_png(8, 8);
+    let capacity = stego::png_capacity(&cover).unwrap();
+    let err = stego::embed_png(&cover, &vec![0u8; capacity + 1]).unwrap_err();
+    assert!(matches!(
+        err,
+        stego::StegoError::InsufficientCapacity { .. }
+    ));
+    assert!(matches!(
+        stego::extract_png(&cover),
+        Err(stego::StegoError::NoPayload)
+    ));
+}
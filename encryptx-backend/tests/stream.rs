@@ -0,0 +1,96 @@
+mod common;
+
+use common::{KEY, OTHER_KEY, cli};
+use std::fs;
+use std::net::{TcpListener, TcpStream};
+use std::process::Stdio;
+use std::thread::sleep;
+use std::time::Duration;
+use tempfile::tempdir;
+
+#[test]
+fn serves_byte_ranges_of_stored_files() {
+    let dir = tempdir().unwrap();
+    let media = dir.path().join("media");
+    fs::create_dir(&media).unwrap();
+    let data: Vec<u8> = (0..50_000u32).map(|i| (i % 251) as u8).collect();
+    fs::write(dir.path().join("clip.mp4"), &data).unwrap();
+    let encrypt = cli()
+        .args(["encrypt", "--file", "clip.mp4", "--key", KEY])
+        .args(["--chunk-size", "4KiB", "-o", "media/clip.mp4.xd"])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert!(encrypt.status.success(), "{encrypt:?}");
+
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let config = dir.path().join("server.toml");
+    fs::write(
+        &config,
+        format!(
+            "[server]\nhost = \"127.0.0.1\"\nport = {port}\nstream_dir = \"{}\"\n",
+            media.display()
+        ),
+    )
+    .unwrap();
+    let mut server = cli()
+        .args(["--config", config.to_str().unwrap()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    for _ in 0..100 {
+        if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            break;
+        }
+        sleep(Duration::from_millis(100));
+    }
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .build()
+        .into();
+    let get = |name: &str, range: Option<&str>, key: &str| {
+        let mut request = agent
+            .get(format!("http://127.0.0.1:{port}/stream/{name}"))
+            .header("x-enc-key", key);
+        if let Some(range) = range {
+            request = request.header("Range", range);
+        }
+        let mut response = request.call().unwrap();
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .map(|value| value.to_str().unwrap().to_string())
+        };
+        let (content_range, content_type) = (header("content-range"), header("content-type"));
+        let status = response.status().as_u16();
+        let body = response.body_mut().read_to_vec().unwrap();
+        (status, content_range, content_type, body)
+    };
+
+    let partial = get("clip.mp4.xd", Some("bytes=10000-29999"), KEY);
+    let whole = get("clip.mp4.xd", None, KEY);
+    let past_end = get("clip.mp4.xd", Some("bytes=60000-"), KEY);
+    let wrong_key = get("clip.mp4.xd", Some("bytes=0-9"), OTHER_KEY);
+    let escaping = get("..%2Fclip.mp4", None, KEY);
+    let missing = get("other.xd", None, KEY);
+    server.kill().unwrap();
+    server.wait().unwrap();
+
+    assert_eq!(partial.0, 206);
+    assert_eq!(partial.1.as_deref(), Some("bytes 10000-29999/50000"));
+    assert_eq!(partial.2.as_deref(), Some("video/mp4"));
+    assert_eq!(partial.3, data[10_000..30_000]);
+    assert_eq!(whole.0, 200);
+    assert_eq!(whole.3, data);
+    assert_eq!(past_end.0, 416);
+    assert_eq!(past_end.1.as_deref(), Some("bytes */50000"));
+    assert_eq!(wrong_key.0, 401);
+    assert_eq!(escaping.0, 400);
+    assert_eq!(missing.0, 404);
+}
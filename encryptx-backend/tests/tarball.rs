@@ -0,0 +1,126 @@
+mod common;
+
+use common::{KEY, cli, run_in};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::Stdio;
+use tempfile::tempdir;
+
+/// Writes a small tree with a nested directory and an empty file.
+fn make_tree(dir: &Path) {
+    fs::create_dir_all(dir.join("project/docs")).unwrap();
+    fs::write(dir.join("project/readme.md"), "top level").unwrap();
+    fs::write(dir.join("project/docs/guide.txt"), "nested file").unwrap();
+    fs::write(dir.join("project/empty"), "").unwrap();
+}
+
+fn assert_tree(dir: &Path) {
+    assert_eq!(
+        fs::read_to_string(dir.join("readme.md")).unwrap(),
+        "top level"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("docs/guide.txt")).unwrap(),
+        "nested file"
+    );
+    assert_eq!(fs::read(dir.join("empty")).unwrap(), b"");
+}
+
+#[test]
+fn directories_round_trip_through_tar() {
+    let dir = tempdir().unwrap();
+    make_tree(dir.path());
+
+    let out = run_in(
+        dir.path(),
+        &["encrypt", "--tar-from", "project", "--key", KEY],
+    );
+    assert!(out.status.success(), "{out:?}");
+    assert!(dir.path().join("project.xd").exists());
+
+    let out = run_in(
+        dir.path(),
+        &[
+            "decrypt",
+            "-f",
+            "project.xd",
+            "--key",
+            KEY,
+            "--untar-to",
+            "restored",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    assert_tree(&dir.path().join("restored"));
+}
+
+#[test]
+fn unpacking_over_existing_files_needs_force() {
+    let dir = tempdir().unwrap();
+    make_tree(dir.path());
+    let out = run_in(
+        dir.path(),
+        &["encrypt", "--tar-from", "project", "--key", KEY],
+    );
+    assert!(out.status.success(), "{out:?}");
+
+    fs::create_dir_all(dir.path().join("restored/docs")).unwrap();
+    fs::write(dir.path().join("restored/docs/guide.txt"), "keep me").unwrap();
+    let untar = |force: bool| {
+        let mut args = vec![
+            "decrypt",
+            "-f",
+            "project.xd",
+            "--key",
+            KEY,
+            "--untar-to",
+            "restored",
+        ];
+        if force {
+            args.push("--force");
+        }
+        run_in(dir.path(), &args)
+    };
+
+    let out = untar(false);
+    assert_eq!(out.status.code(), Some(2), "{out:?}");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--force"));
+    // Nothing was extracted, not even the entries that didn't clash
+    assert_eq!(
+        fs::read_to_string(dir.path().join("restored/docs/guide.txt")).unwrap(),
+        "keep me"
+    );
+    assert!(!dir.path().join("restored/readme.md").exists());
+
+    let out = untar(true);
+    assert!(out.status.success(), "{out:?}");
+    assert_tree(&dir.path().join("restored"));
+}
+
+#[test]
+fn tarballs_pipe_through_stdin_and_stdout() {
+    let dir = tempdir().unwrap();
+    make_tree(dir.path());
+
+    let out = run_in(
+        dir.path(),
+        &["encrypt", "--tar-from", "project", "--key", KEY, "-o", "-"],
+    );
+    assert!(out.status.success(), "{out:?}");
+    assert!(!out.stdout.is_empty());
+    assert!(!dir.path().join("project.xd").exists());
+
+    let mut child = cli()
+        .args(["decrypt", "-f", "-", "--key", KEY, "--untar-to", "piped"])
+        .current_dir(dir.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(&out.stdout).unwrap();
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success(), "{out:?}");
+    assert_tree(&dir.path().join("piped"));
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use common::{KEY, OTHER_KEY, cli};
+use std::fs;
+use std::io::Write;
+use std::process::{Output, Stdio};
+use tempfile::tempdir;
+
+fn run(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = cli()
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn short_secrets_round_trip_as_armored_messages() {
+    let secret = "db password: hunter2";
+
+    let out = run(&["text", "encrypt", "--key", KEY, secret], b"");
+    assert!(out.status.success(), "{out:?}");
+    let message = String::from_utf8(out.stdout).unwrap();
+    assert!(message.starts_with("-----BEGIN ENCRYPTX MESSAGE-----\n"));
+    assert!(message.ends_with("-----END ENCRYPTX MESSAGE-----\n"));
+    assert!(!message.contains("hunter2"));
+
+    let out = run(&["text", "decrypt", "--key", KEY], message.as_bytes());
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(out.stdout, secret.as_bytes());
+
+    // The key isn't carried along, so it takes the right one to decrypt
+    let out = run(&["text", "decrypt"], message.as_bytes());
+    assert_eq!(out.status.code(), Some(2), "{out:?}");
+    let out = run(&["text", "decrypt", "--key", OTHER_KEY], message.as_bytes());
+    assert_eq!(out.status.code(), Some(4), "{out:?}");
+
+    // Quoted in a reply and passed as an argument
+    let quoted: String = message.lines().map(|line| format!("> {line}\n")).collect();
+    let out = run(
+        &[
+            "--json",
+            "text",
+            "decrypt",
+            "--key",
+            KEY,
+            &format!("On Monday you wrote:\n{quoted}"),
+        ],
+        b"",
+    );
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["operation"], "text-decrypt");
+    assert_eq!(report["text"], secret);
+
+    // Piped text is taken as is, trailing newline included
+    let out = run(
+        &["--json", "text", "encrypt", "--password", "correct horse"],
+        b"token\n",
+    );
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["mode"], "password");
+    assert_eq!(report["input_size"], 6);
+    let message = report["message"].as_str().unwrap();
+    let out = run(
+        &["text", "decrypt", "--password", "correct horse"],
+        message.as_bytes(),
+    );
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(out.stdout, b"token\n");
+}
+
+#[test]
+fn binary_messages_go_to_a_file() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("secret.xd");
+    let path = path.to_str().unwrap();
+
+    let out = run(
+        &["text", "encrypt", "--key", KEY, "--binary", "-o", path],
+        b"api token 1234",
+    );
+    assert!(out.status.success(), "{out:?}");
+    let encrypted = fs::read(path).unwrap();
+    assert!(!encrypted.starts_with(b"-----BEGIN"));
+    let out = run(&["text", "encrypt", "--key", KEY, "-o", path, "again"], b"");
+    assert_eq!(out.status.code(), Some(2), "{out:?}");
+
+    let out = run(&["text", "decrypt", "--key", KEY], &encrypted);
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(out.stdout, b"api token 1234");
+}
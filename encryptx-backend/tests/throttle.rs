@@ -0,0 +1,112 @@
+use actix_web::http::StatusCode;
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::middleware::from_fn;
+use actix_web::test::{TestRequest, call_service, init_service};
+use actix_web::{App, HttpResponse, web};
+use encryptx_backend::config::Config;
+use encryptx_backend::throttle::{DecryptThrottle, Throttle, throttle_decrypt};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const SECOND: Duration = Duration::from_secs(1);
+
+#[test]
+fn failures_back_off_exponentially_until_forgotten() {
+    let throttle = Throttle::new(2, 8 * SECOND);
+    let client: IpAddr = "203.0.113.7".parse().unwrap();
+    let start = Instant::now();
+
+    assert_eq!(throttle.record_failure(client, start), None);
+    assert_eq!(throttle.record_failure(client, start), None);
+    assert_eq!(throttle.blocked_for(client, start), None);
+    let backoffs: Vec<_> = (0..5)
+        .map(|_| throttle.record_failure(client, start).unwrap())
+        .collect();
+    assert_eq!(backoffs, [1, 2, 4, 8, 8].map(|s| s * SECOND));
+    assert_eq!(throttle.blocked_for(client, start), Some(8 * SECOND));
+    assert_eq!(throttle.blocked_for(client, start + 8 * SECOND), None);
+    // Other clients are unaffected
+    assert_eq!(
+        throttle.blocked_for("203.0.113.8".parse().unwrap(), start),
+        None
+    );
+
+    // An IPv6 client is counted with the rest of its /64
+    let v6: IpAddr = "2001:db8::1".parse().unwrap();
+    for _ in 0..3 {
+        throttle.record_failure(v6, start);
+    }
+    assert!(
+        throttle
+            .blocked_for("2001:db8::ffff".parse().unwrap(), start)
+            .is_some()
+    );
+
+    // Staying quiet for the maximum backoff after the block starts over
+    let later = start + 16 * SECOND;
+    assert_eq!(throttle.record_failure(client, later), None);
+}
+
+#[test]
+fn parallel_attempts_are_admitted_only_while_failures_are_free() {
+    let throttle = Throttle::new(2, 8 * SECOND);
+    let client: IpAddr = "203.0.113.9".parse().unwrap();
+    let now = Instant::now();
+
+    // Two free failures allow two decryptions at once, but not a third
+    let first = throttle.begin(client, now).unwrap();
+    let second = throttle.begin(client, now).unwrap();
+    assert_eq!(throttle.begin(client, now).err(), Some(SECOND));
+    assert_eq!(first.failed(now), None);
+    assert_eq!(second.failed(now), None);
+
+    // With none left, one at a time, and its failure blocks the next
+    let attempt = throttle.begin(client, now).unwrap();
+    assert!(throttle.begin(client, now).is_err());
+    assert_eq!(attempt.failed(now), Some(SECOND));
+    assert_eq!(throttle.begin(client, now).err(), Some(SECOND));
+
+    // A success frees its slot without counting as a failure
+    let later = now + SECOND;
+    drop(throttle.begin(client, later).unwrap());
+    assert!(throttle.begin(client, later).is_ok());
+}
+
+#[actix_web::test]
+async fn blocked_clients_get_429_with_retry_after() {
+    let throttle: DecryptThrottle = Some(Arc::new(Throttle::new(1, 60 * SECOND)));
+    let app = init_service(
+        App::new()
+            .app_data(web::Data::new(Config::default()))
+            .app_data(web::Data::new(throttle))
+            .route(
+                "/decrypt",
+                web::post()
+                    .to(|| async { HttpResponse::Unauthorized().finish() })
+                    .wrap(from_fn(throttle_decrypt)),
+            ),
+    )
+    .await;
+    let attacker: SocketAddr = "198.51.100.1:5000".parse().unwrap();
+    let request = |peer| {
+        TestRequest::post()
+            .uri("/decrypt")
+            .peer_addr(peer)
+            .to_request()
+    };
+
+    // One free failure, then a second that starts the backoff
+    for _ in 0..2 {
+        let response = call_service(&app, request(attacker)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+    let response = call_service(&app, request(attacker)).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), "1");
+
+    // Someone else is still let through
+    let other: SocketAddr = "198.51.100.2:5000".parse().unwrap();
+    let response = call_service(&app, request(other)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
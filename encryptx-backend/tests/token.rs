@@ -0,0 +1,116 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::cli;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tempfile::tempdir;
+
+/// Stand-ins for the libfido2 tools: a token holding the credential in $FAKE_CREDENTIAL,
+/// whose hmac-secret is derived from the credential ID and salt.
+const FAKE_TOOLS: [(&str, &str); 3] = [
+    (
+        "fido2-token",
+        "echo '/dev/fake0: vendor=0x1050, product=0x0407 (Fake Key)'",
+    ),
+    (
+        "fido2-cred",
+        "read cdh; read rp; read user; read id\n\
+         printf '%s\\n' \"$cdh\" \"$rp\" packed authdata \"$FAKE_CREDENTIAL\" sig",
+    ),
+    (
+        "fido2-assert",
+        "read cdh; read rp; read cred; read salt\n\
+         [ \"$cred\" = \"$FAKE_CREDENTIAL\" ] || { echo 'FIDO_ERR_NO_CREDENTIALS' >&2; exit 1; }\n\
+         secret=$(printf '%s%s' \"$cred\" \"$salt\" | sha256sum | cut -c1-32)\n\
+         printf '%s\\n' \"$cdh\" \"$rp\" authdata sig \"$(printf '%s' \"$secret\" | base64)\"",
+    ),
+];
+
+fn install_fake_tools(dir: &Path) {
+    for (name, script) in FAKE_TOOLS {
+        let path = dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
+
+#[test]
+fn token_keyslots_open_only_with_the_enrolled_token() {
+    let dir = tempdir().unwrap();
+    let tools = dir.path().join("tools");
+    fs::create_dir(&tools).unwrap();
+    install_fake_tools(&tools);
+    let run = |credential: &str, args: &[&str]| {
+        cli()
+            .args(args)
+            .current_dir(dir.path())
+            .env("ENCRYPTX_FIDO2_TOOLS", &tools)
+            .env("FAKE_CREDENTIAL", credential)
+            .output()
+            .unwrap()
+    };
+
+    let out = run("Y3JlZC0x", &["--json", "token", "list"]);
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["devices"][0]["path"], "/dev/fake0");
+
+    let out = run(
+        "Y3JlZC0x",
+        &["token", "enroll", "--output", "yubikey.token"],
+    );
+    assert!(out.status.success(), "{out:?}");
+
+    fs::write(dir.path().join("notes.txt"), b"for your eyes only").unwrap();
+    let key = "QUJDREVGR0hJSktMTU5PUFFSU1RVVldYWVoxMjM0NTY=";
+    let out = run(
+        "Y3JlZC0x",
+        &[
+            "encrypt",
+            "--file",
+            "notes.txt",
+            "--output",
+            "notes.xd",
+            "--key",
+            key,
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    let out = run(
+        "Y3JlZC0x",
+        &[
+            "keyslot",
+            "add",
+            "notes.xd",
+            "--key",
+            key,
+            "--new-token",
+            "yubikey.token",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+
+    let decrypt = |credential: &str| {
+        run(
+            credential,
+            &[
+                "--json", "decrypt", "--file", "notes.xd", "--token", "--output", "out.txt",
+                "--force",
+            ],
+        )
+    };
+    let out = decrypt("Y3JlZC0x");
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["mode"], "token");
+    assert_eq!(
+        fs::read(dir.path().join("out.txt")).unwrap(),
+        b"for your eyes only"
+    );
+
+    // A different token doesn't hold the credential
+    assert_eq!(decrypt("b3RoZXI=").status.code(), Some(4));
+}
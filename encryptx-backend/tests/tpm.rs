@@ -0,0 +1,115 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::cli;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Output;
+use tempfile::tempdir;
+
+/// Stand-in for the tpm2-tools. The "TPM" of machine `$FAKE_TPM` seals by tagging the data
+/// with its name; a PCR policy records `$FAKE_PCRS`, the current PCR state.
+const FAKE_TOOLS: &str = r#"#!/bin/sh
+pub=; priv=; ctx=; policy=; pcrs=; auth=
+while [ $# -gt 0 ]; do
+    case "$1" in
+        -u) pub=$2; shift ;;
+        -r) priv=$2; shift ;;
+        -c) ctx=$2; shift ;;
+        -L) policy=$2; shift ;;
+        -l) pcrs=$2; shift ;;
+        -p) auth=$2; shift ;;
+        -C|-g|-G|-a|-i) shift ;;
+    esac
+    shift
+done
+case $(basename "$0") in
+    tpm2_createprimary) echo "$FAKE_TPM" > "$ctx" ;;
+    tpm2_createpolicy) echo "$pcrs=$FAKE_PCRS" > "$policy" ;;
+    tpm2_create) cat "$policy" > "$pub" 2>/dev/null || : > "$pub"
+                 { echo "$FAKE_TPM"; cat; } > "$priv" ;;
+    tpm2_load) [ "$(head -n 1 "$priv")" = "$FAKE_TPM" ] || {
+                   echo "ERROR: Esys_Load(0x1DF) - tpm:parameter(1):integrity check failed" >&2; exit 1; }
+               cp "$pub" "$ctx.pub"; tail -n +2 "$priv" > "$ctx" ;;
+    tpm2_unseal) if [ -s "$ctx.pub" ] && [ "$(cat "$ctx.pub")" != "${auth#pcr:}=$FAKE_PCRS" ]; then
+                     echo "ERROR: Esys_Unseal(0x99D) - tpm:session(1):a policy check failed" >&2; exit 1
+                 fi
+                 cat "$ctx" ;;
+esac
+"#;
+
+fn run(dir: &Path, machine: &str, pcrs: &str, args: &[&str]) -> Output {
+    cli()
+        .args(args)
+        .current_dir(dir)
+        .env("ENCRYPTX_TPM2_TOOLS", dir.join("tools"))
+        .env("FAKE_TPM", machine)
+        .env("FAKE_PCRS", pcrs)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn tpm_sealed_files_open_on_the_same_machine_only() {
+    let dir = tempdir().unwrap();
+    let tools = dir.path().join("tools");
+    fs::create_dir(&tools).unwrap();
+    for name in [
+        "tpm2_createprimary",
+        "tpm2_createpolicy",
+        "tpm2_create",
+        "tpm2_load",
+        "tpm2_unseal",
+    ] {
+        fs::write(tools.join(name), FAKE_TOOLS).unwrap();
+        fs::set_permissions(tools.join(name), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    fs::write(dir.path().join("notes.txt"), b"sealed at rest\n").unwrap();
+
+    let encrypt = |pcrs: &str| {
+        let args = [
+            "--json",
+            "encrypt",
+            "--file",
+            "notes.txt",
+            "-o",
+            "notes.xd",
+            "--tpm",
+            "--tpm-pcrs",
+            pcrs,
+            "--force",
+        ];
+        run(dir.path(), "laptop", "boot-a", &args)
+    };
+    assert_eq!(encrypt("24").status.code(), Some(2));
+    let out = encrypt("7");
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["mode"], "tpm");
+
+    // No credential needed on the machine that sealed it
+    let decrypt = |machine: &str, pcrs: &str| {
+        let args = [
+            "--json", "decrypt", "--file", "notes.xd", "-o", "out.txt", "--force",
+        ];
+        run(dir.path(), machine, pcrs, &args)
+    };
+    let out = decrypt("laptop", "boot-a");
+    assert!(out.status.success(), "{out:?}");
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["mode"], "tpm");
+    assert_eq!(
+        fs::read(dir.path().join("out.txt")).unwrap(),
+        b"sealed at rest\n"
+    );
+
+    // Changed PCR values or another machine's TPM refuse to unseal
+    let out = decrypt("laptop", "boot-b");
+    assert_eq!(out.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("policy check failed"));
+    let out = decrypt("desktop", "boot-a");
+    assert_eq!(out.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("integrity check failed"));
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use common::cli;
+use encryptx_backend::api;
+use encryptx_backend::crypto::{self, PasswordKdf};
+use encryptx_backend::format::{self, ParsedHeader, XdReader, XdWriter};
+use std::fs;
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn upgrade_rewrites_legacy_files_and_leaves_current_ones() {
+    let dir = tempdir().unwrap();
+    let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+
+    let pbkdf2 = crypto::encrypt_with_password_kdf_async(
+        b"from before argon2",
+        "pw".to_string(),
+        "old.txt",
+        vec![5u8; 32],
+        PasswordKdf::Pbkdf2 { iterations: 10_000 },
+        None,
+    )
+    .await
+    .unwrap();
+    fs::write(path("old.xd"), &pbkdf2).unwrap();
+
+    // A version 1 key file: single-message bodies don't authenticate the header
+    let encrypted = crypto::encrypt_with_header(b"version one", &[7u8; 32], "v1.txt").unwrap();
+    let file = XdReader::parse(&encrypted).unwrap();
+    let header = String::from_utf8(file.header_json.to_vec())
+        .unwrap()
+        .replace("\"version\":2", "\"version\":1");
+    let v1 = XdWriter::key(header.as_bytes())
+        .nonce(file.nonce)
+        .body(file.ciphertext)
+        .finish()
+        .unwrap();
+    fs::write(path("v1.xd"), &v1).unwrap();
+
+    let current = api::encrypt_file(b"up to date", Some("pw"), None, "new.txt")
+        .await
+        .unwrap()
+        .ciphertext;
+    fs::write(path("new.xd"), &current).unwrap();
+
+    let files = [path("old.xd"), path("v1.xd"), path("new.xd")];
+    let out = cli()
+        .args(["--json", "upgrade", "--dry-run"])
+        .args(&files)
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["upgraded"], 2);
+    assert_eq!(report["current"], 1);
+    assert_eq!(fs::read(path("old.xd")).unwrap(), pbkdf2);
+
+    let out = cli()
+        .arg("upgrade")
+        .args(&files)
+        .args(["--password", "pw"])
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+
+    let upgraded = fs::read(path("old.xd")).unwrap();
+    let info = format::detect(&upgraded).unwrap();
+    assert_eq!(info.version, format::CURRENT_VERSION);
+    assert!(matches!(info.kdf, Some(PasswordKdf::Argon2id(_))));
+    assert!(
+        XdReader::parse(&upgraded)
+            .unwrap()
+            .header
+            .chunk_size()
+            .is_some()
+    );
+    let (plain, metadata) = api::decrypt_file_bytes(&upgraded, Some("pw"), None)
+        .await
+        .unwrap();
+    assert_eq!(plain, b"from before argon2");
+    assert_eq!(metadata.filename, "old.txt");
+
+    let upgraded = fs::read(path("v1.xd")).unwrap();
+    let parsed = XdReader::parse(&upgraded).unwrap();
+    assert!(matches!(&parsed.header, ParsedHeader::Key(h) if h.version == 2));
+    let (plain, _) = crypto::decrypt_with_header(&upgraded, Some(&[7u8; 32])).unwrap();
+    assert_eq!(plain, b"version one");
+
+    assert_eq!(fs::read(path("new.xd")).unwrap(), current);
+
+    // A wrong password fails that file with its own exit code and leaves it untouched
+    fs::write(path("old.xd"), &pbkdf2).unwrap();
+    let status = cli()
+        .args(["upgrade", &path("old.xd"), "--password", "wrong"])
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(4));
+    assert_eq!(fs::read(path("old.xd")).unwrap(), pbkdf2);
+}
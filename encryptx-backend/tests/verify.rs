@@ -0,0 +1,68 @@
+mod common;
+
+use common::run;
+use encryptx_backend::crypto;
+use encryptx_backend::format::XdReader;
+
+#[tokio::test]
+async fn verify_detects_tampered_region() {
+    let key = [7u8; 32];
+    let mut encrypted = crypto::encrypt_with_header(b"verify me", &key, "v.txt").unwrap();
+
+    let parsed = XdReader::parse(&encrypted).unwrap();
+    let file_key = crypto::file_key(&parsed, None, Some(&key)).await.unwrap();
+    let regions = crypto::verify_regions(&parsed, &file_key).unwrap();
+    assert!(regions.iter().all(|r| r.ok));
+
+    // Flip a bit inside the ciphertext
+    let last = encrypted.len() - 1;
+    encrypted[last] ^= 0x01;
+    let parsed = XdReader::parse(&encrypted).unwrap();
+    let regions = crypto::verify_regions(&parsed, &file_key).unwrap();
+    assert_eq!(regions.len(), 1);
+    assert!(!regions[0].ok);
+    assert_eq!(regions[0].offset + regions[0].length, encrypted.len());
+}
+
+#[test]
+fn encrypt_verify_reads_back_the_written_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("ledger.csv");
+    std::fs::write(&input, b"date,amount\n".repeat(100)).unwrap();
+
+    // Keyslot files are checked with the data key, key files with the key itself
+    for (name, credential) in [
+        (
+            "split.xd",
+            &["--split-credentials", "--chunk-size", "4KiB"][..],
+        ),
+        (
+            "key.xd",
+            &["--key", "BwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwc="],
+        ),
+    ] {
+        let output = dir.path().join(name);
+        let mut args = vec![
+            "encrypt",
+            "--file",
+            input.to_str().unwrap(),
+            "--output",
+            output.to_str().unwrap(),
+            "--verify",
+        ];
+        args.extend(credential);
+        let out = run(&args);
+        assert!(out.status.success(), "{out:?}");
+        assert!(String::from_utf8_lossy(&out.stderr).contains("verified all"));
+    }
+
+    let out = run(&[
+        "encrypt",
+        "--file",
+        input.to_str().unwrap(),
+        "--output",
+        "-",
+        "--verify",
+    ]);
+    assert_eq!(out.status.code(), Some(2));
+}
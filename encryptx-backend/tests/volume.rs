@@ -0,0 +1,92 @@
+mod common;
+
+use common::{KEY, run_in};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn encrypted_files_split_into_volumes_and_join_back() {
+    let dir = tempdir().unwrap();
+    let original: Vec<u8> = (0..10_000u32).map(|i| (i * 7 % 251) as u8).collect();
+    fs::write(dir.path().join("movie.bin"), &original).unwrap();
+
+    let out = run_in(
+        dir.path(),
+        &[
+            "encrypt",
+            "--file",
+            "movie.bin",
+            "--key",
+            KEY,
+            "--no-compress",
+            "--volume-size",
+            "4KiB",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    assert!(!dir.path().join("movie.xd").exists());
+    let sizes: Vec<u64> = (1..=3)
+        .map(|i| {
+            fs::metadata(dir.path().join(format!("movie.xd.{i:03}")))
+                .unwrap()
+                .len()
+        })
+        .collect();
+    assert_eq!(sizes[..2], [4096, 4096]);
+    assert!(!dir.path().join("movie.xd.004").exists());
+
+    // Decrypt finds the volumes from the first one, or from the name they were split from
+    fs::remove_file(dir.path().join("movie.bin")).unwrap();
+    let out = run_in(
+        dir.path(),
+        &["decrypt", "--file", "movie.xd.001", "--key", KEY],
+    );
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(fs::read(dir.path().join("movie.bin")).unwrap(), original);
+    let out = run_in(
+        dir.path(),
+        &["decrypt", "--file", "movie.xd", "--key", KEY, "-o", "-"],
+    );
+    assert_eq!(out.stdout, original);
+
+    let out = run_in(dir.path(), &["join", "movie.xd.001"]);
+    assert!(out.status.success(), "{out:?}");
+    let joined = fs::read(dir.path().join("movie.xd")).unwrap();
+    assert_eq!(joined.len() as u64, sizes.iter().sum::<u64>());
+
+    // A shorter split over a longer one leaves no stale volume behind
+    let out = run_in(
+        dir.path(),
+        &[
+            "encrypt",
+            "--file",
+            "movie.bin",
+            "--key",
+            KEY,
+            "--volume-size",
+            "8KiB",
+            "-o",
+            "movie.xd",
+            "--force",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+    assert!(!dir.path().join("movie.xd.003").exists());
+
+    // Volumes are local files of the native format
+    let out = run_in(
+        dir.path(),
+        &[
+            "encrypt",
+            "--file",
+            "movie.bin",
+            "--key",
+            KEY,
+            "--volume-size",
+            "4KiB",
+            "-o",
+            "-",
+        ],
+    );
+    assert_eq!(out.status.code(), Some(2));
+}
@@ -0,0 +1,92 @@
+#![cfg(unix)]
+
+use encryptx_backend::walk::{self, EntryKind, Filter, SymlinkPolicy};
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn names(root: &Path, symlinks: SymlinkPolicy) -> Vec<(String, EntryKind)> {
+    walk::walk(root, symlinks, &Filter::default())
+        .unwrap()
+        .into_iter()
+        .map(|entry| (entry.name, entry.kind))
+        .collect()
+}
+
+#[test]
+fn symlinks_are_stored_followed_or_skipped_on_request() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    fs::create_dir(root.join("sub")).unwrap();
+    fs::write(root.join("a.txt"), b"top").unwrap();
+    fs::write(root.join("sub/f"), b"nested").unwrap();
+    symlink("../a.txt", root.join("sub/link")).unwrap();
+
+    assert_eq!(
+        names(root, SymlinkPolicy::Store),
+        [
+            ("a.txt".to_string(), EntryKind::File),
+            ("sub".to_string(), EntryKind::Dir),
+            ("sub/f".to_string(), EntryKind::File),
+            (
+                "sub/link".to_string(),
+                EntryKind::Symlink("../a.txt".into())
+            ),
+        ]
+    );
+    assert_eq!(
+        names(root, SymlinkPolicy::Follow)[3],
+        ("sub/link".to_string(), EntryKind::File)
+    );
+    assert_eq!(names(root, SymlinkPolicy::Skip).len(), 3);
+
+    // A link back up the tree is fine to store, but can't be followed
+    symlink("..", root.join("sub/up")).unwrap();
+    assert_eq!(names(root, SymlinkPolicy::Store).len(), 5);
+    let err = walk::walk(root, SymlinkPolicy::Follow, &Filter::default()).unwrap_err();
+    assert!(err.to_string().contains("symlink loop"), "{err}");
+}
+
+#[test]
+fn filters_follow_gitignore_rules() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    for file in [
+        "src/main.rs",
+        "src/scratch.tmp",
+        "target/debug/app",
+        "docs/build/index.html",
+        "docs/guide.md",
+        "keep.tmp",
+    ] {
+        let path = root.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, file).unwrap();
+    }
+    let patterns = |lines: &[&str]| lines.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+    let kept = |filter: Filter| {
+        walk::walk(root, SymlinkPolicy::Store, &filter)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect::<Vec<_>>()
+    };
+
+    let ignore = Filter::patterns_in("# build output\ntarget/\n*.tmp\n\n!keep.tmp\n");
+    let excludes = [ignore, patterns(&["docs/build"])].concat();
+    assert_eq!(
+        kept(Filter::new(&excludes, &[]).unwrap()),
+        ["docs", "docs/guide.md", "keep.tmp", "src", "src/main.rs"]
+    );
+
+    // Includes keep matching files and whole matching directories, minus the excludes
+    let filter = Filter::new(&patterns(&["build/"]), &patterns(&["*.rs", "docs/"])).unwrap();
+    assert_eq!(
+        kept(filter),
+        ["docs", "docs/guide.md", "src", "src/main.rs"]
+    );
+
+    assert!(Filter::new(&[], &patterns(&["!*.rs"])).is_err());
+    assert!(Filter::new(&patterns(&["src/[a"]), &[]).is_err());
+}
@@ -0,0 +1,101 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{KEY, cli};
+use std::fs;
+use std::path::Path;
+use std::process::{Child, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use tempfile::tempdir;
+
+/// Waits until `path` exists and decrypts to `expected`.
+fn wait_for_plaintext(path: &Path, expected: &str) {
+    let deadline = Instant::now() + Duration::from_secs(20);
+    loop {
+        let out = cli()
+            .args(["cat", path.to_str().unwrap(), "--key", KEY])
+            .output()
+            .unwrap();
+        if out.status.success() && out.stdout == expected.as_bytes() {
+            return;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "'{}' never decrypted to {expected:?}: {out:?}",
+            path.display()
+        );
+        sleep(Duration::from_millis(100));
+    }
+}
+
+fn stop(child: Child) -> String {
+    unsafe { libc::kill(child.id() as i32, libc::SIGINT) };
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success(), "{out:?}");
+    String::from_utf8(out.stderr).unwrap()
+}
+
+#[test]
+fn watched_files_are_encrypted_as_they_change() {
+    let dir = tempdir().unwrap();
+    let inbox = dir.path().join("in");
+    let outbox = dir.path().join("out");
+    fs::create_dir(&inbox).unwrap();
+    fs::write(inbox.join("before.txt"), "already here").unwrap();
+
+    let child = cli()
+        .args([
+            "watch",
+            inbox.to_str().unwrap(),
+            "--output",
+            outbox.to_str().unwrap(),
+            "--key",
+            KEY,
+            "--debounce",
+            "200",
+            "--exclude",
+            "*.part",
+        ])
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Files there before the watch started are caught up on
+    wait_for_plaintext(&outbox.join("before.txt.xd"), "already here");
+
+    fs::write(inbox.join("new.txt"), "first draft").unwrap();
+    wait_for_plaintext(&outbox.join("new.txt.xd"), "first draft");
+    fs::write(inbox.join("new.txt"), "second draft").unwrap();
+    wait_for_plaintext(&outbox.join("new.txt.xd"), "second draft");
+
+    // A directory moved in whole brings its files along
+    let staged = dir.path().join("staged");
+    fs::create_dir_all(staged.join("deep")).unwrap();
+    fs::write(staged.join("deep/report.txt"), "quarterly").unwrap();
+    fs::rename(&staged, inbox.join("staged")).unwrap();
+    wait_for_plaintext(&outbox.join("staged/deep/report.txt.xd"), "quarterly");
+
+    fs::write(inbox.join("download.part"), "incomplete").unwrap();
+    sleep(Duration::from_secs(1));
+    assert!(!outbox.join("download.part.xd").exists());
+
+    let log = stop(child);
+    assert!(log.contains("'new.txt' encrypted"), "{log}");
+    assert!(log.contains("Stopped watching"), "{log}");
+
+    // The output directory can't be inside the watched one
+    let out = cli()
+        .args([
+            "watch",
+            inbox.to_str().unwrap(),
+            "--output",
+            inbox.join("encrypted").to_str().unwrap(),
+            "--key",
+            KEY,
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(out.status.code(), Some(2), "{out:?}");
+}
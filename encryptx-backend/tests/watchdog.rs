@@ -0,0 +1,25 @@
+use encryptx_backend::watchdog::{self, MemoryWatchdog};
+
+const MB: u64 = 1024 * 1024;
+
+#[test]
+fn reservations_are_refused_past_the_limit_and_released_on_drop() {
+    let watchdog = MemoryWatchdog::new(100 * MB);
+    watchdog.record_rss(40 * MB);
+
+    let first = watchdog.try_reserve(35 * MB).expect("fits under the limit");
+    // 40 MB resident + 35 MB reserved leaves no room for another derivation
+    assert!(watchdog.try_reserve(35 * MB).is_none());
+    drop(first);
+    assert!(watchdog.try_reserve(35 * MB).is_some());
+
+    watchdog.record_rss(90 * MB);
+    assert!(watchdog.try_reserve(19 * MB).is_none());
+}
+
+#[test]
+fn rss_is_read_on_linux() {
+    if cfg!(target_os = "linux") {
+        assert!(watchdog::current_rss().unwrap() > 0);
+    }
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use common::{KEY, cli};
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::Stdio;
+use std::thread::sleep;
+use std::time::Duration;
+use tempfile::tempdir;
+
+/// Sends one request and returns the whole response.
+fn request(port: u16, head: &str) -> Option<String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).ok()?;
+    write!(
+        stream,
+        "{head}\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+    )
+    .ok()?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).ok()?;
+    Some(response)
+}
+
+#[test]
+fn serves_archive_over_webdav() {
+    let dir = tempdir().unwrap();
+    let src = dir.path().join("src");
+    let archive = dir.path().join("src.xd");
+    fs::create_dir_all(src.join("docs")).unwrap();
+    fs::write(src.join("docs/plan.txt"), b"step one").unwrap();
+    let status = cli()
+        .args(["encrypt", "--tar-from", src.to_str().unwrap(), "--key", KEY])
+        .args(["--output", archive.to_str().unwrap()])
+        .output()
+        .unwrap()
+        .status;
+    assert!(status.success());
+
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let mut child = cli()
+        .args(["webdav", archive.to_str().unwrap(), "--key", KEY])
+        .args(["--listen", &format!("127.0.0.1:{port}")])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut listing = None;
+    for _ in 0..100 {
+        listing = request(port, "PROPFIND /docs/ HTTP/1.1\r\nDepth: 1");
+        if listing.is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(100));
+    }
+    let listing = listing.expect("server never came up");
+    let file = request(port, "GET /docs/plan.txt HTTP/1.1\r\nRange: bytes=5-");
+    let put = request(port, "PUT /docs/new.txt HTTP/1.1\r\nContent-Length: 0");
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    assert!(listing.starts_with("HTTP/1.1 207"), "{listing}");
+    assert!(listing.contains("<D:href>/docs/plan.txt</D:href>"));
+    assert!(listing.contains("<D:getcontentlength>8</D:getcontentlength>"));
+    let file = file.unwrap();
+    assert!(file.starts_with("HTTP/1.1 206"), "{file}");
+    assert!(file.ends_with("\r\n\r\none"));
+    assert!(put.unwrap().starts_with("HTTP/1.1 405"));
+
+    let refused = cli()
+        .args(["webdav", archive.to_str().unwrap(), "--key", KEY])
+        .args(["--listen", "0.0.0.0:0"])
+        .output()
+        .unwrap();
+    assert_eq!(refused.status.code(), Some(2));
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use common::run_in;
+use std::fs;
+use std::io::Read;
+use tempfile::tempdir;
+use zip::ZipArchive;
+use zip::result::ZipError;
+
+#[test]
+fn zip_aes_output_opens_with_the_password_only() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("note.txt"), "zipped and sealed").unwrap();
+
+    let out = run_in(
+        dir.path(),
+        &[
+            "encrypt",
+            "-f",
+            "note.txt",
+            "--format",
+            "zip-aes",
+            "--password",
+            "hunter2",
+        ],
+    );
+    assert!(out.status.success(), "{out:?}");
+
+    let data = fs::read(dir.path().join("note.zip")).unwrap();
+    let mut archive = ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+    assert_eq!(archive.len(), 1);
+    {
+        let mut entry = archive.by_index_decrypt(0, b"hunter2").unwrap();
+        assert_eq!(entry.name(), "note.txt");
+        let mut plain = String::new();
+        entry.read_to_string(&mut plain).unwrap();
+        assert_eq!(plain, "zipped and sealed");
+    }
+    assert!(matches!(
+        archive.by_index_decrypt(0, b"wrong"),
+        Err(ZipError::InvalidPassword)
+    ));
+    // Encrypted entries can't be read without a password at all
+    assert!(archive.by_index(0).is_err());
+
+    // A ZIP can only be sealed with a password
+    let out = run_in(
+        dir.path(),
+        &[
+            "encrypt", "-f", "note.txt", "--format", "zip-aes", "--force",
+        ],
+    );
+    assert_eq!(out.status.code(), Some(2), "{out:?}");
+}